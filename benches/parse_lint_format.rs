@@ -0,0 +1,231 @@
+//! Benchmarks of the three stages of `bibadac`, measured separately
+//! on generated bibliographies of 1k, 10k, and 50k entries:
+//! parsing (`BibFile::new`), linting (`LinterState::lint_file`),
+//! and formatting (`write_bibfile`). The import of a helper database
+//! (`--file-db`) is measured as a whole and as a stream, along with
+//! the peak memory of both, which is printed. The search for similar
+//! keys (`similar_keys`) is measured on keys sharing their prefixes.
+//! The checks of the entries are measured with their field map computed
+//! once and shared (`once`), and computed again by the checks (`twice`),
+//! as they were before the map was shared.
+//!
+//! Run them with `cargo bench`, or `cargo bench -- lint/10000`
+//! for a single case.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+use bibadac::bibdb::LocalBibDb;
+use bibadac::bibtex::BibFile;
+use bibadac::format::{FormatOptions, write_bibfile};
+use bibadac::linter::{LinterState, field_map};
+use bibadac::similar_keys::similar_keys;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 50_000];
+
+/// The system allocator, counting the allocated bytes.
+struct PeakAlloc {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+/// The peak memory used by `f` above the memory in use before it.
+fn peak_memory<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATOR.current.load(Ordering::Relaxed);
+    ALLOCATOR.peak.store(before, Ordering::Relaxed);
+    drop(f());
+    ALLOCATOR.peak.load(Ordering::Relaxed) - before
+}
+
+/// A bibliography mixing the kinds of entries found in practice:
+/// published papers, preprints, and entries with lint issues
+/// (missing fields, weird characters, malformed dates, duplicates).
+fn generate(entries: usize) -> String {
+    let mut bib = String::new();
+    for i in 0..entries {
+        let year = 1990 + i % 35;
+        let entry = match i % 4 {
+            0 => format!(
+                "@article{{Author{i}_{year},\n  author = {{Author, Ann and Other, Bob}},\n  title = {{On the {{Theory}} of Things, part {i}}},\n  journal = {{Journal of Things}},\n  year = {{{year}}},\n  doi = {{10.1145/{i}.{year}}},\n}}\n\n"
+            ),
+            1 => format!(
+                "@misc{{preprint{i},\n  Author = {{Doe, Jane}},\n  Title = {{A Preprint about {i} Things}},\n  year = {year},\n  eprint = {{{:04}.{:05}v2}},\n  archivePrefix = {{arXiv}},\n}}\n\n",
+                2000 + i % 1000,
+                i
+            ),
+            2 => format!(
+                "@inproceedings{{conf{i},\n  author = {{Jane Doe}},\n  title = {{Caf\\'e {i}}},\n  booktitle = {{Proceedings}},\n  date = {{01/04/{year}}},\n  url = {{https://doi.org/10.1000/{i}}},\n}}\n\n"
+            ),
+            _ => format!(
+                "@book{{Author{}_{year},\n  title = {{A Book}},\n  year = {{{year}}},\n  isbn = {{978-3-16-148410-{}}},\n  abstract = {{{}}},\n}}\n\n",
+                i - 3,
+                i % 10,
+                "A long abstract. ".repeat(20)
+            ),
+        };
+        bib.push_str(&entry);
+    }
+    bib
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    for size in SIZES {
+        let source = generate(size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| BibFile::new(black_box(source)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_lint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lint");
+    group.sample_size(10);
+    let linter = LinterState::default();
+    for size in SIZES {
+        let source = generate(size);
+        let bib = BibFile::new(&source);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &bib, |b, bib| {
+            b.iter(|| linter.lint_file(bib, bib.list_entries().collect()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_field_map(c: &mut Criterion) {
+    let mut group = c.benchmark_group("field-map");
+    group.sample_size(10);
+    let linter = LinterState::default();
+    for size in SIZES {
+        let source = generate(size);
+        let bib = BibFile::new(&source);
+        let entries = bib.list_entries().collect::<Vec<_>>();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("once", size), &entries, |b, entries| {
+            b.iter(|| {
+                for entry in entries {
+                    let fields = field_map(&bib, entry);
+                    black_box(linter.lint_entry(&bib, entry, &fields));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("twice", size), &entries, |b, entries| {
+            b.iter(|| {
+                for entry in entries {
+                    black_box(field_map(&bib, entry));
+                    let fields = field_map(&bib, entry);
+                    black_box(linter.lint_entry(&bib, entry, &fields));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_format(c: &mut Criterion) {
+    let mut group = c.benchmark_group("format");
+    group.sample_size(10);
+    let mut options = FormatOptions::new(LocalBibDb::new());
+    options.sort_fields = true;
+    options.min_field_length = Some(13);
+    for size in SIZES {
+        let source = generate(size);
+        let bib = BibFile::new(&source);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &bib, |b, bib| {
+            b.iter(|| {
+                let mut out = String::with_capacity(source.len());
+                write_bibfile(bib, &options, &mut out).unwrap();
+                out
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_similar_keys(c: &mut Criterion) {
+    let mut group = c.benchmark_group("similar-keys");
+    group.sample_size(10);
+    let names = ["author", "smith", "vanbenthem", "dijkstra", "lamport"];
+    let words = ["logic", "types", "games", "graphs", "proofs", "automata"];
+    for size in SIZES {
+        let keys = (0..size)
+            .map(|i| {
+                let name = names[i % names.len()];
+                let word = words[(i / 70) % words.len()];
+                format!("{}{}{}{}", name, 1950 + i % 70, word, i / 420)
+            })
+            .collect::<Vec<_>>();
+        let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &keys, |b, keys| {
+            b.iter(|| similar_keys(black_box(keys)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_import(c: &mut Criterion) {
+    let mut group = c.benchmark_group("import");
+    group.sample_size(10);
+    for size in SIZES {
+        let source = generate(size);
+        let whole = || LocalBibDb::new().import_bibtex(&source);
+        let stream = || {
+            LocalBibDb::new()
+                .import_reader(source.as_bytes(), &[])
+                .unwrap()
+        };
+        println!(
+            "import/{}: peak memory {} KiB (whole), {} KiB (stream), for {} KiB of source",
+            size,
+            peak_memory(whole) / 1024,
+            peak_memory(stream) / 1024,
+            source.len() / 1024
+        );
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("whole", size), &source, |b, _| {
+            b.iter(whole)
+        });
+        group.bench_with_input(BenchmarkId::new("stream", size), &source, |b, _| {
+            b.iter(stream)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parse,
+    bench_lint,
+    bench_field_map,
+    bench_format,
+    bench_similar_keys,
+    bench_import
+);
+criterion_main!(benches);
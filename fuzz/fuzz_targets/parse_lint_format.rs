@@ -0,0 +1,21 @@
+//! Feeds arbitrary bytes through the pipeline of `bibadac format`:
+//! decoding, parsing, linting, and formatting, which must not panic
+//! (the output is a `String`, so that it is UTF-8 by construction).
+//! Run with `cargo +nightly fuzz run parse_lint_format`,
+//! and add the minimized crashes to `tests/fixtures/fuzz`.
+#![no_main]
+
+use bibadac::bibdb::LocalBibDb;
+use bibadac::bibtex::BibFile;
+use bibadac::encoding::decode;
+use bibadac::format::{FormatOptions, write_bibfile};
+use bibadac::linter::LinterState;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (source, _) = decode(data.to_vec());
+    let bib = BibFile::new(&source);
+    LinterState::default().lint_file(&bib, bib.list_entries().collect());
+    let mut out = String::new();
+    write_bibfile(&bib, &FormatOptions::new(LocalBibDb::new()), &mut out).unwrap();
+});
@@ -0,0 +1,134 @@
+//! This file is responsible for recognizing the entries of a checked
+//! bibliography that are already in a reference bibliography, as with
+//! `bibadac check new.bib --against library.bib`.
+//!
+//! An entry is the same work as an entry of the reference when they have
+//! the same doi (case insensitive, without its resolver), the same arXiv
+//! eprint (without its version), or the same normalized title and last
+//! name of the first author (see `preprints::paper_key`).
+use std::collections::HashMap;
+
+use crate::arxiv_identifiers::ArxivId;
+use crate::bibtex::BibFile;
+use crate::doi_identifiers::extract_doi;
+use crate::linter::{field_map, FieldMap};
+use crate::preprints::{paper_key, PaperKey};
+
+/// What identifies the work of an entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WorkId {
+    Doi(String),
+    /// An eprint, without its version when it is an arXiv one
+    Eprint(String),
+    Paper(PaperKey),
+}
+
+/// The identifiers of the work of an entry, from the most to the least
+/// reliable (an entry without them is never found in the reference).
+pub fn work_ids(fields: &FieldMap) -> Vec<WorkId> {
+    let mut ids = vec![];
+    if let Some(doi) = fields.get("doi").map(|doi| doi.trim())
+        && !doi.is_empty()
+    {
+        ids.push(WorkId::Doi(extract_doi(doi).unwrap_or(doi).to_lowercase()));
+    }
+    if let Some(eprint) = fields.get("eprint").map(|eprint| eprint.trim())
+        && !eprint.is_empty()
+    {
+        let eprint = match ArxivId::try_from(eprint) {
+            Ok(id) => id.id.to_string(),
+            Err(()) => eprint.to_string(),
+        };
+        ids.push(WorkId::Eprint(eprint));
+    }
+    let paper = fields
+        .get("title")
+        .zip(fields.get("author"))
+        .and_then(|(title, author)| paper_key(title, author));
+    ids.extend(paper.map(WorkId::Paper));
+    ids
+}
+
+/// The works of the reference bibliography, with the key of their entry.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceIndex {
+    keys: HashMap<WorkId, String>,
+}
+
+impl ReferenceIndex {
+    /// Learns the works of the entries of a bibliography
+    /// (the first entry of a work when there are several).
+    pub fn import(&mut self, file: &BibFile) {
+        for entry in file.list_entries() {
+            let key = file.get_slice(entry.key).trim();
+            for id in work_ids(&field_map(file, &entry)) {
+                self.keys.entry(id).or_insert_with(|| key.to_string());
+            }
+        }
+    }
+
+    /// The key of the entry of the reference with the same work
+    /// as the entry of these fields, if any.
+    pub fn find(&self, fields: &FieldMap) -> Option<&str> {
+        work_ids(fields)
+            .iter()
+            .find_map(|id| self.keys.get(id))
+            .map(String::as_str)
+    }
+
+    /// The works, with the key of their entry, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&WorkId, &str)> {
+        self.keys.iter().map(|(id, key)| (id, key.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields<'a>(pairs: &[(&'a str, &'a str)]) -> FieldMap<'a> {
+        pairs.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_work_ids() {
+        let ids = work_ids(&fields(&[
+            ("doi", "https://doi.org/10.1145/ABC"),
+            ("eprint", "2101.00001v2"),
+            ("title", "{M}odel Checking"),
+            ("author", "Doe, Jane and Roe, R."),
+        ]));
+        assert_eq!(
+            ids,
+            vec![
+                WorkId::Doi("10.1145/abc".into()),
+                WorkId::Eprint("2101.00001".into()),
+                WorkId::Paper(("model checking".into(), "doe".into())),
+            ]
+        );
+        assert!(work_ids(&fields(&[("title", "Untitled"), ("doi", " ")])).is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let mut index = ReferenceIndex::default();
+        let library = "@article{doe2020, doi = {10.1145/ABC}, title = {A}, author = {Doe, J.}}\n\
+                       @misc{roe2021, eprint = {2101.00001v1}, title = {B}, author = {Roe, R.}}\n";
+        index.import(&BibFile::new(library));
+
+        // (fields of the checked entry, key in the library)
+        let matrix = [
+            (vec![("doi", "10.1145/abc")], Some("doe2020")),
+            (vec![("eprint", "2101.00001v3")], Some("roe2021")),
+            (
+                vec![("title", "{B}"), ("author", "R. Roe")],
+                Some("roe2021"),
+            ),
+            (vec![("title", "B"), ("author", "Doe, J.")], None),
+            (vec![("doi", "10.1145/abd")], None),
+        ];
+        for (checked, key) in matrix {
+            assert_eq!(index.find(&fields(&checked)), key, "{:?}", checked);
+        }
+    }
+}
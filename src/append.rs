@@ -0,0 +1,121 @@
+//! This file is responsible for writing the entries fetched by
+//! `bibadac setup` to its output file (`--to-file`), which several
+//! instances may append to at the same time, e.g. when the identifiers
+//! are sharded across parallel runs.
+//!
+//! The entries are written in a single call, while holding an exclusive
+//! (advisory) lock on the file, so that the entries of different runs
+//! are never interleaved. Alternatively (`--to-file-per-run`), every run
+//! writes to a new file of its own, to be merged afterwards.
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The entries, one after the other, each followed by a newline.
+fn entries_text<S: AsRef<str>>(entries: &[S]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.as_ref()))
+        .collect()
+}
+
+/// Appends the entries to the file, creating it if needed.
+pub fn append_entries<S: AsRef<str>>(path: &Path, entries: &[S]) -> io::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let text = entries_text(entries);
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.lock()?;
+    let written = file.write_all(text.as_bytes());
+    file.unlock()?;
+    written
+}
+
+/// Writes the entries to a new file of the directory, with a name
+/// unique to the run (`setup-<pid>-<time>.bib`), returning its path.
+pub fn write_per_run<S: AsRef<str>>(dir: &Path, entries: &[S]) -> io::Result<PathBuf> {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    for attempt in 0.. {
+        let path = dir.join(format!(
+            "setup-{}-{}.bib",
+            std::process::id(),
+            nanos + attempt
+        ));
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        file.write_all(entries_text(entries).as_bytes())?;
+        return Ok(path);
+    }
+    unreachable!("no file name is left")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(thread: usize, i: usize) -> String {
+        // long enough to need several writes without the lock
+        format!(
+            "@misc{{t{}_{},\n  note = {{{}}},\n}}\n",
+            thread,
+            i,
+            "x".repeat(8192)
+        )
+    }
+
+    #[test]
+    fn test_concurrent_appends() {
+        let dir = crate::temp_dir("append");
+        let path = dir.join("shared.bib");
+        let threads = (0..8)
+            .map(|t| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        let entries = [entry(t, 2 * i), entry(t, 2 * i + 1)];
+                        append_entries(&path, &entries).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let mut seen = vec![];
+        for written in content.split_inclusive("}\n\n") {
+            let key = &written["@misc{".len()..written.find(',').unwrap()];
+            let (t, i) = key[1..].split_once('_').unwrap();
+            let (t, i) = (t.parse().unwrap(), i.parse().unwrap());
+            assert_eq!(written, format!("{}\n", entry(t, i)));
+            seen.push((t, i));
+        }
+        seen.sort();
+        let expected = (0..8)
+            .flat_map(|t| (0..40).map(move |i| (t, i)))
+            .collect::<Vec<_>>();
+        assert_eq!(seen, expected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_per_run() {
+        let dir = crate::temp_dir("per-run");
+        let a = write_per_run(&dir, &["@misc{a,}"]).unwrap();
+        let b = write_per_run(&dir, &["@misc{b,}"]).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "@misc{a,}\n");
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "@misc{b,}\n");
+        append_entries::<&str>(&dir.join("empty.bib"), &[]).unwrap();
+        assert!(!dir.join("empty.bib").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
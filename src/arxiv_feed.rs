@@ -0,0 +1,239 @@
+//! This file is responsible for reading the Atom feed of the arXiv API
+//! (`https://arxiv.org/api/query?id_list=<id>`), to write the BibTeX
+//! entry of a preprint in `bibadac setup --arxiv <id>`.
+//!
+//! The `<id>` of an entry of the feed is the url of its version
+//! (`http://arxiv.org/abs/2105.01234v3`), so that an identifier given
+//! without a version is written pinned to the latest one. The pinned
+//! `eprint` fields of the entries of `setup` are then the latest versions
+//! that `check --file-db` compares the other files with (see
+//! `LintConfig::import_helper`).
+//!
+//! The feed is read with a few string searches rather than a full XML
+//! parser: the elements of an entry are neither nested in one another
+//! nor written with CDATA sections.
+use crate::arxiv_identifiers::ArxivId;
+use crate::bibtex::unbalanced_brace;
+use crate::dates::Date;
+use crate::linter::unescaped_specials;
+
+/// An entry of the feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    /// The identifier, without its version
+    pub id: String,
+    pub version: Option<usize>,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: String,
+    /// The day of the first version (`2021-05-04`)
+    pub published: String,
+    pub primary_class: Option<String>,
+    pub doi: Option<String>,
+}
+
+/// The attributes and the content of the `<tag>` elements of `xml`.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<(&'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut found = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        // `<id` is not the start of `<identifier>`
+        if !after.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            rest = after;
+            continue;
+        }
+        let Some(end) = after.find('>') else {
+            break;
+        };
+        if let Some(attributes) = after[..end].strip_suffix('/') {
+            found.push((attributes, ""));
+            rest = &after[end + 1..];
+            continue;
+        }
+        let body = &after[end + 1..];
+        let Some(stop) = body.find(&close) else {
+            break;
+        };
+        found.push((&after[..end], &body[..stop]));
+        rest = &body[stop + close.len()..];
+    }
+    found
+}
+
+/// The value of an attribute of an element.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{}=\"", name);
+    let (start, _) = attributes
+        .match_indices(&pattern)
+        .find(|(i, _)| attributes[..*i].ends_with(char::is_whitespace))?;
+    let value = &attributes[start + pattern.len()..];
+    Some(&value[..value.find('"')?])
+}
+
+/// The text of an element, on a single line.
+fn text(xml: &str) -> String {
+    let text = xml
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The text of the first `<tag>` element of `xml`, if not empty.
+fn first_text(xml: &str, tag: &str) -> Option<String> {
+    let (_, content) = elements(xml, tag).into_iter().next()?;
+    Some(text(content)).filter(|t| !t.is_empty())
+}
+
+/// The entries of a feed, leaving out the errors of the API (whose
+/// `<id>` is not the url of a preprint).
+pub fn parse_feed(feed: &str) -> Vec<FeedEntry> {
+    let mut entries = vec![];
+    for (_, entry) in elements(feed, "entry") {
+        let Some(url) = first_text(entry, "id") else {
+            continue;
+        };
+        let Some(id) = ArxivId::extract(&url) else {
+            continue;
+        };
+        entries.push(FeedEntry {
+            id: id.id.to_string(),
+            version: id.version,
+            title: first_text(entry, "title").unwrap_or_default(),
+            authors: elements(entry, "author")
+                .iter()
+                .filter_map(|(_, author)| first_text(author, "name"))
+                .collect(),
+            summary: first_text(entry, "summary").unwrap_or_default(),
+            published: first_text(entry, "published")
+                .map(|date| date.chars().take(10).collect())
+                .unwrap_or_default(),
+            primary_class: elements(entry, "arxiv:primary_category")
+                .iter()
+                .find_map(|(attributes, _)| attribute(attributes, "term"))
+                .map(str::to_string),
+            doi: first_text(entry, "arxiv:doi"),
+        });
+    }
+    entries
+}
+
+/// The latest version of a preprint in the feed.
+pub fn latest_version(entries: &[FeedEntry], id: &str) -> Option<usize> {
+    entries
+        .iter()
+        .filter(|e| e.id == id)
+        .filter_map(|e| e.version)
+        .max()
+}
+
+impl FeedEntry {
+    /// The BibTeX entry of the preprint, whose `eprint` is pinned to
+    /// `version`, with a `note` telling the day it was `retrieved` on.
+    pub fn to_bibtex(&self, version: Option<usize>, retrieved: Option<&Date>) -> String {
+        let eprint = ArxivId {
+            id: &self.id,
+            version,
+        };
+        let year = self.published.get(..4).unwrap_or_default();
+        let note = version
+            .zip(retrieved)
+            .map(|(v, day)| format!("arXiv version {}, retrieved {}", v, day));
+        let fields = [
+            ("title", Some(self.title.clone())),
+            ("author", Some(self.authors.join(" and "))),
+            ("year", Some(year.to_string())),
+            ("abstract", Some(self.summary.clone())),
+            ("archivePrefix", Some("arXiv".to_string())),
+            ("eprint", Some(eprint.to_string())),
+            ("primaryClass", self.primary_class.clone()),
+            ("doi", self.doi.clone()),
+            ("note", note),
+        ];
+        let mut out = format!("@misc{{{},\n", self.id);
+        for (name, value) in fields {
+            let Some(mut value) = value.filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            if unbalanced_brace(&value).is_some() {
+                value.retain(|c| c != '{' && c != '}');
+            }
+            // the text is plain, where LaTeX reads `%` and `&` as commands
+            if !matches!(name, "doi" | "eprint") {
+                let braced = format!("{{{}}}", value);
+                for (span, _) in unescaped_specials(&braced, 0).into_iter().rev() {
+                    value.insert(span.start - 1, '\\');
+                }
+            }
+            out.push_str(&format!("  {} = {{{}}},\n", name, value));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::{Bibliography, LintConfig};
+
+    fn feed() -> String {
+        crate::read_fixture("arxiv", "2105.01234.xml")
+    }
+
+    #[test]
+    fn test_elements() {
+        let xml = r#"<entry><id>a</id><identifier>b</identifier><c x="1" term="t"/></entry>"#;
+        assert_eq!(elements(xml, "id"), vec![("", "a")]);
+        assert_eq!(elements(xml, "c"), vec![(r#" x="1" term="t""#, "")]);
+        assert_eq!(attribute(r#" x="1" term="t""#, "term"), Some("t"));
+        assert_eq!(attribute(r#" xterm="1""#, "term"), None);
+        assert_eq!(text("\n  Words &amp;\n  Trees &lt;3 "), "Words & Trees <3");
+    }
+
+    #[test]
+    fn test_parse_feed() {
+        let entries = parse_feed(&feed());
+        assert_eq!(entries.len(), 2);
+        let latest = &entries[1];
+        assert_eq!(latest.id, "2105.01234");
+        assert_eq!(latest.version, Some(3));
+        assert_eq!(latest.title, "Regular Languages of Words & Trees");
+        assert_eq!(latest.authors, vec!["Jane Doe", "Jean Dupont"]);
+        assert_eq!(latest.published, "2021-05-04");
+        assert_eq!(latest.primary_class.as_deref(), Some("cs.FL"));
+        assert_eq!(latest_version(&entries, "2105.01234"), Some(3));
+        assert_eq!(latest_version(&entries, "2105.04321"), None);
+
+        // the errors of the API are not preprints
+        let error = "<feed><entry><id>http://arxiv.org/api/errors#incorrect_id_format_for_1</id>\
+                     <title>Error</title></entry></feed>";
+        assert!(parse_feed(error).is_empty());
+    }
+
+    #[test]
+    fn test_to_bibtex() {
+        let entries = parse_feed(&feed());
+        let version = latest_version(&entries, "2105.01234");
+        let day = Date::parse("2021-09-02").unwrap();
+        let entry = entries[1].to_bibtex(version, Some(&day));
+        assert!(entry.starts_with("@misc{2105.01234,\n"));
+        assert!(entry.contains("  eprint = {2105.01234v3},\n"));
+        assert!(entry.contains("  year = {2021},\n"));
+        assert!(entry.contains("  title = {Regular Languages of Words \\& Trees},\n"));
+        assert!(entry.contains("  abstract = {We study regular languages of words and trees, and of their $\\{0, 1\\}$-labellings.},\n"));
+        assert!(entry.contains("  note = {arXiv version 3, retrieved 2021-09-02},\n"));
+        assert!(!entries[1].to_bibtex(version, None).contains("note"));
+
+        // the pinned version is the latest one for `check --file-db`
+        let mut config = LintConfig::default();
+        config.import_helper(&Bibliography::from(entry));
+        assert_eq!(config.arxiv_latest.get("2105.01234"), Some(&3));
+    }
+}
@@ -57,6 +57,24 @@ impl<'a> Display for ArxivId<'a> {
     }
 }
 
+impl<'a> ArxivId<'a> {
+    /// Extracts an arxiv identifier from an arxiv.org url
+    /// (`https://arxiv.org/abs/<id>`, `https://arxiv.org/pdf/<id>.pdf`, ...)
+    pub fn extract(url: &'a str) -> Option<ArxivId<'a>> {
+        let start = ["arxiv.org/abs/", "arxiv.org/pdf/"]
+            .iter()
+            .find_map(|prefix| Some(url.find(prefix)? + prefix.len()))?;
+        let rest = &url[start..];
+        let end = rest.find(['?', '#']).unwrap_or(rest.len());
+        let id = rest[..end].trim_end_matches('/');
+        let id = id.strip_suffix(".pdf").unwrap_or(id);
+        if id.is_empty() {
+            return None;
+        }
+        ArxivId::try_from(id).ok()
+    }
+}
+
 impl ArxivId<'_> {
     pub fn to_string(&self) -> String {
         match self.version {
@@ -77,3 +95,17 @@ impl ArxivId<'_> {
         format!("https://arxiv.org/api/query?id_list={}", self.id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let id = ArxivId::extract("https://arxiv.org/abs/2105.01234v2").unwrap();
+        assert_eq!((id.id, id.version), ("2105.01234", Some(2)));
+        let id = ArxivId::extract("http://arxiv.org/pdf/2105.01234.pdf").unwrap();
+        assert_eq!((id.id, id.version), ("2105.01234", None));
+        assert!(ArxivId::extract("https://example.com/abs/2105.01234").is_none());
+    }
+}
@@ -0,0 +1,141 @@
+//! This file is responsible for the assertions of `bibadac assert`,
+//! which a bibliography must satisfy before a release: a minimal
+//! number of entries, conditions on every entry (see the `filter`
+//! module), and entry types that are not allowed.
+use std::fmt::{self, Display, Formatter};
+
+use crate::filter::{Filter, FilterError};
+use crate::merge::OwnedEntry;
+
+#[derive(Debug, Clone, Default)]
+pub struct Assertions {
+    pub min_entries: Option<usize>,
+    /// The conditions on every entry, as written, and parsed
+    pub every: Vec<(String, Filter)>,
+    /// Lowercase entry types, without `@`
+    pub forbidden_types: Vec<String>,
+}
+
+/// An assertion that does not hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TooFewEntries { count: usize, min: usize },
+    Condition { key: String, condition: String },
+    ForbiddenType { key: String, entrytype: String },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::TooFewEntries { count, min } => {
+                write!(f, "{} entries, expected at least {}", count, min)
+            }
+            Violation::Condition { key, condition } => {
+                write!(f, "{} does not satisfy `{}`", key, condition)
+            }
+            Violation::ForbiddenType { key, entrytype } => {
+                write!(f, "{} is a forbidden @{}", key, entrytype)
+            }
+        }
+    }
+}
+
+impl Assertions {
+    /// Adds a condition on every entry.
+    pub fn require(&mut self, condition: &str) -> Result<(), FilterError> {
+        let filter = condition.parse()?;
+        self.every.push((condition.to_string(), filter));
+        Ok(())
+    }
+
+    /// Forbids an entry type (`misc` or `@misc`).
+    pub fn forbid_type(&mut self, entrytype: &str) {
+        let entrytype = entrytype.trim_start_matches('@').to_lowercase();
+        if !self.forbidden_types.contains(&entrytype) {
+            self.forbidden_types.push(entrytype);
+        }
+    }
+
+    /// The assertions that an entry does not satisfy.
+    pub fn check_entry(&self, entry: &OwnedEntry) -> Vec<Violation> {
+        let mut violations = vec![];
+        let entrytype = entry.entrytype.trim_start_matches('@').to_lowercase();
+        if self.forbidden_types.contains(&entrytype) {
+            violations.push(Violation::ForbiddenType {
+                key: entry.key.clone(),
+                entrytype,
+            });
+        }
+        for (condition, filter) in self.every.iter() {
+            if !filter.matches(entry) {
+                violations.push(Violation::Condition {
+                    key: entry.key.clone(),
+                    condition: condition.clone(),
+                });
+            }
+        }
+        violations
+    }
+
+    /// The assertions on the number of entries of all the files.
+    pub fn check_count(&self, count: usize) -> Option<Violation> {
+        match self.min_entries {
+            Some(min) if count < min => Some(Violation::TooFewEntries { count, min }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, entrytype: &str, fields: &[(&str, &str)]) -> OwnedEntry {
+        OwnedEntry {
+            key: key.to_string(),
+            entrytype: entrytype.to_string(),
+            fields: fields
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            annotations: vec![],
+        }
+    }
+
+    #[test]
+    fn test_assertions() {
+        let mut assertions = Assertions {
+            min_entries: Some(3),
+            ..Assertions::default()
+        };
+        assertions.require("has(doi) || has(eprint)").unwrap();
+        assertions.forbid_type("@Misc");
+        assert!(assertions.require("has(").is_err());
+
+        let good = entry("good", "@article", &[("doi", "{10.1/a}")]);
+        assert!(assertions.check_entry(&good).is_empty());
+        let bad = entry("bad", "@MISC", &[("url", "{https://example.org}")]);
+        assert_eq!(
+            assertions.check_entry(&bad),
+            vec![
+                Violation::ForbiddenType {
+                    key: "bad".into(),
+                    entrytype: "misc".into()
+                },
+                Violation::Condition {
+                    key: "bad".into(),
+                    condition: "has(doi) || has(eprint)".into()
+                },
+            ]
+        );
+        assert_eq!(
+            assertions.check_count(2),
+            Some(Violation::TooFewEntries { count: 2, min: 3 })
+        );
+        assert_eq!(assertions.check_count(3), None);
+        assert_eq!(
+            assertions.check_entry(&bad)[1].to_string(),
+            "bad does not satisfy `has(doi) || has(eprint)`"
+        );
+    }
+}
@@ -1,27 +1,107 @@
-/// This is a minimal library
-/// file to write list of authors
-/// in the "BibTeX" format, that is
-/// "Author1, Author1 and Author2 and Author3, Author3"
-///
-/// This provides a way to check validity of a given string
-/// and can also be used to *format* the list of authors
-/// correctly.
+//! This is a minimal library
+//! file to write list of authors
+//! in the "BibTeX" format, that is
+//! "Author1, Author1 and Author2 and Author3, Author3"
+//!
+//! This provides a way to check validity of a given string
+//! and can also be used to *format* the list of authors
+//! correctly.
+//!
+//! The ties (`D.~E. Knuth`) and the no-break spaces, such as the
+//! narrow ones of French names, join the words they separate: they
+//! are kept as they are, and never replaced by a breaking space.
+
+/// The characters that join two words: the tie `~`,
+/// the no-break space, and the narrow no-break space.
+pub const TIES: [char; 3] = ['~', '\u{A0}', '\u{202F}'];
+
+/// Whether `c` separates two words (a space that is not a tie).
+pub fn is_breaking_space(c: char) -> bool {
+    c.is_whitespace() && !TIES.contains(&c)
+}
+
+/// The depth of the braces before every character of `text`.
+fn brace_depths(text: &str) -> impl Iterator<Item = (usize, char, i32)> + '_ {
+    text.char_indices().scan(0, |depth, (i, c)| {
+        let before = *depth;
+        match c {
+            '{' => *depth += 1,
+            '}' => *depth -= 1,
+            _ => {}
+        }
+        Some((i, c, before))
+    })
+}
+
+/// The words of a name, the tied words, and the words
+/// protected by braces (`{World Health Organization}`),
+/// being a single word.
+fn words(author: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    for (i, c, depth) in brace_depths(author) {
+        if depth == 0 && is_breaking_space(c) {
+            words.push(&author[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    words.push(&author[start..]);
+    words.retain(|w| !w.is_empty());
+    words
+}
+
+/// The names of a list of people, separated by an `and`
+/// outside of braces (`{Barnes and Noble}` is a single name).
+fn names(authors: &str) -> Vec<&str> {
+    const AND: &str = " and ";
+    let mut names = vec![];
+    let mut start = 0;
+    for (i, _, depth) in brace_depths(authors) {
+        if depth == 0 && i >= start && authors[i..].starts_with(AND) {
+            names.push(&authors[start..i]);
+            start = i + AND.len();
+        }
+    }
+    names.push(&authors[start..]);
+    names
+}
+
+/// Whether the words end with `et al.` (or a tied `et~al.`),
+/// and how many words it takes.
+fn et_al_len(words: &[&str]) -> usize {
+    let is_tied_et_al = |word: &str| {
+        let tie = word.strip_prefix("et").and_then(|w| w.strip_suffix("al."));
+        tie.is_some_and(|tie| tie.chars().count() == 1 && tie.chars().all(|c| TIES.contains(&c)))
+    };
+    match words {
+        [.., "et", "al."] => 2,
+        [.., last] if is_tied_et_al(last) => 1,
+        _ => 0,
+    }
+}
 
 pub fn format_authors(authors: &str) -> String {
-    authors
-        .split(" and ")
+    names(authors)
+        .into_iter()
         .map(|author| {
             if author.contains(",") {
                 return author.to_string();
             }
-            let parts = author.trim().split_whitespace().collect::<Vec<&str>>();
-            if parts.len() == 1 {
-                parts[0].into()
+            let parts = words(author);
+            let (parts, et_al) = parts.split_at(parts.len() - et_al_len(&parts));
+            if parts.len() <= 1 {
+                parts
+                    .iter()
+                    .chain(et_al)
+                    .cloned()
+                    .collect::<Vec<&str>>()
+                    .join(" ")
             } else {
                 let new_first = parts[parts.len() - 1].to_string() + ",";
                 vec![&new_first.as_str()]
                     .into_iter()
                     .chain(parts[0..parts.len() - 1].iter())
+                    .chain(et_al)
                     .cloned()
                     .collect::<Vec<&str>>()
                     .join(" ")
@@ -32,10 +112,10 @@ pub fn format_authors(authors: &str) -> String {
 }
 
 pub fn check_authors(authors: &str) -> bool {
-    let authors = authors.split(" and ");
-    for author in authors {
-        let parts = author.trim().split_whitespace().collect::<Vec<&str>>();
-        if parts.len() == 1 {
+    for author in names(authors) {
+        let parts = words(author);
+        let parts = &parts[..parts.len() - et_al_len(&parts)];
+        if parts.len() <= 1 {
             continue;
         }
         if parts.len() >= 2 {
@@ -73,6 +153,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_format_braced_authors() {
+        assert_eq!(
+            format_authors("{World Health Organization} and Jane Doe"),
+            "{World Health Organization} and Doe, Jane"
+        );
+        assert_eq!(format_authors("{Barnes and Noble}"), "{Barnes and Noble}");
+        assert_eq!(
+            format_authors("Jean {de La} Fontaine"),
+            "Fontaine, Jean {de La}"
+        );
+        assert!(check_authors("{World Health Organization} and Doe, Jane"));
+    }
+
+    #[test]
+    fn test_format_tied_authors() {
+        assert_eq!(format_authors("D.~E. Knuth"), "Knuth, D.~E.");
+        assert_eq!(format_authors("Donald~E. Knuth"), "Knuth, Donald~E.");
+        // narrow no-break space before the initial
+        assert_eq!(
+            format_authors("Jean-Pierre\u{202F}M. Dupont"),
+            "Dupont, Jean-Pierre\u{202F}M."
+        );
+        assert_eq!(format_authors("Jean\u{A0}Dupont"), "Jean\u{A0}Dupont");
+        assert_eq!(format_authors("D.~E. Knuth et~al."), "Knuth, D.~E. et~al.");
+        assert_eq!(format_authors("D. Knuth et al."), "Knuth, D. et al.");
+        assert_eq!(format_authors("Knuth et~al."), "Knuth et~al.");
+        // formatted names are left untouched
+        for authors in [
+            "Knuth, D.~E. and Dupont, Jean\u{202F}M.",
+            "Knuth, D.~E. et~al.",
+            "Knuth, D.\u{A0}E. and others",
+        ] {
+            assert_eq!(format_authors(authors), authors);
+            assert!(check_authors(authors));
+        }
+        assert!(check_authors("Knuth et~al."));
+        assert!(!check_authors("D.~E. Knuth"));
+    }
+
     #[test]
     fn test_check_authors() {
         let authors = "Author1 and A B C and Author3";
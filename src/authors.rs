@@ -0,0 +1,444 @@
+//! This file is responsible for the names of the people of the entries
+//! (`author` and `editor` fields), and for `bibadac authors`, which groups
+//! the spellings of the same person across a bibliography, such as
+//! `Jérôme Leroux`, `Jerome Leroux`, and `J. Leroux`.
+//!
+//! A name is read as BibTeX does: `von Last, Jr, First`, `von Last, First`,
+//! or `First von Last`, where the von part is made of the lowercase words
+//! before the last name (`Ludwig van Beethoven`). Braces protect their
+//! content, so that `{Barnes and Noble}` is a single name.
+//!
+//! Two spellings are the same person (see `same_person`) when their last
+//! names have the same collation key (LaTeX accents, Unicode accents, braces,
+//! and case are ignored), and their given names agree one by one: the same
+//! name, or an initial and a name starting with it. Since false merges are
+//! worse than misses, a spelling joins a cluster only when it is the same
+//! person as every spelling of the cluster, and of a single cluster:
+//! `J. Leroux` stays alone when there are a `Jérôme` and a `Jean Leroux`.
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use crate::author_format::{is_breaking_space, TIES};
+use crate::collation::collation_key;
+
+/// A person of an `author` or `editor` field, as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Author {
+    pub first: String,
+    pub von: String,
+    pub last: String,
+    pub jr: String,
+}
+
+/// A given name, folded by its collation key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GivenName {
+    Initial(char),
+    Name(String),
+}
+
+/// Splits `s` at the occurrences of `sep` outside of braces.
+fn split_unbraced<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let (mut depth, mut start, mut i) = (0usize, 0, 0);
+    while let Some(c) = s[i..].chars().next() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && s[i..].starts_with(sep) => {
+                parts.push(&s[start..i]);
+                i += sep.len();
+                start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Whether a word belongs to the von part: its first letter outside
+/// of braces is lowercase, a special character such as `{\'E}` having
+/// the case of its letter.
+fn is_von(word: &str) -> bool {
+    let mut depth = 0;
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if depth == 0 && chars.peek() == Some(&'\\') => {
+                chars.next();
+                chars.next_if(|c| !c.is_alphabetic());
+                return chars
+                    .find(|c| c.is_alphabetic())
+                    .is_some_and(char::is_lowercase);
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if depth == 0 && c.is_alphabetic() => return c.is_lowercase(),
+            _ => {}
+        }
+    }
+    false
+}
+
+impl Author {
+    /// Reads a single name, `None` when it has more than two commas.
+    pub fn parse(name: &str) -> Option<Author> {
+        let parts = split_unbraced(name, ",")
+            .into_iter()
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        let (von_last, jr, first) = match parts[..] {
+            [first_von_last] => {
+                let words = split_unbraced(first_von_last, " ");
+                let last_word = words.len() - 1;
+                let von_start = words[..last_word].iter().position(|w| is_von(w));
+                let first = &words[..von_start.unwrap_or(last_word)];
+                let author = Author::from_von_last(&words[first.len()..]);
+                return Some(Author {
+                    first: first.join(" "),
+                    ..author
+                });
+            }
+            [von_last, first] => (von_last, "", first),
+            [von_last, jr, first] => (von_last, jr, first),
+            _ => return None,
+        };
+        Some(Author {
+            first: first.to_string(),
+            jr: jr.to_string(),
+            ..Author::from_von_last(&split_unbraced(von_last, " "))
+        })
+    }
+
+    /// The von and last parts, the last word being always in the last name.
+    fn from_von_last(words: &[&str]) -> Author {
+        let last_word = words.len().saturating_sub(1);
+        let von_end = words[..last_word]
+            .iter()
+            .rposition(|w| is_von(w))
+            .map_or(0, |i| i + 1);
+        Author {
+            first: String::new(),
+            von: words[..von_end].join(" "),
+            last: words[von_end..].join(" "),
+            jr: String::new(),
+        }
+    }
+
+    /// The key of the last name (with its von and jr parts).
+    pub fn surname_key(&self) -> String {
+        let surname = [&self.von, &self.last, &self.jr]
+            .map(|s| s.as_str())
+            .join(" ");
+        collation_key(&surname)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The given names, split at spaces, ties, dots, and hyphens
+    /// (`J.-P.` and `Jean-Pierre` both have two given names).
+    pub fn given_names(&self) -> Vec<GivenName> {
+        self.first
+            .split(|c: char| c.is_whitespace() || TIES.contains(&c) || c == '.' || c == '-')
+            .filter_map(|word| {
+                let key = collation_key(word)
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .collect::<String>();
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (None, _) => None,
+                    (Some(c), None) => Some(GivenName::Initial(c)),
+                    _ => Some(GivenName::Name(key)),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Display for Author {
+    /// The name written `von Last, Jr, First`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.von.is_empty() {
+            write!(f, "{} ", self.von)?;
+        }
+        write!(f, "{}", self.last)?;
+        if !self.jr.is_empty() {
+            write!(f, ", {}", self.jr)?;
+        }
+        if !self.first.is_empty() {
+            write!(f, ", {}", self.first)?;
+        }
+        Ok(())
+    }
+}
+
+/// The people of an `author` or `editor` field (without `others`).
+pub fn parse_authors(value: &str) -> Vec<Author> {
+    let value = value
+        .split(is_breaking_space)
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    split_unbraced(&value, " and ")
+        .into_iter()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && *name != "others")
+        .filter_map(Author::parse)
+        .collect()
+}
+
+/// The field that keeps the full `author` field of the entries
+/// truncated by `format --max-authors`.
+pub const AUTHORS_BACKUP_FIELD: &str = "authorsfull";
+
+/// The first `max` people of an `author` field (without its delimiters)
+/// followed by `and others`, when it has more than `max` of them. Braces
+/// protect their content, so that `{Barnes and Noble}` is a single name,
+/// and the fields that already have `others` are left as they are.
+pub fn truncate_authors(value: &str, max: usize) -> Option<String> {
+    let value = value
+        .split(is_breaking_space)
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let names = split_unbraced(&value, " and ")
+        .into_iter()
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    if names.len() <= max || names.contains(&"others") {
+        return None;
+    }
+    Some(format!("{} and others", names[..max].join(" and ")))
+}
+
+/// Whether two spellings may be the same person: the same last name
+/// up to accents and case, and given names that agree one by one (the
+/// same name, or an initial and a name starting with it), the extra
+/// given names of one of them being ignored. A name without given names
+/// is only the same person as another name without given names.
+pub fn same_person(a: &Author, b: &Author) -> bool {
+    if a.surname_key() != b.surname_key() {
+        return false;
+    }
+    let (a, b) = (a.given_names(), b.given_names());
+    if a.is_empty() || b.is_empty() {
+        return a.is_empty() && b.is_empty();
+    }
+    a.iter().zip(b.iter()).all(|pair| match pair {
+        (GivenName::Name(x), GivenName::Name(y)) => x == y,
+        (GivenName::Initial(c), GivenName::Name(name))
+        | (GivenName::Name(name), GivenName::Initial(c)) => name.starts_with(*c),
+        (GivenName::Initial(c), GivenName::Initial(d)) => c == d,
+    })
+}
+
+/// A spelling of a person (written `von Last, Jr, First`),
+/// and the keys of the entries using it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Spelling {
+    pub name: String,
+    pub keys: Vec<String>,
+}
+
+/// The spellings of what is likely the same person.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthorCluster {
+    pub spellings: Vec<Spelling>,
+}
+
+/// Groups the spellings of the people of the entries, given the key of
+/// the entries along with the value of their `author` or `editor` field.
+///
+/// The most complete spellings (more given names written in full) are
+/// placed first, so that they form the clusters that the initials join.
+/// Every spelling is in exactly one cluster, most of them alone.
+pub fn author_clusters<'a>(
+    people: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Vec<AuthorCluster> {
+    let mut spellings: BTreeMap<String, (Author, Vec<String>)> = BTreeMap::new();
+    for (key, value) in people {
+        for author in parse_authors(value) {
+            let (_, keys) = spellings
+                .entry(author.to_string())
+                .or_insert_with(|| (author, vec![]));
+            if keys.last().is_none_or(|last| last != key) {
+                keys.push(key.to_string());
+            }
+        }
+    }
+
+    let mut by_surname: BTreeMap<String, Vec<(String, Author, Vec<String>)>> = BTreeMap::new();
+    for (name, (author, keys)) in spellings {
+        by_surname
+            .entry(author.surname_key())
+            .or_default()
+            .push((name, author, keys));
+    }
+
+    let mut clusters = vec![];
+    for (_, mut spellings) in by_surname {
+        spellings.sort_by_cached_key(|(name, author, _)| {
+            let given = author.given_names();
+            let full = given
+                .iter()
+                .filter(|g| matches!(g, GivenName::Name(_)))
+                .count();
+            (Reverse(full), Reverse(given.len()), name.clone())
+        });
+        let mut groups: Vec<Vec<(String, Author, Vec<String>)>> = vec![];
+        for spelling in spellings {
+            let mut candidates = groups.iter_mut().filter(|group| {
+                group
+                    .iter()
+                    .all(|(_, author, _)| same_person(author, &spelling.1))
+            });
+            match (candidates.next(), candidates.next()) {
+                (Some(group), None) => group.push(spelling),
+                // a new person, or a spelling that fits several of them
+                _ => groups.push(vec![spelling]),
+            }
+        }
+        clusters.extend(groups.into_iter().map(|group| {
+            AuthorCluster {
+                spellings: group
+                    .into_iter()
+                    .map(|(name, _, keys)| Spelling { name, keys })
+                    .collect(),
+            }
+        }));
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn author(name: &str) -> Author {
+        Author::parse(name).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let names = [
+            ("Jérôme Leroux", "Leroux, Jérôme"),
+            ("Leroux, Jérôme", "Leroux, Jérôme"),
+            ("Ludwig van Beethoven", "van Beethoven, Ludwig"),
+            ("van der Waals, Johannes", "van der Waals, Johannes"),
+            ("Smith, Jr, John", "Smith, Jr, John"),
+            ("D.~E. Knuth", "Knuth, D.~E."),
+            ("{Barnes and Noble}", "{Barnes and Noble}"),
+            ("Leroux", "Leroux"),
+            ("{\\'E}va de la Cruz", "de la Cruz, {\\'E}va"),
+        ];
+        for (name, written) in names {
+            assert_eq!(author(name).to_string(), written, "{:?}", name);
+        }
+        assert_eq!(Author::parse("a, b, c, d"), None);
+        let people = parse_authors("Doe, Jane and\n  John   Roe and {Barnes and Noble} and others");
+        assert_eq!(
+            people.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            vec!["Doe, Jane", "Roe, John", "{Barnes and Noble}"]
+        );
+    }
+
+    #[test]
+    fn test_truncate_authors() {
+        let authors = "A, A and B, B and {C and D} and E, E";
+        // the exact boundary is left as is
+        assert_eq!(truncate_authors(authors, 4), None);
+        assert_eq!(truncate_authors(authors, 5), None);
+        assert_eq!(
+            truncate_authors(authors, 3).as_deref(),
+            Some("A, A and B, B and {C and D} and others")
+        );
+        assert_eq!(
+            truncate_authors("A, A and\n  B, B", 1).as_deref(),
+            Some("A, A and others")
+        );
+        // idempotent, and the truncated fields are left as they are
+        assert_eq!(truncate_authors("A, A and B, B and others", 1), None);
+        assert_eq!(truncate_authors("A, A and others", 1), None);
+    }
+
+    #[test]
+    fn test_given_names() {
+        use GivenName::*;
+        assert_eq!(
+            author("Leroux, J.-P.").given_names(),
+            vec![Initial('j'), Initial('p')]
+        );
+        assert_eq!(
+            author("Jérôme Leroux").given_names(),
+            vec![Name("jerome".into())]
+        );
+        assert_eq!(
+            author("J{\\'e}r{\\^o}me Leroux").given_names(),
+            vec![Name("jerome".into())]
+        );
+    }
+
+    #[test]
+    fn test_same_person() {
+        // (first spelling, second spelling, same person)
+        let matrix = [
+            ("Jérôme Leroux", "Jerome Leroux", true),
+            ("Jérôme Leroux", "J. Leroux", true),
+            ("Jérôme Leroux", "Leroux, J{\\'e}r{\\^o}me", true),
+            ("J.-P. Jouannaud", "Jean-Pierre Jouannaud", true),
+            ("Jean-Pierre Jouannaud", "Jean Jouannaud", true),
+            ("Donald E. Knuth", "D.~E. Knuth", true),
+            ("Donald E. Knuth", "D. Knuth", true),
+            ("Jérôme Leroux", "Jean Leroux", false),
+            ("J. Leroux", "P. Leroux", false),
+            ("Jérôme Leroux", "Jérôme Lerouge", false),
+            ("Jérôme Leroux", "Leroux", false),
+            ("Smith, Jr, John", "Smith, John", false),
+        ];
+        for (a, b, same) in matrix {
+            assert_eq!(same_person(&author(a), &author(b)), same, "{} / {}", a, b);
+            assert_eq!(same_person(&author(b), &author(a)), same, "{} / {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_author_clusters() {
+        let people = [
+            ("a", "Jérôme Leroux and Doe, Jane"),
+            ("b", "Jerome Leroux"),
+            ("c", "J. Leroux and Jane Doe"),
+            ("d", "Leroux, Jérôme"),
+        ];
+        let clusters = author_clusters(people);
+        fn names(cluster: &AuthorCluster) -> Vec<(&str, String)> {
+            cluster
+                .spellings
+                .iter()
+                .map(|s| (s.name.as_str(), s.keys.join(",")))
+                .collect()
+        }
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(names(&clusters[0]), vec![("Doe, Jane", "a,c".to_string())]);
+        assert_eq!(
+            names(&clusters[1]),
+            vec![
+                ("Leroux, Jerome", "b".to_string()),
+                ("Leroux, Jérôme", "a,d".to_string()),
+                ("Leroux, J.", "c".to_string()),
+            ]
+        );
+
+        // the initials of two people are left alone
+        let clusters = author_clusters([("a", "Jérôme Leroux and Jean Leroux and J. Leroux")]);
+        assert_eq!(clusters.len(), 3);
+        assert!(clusters.iter().all(|c| c.spellings.len() == 1));
+    }
+}
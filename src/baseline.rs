@@ -0,0 +1,417 @@
+//! This file is responsible for `bibadac check --since <ref>` and
+//! `bibadac check --baseline <report.json>`: only reporting the
+//! diagnostics that a change introduced.
+//!
+//! The baseline is either the file linted at the given revision, or
+//! a JSON report of a previous run. A diagnostic is new when its rule,
+//! the key of its entry, and its message are not in the baseline (as
+//! many times). Locations are ignored, so that moving an entry around
+//! does not make its issues new, and the issues of an entry whose key
+//! is gone are matched against the entries whose key is new, so that
+//! renaming an entry does not either.
+//!
+//! The history of the files is read through the `History` trait,
+//! implemented by calling `git`, so that tests can provide their own.
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::bibliography::{Bibliography, Diagnostic, LintConfig};
+use crate::linter::LintMessage;
+
+pub trait History {
+    /// The content of the file at the given revision, following renames,
+    /// or `None` if the file did not exist at that revision.
+    fn old_content(&self, rev: &str, path: &Path) -> io::Result<Option<String>>;
+}
+
+/// The history of the git repository of the current directory.
+pub struct Git;
+
+fn git(args: &[&str]) -> io::Result<Option<String>> {
+    let output = Command::new("git").args(args).output()?;
+    if output.status.success() {
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The former name of a file renamed since the revision,
+/// given the output of `git diff --name-status -M`.
+pub fn renamed_from(name_status: &str, path: &str) -> Option<String> {
+    name_status.lines().find_map(|line| {
+        let mut parts = line.split('\t');
+        if !parts.next()?.starts_with('R') {
+            return None;
+        }
+        let (old, new) = (parts.next()?, parts.next()?);
+        (new == path).then(|| old.to_string())
+    })
+}
+
+/// The path relative to the current directory, as git prints it.
+fn relative(path: &Path) -> PathBuf {
+    let path = match std::env::current_dir() {
+        Ok(dir) => path.strip_prefix(dir).unwrap_or(path),
+        Err(_) => path,
+    };
+    path.strip_prefix("./").unwrap_or(path).to_path_buf()
+}
+
+impl History for Git {
+    fn old_content(&self, rev: &str, path: &Path) -> io::Result<Option<String>> {
+        // tells an unknown revision apart from a new file
+        let commit = format!("{}^{{commit}}", rev);
+        if git(&["rev-parse", "--verify", "--quiet", &commit])?.is_none() {
+            return Err(io::Error::other(format!("Unknown git revision {}", rev)));
+        }
+        let path = relative(path).to_string_lossy().to_string();
+        let name_status = git(&["diff", "--name-status", "-M", "--relative", rev, "--"])?;
+        let old_path = name_status
+            .and_then(|status| renamed_from(&status, &path))
+            .unwrap_or(path);
+        git(&["show", &format!("{}:./{}", rev, old_path)])
+    }
+}
+
+/// What identifies a diagnostic across versions of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub rule: &'static str,
+    /// The key of the entry of the diagnostic (empty outside entries)
+    pub key: String,
+    pub message: String,
+}
+
+impl Fingerprint {
+    pub fn new(key: Option<String>, diagnostic: &Diagnostic) -> Self {
+        let message = match &diagnostic.msg {
+            // as in the reports that only quoted the key (schema 2),
+            // and since retitling one of the entries is not a new issue
+            LintMessage::DuplicateKey { key, .. } => format!("DuplicateKey({:?})", key),
+            msg => format!("{:?}", msg),
+        };
+        Fingerprint {
+            rule: diagnostic.msg.rule_name(),
+            key: key.unwrap_or_default(),
+            // the message without its layout
+            message: message.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    /// The fingerprint of the same issue in an entry named otherwise.
+    fn without_key(&self) -> (&'static str, String) {
+        // the key appears quoted in the messages mentioning it
+        let key = format!("{:?}", self.key);
+        (self.rule, self.message.replace(&key, ""))
+    }
+}
+
+/// A diagnostic of a JSON report, along with the key of its entry.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReportedDiagnostic {
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// The key of the entry containing the diagnostic,
+/// given the result of `Bibliography::entry_keys`.
+pub fn entry_key(keys: &[(Range<usize>, String)], diagnostic: &Diagnostic) -> Option<String> {
+    let loc = diagnostic.loc.first()?;
+    keys.iter()
+        .find(|(range, _)| range.contains(&loc.start_byte))
+        .map(|(_, key)| key.clone())
+}
+
+pub fn fingerprints(bib: &Bibliography, diagnostics: &[Diagnostic]) -> Vec<Fingerprint> {
+    let keys = bib.entry_keys();
+    diagnostics
+        .iter()
+        .map(|d| Fingerprint::new(entry_key(&keys, d), d))
+        .collect()
+}
+
+/// The diagnostics as written in a JSON report, whose offsets are the
+/// ones of the bytes of the file (see `Bibliography::original_location`).
+pub fn reported(bib: &Bibliography, diagnostics: &[Diagnostic]) -> Vec<ReportedDiagnostic> {
+    let keys = bib.entry_keys();
+    diagnostics
+        .iter()
+        .map(|d| ReportedDiagnostic {
+            diagnostic: Diagnostic {
+                loc: d
+                    .loc
+                    .iter()
+                    .map(|loc| bib.original_location(*loc))
+                    .collect(),
+                ..d.clone()
+            },
+            key: entry_key(&keys, d),
+        })
+        .collect()
+}
+
+/// Matches the `current` fingerprints against the `baseline` ones,
+/// returning whether every current fingerprint is in the baseline,
+/// and the number of baseline fingerprints that are gone (fixed).
+///
+/// The fingerprints left are then matched without their key, between
+/// the entries whose key is not in the baseline anymore and the entries
+/// whose key is new, which are most likely renamed entries.
+pub fn match_baseline(current: &[Fingerprint], baseline: &[Fingerprint]) -> (Vec<bool>, usize) {
+    let mut known: HashMap<&Fingerprint, usize> = HashMap::new();
+    for fingerprint in baseline {
+        *known.entry(fingerprint).or_default() += 1;
+    }
+    let mut matched = current
+        .iter()
+        .map(|fingerprint| match known.get_mut(fingerprint) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+
+    let current_keys = current.iter().map(|f| &f.key).collect::<HashSet<_>>();
+    let baseline_keys = baseline.iter().map(|f| &f.key).collect::<HashSet<_>>();
+    let mut gone: HashMap<_, usize> = HashMap::new();
+    for (fingerprint, count) in known {
+        if count > 0 && !fingerprint.key.is_empty() && !current_keys.contains(&fingerprint.key) {
+            *gone.entry(fingerprint.without_key()).or_default() += count;
+        }
+    }
+    for (fingerprint, matched) in current.iter().zip(matched.iter_mut()) {
+        if *matched || fingerprint.key.is_empty() || baseline_keys.contains(&fingerprint.key) {
+            continue;
+        }
+        if let Some(count) = gone.get_mut(&fingerprint.without_key())
+            && *count > 0
+        {
+            *count -= 1;
+            *matched = true;
+        }
+    }
+    let fixed = baseline.len() - matched.iter().filter(|m| **m).count();
+    (matched, fixed)
+}
+
+/// Keeps the diagnostics whose fingerprint is not matched.
+fn unmatched(diagnostics: Vec<Diagnostic>, matched: Vec<bool>) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(d, _)| d)
+        .collect()
+}
+
+/// Keeps the diagnostics of `current` that are not in `baseline`,
+/// returning them along with the number of suppressed diagnostics.
+pub fn new_diagnostics(
+    current: &Bibliography,
+    diagnostics: Vec<Diagnostic>,
+    baseline: &Bibliography,
+    config: &LintConfig,
+) -> (Vec<Diagnostic>, usize) {
+    let old = baseline.lint(config);
+    let (matched, fixed) = match_baseline(
+        &fingerprints(current, &diagnostics),
+        &fingerprints(baseline, &old),
+    );
+    (unmatched(diagnostics, matched), old.len() - fixed)
+}
+
+/// Keeps the diagnostics of `current` that are not in the report of
+/// a previous run, returning them along with the number of suppressed
+/// diagnostics and the number of reported diagnostics that are fixed.
+pub fn new_since_report(
+    current: &Bibliography,
+    diagnostics: Vec<Diagnostic>,
+    report: &[ReportedDiagnostic],
+) -> (Vec<Diagnostic>, usize, usize) {
+    let baseline = report
+        .iter()
+        .map(|r| Fingerprint::new(r.key.clone(), &r.diagnostic))
+        .collect::<Vec<_>>();
+    let (matched, fixed) = match_baseline(&fingerprints(current, &diagnostics), &baseline);
+    let suppressed = report.len() - fixed;
+    (unmatched(diagnostics, matched), suppressed, fixed)
+}
+
+/// Keeps the diagnostics introduced since the revision
+/// (all of them when the file is new).
+pub fn since<H: History + ?Sized>(
+    history: &H,
+    rev: &str,
+    path: &Path,
+    current: &Bibliography,
+    diagnostics: Vec<Diagnostic>,
+    config: &LintConfig,
+) -> io::Result<(Vec<Diagnostic>, usize)> {
+    let Some(old) = history.old_content(rev, path)? else {
+        return Ok((diagnostics, 0));
+    };
+    // read the old version the same way as the current one
+    let baseline = if current.markdown().is_some() {
+        Bibliography::from_markdown(old)
+    } else {
+        Bibliography::from(old)
+    };
+    Ok(new_diagnostics(current, diagnostics, &baseline, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake(HashMap<&'static str, &'static str>);
+
+    impl History for Fake {
+        fn old_content(&self, _: &str, path: &Path) -> io::Result<Option<String>> {
+            Ok(self.0.get(path.to_str().unwrap()).map(|s| s.to_string()))
+        }
+    }
+
+    fn check_since(history: &Fake, path: &str, content: &str) -> (Vec<Diagnostic>, usize) {
+        let config = LintConfig::default();
+        let current: Bibliography = content.parse().unwrap();
+        let diagnostics = current.lint(&config);
+        since(
+            history,
+            "HEAD",
+            Path::new(path),
+            &current,
+            diagnostics,
+            &config,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_renamed_from() {
+        let status = "M\ta.bib\nR087\told/refs.bib\trefs.bib\nA\tnew.bib\n";
+        assert_eq!(
+            renamed_from(status, "refs.bib"),
+            Some("old/refs.bib".into())
+        );
+        assert_eq!(renamed_from(status, "a.bib"), None);
+        assert_eq!(renamed_from(status, "new.bib"), None);
+    }
+
+    #[test]
+    fn test_only_new_diagnostics() {
+        let old = "@misc{a, title = {A}}\n";
+        let history = Fake(HashMap::from([("refs.bib", old)]));
+        // the missing fields of `a` are baseline issues, even after moving it
+        let (new, suppressed) = check_since(
+            &history,
+            "refs.bib",
+            "@misc{b, title = {B}}\n\n@misc{a, title = {A}}\n",
+        );
+        assert!(suppressed > 0);
+        assert!(!new.is_empty());
+        assert_eq!(new.len(), suppressed);
+    }
+
+    fn fingerprint(rule: &'static str, key: &str, message: &str) -> Fingerprint {
+        Fingerprint {
+            rule,
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_match_baseline() {
+        let baseline = vec![
+            fingerprint("missing-field", "a", "MissingField(\"year\")"),
+            fingerprint("missing-field", "b", "MissingField(\"year\")"),
+            fingerprint("duplicate-key", "b", "DuplicateKey(\"b\")"),
+            fingerprint("missing-field", "c", "MissingField(\"year\")"),
+        ];
+        // `a` moved after `b`, `b` renamed to `d` (which has a new issue),
+        // and `c` fixed
+        let current = vec![
+            fingerprint("duplicate-key", "d", "DuplicateKey(\"d\")"),
+            fingerprint("missing-field", "d", "MissingField(\"year\")"),
+            fingerprint("missing-field", "d", "MissingField(\"title\")"),
+            fingerprint("missing-field", "a", "MissingField(\"year\")"),
+        ];
+        let (matched, fixed) = match_baseline(&current, &baseline);
+        assert_eq!(matched, vec![true, true, false, true]);
+        assert_eq!(fixed, 1);
+
+        // an issue of an entry that is still there is not renamed
+        let current = vec![fingerprint("missing-field", "e", "MissingField(\"year\")")];
+        let baseline = vec![fingerprint("missing-field", "e", "MissingField(\"title\")")];
+        assert_eq!(match_baseline(&current, &baseline), (vec![false], 1));
+    }
+
+    #[test]
+    fn test_duplicate_key_fingerprint() {
+        use crate::bibliography::Location;
+        use crate::linter::KeyOccurrence;
+
+        let diagnostic = Diagnostic {
+            msg: LintMessage::DuplicateKey {
+                key: "b".into(),
+                occurrences: vec![KeyOccurrence::new("misc", "B"); 2],
+            },
+            loc: vec![Location::of_span("", 0..0)],
+            severity: None,
+            fixable: false,
+        };
+        // the same as in the baselines without the entries
+        assert_eq!(
+            Fingerprint::new(Some("b".into()), &diagnostic),
+            fingerprint("duplicate-key", "b", "DuplicateKey(\"b\")")
+        );
+    }
+
+    #[test]
+    fn test_report_baseline() {
+        let config = LintConfig::default();
+        let old: Bibliography = "@misc{a, title = {A}}\n@misc{b, title = {B}}\n"
+            .parse()
+            .unwrap();
+        let report = reported(&old, &old.lint(&config));
+        assert!(report.iter().all(|r| r.key.is_some()));
+
+        let check = |content: &str| {
+            let current: Bibliography = content.parse().unwrap();
+            let diagnostics = current.lint(&config);
+            new_since_report(&current, diagnostics, &report)
+        };
+        // moved
+        let (new, suppressed, fixed) = check("@misc{b, title = {B}}\n\n@misc{a, title = {A}}\n");
+        assert!(new.is_empty());
+        assert_eq!((suppressed, fixed), (report.len(), 0));
+        // renamed
+        let (new, _, fixed) = check("@misc{c, title = {A}}\n@misc{b, title = {B}}\n");
+        assert!(new.is_empty());
+        assert_eq!(fixed, 0);
+        // removed
+        let (new, suppressed, fixed) = check("@misc{b, title = {B}}\n");
+        assert!(new.is_empty());
+        assert!(fixed > 0);
+        assert_eq!(suppressed + fixed, report.len());
+    }
+
+    #[test]
+    fn test_new_file() {
+        let history = Fake(HashMap::new());
+        let (new, suppressed) = check_since(&history, "new.bib", "@misc{a, title = {A}}\n");
+        assert!(!new.is_empty());
+        assert_eq!(suppressed, 0);
+    }
+}
@@ -1,31 +1,47 @@
-/// This file is responsible for 
-/// fetching database information 
-/// on different websites/local files
-/// to help the linter and formatter
-/// to do their job.
-
-use std::collections::HashMap;
-
+//! This file is responsible for 
+//! fetching database information 
+//! on different websites/local files
+//! to help the linter and formatter
+//! to do their job.
+//!
+//! Huge helper files (e.g. a dump of DBLP) are imported with
+//! `LocalBibDb::import_reader`, a chunk of entries at a time, only
+//! keeping the fields used to match the entries (`MATCHED_FIELDS`)
+//! and the ones asked for, with interned field names.
+
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::sync::Arc;
+use tracing::{debug, trace};
+
+use crate::values::same_value;
+
+/// The fields that `complete` matches the entries on.
+pub const MATCHED_FIELDS: [&str; 5] = ["title", "sha256", "doi", "eprint", "url"];
+
+/// The size of the chunks parsed by `LocalBibDb::import_reader`.
+const CHUNK_SIZE: usize = 1 << 20;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct PreBibEntry {
-    pub properties : HashMap<String, String>,
+    pub properties : HashMap<Arc<str>, String>,
 }
 
 impl PreBibEntry {
 
+    /// Whether the entry agrees with `other` on the `MATCHED_FIELDS` of
+    /// `other`, up to case, braces, and spacing (see `values::same_value`).
     fn is_extension_of(&self, other : &PreBibEntry) -> bool {
         other.properties
             .iter()
             .all(|(k,v)| {
-                if k != "title" && k != "sha256" && k != "doi" && k != "eprint" && k != "url" {
+                if !MATCHED_FIELDS.iter().any(|f| f.eq_ignore_ascii_case(k)) {
                     return true;
                 }
-                if let Some(v2) = self.properties.get(k) {
-                    v == v2
-                } else {
-                    false
-                }
+                self.properties
+                    .iter()
+                    .find(|(k2, _)| k2.eq_ignore_ascii_case(k))
+                    .is_some_and(|(_, v2)| same_value(k, v, v2))
             })
     }
 
@@ -33,7 +49,7 @@ impl PreBibEntry {
         other.properties
              .iter()
              .for_each(|(k,v)| {
-                 self.properties.entry(k.to_string()).or_insert(v.to_string());
+                 self.properties.entry(k.clone()).or_insert(v.to_string());
              });
     }
 }
@@ -45,13 +61,16 @@ pub trait BibDb {
 }
 
 
+#[derive(Debug)]
 pub struct LocalBibDb {
     pub entries : Vec<PreBibEntry>,
+    /// The field names, shared by the entries
+    names : HashSet<Arc<str>>,
 }
 
 impl Default for LocalBibDb {
     fn default() -> Self {
-        LocalBibDb { entries: vec![] }
+        LocalBibDb { entries: vec![], names: HashSet::new() }
     }
 }
 
@@ -61,28 +80,95 @@ impl LocalBibDb {
     }
 
     pub fn import_bibtex(mut self, ctn : &str) -> Self {
+        let count = self.import_chunk(ctn, None);
+        debug!(entries = count, "imported bibtex database");
+        self
+    }
+
+    /// Imports a bibtex database a chunk of entries at a time, only
+    /// keeping the `MATCHED_FIELDS` and the given ones. The entries are
+    /// cut at the lines starting with `@`.
+    pub fn import_reader(mut self, reader : impl BufRead, fields : &[String]) -> std::io::Result<Self> {
+        let fields = MATCHED_FIELDS.iter()
+            .map(|f| f.to_string())
+            .chain(fields.iter().map(|f| f.to_lowercase()))
+            .collect::<HashSet<_>>();
+        let mut count = 0;
+        let mut chunk = String::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.starts_with('@') && chunk.len() >= CHUNK_SIZE {
+                count += self.import_chunk(&chunk, Some(&fields));
+                chunk.clear();
+            }
+            chunk.push_str(&line);
+            chunk.push('\n');
+        }
+        count += self.import_chunk(&chunk, Some(&fields));
+        debug!(entries = count, "imported bibtex database");
+        Ok(self)
+    }
+
+    /// Imports the entries of `ctn`, with the given fields (all
+    /// of them when `None`), and returns the number of entries.
+    fn import_chunk(&mut self, ctn : &str, fields : Option<&HashSet<String>>) -> usize {
         use crate::bibtex::BibFile;
         let file = BibFile::new(ctn);
-        let new_entries : Vec<PreBibEntry> = file.list_entries()
-            .into_iter()
-            .map(|e| {
-                PreBibEntry {
-                    properties:
-                        e.fields
-                         .into_iter()
-                         .map(|f| {
-                             (file.get_slice(f.name).into(), file.get_slice(f.value).into())
-                         })
-                        .collect()
+        let before = self.entries.len();
+        for e in file.list_entries() {
+            let mut properties = HashMap::new();
+            for f in e.fields.iter() {
+                let name = file.get_slice(f.name);
+                if fields.is_some_and(|fields| !fields.contains(&name.to_lowercase())) {
+                    continue;
                 }
-            }).collect();
-        self.entries.extend(new_entries);
-        self
+                properties.insert(self.intern(name), file.get_slice(f.value).to_string());
+            }
+            self.entries.push(PreBibEntry { properties });
+        }
+        self.entries.len() - before
+    }
+
+    fn intern(&mut self, name : &str) -> Arc<str> {
+        if let Some(name) = self.names.get(name) {
+            return name.clone();
+        }
+        let name : Arc<str> = name.into();
+        self.names.insert(name.clone());
+        name
     }
 }
 
 
-impl BibDb for &mut LocalBibDb {
+impl<T: BibDb + ?Sized> BibDb for &T {
+    fn get_doi(&self, doi : &str) -> Option<PreBibEntry> {
+        (**self).get_doi(doi)
+    }
+
+    fn get_eprint(&self, eprint : &str) -> Option<PreBibEntry> {
+        (**self).get_eprint(eprint)
+    }
+
+    fn complete(&self, partial : &PreBibEntry) -> PreBibEntry {
+        (**self).complete(partial)
+    }
+}
+
+impl<T: BibDb + ?Sized> BibDb for &mut T {
+    fn get_doi(&self, doi : &str) -> Option<PreBibEntry> {
+        (**self).get_doi(doi)
+    }
+
+    fn get_eprint(&self, eprint : &str) -> Option<PreBibEntry> {
+        (**self).get_eprint(eprint)
+    }
+
+    fn complete(&self, partial : &PreBibEntry) -> PreBibEntry {
+        (**self).complete(partial)
+    }
+}
+
+impl BibDb for LocalBibDb {
     fn get_doi(&self, doi : &str) -> Option<PreBibEntry> {
         self.entries.iter().find(|e| {
             if let Some(d) = e.properties.get("doi") {
@@ -107,9 +193,50 @@ impl BibDb for &mut LocalBibDb {
         let mut output = partial.clone();
         for entry in self.entries.iter() {
             if entry.is_extension_of(partial) {
+                trace!(fields = ?entry.properties.keys().collect::<Vec<_>>(), "completing entry");
                 output.merge(entry)
             }
         }
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_extension_of() {
+        let entry = |fields: &[(&str, &str)]| PreBibEntry {
+            properties: fields.iter().map(|(k, v)| (Arc::from(*k), v.to_string())).collect(),
+        };
+        let full = entry(&[
+            ("title", "{Model Checking}"),
+            ("doi", "{10.1/ABC}"),
+            ("pages", "{1--2}"),
+        ]);
+        assert!(full.is_extension_of(&entry(&[("TITLE", "{{model checking}}")])));
+        assert!(full.is_extension_of(&entry(&[("doi", "{https://doi.org/10.1/abc}")])));
+        // the other fields do not have to match
+        assert!(full.is_extension_of(&entry(&[("title", "{Model Checking}"), ("pages", "{3}")])));
+        assert!(!full.is_extension_of(&entry(&[("title", "{Model-Checking}")])));
+        assert!(!full.is_extension_of(&entry(&[("eprint", "{2101.00001}")])));
+    }
+
+    #[test]
+    fn test_import_reader() {
+        let source = "@article{a,\n  title = {A},\n  doi = {10.1/a},\n  pages = {1--2},\n  abstract = {Long},\n}\n\
+                      @misc{b,\n  title = {B},\n  eprint = {2101.00001},\n  pages = {3},\n}\n";
+        let db = LocalBibDb::new()
+            .import_reader(source.as_bytes(), &["Pages".to_string()])
+            .unwrap();
+        assert_eq!(db.entries.len(), 2);
+        let mut names = db.entries[0].properties.keys().map(|k| k.as_ref()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["doi", "pages", "title"]);
+        assert_eq!(db.entries[1].properties["pages"], "{3}");
+        // the names are shared by the entries
+        let title = |i: usize| db.entries[i].properties.keys().find(|k| k.as_ref() == "title").unwrap().clone();
+        assert!(Arc::ptr_eq(&title(0), &title(1)));
+    }
+}
@@ -0,0 +1,1093 @@
+//! This file is responsible for the library-level entry
+//! point of `bibadac`: a `Bibliography` owns its source, and
+//! can be linted, formatted, and merged without dealing with
+//! `BibFile`, `LinterState`, or `FormatOptions` directly.
+//!
+//! These types borrow the source string. Instead of storing the
+//! parse tree next to the source (a self-referential struct), the
+//! source is parsed again by every operation: tree-sitter is fast
+//! enough for bibliographies, and the API stays free of lifetimes.
+//! `with_bibfile` gives access to the parsed file when needed.
+//!
+//! Markdown documents are supported as in the command line:
+//! the prose is blanked out of the source (see the `markdown`
+//! module), and formatting only rewrites the code blocks.
+//!
+//! Files that are not UTF-8 are transcoded when read (see the
+//! `encoding` module), and always written back in UTF-8.
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::against::ReferenceIndex;
+use crate::arxiv_identifiers::ArxivId;
+use crate::bibdb::LocalBibDb;
+use crate::bibtex::tree_sitter::Node;
+use crate::bibtex::BibFile;
+use crate::bibtex_spec::{is_entry_type, Flavor};
+use crate::convert::conversion_issues;
+use crate::dates::Date;
+use crate::encoding::{decode, strip_byte_order_mark};
+use crate::eprints::EprintType;
+use crate::fixes::Fix;
+use crate::line_endings::{normalize, LineEnding, LineEndings};
+use crate::format::{
+    changed_lines, completion, field_name_width, write_bibfile, FormatOptions, FormatStats,
+};
+use crate::linter::{string_value, LintMessage, LinterState, RequiredField};
+use crate::markdown::{bibtex_fences, mask_outside, replace_fences};
+use crate::merge::{merge_entries, merge_entries_traced, NonInteractive, OwnedEntry, Responder};
+use crate::provenance::annotate;
+use crate::severity::{effective_severity, RuleSeverities, Severity};
+use crate::timings::{Clock, FileTimings};
+
+/// What the linter checks, owning the data that
+/// `LinterState` borrows (none by default).
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    pub required_fields: Vec<RequiredField>,
+    pub max_field_length: HashMap<String, usize>,
+    /// Latest known version of arXiv identifiers
+    pub arxiv_latest: HashMap<String, usize>,
+    pub revoked_dois: HashSet<String>,
+    pub doi_arxiv: HashMap<String, String>,
+    pub arxiv_doi: HashMap<String, String>,
+    /// Only report the crucial errors
+    pub concise: bool,
+    /// Values of the `@string` definitions of the loaded
+    /// files, by lowercase name (see `import_strings`)
+    pub string_definitions: HashMap<String, HashSet<String>>,
+    /// The `verified` fields dated before this day are stale
+    pub verified_before: Option<Date>,
+    /// Fields that the duplicates of a work may disagree on
+    /// (none when not set, see `linter::CONFLICT_FIELDS`)
+    pub conflict_ignored_fields: Option<Vec<String>>,
+    /// Statuses accepted in the `year` field
+    /// (`dates::YEAR_STATUS_WORDS` when not set)
+    pub year_status_words: Option<Vec<String>>,
+    /// The words of the user dictionary of the spell-check, which
+    /// only runs when set (and with the `spellcheck` feature)
+    pub dictionary: Option<Vec<String>>,
+    /// Report the text between the entries
+    pub strict: bool,
+    /// The keys of the entries of the loaded files
+    /// (see `import_keys`)
+    pub entry_keys: HashSet<String>,
+    /// The severities of the `[check.rules]` sections
+    /// of the configuration file
+    pub severities: RuleSeverities,
+    /// The works of the `--against` bibliography
+    /// (see `import_against`)
+    pub against: Option<ReferenceIndex>,
+    /// Report the entries missing from the `--against`
+    /// bibliography, instead of those already in it
+    pub against_invert: bool,
+    /// The database that completes the entries before they are
+    /// linted, as the formatter would (`--lint-after-completion`)
+    pub completion: Option<Arc<LocalBibDb>>,
+}
+
+impl LintConfig {
+    /// Learns the latest versions of the arXiv
+    /// preprints cited in a helper bibliography.
+    pub fn import_helper(&mut self, helper: &Bibliography) {
+        helper.with_bibfile(|bib| {
+            for entry in bib.list_entries() {
+                if EprintType::of_entry(bib, &entry) != EprintType::Arxiv {
+                    continue;
+                }
+                let eprints = entry
+                    .fields
+                    .iter()
+                    .filter(|f| bib.get_slice(f.name) == "eprint")
+                    .filter_map(|f| ArxivId::try_from(bib.get_braceless_slice(f.value)).ok());
+                for eprint in eprints {
+                    if let Some(v) = eprint.version {
+                        self.arxiv_latest
+                            .entry(eprint.id.to_string())
+                            .and_modify(|u| *u = std::cmp::max(*u, v))
+                            .or_insert(v);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Learns the `@string` definitions of a bibliography, so
+    /// that conflicting definitions in other files are reported.
+    pub fn import_strings(&mut self, bib: &Bibliography) {
+        bib.with_bibfile(|file| {
+            for string in file.list_string_definitions() {
+                self.string_definitions
+                    .entry(file.get_slice(string.name).to_lowercase())
+                    .or_default()
+                    .insert(string_value(file, &string).to_string());
+            }
+        })
+    }
+
+    /// Learns the keys of the entries of a bibliography, so that
+    /// the `related` fields of other files may refer to them.
+    pub fn import_keys(&mut self, bib: &Bibliography) {
+        bib.with_bibfile(|file| {
+            for entry in file.list_entries() {
+                self.entry_keys.insert(file.get_slice(entry.key).to_string());
+            }
+        })
+    }
+
+    /// Learns the works of the bibliography that the
+    /// entries are checked against (`check --against`).
+    pub fn import_against(&mut self, bib: &Bibliography) {
+        bib.with_bibfile(|file| self.against.get_or_insert_default().import(file))
+    }
+
+    /// The severity of a message about an entry of the given type,
+    /// when the configuration changes it (see `effective_severity`).
+    pub fn severity(&self, msg: &LintMessage, entry_type: Option<&str>) -> Option<Severity> {
+        let default = Severity::of(msg);
+        let severity = effective_severity(&self.severities, msg.rule_name(), entry_type, default);
+        (severity != default).then_some(severity)
+    }
+
+    fn linter(&self) -> LinterState<'_> {
+        LinterState {
+            revoked_dois: self.revoked_dois.iter().map(String::as_str).collect(),
+            arxiv_latest: self
+                .arxiv_latest
+                .iter()
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect(),
+            doi_arxiv: self
+                .doi_arxiv
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+            arxiv_doi: self
+                .arxiv_doi
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+            required_fields: self.required_fields.clone(),
+            max_field_length: self.max_field_length.clone(),
+            string_definitions: self
+                .string_definitions
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.iter().map(String::as_str).collect()))
+                .collect(),
+            verified_before: self.verified_before,
+            conflict_ignored_fields: self.conflict_ignored_fields.clone(),
+            year_status_words: self.year_status_words.clone(),
+            #[cfg(feature = "spellcheck")]
+            spellcheck: self
+                .dictionary
+                .clone()
+                .map(crate::spellcheck::SpellChecker::new),
+            strict: self.strict,
+            entry_keys: self.entry_keys.iter().map(String::as_str).collect(),
+            against: self.against.as_ref(),
+            against_invert: self.against_invert,
+            completed: HashMap::new(),
+        }
+    }
+}
+
+/// The formatting options, with a local database
+/// to complete the entries (empty by default).
+/// The alignment (`min_field_length`) is computed
+/// from the bibliography when it is not set.
+pub type FormatSettings = FormatOptions<LocalBibDb>;
+
+/// A location in the source, where lines
+/// and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+impl Location {
+    /// The location of a byte range of the source.
+    pub fn of_span(source: &str, span: std::ops::Range<usize>) -> Self {
+        let before = &source[..span.start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: span.start - line_start + 1,
+            start_byte: span.start,
+            end_byte: span.end,
+        }
+    }
+}
+
+impl From<Node<'_>> for Location {
+    fn from(node: Node<'_>) -> Self {
+        Location {
+            line: node.start_position().row + 1,
+            column: node.start_position().column + 1,
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+        }
+    }
+}
+
+/// A lint message, and the locations that triggered it
+/// (the owned counterpart of `linter::Lint`).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Diagnostic {
+    pub msg: LintMessage,
+    pub loc: Vec<Location>,
+    /// The severity configured for the rule (see the `severity`
+    /// module), when it is not the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// Whether completing the entry from the database would resolve
+    /// the message (`--lint-after-completion`), which is then a warning
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fixable: bool,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.severity.unwrap_or_else(|| Severity::of(&self.msg))
+    }
+
+    pub fn is_crucial(&self) -> bool {
+        self.severity().is_crucial()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bibliography {
+    /// The BibTeX content, where everything outside
+    /// of the code blocks of a markdown document is blanked out
+    source: String,
+    /// The markdown document, if the content was embedded in one
+    markdown: Option<String>,
+    /// The encoding the file was decoded from, when it is not UTF-8
+    encoding: Option<&'static str>,
+    /// The ending of most lines of the file, which are
+    /// all read as `\n` (see the `line_endings` module)
+    line_ending: LineEnding,
+    /// The ending of every line of the file
+    line_endings: LineEndings,
+}
+
+impl From<String> for Bibliography {
+    fn from(source: String) -> Self {
+        Bibliography {
+            line_ending: LineEnding::detect(&source),
+            line_endings: LineEndings::of(&source),
+            source: normalize(source),
+            markdown: None,
+            encoding: None,
+        }
+    }
+}
+
+impl FromStr for Bibliography {
+    type Err = Infallible;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Ok(Bibliography::from(source.to_string()))
+    }
+}
+
+impl Bibliography {
+    /// Reads a BibTeX file, or a markdown document
+    /// when the extension is `.md` or `.qmd`.
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let is_markdown = path
+            .extension()
+            .is_some_and(|ext| ext == "md" || ext == "qmd");
+        Ok(Bibliography::from_bytes(std::fs::read(path)?, is_markdown))
+    }
+
+    /// Reads the content of a file, in UTF-8, Latin-1,
+    /// or Windows-1252 (see `encoding::decode`).
+    pub fn from_bytes(bytes: Vec<u8>, is_markdown: bool) -> Self {
+        let (content, encoding) = decode(bytes);
+        let bibliography = if is_markdown {
+            Bibliography::from_markdown(content)
+        } else {
+            Bibliography::from(content)
+        };
+        Bibliography {
+            encoding,
+            ..bibliography
+        }
+    }
+
+    /// The entries of the ```` ```{=bibtex} ```` code blocks of a document.
+    pub fn from_markdown(markdown: String) -> Self {
+        let line_ending = LineEnding::detect(&markdown);
+        let line_endings = LineEndings::of(&markdown);
+        let markdown = normalize(markdown);
+        let fences = bibtex_fences(&markdown);
+        Bibliography {
+            source: mask_outside(&markdown, &fences),
+            markdown: Some(markdown),
+            encoding: None,
+            line_ending,
+            line_endings,
+        }
+    }
+
+    pub fn from_entries(entries: &[OwnedEntry]) -> Self {
+        Bibliography::from(
+            entries
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    /// The BibTeX source (with the prose of a markdown document blanked out,
+    /// and `\n` line endings), in which the `Location`s of the diagnostics
+    /// are given.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn markdown(&self) -> Option<&str> {
+        self.markdown.as_deref()
+    }
+
+    /// The encoding the content was decoded from, when it is not UTF-8.
+    pub fn encoding(&self) -> Option<&'static str> {
+        self.encoding
+    }
+
+    /// The ending of most lines of the file, to write it back with.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// The ending of every line of the file, to write it back with.
+    pub fn line_endings(&self) -> &LineEndings {
+        &self.line_endings
+    }
+
+    /// A location of the source as the bytes of the file, which may
+    /// have `\r\n` line endings (the line and the column are the same).
+    pub fn original_location(&self, loc: Location) -> Location {
+        Location {
+            start_byte: self.line_endings.original_offset(loc.start_byte),
+            end_byte: self.line_endings.original_offset(loc.end_byte),
+            ..loc
+        }
+    }
+
+    /// Parses the source, and gives the parsed file to `f`.
+    pub fn with_bibfile<R>(&self, f: impl FnOnce(&BibFile) -> R) -> R {
+        f(&BibFile::new(&self.source))
+    }
+
+    /// The line and column (from 1) of the first syntax error, if any.
+    pub fn first_syntax_error(&self) -> Option<(usize, usize)> {
+        self.with_bibfile(|file| {
+            file.iterate()
+                .find(|node| node.kind() == "ERROR" || node.is_missing())
+                .map(|node| {
+                    let position = node.start_position();
+                    (position.row + 1, position.column + 1)
+                })
+        })
+    }
+
+    /// The keys of the entries, with their byte ranges.
+    pub fn entry_keys(&self) -> Vec<(std::ops::Range<usize>, String)> {
+        self.with_bibfile(|file| {
+            file.list_entries()
+                .map(|e| (e.loc.byte_range(), file.get_slice(e.key).to_string()))
+                .collect()
+        })
+    }
+
+    /// The byte ranges of the entries whose type is not one of `types`
+    /// (up to case and aliases).
+    pub fn other_entry_types(&self, types: &[String]) -> Vec<std::ops::Range<usize>> {
+        self.with_bibfile(|file| {
+            file.list_entries()
+                .filter(|e| !is_entry_type(file.get_slice(e.entrytype), types))
+                .map(|e| e.loc.byte_range())
+                .collect()
+        })
+    }
+
+    /// The conversions of the entries that `--convert-to` does not
+    /// apply (as they would lose information), with the entry keys.
+    pub fn conversion_issues(&self, flavor: Flavor) -> Vec<(String, String)> {
+        self.with_bibfile(|file| conversion_issues(file, flavor))
+    }
+
+    pub fn entries(&self) -> Vec<OwnedEntry> {
+        self.with_bibfile(|bib| {
+            bib.list_entries()
+                .map(|e| OwnedEntry::from_entry(bib, &e))
+                .collect()
+        })
+    }
+
+    pub fn lint(&self, config: &LintConfig) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        self.lint_streaming(config, |d| diagnostics.push(d));
+        diagnostics
+    }
+
+    /// Lints the bibliography, giving the diagnostics to `sink` as
+    /// they are found (see `LinterState::lint_file_streaming`).
+    pub fn lint_streaming(&self, config: &LintConfig, sink: impl FnMut(Diagnostic)) {
+        self.lint_timed(config, None, sink)
+    }
+
+    /// `lint_streaming`, recording the time of its phases and the size of
+    /// the file in `timings` (see the `timings` module).
+    pub fn lint_timed(
+        &self,
+        config: &LintConfig,
+        mut timings: Option<&mut FileTimings>,
+        mut sink: impl FnMut(Diagnostic),
+    ) {
+        let mut total = Clock::start(timings.is_some());
+        let mut clock = total;
+        let bib = BibFile::new(&self.source);
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.parse_ms += clock.lap();
+            timings.nodes += bib.tree.root_node().descendant_count();
+        }
+        let mut linter = config.linter();
+        let mut sink = |d: Diagnostic| {
+            if !config.concise || d.is_crucial() {
+                sink(d)
+            }
+        };
+        // the linter only sees the decoded text
+        if let Some(encoding) = self.encoding {
+            let msg = LintMessage::NonUtf8Encoding(encoding.to_string());
+            sink(Diagnostic {
+                severity: config.severity(&msg, None),
+                msg,
+                loc: vec![Location::of_span(&self.source, 0..0)],
+                fixable: false,
+            });
+        }
+        // the entries, to find the type of the entry of a message
+        // (their ranges are sorted, as the entries are in file order)
+        let entries = bib.list_entries().collect::<Vec<_>>();
+        let types = if config.severities.is_empty() {
+            vec![]
+        } else {
+            entries
+                .iter()
+                .map(|e| (e.loc.byte_range(), bib.get_slice(e.entrytype)))
+                .collect()
+        };
+        // the fields of the database are completed once, as the formatter does
+        if let Some(database) = &config.completion {
+            linter.completed = entries
+                .iter()
+                .map(|e| {
+                    let fields = completion(&bib, e, database.as_ref())
+                        .properties
+                        .into_iter()
+                        .map(|(name, value)| (name.to_lowercase(), value))
+                        .collect::<HashMap<_, _>>();
+                    (e.loc.start_byte(), fields)
+                })
+                .filter(|(_, fields)| !fields.is_empty())
+                .collect();
+        }
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.entries += entries.len();
+            timings.collection_ms += clock.lap();
+        }
+        let completed = &linter.completed;
+        linter.lint_file_timed(&bib, entries, timings.as_deref_mut(), |l| {
+            let entry_type = l.loc.first().and_then(|node| {
+                let start = node.start_byte();
+                let after = types.partition_point(|(range, _)| range.start <= start);
+                let (range, entry_type) = types.get(after.checked_sub(1)?)?;
+                range.contains(&start).then_some(*entry_type)
+            });
+            let fixable = match &l.msg {
+                LintMessage::MissingField(name) | LintMessage::MissingOptionalField(name) => l
+                    .loc
+                    .first()
+                    .and_then(|node| completed.get(&node.start_byte()))
+                    .is_some_and(|fields| fields.contains_key(name)),
+                _ => false,
+            };
+            // the messages that the completion resolves are warnings
+            let severity = match fixable {
+                true => Some(Severity::Warning).filter(|s| *s != Severity::of(&l.msg)),
+                false => config.severity(&l.msg, entry_type),
+            };
+            let mut loc = l.loc.into_iter().map(Location::from).collect::<Vec<_>>();
+            // the part of the first location the message is about
+            if let Some(span) = l.span {
+                loc[0] = Location::of_span(&self.source, span);
+            }
+            sink(Diagnostic {
+                msg: l.msg,
+                loc,
+                severity,
+                fixable,
+            })
+        });
+        if let Some(timings) = timings {
+            timings.total_ms += total.lap();
+        }
+    }
+
+    /// The replacements of the messages that have an obvious one
+    /// (see `Lint::suggestion`), in the source, whose byte offsets
+    /// are the ones of the file (see `original`).
+    pub fn fixes(&self, config: &LintConfig) -> Vec<Fix> {
+        let bib = BibFile::new(&self.source);
+        let entries = bib.list_entries().collect::<Vec<_>>();
+        let mut fixes = vec![];
+        config.linter().lint_file_streaming(&bib, entries, |l| {
+            if let Some((span, replacement)) = l.suggestion(&bib) {
+                fixes.push(Fix {
+                    span,
+                    replacement,
+                    rule: l.msg.rule_name(),
+                });
+            }
+        });
+        fixes
+    }
+
+    /// The decoded content of the file (with `\n` line endings):
+    /// the markdown document, or the BibTeX source.
+    pub fn original(&self) -> &str {
+        self.markdown.as_deref().unwrap_or(&self.source)
+    }
+
+    pub fn format(&self, settings: &FormatSettings) -> String {
+        self.format_with_stats(settings).0
+    }
+
+    /// Formats the bibliography (with `\n` line endings, see `line_ending`),
+    /// and tells what the filters of the settings removed from the output.
+    pub fn format_with_stats(&self, settings: &FormatSettings) -> (String, FormatStats) {
+        let mut stats = FormatStats::default();
+        let mut format_bibtex = |content: &str| {
+            let bib = BibFile::new(content);
+            let mut options = settings.as_ref();
+            if options.min_field_length.is_none() {
+                options.min_field_length = Some(field_name_width(&bib));
+            }
+            let mut formatted = String::new();
+            stats += write_bibfile(&bib, &options, &mut formatted)
+                .expect("Writing to a String cannot fail");
+            formatted
+        };
+        let formatted = match &self.markdown {
+            // only the code blocks are rewritten, the prose is left untouched
+            Some(markdown) => {
+                let markdown = strip_byte_order_mark(markdown);
+                let fences = bibtex_fences(markdown);
+                replace_fences(markdown, &fences, |fence| {
+                    format_bibtex(fence).trim_end().to_string() + "\n"
+                })
+            }
+            None => format_bibtex(&self.source),
+        };
+        (formatted, stats)
+    }
+
+    /// Formats the bibliography twice, which must give the output of the
+    /// first time: otherwise, the lines that the second time changes
+    /// (see `format::changed_lines`), which point to a bug of the formatter.
+    pub fn verify_idempotent(&self, settings: &FormatSettings) -> Result<(), String> {
+        let formatted = self.format(settings);
+        let again = match self.markdown {
+            Some(_) => Bibliography::from_markdown(formatted.clone()),
+            None => Bibliography::from(formatted.clone()),
+        };
+        match changed_lines(&formatted, &again.format(settings)) {
+            Some(diff) => Err(diff),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds the entries of `other`, keeping the first
+    /// entry when two conflicting entries share a key.
+    pub fn merge(&mut self, other: Bibliography) {
+        self.merge_with(other, &mut NonInteractive)
+    }
+
+    /// Adds the entries of `other`, asking the responder
+    /// how to resolve conflicting entries (see `merge_entries`).
+    /// The result is a plain BibTeX bibliography.
+    pub fn merge_with<R: Responder + ?Sized>(&mut self, other: Bibliography, responder: &mut R) {
+        *self = Bibliography::merge_all([std::mem::take(self), other], responder);
+    }
+
+    /// Merges the entries of all the bibliographies at once, so that
+    /// each conflict is asked once, even when it is skipped and a
+    /// later bibliography has the same key again.
+    pub fn merge_all<R: Responder + ?Sized>(
+        bibliographies: impl IntoIterator<Item = Bibliography>,
+        responder: &mut R,
+    ) -> Bibliography {
+        let entries = bibliographies
+            .into_iter()
+            .flat_map(|b| b.entries())
+            .collect::<Vec<_>>();
+        Bibliography::from_entries(&merge_entries(entries, responder))
+    }
+
+    /// `merge_all`, annotating the fields of the merged entries taken
+    /// from another bibliography than the one of their entry with a
+    /// `% bibadac:` comment (see the `provenance` module), the
+    /// bibliographies being named by `names`.
+    pub fn merge_all_annotated<R: Responder + ?Sized>(
+        bibliographies: impl IntoIterator<Item = Bibliography>,
+        names: &[String],
+        responder: &mut R,
+    ) -> Bibliography {
+        let entries = bibliographies
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, b)| b.entries().into_iter().map(move |e| (e, i)))
+            .collect::<Vec<_>>();
+        let merged = merge_entries_traced(entries, responder)
+            .into_iter()
+            .map(|(mut entry, provenance)| {
+                annotate(&mut entry, &provenance, names);
+                entry
+            })
+            .collect::<Vec<_>>();
+        Bibliography::from_entries(&merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bib(source: &str) -> Bibliography {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn test_lint() {
+        let bibliography = bib("@misc{a,\n  title = {T},\n}\n");
+        let diagnostics = bibliography.lint(&LintConfig::default());
+        assert!(diagnostics
+            .iter()
+            .any(|d| matches!(&d.msg, LintMessage::MissingField(f) if f == "author")));
+        assert_eq!(diagnostics[0].loc[0].line, 1);
+
+        let concise = LintConfig {
+            concise: true,
+            ..LintConfig::default()
+        };
+        assert!(bibliography.lint(&concise).iter().all(|d| d.msg.is_crucial()));
+    }
+
+    #[test]
+    fn test_lint_timed() {
+        let source = (0..200)
+            .map(|i| format!("@article{{doe{i},\n  title = {{Paper {i}}},\n  year = 2020,\n}}\n"))
+            .collect::<String>();
+        let bibliography = bib(&source);
+        let config = LintConfig::default();
+        let mut timings = FileTimings::default();
+        let mut timed = vec![];
+        bibliography.lint_timed(&config, Some(&mut timings), |d| timed.push(d));
+        assert_eq!(timed.len(), bibliography.lint(&config).len());
+        assert_eq!(timings.entries, 200);
+        assert!(timings.nodes > 200 * 3);
+        assert!(timings.parse_ms > 0.0);
+        assert!(timings.entry_lints_ms > 0.0);
+        // the phases are within the whole check
+        assert!(timings.phases_ms() <= timings.total_ms, "{:?}", timings);
+    }
+
+    #[test]
+    fn test_lint_after_completion() {
+        let bibliography =
+            bib("@misc{a,\n  title = {Model Checking},\n  author = {Doe, Jane},\n}\n");
+        let database = LocalBibDb::new().import_bibtex(
+            "@misc{db, title = {Model Checking}, year = 2020, doi = {https://doi.org/10.1/x}}\n",
+        );
+        let config = LintConfig {
+            completion: Some(Arc::new(database)),
+            ..LintConfig::default()
+        };
+        let diagnostics = bibliography.lint(&config);
+        // the year that the database has is a warning
+        let year = diagnostics
+            .iter()
+            .find(|d| matches!(&d.msg, LintMessage::MissingField(f) if f == "year"))
+            .unwrap();
+        assert!(year.fixable);
+        assert!(!year.is_crucial());
+        // the completed values are linted, on the entry
+        let doi = diagnostics
+            .iter()
+            .find(|d| matches!(d.msg, LintMessage::HttpDoi))
+            .unwrap();
+        assert_eq!(doi.loc[0].line, 1);
+        assert!(!doi.fixable);
+
+        // without completion, the year is missing
+        let diagnostics = bibliography.lint(&LintConfig::default());
+        assert!(diagnostics.iter().all(|d| !d.fixable));
+        assert!(diagnostics.iter().all(|d| !matches!(d.msg, LintMessage::HttpDoi)));
+        assert!(diagnostics.iter().any(
+            |d| matches!(&d.msg, LintMessage::MissingField(f) if f == "year") && d.is_crucial()
+        ));
+    }
+
+    #[test]
+    fn test_fixes() {
+        let content = "@Misc{a,\n  title = {T},\n  doi = {https://doi.org/10.1/x},\n}\n";
+        let bibliography = bib(content);
+        let (fixes, dropped) = crate::fixes::select(bibliography.fixes(&LintConfig::default()));
+        assert!(dropped.is_empty());
+        let rules = fixes.iter().map(|f| f.rule).collect::<Vec<_>>();
+        assert_eq!(rules, ["entry-type-case", "http-doi"]);
+        assert_eq!(
+            crate::fixes::apply(bibliography.original(), &fixes),
+            "@misc{a,\n  title = {T},\n  doi = {10.1/x},\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_string_definitions() {
+        let rules = |bib: &Bibliography, config: &LintConfig| {
+            bib.lint(config)
+                .into_iter()
+                .map(|d| d.msg.rule_name())
+                .filter(|r| r.contains("string") || r.contains("month"))
+                .collect::<Vec<_>>()
+        };
+        let config = LintConfig::default();
+        let local = bib("@string{ieee = {IEEE}}\n@string{IEEE = \"IEEE\"}\n@string{acm = {ACM}}\n");
+        assert!(rules(&local, &config).is_empty());
+        let conflict = bib("@string{acm = {ACM}}\n@string{ACM = {Assoc. Comput. Mach.}}\n");
+        assert_eq!(rules(&conflict, &config), vec!["duplicate-string-definition"]);
+        assert_eq!(conflict.lint(&config)[0].loc.len(), 2);
+        let month = bib("@string{jan = {Janvier}}\n");
+        assert_eq!(rules(&month, &config), vec!["shadowed-month-macro"]);
+
+        // across files
+        let mut config = LintConfig::default();
+        config.import_strings(&local);
+        config.import_strings(&bib("@string{acm = {Assoc. Comput. Mach.}}\n"));
+        assert_eq!(rules(&local, &config), vec!["duplicate-string-definition"]);
+    }
+
+    #[test]
+    fn test_related() {
+        let rules = |bib: &Bibliography, config: &LintConfig| {
+            bib.lint(config)
+                .into_iter()
+                .filter_map(|d| match d.msg {
+                    LintMessage::UnresolvedRelated(key) => Some(key),
+                    LintMessage::PreprintPublishedPair { .. } => Some("pair".to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let pair = "@misc{pre, title = {On Things}, author = {Doe, Jane}, eprint = {2001.00001}}\n\
+                    @article{pub, title = {On Things}, author = {Doe, Jane}, doi = {10.1/x}}\n";
+        let config = LintConfig::default();
+        assert_eq!(rules(&bib(pair), &config), vec!["pair"]);
+        let related = pair.replace(
+            "eprint =",
+            "related = {pub}, relatedtype = {publishedas}, eprint =",
+        );
+        assert!(rules(&bib(&related), &config).is_empty());
+
+        // several keys, one of them in another file
+        let survey = bib("@misc{survey, related = {pub, other, missing}}\n");
+        let mut config = LintConfig::default();
+        config.import_keys(&bib(pair));
+        config.import_keys(&bib("@misc{other, title = {Other}}\n"));
+        assert_eq!(rules(&survey, &config), vec!["missing"]);
+    }
+
+    #[test]
+    fn test_format() {
+        let bibliography = bib("@MISC{a, Title={T}, year = 2020}");
+        assert_eq!(
+            bibliography.format(&FormatSettings::default()),
+            "@misc{a,\n  title = {T},\n  year  = 2020,\n}\n\n"
+        );
+    }
+
+    /// A small generator of random numbers (xorshift), so that
+    /// a failing case is replayed from its seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    /// A random value, braced, quoted, or a number, with
+    /// nested braces, accents, commas, ties, and line breaks.
+    fn random_value(rng: &mut Rng, field: &str) -> String {
+        let words = [
+            "Model",
+            "checking",
+            "{LTL}",
+            "{\\'e}t{\\'e}",
+            "a,b",
+            "x\n  y",
+            "x~y",
+            "{\\\"o}",
+            "--",
+            "Doe, Jane",
+            "{{Nested} Braces}",
+            "\n",
+        ];
+        let text = (0..1 + rng.below(4))
+            .map(|_| rng.pick(&words))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match rng.below(4) {
+            0 if field != "author" => (1900 + rng.below(150)).to_string(),
+            1 => format!("\"{}\"", text.replace("{\\\"o}", "{\\o}")),
+            _ => format!("{{{}}}", text),
+        }
+    }
+
+    #[test]
+    fn test_format_idempotent() {
+        use crate::bibtex_spec::{BIBTEX_ENTRY_TYPES, BIBTEX_FIELDS};
+        let types = BIBTEX_ENTRY_TYPES
+            .into_iter()
+            .filter(|t| *t != "mapping")
+            .collect::<Vec<_>>();
+        for seed in 1..=200 {
+            let mut rng = Rng(seed);
+            let mut source = String::new();
+            for i in 0..1 + rng.below(3) {
+                let mut fields = BIBTEX_FIELDS.to_vec();
+                let entrytype = rng.pick(&types).to_string();
+                source += &format!("@{}{{k{},\n", entrytype, i);
+                for _ in 0..rng.below(6) {
+                    let field = fields.remove(rng.below(fields.len()));
+                    let value = random_value(&mut rng, field);
+                    source += &format!("  {} = {},\n", field, value);
+                }
+                source += "}\n\n";
+            }
+            let settings = FormatSettings::default();
+            let verified = bib(&source).verify_idempotent(&settings);
+            assert!(
+                verified.is_ok(),
+                "seed {}:\n{}\n{}",
+                seed,
+                source,
+                verified.unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_markdown() {
+        let bibliography =
+            Bibliography::from_markdown("# Refs\n\n```bibtex\n@MISC{a, year = 2020}\n```\n".into());
+        assert_eq!(
+            bibliography.format(&FormatSettings::default()),
+            "# Refs\n\n```bibtex\n@misc{a,\n  year = 2020,\n}\n```\n"
+        );
+    }
+
+    fn fixture(name: &str) -> Bibliography {
+        Bibliography::from_bytes(crate::read_fixture_bytes("encoding", name), false)
+    }
+
+    #[test]
+    fn test_encodings() {
+        let has = |bib: &Bibliography, rule: &str| {
+            bib.lint(&LintConfig::default())
+                .iter()
+                .any(|d| d.msg.rule_name() == rule)
+        };
+        let utf8 = fixture("utf8.bib");
+        assert!(!has(&utf8, "byte-order-mark") && !has(&utf8, "non-utf8-encoding"));
+
+        let bom = fixture("utf8-bom.bib");
+        assert!(has(&bom, "byte-order-mark"));
+        assert_eq!(bom.entries().len(), 1);
+
+        let latin1 = fixture("latin1.bib");
+        assert_eq!(latin1.encoding(), Some("ISO-8859-1"));
+        assert!(latin1.lint(&LintConfig::default()).iter().any(
+            |d| matches!(&d.msg, LintMessage::NonUtf8Encoding(e) if e == "ISO-8859-1")
+        ));
+        assert_eq!(fixture("windows1252.bib").encoding(), Some("windows-1252"));
+
+        // written back in UTF-8, without byte order mark
+        let settings = FormatSettings::default();
+        assert_eq!(bom.format(&settings), utf8.format(&settings));
+        assert_eq!(latin1.format(&settings), utf8.format(&settings));
+    }
+
+    fn line_endings_fixture(name: &str) -> Bibliography {
+        Bibliography::from(crate::read_fixture("line_endings", name))
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let config = LintConfig::default();
+        let report = |bib: &Bibliography| serde_json::to_value(bib.lint(&config)).unwrap();
+        let lf = line_endings_fixture("lf.bib");
+        let crlf = line_endings_fixture("crlf.bib");
+        let mixed = line_endings_fixture("mixed.bib");
+        assert_eq!(lf.line_ending(), LineEnding::Lf);
+        assert_eq!(crlf.line_ending(), LineEnding::Crlf);
+        assert_eq!(mixed.line_ending(), LineEnding::Crlf);
+        // the same diagnostics, at the same lines and columns, but the
+        // JSON report has the offsets of the bytes of the file
+        let reported = |bib: &Bibliography| crate::baseline::reported(bib, &bib.lint(&config));
+        for bib in [&crlf, &mixed] {
+            assert_eq!(bib.source(), lf.source());
+            assert_eq!(report(bib), report(&lf));
+            for (r, l) in reported(bib).iter().zip(reported(&lf)) {
+                let (r, l) = (r.diagnostic.loc[0], l.diagnostic.loc[0]);
+                assert_eq!((r.line, r.column), (l.line, l.column));
+                assert!(r.start_byte >= l.start_byte);
+            }
+        }
+        assert_ne!(
+            serde_json::to_value(reported(&crlf)).unwrap(),
+            serde_json::to_value(reported(&lf)).unwrap()
+        );
+        let doi = |bib: &Bibliography| {
+            bib.lint(&config)
+                .into_iter()
+                .find(|d| matches!(d.msg, LintMessage::HttpDoi))
+                .unwrap()
+                .loc[0]
+        };
+        assert_eq!((doi(&crlf).line, doi(&crlf).column), (6, 13));
+        // the bytes of the file have a `\r` more per line before it
+        for bib in [&lf, &crlf, &mixed] {
+            let original = bib.line_endings().restore(bib.original());
+            let loc = bib.original_location(doi(bib));
+            assert_eq!(
+                &original[loc.start_byte..loc.end_byte],
+                &bib.source()[doi(bib).start_byte..doi(bib).end_byte]
+            );
+        }
+        assert_eq!(
+            crlf.original_location(doi(&crlf)).start_byte,
+            doi(&lf).start_byte + 5
+        );
+
+        // written back with CRLF only, which a second format keeps
+        let settings = FormatSettings::default();
+        assert_eq!(crlf.format(&settings), lf.format(&settings));
+        for bib in [&crlf, &mixed] {
+            let formatted = bib.line_ending().apply(&bib.format(&settings));
+            assert!(!formatted.replace("\r\n", "").contains(['\r', '\n']));
+            let again = Bibliography::from(formatted.clone());
+            assert_eq!(
+                again.line_ending().apply(&again.format(&settings)),
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut bibliography = bib("@misc{a, year = 2020}\n@misc{b, year = 2021}\n");
+        bibliography.merge(bib("@misc{b, year = 2022}\n@misc{c, year = 2023}\n"));
+        let entries = bibliography.entries();
+        let keys = entries.iter().map(|e| e.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+        // the first entry is kept
+        assert_eq!(entries[1].get("year"), Some("2021"));
+    }
+
+    #[test]
+    fn test_merge_all_asks_once() {
+        struct Skip(usize);
+        impl Responder for Skip {
+            fn ask(&mut self, _: &str) -> Option<String> {
+                self.0 += 1;
+                Some("s".to_string())
+            }
+        }
+        let mut responder = Skip(0);
+        let merged = Bibliography::merge_all(
+            [
+                bib("@misc{a, year = 2020}\n"),
+                bib("@misc{a, year = 2021}\n"),
+                bib("@misc{b, year = 2022}\n"),
+            ],
+            &mut responder,
+        );
+        assert_eq!(responder.0, 1);
+        assert_eq!(merged.entries().len(), 3);
+    }
+
+    #[test]
+    fn test_merge_annotated() {
+        struct MergeSecond;
+        impl Responder for MergeSecond {
+            fn ask(&mut self, question: &str) -> Option<String> {
+                let answer = if question.contains("[m]") { "m" } else { "2" };
+                Some(answer.to_string())
+            }
+        }
+        let names = ["a.bib".to_string(), "b.bib".to_string()];
+        let merged = Bibliography::merge_all_annotated(
+            [
+                bib("@misc{a, title = {T}, pages = {1--2}}\n"),
+                bib("@misc{a, title = {T}, pages = {1--3}, year = 2020}\n"),
+            ],
+            &names,
+            &mut MergeSecond,
+        );
+        let entries = merged.entries();
+        assert_eq!(
+            entries[0].annotations,
+            vec![
+                ("pages".to_string(), "pages from b.bib".to_string()),
+                ("year".to_string(), "year from b.bib".to_string()),
+            ]
+        );
+        // the annotation follows its field through sorting, until stripped
+        let mut settings = FormatSettings {
+            sort_fields: true,
+            ..FormatSettings::default()
+        };
+        let formatted = merged.format(&settings);
+        let lines = formatted.lines().collect::<Vec<_>>();
+        let pages = lines.iter().position(|l| l.contains("1--3")).unwrap();
+        assert_eq!(lines[pages + 1].trim(), "% bibadac: pages from b.bib");
+        assert_eq!(bib(&formatted).format(&settings), formatted);
+        settings.strip_annotations = true;
+        let stripped = merged.format(&settings);
+        assert!(!stripped.contains("bibadac:"));
+        assert!(stripped.contains("1--3"));
+    }
+}
@@ -4,16 +4,25 @@
 /// such files.
 use once_cell::sync::OnceCell;
 pub use tree_sitter;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
 use tree_sitter::{Language, Node, Parser, Tree, TreeCursor};
 use tree_sitter_bibtex as bibparser;
 
 use crate::bibtex;
+use crate::metadata::{entry_groups, metadata_spans, parse_jabref_groups, MetadataComment};
+use crate::provenance::mask_annotations;
+use crate::values::{builtin_string, canonical_value};
 
 
 #[derive(Debug, Clone)]
 pub struct BibFile<'a> {
     pub content: &'a str,
     pub tree: Tree,
+    /// The spans of the values of the `@string` definitions, by
+    /// lowercase name, read on first use (see `string_spans`).
+    strings: OnceCell<HashMap<String, Range<usize>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -92,14 +101,50 @@ impl<'a> BibEntry<'a> {
     }
 }
 
+/// A `@string{name = value}` macro definition
+#[derive(Debug, Clone)]
+pub struct BibString<'a> {
+    pub loc: Node<'a>,
+    pub name: Node<'a>,
+    pub value: Node<'a>,
+}
+
+impl<'a> BibString<'a> {
+    pub fn from_node(node: Node<'a>) -> Option<Self> {
+        if node.kind() != "string" {
+            return None;
+        }
+        let mut cursor = node.walk();
+        let mut name = None;
+        let mut value = None;
+        for prop in node.children(&mut cursor) {
+            match prop.kind() {
+                "identifier" => name = Some(prop),
+                "value" => value = Some(prop),
+                _ => {}
+            }
+        }
+        Some(BibString {
+            loc: node,
+            name: name?,
+            value: value?,
+        })
+    }
+}
+
 impl<'a> BibFile<'a> {
     pub fn new(content: &'a str) -> Self {
         let mut parser = Parser::new();
         parser
             .set_language(&bibparser::LANGUAGE.into())
             .expect("Failed to load bibtex language");
-        let tree = parser.parse(content, None).unwrap();
-        Self { content, tree }
+        // the `% bibadac:` comments inside of the entries are not BibTeX
+        let tree = parser.parse(mask_annotations(content).as_ref(), None).unwrap();
+        Self {
+            content,
+            tree,
+            strings: OnceCell::new(),
+        }
     }
 
     pub fn iterate(&'a self) -> impl Iterator<Item = Node<'a>> {
@@ -118,11 +163,152 @@ impl<'a> BibFile<'a> {
 
     pub fn get_braceless_slice(&self, node: Node) -> &'a str {
         let slice = self.get_slice(node);
-        if slice.starts_with('{') && slice.ends_with('}') {
-            &slice[1..slice.len() - 1]
+        slice
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .unwrap_or(slice)
+    }
+
+    /// The byte range of the content of a value, without its
+    /// delimiters (`{...}` or `"..."`), along with the content,
+    /// so that the lints can point inside of the delimiters.
+    pub fn get_value_span(&self, node: Node) -> (Range<usize>, &'a str) {
+        let slice = self.get_slice(node);
+        let delimited = slice.len() >= 2
+            && ((slice.starts_with('{') && slice.ends_with('}'))
+                || (slice.starts_with('"') && slice.ends_with('"')));
+        let span = if delimited {
+            node.start_byte() + 1..node.end_byte() - 1
         } else {
-            slice
+            node.start_byte()..node.end_byte()
+        };
+        (span.clone(), &self.content[span])
+    }
+
+    pub fn list_string_definitions(&'a self) -> impl Iterator<Item = BibString<'a>> {
+        let mut cursor = self.tree.root_node().walk();
+        self.tree
+            .root_node()
+            .children(&mut cursor)
+            .filter_map(BibString::from_node)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Names of the macros (`@string` definitions or
+    /// builtins such as `jan`) used in a field value,
+    /// e.g. `IEEEtrans` in `journal = IEEEtrans # " 12"`.
+    pub fn macro_references(&self, value: Node<'a>) -> Vec<&'a str> {
+        let mut cursor = value.walk();
+        let mut t_cursor = value.walk();
+        let mut names = vec![];
+        for token in value.children(&mut cursor) {
+            for part in token.children(&mut t_cursor) {
+                if part.kind() == "identifier" {
+                    names.push(self.get_slice(part));
+                }
+            }
+        }
+        names
+    }
+
+    /// Adds to `used` the (lowercase) names of the `@string`
+    /// definitions that the definitions named in `used` refer to,
+    /// since strings may themselves refer to other strings.
+    pub fn referenced_strings(&'a self, used: &mut HashSet<String>) {
+        let strings = self.list_string_definitions().collect::<Vec<_>>();
+        loop {
+            let before = used.len();
+            for s in strings.iter() {
+                if used.contains(&self.get_slice(s.name).to_lowercase()) {
+                    used.extend(
+                        self.macro_references(s.value)
+                            .into_iter()
+                            .map(|r| r.to_lowercase()),
+                    );
+                }
+            }
+            if used.len() == before {
+                break;
+            }
+        }
+    }
+
+    /// The spans of the values of the `@string` definitions, by
+    /// lowercase name (the names of the macros are case insensitive).
+    /// A string defined twice has its last value.
+    fn string_spans(&self) -> &HashMap<String, Range<usize>> {
+        self.strings.get_or_init(|| {
+            self.list_string_definitions()
+                .map(|s| (self.get_slice(s.name).to_lowercase(), s.value.byte_range()))
+                .collect()
+        })
+    }
+
+    /// The values of the `@string` definitions, as written (with their
+    /// delimiters), by lowercase name. A string defined twice has its
+    /// last value.
+    pub fn list_strings(&'a self) -> HashMap<&'a str, &'a str> {
+        self.string_spans()
+            .iter()
+            .map(|(name, span)| (name.as_str(), &self.content[span.clone()]))
+            .collect()
+    }
+
+    /// The text of a field value, with the `@string` definitions of the
+    /// file (and the builtins such as `jan`) expanded, e.g. `IEEE Trans.
+    /// 12` for `journal = IEEEtrans # " 12"`: the contents of its groups,
+    /// and its numbers, with the macros replaced by the expansion of
+    /// their value. The unknown macros, and the macros deeper than
+    /// `MAX_EXPANSION_DEPTH`, are left as their name.
+    pub fn get_expanded_slice(&'a self, node: Node) -> Cow<'a, str> {
+        let strings = self.string_spans();
+        let lookup = |name: &str| strings.get(name).map(|span| &self.content[span.clone()]);
+        expand_at_depth(self.get_slice(node), &lookup, 0)
+    }
+
+    /// The `@comment` blocks where JabRef and BibDesk store their
+    /// metadata, with their byte ranges (see `metadata`).
+    pub fn metadata_comments(&self) -> Vec<(MetadataComment, Range<usize>)> {
+        let mut cursor = self.tree.root_node().walk();
+        let starts = self
+            .tree
+            .root_node()
+            .children(&mut cursor)
+            .map(|node| node.start_byte())
+            .collect::<Vec<_>>();
+        metadata_spans(self.content, starts)
+    }
+
+    /// The keys of the entries of every group of JabRef, by name: the
+    /// groups of the groups tree, and the ones named by the `groups`
+    /// fields of the entries.
+    pub fn jabref_groups(&'a self) -> BTreeMap<String, Vec<String>> {
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (dialect, span) in self.metadata_comments() {
+            if dialect != MetadataComment::Jabref {
+                continue;
+            }
+            for group in parse_jabref_groups(&self.content[span]).unwrap_or_default() {
+                if !group.name.is_empty() {
+                    groups.entry(group.name).or_default().extend(group.keys);
+                }
+            }
         }
+        for entry in self.list_entries() {
+            let key = self.get_slice(entry.key);
+            let field = entry
+                .fields
+                .iter()
+                .find(|f| self.get_slice(f.name).eq_ignore_ascii_case("groups"));
+            for name in field.map_or(vec![], |f| entry_groups(self.get_slice(f.value))) {
+                let keys = groups.entry(name.to_string()).or_default();
+                if !keys.iter().any(|k| k == key) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+        groups
     }
 
     pub fn list_entries(&'a self) -> impl Iterator<Item = BibEntry<'a>> {
@@ -144,6 +330,392 @@ impl<'a> BibFile<'a> {
 
         entries.into_iter()
     }
+
+    /// The entry of the given key (the first one, when it is duplicated).
+    pub fn find_entry(&'a self, key: &str) -> Option<BibEntry<'a>> {
+        self.list_entries()
+            .find(|entry| self.get_slice(entry.key).trim() == key)
+    }
+
+    /// The header of an entry from the end of its type to the comma after
+    /// its key (`{ key ,`), when it has spaces around the brace or the key
+    /// that `bibadac format` removes.
+    pub fn header_spacing(&self, entry: &BibEntry) -> Option<Range<usize>> {
+        let start = entry.entrytype.end_byte();
+        let open = self.content.get(start..entry.key.start_byte())?;
+        let key = self.get_slice(entry.key);
+        let after = self.content.get(entry.key.end_byte()..)?;
+        let trailing = after.len() - after.trim_start().len();
+        let end = entry.key.end_byte() + trailing;
+        let stray = open.len() > 1 || key.trim() != key || trailing > 0;
+        // only the spaces of a well-formed header, the rest are syntax errors
+        let well_formed =
+            matches!(open.trim(), "{" | "(") && after.trim_start().starts_with([',', '}', ')']);
+        (stray && well_formed).then_some(start..end)
+    }
+
+    /// The syntax tree, as an S-expression (the notation of tree-sitter).
+    pub fn to_sexp(&self) -> String {
+        self.tree.root_node().to_sexp()
+    }
+
+    /// The syntax tree below a node, one node per line, indented by
+    /// its depth: its kind (quoted for the punctuation), its byte range
+    /// and position, whether it is an `ERROR` or `MISSING` node, and
+    /// the text of the leaves, e.g. `key_brace 9..14 L1:C10 "doe20"`.
+    pub fn debug_tree(&self, node: Node) -> String {
+        use std::fmt::Write;
+        let mut out = String::new();
+        let mut cursor = node.walk();
+        let mut depth = 0;
+        loop {
+            let node = cursor.node();
+            let kind = if node.is_named() {
+                node.kind().to_string()
+            } else {
+                format!("{:?}", node.kind())
+            };
+            let position = node.start_position();
+            let _ = write!(
+                out,
+                "{:indent$}{} {}..{} L{}:C{}",
+                "",
+                kind,
+                node.start_byte(),
+                node.end_byte(),
+                position.row + 1,
+                position.column + 1,
+                indent = 2 * depth
+            );
+            if node.is_error() {
+                out.push_str(" ERROR");
+            }
+            if node.is_missing() {
+                out.push_str(" MISSING");
+            }
+            if node.is_named() && node.child_count() == 0 {
+                let _ = write!(out, " {:?}", self.get_slice(node));
+            }
+            out.push('\n');
+
+            if cursor.goto_first_child() {
+                depth += 1;
+                continue;
+            }
+            // the next sibling of the node, or of its closest ancestor
+            while !cursor.goto_next_sibling() {
+                if depth == 0 || !cursor.goto_parent() {
+                    return out;
+                }
+                depth -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueTokenKind {
+    /// Characters inside of a group, or a number (`year = 2020`)
+    Text,
+    /// `{...}`, including the braces
+    BraceGroup,
+    /// `"..."`, including the quotes
+    QuotedGroup,
+    /// A macro name (`jan`, or a `@string` definition)
+    MacroRef,
+    /// The `#` operator
+    Concat,
+    /// An escaped special character (`\%`, see `ESCAPED_SPECIALS`)
+    Escape,
+}
+
+/// The special characters of LaTeX that are written escaped in the
+/// text of a value (`\%`, `\&`, `\_`, `\#`, `\$`, `\{`, `\}`).
+pub const ESCAPED_SPECIALS: &[char] = &['%', '&', '_', '#', '$', '{', '}'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueToken {
+    pub kind: ValueTokenKind,
+    /// Byte range in the file
+    pub span: Range<usize>,
+    /// Number of groups around the token: the delimiters of the
+    /// value are at depth 0, and their content at depth 1, so that
+    /// a character is protected by braces when its depth is at least 2.
+    pub depth: usize,
+}
+
+/// The tokens of a field value, see `tokenize_value`.
+pub fn value_tokens(file: &BibFile, value: Node) -> Vec<ValueToken> {
+    tokenize_value(file.get_slice(value), value.start_byte())
+}
+
+/// Cuts a field value, found at the given offset of the file, into tokens.
+/// A group comes before the tokens of its content.
+///
+/// The escaped special characters (`\%`, `\{`) are escapes, the other
+/// escaped characters (`\"`, `\'`) are text, a quote only closes a quoted
+/// group outside of braces, an unbalanced closing brace is text, and
+/// the groups that are not closed run to the end of the value.
+pub fn tokenize_value(value: &str, offset: usize) -> Vec<ValueToken> {
+    use ValueTokenKind::*;
+
+    let mut tokens: Vec<ValueToken> = vec![];
+    // indices (in tokens) of the groups that are not closed yet
+    let mut open: Vec<usize> = vec![];
+    // kind and start of the current text or macro
+    let mut text: Option<(ValueTokenKind, usize)> = None;
+    let flush = |tokens: &mut Vec<ValueToken>,
+                 text: &mut Option<(ValueTokenKind, usize)>,
+                 end: usize,
+                 depth: usize| {
+        if let Some((kind, start)) = text.take() {
+            tokens.push(ValueToken {
+                kind,
+                span: offset + start..offset + end,
+                depth,
+            });
+        }
+    };
+
+    let mut chars = value.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let depth = open.len();
+        let innermost = open.last().map(|&t| tokens[t].kind);
+        match (innermost, c) {
+            (_, '{') | (None, '"') => {
+                flush(&mut tokens, &mut text, i, depth);
+                open.push(tokens.len());
+                tokens.push(ValueToken {
+                    kind: if c == '{' { BraceGroup } else { QuotedGroup },
+                    span: offset + i..offset + value.len(),
+                    depth,
+                });
+            }
+            (Some(BraceGroup), '}') | (Some(QuotedGroup), '"') => {
+                flush(&mut tokens, &mut text, i, depth);
+                if let Some(group) = open.pop() {
+                    tokens[group].span.end = offset + i + c.len_utf8();
+                }
+            }
+            (None, '#') => {
+                flush(&mut tokens, &mut text, i, depth);
+                tokens.push(ValueToken {
+                    kind: Concat,
+                    span: offset + i..offset + i + 1,
+                    depth,
+                });
+            }
+            (None, c) if c.is_whitespace() => flush(&mut tokens, &mut text, i, depth),
+            (None, c) => {
+                text.get_or_insert((if c.is_alphabetic() { MacroRef } else { Text }, i));
+            }
+            (Some(_), '\\') => match chars.next() {
+                Some((_, e)) if ESCAPED_SPECIALS.contains(&e) => {
+                    flush(&mut tokens, &mut text, i, depth);
+                    tokens.push(ValueToken {
+                        kind: Escape,
+                        span: offset + i..offset + i + 1 + e.len_utf8(),
+                        depth,
+                    });
+                }
+                _ => {
+                    text.get_or_insert((Text, i));
+                }
+            },
+            (Some(_), _) => {
+                text.get_or_insert((Text, i));
+            }
+        }
+    }
+    flush(&mut tokens, &mut text, value.len(), open.len());
+    tokens
+}
+
+/// The tokens, with the escapes joined to the text around them, for
+/// the checks that read the text of a value as it is written.
+pub fn join_escapes(tokens: Vec<ValueToken>) -> Vec<ValueToken> {
+    use ValueTokenKind::*;
+
+    let mut joined: Vec<ValueToken> = vec![];
+    for mut token in tokens {
+        if token.kind == Escape {
+            token.kind = Text;
+        }
+        match joined.last_mut() {
+            Some(last)
+                if token.kind == Text
+                    && last.kind == Text
+                    && last.depth == token.depth
+                    && last.span.end == token.span.start =>
+            {
+                last.span.end = token.span.end;
+            }
+            _ => joined.push(token),
+        }
+    }
+    joined
+}
+
+/// The depth at which the `@string` definitions stop being expanded,
+/// so that the circular definitions end.
+pub const MAX_EXPANSION_DEPTH: usize = 10;
+
+/// Expands `value` (as written, with its delimiters), with the
+/// definitions given by `lookup` (by lowercase name), or the builtins
+/// (see `BibFile::get_expanded_slice`).
+fn expand_at_depth<'a>(
+    value: &'a str,
+    lookup: &impl Fn(&str) -> Option<&'a str>,
+    depth: usize,
+) -> Cow<'a, str> {
+    if let Some(content) = group_content(value) {
+        return Cow::Borrowed(content);
+    }
+    let mut text = String::new();
+    for token in tokenize_value(value, 0) {
+        let part = &value[token.span];
+        match token.kind {
+            _ if token.depth > 0 => {}
+            ValueTokenKind::BraceGroup | ValueTokenKind::QuotedGroup => {
+                text += group_content(part).unwrap_or(&part[1..]);
+            }
+            ValueTokenKind::MacroRef => {
+                // the names of the macros are case insensitive
+                match lookup(&part.to_lowercase()) {
+                    Some(definition) if depth < MAX_EXPANSION_DEPTH => {
+                        text += &expand_at_depth(definition, lookup, depth + 1);
+                    }
+                    Some(_) => text += part,
+                    None => text += builtin_string(part).unwrap_or(part),
+                }
+            }
+            ValueTokenKind::Concat => {}
+            ValueTokenKind::Text | ValueTokenKind::Escape => text += part,
+        }
+    }
+    Cow::Owned(text)
+}
+
+/// The content of a value made of a single closed group (`{...}` or
+/// `"..."`), e.g. `Doe, J.` for `{Doe, J.}`, and `None` for the
+/// numbers, the macros and the concatenations.
+pub fn group_content(value: &str) -> Option<&str> {
+    let mut outer = tokenize_value(value, 0)
+        .into_iter()
+        .filter(|t| t.depth == 0);
+    let group = outer.next()?;
+    let close = match group.kind {
+        ValueTokenKind::BraceGroup => '}',
+        ValueTokenKind::QuotedGroup => '"',
+        _ => return None,
+    };
+    let single = outer.next().is_none() && group.span == (0..value.len());
+    (single && value.len() >= 2 && value.ends_with(close)).then(|| &value[1..value.len() - 1])
+}
+
+/// The offset of the first brace of a field value that is not matched:
+/// a closing brace without an opening one, or else the first opening
+/// brace that is not closed. As in `tokenize_value`, the escaped braces
+/// (`\{`, `\}`) are escapes, which do not open or close a group.
+///
+/// The parser balances the braces of a value by swallowing the
+/// following fields, up to the end of the entry: the value is then
+/// only checked up to the first swallowed field (see `swallowed_field`).
+pub fn unbalanced_brace(value: &str) -> Option<usize> {
+    let value = &value[..swallowed_field(value).unwrap_or(value.len())];
+    let mut open: Vec<usize> = vec![];
+    let mut chars = value.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // an escape (`\{`), or the first character of a command (`\\`)
+            '\\' => {
+                chars.next();
+            }
+            '{' => open.push(i),
+            '}' if open.pop().is_none() => return Some(i),
+            _ => {}
+        }
+    }
+    open.first().copied()
+}
+
+/// The fingerprint of an entry: the sha256 of its normalized content,
+/// so that tools can tell which entries changed without diffing text.
+///
+/// The fingerprint is part of the stability contract of `bibadac`, and
+/// only changes with the content of the entry. It is computed from:
+///
+/// 1. the entry type, in lowercase;
+/// 2. the fields, as the pairs of their name (in lowercase) and their
+///    value in the canonical form of `values::canonical_value` (without
+///    delimiters and braces, concatenations joined, month macros
+///    replaced, whitespace collapsed, months by number, dois bare and in
+///    lowercase, and names written `Last, First`), with their case;
+/// 3. sorted, keeping the repeated fields, each string being hashed
+///    after its length in bytes (a little-endian `u64`).
+///
+/// Hence, reformatting an entry, reordering its fields, changing their
+/// delimiters or their spacing keeps its fingerprint, while any other
+/// change of a value, of the entry type, or of the set of fields does
+/// not. The key is not part of the fingerprint.
+pub fn entry_fingerprint(file: &BibFile, entry: &BibEntry) -> [u8; 32] {
+    use sha2::Digest;
+    let mut fields = entry
+        .fields
+        .iter()
+        .map(|field| {
+            let name = file.get_slice(field.name).to_lowercase();
+            let value = canonical_value(&name, file.get_slice(field.value));
+            (name, value)
+        })
+        .collect::<Vec<_>>();
+    fields.sort();
+    let mut hasher = sha2::Sha256::new();
+    let mut hash = |text: &str| {
+        hasher.update((text.len() as u64).to_le_bytes());
+        hasher.update(text.as_bytes());
+    };
+    let entrytype = file.get_slice(entry.entrytype).trim_start_matches('@');
+    hash(&entrytype.to_lowercase());
+    for (name, value) in fields.iter() {
+        hash(name);
+        hash(value);
+    }
+    hasher.finalize().into()
+}
+
+/// The offset of the comma that ends a field value, followed by
+/// another field (`,\n  author = ...`), when a brace group of the
+/// value runs over it.
+fn swallowed_field(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = value.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth > 0 => {
+                let rest = value[i + 1..].trim_start_matches([' ', '\t', '\r']);
+                let Some(rest) = rest.strip_prefix('\n') else {
+                    continue;
+                };
+                let rest = rest.trim_start();
+                let name_len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(rest.len());
+                let starts_field = rest.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && rest[name_len..].trim_start().starts_with('=');
+                if starts_field {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
 }
 
 struct DFSIterator<'a> {
@@ -180,3 +752,342 @@ impl<'a> Iterator for DFSIterator<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ValueTokenKind::*;
+
+    fn tokens(value: &str) -> Vec<(ValueTokenKind, &str, usize)> {
+        tokenize_value(value, 0)
+            .into_iter()
+            .map(|t| (t.kind, &value[t.span], t.depth))
+            .collect()
+    }
+
+    #[test]
+    fn test_debug_tree() {
+        let file = BibFile::new("@misc{a, title = {A}}\n@misc{b, title = {B}\n");
+        assert!(file.to_sexp().starts_with("(document (entry"));
+
+        let entry = file.find_entry("a").unwrap();
+        let tree = file.debug_tree(entry.loc);
+        let lines = tree.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("entry 0..21 L1:C1"));
+        assert!(lines.iter().any(|l| l.starts_with("  key_brace 6..7 L1:C7") && l.ends_with("\"a\"")));
+        assert!(lines.iter().any(|l| l.starts_with("  \"{\"")));
+        assert!(!tree.contains("ERROR") && !tree.contains("MISSING"));
+        // nothing of the other entry
+        assert!(!tree.contains("L2:"));
+
+        // the closing brace of the last entry is missing
+        let tree = file.debug_tree(file.tree.root_node());
+        assert!(tree.contains("MISSING"));
+        assert!(file.find_entry("c").is_none());
+    }
+
+    #[test]
+    fn test_nested_braces() {
+        assert_eq!(
+            tokens("{The {RNA} world}"),
+            vec![
+                (BraceGroup, "{The {RNA} world}", 0),
+                (Text, "The ", 1),
+                (BraceGroup, "{RNA}", 1),
+                (Text, "RNA", 2),
+                (Text, " world", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quotes_and_macros() {
+        assert_eq!(
+            tokens("jan # \" {A}\" # 2020"),
+            vec![
+                (MacroRef, "jan", 0),
+                (Concat, "#", 0),
+                (QuotedGroup, "\" {A}\"", 0),
+                (Text, " ", 1),
+                (BraceGroup, "{A}", 1),
+                (Text, "A", 2),
+                (Concat, "#", 0),
+                (Text, "2020", 0),
+            ]
+        );
+        // a quote inside of braces does not close the value
+        assert_eq!(
+            tokens("\"{\"}\""),
+            vec![
+                (QuotedGroup, "\"{\"}\"", 0),
+                (BraceGroup, "{\"}", 1),
+                (Text, "\"", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_brace() {
+        // (value, offset of the unmatched brace)
+        let matrix = [
+            ("{The {L}TL problem}", None),
+            ("\"The {L}TL problem\"", None),
+            ("{The {L TL problem", Some(0)),
+            ("{The {L}TL} problem}", Some(19)),
+            ("\"The L}TL problem\"", Some(6)),
+            ("{The \\{L TL problem}", None),
+            ("{50\\% of \\}s}", None),
+            ("{The \\{L} TL problem}", Some(20)),
+            ("{The \\}L} TL problem}", Some(20)),
+            ("{The \\\\{L} TL problem}", None),
+            // the following fields, swallowed by the value
+            (
+                "{The {L TL problem},\n  author = {Doe, J.},\n  year = 2020,\n}",
+                Some(0),
+            ),
+            ("{Smith, A., and Doe, J.},\n  title = {T}", None),
+        ];
+        for (value, offset) in matrix {
+            assert_eq!(unbalanced_brace(value), offset, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_value_span() {
+        let content = "@misc{a, doi = {10.1/x}, title = \"T\", year = 2020, note = {}}";
+        let file = BibFile::new(content);
+        let entry = file.list_entries().next().unwrap();
+        let spans = entry
+            .fields
+            .iter()
+            .map(|f| {
+                let (span, text) = file.get_value_span(f.value);
+                assert_eq!(&content[span.clone()], text);
+                (span.start, text)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![(16, "10.1/x"), (34, "T"), (45, "2020"), (59, "")]
+        );
+    }
+
+    #[test]
+    fn test_expanded_slice() {
+        let content = "@string{IEEEtrans = {IEEE Trans.}}\n\
+                       @string{tcs = \"Theor. \" # Comput}\n\
+                       @string{Comput = \"Comput. Sci.\"}\n\
+                       @string{loop = loop # \"!\"}\n\
+                       @misc{a, journal = IEEEtrans # \" 12\", note = TCS, month = jan,\n\
+                       title = {A {B}}, year = 2020, howpublished = unknown, series = Loop}";
+        let file = BibFile::new(content);
+        let strings = file.list_strings();
+        assert_eq!(strings.len(), 4);
+        assert_eq!(strings["ieeetrans"], "{IEEE Trans.}");
+        let entry = file.list_entries().next().unwrap();
+        let expanded = entry
+            .fields
+            .iter()
+            .map(|f| file.get_expanded_slice(f.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            &expanded[..6],
+            [
+                "IEEE Trans. 12",
+                "Theor. Comput. Sci.",
+                "January",
+                "A {B}",
+                "2020",
+                "unknown",
+            ]
+        );
+        // the circular definitions stop
+        assert_eq!(
+            expanded[6],
+            format!("loop{}", "!".repeat(MAX_EXPANSION_DEPTH))
+        );
+    }
+
+    #[test]
+    fn test_expanded_slice_redefined() {
+        // the last definition wins, whatever the case of its name
+        let file =
+            BibFile::new("@string{acm = {ACM}}\n@string{ACM = {Assoc.}}\n@misc{a, note = Acm}");
+        let entry = file.list_entries().next().unwrap();
+        assert_eq!(file.get_expanded_slice(entry.fields[0].value), "Assoc.");
+        assert_eq!(file.list_strings(), HashMap::from([("acm", "{Assoc.}")]));
+    }
+
+    #[test]
+    fn test_jabref_groups() {
+        let content = "@misc{a, groups = {Reading, Logic}}\n\
+                       @misc{b, groups = {Logic}}\n\
+                       @misc{c, title = {C}}\n\
+                       @comment{jabref-meta: groupstree:\n\
+                       0 AllEntriesGroup:;\n\
+                       1 ExplicitGroup:Old\\;0\\;c\\;a\\;;\n\
+                       1 StaticGroup:Logic\\;0\\;1\\;\\;\\;\\;;\n\
+                       1 StaticGroup:Empty\\;0\\;1\\;\\;\\;\\;;\n\
+                       }\n";
+        let bib = BibFile::new(content);
+        let groups = bib.jabref_groups();
+        assert_eq!(
+            groups.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("Empty".to_string(), vec![]),
+                ("Logic".to_string(), vec!["a".to_string(), "b".to_string()]),
+                ("Old".to_string(), vec!["c".to_string(), "a".to_string()]),
+                ("Reading".to_string(), vec!["a".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaped_and_unbalanced_braces() {
+        assert_eq!(
+            tokens("{50\\% \\{a\\}}"),
+            vec![
+                (BraceGroup, "{50\\% \\{a\\}}", 0),
+                (Text, "50", 1),
+                (Escape, "\\%", 1),
+                (Text, " ", 1),
+                (Escape, "\\{", 1),
+                (Text, "a", 1),
+                (Escape, "\\}", 1),
+            ]
+        );
+        // unclosed groups run to the end of the value
+        assert_eq!(
+            tokens("{a {b"),
+            vec![
+                (BraceGroup, "{a {b", 0),
+                (Text, "a ", 1),
+                (BraceGroup, "{b", 1),
+                (Text, "b", 2),
+            ]
+        );
+        // an extra closing brace is text
+        assert_eq!(
+            tokens("\"a}\""),
+            vec![(QuotedGroup, "\"a}\"", 0), (Text, "a}", 1)]
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        for special in ESCAPED_SPECIALS {
+            let value = format!("{{a\\{}b}}", special);
+            let escape = format!("\\{}", special);
+            assert_eq!(
+                tokens(&value),
+                vec![
+                    (BraceGroup, value.as_str(), 0),
+                    (Text, "a", 1),
+                    (Escape, escape.as_str(), 1),
+                    (Text, "b", 1),
+                ],
+                "{}",
+                value
+            );
+        }
+        // the accents and the commands are text
+        assert_eq!(
+            tokens("{Trait\\'e \\emph{x}}"),
+            vec![
+                (BraceGroup, "{Trait\\'e \\emph{x}}", 0),
+                (Text, "Trait\\'e \\emph", 1),
+                (BraceGroup, "{x}", 1),
+                (Text, "x", 2),
+            ]
+        );
+        // the escapes are joined back to their text
+        let value = "{95\\% on {A\\&B}}";
+        let joined = join_escapes(tokenize_value(value, 0))
+            .into_iter()
+            .map(|t| (t.kind, &value[t.span], t.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            joined,
+            vec![
+                (BraceGroup, value, 0),
+                (Text, "95\\% on ", 1),
+                (BraceGroup, "{A\\&B}", 1),
+                (Text, "A\\&B", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_content() {
+        assert_eq!(group_content("{Doe, J.}"), Some("Doe, J."));
+        assert_eq!(group_content("\"Doe and {Roe}\""), Some("Doe and {Roe}"));
+        assert_eq!(group_content("{}"), Some(""));
+        assert_eq!(group_content("1"), None);
+        assert_eq!(group_content("authors"), None);
+        assert_eq!(group_content("{Doe} # \" and Roe\""), None);
+        assert_eq!(group_content("{Doe"), None);
+        assert_eq!(group_content("{"), None);
+        assert_eq!(group_content(""), None);
+    }
+
+    #[test]
+    fn test_entry_fingerprint() {
+        let fingerprints = |content: &str| {
+            let file = BibFile::new(content);
+            file.list_entries()
+                .map(|entry| entry_fingerprint(&file, &entry))
+                .collect::<Vec<_>>()
+        };
+        let entry = "@misc{a, title = {A}, author = {Jane Doe}}";
+        // the fingerprint is stable across versions of bibadac
+        let hex = fingerprints(entry)[0]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        assert_eq!(
+            hex,
+            "519db1b7a0cb74777a8c0b61680fd55e6552d6e81cc447fda42b906bba5e4923"
+        );
+
+        // reformatting the entry keeps its fingerprint
+        let versions = fingerprints(
+            "@misc{a, title = {A}, author = {Jane Doe}}\n\
+             @MISC{b,\n  author = {Doe, Jane},\n  Title  = \"A\",\n}\n\
+             @misc{c,\n  title = {{A}},\n\n  author = { Doe,\n    Jane },\n}\n",
+        );
+        assert!(versions.iter().all(|f| *f == versions[0]));
+
+        // any other change does not
+        let changes = fingerprints(
+            "@misc{a, title = {a}, author = {Jane Doe}}\n\
+             @misc{a, title = {A}, author = {Jane Doe}, year = 2020}\n\
+             @book{a, title = {A}, author = {Jane Doe}}\n\
+             @misc{a, title = {A}, author = {John Doe}}\n\
+             @misc{a, title = {A}, title = {A}, author = {Jane Doe}}\n",
+        );
+        for change in changes {
+            assert_ne!(change, versions[0]);
+        }
+    }
+
+    #[test]
+    fn test_entry_fingerprint_of_formatted_entries() {
+        use crate::bibdb::LocalBibDb;
+        use crate::format::{write_bibfile, FormatOptions};
+
+        let content = "@Article{knuth1984,\n    title = \"Literate \" # \"Programming\",\n  author={Donald E. Knuth},\n  month = jan, year = 1984,\n  doi = {https://doi.org/10.1093/COMJNL/27.2.97}\n}\n";
+        let original = BibFile::new(content);
+        let entry = original.list_entries().next().unwrap();
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.sort_fields = true;
+        let mut formatted = String::new();
+        write_bibfile(&original, &options, &mut formatted).unwrap();
+        let file = BibFile::new(&formatted);
+        let formatted_entry = file.list_entries().next().unwrap();
+        assert_eq!(
+            entry_fingerprint(&original, &entry),
+            entry_fingerprint(&file, &formatted_entry)
+        );
+    }
+}
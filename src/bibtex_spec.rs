@@ -61,6 +61,116 @@ pub const BIBTEX_FIELDS: [&str; 28] = [
     "keywords",
 ];
 
+/// The conventions that entries are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    Bibtex,
+    Biblatex,
+}
+
+/// The BibTeX entry types that BibLaTeX writes otherwise: the
+/// BibLaTeX type, and the value of its `type` field (if any).
+/// Types without a `type` value are only converted to BibLaTeX.
+pub const BIBLATEX_ENTRY_TYPES: [(&str, &str, Option<&str>); 3] = [
+    ("mastersthesis", "thesis", Some("mathesis")),
+    ("phdthesis", "thesis", Some("phdthesis")),
+    ("conference", "inproceedings", None),
+];
+
+/// The entry types that BibLaTeX reads as another type.
+pub const ENTRY_TYPE_ALIASES: [(&str, &str); 3] = [
+    ("conference", "inproceedings"),
+    ("electronic", "online"),
+    ("www", "online"),
+];
+
+/// The lowercase entry type (`@Conference` or `conference`),
+/// without `@`, and with its alias resolved (`inproceedings`).
+pub fn canonical_entry_type(entrytype: &str) -> String {
+    let entrytype = entrytype.trim_start_matches('@').to_lowercase();
+    match ENTRY_TYPE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == entrytype)
+    {
+        Some((_, target)) => target.to_string(),
+        None => entrytype,
+    }
+}
+
+/// Whether the entry type is one of the `types`, up to case and aliases.
+pub fn is_entry_type(entrytype: &str, types: &[String]) -> bool {
+    let entrytype = canonical_entry_type(entrytype);
+    types.iter().any(|t| canonical_entry_type(t) == entrytype)
+}
+
+/// The BibTeX fields that BibLaTeX names otherwise.
+pub const BIBLATEX_FIELDS: [(&str, &str); 4] = [
+    ("journal", "journaltitle"),
+    ("address", "location"),
+    ("archiveprefix", "eprinttype"),
+    ("primaryclass", "eprintclass"),
+];
+
+/// The field naming the people responsible for the entries of a type,
+/// where `author` and `editor` are often confused: the proceedings and
+/// collections are edited, and their parts are authored (the editors
+/// being those of the book).
+pub const PEOPLE_FIELDS: [(&str, &str); 6] = [
+    ("proceedings", "editor"),
+    ("mvproceedings", "editor"),
+    ("collection", "editor"),
+    ("mvcollection", "editor"),
+    ("incollection", "author"),
+    ("inbook", "author"),
+];
+
+/// The field naming the people of the entry type (`@proceedings`
+/// or `proceedings`, in any case), if listed in `PEOPLE_FIELDS`.
+pub fn people_field(entrytype: &str) -> Option<&'static str> {
+    let entrytype = entrytype.trim_start_matches('@');
+    PEOPLE_FIELDS
+        .iter()
+        .find(|(t, _)| t.eq_ignore_ascii_case(entrytype))
+        .map(|(_, field)| *field)
+}
+
+/// The field that the people of an entry are in instead of the field
+/// of its type (see `people_field`), and that field, given whether the
+/// entry has an `author` and an `editor` field.
+pub fn confused_people_field(
+    entrytype: &str,
+    has_author: bool,
+    has_editor: bool,
+) -> Option<(&'static str, &'static str)> {
+    match people_field(entrytype)? {
+        "editor" if has_author && !has_editor => Some(("author", "editor")),
+        "author" if has_editor && !has_author => Some(("editor", "author")),
+        _ => None,
+    }
+}
+
+/// The fields that an entry inherits from its `crossref` parent, such
+/// as the booktitle of its proceedings, when it does not have them.
+pub const CROSSREF_INHERITED_FIELDS: [&str; 10] = [
+    "booktitle",
+    "editor",
+    "publisher",
+    "address",
+    "organization",
+    "series",
+    "volume",
+    "number",
+    "year",
+    "month",
+];
+
+/// Whether an entry inherits the field (in any case) from its `crossref` parent.
+pub fn is_crossref_inherited(field: &str) -> bool {
+    CROSSREF_INHERITED_FIELDS
+        .iter()
+        .any(|f| f.eq_ignore_ascii_case(field))
+}
+
 struct NFA<T> {
     final_states: Vec<T>,
     transitions: Vec<(T, Option<char>, T)>,
@@ -259,6 +369,29 @@ pub fn entry_typo(s: &str) -> Vec<&'static str> {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_confused_people_field() {
+        assert_eq!(people_field("@Proceedings"), Some("editor"));
+        assert_eq!(people_field("article"), None);
+        // the authors of proceedings are their editors
+        assert_eq!(
+            confused_people_field("@proceedings", true, false),
+            Some(("author", "editor"))
+        );
+        assert_eq!(confused_people_field("@collection", true, true), None);
+        assert_eq!(confused_people_field("@proceedings", false, true), None);
+        // the editors of a chapter are those of the book
+        assert_eq!(
+            confused_people_field("@incollection", false, true),
+            Some(("editor", "author"))
+        );
+        assert_eq!(confused_people_field("@InBook", true, true), None);
+        assert_eq!(confused_people_field("@article", true, false), None);
+        assert_eq!(confused_people_field("@book", false, true), None);
+        assert!(is_crossref_inherited("BookTitle"));
+        assert!(!is_crossref_inherited("title"));
+    }
+
     #[test]
     fn test_word_automaton_two_words() {
         let a = assigning_automaton(vec!["hello", "world"]);
@@ -296,6 +429,18 @@ mod test {
         assert_eq!(field_typo("auth").len(), 0);
     }
 
+    #[test]
+    fn test_entry_type_aliases() {
+        assert_eq!(canonical_entry_type("@Conference"), "inproceedings");
+        assert_eq!(canonical_entry_type("WWW"), "online");
+        assert_eq!(canonical_entry_type("@Article"), "article");
+        let types = vec!["InProceedings".to_string(), "book".to_string()];
+        assert!(is_entry_type("@conference", &types));
+        assert!(is_entry_type("@BOOK", &types));
+        assert!(!is_entry_type("@article", &types));
+        assert!(is_entry_type("@www", &["electronic".to_string()]));
+    }
+
     #[test]
     fn test_ambiguous_completion() {
         assert_eq!(entry_typo("mvbook"), vec!["mvbook"]);
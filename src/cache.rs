@@ -0,0 +1,273 @@
+//! This file is responsible for the on-disk cache
+//! of the metadata downloaded by `bibadac`.
+//!
+//! The cache lives in the user cache directory
+//! (`$XDG_CACHE_HOME/bibadac` on Linux), unless the
+//! `BIBADAC_CACHE_DIR` environment variable says otherwise.
+//!
+//! Every entry is stored in its own file, named after the
+//! sha256 of its key (e.g. `doi:10.1145/123`), and written
+//! atomically (temporary file + rename). Hence, several `bibadac`
+//! processes can read, write, and clear the cache at the same time
+//! without ever observing a half-written entry.
+//!
+//! A `CACHEDIR.TAG` file marks the directory as a bibadac cache,
+//! and the cache refuses to operate on untagged directories,
+//! so that a wrong `BIBADAC_CACHE_DIR` never deletes unrelated files.
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::dates::Date;
+
+pub const CACHE_DIR_ENV: &str = "BIBADAC_CACHE_DIR";
+
+const TAG_FILE: &str = "CACHEDIR.TAG";
+const TAG: &str = "Signature: 8a477f597d28d172789f06886806bc55\n\
+                   # This file is a cache directory tag created by bibadac.\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: String,
+    /// Seconds since the unix epoch
+    pub created: u64,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    /// Size on disk, in bytes
+    pub size: u64,
+    pub oldest: Option<u64>,
+    pub newest: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Cache {
+    /// The cache directory: `BIBADAC_CACHE_DIR` if set,
+    /// the user cache directory otherwise.
+    pub fn default_dir() -> Option<PathBuf> {
+        match std::env::var_os(CACHE_DIR_ENV) {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ => directories::ProjectDirs::from("", "", "bibadac")
+                .map(|dirs| dirs.cache_dir().to_path_buf()),
+        }
+    }
+
+    /// Opens the cache, creating (and tagging) the directory
+    /// if it does not exist yet.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Cache> {
+        let dir = dir.into();
+        let is_empty = match std::fs::read_dir(&dir) {
+            Ok(mut files) => files.next().is_none(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => true,
+            Err(e) => return Err(e),
+        };
+        if is_empty {
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(dir.join(TAG_FILE), TAG)?;
+        }
+        Cache::existing(dir)
+    }
+
+    /// Opens an existing cache, refusing to operate on
+    /// a directory that is not a bibadac cache.
+    pub fn existing(dir: impl Into<PathBuf>) -> io::Result<Cache> {
+        let dir = dir.into();
+        let tag = std::fs::read_to_string(dir.join(TAG_FILE)).unwrap_or_default();
+        if !tag.contains("bibadac") {
+            return Err(io::Error::other(format!(
+                "{} does not look like a bibadac cache (no {} file)",
+                dir.display(),
+                TAG_FILE
+            )));
+        }
+        Ok(Cache { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        use sha2::Digest;
+        let hash = format!("{:x}", sha2::Sha256::digest(key.as_bytes()));
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CacheEntry> {
+        let content = std::fs::read_to_string(self.path(key)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&content).ok()?;
+        // protects against (very unlikely) hash collisions
+        if entry.key == key { Some(entry) } else { None }
+    }
+
+    pub fn put(&self, key: &str, payload: &str) -> io::Result<()> {
+        let entry = CacheEntry {
+            key: key.to_string(),
+            created: now(),
+            payload: payload.to_string(),
+        };
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+        std::fs::write(
+            &tmp,
+            serde_json::to_string(&entry).map_err(io::Error::other)?,
+        )?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    /// Lists the entry files along with their metadata,
+    /// skipping the files that disappear while listing.
+    fn list(&self) -> io::Result<Vec<(PathBuf, u64, u64)>> {
+        let mut files = vec![];
+        for file in std::fs::read_dir(&self.dir)? {
+            let file = file?;
+            let path = file.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let Ok(entry) = serde_json::from_str::<CacheEntry>(&content) else {
+                continue;
+            };
+            files.push((path, content.len() as u64, entry.created));
+        }
+        Ok(files)
+    }
+
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        let files = self.list()?;
+        Ok(CacheStats {
+            entries: files.len(),
+            size: files.iter().map(|(_, size, _)| size).sum(),
+            oldest: files.iter().map(|(_, _, created)| *created).min(),
+            newest: files.iter().map(|(_, _, created)| *created).max(),
+        })
+    }
+
+    /// Removes the entries (older than the given age, if any),
+    /// returning the number of removed entries.
+    pub fn clear(&self, older_than: Option<Duration>) -> io::Result<usize> {
+        let limit = older_than.map(|age| now().saturating_sub(age.as_secs()));
+        let mut removed = 0;
+        for (path, _, created) in self.list()? {
+            if limit.is_some_and(|limit| created >= limit) {
+                continue;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                // another process removed it first
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Parses an age such as `30d`, `12h`, `2w`, or `90s`.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (count, unit) = s.split_at(split);
+    let invalid = || format!("Invalid age {:?} (expected e.g. 30d)", s);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Invalid age unit {:?} (expected s, m, h, d, or w)",
+                unit
+            ));
+        }
+    };
+    let secs = count.checked_mul(seconds).ok_or_else(invalid)?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(secs: u64) -> String {
+    let time = secs % 86400;
+    format!(
+        "{} {:02}:{:02}:{:02} UTC",
+        Date::from_unix_days((secs / 86400) as i64),
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_age("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_age("90"), Ok(Duration::from_secs(90)));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+        assert!(parse_age("30000000000000000w").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1700000000), "2023-11-14 22:13:20 UTC");
+    }
+
+    #[test]
+    fn test_refuses_foreign_directories() {
+        let dir = crate::temp_dir("cache-foreign");
+        std::fs::write(dir.join("notes.txt"), "precious").unwrap();
+        assert!(Cache::open(&dir).is_err());
+        assert!(Cache::existing(&dir).is_err());
+        assert!(dir.join("notes.txt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_put_get_clear() {
+        let dir = crate::temp_dir("cache-roundtrip");
+        let cache = Cache::open(&dir).unwrap();
+        cache.put("doi:10.1145/123", "@article{a,}").unwrap();
+        cache.put("doi:10.1145/456", "@article{b,}").unwrap();
+        let entry = cache.get("doi:10.1145/123").unwrap();
+        assert_eq!(entry.payload, "@article{a,}");
+        assert!(cache.get("doi:10.1145/789").is_none());
+
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.oldest <= stats.newest);
+
+        // nothing is older than a day
+        assert_eq!(cache.clear(Some(Duration::from_secs(86400))).unwrap(), 0);
+        assert_eq!(cache.clear(None).unwrap(), 2);
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+        // the tag survives clearing
+        assert!(Cache::existing(&dir).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
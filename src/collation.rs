@@ -0,0 +1,67 @@
+//! This file is responsible for the order in which the formatter
+//! sorts the entries and the fields, which must not depend on the
+//! locale of the machine, so that reformatting is reproducible.
+//!
+//! Strings are compared by their collation key, where the LaTeX
+//! accents and braces are removed, the text is decomposed (NFKD)
+//! without its combining marks, and case folded: `{\'E}sik`,
+//! `Ésik`, and `esik` have the same key. Equal keys are then
+//! ordered by the bytes of the strings, so that the order is total.
+use std::cmp::Ordering;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// The key under which `s` is sorted.
+pub fn collation_key(s: &str) -> String {
+    let mut plain = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            // accents such as \' or \" are dropped, and the letter
+            // commands such as \L or \o are kept as their letters
+            '\\' => {
+                chars.next_if(|c| !c.is_alphabetic());
+            }
+            _ => plain.push(c),
+        }
+    }
+    plain
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(|c| match c {
+            'ß' => "ss".chars().collect::<Vec<_>>(),
+            c => c.to_lowercase().collect(),
+        })
+        .collect()
+}
+
+/// Compares two strings by collation key, then by bytes.
+pub fn collate(a: &str, b: &str) -> Ordering {
+    collation_key(a)
+        .cmp(&collation_key(b))
+        .then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collation_key() {
+        assert_eq!(collation_key("Ésik"), "esik");
+        assert_eq!(collation_key("{\\'E}sik"), "esik");
+        assert_eq!(collation_key("G{\\\"o}del"), "godel");
+        assert_eq!(collation_key("Straße"), "strasse");
+        assert_eq!(collation_key("ﬁeld"), "field");
+        assert_eq!(collation_key("\\L{}ukasiewicz"), "lukasiewicz");
+    }
+
+    #[test]
+    fn test_collate() {
+        let mut names = vec!["Fagin", "Ésik", "esik", "Esik", "Eilenberg"];
+        names.sort_by(|a, b| collate(a, b));
+        assert_eq!(names, vec!["Eilenberg", "Esik", "esik", "Ésik", "Fagin"]);
+    }
+}
@@ -0,0 +1,81 @@
+//! This file is responsible for finding the markers that git leaves in
+//! a file when a merge conflicts (`<<<<<<< HEAD`, `=======`, `>>>>>>>`),
+//! which BibTeX silently accepts between entries, and which end up in
+//! field values when they are inside of an entry.
+//!
+//! A marker is a line starting with seven marker characters, followed
+//! by the end of the line or a space (and the name of the branch).
+use std::ops::Range;
+
+/// The markers of a conflict, including the base of the diff3 style.
+pub const CONFLICT_MARKERS: [&str; 4] = ["<<<<<<<", "|||||||", "=======", ">>>>>>>"];
+
+/// Whether a line (without its line break) is a conflict marker.
+pub fn is_conflict_marker(line: &str) -> bool {
+    CONFLICT_MARKERS.iter().any(|marker| {
+        line.strip_prefix(marker)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\r']))
+    })
+}
+
+/// The byte ranges of the conflict markers of a file, line breaks excluded.
+pub fn conflict_markers(content: &str) -> Vec<Range<usize>> {
+    let mut markers = vec![];
+    let mut start = 0;
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        if is_conflict_marker(text) {
+            markers.push(start..start + text.len());
+        }
+        start += line.len();
+    }
+    markers
+}
+
+/// The text of a file outside of the conflict markers, as the stray
+/// text of `check --strict` is only reported when it is not a marker.
+pub fn without_conflict_markers(text: &str) -> String {
+    text.split_inclusive('\n')
+        .filter(|line| !is_conflict_marker(line.trim_end_matches(['\n', '\r'])))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_conflict_marker() {
+        assert!(is_conflict_marker("<<<<<<< HEAD"));
+        assert!(is_conflict_marker("======="));
+        assert!(is_conflict_marker(">>>>>>> feature/refs\r"));
+        assert!(is_conflict_marker("||||||| merged common ancestors"));
+        // longer rules, or markers that do not start the line
+        assert!(!is_conflict_marker("========"));
+        assert!(!is_conflict_marker("<<<<<<<HEAD"));
+        assert!(!is_conflict_marker("  ======="));
+        assert!(!is_conflict_marker("title = {<<<<<<< x}"));
+    }
+
+    #[test]
+    fn test_conflict_markers() {
+        let content = crate::read_fixture("conflicts", "outside.bib");
+        let markers = conflict_markers(&content)
+            .into_iter()
+            .map(|m| &content[m])
+            .collect::<Vec<_>>();
+        assert_eq!(markers, vec!["<<<<<<< HEAD", "=======", ">>>>>>> other"]);
+
+        let content = crate::read_fixture("conflicts", "inside.bib");
+        assert_eq!(conflict_markers(&content).len(), 3);
+        assert!(conflict_markers("@misc{a, title = {=====}}\n").is_empty());
+    }
+
+    #[test]
+    fn test_without_conflict_markers() {
+        assert_eq!(
+            without_conflict_markers("<<<<<<< HEAD\n\n=======\nstray\n>>>>>>> b\n"),
+            "\nstray\n"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! This file is responsible for `format --convert-to`, converting the
+//! entries between the conventions of BibTeX and BibLaTeX, following
+//! the tables of `bibtex_spec`: entry types (`@mastersthesis` is a
+//! `@thesis` with `type = {mathesis}`), aliased fields (`journal` is
+//! `journaltitle`), and the `year` and `month` fields, which BibLaTeX
+//! writes as a single `date`.
+//!
+//! A conversion that would lose information (a `@thesis` of another
+//! type, a `date` with a day, two aliased fields in the same entry)
+//! is not applied, and reported as an issue instead.
+use crate::bibtex::{BibEntry, BibFile};
+use crate::bibtex_spec::{Flavor, BIBLATEX_ENTRY_TYPES, BIBLATEX_FIELDS};
+use crate::dates::{month_macro, month_number, Date, DateValue};
+
+/// What happens to a field of the entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldEdit {
+    Keep,
+    Rename(&'static str),
+    /// A new name and value
+    Replace(&'static str, String),
+    Drop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    /// The new entry type (without `@`)
+    pub entrytype: Option<&'static str>,
+    /// The edit of every field of the entry, in order
+    pub fields: Vec<FieldEdit>,
+    /// The fields written after the others
+    pub added: Vec<(&'static str, String)>,
+    /// The conversions that were not applied
+    pub issues: Vec<String>,
+}
+
+fn braceless(value: &str) -> &str {
+    value
+        .trim()
+        .trim_start_matches(['{', '"'])
+        .trim_end_matches(['}', '"'])
+        .trim()
+}
+
+/// The number of a `month` value: `mar`, `{March}`, `jan.`, or `3`,
+/// when it is a single month.
+pub(crate) fn month_value(value: &str) -> Option<u32> {
+    let value = braceless(value);
+    match value.parse::<u32>() {
+        Ok(month) => (1..=12).contains(&month).then_some(month),
+        Err(_) => month_number(value),
+    }
+}
+
+/// The conversion of an entry, given its type (`@article`) and its
+/// fields, as pairs of lowercase names and values.
+pub fn convert(entrytype: &str, fields: &[(String, &str)], flavor: Flavor) -> Conversion {
+    let entrytype = entrytype.trim_start_matches('@').to_lowercase();
+    let position = |name: &str| fields.iter().position(|(n, _)| n == name);
+    let mut conversion = Conversion {
+        entrytype: None,
+        fields: vec![FieldEdit::Keep; fields.len()],
+        added: vec![],
+        issues: vec![],
+    };
+
+    match flavor {
+        Flavor::Biblatex => {
+            if let Some((_, to, kind)) = BIBLATEX_ENTRY_TYPES
+                .iter()
+                .find(|(from, _, _)| *from == entrytype)
+            {
+                match (kind, position("type")) {
+                    (Some(_), Some(_)) => conversion.issues.push(format!(
+                        "@{} has a type field, not converted to @{}",
+                        entrytype, to
+                    )),
+                    (kind, _) => {
+                        conversion.entrytype = Some(to);
+                        if let Some(kind) = kind {
+                            conversion.added.push(("type", format!("{{{}}}", kind)));
+                        }
+                    }
+                }
+            }
+        }
+        Flavor::Bibtex => {
+            let kinds = BIBLATEX_ENTRY_TYPES
+                .iter()
+                .filter(|(_, to, kind)| *to == entrytype && kind.is_some())
+                .collect::<Vec<_>>();
+            if !kinds.is_empty() {
+                let kind = position("type").map(|i| (i, braceless(fields[i].1)));
+                match kind.and_then(|(i, kind)| {
+                    kinds
+                        .iter()
+                        .find(|(_, _, k)| *k == Some(kind))
+                        .map(|(from, _, _)| (i, from))
+                }) {
+                    Some((i, from)) => {
+                        conversion.entrytype = Some(from);
+                        conversion.fields[i] = FieldEdit::Drop;
+                    }
+                    None => conversion.issues.push(format!(
+                        "@{} of type {} has no BibTeX counterpart",
+                        entrytype,
+                        kind.map_or("(none)", |(_, kind)| kind)
+                    )),
+                }
+            }
+        }
+    }
+
+    for (i, (name, _)) in fields.iter().enumerate() {
+        let target = BIBLATEX_FIELDS
+            .iter()
+            .find_map(|(bibtex, biblatex)| match flavor {
+                Flavor::Biblatex => (bibtex == name).then_some(*biblatex),
+                Flavor::Bibtex => (biblatex == name).then_some(*bibtex),
+            });
+        let Some(target) = target else {
+            continue;
+        };
+        if position(target).is_some() {
+            conversion.issues.push(format!(
+                "both {} and {} are present, {} is not renamed",
+                name, target, name
+            ));
+        } else {
+            conversion.fields[i] = FieldEdit::Rename(target);
+        }
+    }
+
+    match flavor {
+        Flavor::Biblatex => {
+            let (Some(y), Some(m)) = (position("year"), position("month")) else {
+                return conversion;
+            };
+            let year = braceless(fields[y].1);
+            let date = match (year.len() == 4, year.parse(), month_value(fields[m].1)) {
+                (true, Ok(year), Some(month)) => Some(Date {
+                    year,
+                    month: Some(month),
+                    day: None,
+                }),
+                _ => None,
+            };
+            match date {
+                Some(_) if position("date").is_some() => conversion
+                    .issues
+                    .push("both date and year are present, year is not converted".to_string()),
+                Some(date) => {
+                    conversion.fields[y] = FieldEdit::Replace("date", format!("{{{}}}", date));
+                    conversion.fields[m] = FieldEdit::Drop;
+                }
+                None => conversion.issues.push(format!(
+                    "year {} and month {} are not a date",
+                    fields[y].1, fields[m].1
+                )),
+            }
+        }
+        Flavor::Bibtex => {
+            let Some(d) = position("date") else {
+                return conversion;
+            };
+            if position("year").is_some() || position("month").is_some() {
+                conversion
+                    .issues
+                    .push("both date and year are present, date is not converted".to_string());
+                return conversion;
+            }
+            match DateValue::parse(braceless(fields[d].1)) {
+                Some(DateValue::Date(Date {
+                    year,
+                    month,
+                    day: None,
+                })) => {
+                    conversion.fields[d] = FieldEdit::Replace("year", year.to_string());
+                    if let Some(month) = month.and_then(month_macro) {
+                        conversion.added.push(("month", month.to_string()));
+                    }
+                }
+                _ => conversion
+                    .issues
+                    .push(format!("date {} has no BibTeX counterpart", fields[d].1)),
+            }
+        }
+    }
+    conversion
+}
+
+/// The lowercase names and the values of the fields of an entry.
+pub fn entry_fields<'a>(bib: &'a BibFile, entry: &BibEntry) -> Vec<(String, &'a str)> {
+    entry
+        .fields
+        .iter()
+        .map(|f| (bib.get_slice(f.name).to_lowercase(), bib.get_slice(f.value)))
+        .collect()
+}
+
+/// The issues of the conversion of every entry, along with its key.
+pub fn conversion_issues(bib: &BibFile, flavor: Flavor) -> Vec<(String, String)> {
+    bib.list_entries()
+        .flat_map(|entry| {
+            let key = bib.get_slice(entry.key).to_string();
+            convert(
+                bib.get_slice(entry.entrytype),
+                &entry_fields(bib, &entry),
+                flavor,
+            )
+            .issues
+            .into_iter()
+            .map(move |issue| (key.clone(), issue))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields<'a>(fields: &[(&str, &'a str)]) -> Vec<(String, &'a str)> {
+        fields.iter().map(|(n, v)| (n.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn test_convert_to_biblatex() {
+        let entry = fields(&[
+            ("journal", "{J}"),
+            ("archiveprefix", "{arXiv}"),
+            ("year", "2020"),
+            ("month", "mar"),
+        ]);
+        assert_eq!(
+            convert("@misc", &entry, Flavor::Biblatex),
+            Conversion {
+                entrytype: None,
+                fields: vec![
+                    FieldEdit::Rename("journaltitle"),
+                    FieldEdit::Rename("eprinttype"),
+                    FieldEdit::Replace("date", "{2020-03}".to_string()),
+                    FieldEdit::Drop,
+                ],
+                added: vec![],
+                issues: vec![],
+            }
+        );
+        let conversion = convert("@conference", &fields(&[]), Flavor::Biblatex);
+        assert_eq!(conversion.entrytype, Some("inproceedings"));
+        // a conference is not converted back
+        let conversion = convert("@inproceedings", &fields(&[]), Flavor::Bibtex);
+        assert_eq!(conversion.entrytype, None);
+        assert!(conversion.issues.is_empty());
+    }
+
+    #[test]
+    fn test_lossy_conversions() {
+        let entry = fields(&[("type", "{Master's thesis}")]);
+        let conversion = convert("@mastersthesis", &entry, Flavor::Biblatex);
+        assert_eq!(conversion.entrytype, None);
+        assert_eq!(conversion.issues.len(), 1);
+
+        let entry = fields(&[("type", "{candthesis}")]);
+        let conversion = convert("@thesis", &entry, Flavor::Bibtex);
+        assert_eq!(conversion.entrytype, None);
+        assert_eq!(conversion.fields, vec![FieldEdit::Keep]);
+        assert_eq!(conversion.issues.len(), 1);
+
+        let entry = fields(&[("journal", "{J}"), ("journaltitle", "{J.}")]);
+        let conversion = convert("@article", &entry, Flavor::Biblatex);
+        assert_eq!(conversion.fields, vec![FieldEdit::Keep, FieldEdit::Keep]);
+        assert_eq!(conversion.issues.len(), 1);
+
+        let entry = fields(&[("date", "{2020-03-01}")]);
+        let conversion = convert("@article", &entry, Flavor::Bibtex);
+        assert_eq!(conversion.fields, vec![FieldEdit::Keep]);
+        assert_eq!(conversion.issues.len(), 1);
+
+        let entry = fields(&[("year", "{2020}"), ("month", "{Spring}")]);
+        let conversion = convert("@article", &entry, Flavor::Biblatex);
+        assert_eq!(conversion.fields, vec![FieldEdit::Keep, FieldEdit::Keep]);
+        assert_eq!(conversion.issues.len(), 1);
+    }
+}
@@ -0,0 +1,133 @@
+//! This file is responsible for the metadata of the dois registered by
+//! DataCite (datasets, software, Zenodo records), whose BibTeX from
+//! dx.doi.org is often empty. Their CSL-JSON from api.datacite.org is
+//! written as a BibTeX entry instead, in the style of dx.doi.org
+//! (`@software{Doe_2021, ...}`), keeping the version and the url of
+//! the repository.
+use serde_json::Value;
+
+/// The url of the CSL-JSON of a DataCite doi.
+pub fn csl_url(doi: &str) -> String {
+    format!(
+        "https://api.datacite.org/dois/application/vnd.citationstyles.csl+json/{}",
+        doi
+    )
+}
+
+/// The BibTeX entry type of a CSL type.
+pub fn entry_type(csl_type: &str) -> &'static str {
+    match csl_type {
+        "software" => "software",
+        "dataset" => "dataset",
+        _ => "misc",
+    }
+}
+
+/// The name of a CSL author, as written in BibTeX.
+fn author(author: &Value) -> Option<String> {
+    if let Some(literal) = author.get("literal").and_then(Value::as_str) {
+        return Some(format!("{{{}}}", literal));
+    }
+    let family = author.get("family")?.as_str()?;
+    Some(match author.get("given").and_then(Value::as_str) {
+        Some(given) => format!("{}, {}", family, given),
+        None => family.to_string(),
+    })
+}
+
+/// The BibTeX entry of a CSL-JSON record of DataCite, `None` when
+/// the record has no title.
+pub fn csl_to_bibtex(text: &str) -> Option<String> {
+    let record: Value = serde_json::from_str(text).ok()?;
+    let string = |name: &str| record.get(name).and_then(Value::as_str);
+    let title = string("title")?;
+    let authors = record
+        .get("author")
+        .and_then(Value::as_array)
+        .map(|authors| authors.iter().filter_map(author).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let year = record.pointer("/issued/date-parts/0/0").and_then(|y| {
+        y.as_u64()
+            .map(|y| y.to_string())
+            .or(y.as_str().map(str::to_string))
+    });
+
+    let first = record
+        .pointer("/author/0/family")
+        .or(record.pointer("/author/0/literal"))
+        .and_then(Value::as_str)
+        .map(|name| {
+            name.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "DataCite".to_string());
+    let key = match &year {
+        Some(year) => format!("{}_{}", first, year),
+        None => first,
+    };
+
+    let mut fields = vec![("title", title.to_string())];
+    if !authors.is_empty() {
+        fields.push(("author", authors.join(" and ")));
+    }
+    if let Some(publisher) = string("publisher") {
+        fields.push(("publisher", publisher.to_string()));
+    }
+    if let Some(year) = year {
+        fields.push(("year", year));
+    }
+    if let Some(version) = string("version") {
+        fields.push(("version", version.to_string()));
+    }
+    if let Some(doi) = string("DOI") {
+        // DataCite gives the dois in uppercase
+        fields.push(("doi", doi.to_lowercase()));
+    }
+    if let Some(url) = string("URL") {
+        fields.push(("url", url.to_string()));
+    }
+    let fields = fields
+        .iter()
+        .map(|(name, value)| format!("{}={{{}}}", name, value))
+        .collect::<Vec<_>>();
+    Some(format!(
+        "@{}{{{}, {}}}",
+        entry_type(string("type").unwrap_or_default()),
+        key,
+        fields.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZENODO: &str =
+        include_str!("../tests/fixtures/mirror/doi_10.5281_zenodo.4275391-b2d21e0c441352de");
+
+    #[test]
+    fn test_csl_to_bibtex() {
+        assert_eq!(
+            csl_to_bibtex(ZENODO).unwrap(),
+            "@software{Doe_2020, title={bibtool-rs: a BibTeX toolkit}, \
+             author={Doe, Jane and {The Bibtool Developers}}, publisher={Zenodo}, \
+             year={2020}, version={v1.2.0}, doi={10.5281/zenodo.4275391}, \
+             url={https://zenodo.org/record/4275391}}"
+        );
+        let dataset = r#"{"type": "dataset", "title": "Measurements", "DOI": "10.5061/DRYAD.X"}"#;
+        assert_eq!(
+            csl_to_bibtex(dataset).unwrap(),
+            "@dataset{DataCite, title={Measurements}, doi={10.5061/dryad.x}}"
+        );
+        assert_eq!(csl_to_bibtex(r#"{"type": "article"}"#), None);
+    }
+
+    #[test]
+    fn test_entry_type() {
+        assert_eq!(entry_type("software"), "software");
+        assert_eq!(entry_type("dataset"), "dataset");
+        assert_eq!(entry_type("article"), "misc");
+    }
+}
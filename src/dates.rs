@@ -0,0 +1,362 @@
+//! This file is responsible for the date fields of BibLaTeX
+//! (`date`, `urldate`, ...), that must be written in ISO-8601:
+//! `2023`, `2023-04`, `2023-04-01`, ranges `2021/2022`,
+//! and open ranges `2020/..` (or `2020/`).
+//!
+//! It also rewrites some common non-ISO forms (`13/04/2023`,
+//! `April 1, 2023`), as long as they are not ambiguous: `03/04/2023`
+//! can be the 3rd of April or the 4th of March, and is left alone.
+//!
+//! The `year` field is not a date: besides a year, it can hold a
+//! range (`2023--2024`) or a publication status (`forthcoming`).
+use std::fmt::{self, Display, Formatter};
+
+/// The BibLaTeX fields containing dates.
+pub const DATE_FIELDS: [&str; 4] = ["date", "urldate", "eventdate", "origdate"];
+
+/// The publication statuses accepted in a `year` field.
+pub const YEAR_STATUS_WORDS: [&str; 5] =
+    ["forthcoming", "in press", "to appear", "submitted", "accepted"];
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn digits(s: &str, len: usize) -> Option<u32> {
+    if s.len() == len && s.chars().all(|c| c.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+impl Date {
+    fn is_valid(&self) -> bool {
+        match (self.month, self.day) {
+            (None, None) => true,
+            (Some(month), None) => (1..=12).contains(&month),
+            (Some(month), Some(day)) => {
+                (1..=12).contains(&month) && day >= 1 && day <= days_in_month(self.year, month)
+            }
+            (None, Some(_)) => false,
+        }
+    }
+
+    /// Parses `YYYY`, `YYYY-MM`, or `YYYY-MM-DD`.
+    pub fn parse(s: &str) -> Option<Date> {
+        let mut parts = s.split('-');
+        let year = digits(parts.next()?, 4)? as i32;
+        let month = parts.next().map(|m| digits(m, 2)).map_or(Ok(None), |m| m.map(Some).ok_or(()));
+        let day = parts.next().map(|d| digits(d, 2)).map_or(Ok(None), |d| d.map(Some).ok_or(()));
+        if parts.next().is_some() {
+            return None;
+        }
+        let date = Date {
+            year,
+            month: month.ok()?,
+            day: day.ok()?,
+        };
+        if date.is_valid() { Some(date) } else { None }
+    }
+
+    /// The day that is `days` days after 1970-01-01.
+    pub fn from_unix_days(days: i64) -> Date {
+        // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date {
+            year: year as i32,
+            month: Some(month as u32),
+            day: Some(day as u32),
+        }
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}", self.year)?;
+        if let Some(month) = self.month {
+            write!(f, "-{:02}", month)?;
+        }
+        if let Some(day) = self.day {
+            write!(f, "-{:02}", day)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateValue {
+    Date(Date),
+    /// A range, where `None` is an open end
+    Range(Option<Date>, Option<Date>),
+}
+
+impl DateValue {
+    pub fn parse(s: &str) -> Option<DateValue> {
+        let s = s.trim();
+        let Some((start, end)) = s.split_once('/') else {
+            return Date::parse(s).map(DateValue::Date);
+        };
+        let bound = |b: &str| match b {
+            "" | ".." => Some(None),
+            _ => Date::parse(b).map(Some),
+        };
+        match (bound(start)?, bound(end)?) {
+            (None, None) => None,
+            (Some(start), Some(end)) if start > end => None,
+            (start, end) => Some(DateValue::Range(start, end)),
+        }
+    }
+
+    /// The year the date (or range) starts with.
+    pub fn year(&self) -> Option<i32> {
+        match self {
+            DateValue::Date(date) | DateValue::Range(Some(date), _) => Some(date.year),
+            DateValue::Range(None, end) => end.map(|d| d.year),
+        }
+    }
+}
+
+/// The value of a `year` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YearValue {
+    Exact(i32),
+    /// `2023--2024`, `2023-2024`, or `2023/2024`
+    Range(i32, i32),
+    /// Letters only, e.g. `forthcoming` or `in press` (lowercase)
+    TextualStatus(String),
+    Invalid(String),
+}
+
+impl YearValue {
+    pub fn parse(s: &str) -> YearValue {
+        let s = s.trim();
+        let year = |y: &str| digits(y.trim(), 4).map(|y| y as i32);
+        if let Some(y) = year(s) {
+            return YearValue::Exact(y);
+        }
+        for separator in ["--", "\u{2013}", "-", "/"] {
+            if let Some((start, end)) = s.split_once(separator) {
+                return match (year(start), year(end)) {
+                    (Some(start), Some(end)) if start <= end => YearValue::Range(start, end),
+                    _ => YearValue::Invalid(s.to_string()),
+                };
+            }
+        }
+        if !s.is_empty() && s.chars().all(|c| c.is_alphabetic() || c == ' ') {
+            let words = s.split_whitespace().collect::<Vec<_>>();
+            return YearValue::TextualStatus(words.join(" ").to_lowercase());
+        }
+        YearValue::Invalid(s.to_string())
+    }
+
+    /// The year that entries are sorted by (most recent first): the
+    /// end of ranges, and after every year for statuses, since the
+    /// work is not yet published. Invalid years sort last.
+    pub fn sort_key(&self) -> i32 {
+        match self {
+            YearValue::Exact(year) | YearValue::Range(_, year) => *year,
+            YearValue::TextualStatus(_) => i32::MAX,
+            YearValue::Invalid(_) => 0,
+        }
+    }
+}
+
+/// The number of a month, from its name or abbreviation.
+pub fn month_number(word: &str) -> Option<u32> {
+    let word = word.trim_end_matches('.').to_lowercase();
+    if word.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|m| m.starts_with(&word))
+        .map(|i| i as u32 + 1)
+}
+
+/// The BibTeX macro of a month (`mar` for 3).
+pub fn month_macro(month: u32) -> Option<&'static str> {
+    let name = MONTHS.get(month.checked_sub(1)? as usize)?;
+    Some(&name[..3])
+}
+
+/// Rewrites a non-ISO date to ISO, when it is unambiguous.
+/// Returns `None` for ISO dates and for the dates it cannot rewrite.
+pub fn normalize_date(s: &str) -> Option<String> {
+    let s = s.trim();
+    if DateValue::parse(s).is_some() {
+        return None;
+    }
+
+    // numeric dates: 13/04/2023, 04.13.2023, 2023/04/13
+    let numbers = s.split(['/', '.', '-']).collect::<Vec<_>>();
+    if numbers.len() == 3 && numbers.iter().all(|n| n.chars().all(|c| c.is_ascii_digit())) {
+        let number = |n: &str| digits(n, 2).or_else(|| digits(n, 1));
+        let date = if let Some(year) = digits(numbers[0], 4) {
+            Date {
+                year: year as i32,
+                month: Some(number(numbers[1])?),
+                day: Some(number(numbers[2])?),
+            }
+        } else {
+            let year = digits(numbers[2], 4)? as i32;
+            let (a, b) = (number(numbers[0])?, number(numbers[1])?);
+            let (day, month) = match (a <= 12, b <= 12) {
+                (true, true) if a != b => return None,
+                (true, true) | (false, true) => (a, b),
+                (true, false) => (b, a),
+                (false, false) => return None,
+            };
+            Date {
+                year,
+                month: Some(month),
+                day: Some(day),
+            }
+        };
+        return date.is_valid().then(|| date.to_string());
+    }
+
+    // written dates: April 1, 2023 / 1 April 2023 / Apr. 2023
+    let words = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+    let (mut month, mut year, mut day) = (None, None, None);
+    for word in words {
+        if let Some(m) = month_number(word) {
+            month = month.xor(Some(m)).or(None);
+            month?;
+        } else if let Some(y) = digits(word, 4) {
+            year = year.xor(Some(y as i32));
+            year?;
+        } else {
+            let d = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            let d = digits(d, 2).or_else(|| digits(d, 1))?;
+            day = day.xor(Some(d));
+            day?;
+        }
+    }
+    let date = Date {
+        year: year?,
+        month: Some(month?),
+        day,
+    };
+    date.is_valid().then(|| date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix_days() {
+        assert_eq!(Date::from_unix_days(0).to_string(), "1970-01-01");
+        assert_eq!(Date::from_unix_days(11016).to_string(), "2000-02-29");
+        assert_eq!(Date::from_unix_days(-1).to_string(), "1969-12-31");
+    }
+
+    #[test]
+    fn test_parse() {
+        for valid in [
+            "2023",
+            "2023-04",
+            "2023-04-01",
+            "2024-02-29",
+            "2021/2022",
+            "2020/..",
+            "2020/",
+            "../2020-05",
+        ] {
+            assert!(DateValue::parse(valid).is_some(), "{}", valid);
+        }
+        for invalid in [
+            "23",
+            "2023-4-1",
+            "2023-13",
+            "2023-02-29",
+            "2022/2021",
+            "../..",
+            "01/04/2023",
+            "2023-04-01-02",
+        ] {
+            assert!(DateValue::parse(invalid).is_none(), "{}", invalid);
+        }
+        assert_eq!(DateValue::parse("2021/2022").unwrap().year(), Some(2021));
+        assert_eq!(DateValue::parse("../2020").unwrap().year(), Some(2020));
+    }
+
+    #[test]
+    fn test_year_value() {
+        assert_eq!(YearValue::parse(" 2023 "), YearValue::Exact(2023));
+        assert_eq!(YearValue::parse("2023--2024"), YearValue::Range(2023, 2024));
+        assert_eq!(YearValue::parse("2023\u{2013}2024"), YearValue::Range(2023, 2024));
+        assert_eq!(YearValue::parse("2023/2024"), YearValue::Range(2023, 2024));
+        assert_eq!(
+            YearValue::parse("In  Press"),
+            YearValue::TextualStatus("in press".into())
+        );
+        for invalid in ["2024--2023", "23", "2023a", "2023--", "n.d.", ""] {
+            assert_eq!(
+                YearValue::parse(invalid),
+                YearValue::Invalid(invalid.into()),
+                "{}",
+                invalid
+            );
+        }
+        let mut years = ["2020", "forthcoming", "20xx", "2019--2021"]
+            .map(|y| YearValue::parse(y).sort_key());
+        years.sort();
+        assert_eq!(years, [0, 2020, 2021, i32::MAX]);
+    }
+
+    #[test]
+    fn test_normalize_date() {
+        assert_eq!(normalize_date("13/04/2023"), Some("2023-04-13".into()));
+        assert_eq!(normalize_date("04/13/2023"), Some("2023-04-13".into()));
+        assert_eq!(normalize_date("1.1.2023"), Some("2023-01-01".into()));
+        assert_eq!(normalize_date("2023/04/01"), Some("2023-04-01".into()));
+        assert_eq!(normalize_date("April 1, 2023"), Some("2023-04-01".into()));
+        assert_eq!(normalize_date("1st Apr. 2023"), Some("2023-04-01".into()));
+        assert_eq!(normalize_date("September 2020"), Some("2020-09".into()));
+        // ambiguous, invalid, or already ISO
+        assert_eq!(normalize_date("03/04/2023"), None);
+        assert_eq!(normalize_date("31/02/2023"), None);
+        assert_eq!(normalize_date("April May 2023"), None);
+        assert_eq!(normalize_date("2023-04-01"), None);
+    }
+}
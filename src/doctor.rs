@@ -0,0 +1,355 @@
+//! This file is responsible for `bibadac doctor`, which checks the
+//! environment (working directory, network, cache, grammar) and the
+//! input files, and suggests a remedy for every problem found.
+//!
+//! Every check is a function returning its findings, so that they
+//! can be tested (and skipped) one by one. The exit code of the
+//! command is the worst status found (see `Status::exit_code`).
+use std::path::Path;
+use std::time::Duration;
+
+use crate::bibtex::BibFile;
+use crate::cache::Cache;
+use crate::encoding::BYTE_ORDER_MARK;
+
+/// The names of the checks, as given to `--skip`.
+pub const CHECKS: [&str; 5] = ["working-directory", "network", "cache", "inputs", "grammar"];
+
+/// The url requested by the network check.
+pub const NETWORK_URL: &str = "https://doi.org";
+
+/// A document that the grammar must parse without errors.
+const SELF_TEST: &str = "@string{acm = {ACM}}\n\
+    @article{key,\n  author = {Doe, Jane},\n  title = {T{\\'e}st},\n  journal = acm # { Journal},\n  year = 2024,\n}\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    /// 0 when everything is fine, 1 for warnings, 2 for failures.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Status::Ok => 0,
+            Status::Warn => 1,
+            Status::Fail => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// One of `CHECKS`
+    pub check: &'static str,
+    pub status: Status,
+    pub message: String,
+    /// How to fix the problem
+    pub remedy: Option<&'static str>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            check,
+            status: Status::Ok,
+            message: message.into(),
+            remedy: None,
+        }
+    }
+
+    fn problem(
+        check: &'static str,
+        status: Status,
+        message: impl Into<String>,
+        remedy: &'static str,
+    ) -> Self {
+        Finding {
+            check,
+            status,
+            message: message.into(),
+            remedy: Some(remedy),
+        }
+    }
+}
+
+/// The worst status of the findings (`Ok` when there are none).
+pub fn worst(findings: &[Finding]) -> Status {
+    findings
+        .iter()
+        .map(|f| f.status)
+        .max()
+        .unwrap_or(Status::Ok)
+}
+
+/// Whether files can be created in the directory where
+/// `setup` downloads the pdfs.
+pub fn check_working_directory(dir: &Path) -> Finding {
+    const CHECK: &str = "working-directory";
+    if !dir.is_dir() {
+        return Finding::problem(
+            CHECK,
+            Status::Fail,
+            format!("{} is not a directory", dir.display()),
+            "create it, or give another directory with --working-directory",
+        );
+    }
+    let probe = dir.join(format!(".bibadac-doctor-{}", std::process::id()));
+    match std::fs::write(&probe, b"").and_then(|_| std::fs::remove_file(&probe)) {
+        Ok(()) => Finding::ok(CHECK, format!("{} is writable", dir.display())),
+        Err(e) => Finding::problem(
+            CHECK,
+            Status::Fail,
+            format!("cannot write to {}: {}", dir.display(), e),
+            "check the permissions, or give another directory with --working-directory",
+        ),
+    }
+}
+
+/// Whether an HTTPS request succeeds, which `setup` needs.
+pub async fn check_network(url: &str) -> Finding {
+    const CHECK: &str = "network";
+    const REMEDY: &str =
+        "check the connection or the HTTPS_PROXY variable, or use `setup --mirror`";
+    let client = match crate::network::client_builder()
+        .user_agent(crate::setup::USER_AGENT)
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return Finding::problem(CHECK, Status::Warn, e.to_string(), REMEDY),
+    };
+    match client.head(url).send().await {
+        Ok(response) if response.status().is_success() || response.status().is_redirection() => {
+            Finding::ok(CHECK, format!("{} answered {}", url, response.status()))
+        }
+        Ok(response) => Finding::problem(
+            CHECK,
+            Status::Warn,
+            format!("{} answered {}", url, response.status()),
+            REMEDY,
+        ),
+        Err(e) => Finding::problem(
+            CHECK,
+            Status::Warn,
+            format!("could not reach {}: {}", url, e),
+            REMEDY,
+        ),
+    }
+}
+
+/// Whether the cache of downloaded metadata can be opened, without
+/// creating (or tagging) its directory as `Cache::open` does.
+pub fn check_cache(dir: Option<&Path>) -> Finding {
+    const CHECK: &str = "cache";
+    let Some(dir) = dir else {
+        return Finding::problem(
+            CHECK,
+            Status::Warn,
+            "no cache directory was found, the downloads are not cached",
+            "set the BIBADAC_CACHE_DIR variable to a writable directory",
+        );
+    };
+    if !dir.exists() {
+        return Finding::ok(
+            CHECK,
+            format!(
+                "{} does not exist yet, the first download creates it",
+                dir.display()
+            ),
+        );
+    }
+    // an empty directory becomes a cache on the first download
+    let is_empty = std::fs::read_dir(dir).is_ok_and(|mut files| files.next().is_none());
+    let cache = if is_empty {
+        Ok(())
+    } else {
+        Cache::existing(dir).map(|_| ())
+    };
+    match cache {
+        Ok(()) => match check_working_directory(dir).status {
+            Status::Ok => Finding::ok(CHECK, format!("{} is usable", dir.display())),
+            _ => Finding::problem(
+                CHECK,
+                Status::Warn,
+                format!("cannot write to {}", dir.display()),
+                "check its permissions, or set the BIBADAC_CACHE_DIR variable",
+            ),
+        },
+        Err(e) => Finding::problem(
+            CHECK,
+            Status::Warn,
+            format!("cannot open {}: {}", dir.display(), e),
+            "check its permissions, or set the BIBADAC_CACHE_DIR variable",
+        ),
+    }
+}
+
+/// The problems of an input file: larger than `max_size` bytes,
+/// starting with a byte order mark, or mixing line endings.
+pub fn check_input(name: &str, bytes: &[u8], max_size: u64) -> Vec<Finding> {
+    const CHECK: &str = "inputs";
+    let mut findings = vec![];
+    if bytes.len() as u64 > max_size {
+        findings.push(Finding::problem(
+            CHECK,
+            Status::Warn,
+            format!(
+                "{} is {:.1} MB, above {:.1} MB",
+                name,
+                bytes.len() as f64 / 1e6,
+                max_size as f64 / 1e6
+            ),
+            "split it with `bibadac split`, or raise --max-file-size",
+        ));
+    }
+    let mut bom = [0; 3];
+    BYTE_ORDER_MARK.encode_utf8(&mut bom);
+    if bytes.starts_with(&bom) {
+        findings.push(Finding::problem(
+            CHECK,
+            Status::Warn,
+            format!("{} starts with a byte order mark", name),
+            "`bibadac format --in-place` removes it",
+        ));
+    }
+    let newlines = bytes.iter().filter(|b| **b == b'\n').count();
+    let crlf = bytes.windows(2).filter(|w| w == b"\r\n").count();
+    if crlf > 0 && crlf < newlines {
+        findings.push(Finding::problem(
+            CHECK,
+            Status::Warn,
+            format!(
+                "{} mixes line endings ({} CRLF, {} LF)",
+                name,
+                crlf,
+                newlines - crlf
+            ),
+            "`bibadac format --in-place` writes the ending of most lines (or `--line-ending`)",
+        ));
+    }
+    findings
+}
+
+/// The problems of the input files, or a single `Ok` finding.
+pub fn check_inputs(inputs: &[(String, Vec<u8>)], max_size: u64) -> Vec<Finding> {
+    let findings = inputs
+        .iter()
+        .flat_map(|(name, bytes)| check_input(name, bytes, max_size))
+        .collect::<Vec<_>>();
+    if findings.is_empty() {
+        vec![Finding::ok(
+            "inputs",
+            format!("{} input files look fine", inputs.len()),
+        )]
+    } else {
+        findings
+    }
+}
+
+/// Whether the grammar parses a known document.
+pub fn check_grammar() -> Finding {
+    const CHECK: &str = "grammar";
+    let file = BibFile::new(SELF_TEST);
+    let errors = file
+        .iterate()
+        .filter(|n| n.kind() == "ERROR" || n.is_missing())
+        .count();
+    let entries = file.list_entries().collect::<Vec<_>>();
+    let parsed = errors == 0
+        && entries.len() == 1
+        && file.get_slice(entries[0].key) == "key"
+        && entries[0].fields.len() == 4;
+    if parsed {
+        Finding::ok(CHECK, "the self-test document parses")
+    } else {
+        Finding::problem(
+            CHECK,
+            Status::Fail,
+            format!(
+                "the self-test document parses with {} errors and {} entries",
+                errors,
+                entries.len()
+            ),
+            "the BibTeX grammar was not built correctly, reinstall bibadac",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_input() {
+        assert_eq!(check_input("a.bib", b"@misc{a,}\n@misc{b,}\n", 100), vec![]);
+        let statuses = |bytes: &[u8], max_size| {
+            check_input("a.bib", bytes, max_size)
+                .iter()
+                .map(|f| f.message.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            statuses("\u{FEFF}@misc{a,}\r\n@misc{b,}\n".as_bytes(), 10),
+            vec![
+                "a.bib is 0.0 MB, above 0.0 MB",
+                "a.bib starts with a byte order mark",
+                "a.bib mixes line endings (1 CRLF, 1 LF)",
+            ]
+        );
+        // a file with CRLF line endings only is fine
+        assert_eq!(
+            statuses(b"@misc{a,}\r\n@misc{b,}\r\n", 100),
+            Vec::<String>::new()
+        );
+        let inputs = vec![("a.bib".to_string(), b"@misc{a,}\n".to_vec())];
+        assert_eq!(
+            check_inputs(&inputs, 100),
+            vec![Finding::ok("inputs", "1 input files look fine")]
+        );
+    }
+
+    #[test]
+    fn test_check_working_directory() {
+        let dir = std::env::temp_dir();
+        assert_eq!(check_working_directory(&dir).status, Status::Ok);
+        let missing = dir.join(format!("bibadac-doctor-missing-{}", std::process::id()));
+        assert_eq!(check_working_directory(&missing).status, Status::Fail);
+        assert_eq!(check_cache(None).status, Status::Warn);
+    }
+
+    #[test]
+    fn test_check_cache() {
+        let dir = crate::temp_dir("doctor-cache");
+        // neither created nor tagged
+        let missing = dir.join("cache");
+        assert_eq!(check_cache(Some(&missing)).status, Status::Ok);
+        assert!(!missing.exists());
+        assert_eq!(check_cache(Some(&dir)).status, Status::Ok);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        std::fs::write(dir.join("notes.txt"), "not a cache").unwrap();
+        assert_eq!(check_cache(Some(&dir)).status, Status::Warn);
+        Cache::open(&missing).unwrap();
+        assert_eq!(check_cache(Some(&missing)).status, Status::Ok);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_worst() {
+        assert_eq!(worst(&[]), Status::Ok);
+        let findings = vec![
+            Finding::ok("grammar", "ok"),
+            Finding::problem("inputs", Status::Warn, "warn", "fix"),
+        ];
+        assert_eq!(worst(&findings), Status::Warn);
+        assert_eq!(worst(&findings).exit_code(), 1);
+    }
+
+    #[test]
+    fn test_check_grammar() {
+        assert_eq!(check_grammar().status, Status::Ok);
+    }
+}
@@ -0,0 +1,105 @@
+//!
+//! Manages DOI identifiers,
+//! and in particular their many
+//! written forms found in the wild.
+//!
+
+/// Extracts the DOI from a resolver url
+/// (`https://doi.org/10.x/y`, `http://dx.doi.org/10.x/y`, ...)
+pub fn extract_doi(url: &str) -> Option<&str> {
+    let start = url.to_ascii_lowercase().find("doi.org/")? + "doi.org/".len();
+    let rest = &url[start..];
+    let end = rest.find(['?', '#']).unwrap_or(rest.len());
+    let doi = rest[..end].trim_end_matches('/');
+    if doi.starts_with("10.") {
+        Some(doi)
+    } else {
+        None
+    }
+}
+
+/// Whether the url is nothing more than
+/// a resolver link for the given DOI.
+pub fn is_resolver_url(url: &str, doi: &str) -> bool {
+    let url = url.trim();
+    match extract_doi(url) {
+        Some(d) => d.eq_ignore_ascii_case(doi) && url.trim_end_matches('/').ends_with(d),
+        None => false,
+    }
+}
+
+/// Canonical form of a DOI: the bare, lowercase `10.x/y`,
+/// whichever way it was written (`https://doi.org/10.x/y`,
+/// `doi:10.x/y`, `DOI: 10.x/y`, ...).
+pub fn normalize_doi(value: &str) -> Option<String> {
+    let value = value.trim();
+    let doi = match extract_doi(value) {
+        Some(doi) => doi,
+        None if value.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("doi:")) => {
+            value[4..].trim_start()
+        }
+        None => value,
+    };
+    if doi.starts_with("10.") && !doi.contains(char::is_whitespace) {
+        Some(doi.to_lowercase())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_doi() {
+        assert_eq!(
+            extract_doi("https://doi.org/10.1145/123"),
+            Some("10.1145/123")
+        );
+        assert_eq!(
+            extract_doi("http://dx.doi.org/10.1145/123/"),
+            Some("10.1145/123")
+        );
+        assert_eq!(
+            extract_doi("https://DOI.org/10.1145/123?x=1"),
+            Some("10.1145/123")
+        );
+        assert_eq!(extract_doi("https://example.com/10.1145/123"), None);
+        assert_eq!(extract_doi("https://doi.org/"), None);
+    }
+
+    #[test]
+    fn test_is_resolver_url() {
+        assert!(is_resolver_url(
+            "https://doi.org/10.1145/123",
+            "10.1145/123"
+        ));
+        assert!(is_resolver_url(
+            "https://doi.org/10.1145/ABC",
+            "10.1145/abc"
+        ));
+        assert!(!is_resolver_url(
+            "https://doi.org/10.1145/123?download=1",
+            "10.1145/123"
+        ));
+        assert!(!is_resolver_url(
+            "https://doi.org/10.1145/124",
+            "10.1145/123"
+        ));
+    }
+
+    #[test]
+    fn test_normalize_doi() {
+        for written in [
+            "https://doi.org/10.1145/ABC",
+            "doi:10.1145/abc",
+            "DOI: 10.1145/ABC",
+            " 10.1145/abc ",
+        ] {
+            assert_eq!(normalize_doi(written), Some("10.1145/abc".to_string()));
+        }
+        assert_eq!(normalize_doi("arXiv:2101.00001"), None);
+        assert_eq!(normalize_doi("https://example.com/paper"), None);
+    }
+}
@@ -0,0 +1,524 @@
+//! This file is responsible for `bibadac check --online`, which follows
+//! the dois of the entries from `https://doi.org/` to their landing page,
+//! to catch a doi that still resolves, but to another work, e.g. after
+//! the migration of a publisher.
+//!
+//! The client does not follow the redirects: they are followed one at a
+//! time, so that the chain is known. A doi that is more than
+//! `FRAGILE_HOPS` redirects away from its landing page is fragile, and
+//! the host of the landing page is compared to the domain of the journal
+//! or of the publisher of the entry, when it is known:
+//!
+//! ```toml
+//! [check.doi_domains]
+//! "journal of logic and computation" = "oup.com"
+//! ```
+//!
+//! A name matches the journals and publishers that contain its words
+//! (case insensitive), the journal first. The names of the configuration
+//! file come before the `DEFAULT_DOMAINS`.
+//!
+//! The dois of all the checked files share the budget of `--max-requests`
+//! (see `online`): the dois beyond it are reported as not verified. The
+//! requests are identified as those of `setup` (`--user-agent` or
+//! `--polite-email`), and stop at the first Ctrl-C.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+
+use reqwest::Url;
+use tracing::debug;
+
+use crate::bibliography::{Bibliography, Diagnostic, LintConfig, Location};
+use crate::cache::Cache;
+use crate::doi_identifiers::normalize_doi;
+use crate::linter::LintMessage;
+use crate::online::{Budget, Identifier, IdentifierKind, BUDGET_EXHAUSTED};
+
+/// The resolver of the dois.
+pub const DOI_RESOLVER: &str = "https://doi.org/";
+
+/// The redirects beyond which a doi is fragile.
+pub const FRAGILE_HOPS: usize = 5;
+
+/// The redirects beyond which a doi is not followed any further.
+pub const MAX_HOPS: usize = 20;
+
+/// The timeout of every request.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The domains of the landing pages of the major publishers.
+pub const DEFAULT_DOMAINS: &[(&str, &str)] = &[
+    ("association for computing machinery", "acm.org"),
+    ("acm", "acm.org"),
+    ("ieee", "ieee.org"),
+    ("springer", "springer.com"),
+    ("elsevier", "sciencedirect.com"),
+    ("wiley", "wiley.com"),
+    ("oxford university press", "oup.com"),
+    ("cambridge university press", "cambridge.org"),
+    ("taylor & francis", "tandfonline.com"),
+    ("sage", "sagepub.com"),
+    ("schloss dagstuhl", "dagstuhl.de"),
+    ("lipics", "dagstuhl.de"),
+    ("siam", "siam.org"),
+    ("american mathematical society", "ams.org"),
+    ("de gruyter", "degruyter.com"),
+    ("world scientific", "worldscientific.com"),
+    ("mdpi", "mdpi.com"),
+    ("plos", "plos.org"),
+    ("arxiv", "arxiv.org"),
+];
+
+/// The `[check.doi_domains]` section: the domains of the landing
+/// pages, by name of journal or publisher.
+pub type DoiDomains = BTreeMap<String, String>;
+
+/// The lowercase words of a name.
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The domains of the landing pages, by name.
+#[derive(Debug, Clone)]
+pub struct DomainMap {
+    names: Vec<(Vec<String>, String)>,
+}
+
+impl Default for DomainMap {
+    fn default() -> Self {
+        DomainMap::new(&DoiDomains::new())
+    }
+}
+
+impl DomainMap {
+    /// The configured domains, then the default ones.
+    pub fn new(configured: &DoiDomains) -> Self {
+        let names = configured
+            .iter()
+            .map(|(name, domain)| (name.as_str(), domain.as_str()))
+            .chain(DEFAULT_DOMAINS.iter().copied())
+            .map(|(name, domain)| {
+                let domain = domain.trim().trim_start_matches('.').to_lowercase();
+                (words(name), domain)
+            })
+            .filter(|(name, domain)| !name.is_empty() && !domain.is_empty())
+            .collect();
+        DomainMap { names }
+    }
+
+    /// The domain of the landing pages of a journal, or else of a publisher.
+    pub fn expected(&self, journal: Option<&str>, publisher: Option<&str>) -> Option<&str> {
+        [journal, publisher]
+            .into_iter()
+            .flatten()
+            .find_map(|value| {
+                let value = words(value);
+                self.names
+                    .iter()
+                    .find(|(name, _)| value.windows(name.len()).any(|w| w == name.as_slice()))
+                    .map(|(_, domain)| domain.as_str())
+            })
+    }
+}
+
+/// Whether a host is the domain, or one of its subdomains.
+pub fn host_matches(host: &str, domain: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Where a doi resolves to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoiTarget {
+    /// The host of the landing page
+    pub host: String,
+    /// The redirects from the resolver to the landing page
+    pub hops: usize,
+}
+
+/// The messages about where the doi of an entry resolves to,
+/// given the domain expected for the entry.
+pub fn target_lints(target: &DoiTarget, expected: Option<&str>) -> Vec<LintMessage> {
+    let mut lints = vec![];
+    if target.hops > FRAGILE_HOPS {
+        lints.push(LintMessage::FragileDoiRedirect(target.hops));
+    }
+    if let Some(domain) = expected
+        && !host_matches(&target.host, domain)
+    {
+        lints.push(LintMessage::UnexpectedDoiTarget(target.host.clone()));
+    }
+    lints
+}
+
+/// The url of a doi at the resolver.
+pub fn doi_url(doi: &str) -> String {
+    let doi = doi
+        .replace('%', "%25")
+        .replace('#', "%23")
+        .replace('?', "%3F");
+    format!("{}{}", DOI_RESOLVER, doi)
+}
+
+fn is_redirect(status: u16) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+/// The status of a response, and its `Location` header.
+pub type Step = (u16, Option<String>);
+
+/// Follows the redirects from `start`, with `step` answering the
+/// request of a url without following its redirect.
+pub async fn follow_redirects<F, Fut>(start: &str, mut step: F) -> Result<DoiTarget, String>
+where
+    F: FnMut(Url) -> Fut,
+    Fut: Future<Output = Result<Step, String>>,
+{
+    let mut url = Url::parse(start).map_err(|e| format!("Invalid url {:?}: {}", start, e))?;
+    let mut hops = 0;
+    loop {
+        let (status, location) = step(url.clone()).await?;
+        if !is_redirect(status) {
+            // the resolver does not know the doi
+            if hops == 0 && status >= 400 {
+                return Err(format!("{} answered {}", url, status));
+            }
+            let host = url.host_str().unwrap_or_default().to_string();
+            return Ok(DoiTarget { host, hops });
+        }
+        let location = location.ok_or_else(|| format!("{} redirects without a location", url))?;
+        url = url
+            .join(&location)
+            .map_err(|e| format!("Invalid redirect to {:?}: {}", location, e))?;
+        hops += 1;
+        if hops > MAX_HOPS {
+            return Err(format!("more than {} redirects", MAX_HOPS));
+        }
+    }
+}
+
+/// Resolves the dois, remembering them for the duration of a run.
+#[derive(Debug)]
+pub struct DoiResolver {
+    client: reqwest::Client,
+    known: HashMap<String, Result<DoiTarget, String>>,
+    /// The dois beyond the budget (see `online`)
+    exhausted: HashSet<String>,
+    /// The dois not verified at all (`--online-only`)
+    out_of_scope: HashSet<String>,
+}
+
+impl DoiResolver {
+    /// In offline mode, every doi fails to resolve (see `network`).
+    pub fn new(user_agent: &str) -> Result<Self, String> {
+        let client = crate::network::client_builder()
+            .user_agent(user_agent)
+            .timeout(TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| format!("Could not build the http client: {}", e))?;
+        Ok(DoiResolver {
+            client,
+            known: HashMap::new(),
+            exhausted: HashSet::new(),
+            out_of_scope: HashSet::new(),
+        })
+    }
+
+    /// Plans the verification of `dois` within the budget: the others
+    /// fail to resolve with `BUDGET_EXHAUSTED`, or are not in scope.
+    pub fn limit(&mut self, dois: &[String], budget: &Budget, cache: Option<&Cache>) {
+        let identifiers = dois
+            .iter()
+            .map(|doi| Identifier {
+                kind: IdentifierKind::Doi,
+                value: doi.clone(),
+            })
+            .collect::<Vec<_>>();
+        let plan = budget.plan(&identifiers, cache);
+        self.exhausted = plan.exhausted.iter().map(|&i| dois[i].clone()).collect();
+        self.out_of_scope = plan.out_of_scope.iter().map(|&i| dois[i].clone()).collect();
+    }
+
+    /// Whether the doi is verified at all (see `limit`).
+    pub fn in_scope(&self, doi: &str) -> bool {
+        !self.out_of_scope.contains(doi)
+    }
+
+    pub async fn resolve(&mut self, doi: &str) -> Result<DoiTarget, String> {
+        if let Some(known) = self.known.get(doi) {
+            return known.clone();
+        }
+        if self.exhausted.contains(doi) {
+            return Err(BUDGET_EXHAUSTED.to_string());
+        }
+        let client = &self.client;
+        let target = follow_redirects(&doi_url(doi), |url| async move {
+            let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            Ok((response.status().as_u16(), location))
+        })
+        .await;
+        debug!(%doi, ?target, "doi resolved");
+        self.known.insert(doi.to_string(), target.clone());
+        target
+    }
+}
+
+/// A doi of an entry, and what tells where it should resolve to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoiField {
+    /// The bare, lowercase doi
+    pub doi: String,
+    pub entry_type: String,
+    pub journal: Option<String>,
+    pub publisher: Option<String>,
+    /// The location of the `doi` field
+    pub loc: Location,
+}
+
+/// The dois of the entries of a bibliography.
+pub fn doi_fields(bib: &Bibliography) -> Vec<DoiField> {
+    bib.with_bibfile(|file| {
+        let mut fields = vec![];
+        for entry in file.list_entries() {
+            let mut doi = None;
+            let mut journal = None;
+            let mut publisher = None;
+            for field in entry.fields.iter() {
+                let value = file.get_braceless_slice(field.value);
+                match file.get_slice(field.name).to_lowercase().as_str() {
+                    "doi" => doi = normalize_doi(value).map(|d| (d, Location::from(field.loc))),
+                    "journal" | "journaltitle" => journal = Some(value.to_string()),
+                    "publisher" => publisher = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            if let Some((doi, loc)) = doi {
+                fields.push(DoiField {
+                    doi,
+                    entry_type: file.get_slice(entry.entrytype).to_lowercase(),
+                    journal,
+                    publisher,
+                    loc,
+                });
+            }
+        }
+        fields
+    })
+}
+
+/// The diagnostics about where the dois of a bibliography resolve to,
+/// and the dois that could not be resolved, with the reason.
+pub async fn online_diagnostics(
+    bib: &Bibliography,
+    resolver: &mut DoiResolver,
+    domains: &DomainMap,
+    config: &LintConfig,
+) -> (Vec<Diagnostic>, Vec<(String, String)>) {
+    let mut diagnostics = vec![];
+    let mut failures = vec![];
+    for field in doi_fields(bib) {
+        // a doi may take several redirects (and seconds) to resolve
+        if crate::interrupt::is_interrupted() {
+            break;
+        }
+        if !resolver.in_scope(&field.doi) {
+            continue;
+        }
+        let target = match resolver.resolve(&field.doi).await {
+            Ok(target) => target,
+            Err(e) => {
+                failures.push((field.doi, e));
+                continue;
+            }
+        };
+        let expected = domains.expected(field.journal.as_deref(), field.publisher.as_deref());
+        for msg in target_lints(&target, expected) {
+            diagnostics.push(Diagnostic {
+                severity: config.severity(&msg, Some(&field.entry_type)),
+                msg,
+                loc: vec![field.loc],
+                fixable: false,
+            });
+        }
+    }
+    (diagnostics, failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_domain() {
+        let domains = DomainMap::default();
+        assert_eq!(domains.expected(None, Some("ACM")), Some("acm.org"));
+        assert_eq!(
+            domains.expected(None, Some("Taylor {\\&} Francis")),
+            Some("tandfonline.com")
+        );
+        assert_eq!(
+            domains.expected(Some("LIPIcs"), Some("Springer")),
+            Some("dagstuhl.de")
+        );
+        // the names match whole words
+        assert_eq!(domains.expected(Some("Pharmacology Today"), None), None);
+        assert_eq!(domains.expected(None, None), None);
+
+        let configured = DoiDomains::from([(
+            "Journal of Logic and Computation".to_string(),
+            ".OUP.com".to_string(),
+        )]);
+        let domains = DomainMap::new(&configured);
+        assert_eq!(
+            domains.expected(Some("Journal of Logic and Computation"), Some("Springer")),
+            Some("oup.com")
+        );
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("dl.acm.org", "acm.org"));
+        assert!(host_matches("ACM.org.", "acm.org"));
+        assert!(!host_matches("notacm.org", "acm.org"));
+        assert!(!host_matches("acm.org.example.com", "acm.org"));
+    }
+
+    #[test]
+    fn test_target_lints() {
+        let target = DoiTarget {
+            host: "link.springer.com".to_string(),
+            hops: 2,
+        };
+        assert!(target_lints(&target, Some("springer.com")).is_empty());
+        assert!(target_lints(&target, None).is_empty());
+        let lints = target_lints(&target, Some("ieee.org"));
+        assert!(
+            matches!(&lints[..], [LintMessage::UnexpectedDoiTarget(host)] if host == "link.springer.com")
+        );
+
+        let fragile = DoiTarget {
+            hops: FRAGILE_HOPS + 1,
+            ..target
+        };
+        let lints = target_lints(&fragile, Some("springer.com"));
+        assert!(matches!(&lints[..], [LintMessage::FragileDoiRedirect(6)]));
+    }
+
+    #[test]
+    fn test_doi_url() {
+        assert_eq!(
+            doi_url("10.1000/a#b?c"),
+            "https://doi.org/10.1000/a%23b%3Fc"
+        );
+    }
+
+    fn follow(answers: &[(&str, u16, Option<&str>)]) -> Result<DoiTarget, String> {
+        let answers: HashMap<String, Step> = answers
+            .iter()
+            .map(|(url, status, location)| {
+                (url.to_string(), (*status, location.map(str::to_string)))
+            })
+            .collect();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(follow_redirects(&doi_url("10.1000/a"), |url| {
+            let answer = answers
+                .get(url.as_str())
+                .cloned()
+                .ok_or_else(|| format!("no answer for {}", url));
+            std::future::ready(answer)
+        }))
+    }
+
+    #[test]
+    fn test_follow_redirects() {
+        let target = follow(&[
+            (
+                "https://doi.org/10.1000/a",
+                302,
+                Some("https://linkinghub.example.com/a"),
+            ),
+            (
+                "https://linkinghub.example.com/a",
+                301,
+                Some("https://www.example.org/article/a"),
+            ),
+            (
+                "https://www.example.org/article/a",
+                302,
+                Some("/login?next=a"),
+            ),
+            ("https://www.example.org/login?next=a", 403, None),
+        ])
+        .unwrap();
+        assert_eq!(target.host, "www.example.org");
+        assert_eq!(target.hops, 3);
+
+        // an unknown doi
+        assert!(follow(&[("https://doi.org/10.1000/a", 404, None)]).is_err());
+        // a redirect without a location
+        assert!(follow(&[("https://doi.org/10.1000/a", 302, None)]).is_err());
+        // a loop
+        let looping = follow(&[("https://doi.org/10.1000/a", 302, Some("/10.1000/a"))]);
+        assert_eq!(looping, Err(format!("more than {} redirects", MAX_HOPS)));
+    }
+
+    #[test]
+    fn test_doi_fields() {
+        let bib = Bibliography::from(
+            "@article{a,\n  doi = {https://doi.org/10.1000/A},\n  journal = {LIPIcs},\n}\n\
+             @misc{b,\n  title = {No doi},\n}\n"
+                .to_string(),
+        );
+        let fields = doi_fields(&bib);
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].doi, "10.1000/a");
+        assert_eq!(fields[0].entry_type, "article");
+        assert_eq!(fields[0].journal.as_deref(), Some("LIPIcs"));
+        assert_eq!(fields[0].loc.line, 2);
+    }
+
+    #[test]
+    fn test_limit() {
+        let dois = ["10.1000/a", "10.1000/b", "10.1000/a"].map(str::to_string);
+        let mut resolver = DoiResolver::new(crate::setup::USER_AGENT).unwrap();
+        let budget = Budget {
+            kinds: vec![],
+            max_requests: Some(1),
+        };
+        resolver.limit(&dois, &budget, None);
+        let found = DoiTarget {
+            host: "www.example.org".to_string(),
+            hops: 1,
+        };
+        resolver.known.insert("10.1000/a".to_string(), Ok(found.clone()));
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        // the budget is spent on the first doi, the second one is not sent
+        assert_eq!(rt.block_on(resolver.resolve("10.1000/a")), Ok(found));
+        assert_eq!(
+            rt.block_on(resolver.resolve("10.1000/b")),
+            Err(BUDGET_EXHAUSTED.to_string())
+        );
+        assert!(resolver.in_scope("10.1000/b"));
+
+        let budget = Budget {
+            kinds: vec![IdentifierKind::Arxiv],
+            max_requests: None,
+        };
+        resolver.limit(&dois, &budget, None);
+        assert!(!resolver.in_scope("10.1000/a"));
+    }
+}
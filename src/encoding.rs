@@ -0,0 +1,71 @@
+//! This file is responsible for reading bibliographies that
+//! are not plain UTF-8: files starting with a byte order mark,
+//! and files encoded in Latin-1 or Windows-1252.
+//!
+//! The byte order mark is kept in the text, so that the locations
+//! of the diagnostics are the ones of the file: the linter reports
+//! it, and the formatter drops it. Other encodings are transcoded
+//! to UTF-8 when reading, and their name is kept for the linter.
+
+/// The byte order mark, as decoded from UTF-8.
+pub const BYTE_ORDER_MARK: char = '\u{FEFF}';
+
+/// Decodes the content of a file, along with the name of its
+/// encoding when it is not UTF-8.
+///
+/// Content that is not UTF-8 is decoded as Windows-1252, which
+/// agrees with Latin-1 (ISO-8859-1) on its printable characters.
+/// It is named Latin-1 unless it uses the bytes 0x80 to 0x9F, which
+/// are control characters in Latin-1 but quotes, dashes, or the euro
+/// sign in Windows-1252.
+pub fn decode(bytes: Vec<u8>) -> (String, Option<&'static str>) {
+    match String::from_utf8(bytes) {
+        Ok(text) => (text, None),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            let (text, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(&bytes);
+            let encoding = if bytes.iter().any(|b| (0x80..0xA0).contains(b)) {
+                "windows-1252"
+            } else {
+                "ISO-8859-1"
+            };
+            (text.into_owned(), Some(encoding))
+        }
+    }
+}
+
+/// The text without its byte order mark.
+pub fn strip_byte_order_mark(text: &str) -> &str {
+    text.strip_prefix(BYTE_ORDER_MARK).unwrap_or(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_fixtures() {
+        let (utf8, encoding) = decode(crate::read_fixture_bytes("encoding", "utf8.bib"));
+        assert_eq!(encoding, None);
+        assert!(utf8.contains("Gödel") && utf8.contains("Über"));
+
+        let (bom, encoding) = decode(crate::read_fixture_bytes("encoding", "utf8-bom.bib"));
+        assert_eq!(encoding, None);
+        assert!(bom.starts_with(BYTE_ORDER_MARK));
+        assert_eq!(strip_byte_order_mark(&bom), utf8);
+
+        let (latin1, encoding) = decode(crate::read_fixture_bytes("encoding", "latin1.bib"));
+        assert_eq!(encoding, Some("ISO-8859-1"));
+        assert_eq!(latin1, utf8);
+
+        let (windows, encoding) = decode(crate::read_fixture_bytes("encoding", "windows1252.bib"));
+        assert_eq!(encoding, Some("windows-1252"));
+        assert!(windows.contains("{„Über formal unentscheidbare Sätze“}"));
+    }
+
+    #[test]
+    fn test_strip_byte_order_mark() {
+        assert_eq!(strip_byte_order_mark("\u{FEFF}@misc{a,}"), "@misc{a,}");
+        assert_eq!(strip_byte_order_mark("@misc{a,}"), "@misc{a,}");
+    }
+}
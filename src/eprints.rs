@@ -0,0 +1,84 @@
+//! This file is responsible for the archive an `eprint` field
+//! refers to. Biblatex names it in the `eprinttype` field, and
+//! BibTeX styles in the `archiveprefix` field (case-insensitive),
+//! e.g. `eprinttype = {hal}` or `archivePrefix = {bioRxiv}`.
+//! When both are absent, the eprint is an arXiv identifier, which
+//! is the only kind of eprint that goes through `ArxivId`.
+use crate::bibtex::{BibEntry, BibFile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EprintType {
+    Arxiv,
+    /// HAL identifiers (`hal-01234567`)
+    Hal,
+    /// bioRxiv identifiers, with or without their `10.1101/` prefix
+    BioRxiv,
+    /// Any other archive (JSTOR, PubMed, ...), left alone
+    Other,
+}
+
+impl EprintType {
+    /// The archive of an eprint, given the values of the
+    /// `eprinttype` and `archiveprefix` fields of its entry.
+    pub fn of(eprinttype: Option<&str>, archiveprefix: Option<&str>) -> Self {
+        let Some(name) = eprinttype.or(archiveprefix) else {
+            return EprintType::Arxiv;
+        };
+        match name.trim().to_lowercase().as_str() {
+            "arxiv" => EprintType::Arxiv,
+            "hal" => EprintType::Hal,
+            "biorxiv" => EprintType::BioRxiv,
+            _ => EprintType::Other,
+        }
+    }
+
+    /// The archive of the `eprint` field of an entry.
+    pub fn of_entry(file: &BibFile, entry: &BibEntry) -> Self {
+        let field = |name: &str| {
+            entry
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(name))
+                .map(|f| file.get_braceless_slice(f.value))
+        };
+        EprintType::of(field("eprinttype"), field("archiveprefix"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eprint_type() {
+        assert_eq!(EprintType::of(None, None), EprintType::Arxiv);
+        assert_eq!(EprintType::of(Some("arXiv"), None), EprintType::Arxiv);
+        assert_eq!(EprintType::of(None, Some("HAL")), EprintType::Hal);
+        assert_eq!(EprintType::of(None, Some("bioRxiv")), EprintType::BioRxiv);
+        assert_eq!(
+            EprintType::of(Some("jstor"), Some("arXiv")),
+            EprintType::Other
+        );
+    }
+
+    #[test]
+    fn test_fixture_entries() {
+        let content = crate::read_fixture(".", "eprints.bib");
+        let file = BibFile::new(&content);
+        let types = file
+            .list_entries()
+            .map(|e| (file.get_slice(e.key), EprintType::of_entry(&file, &e)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                ("arxiv-default", EprintType::Arxiv),
+                ("arxiv-prefix", EprintType::Arxiv),
+                ("arxiv-eprinttype", EprintType::Arxiv),
+                ("hal", EprintType::Hal),
+                ("biorxiv", EprintType::BioRxiv),
+                ("jstor", EprintType::Other),
+            ]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! This file is responsible for `bibadac extract`, which pulls the
+//! entries cited by a LaTeX document out of a large bibliography,
+//! so that a paper comes with a bibliography of its own.
+//!
+//! Along with the cited entries come the entries they inherit fields
+//! from (their `crossref` parents, transitively), written after their
+//! children as BibTeX requires, and the `@string` definitions that any
+//! of them refers to, even through other strings.
+use std::collections::{HashMap, HashSet};
+
+use crate::bibtex::{BibEntry, BibFile};
+
+/// The key that cites every entry (`\nocite{*}`).
+pub const CITE_ALL: &str = "*";
+
+/// The document without its comments (from an unescaped `%`
+/// to the end of the line), keeping the line breaks.
+fn strip_comments(tex: &str) -> String {
+    tex.split_inclusive('\n')
+        .map(|line| {
+            let mut escaped = false;
+            let comment = line.char_indices().find_map(|(i, c)| match c {
+                '\\' => {
+                    escaped = !escaped;
+                    None
+                }
+                '%' if !escaped => Some(i),
+                _ => {
+                    escaped = false;
+                    None
+                }
+            });
+            match comment {
+                Some(i) if line.ends_with('\n') => format!("{}\n", &line[..i]),
+                Some(i) => line[..i].to_string(),
+                None => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// The keys cited in a LaTeX document (`\cite{a,b}`, `\citep[p.~3]{a}`,
+/// `\autocite*{a}`, `\nocite{*}`, ...), in order and without duplicates.
+pub fn cited_keys(tex: &str) -> Vec<String> {
+    let tex = strip_comments(tex);
+    let mut keys: Vec<String> = vec![];
+    let mut rest = tex.as_str();
+    while let Some(pos) = rest.find('\\') {
+        rest = &rest[pos + 1..];
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let (name, after) = rest.split_at(name_len);
+        rest = after;
+        if !name.to_ascii_lowercase().contains("cite") {
+            continue;
+        }
+        // the star, and the optional arguments
+        let mut args = rest.strip_prefix('*').unwrap_or(rest);
+        while let Some(arg) = args.trim_start().strip_prefix('[') {
+            match arg.find(']') {
+                Some(close) => args = &arg[close + 1..],
+                None => break,
+            }
+        }
+        let Some(list) = args.trim_start().strip_prefix('{') else {
+            continue;
+        };
+        let Some(close) = list.find('}') else {
+            continue;
+        };
+        for key in list[..close].split(',').map(str::trim) {
+            if !key.is_empty() && !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+        rest = &list[close + 1..];
+    }
+    keys
+}
+
+/// The part of a bibliography that a document cites.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extraction {
+    /// The `@string` definitions, then the entries,
+    /// as they are written in the bibliography
+    pub content: String,
+    /// The cited keys, and the keys of the
+    /// `crossref` parents, that are not in the bibliography
+    pub missing: Vec<String>,
+}
+
+/// The key of the `crossref` parent of an entry.
+fn crossref<'a>(bib: &BibFile<'a>, entry: &BibEntry<'a>) -> Option<&'a str> {
+    entry
+        .fields
+        .iter()
+        .find(|f| bib.get_slice(f.name).eq_ignore_ascii_case("crossref"))
+        .map(|f| bib.get_braceless_slice(f.value).trim())
+        .filter(|key| !key.is_empty())
+}
+
+/// The entries of the `keys` (case insensitive, as BibTeX does) with
+/// their `crossref` parents, and the `@string` definitions they need.
+pub fn extract<'a>(bib: &'a BibFile<'a>, keys: &[String]) -> Extraction {
+    let entries = bib.list_entries().collect::<Vec<_>>();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        index
+            .entry(bib.get_slice(entry.key).to_lowercase())
+            .or_insert(i);
+    }
+
+    // the selected entries, with the length of the longest chain
+    // of crossrefs leading to them from a cited entry
+    let mut ranks: HashMap<usize, usize> = HashMap::new();
+    let mut missing: Vec<String> = vec![];
+    for key in keys {
+        if key == CITE_ALL {
+            ranks.extend((0..entries.len()).map(|i| (i, 0)));
+        } else if let Some(&i) = index.get(&key.to_lowercase()) {
+            ranks.insert(i, 0);
+        } else if !missing.contains(key) {
+            missing.push(key.clone());
+        }
+    }
+    let cited = ranks.keys().copied().collect::<Vec<_>>();
+    for i in cited {
+        let mut child = i;
+        // a cycle of crossrefs stops after every entry was seen
+        for depth in 1..=entries.len() {
+            let Some(key) = crossref(bib, &entries[child]) else {
+                break;
+            };
+            let Some(&parent) = index.get(&key.to_lowercase()) else {
+                if !missing.iter().any(|k| k == key) {
+                    missing.push(key.to_string());
+                }
+                break;
+            };
+            let rank = ranks.entry(parent).or_insert(0);
+            *rank = (*rank).max(depth);
+            child = parent;
+        }
+    }
+
+    let mut used = ranks
+        .keys()
+        .flat_map(|&i| {
+            entries[i]
+                .fields
+                .iter()
+                .flat_map(|f| bib.macro_references(f.value))
+                .collect::<Vec<_>>()
+        })
+        .map(|name| name.to_lowercase())
+        .collect::<HashSet<_>>();
+    bib.referenced_strings(&mut used);
+    let strings = bib
+        .list_string_definitions()
+        .filter(|s| used.contains(&bib.get_slice(s.name).to_lowercase()))
+        .map(|s| bib.get_slice(s.loc));
+
+    // the parents after their children, otherwise in the order of the file
+    let mut selected = ranks.into_iter().collect::<Vec<_>>();
+    selected.sort_by_key(|&(i, rank)| (rank, i));
+    let parts = strings
+        .chain(selected.iter().map(|&(i, _)| bib.get_slice(entries[i].loc)))
+        .collect::<Vec<_>>();
+    let content = if parts.is_empty() {
+        String::new()
+    } else {
+        parts.join("\n\n") + "\n"
+    };
+    Extraction { content, missing }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    /// The keys of the entries of the extracted content, in order.
+    fn extracted_keys(extraction: &Extraction) -> Vec<String> {
+        let bib = BibFile::new(&extraction.content);
+        bib.list_entries()
+            .map(|e| bib.get_slice(e.key).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_cited_keys() {
+        let tex = "See \\cite{a, b} and \\citep[p.~3][]{c}.\n\
+                   % \\cite{commented}\n\
+                   50\\% of \\autocite*{b,\n d} \\emph{e} \\nocite{*}\n";
+        assert_eq!(cited_keys(tex), keys(&["a", "b", "c", "d", "*"]));
+        assert_eq!(cited_keys("\\cite{unclosed"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_extract_crossref_closure() {
+        let content = "@proceedings{series, title = {Series}}\n\
+                       @proceedings{icalp, title = {ICALP}, crossref = {Series}}\n\
+                       @inproceedings{a, title = {A}, crossref = {icalp}}\n\
+                       @inproceedings{b, title = {B}, crossref = {lics}}\n\
+                       @article{c, title = {C}}\n\
+                       @inproceedings{loop, title = {L}, crossref = {loop}}\n";
+        let bib = BibFile::new(content);
+
+        // the parents come after their children, even when cited
+        let extraction = extract(&bib, &keys(&["series", "A", "missing"]));
+        assert_eq!(extracted_keys(&extraction), keys(&["a", "icalp", "series"]));
+        assert_eq!(extraction.missing, keys(&["missing"]));
+
+        // the missing parents are reported, and cycles stop
+        let extraction = extract(&bib, &keys(&["b", "c", "loop"]));
+        assert_eq!(extracted_keys(&extraction), keys(&["b", "c", "loop"]));
+        assert_eq!(extraction.missing, keys(&["lics"]));
+
+        let extraction = extract(&bib, &keys(&[CITE_ALL]));
+        assert_eq!(extracted_keys(&extraction).len(), 6);
+        assert_eq!(extract(&bib, &[]).content, "");
+    }
+
+    #[test]
+    fn test_extract_strings() {
+        let content = "@string{acm = {ACM}}\n\
+                       @string{tocl = acm # { Transactions on Logic}}\n\
+                       @string{lncs = {LNCS}}\n\
+                       @string{unused = {Unused}}\n\
+                       @article{a, journal = tocl}\n\
+                       @proceedings{p, series = lncs}\n\
+                       @inproceedings{b, title = {B}, crossref = {p}}\n\
+                       @article{c, journal = unused}\n";
+        let bib = BibFile::new(content);
+        let extraction = extract(&bib, &keys(&["a", "b"]));
+        assert_eq!(
+            extraction.content,
+            "@string{acm = {ACM}}\n\n\
+             @string{tocl = acm # { Transactions on Logic}}\n\n\
+             @string{lncs = {LNCS}}\n\n\
+             @article{a, journal = tocl}\n\n\
+             @inproceedings{b, title = {B}, crossref = {p}}\n\n\
+             @proceedings{p, series = lncs}\n"
+        );
+    }
+}
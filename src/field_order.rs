@@ -0,0 +1,160 @@
+//! This file is responsible for the custom order of the fields of
+//! `bibadac format --field-order author,title,*,year+month`:
+//!
+//! - the listed fields are written in the given order;
+//! - the other fields are written in place of the `*` (at the end when
+//!   there is none), in their order, or sorted with `--sort-fields`;
+//! - the fields of a group such as `year+month` are always adjacent,
+//!   in the order of the group, whichever of them an entry has.
+//!
+//! The fields dropped by `--keep-field` or `--remove-field` are simply
+//! absent, and the fields of `--pin-last-field` still come last.
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The order of `--field-order`, written as in the command line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FieldOrder {
+    /// The groups of fields (lowercase), most of them of one field
+    groups: Vec<Vec<String>>,
+    /// The position of the other fields among the groups
+    rest: usize,
+}
+
+impl FieldOrder {
+    /// Where a field (lowercase) goes: the position of its group, then
+    /// its position in the group. The other fields all have the position
+    /// of the `*`, so that a stable sort keeps them in their order.
+    pub fn rank(&self, name: &str) -> (usize, usize) {
+        self.groups
+            .iter()
+            .enumerate()
+            .find_map(|(i, group)| {
+                let j = group.iter().position(|field| field == name)?;
+                let i = if i < self.rest { i } else { i + 1 };
+                Some((i, j))
+            })
+            .unwrap_or((self.rest, 0))
+    }
+}
+
+impl FromStr for FieldOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut groups: Vec<Vec<String>> = vec![];
+        let mut rest = None;
+        for item in s.split(',').map(str::trim) {
+            if item == "*" {
+                if rest.replace(groups.len()).is_some() {
+                    return Err(format!("`*` appears twice in the field order {:?}", s));
+                }
+                continue;
+            }
+            let mut group = vec![];
+            for name in item.split('+').map(|name| name.trim().to_lowercase()) {
+                if name.is_empty() || name == "*" {
+                    return Err(format!(
+                        "Invalid field {:?} in the field order {:?}",
+                        item, s
+                    ));
+                }
+                if groups.iter().chain([&group]).any(|g| g.contains(&name)) {
+                    return Err(format!(
+                        "The field {} appears twice in the field order {:?}",
+                        name, s
+                    ));
+                }
+                group.push(name);
+            }
+            groups.push(group);
+        }
+        Ok(FieldOrder {
+            rest: rest.unwrap_or(groups.len()),
+            groups,
+        })
+    }
+}
+
+impl Display for FieldOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut items = self
+            .groups
+            .iter()
+            .map(|group| group.join("+"))
+            .collect::<Vec<_>>();
+        if self.rest < items.len() {
+            items.insert(self.rest, "*".to_string());
+        }
+        write!(f, "{}", items.join(","))
+    }
+}
+
+impl TryFrom<String> for FieldOrder {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<FieldOrder> for String {
+    fn from(order: FieldOrder) -> String {
+        order.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names sorted by rank, as the formatter does.
+    fn sorted<'a>(order: &str, names: &[&'a str]) -> Vec<&'a str> {
+        let order = order.parse::<FieldOrder>().unwrap();
+        let mut names = names.to_vec();
+        names.sort_by_key(|name| order.rank(name));
+        names
+    }
+
+    #[test]
+    fn test_rank() {
+        let names = ["journal", "month", "pages", "title", "year"];
+        assert_eq!(
+            sorted("title,year+month", &names),
+            vec!["title", "year", "month", "journal", "pages"]
+        );
+        assert_eq!(
+            sorted("title,*,year+month", &names),
+            vec!["title", "journal", "pages", "year", "month"]
+        );
+        // whichever fields of the group are present
+        assert_eq!(
+            sorted("*,Year+Month", &["month", "journal", "pages"]),
+            vec!["journal", "pages", "month"]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let order = "author, title,*,year+month".parse::<FieldOrder>().unwrap();
+        assert_eq!(order.to_string(), "author,title,*,year+month");
+        assert_eq!(
+            "title,year+month"
+                .parse::<FieldOrder>()
+                .unwrap()
+                .to_string(),
+            "title,year+month"
+        );
+        for invalid in [
+            "title,,year",
+            "year+",
+            "*,title,*",
+            "year,year+month",
+            "a+*",
+        ] {
+            assert!(invalid.parse::<FieldOrder>().is_err(), "{:?}", invalid);
+        }
+    }
+}
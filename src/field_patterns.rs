@@ -0,0 +1,193 @@
+//! This file is responsible for the field names given to
+//! `--keep-field`, `--remove-field`, and `--entry-field`,
+//! such as `bdsk-*`, `author|editor`, or `url,doi`:
+//!
+//! - a list is separated by `,` or `|`, and the names are trimmed;
+//! - `*` stands for any characters, and `?` for exactly one;
+//! - the names are matched up to case.
+//!
+//! The patterns are compiled once when the options are parsed: the names
+//! without wildcards are looked up in a set, and only the others are
+//! matched one by one, which matters for large files and long lists.
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The characters that cannot appear in a pattern, since they cannot
+/// appear in a field name (`[` and `]` are not character classes).
+const FORBIDDEN: &[char] = &['{', '}', '(', ')', '[', ']', '=', '"', '#', '%', '\\'];
+
+/// The compiled patterns, written as in the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FieldPatterns {
+    source: String,
+    /// The names without wildcards (lowercase)
+    exact: HashSet<String>,
+    /// The patterns with wildcards (lowercase)
+    globs: Vec<Vec<char>>,
+}
+
+/// Whether `name` matches the `pattern`, backtracking to the last `*`.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // the position of the last `*`, and of the name when it was met
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl FieldPatterns {
+    /// Whether the field name matches one of the patterns.
+    pub fn matches(&self, name: &str) -> bool {
+        let lowercase;
+        let name = if name.chars().any(char::is_uppercase) {
+            lowercase = name.to_lowercase();
+            lowercase.as_str()
+        } else {
+            name
+        };
+        if self.exact.contains(name) {
+            return true;
+        }
+        if self.globs.is_empty() {
+            return false;
+        }
+        let name = name.chars().collect::<Vec<_>>();
+        self.globs.iter().any(|glob| glob_match(glob, &name))
+    }
+}
+
+impl FromStr for FieldPatterns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut patterns = FieldPatterns {
+            source: s.trim().to_string(),
+            ..FieldPatterns::default()
+        };
+        for item in s.split([',', '|']).map(str::trim) {
+            let invalid = |reason: &str| {
+                Err(format!(
+                    "Invalid field pattern {:?} in {:?}: {}",
+                    item, s, reason
+                ))
+            };
+            if item.is_empty() {
+                return invalid("it is empty");
+            }
+            if let Some(c) = item
+                .chars()
+                .find(|c| c.is_whitespace() || FORBIDDEN.contains(c))
+            {
+                return invalid(&format!("{:?} cannot appear in a field name", c));
+            }
+            let item = item.to_lowercase();
+            if item.contains(['*', '?']) {
+                patterns.globs.push(item.chars().collect());
+            } else {
+                patterns.exact.insert(item);
+            }
+        }
+        Ok(patterns)
+    }
+}
+
+/// All the patterns of a list, as given by repeating an option.
+impl<'a> FromIterator<&'a FieldPatterns> for FieldPatterns {
+    fn from_iter<I: IntoIterator<Item = &'a FieldPatterns>>(iter: I) -> Self {
+        let mut all = FieldPatterns::default();
+        let mut sources = vec![];
+        for patterns in iter {
+            sources.push(patterns.source.as_str());
+            all.exact.extend(patterns.exact.iter().cloned());
+            all.globs.extend(patterns.globs.iter().cloned());
+        }
+        all.source = sources.join(",");
+        all
+    }
+}
+
+impl Display for FieldPatterns {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl TryFrom<String> for FieldPatterns {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<FieldPatterns> for String {
+    fn from(patterns: FieldPatterns) -> String {
+        patterns.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(patterns: &str, name: &str) -> bool {
+        patterns.parse::<FieldPatterns>().unwrap().matches(name)
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("title", "title"));
+        assert!(matches("Title", "TITLE"));
+        assert!(!matches("title", "subtitle"));
+        assert!(matches("bdsk-*", "bdsk-url-1"));
+        assert!(matches("bdsk-*", "Bdsk-File-1"));
+        assert!(!matches("bdsk-*", "bdsk"));
+        assert!(matches("author|editor", "editor"));
+        assert!(matches("url, doi", "doi"));
+        assert!(matches("bdsk-url-?", "bdsk-url-2"));
+        assert!(!matches("bdsk-url-?", "bdsk-url-12"));
+        assert!(matches("*date*", "urldate"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("a*b*c", "aXbYbZ"));
+        assert!(matches("*", "anything"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |s: &str| s.parse::<FieldPatterns>().unwrap_err();
+        assert!(error("title,,doi").contains("\"\""));
+        assert!(error("bdsk-[0-9]").contains("\"bdsk-[0-9]\""));
+        assert!(error("author|a b").contains("\"a b\""));
+    }
+
+    #[test]
+    fn test_collect() {
+        let lists = ["title".parse().unwrap(), "bdsk-*|doi".parse().unwrap()];
+        let all = lists.iter().collect::<FieldPatterns>();
+        assert!(all.matches("title") && all.matches("doi") && all.matches("bdsk-file-1"));
+        assert!(!all.matches("note"));
+        assert_eq!(all.to_string(), "title,bdsk-*|doi");
+    }
+}
@@ -0,0 +1,259 @@
+//! This file is responsible for knowing the sha256 of the PDFs of a
+//! directory (and its subdirectories), e.g. the working directory of
+//! `bibadac setup --documents`, which may hold thousands of them.
+//!
+//! The files are hashed in parallel, and the hashes are remembered in
+//! a `.bibadac-hashes.json` sidecar of the directory, by path, size, and
+//! modification time: the next runs only hash the new or changed files.
+//! An unreadable or malformed sidecar is silently discarded.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The name of the sidecar, in the indexed directory.
+pub const SIDECAR: &str = ".bibadac-hashes.json";
+
+/// A file as it was hashed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    pub size: u64,
+    /// Modification time, in nanoseconds since the Unix epoch
+    pub mtime: u64,
+    pub sha256: String,
+}
+
+/// The PDFs of a directory, with their sha256.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileIndex {
+    /// The files, by path relative to the directory (with `/` separators)
+    files: BTreeMap<String, IndexedFile>,
+    #[serde(skip)]
+    dir: PathBuf,
+    /// The number of files hashed (rather than found in the sidecar)
+    #[serde(skip)]
+    hashed: usize,
+    /// Whether the sidecar is out of date
+    #[serde(skip)]
+    stale: bool,
+}
+
+/// The PDFs of the directory and its subdirectories, relative to it.
+fn list_pdfs(root: &Path, dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for file in std::fs::read_dir(dir)? {
+        let file = file?;
+        let path = file.path();
+        if file.file_type()?.is_dir() {
+            list_pdfs(root, &path, paths)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            paths.push(path.strip_prefix(root).unwrap_or(&path).to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// The size and modification time of a file.
+fn stat(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    Ok((metadata.len(), mtime))
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    use sha2::Digest;
+    let bytes = std::fs::read(path)?;
+    Ok(format!("{:x}", sha2::Sha256::digest(&bytes)))
+}
+
+/// Hashes the files on every available core, in order.
+fn hash_files(paths: &[PathBuf]) -> Vec<io::Result<String>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = paths.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let handles = paths
+            .chunks(chunk)
+            .map(|paths| scope.spawn(|| paths.iter().map(|p| hash_file(p)).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("a hashing thread panicked"))
+            .collect()
+    })
+}
+
+impl FileIndex {
+    /// Indexes the PDFs of the directory, reusing the hashes of its
+    /// sidecar for the unchanged files, and updates the sidecar.
+    pub fn open(dir: &Path) -> io::Result<FileIndex> {
+        let index = FileIndex::scan(dir)?;
+        if index.stale {
+            index.save()?;
+        }
+        Ok(index)
+    }
+
+    /// Indexes the PDFs of the directory, reusing the hashes of
+    /// its sidecar for the unchanged files, without writing it.
+    pub fn scan(dir: &Path) -> io::Result<FileIndex> {
+        let sidecar = dir.join(SIDECAR);
+        let known = std::fs::read_to_string(&sidecar)
+            .ok()
+            .and_then(|content| serde_json::from_str::<FileIndex>(&content).ok())
+            .unwrap_or_default();
+        let mut paths = vec![];
+        list_pdfs(dir, dir, &mut paths)?;
+
+        let mut files = BTreeMap::new();
+        let mut changed = vec![];
+        for path in paths {
+            let name = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let (size, mtime) = stat(&dir.join(&path))?;
+            match known.files.get(&name) {
+                Some(file) if file.size == size && file.mtime == mtime => {
+                    files.insert(name, file.clone());
+                }
+                _ => changed.push((name, dir.join(&path), size, mtime)),
+            }
+        }
+        let paths = changed
+            .iter()
+            .map(|(_, p, _, _)| p.clone())
+            .collect::<Vec<_>>();
+        for ((name, _, size, mtime), sha256) in changed.iter().zip(hash_files(&paths)) {
+            let file = IndexedFile {
+                size: *size,
+                mtime: *mtime,
+                sha256: sha256?,
+            };
+            files.insert(name.clone(), file);
+        }
+
+        Ok(FileIndex {
+            stale: !changed.is_empty() || known.files.len() != files.len(),
+            files,
+            dir: dir.to_path_buf(),
+            hashed: changed.len(),
+        })
+    }
+
+    /// Writes the sidecar of the directory.
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(self.dir.join(SIDECAR), content)
+    }
+
+    /// The indexed directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The number of files hashed when opening the index,
+    /// rather than found unchanged in the sidecar.
+    pub fn hashed(&self) -> usize {
+        self.hashed
+    }
+
+    /// The files, by path relative to the directory, in order.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &IndexedFile)> {
+        self.files.iter().map(|(name, file)| (name.as_str(), file))
+    }
+
+    /// The file at a path relative to the directory.
+    pub fn get(&self, name: &str) -> Option<&IndexedFile> {
+        self.files.get(name)
+    }
+
+    /// The sha256 of all the files.
+    pub fn sha256s(&self) -> HashSet<&str> {
+        self.files.values().map(|f| f.sha256.as_str()).collect()
+    }
+
+    /// Whether a file of the directory has this sha256.
+    pub fn contains_sha256(&self, sha256: &str) -> bool {
+        self.files
+            .values()
+            .any(|f| f.sha256.eq_ignore_ascii_case(sha256))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// Writes a file, with a modification time of `seconds` after the epoch.
+    fn write(path: &Path, content: &str, seconds: u64) {
+        std::fs::write(path, content).unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_file_index() {
+        let dir = crate::temp_dir("file-index");
+        std::fs::create_dir_all(dir.join("2023")).unwrap();
+        write(&dir.join("a.pdf"), "a", 1);
+        write(&dir.join("2023/b.PDF"), "b", 1);
+        write(&dir.join("notes.txt"), "c", 1);
+
+        let index = FileIndex::open(&dir).unwrap();
+        assert_eq!(index.hashed(), 2);
+        let names = index.files().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["2023/b.PDF", "a.pdf"]);
+        assert_eq!(
+            index.get("a.pdf").unwrap().sha256,
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        );
+        assert!(index.contains_sha256(&index.get("2023/b.PDF").unwrap().sha256));
+        assert!(dir.join(SIDECAR).exists());
+
+        // scanning does not write the sidecar
+        std::fs::remove_file(dir.join(SIDECAR)).unwrap();
+        assert_eq!(FileIndex::scan(&dir).unwrap().hashed(), 2);
+        assert!(!dir.join(SIDECAR).exists());
+        FileIndex::open(&dir).unwrap();
+
+        // nothing changed
+        assert_eq!(FileIndex::open(&dir).unwrap().hashed(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_index_invalidation() {
+        let dir = crate::temp_dir("file-index-invalidation");
+        write(&dir.join("a.pdf"), "a", 1);
+        write(&dir.join("b.pdf"), "b", 1);
+        let old = FileIndex::open(&dir).unwrap();
+
+        // another size, then another modification time with the same size
+        write(&dir.join("a.pdf"), "aa", 1);
+        let index = FileIndex::open(&dir).unwrap();
+        assert_eq!(index.hashed(), 1);
+        assert_ne!(index.get("a.pdf"), old.get("a.pdf"));
+        assert_eq!(index.get("b.pdf"), old.get("b.pdf"));
+        write(&dir.join("b.pdf"), "c", 2);
+        let index = FileIndex::open(&dir).unwrap();
+        assert_eq!(index.hashed(), 1);
+        assert!(!index.contains_sha256(&old.get("b.pdf").unwrap().sha256));
+
+        // removed files are forgotten, and a malformed sidecar is discarded
+        std::fs::remove_file(dir.join("b.pdf")).unwrap();
+        assert_eq!(FileIndex::open(&dir).unwrap().files().count(), 1);
+        std::fs::write(dir.join(SIDECAR), "{").unwrap();
+        assert_eq!(FileIndex::open(&dir).unwrap().hashed(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
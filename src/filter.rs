@@ -0,0 +1,343 @@
+//! This file is responsible for the conditions on the entries, such
+//! as `has(doi) || has(eprint)`, that `bibadac assert` checks on every
+//! entry of a bibliography.
+//!
+//! A condition is made of:
+//!
+//! - `has(field)`: the entry has a non-empty `field`
+//! - `type(article, inproceedings)`: the entry has one of the types
+//! - `field == "value"` and `field != "value"`: the value of the field,
+//!   compared up to braces, case, and spacing (see the `values` module)
+//! - `!`, `&&`, `||`, and parentheses, where `&&` binds tighter than `||`
+//!
+//! Field and type names are case insensitive, and the types are
+//! compared with their aliases resolved (`conference` is `inproceedings`).
+use crate::bibtex_spec::is_entry_type;
+use crate::merge::OwnedEntry;
+use crate::values::same_value;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FilterError {
+    #[error("unexpected `{1}` at position {0}")]
+    UnexpectedChar(usize, char),
+    #[error("unclosed string at position {0}")]
+    UnclosedString(usize),
+    #[error("unexpected `{1}` at position {0}")]
+    UnexpectedToken(usize, String),
+    #[error("unexpected end of the condition")]
+    UnexpectedEnd,
+    #[error("unknown function `{0}` (expected has or type)")]
+    UnknownFunction(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Has(String),
+    Type(Vec<String>),
+    Equals(String, String),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    Text(String),
+    Open,
+    Close,
+    Comma,
+    Not,
+    And,
+    Or,
+    Equals,
+    Differs,
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Name(name) => name.clone(),
+            Token::Text(text) => format!("{:?}", text),
+            Token::Open => "(".into(),
+            Token::Close => ")".into(),
+            Token::Comma => ",".into(),
+            Token::Not => "!".into(),
+            Token::And => "&&".into(),
+            Token::Or => "||".into(),
+            Token::Equals => "==".into(),
+            Token::Differs => "!=".into(),
+        }
+    }
+}
+
+/// The tokens of a condition, with their positions.
+fn tokenize(condition: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let mut tokens = vec![];
+    let mut chars = condition.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::Differs,
+            '!' => Token::Not,
+            '=' if chars.next_if(|(_, c)| *c == '=').is_some() => Token::Equals,
+            '&' if chars.next_if(|(_, c)| *c == '&').is_some() => Token::And,
+            '|' if chars.next_if(|(_, c)| *c == '|').is_some() => Token::Or,
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(FilterError::UnclosedString(i)),
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => text.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, c)) => text.push(c),
+                    }
+                }
+                Token::Text(text)
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' => {
+                let mut name = c.to_string();
+                while let Some((_, c)) =
+                    chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '-')
+                {
+                    name.push(c);
+                }
+                Token::Name(name.to_lowercase())
+            }
+            c => return Err(FilterError::UnexpectedChar(i, c)),
+        };
+        tokens.push((i, token));
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Result<Token, FilterError> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token.ok_or(FilterError::UnexpectedEnd)
+    }
+
+    fn unexpected(&self) -> FilterError {
+        match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some((i, token)) if self.pos <= self.tokens.len() => {
+                FilterError::UnexpectedToken(*i, token.text())
+            }
+            _ => FilterError::UnexpectedEnd,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), FilterError> {
+        if self.next()? == expected {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<Filter, FilterError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Filter, FilterError> {
+        let name = match self.next()? {
+            Token::Open => {
+                let filter = self.or()?;
+                self.expect(Token::Close)?;
+                return Ok(filter);
+            }
+            Token::Name(name) => name,
+            _ => return Err(self.unexpected()),
+        };
+        match self.next()? {
+            Token::Open => {
+                let mut names = vec![];
+                loop {
+                    match self.next()? {
+                        Token::Name(name) => names.push(name),
+                        _ => return Err(self.unexpected()),
+                    }
+                    match self.next()? {
+                        Token::Comma => continue,
+                        Token::Close => break,
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+                match name.as_str() {
+                    "has" if names.len() == 1 => Ok(Filter::Has(names.remove(0))),
+                    "has" => Err(FilterError::UnexpectedToken(
+                        self.tokens[self.pos - 1].0,
+                        ")".into(),
+                    )),
+                    "type" => Ok(Filter::Type(names)),
+                    _ => Err(FilterError::UnknownFunction(name)),
+                }
+            }
+            op @ (Token::Equals | Token::Differs) => {
+                let Token::Text(value) = self.next()? else {
+                    return Err(self.unexpected());
+                };
+                let equals = Filter::Equals(name, value);
+                Ok(match op {
+                    Token::Equals => equals,
+                    _ => Filter::Not(Box::new(equals)),
+                })
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(condition: &str) -> Result<Self, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenize(condition)?,
+            pos: 0,
+        };
+        let filter = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            Some((i, token)) => Err(FilterError::UnexpectedToken(*i, token.text())),
+            None => Ok(filter),
+        }
+    }
+}
+
+impl Filter {
+    /// Whether the entry satisfies the condition.
+    pub fn matches(&self, entry: &OwnedEntry) -> bool {
+        match self {
+            Filter::Has(field) => entry
+                .get(field)
+                .is_some_and(|v| !same_value(field, v, "{}")),
+            Filter::Type(types) => is_entry_type(&entry.entrytype, types),
+            Filter::Equals(field, value) => entry
+                .get(field)
+                .is_some_and(|v| same_value(field, v, &format!("{{{}}}", value))),
+            Filter::Not(filter) => !filter.matches(entry),
+            Filter::And(a, b) => a.matches(entry) && b.matches(entry),
+            Filter::Or(a, b) => a.matches(entry) || b.matches(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(entrytype: &str, fields: &[(&str, &str)]) -> OwnedEntry {
+        OwnedEntry {
+            key: "k".to_string(),
+            entrytype: entrytype.to_string(),
+            fields: fields
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            annotations: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let has = |f: &str| Box::new(Filter::Has(f.to_string()));
+        assert_eq!(
+            "has(doi) || has(eprint) && !has(url)".parse(),
+            Ok(Filter::Or(
+                has("doi"),
+                Box::new(Filter::And(
+                    has("eprint"),
+                    Box::new(Filter::Not(has("url")))
+                ))
+            ))
+        );
+        assert_eq!(
+            "(has(DOI) || has(eprint)) && type(Article, book)".parse(),
+            Ok(Filter::And(
+                Box::new(Filter::Or(has("doi"), has("eprint"))),
+                Box::new(Filter::Type(vec!["article".into(), "book".into()]))
+            ))
+        );
+        assert_eq!(
+            "year != \"2020\"".parse(),
+            Ok(Filter::Not(Box::new(Filter::Equals(
+                "year".into(),
+                "2020".into()
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |s: &str| s.parse::<Filter>().unwrap_err();
+        assert_eq!(error("has(doi"), FilterError::UnexpectedEnd);
+        assert_eq!(error("has(doi) &"), FilterError::UnexpectedChar(9, '&'));
+        assert_eq!(
+            error("has(doi) has(url)"),
+            FilterError::UnexpectedToken(9, "has".into())
+        );
+        assert_eq!(error("title == \"open"), FilterError::UnclosedString(9));
+        assert_eq!(
+            error("exists(doi)"),
+            FilterError::UnknownFunction("exists".into())
+        );
+        assert_eq!(
+            error("has(doi, url)"),
+            FilterError::UnexpectedToken(12, ")".into())
+        );
+        assert_eq!(error(""), FilterError::UnexpectedEnd);
+    }
+
+    #[test]
+    fn test_matches() {
+        let article = entry(
+            "@Article",
+            &[("DOI", "{10.1/a}"), ("url", "{}"), ("year", "2020")],
+        );
+        let matches = |s: &str| s.parse::<Filter>().unwrap().matches(&article);
+        assert!(matches("has(doi) || has(eprint)"));
+        assert!(!matches("has(url)"));
+        assert!(matches("type(article, inproceedings)"));
+        assert!(!matches("type(misc)"));
+        assert!(matches("year == \"2020\" && doi == \"10.1/A\""));
+        assert!(matches("!(year != \"2020\")"));
+        assert!(!matches("title == \"\""));
+        let conference = entry("@Conference", &[("year", "2020")]);
+        assert!(Filter::Type(vec!["inproceedings".into()]).matches(&conference));
+    }
+}
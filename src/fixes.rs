@@ -0,0 +1,376 @@
+//! This file is responsible for the fixes of `bibadac check --fix`,
+//! which replace the spans of the messages that have an obvious
+//! replacement (see `Lint::suggestion`), and for the patches of
+//! `--fix --dry-run --patch-out`, in the unified format that
+//! `git apply` (or `patch -p1`) understands.
+//!
+//! Two fixes of the same part of the file cannot both be applied:
+//! the fixes are taken in the order of the file, and a fix that
+//! overlaps one already taken is left out.
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use crate::line_endings::{LineEnding, LineEndings};
+
+/// The lines of context around the changes of a patch.
+const CONTEXT: usize = 3;
+
+/// A replacement of a byte range of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub span: Range<usize>,
+    pub replacement: String,
+    /// The rule of the message it fixes (see `LintMessage::rule_name`)
+    pub rule: &'static str,
+}
+
+/// Whether `fix` touches the part of the file replaced by `previous`,
+/// which comes first: two insertions at the same place overlap, as
+/// their order would be arbitrary.
+fn overlaps(previous: &Fix, fix: &Fix) -> bool {
+    fix.span.start < previous.span.end || fix.span.start == previous.span.start
+}
+
+/// The fixes that can be applied together, sorted, and the ones
+/// left out as they overlap an earlier fix.
+pub fn select(mut fixes: Vec<Fix>) -> (Vec<Fix>, Vec<Fix>) {
+    fixes.sort_by_key(|fix| fix.span.start);
+    let mut kept: Vec<Fix> = vec![];
+    let mut dropped = vec![];
+    for fix in fixes {
+        match kept.last() {
+            Some(previous) if overlaps(previous, &fix) => dropped.push(fix),
+            _ => kept.push(fix),
+        }
+    }
+    (kept, dropped)
+}
+
+/// The content with the fixes (sorted, without overlaps) applied.
+pub fn apply(content: &str, fixes: &[Fix]) -> String {
+    apply_range(content, 0..content.len(), fixes)
+}
+
+/// The content (with `\n` endings) with the fixes applied, written back
+/// with the ending of every line of the file.
+pub fn apply_with_endings(content: &str, fixes: &[Fix], endings: &LineEndings) -> String {
+    let fixes = fixes
+        .iter()
+        .map(|fix| {
+            // the lines a fix adds have the ending of the line it starts in
+            let newline = content[fix.span.start..]
+                .find('\n')
+                .map_or(content.len(), |i| fix.span.start + i);
+            Fix {
+                span: endings.original_offset(fix.span.start)
+                    ..endings.original_offset(fix.span.end),
+                replacement: endings.ending_at(newline).apply(&fix.replacement),
+                rule: fix.rule,
+            }
+        })
+        .collect::<Vec<_>>();
+    apply(&endings.restore(content), &fixes)
+}
+
+/// The part `range` of the content with the fixes inside of it applied.
+fn apply_range(content: &str, range: Range<usize>, fixes: &[Fix]) -> String {
+    let mut out = String::with_capacity(range.len());
+    let mut last = range.start;
+    for fix in fixes {
+        out.push_str(&content[last..fix.span.start]);
+        out.push_str(&fix.replacement);
+        last = fix.span.end;
+    }
+    out.push_str(&content[last..range.end]);
+    out
+}
+
+/// Writes a line of a hunk with its ending, marking the last
+/// line of a file without a line break as `diff` does.
+fn push_line(out: &mut String, prefix: char, line: &str, ending: LineEnding) {
+    out.push(prefix);
+    match line.strip_suffix('\n') {
+        Some(text) => {
+            out.push_str(text);
+            out.push_str(ending.as_str());
+        }
+        None => {
+            out.push_str(line);
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// The path of a file in the headers of a patch (`a/<path>`), which
+/// `git apply` reads relative to the working directory `cwd`: the
+/// files outside of it are given without their root.
+pub fn patch_path(path: &Path, cwd: &Path) -> String {
+    path.strip_prefix(cwd)
+        .unwrap_or(path)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The lines changed by some fixes, and the fixes.
+struct Change<'a> {
+    lines: Range<usize>,
+    fixes: &'a [Fix],
+}
+
+/// The unified diff of the fixes (sorted, without overlaps) of the
+/// file at `path`, with one hunk per fixed region, empty without fixes.
+/// The content has `\n` endings, and the lines of the patch the
+/// `endings` of the lines of the file.
+pub fn unified_diff(path: &str, content: &str, fixes: &[Fix], endings: &LineEndings) -> String {
+    if fixes.is_empty() {
+        return String::new();
+    }
+    let lines = content.split_inclusive('\n').collect::<Vec<_>>();
+    let mut starts = Vec::with_capacity(lines.len() + 1);
+    let mut offset = 0;
+    for line in &lines {
+        starts.push(offset);
+        offset += line.len();
+    }
+    starts.push(offset);
+    let line_of = |byte: usize| {
+        starts
+            .partition_point(|start| *start <= byte)
+            .saturating_sub(1)
+            .min(lines.len().saturating_sub(1))
+    };
+    let ending = |line: usize| endings.ending_at(starts[line + 1].saturating_sub(1));
+
+    // the fixes of the same or consecutive lines change them together
+    let mut changes: Vec<Change> = vec![];
+    let mut first = 0;
+    for (i, fix) in fixes.iter().enumerate() {
+        let end = line_of(fix.span.end.max(fix.span.start + 1) - 1);
+        let start = line_of(fix.span.start);
+        match changes.last_mut() {
+            Some(change) if start <= change.lines.end => {
+                change.lines.end = change.lines.end.max(end + 1);
+                change.fixes = &fixes[first..=i];
+            }
+            _ => {
+                first = i;
+                changes.push(Change {
+                    lines: start..end + 1,
+                    fixes: &fixes[i..=i],
+                });
+            }
+        }
+    }
+
+    // the changes whose contexts meet are in the same hunk
+    let mut hunks: Vec<Vec<Change>> = vec![];
+    for change in changes {
+        match hunks.last_mut() {
+            Some(hunk) if change.lines.start <= hunk.last().unwrap().lines.end + 2 * CONTEXT => {
+                hunk.push(change)
+            }
+            _ => hunks.push(vec![change]),
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    // the difference of line numbers of the fixed file
+    let mut shift = 0isize;
+    for hunk in hunks {
+        let start = hunk[0].lines.start.saturating_sub(CONTEXT);
+        let end = (hunk.last().unwrap().lines.end + CONTEXT).min(lines.len());
+        let mut body = String::new();
+        let (mut old_count, mut new_count) = (0, 0);
+        let mut line = start;
+        for change in &hunk {
+            for (i, context) in lines.iter().enumerate().take(change.lines.start).skip(line) {
+                push_line(&mut body, ' ', context, ending(i));
+            }
+            old_count += change.lines.start - line;
+            new_count += change.lines.start - line;
+            for (i, old) in lines
+                .iter()
+                .enumerate()
+                .take(change.lines.end)
+                .skip(change.lines.start)
+            {
+                push_line(&mut body, '-', old, ending(i));
+                old_count += 1;
+            }
+            // the new lines have the endings of the lines they replace
+            let range = starts[change.lines.start]..starts[change.lines.end];
+            for (i, new) in apply_range(content, range, change.fixes)
+                .split_inclusive('\n')
+                .enumerate()
+            {
+                let old = (change.lines.start + i).min(change.lines.end - 1);
+                push_line(&mut body, '+', new, ending(old));
+                new_count += 1;
+            }
+            line = change.lines.end;
+        }
+        for (i, context) in lines.iter().enumerate().take(end).skip(line) {
+            push_line(&mut body, ' ', context, ending(i));
+        }
+        old_count += end - line;
+        new_count += end - line;
+        // an empty range starts at the line before it
+        let new_start = (start as isize + shift) as usize + usize::from(new_count > 0);
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            start + 1,
+            old_count,
+            new_start,
+            new_count
+        ));
+        out.push_str(&body);
+        shift += new_count as isize - old_count as isize;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn fix(content: &str, old: &str, replacement: &str) -> Fix {
+        let start = content.find(old).unwrap();
+        Fix {
+            span: start..start + old.len(),
+            replacement: replacement.to_string(),
+            rule: "http-doi",
+        }
+    }
+
+    const CONTENT: &str = "@Article{a,\n  doi = {https://doi.org/10.1/A},\n  year = {2020},\n}\n\n\
+                           @misc{b,\n  title = {B},\n}\n\n\n\n\n\n\
+                           @misc{c,\n  doi = {http://dx.doi.org/10.2/c},\n}";
+
+    #[test]
+    fn test_select() {
+        let fixes = vec![
+            fix(CONTENT, "10.2/c", "x"),
+            fix(CONTENT, "https://doi.org/10.1/A", "10.1/a"),
+            fix(CONTENT, "doi.org/10.1", "y"),
+            fix(CONTENT, "Article", "article"),
+        ];
+        let (kept, dropped) = select(fixes);
+        assert_eq!(
+            kept.iter()
+                .map(|f| f.replacement.as_str())
+                .collect::<Vec<_>>(),
+            ["article", "10.1/a", "x"]
+        );
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].replacement, "y");
+        // the same insertion point
+        let insertion = |replacement: &str| Fix {
+            span: 3..3,
+            replacement: replacement.to_string(),
+            rule: "http-doi",
+        };
+        let (kept, dropped) = select(vec![insertion("a"), insertion("b")]);
+        assert_eq!((kept.len(), dropped.len()), (1, 1));
+    }
+
+    #[test]
+    fn test_patch_path() {
+        let cwd = Path::new("/home/me/paper");
+        assert_eq!(patch_path(Path::new("refs.bib"), cwd), "refs.bib");
+        assert_eq!(patch_path(Path::new("./bib/refs.bib"), cwd), "bib/refs.bib");
+        assert_eq!(
+            patch_path(Path::new("/home/me/paper/refs.bib"), cwd),
+            "refs.bib"
+        );
+        assert_eq!(patch_path(Path::new("/tmp/refs.bib"), cwd), "tmp/refs.bib");
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let (fixes, _) = select(vec![
+            fix(CONTENT, "Article", "article"),
+            fix(CONTENT, "https://doi.org/10.1/A", "10.1/a"),
+            fix(CONTENT, "http://dx.doi.org/10.2/c", "10.2/c"),
+        ]);
+        assert_eq!(
+            unified_diff("refs.bib", CONTENT, &fixes, &LineEndings::default()),
+            "--- a/refs.bib\n+++ b/refs.bib\n\
+             @@ -1,5 +1,5 @@\n\
+             -@Article{a,\n\
+             -  doi = {https://doi.org/10.1/A},\n\
+             +@article{a,\n\
+             +  doi = {10.1/a},\n\
+             \x20  year = {2020},\n\
+             \x20}\n\
+             \x20\n\
+             @@ -12,5 +12,5 @@\n\
+             \x20\n\
+             \x20\n\
+             \x20@misc{c,\n\
+             -  doi = {http://dx.doi.org/10.2/c},\n\
+             +  doi = {10.2/c},\n\
+             \x20}\n\
+             \\ No newline at end of file\n"
+        );
+        assert_eq!(
+            unified_diff("refs.bib", CONTENT, &[], &LineEndings::default()),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_git_apply() {
+        let dir = crate::temp_dir("fixes");
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .map(|output| output.status.success())
+        };
+        if !git(&["init", "--quiet"]).unwrap_or(false) {
+            // git is not installed
+            return;
+        }
+        let (fixes, dropped) = select(vec![
+            fix(CONTENT, "Article", "article"),
+            fix(CONTENT, "https://doi.org/10.1/A", "10.1/a"),
+            fix(CONTENT, "doi.org/10.1", "overlap"),
+            fix(CONTENT, "  title = {B},\n", ""),
+            fix(CONTENT, "http://dx.doi.org/10.2/c", "10.2/c"),
+        ]);
+        assert_eq!(dropped.len(), 1);
+        // the patch of a CRLF file keeps its endings, line by line
+        // when the file mixes both
+        let mixed = CONTENT
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(i, line)| match i % 2 {
+                0 => LineEnding::Crlf.apply(line),
+                _ => line.to_string(),
+            })
+            .collect::<String>();
+        for file in [CONTENT.to_string(), LineEnding::Crlf.apply(CONTENT), mixed] {
+            std::fs::write(dir.join("refs.bib"), &file).unwrap();
+            let endings = LineEndings::of(&file);
+            let patch = unified_diff("refs.bib", CONTENT, &fixes, &endings);
+            std::fs::write(dir.join("fixes.patch"), &patch).unwrap();
+            assert!(git(&["apply", "--check", "fixes.patch"]).unwrap());
+            assert!(git(&["apply", "fixes.patch"]).unwrap());
+            let fixed = std::fs::read_to_string(dir.join("refs.bib")).unwrap();
+            assert_eq!(fixed, apply_with_endings(CONTENT, &fixes, &endings));
+        }
+        let fixed = apply(CONTENT, &fixes);
+        assert!(fixed.starts_with("@article{a,\n  doi = {10.1/a},\n"));
+        let endings = LineEndings::of("@Article{a,\r\n  doi = {x},\n");
+        assert_eq!(
+            apply_with_endings(CONTENT, &fixes, &endings),
+            LineEnding::Crlf.apply("@article{a,\n") + &fixed["@article{a,\n".len()..]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
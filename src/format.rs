@@ -1,4 +1,5 @@
-use crate::author_format::format_authors;
+use crate::author_format::{format_authors, is_breaking_space};
+use crate::authors::{truncate_authors, AUTHORS_BACKUP_FIELD};
 use crate::bibdb::{BibDb, PreBibEntry};
 /// This file is responsible for formatting the bibtex
 /// entries into a "nice" representation.
@@ -6,7 +7,8 @@ use crate::bibdb::{BibDb, PreBibEntry};
 /// With proper indentation and *aligned* equal signs in each entry.
 /// Also, line breaks are taken into account.
 ///
-/// 3. The fields are sorted alphabetically.
+/// 3. The fields are sorted alphabetically
+///    (see the `collation` module).
 /// 4. The entry type and fields are always in lowercase
 /// 5. The *author field* is formatted using the
 ///    Name, Firstname convention.
@@ -18,8 +20,22 @@ use crate::bibdb::{BibDb, PreBibEntry};
 /// and can *fill* the missing fields using this extra
 /// information (if unambiguous).
 ///
-use crate::bibtex::{BibEntry, BibFile};
-use std::collections::HashMap;
+use crate::bibtex::tree_sitter::Node;
+use crate::bibtex::{group_content, BibEntry, BibFile, BibString};
+use crate::bibtex_spec::{confused_people_field, is_entry_type, Flavor};
+use crate::collation::{collate, collation_key};
+use crate::convert::{convert, entry_fields, FieldEdit};
+use crate::dates::{normalize_date, YearValue, DATE_FIELDS};
+use crate::doi_identifiers::{extract_doi, is_resolver_url, normalize_doi};
+use crate::encoding::strip_byte_order_mark;
+use crate::field_order::FieldOrder;
+use crate::field_patterns::FieldPatterns;
+use crate::pages::normalize_pages;
+use crate::provenance::{field_annotations, ANNOTATION_PREFIX};
+use crate::title_case::{recase_title, TitleCase};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct FormatOptions<T> {
@@ -28,12 +44,100 @@ pub struct FormatOptions<T> {
     pub sort_fields: bool,
     pub sort_entries: bool,
     pub format_author: bool,
-    pub field_filter: Option<Vec<String>>,
-    pub whitelist: Option<Vec<String>>,
-    pub blacklist: Option<Vec<String>>,
+    pub field_filter: Option<FieldPatterns>,
+    /// Only format the entries of these types (up to case and
+    /// aliases), the others are written as they are, and are
+    /// never removed by the `field_filter`.
+    pub entry_types: Option<Vec<String>>,
+    /// The fields kept, the blacklist taking precedence
+    /// (see the `field_patterns` module).
+    pub whitelist: Option<FieldPatterns>,
+    pub blacklist: Option<FieldPatterns>,
+    /// Rewrite the doi to its bare lowercase form,
+    /// moving it out of resolver links found in the url.
+    pub normalize_doi: bool,
+    /// Rewrite the date fields to ISO-8601,
+    /// when their current form is unambiguous.
+    pub normalize_dates: bool,
+    /// Remove the `pp.` prefix of the pages, and write
+    /// their ranges with `--` (see the `pages` module).
+    pub normalize_pages: bool,
+    /// Maximal length (in characters) of the given fields,
+    /// longer values are cut at a word boundary.
+    pub truncate_fields: HashMap<String, usize>,
+    /// Remove the `@string` definitions that no entry refers to.
+    pub prune_strings: bool,
+    /// The (lowercase) names of the `@string` definitions used by the
+    /// files formatted together (see `StringUsage`), when the strings of
+    /// a file are not only used by its own entries.
+    pub used_strings: Option<HashSet<String>>,
+    /// Fields (lowercase) always written last, in this order,
+    /// even when sorting the fields (e.g. `verified`).
+    pub pinned_last_fields: Vec<String>,
+    /// The order of the fields, applied after sorting them
+    /// (see the `field_order` module).
+    pub field_order: Option<FieldOrder>,
+    /// Convert the entry types and fields to the conventions of
+    /// BibTeX or BibLaTeX (see the `convert` module).
+    pub convert_types: Option<Flavor>,
+    /// Rename the `author` field of the proceedings and collections
+    /// without editor to `editor` (see `bibtex_spec::PEOPLE_FIELDS`).
+    pub fix_editors: bool,
+    /// Remove the `% bibadac:` comments written by `merge --annotate-merge`
+    /// (see the `provenance` module).
+    pub strip_annotations: bool,
+    /// Write the `pages` of the entries with an `articleno` and
+    /// `numpages` but no `pages`, in the ACM convention (see `acm_pages`).
+    pub synthesize_pages: bool,
+    /// Keep the first people of the longer `author` fields, followed by
+    /// `and others`, the full field being kept in the `backup_authors`.
+    pub max_authors: Option<usize>,
+    /// Write the `backup_authors` field back to the `author` field.
+    pub restore_authors: bool,
+    /// The field (lowercase) of the full `author` field of
+    /// `max_authors` (`authors::AUTHORS_BACKUP_FIELD` by default).
+    pub backup_authors: String,
+    /// Recase the titles entirely in uppercase or in lowercase
+    /// (see the `title_case` module).
+    pub fix_title_case: Option<TitleCase>,
     pub database: T,
 }
 
+/// What the filters of the options removed
+/// from the output (blacklist/whitelist/field_filter).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FormatStats {
+    pub dropped_fields: usize,
+    /// Entries that lost at least one field
+    pub trimmed_entries: usize,
+    /// Entries removed entirely by the field filter
+    pub skipped_entries: usize,
+    /// `@string` definitions that no entry refers to
+    /// (removed with `prune_strings`)
+    pub unused_strings: usize,
+    /// Entries whose conversion (`convert_types`) would have lost
+    /// information, and that were left partly unconverted
+    pub unconverted_entries: usize,
+}
+
+impl std::ops::AddAssign for FormatStats {
+    fn add_assign(&mut self, other: FormatStats) {
+        self.dropped_fields += other.dropped_fields;
+        self.trimmed_entries += other.trimmed_entries;
+        self.skipped_entries += other.skipped_entries;
+        self.unused_strings += other.unused_strings;
+        self.unconverted_entries += other.unconverted_entries;
+    }
+}
+
+impl FormatStats {
+    /// Whether no field or entry was removed (unused
+    /// `@string` definitions carry no information).
+    pub fn is_lossless(&self) -> bool {
+        self.dropped_fields == 0 && self.trimmed_entries == 0 && self.skipped_entries == 0
+    }
+}
+
 /// Hints are of the form
 /// "doi", "name" -> ["alice"]
 /// "doi", "title" -> ["title1", "title2"]
@@ -50,12 +154,193 @@ impl<T> FormatOptions<T> {
             sort_fields: false,
             sort_entries: false,
             field_filter: None,
+            entry_types: None,
             whitelist: None,
             blacklist: None,
+            normalize_doi: false,
+            normalize_dates: false,
+            normalize_pages: false,
+            truncate_fields: HashMap::new(),
+            prune_strings: false,
+            used_strings: None,
+            pinned_last_fields: vec![],
+            field_order: None,
+            convert_types: None,
+            fix_editors: false,
+            strip_annotations: false,
+            synthesize_pages: false,
+            max_authors: None,
+            restore_authors: false,
+            backup_authors: AUTHORS_BACKUP_FIELD.to_string(),
+            fix_title_case: None,
             format_author: true,
             database: db,
         }
     }
+
+    /// The same options, borrowing the database.
+    pub fn as_ref(&self) -> FormatOptions<&T> {
+        FormatOptions {
+            indent: self.indent,
+            min_field_length: self.min_field_length,
+            sort_fields: self.sort_fields,
+            sort_entries: self.sort_entries,
+            format_author: self.format_author,
+            field_filter: self.field_filter.clone(),
+            entry_types: self.entry_types.clone(),
+            whitelist: self.whitelist.clone(),
+            blacklist: self.blacklist.clone(),
+            normalize_doi: self.normalize_doi,
+            normalize_dates: self.normalize_dates,
+            normalize_pages: self.normalize_pages,
+            truncate_fields: self.truncate_fields.clone(),
+            prune_strings: self.prune_strings,
+            used_strings: self.used_strings.clone(),
+            pinned_last_fields: self.pinned_last_fields.clone(),
+            field_order: self.field_order.clone(),
+            convert_types: self.convert_types,
+            fix_editors: self.fix_editors,
+            strip_annotations: self.strip_annotations,
+            synthesize_pages: self.synthesize_pages,
+            max_authors: self.max_authors,
+            restore_authors: self.restore_authors,
+            backup_authors: self.backup_authors.clone(),
+            fix_title_case: self.fix_title_case,
+            database: &self.database,
+        }
+    }
+}
+
+impl<T: Default> Default for FormatOptions<T> {
+    fn default() -> Self {
+        FormatOptions::new(T::default())
+    }
+}
+
+/// The lines that differ between two outputs of the formatter, written
+/// as a diff of the region between their common first and last lines,
+/// or `None` when they are the same.
+pub fn changed_lines(before: &str, after: &str) -> Option<String> {
+    if before == after {
+        return None;
+    }
+    let (before, after) = (
+        before.split('\n').collect::<Vec<_>>(),
+        after.split('\n').collect::<Vec<_>>(),
+    );
+    let prefix = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut diff = format!("@@ line {} @@\n", prefix + 1);
+    for line in &before[prefix..before.len() - suffix] {
+        diff += &format!("-{}\n", line);
+    }
+    for line in &after[prefix..after.len() - suffix] {
+        diff += &format!("+{}\n", line);
+    }
+    Some(diff)
+}
+
+/// Width of the longest field name of the file,
+/// used to align the fields (`min_field_length`).
+pub fn field_name_width(bib: &BibFile) -> usize {
+    bib.list_entries()
+        .map(|entry| {
+            entry
+                .fields
+                .iter()
+                .map(|field| bib.get_slice(field.name).len())
+                .max()
+                .unwrap_or(0)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Truncates a delimited value (`{...}` or `"..."`) so that
+/// its content has at most `max` characters, cutting at a word
+/// boundary outside of brace groups and appending an ellipsis.
+/// A value without such a boundary (e.g. a long url) is cut at
+/// the character, closing its open brace groups.
+/// Returns `None` if the value is short enough.
+pub fn truncate_value(value: &str, max: usize) -> Option<String> {
+    let (open, close) = match value.chars().next()? {
+        '{' => ('{', '}'),
+        '"' => ('"', '"'),
+        _ => return None,
+    };
+    let inner = value.strip_prefix(open)?.strip_suffix(close)?;
+    if inner.chars().count() <= max {
+        return None;
+    }
+    let mut depth = 0;
+    let mut cut = 0;
+    // the end of the last character that can be kept when cutting
+    // at the character, and the number of groups to close there
+    let mut end = (0, 0);
+    // keep room for the ellipsis
+    for (n, (i, c)) in inner.char_indices().enumerate().take(max.saturating_sub(1)) {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => cut = i,
+            _ => {}
+        }
+        if n + 1 + (depth.max(0) as usize) < max {
+            end = (i + c.len_utf8(), depth);
+        }
+    }
+    if inner[..cut].trim_end().is_empty() {
+        let (end, depth) = end;
+        let closing = "}".repeat(depth.max(0) as usize);
+        return Some(format!("{}{}{}…{}", open, &inner[..end], closing, close));
+    }
+    Some(format!("{}{}…{}", open, inner[..cut].trim_end(), close))
+}
+
+/// The pages of an article of the ACM, given its article number
+/// and number of pages: `17:1--17:25`, or `17:1` for a single page.
+pub fn acm_pages(articleno: &str, numpages: &str) -> Option<String> {
+    let articleno = articleno.trim();
+    let numpages = numpages.trim().parse::<u32>().ok()?;
+    if articleno.is_empty() || articleno.contains(char::is_whitespace) || numpages == 0 {
+        return None;
+    }
+    if numpages == 1 {
+        Some(format!("{}:1", articleno))
+    } else {
+        Some(format!("{}:1--{}:{}", articleno, articleno, numpages))
+    }
+}
+
+/// Writes the `% bibadac:` comments of a field (see the `provenance` module).
+fn write_annotations<T, K>(
+    annotations: &[&str],
+    options: &FormatOptions<K>,
+    out: &mut T,
+) -> std::fmt::Result
+where
+    T: std::fmt::Write,
+{
+    for annotation in annotations {
+        writeln!(
+            out,
+            "{:indent$}% {} {}",
+            "",
+            ANNOTATION_PREFIX,
+            annotation,
+            indent = options.indent
+        )?;
+    }
+    Ok(())
 }
 
 pub fn write_bibfield<T, K>(
@@ -70,181 +355,527 @@ where
     T: std::fmt::Write,
     K: BibDb,
 {
-    let lines: Vec<_> = value.split('\n').collect();
+    // the files are read with `\n` endings, but not every `BibFile`
+    let mut lines = value.split('\n').map(|line| line.trim_end_matches('\r'));
     let subsequent_indent = options.indent + 4 + options.min_field_length.unwrap_or(0);
+    // names are almost always lowercase already
+    let name = if name.chars().any(char::is_uppercase) {
+        Cow::Owned(name.to_lowercase())
+    } else {
+        Cow::Borrowed(name)
+    };
     write!(
         out,
         "{:indent$}{:<width$} = {value}",
         "",
-        name.to_lowercase(),
-        value = lines[0],
+        name,
+        value = lines.next().unwrap_or(""),
         indent = options.indent,
         width = options.min_field_length.unwrap_or(0),
     )?;
-    for line in lines[1..].iter() {
+    for line in lines {
         write!(
             out,
             "\n{:indent$}{}",
             "",
-            line.trim(),
+            // the ties at the ends of the lines are kept
+            line.trim_matches(is_breaking_space),
             indent = subsequent_indent
         )?;
     }
     write!(out, ",\n")
 }
 
-pub fn write_bibentry<T, K>(
-    bib: &BibFile,
-    entry: &BibEntry,
-    options: &FormatOptions<K>,
-    out: &mut T,
-) 
--> std::fmt::Result
-where
-    T: std::fmt::Write,
-    K: BibDb,
-{
-    let key = bib.get_slice(entry.key);
-    let entrytype = bib.get_slice(entry.entrytype);
+/// The fields that the database adds to an entry (the fields
+/// that the entry already has are left out), as the formatter
+/// writes them below the fields of the entry.
+pub fn completion<K: BibDb>(bib: &BibFile, entry: &BibEntry, database: &K) -> PreBibEntry {
     let prebib = PreBibEntry {
         properties: entry
             .fields
             .iter()
             .map(|f| {
                 (
-                    bib.get_slice(f.name).to_lowercase(),
+                    bib.get_slice(f.name).to_lowercase().into(),
                     bib.get_slice(f.value).into(),
                 )
             })
-            .collect::<HashMap<String, String>>(),
+            .collect::<HashMap<Arc<str>, String>>(),
     };
-    let mut compl = options.database.complete(&prebib);
+    let mut compl = database.complete(&prebib);
     compl
         .properties
         .retain(|k, _| !prebib.properties.contains_key(k));
+    compl
+}
+
+pub fn write_bibentry<T, K>(
+    bib: &BibFile,
+    entry: &BibEntry,
+    options: &FormatOptions<K>,
+    out: &mut T,
+) 
+-> Result<FormatStats, std::fmt::Error>
+where
+    T: std::fmt::Write,
+    K: BibDb,
+{
+    let mut stats = FormatStats::default();
+    if let Some(entry_types) = &options.entry_types
+        && !is_entry_type(bib.get_slice(entry.entrytype), entry_types)
+    {
+        write!(out, "{}\n\n", bib.get_slice(entry.loc))?;
+        return Ok(stats);
+    }
+    let key = bib.get_slice(entry.key).trim();
+    let entrytype = bib.get_slice(entry.entrytype);
+    let compl = completion(bib, entry, &options.database);
 
-    let mut fields = entry.fields.clone();
+    // the order in which the fields are written
+    let mut order = (0..entry.fields.len()).collect::<Vec<_>>();
     if options.sort_fields {
-        fields.sort_by_key(|field| bib.get_slice(field.name).to_lowercase());
+        let names = entry
+            .fields
+            .iter()
+            .map(|f| {
+                let name = bib.get_slice(f.name);
+                (collation_key(name), name)
+            })
+            .collect::<Vec<_>>();
+        // ties are broken by the bytes of the names, then by source order
+        order.sort_by(|&i, &j| names[i].cmp(&names[j]).then(i.cmp(&j)));
+    }
+    if let Some(field_order) = &options.field_order {
+        // a stable sort keeps the other fields in their (sorted) order
+        order.sort_by_cached_key(|&i| {
+            field_order.rank(&bib.get_slice(entry.fields[i].name).to_lowercase())
+        });
     }
+    if !options.pinned_last_fields.is_empty() {
+        // a stable sort keeps the other fields in place
+        order.sort_by_cached_key(|&i| {
+            let name = bib.get_slice(entry.fields[i].name).to_lowercase();
+            options
+                .pinned_last_fields
+                .iter()
+                .position(|p| *p == name)
+                .map(|p| p + 1)
+        });
+    }
+    let fields = &entry.fields;
 
     if let Some(field_filter) = &options.field_filter {
         if !fields
             .iter()
-            .any(|field| field_filter.contains(&bib.get_slice(field.name).to_lowercase()))
+            .any(|field| field_filter.matches(bib.get_slice(field.name)))
         {
-            return Ok(());
+            stats.skipped_entries += 1;
+            return Ok(stats);
         }
     }
 
-    write!(out, "{}{{{key},\n", entrytype.to_lowercase(), key = key)?;
+    // Skip fields that are not in the whitelist,
+    // and if they are in the whitelist, skip if they are in the blacklist
+    let keep = |name: &str| {
+        options.whitelist.as_ref().is_none_or(|w| w.matches(name))
+            && options.blacklist.as_ref().is_none_or(|b| !b.matches(name))
+    };
 
-    for field in fields {
-        // Skip fields that are not in the whitelist
-        if let Some(whitelist) = &options.whitelist {
-            if !whitelist.contains(&bib.get_slice(field.name).to_lowercase()) {
+    // The canonical doi, taken from the doi field,
+    // or from a resolver link in the url field when there is no doi.
+    let field_value = |name: &str| {
+        fields
+            .iter()
+            .find(|f| bib.get_slice(f.name).eq_ignore_ascii_case(name))
+            .map(|f| bib.get_braceless_slice(f.value))
+    };
+    let has_doi = field_value("doi").is_some();
+    let doi = match (options.normalize_doi, field_value("doi"), field_value("url")) {
+        (false, _, _) => None,
+        (true, Some(doi), _) => normalize_doi(doi),
+        (true, None, Some(url)) => extract_doi(url).map(|d| d.to_lowercase()),
+        (true, None, None) => None,
+    };
+    let redundant_url = match (&doi, field_value("url")) {
+        (Some(doi), Some(url)) => is_resolver_url(url, doi),
+        _ => false,
+    };
+    // the authors of proceedings are their editors
+    let rename_author = options.fix_editors
+        && confused_people_field(
+            entrytype,
+            field_value("author").is_some(),
+            field_value("editor").is_some(),
+        ) == Some(("author", "editor"));
+    // written after the numpages, never over existing pages
+    let pages = match (field_value("articleno"), field_value("numpages")) {
+        (Some(articleno), Some(numpages))
+            if options.synthesize_pages && field_value("pages").is_none() && keep("pages") =>
+        {
+            acm_pages(articleno, numpages)
+        }
+        _ => None,
+    };
+    // the author field of `max_authors`, written before its backup
+    let backup = options.backup_authors.as_str();
+    let truncated = match (
+        options.max_authors,
+        field_value("author"),
+        field_value(backup),
+    ) {
+        (Some(max), Some(authors), None) if keep(backup) => truncate_authors(authors, max),
+        _ => None,
+    };
+    // the backup takes the place of the truncated field
+    let restore =
+        options.restore_authors && field_value(backup).is_some() && keep(backup) && keep("author");
+
+    let conversion = options
+        .convert_types
+        .map(|flavor| convert(entrytype, &entry_fields(bib, entry), flavor));
+    if conversion.as_ref().is_some_and(|c| !c.issues.is_empty()) {
+        stats.unconverted_entries += 1;
+    }
+    let entrytype = match conversion.as_ref().and_then(|c| c.entrytype) {
+        Some(entrytype) => format!("@{}", entrytype),
+        None => entrytype.to_lowercase(),
+    };
+
+    writeln!(out, "{}{{{key},", entrytype, key = key)?;
+
+    // the `% bibadac:` comments of a field, written after it
+    // (at the next field, whatever the branch writing the field)
+    let annotations = field_annotations(bib, entry);
+    let mut pending: &[&str] = &[];
+    // the lowercase name of the current field, reusing its allocation
+    let mut name = String::new();
+    for &i in order.iter() {
+        write_annotations(pending, options, out)?;
+        pending = &[];
+        let field = &fields[i];
+        name.clear();
+        name.extend(bib.get_slice(field.name).chars().flat_map(char::to_lowercase));
+        if !keep(&name) {
+            stats.dropped_fields += 1;
+            continue;
+        }
+        if !options.strip_annotations {
+            pending = &annotations[i];
+        }
+        match conversion.as_ref().map(|c| &c.fields[i]) {
+            Some(FieldEdit::Drop) => {
+                pending = &[];
                 continue;
             }
+            Some(FieldEdit::Rename(target)) => {
+                write_bibfield(bib, target, bib.get_slice(field.value), options, out)?;
+                continue;
+            }
+            Some(FieldEdit::Replace(target, value)) => {
+                write_bibfield(bib, target, value, options, out)?;
+                continue;
+            }
+            Some(FieldEdit::Keep) | None => {}
         }
-        // If they are in the whitelist, skip if they are in the blacklist
-        if let Some(blacklist) = &options.blacklist {
-            if blacklist.contains(&bib.get_slice(field.name).to_lowercase()) {
+        if let Some(doi) = &doi {
+            if name == "doi" {
+                write_bibfield(bib, "doi", &format!("{{{}}}", doi), options, out)?;
+                continue;
+            }
+            if name == "url" {
+                if !redundant_url {
+                    write_bibfield(bib, "url", bib.get_slice(field.value), options, out)?;
+                }
+                if !has_doi && keep("doi") {
+                    write_bibfield(bib, "doi", &format!("{{{}}}", doi), options, out)?;
+                }
                 continue;
             }
         }
-        if options.format_author && bib.get_slice(field.name) == "author" {
-            let authors = bib.get_slice(field.value);
-            let mut formatted_authors = "{".to_string();
-            formatted_authors += &format_authors(&authors[1..authors.len() - 1]);
-            formatted_authors += "}";
-            write_bibfield(bib, "author", &formatted_authors, options, out)?;
+        if let Some(authors) = &truncated
+            && name == "author"
+        {
+            let authors = match options.format_author {
+                true => format_authors(authors),
+                false => authors.clone(),
+            };
+            write_bibfield(bib, "author", &format!("{{{}}}", authors), options, out)?;
+            write_bibfield(bib, backup, bib.get_slice(field.value), options, out)?;
+            continue;
+        }
+        if restore && name == "author" {
+            continue;
+        }
+        let field_name = match bib.get_slice(field.name) {
+            _ if rename_author && name == "author" => "editor",
+            _ if restore && name == backup => "author",
+            field_name => field_name,
+        };
+        let is_author = bib.get_slice(field.name) == "author" || (restore && name == backup);
+        // the numbers, macros and concatenations are written as they are
+        if options.format_author
+            && is_author
+            && let Some(authors) = group_content(bib.get_slice(field.value))
+        {
+            let formatted_authors = format!("{{{}}}", format_authors(authors));
+            write_bibfield(bib, field_name, &formatted_authors, options, out)?;
         } else {
+            let value = bib.get_slice(field.value);
+            let fixed = if options.normalize_dates && DATE_FIELDS.contains(&name.as_str()) {
+                normalize_date(bib.get_braceless_slice(field.value))
+            } else if options.normalize_pages && name == "pages" {
+                normalize_pages(bib.get_braceless_slice(field.value))
+            } else {
+                None
+            }
+            .map(|value| format!("{{{}}}", value))
+            .or_else(|| {
+                let style = options.fix_title_case.filter(|_| name == "title")?;
+                recase_title(value, style)
+            });
+            let truncated = options
+                .truncate_fields
+                .get(&name)
+                .and_then(|max| truncate_value(value, *max));
             write_bibfield(
                 bib,
-                bib.get_slice(field.name),
-                bib.get_slice(field.value),
+                field_name,
+                fixed.as_deref().or(truncated.as_deref()).unwrap_or(value),
                 options,
                 out,
             )?;
         }
+        if let Some(pages) = pages.as_ref().filter(|_| name == "numpages") {
+            write_bibfield(bib, "pages", &format!("{{{}}}", pages), options, out)?;
+        }
+    }
+    write_annotations(pending, options, out)?;
+
+    for (name, value) in conversion.iter().flat_map(|c| &c.added) {
+        if keep(name) {
+            write_bibfield(bib, name, value, options, out)?;
+        }
     }
 
     if compl.properties.len() > 1 {
         writeln!(out)?;
     }
     for (name, value) in compl.properties {
-        // Skip fields that are not in the whitelist
-        if let Some(whitelist) = &options.whitelist {
-            if !whitelist.contains(&name) {
-                continue;
+        if !keep(&name) {
+            continue;
+        }
+        write_bibfield(bib, &name, &value, options, out)?;
+    }
+
+    write!(out, "}}\n\n")?;
+    if stats.dropped_fields > 0 {
+        stats.trimmed_entries += 1;
+    }
+    Ok(stats)
+}
+
+/// The `@string` definitions that the entries of several files refer
+/// to, as a file may use the strings of another one.
+#[derive(Debug, Clone, Default)]
+pub struct StringUsage {
+    /// The (lowercase) names the entries refer to
+    used: HashSet<String>,
+    /// The (lowercase) names the definitions refer to, by name
+    references: Vec<(String, Vec<String>)>,
+}
+
+impl StringUsage {
+    pub fn add(&mut self, bib: &BibFile) {
+        for entry in bib.list_entries() {
+            for field in entry.fields.iter() {
+                let names = bib.macro_references(field.value).into_iter();
+                self.used.extend(names.map(str::to_lowercase));
             }
         }
-        // If they are in the whitelist, skip if they are in the blacklist
-        if let Some(blacklist) = &options.blacklist {
-            if blacklist.contains(&name) {
-                continue;
+        for s in bib.list_string_definitions() {
+            let names = bib.macro_references(s.value).into_iter();
+            self.references.push((
+                bib.get_slice(s.name).to_lowercase(),
+                names.map(str::to_lowercase).collect(),
+            ));
+        }
+    }
+
+    /// The (lowercase) names of the strings used,
+    /// even through other strings.
+    pub fn used(&self) -> HashSet<String> {
+        let mut used = self.used.clone();
+        loop {
+            let before = used.len();
+            for (name, references) in self.references.iter() {
+                if used.contains(name) {
+                    used.extend(references.iter().cloned());
+                }
+            }
+            if used.len() == before {
+                return used;
             }
         }
-        write_bibfield(bib, &name, &value, options, out)?;
     }
+}
 
-    write!(out, "}}\n\n")?;
-    Ok(())
+/// The (lowercase) names of the `@string` definitions of the file
+/// that are not `used`.
+fn unused_among(bib: &BibFile, used: &HashSet<String>) -> HashSet<String> {
+    bib.list_string_definitions()
+        .map(|s| bib.get_slice(s.name).to_lowercase())
+        .filter(|name| !used.contains(name))
+        .collect()
+}
+
+/// The (lowercase) names of the `@string` definitions
+/// that no entry refers to, even through other strings.
+pub fn unused_strings(bib: &BibFile) -> HashSet<String> {
+    let mut usage = StringUsage::default();
+    usage.add(bib);
+    unused_among(bib, &usage.used())
+}
+
+/// What the entries are sorted by.
+struct EntrySortKey<'a> {
+    year: i32,
+    /// The collation key of the authors
+    author: String,
+    key: &'a str,
+}
+
+fn entry_sort_key<'a>(bib: &'a BibFile, entry: &BibEntry) -> EntrySortKey<'a> {
+    let field = |name: &str| {
+        entry
+            .fields
+            .iter()
+            .find(|f| bib.get_slice(f.name).eq_ignore_ascii_case(name))
+            .map(|f| bib.get_braceless_slice(f.value).trim_matches('"').trim())
+    };
+    EntrySortKey {
+        year: field("year").map_or(0, |y| YearValue::parse(y).sort_key()),
+        author: collation_key(field("author").unwrap_or("")),
+        key: bib.get_slice(entry.key),
+    }
+}
+
+/// The entry of a node, unless it has syntax errors, or tokens that
+/// tree-sitter inserted (`is_missing`): such entries are written as is.
+fn formattable(node: Node) -> Option<BibEntry> {
+    BibEntry::from_node(node).filter(|_| !node.has_error())
 }
 
 pub fn write_bibfile<T, K>(bib: &BibFile, options: &FormatOptions<K>, out: &mut T)
-    -> std::fmt::Result
+    -> Result<FormatStats, std::fmt::Error>
 where
     T: std::fmt::Write,
     K: BibDb,
 {
-    if options.sort_entries {
-        let mut cursor = bib.tree.root_node().walk();
-        for entry in bib.tree.root_node().children(&mut cursor) {
-            if let Some(_) = BibEntry::from_node(entry) {
-            } else {
-                let slice = bib.get_slice(entry);
-                write!(out, "{}", slice).unwrap();
+    let mut stats = FormatStats::default();
+    let unused = match &options.used_strings {
+        Some(used) => unused_among(bib, used),
+        None => unused_strings(bib),
+    };
+    // everything that is not an entry is written as is,
+    // except for the unused strings when pruning them
+    let write_other = |node: Node, out: &mut T, stats: &mut FormatStats| {
+        if let Some(string) = BibString::from_node(node)
+            && unused.contains(&bib.get_slice(string.name).to_lowercase())
+        {
+            stats.unused_strings += 1;
+            if options.prune_strings {
+                return Ok(());
             }
         }
-        let mut entries = bib.list_entries().collect::<Vec<_>>();
-        entries.sort_by_key(|e| {
-            let year = e
-                .fields
+        write!(out, "{}", strip_byte_order_mark(bib.get_slice(node)))
+    };
+    let mut cursor = bib.tree.root_node().walk();
+    let nodes = bib
+        .tree
+        .root_node()
+        .children(&mut cursor)
+        .collect::<Vec<_>>();
+    // the metadata of JabRef and BibDesk is written as is, along
+    // with the whitespace that follows it, in place of its items
+    let metadata = bib
+        .metadata_comments()
+        .into_iter()
+        .map(|(_, span)| {
+            let next = nodes
                 .iter()
-                .find_map(|f| {
-                    if bib.get_slice(f.name) == "year" {
-                        let ctn = bib.get_slice(f.value);
-                        let first_char = ctn.chars().nth(0)?;
-                        if !first_char.is_digit(10) {
-                            let ctn2 = &ctn[1..std::cmp::max(1, ctn.len() - 1)];
-                            Some(i32::from_str_radix(ctn2, 10).unwrap_or(0))
-                        } else {
-                            Some(i32::from_str_radix(ctn, 10).unwrap_or(0))
-                        }
-                    } else {
-                        None
-                    }
-                })
-                .unwrap_or(0);
-            -year
+                .map(|n| n.start_byte())
+                .find(|start| *start >= span.end)
+                .unwrap_or(bib.content.len());
+            let gap = &bib.content[span.end..next];
+            if gap.trim().is_empty() {
+                span.start..next
+            } else {
+                span
+            }
+        })
+        .collect::<Vec<_>>();
+    let span_of = |node: Node| {
+        metadata
+            .iter()
+            .find(|span| span.contains(&node.start_byte()))
+    };
+    let write_metadata =
+        |span: &std::ops::Range<usize>, node: Node, out: &mut T| -> std::fmt::Result {
+            if node.start_byte() == span.start {
+                write!(out, "{}", &bib.content[span.clone()])?;
+            }
+            Ok(())
+        };
+    if options.sort_entries {
+        // the metadata after the last entry stays at the end of the file
+        let last_entry = nodes
+            .iter()
+            .filter(|n| span_of(**n).is_none() && formattable(**n).is_some())
+            .map(|n| n.end_byte())
+            .max()
+            .unwrap_or(0);
+        let mut trailing = vec![];
+        for node in nodes.iter().copied() {
+            match span_of(node) {
+                None if formattable(node).is_some() => {}
+                Some(span) if span.start >= last_entry => trailing.push((span, node)),
+                Some(span) => write_metadata(span, node, out)?,
+                None => write_other(node, out, &mut stats)?,
+            }
+        }
+        let mut entries = bib
+            .list_entries()
+            .filter(|e| span_of(e.loc).is_none() && !e.loc.has_error())
+            .map(|e| (entry_sort_key(bib, &e), e))
+            .collect::<Vec<_>>();
+        // the most recent entries first, then by author and key,
+        // and in source order when everything else is equal
+        entries.sort_by(|(a, e), (b, f)| {
+            b.year
+                .cmp(&a.year)
+                .then_with(|| a.author.cmp(&b.author))
+                .then_with(|| collate(a.key, b.key))
+                .then(e.loc.start_byte().cmp(&f.loc.start_byte()))
         });
-        for entry in entries {
-            write_bibentry(bib, &entry, options, out)?;
+        for (_, entry) in entries {
+            stats += write_bibentry(bib, &entry, options, out)?;
+        }
+        for (span, node) in trailing {
+            write_metadata(span, node, out)?;
         }
     } else {
-        let mut cursor = bib.tree.root_node().walk();
-        for entry in bib.tree.root_node().children(&mut cursor) {
-            if let Some(entry) = BibEntry::from_node(entry) {
-                write_bibentry(bib, &entry, options, out)?;
+        for node in nodes.iter().copied() {
+            if let Some(span) = span_of(node) {
+                write_metadata(span, node, out)?;
+            } else if let Some(entry) = formattable(node) {
+                stats += write_bibentry(bib, &entry, options, out)?;
             } else {
-                let slice = bib.get_slice(entry);
-                write!(out, "{}", slice)?;
+                write_other(node, out, &mut stats)?;
             }
         }
     }
 
-    Ok(())
+    Ok(stats)
 }
 
 pub struct BibFormat<'a, K> {
@@ -256,6 +887,557 @@ impl<'a,K> std::fmt::Display for BibFormat<'a, K>
 where K: BibDb 
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write_bibfile(self.bib, self.options, f)
+        write_bibfile(self.bib, self.options, f).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibdb::LocalBibDb;
+
+    fn format_normalized(input: &str) -> String {
+        let bib = BibFile::new(input);
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.normalize_doi = true;
+        options.normalize_dates = true;
+        options.normalize_pages = true;
+        BibFormat {
+            bib: &bib,
+            options: &options,
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn test_format_stats() {
+        let bib = BibFile::new(
+            "@article{a,\n  title = {A},\n  doi = {10.1/a},\n  year = 2020,\n}\n@misc{b,\n  note = {B},\n}\n",
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.whitelist = Some("title".parse().unwrap());
+        let mut out = String::new();
+        let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(
+            stats,
+            FormatStats {
+                dropped_fields: 3,
+                trimmed_entries: 2,
+                skipped_entries: 0,
+                unused_strings: 0,
+                unconverted_entries: 0,
+            }
+        );
+
+        options.whitelist = None;
+        options.field_filter = Some("doi".parse().unwrap());
+        let mut out = String::new();
+        let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(
+            stats,
+            FormatStats {
+                dropped_fields: 0,
+                trimmed_entries: 0,
+                skipped_entries: 1,
+                unused_strings: 0,
+                unconverted_entries: 0,
+            }
+        );
+        assert!(!out.contains("@misc"));
+    }
+
+    #[test]
+    fn test_field_patterns() {
+        let bib = BibFile::new(
+            "@misc{a,\n  Bdsk-Url-1 = {U},\n  bdsk-file-1 = {F},\n  title = {T},\n  editor = {E},\n  note = {N},\n}\n",
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.blacklist = Some("bdsk-*".parse().unwrap());
+        let mut out = String::new();
+        write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(
+            out,
+            "@misc{a,\n  title = {T},\n  editor = {E},\n  note = {N},\n}\n\n"
+        );
+
+        // a field in both lists is removed
+        options.whitelist = Some("title|editor|bdsk-url-?".parse().unwrap());
+        options.blacklist = Some("Editor, bdsk-*".parse().unwrap());
+        let mut out = String::new();
+        let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(out, "@misc{a,\n  title = {T},\n}\n\n");
+        assert_eq!(stats.dropped_fields, 4);
+    }
+
+    #[test]
+    fn test_pinned_last_fields() {
+        let bib = BibFile::new(
+            "@misc{a,\n  Verified = {2024-01-10 by alice},\n  year = 2020,\n  note = {N},\n  author = {A},\n}\n",
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.format_author = false;
+        options.pinned_last_fields = vec!["verified".to_string(), "note".to_string()];
+        let names = |options: &FormatOptions<&mut LocalBibDb>| {
+            let mut out = String::new();
+            write_bibfile(&bib, options, &mut out).unwrap();
+            out.lines()
+                .skip(1)
+                .filter_map(|l| l.split_once('=').map(|(n, _)| n.trim().to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&options), vec!["year", "author", "verified", "note"]);
+        options.sort_fields = true;
+        assert_eq!(names(&options), vec!["author", "year", "verified", "note"]);
+    }
+
+    #[test]
+    fn test_field_order() {
+        let bib = BibFile::new(
+            "@article{a,\n  title = {T},\n  Month = jan,\n  pages = {1--2},\n  year = 2020,\n  journal = {J},\n  verified = {2024-01-10},\n  author = {A},\n}\n",
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.format_author = false;
+        options.sort_fields = true;
+        options.field_order = Some("author,title,*,year+month".parse().unwrap());
+        let names = |options: &FormatOptions<&mut LocalBibDb>| {
+            let mut out = String::new();
+            write_bibfile(&bib, options, &mut out).unwrap();
+            out.lines()
+                .skip(1)
+                .filter_map(|l| l.split_once('=').map(|(n, _)| n.trim().to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&options),
+            vec![
+                "author", "title", "journal", "pages", "verified", "year", "month"
+            ]
+        );
+        // with the pinned fields last, and without the fields that are not kept
+        options.pinned_last_fields = vec!["verified".to_string()];
+        options.whitelist = Some("month,pages,title,verified".parse().unwrap());
+        assert_eq!(names(&options), vec!["title", "pages", "month", "verified"]);
+    }
+
+    #[test]
+    fn test_entry_types() {
+        let bib = BibFile::new(
+            "@article{a,  title={A}, doi = {10.1/a}}\n\
+             @Conference{b,  title={B}}\n\
+             @misc{c,  title={C}}\n\
+             @inproceedings{d,  title={D}, doi = {10.1/d}}\n",
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.entry_types = Some(vec!["Article".to_string(), "inproceedings".to_string()]);
+        let mut out = String::new();
+        write_bibfile(&bib, &options, &mut out).unwrap();
+        assert!(out.contains("@article{a,\n  title = {A},\n"));
+        // the alias of inproceedings is formatted
+        assert!(out.contains("@conference{b,\n  title = {B},\n"));
+        assert!(out.contains("@misc{c,  title={C}}\n\n"));
+
+        // the field filter only removes the entries of the types
+        options.field_filter = Some("doi".parse().unwrap());
+        let mut out = String::new();
+        let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(stats.skipped_entries, 1);
+        assert!(!out.contains("@conference{b"));
+        assert!(out.contains("@misc{c,  title={C}}"));
+        assert!(out.contains("@article{a,\n") && out.contains("@inproceedings{d,\n"));
+    }
+
+    #[test]
+    fn test_sort_entries() {
+        let bib = BibFile::new(
+            "@misc{fagin, author = {Fagin, Ronald}, year = 2001}\n\
+             @misc{esik2, author = {{\\'E}sik, Zolt{\\'a}n}, year = 2001}\n\
+             @misc{old, author = {Abramsky, Samson}, year = 1990}\n\
+             @misc{esik1, author = {\u{c9}sik, Zolt\u{e1}n}, year = 2001}\n\
+             @misc{eilenberg, author = {Eilenberg, Samuel}, year = 2001}\n\
+             @misc{dup, title = {First}, year = 2001}\n\
+             @misc{dup, title = {Second}, year = 2001}\n",
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.format_author = false;
+        options.sort_entries = true;
+        let format = |options: &FormatOptions<&mut LocalBibDb>| {
+            let mut out = String::new();
+            write_bibfile(&bib, options, &mut out).unwrap();
+            out
+        };
+        let out = format(&options);
+        let keys = out
+            .lines()
+            .filter_map(|l| l.strip_prefix("@misc{"))
+            .map(|l| l.trim_end_matches(','))
+            .collect::<Vec<_>>();
+        // accented names sort with their plain letters
+        assert_eq!(
+            keys,
+            vec!["dup", "dup", "eilenberg", "esik1", "esik2", "fagin", "old"]
+        );
+        assert!(out.find("First").unwrap() < out.find("Second").unwrap());
+        for _ in 0..5 {
+            assert_eq!(format(&options), out);
+        }
+    }
+
+    #[test]
+    fn test_prune_strings() {
+        let bib = BibFile::new(
+            "@string{acm = {ACM}}\n@string{pacm = \"Proc. \" # acm}\n@string{old = {Old}}\n@misc{a, journal = pacm}\n",
+        );
+        assert_eq!(unused_strings(&bib), HashSet::from(["old".to_string()]));
+
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        let mut out = String::new();
+        let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(stats.unused_strings, 1);
+        assert!(out.contains("@string{old"));
+
+        options.prune_strings = true;
+        let mut out = String::new();
+        let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+        assert_eq!(stats.unused_strings, 1);
+        assert!(!out.contains("@string{old") && out.contains("@string{acm"));
+        assert!(stats.is_lossless());
+    }
+
+    #[test]
+    fn test_prune_strings_of_several_files() {
+        let strings = BibFile::new("@string{acm = {ACM}}\n@string{old = {Old}}\n");
+        let entries = BibFile::new("@string{pacm = \"Proc. \" # acm}\n@misc{a, journal = pacm}\n");
+        let mut usage = StringUsage::default();
+        usage.add(&strings);
+        usage.add(&entries);
+        let used = usage.used();
+        assert_eq!(used, HashSet::from(["acm".to_string(), "pacm".to_string()]));
+
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.prune_strings = true;
+        options.used_strings = Some(used);
+        let mut out = String::new();
+        let stats = write_bibfile(&strings, &options, &mut out).unwrap();
+        assert_eq!(stats.unused_strings, 1);
+        assert!(out.contains("@string{acm") && !out.contains("@string{old"));
+        // on its own, the file uses none of its strings
+        assert_eq!(unused_strings(&strings).len(), 2);
+    }
+
+    #[test]
+    fn test_metadata_comments() {
+        let jabref = "@Comment{jabref-meta: grouping:\n0 AllEntriesGroup:;\n1 StaticGroup:Reading\\;0\\;1\\;\\;\\;\\;;\n}\n";
+        let bibdesk = "@comment{BibDesk Static Groups{\n<?xml version=\"1.0\"?>\n<plist><dict><key>keys</key><string>b,a</string></dict></plist>\n}}\n";
+        let input = format!(
+            "@misc{{b, year = 1990}}\n\n@misc{{a, year = 2020}}\n\n{}\n{}",
+            jabref, bibdesk
+        );
+        let bib = BibFile::new(&input);
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        for sort in [false, true] {
+            options.sort_entries = sort;
+            let mut out = String::new();
+            write_bibfile(&bib, &options, &mut out).unwrap();
+            // kept byte-for-byte, after the entries
+            let tail = format!("{}\n{}", jabref, bibdesk);
+            assert!(out.ends_with(&tail), "{}", out);
+            assert_eq!(out.matches("@misc").count(), 2);
+            assert_eq!(out.find("{a,").unwrap() < out.find("{b,").unwrap(), sort);
+        }
+    }
+
+    #[test]
+    fn test_convert_types_round_trip() {
+        let input = "@mastersthesis{k,\n  author = {Doe, Jane},\n  title = {T},\n  school = {S},\n  address = {Paris},\n  year = 2020,\n  month = mar,\n}\n\n";
+        let format = |input: &str, flavor| {
+            let bib = BibFile::new(input);
+            let mut db = LocalBibDb::new();
+            let mut options = FormatOptions::new(&mut db);
+            options.convert_types = Some(flavor);
+            let mut out = String::new();
+            let stats = write_bibfile(&bib, &options, &mut out).unwrap();
+            assert_eq!(stats.unconverted_entries, 0);
+            out
+        };
+        let biblatex = format(input, Flavor::Biblatex);
+        assert_eq!(
+            biblatex,
+            "@thesis{k,\n  author = {Doe, Jane},\n  title = {T},\n  school = {S},\n  location = {Paris},\n  date = {2020-03},\n  type = {mathesis},\n}\n\n"
+        );
+        assert_eq!(format(&biblatex, Flavor::Bibtex), input);
+    }
+
+    #[test]
+    fn test_truncate_value() {
+        assert_eq!(truncate_value("{short}", 10), None);
+        assert_eq!(truncate_value("2020", 2), None);
+        assert_eq!(
+            truncate_value("{a long abstract}", 10),
+            Some("{a long…}".to_string())
+        );
+        assert_eq!(
+            truncate_value("\"a long abstract\"", 10),
+            Some("\"a long…\"".to_string())
+        );
+        // never cut inside of a brace group
+        assert_eq!(
+            truncate_value("{a {long abstract} here}", 15),
+            Some("{a…}".to_string())
+        );
+        // a single word is cut at the character
+        assert_eq!(
+            truncate_value("{https://example.org/a/long/path}", 12),
+            Some("{https://exa…}".to_string())
+        );
+        assert_eq!(
+            truncate_value("{{Supercalifragilistic} words}", 8),
+            Some("{{Super}…}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ties_round_trip() {
+        let input = "@article{k,\n  author = {Knuth, D.~E. and Dupont, J.\u{202F}P. and others},\n  \
+                     title  = {Proc.~of the Workshop\u{A0}\n            on~Things, et~al.},\n}\n\n";
+        assert_eq!(format_normalized(input), input);
+        let input = "@article{k,\n  author = {D.~E. Knuth et~al.},\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@article{k,\n  author = {Knuth, D.~E. et~al.},\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_pass_through() {
+        let input = crate::read_fixture("syntax", "unclosed_entry.bib");
+        let formatted = format_normalized(&input);
+        assert!(formatted.starts_with("@article{closed,\n"));
+        assert!(formatted.ends_with(&input[input.find("@article{unclosed").unwrap()..]));
+
+        let input = crate::read_fixture("syntax", "unclosed_value.bib");
+        let broken = &input[..input.find("\n\n").unwrap()];
+        assert!(format_normalized(&input).contains(broken));
+    }
+
+    #[test]
+    fn test_changed_lines() {
+        assert_eq!(changed_lines("a\nb\n", "a\nb\n"), None);
+        assert_eq!(
+            changed_lines("a\nb\nc\n", "a\nB\nb2\nc\n").as_deref(),
+            Some("@@ line 2 @@\n-b\n+B\n+b2\n")
+        );
+        assert_eq!(
+            changed_lines("a\n", "a\n\n").as_deref(),
+            Some("@@ line 3 @@\n+\n")
+        );
+    }
+
+    #[test]
+    fn test_normalize_headers() {
+        let formatted = crate::read_fixture("syntax", "ugly_headers.formatted.bib");
+        let input = crate::read_fixture("syntax", "ugly_headers.bib");
+        assert_eq!(format_normalized(&input), formatted);
+        assert_eq!(format_normalized(&formatted), formatted);
+    }
+
+    #[test]
+    fn test_fix_editors() {
+        let input = "@proceedings{a,\n  author = {Doe, Jane},\n  title = {Proc.},\n}\n\n\
+                     @proceedings{b,\n  author = {Roe, Rick},\n  editor = {Doe, Jane},\n}\n\n\
+                     @incollection{c,\n  editor = {Doe, Jane},\n}\n\n";
+        let bib = BibFile::new(input);
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        let format = |options: &FormatOptions<_>| BibFormat { bib: &bib, options }.to_string();
+        assert_eq!(format(&options), input);
+        let fixed = input.replacen("author = {Doe", "editor = {Doe", 1);
+        options.fix_editors = true;
+        assert_eq!(format(&options), fixed);
+        options.format_author = false;
+        assert_eq!(format(&options), fixed);
+    }
+
+    #[test]
+    fn test_fix_title_case() {
+        let input = "@article{a,\n  title = {THE COMPLEXITY OF {LTL} MODEL CHECKING},\n}\n\n\
+                     @article{b,\n  title = \"the complexity of ltl: a survey\",\n}\n\n\
+                     @article{c,\n  title = {On the {NP}-hardness of Things},\n  booktitle = {PROCEEDINGS OF LICS},\n}\n\n";
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        let format = |input: &str, options: &FormatOptions<_>| {
+            let bib = BibFile::new(input);
+            BibFormat { bib: &bib, options }.to_string()
+        };
+        assert_eq!(format(input, &options), input);
+        options.fix_title_case = Some(TitleCase::Sentence);
+        let sentence = input
+            .replacen(
+                "THE COMPLEXITY OF {LTL} MODEL CHECKING",
+                "The complexity of {LTL} model checking",
+                1,
+            )
+            .replacen(
+                "the complexity of ltl: a survey",
+                "The complexity of ltl: A survey",
+                1,
+            );
+        assert_eq!(format(input, &options), sentence);
+        assert_eq!(format(&sentence, &options), sentence);
+        options.fix_title_case = Some(TitleCase::Title);
+        let title = input
+            .replacen(
+                "THE COMPLEXITY OF {LTL} MODEL CHECKING",
+                "The Complexity of {LTL} Model Checking",
+                1,
+            )
+            .replacen(
+                "the complexity of ltl: a survey",
+                "The Complexity of Ltl: A Survey",
+                1,
+            );
+        assert_eq!(format(input, &options), title);
+        assert_eq!(format(&title, &options), title);
+    }
+
+    #[test]
+    fn test_acm_pages() {
+        assert_eq!(acm_pages("17", "25"), Some("17:1--17:25".to_string()));
+        assert_eq!(acm_pages(" 3 ", "1"), Some("3:1".to_string()));
+        assert_eq!(acm_pages("17", "0"), None);
+        assert_eq!(acm_pages("17", "many"), None);
+        assert_eq!(acm_pages("", "25"), None);
+    }
+
+    #[test]
+    fn test_synthesize_pages() {
+        let input = "@article{a,\n  articleno = {17},\n  numpages = {25},\n  year = {2020},\n}\n\n\
+                     @article{b,\n  articleno = {3},\n  numpages = {1},\n}\n\n\
+                     @article{c,\n  articleno = {17},\n  numpages = {25},\n  pages = {1--25},\n}\n\n\
+                     @article{d,\n  articleno = {17},\n}\n\n";
+        let bib = BibFile::new(input);
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        let format = |options: &FormatOptions<_>| BibFormat { bib: &bib, options }.to_string();
+        assert_eq!(format(&options), input);
+        options.synthesize_pages = true;
+        // existing pages are kept, and a lone articleno is left as is
+        let synthesized = input
+            .replacen(
+                "numpages = {25},\n  year",
+                "numpages = {25},\n  pages = {17:1--17:25},\n  year",
+                1,
+            )
+            .replacen(
+                "numpages = {1},\n",
+                "numpages = {1},\n  pages = {3:1},\n",
+                1,
+            );
+        assert_eq!(format(&options), synthesized);
+    }
+
+    #[test]
+    fn test_max_authors() {
+        let input = "@article{a,\n  author = {A, A and B, B and {C and D}},\n  year = {2020},\n}\n\n\
+                     @article{b,\n  author = {A, A and B, B},\n}\n\n";
+        let truncated = input.replacen(
+            "{C and D}},\n",
+            "others},\n  authorsfull = {A, A and B, B and {C and D}},\n",
+            1,
+        );
+        let mut db = LocalBibDb::new();
+        let mut options = FormatOptions::new(&mut db);
+        options.format_author = false;
+        options.max_authors = Some(2);
+        let format = |input: &str, options: &FormatOptions<_>| {
+            let bib = BibFile::new(input);
+            BibFormat { bib: &bib, options }.to_string()
+        };
+        // the second entry has exactly the maximal number of people
+        assert_eq!(format(input, &options), truncated);
+        assert_eq!(format(&truncated, &options), truncated);
+        options.max_authors = None;
+        options.restore_authors = true;
+        assert_eq!(format(&truncated, &options), input);
+        assert_eq!(format(input, &options), input);
+    }
+
+    #[test]
+    fn test_normalize_dates() {
+        let input = "@online{k,\n  date = {April 1, 2023},\n  urldate = {13/04/2023},\n  eventdate = {03/04/2023},\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@online{k,\n  date = {2023-04-01},\n  urldate = {2023-04-13},\n  eventdate = {03/04/2023},\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_pages() {
+        let input = "@article{k,\n  pages = {pp. 101–115},\n}\n\n\
+                     @book{l,\n  pages = {xi--xv},\n}\n\n\
+                     @misc{m,\n  pages = {electronic},\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@article{k,\n  pages = {101--115},\n}\n\n\
+             @book{l,\n  pages = {xi--xv},\n}\n\n\
+             @misc{m,\n  pages = {electronic},\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_doi_field() {
+        for doi in [
+            "https://doi.org/10.1145/ABC",
+            "doi:10.1145/abc",
+            "DOI: 10.1145/ABC",
+            "10.1145/ABC",
+        ] {
+            let input = format!("@article{{k,\n  doi = {{{}}},\n}}\n", doi);
+            assert_eq!(
+                format_normalized(&input),
+                "@article{k,\n  doi = {10.1145/abc},\n}\n\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_doi_moves_resolver_url() {
+        let input = "@article{k,\n  url = {https://doi.org/10.1145/ABC},\n  year = 2020,\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@article{k,\n  doi = {10.1145/abc},\n  year = 2020,\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_doi_keeps_informative_url() {
+        let input = "@article{k,\n  url = {https://doi.org/10.1145/abc?download=1},\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@article{k,\n  url = {https://doi.org/10.1145/abc?download=1},\n  doi = {10.1145/abc},\n}\n\n"
+        );
+        let input = "@article{k,\n  doi = {10.1145/abc},\n  url = {https://example.com},\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@article{k,\n  doi = {10.1145/abc},\n  url = {https://example.com},\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_doi_drops_redundant_url() {
+        let input = "@article{k,\n  doi = {doi:10.1145/abc},\n  url = {https://dx.doi.org/10.1145/ABC},\n}\n";
+        assert_eq!(
+            format_normalized(input),
+            "@article{k,\n  doi = {10.1145/abc},\n}\n\n"
+        );
     }
 }
@@ -0,0 +1,142 @@
+//! This file is responsible for the comment that `bibadac format
+//! --to-file` writes at the top of the files it creates, recording
+//! how they were produced:
+//!
+//! ```text
+//! % Generated by bibadac 0.1.0 on 2024-03-01 from refs.bib with options: sort-entries, keep-field=author,title
+//! ```
+//!
+//! The header of a formatted file is recognized, and replaced when
+//! the file is formatted again, instead of stacking up. It is never
+//! written to the standard output, so that pipelines stay clean.
+use serde_json::Value;
+
+/// The start of the header, which is how it is recognized.
+pub const HEADER_PREFIX: &str = "% Generated by bibadac ";
+
+/// The options, as they are given on the command line: the name of
+/// the flags that are set, and the values of the other options,
+/// e.g. `sort-entries, keep-field=author,title`.
+pub fn describe_options(options: &[(String, Value)]) -> String {
+    options
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = name.replace('_', "-");
+            let value = match value {
+                Value::Bool(true) => return Some(name),
+                Value::Bool(false) | Value::Null => return None,
+                Value::String(s) => s.clone(),
+                Value::Array(values) => values
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => s.clone(),
+                        // e.g. the (field, length) pairs of --truncate-field
+                        Value::Array(pair) => pair
+                            .iter()
+                            .map(|p| p.as_str().map_or(p.to_string(), str::to_string))
+                            .collect::<Vec<_>>()
+                            .join("="),
+                        v => v.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+                value => value.to_string(),
+            };
+            Some(format!("{}={}", name, value))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The header of a file formatted from `input` on `date`.
+pub fn header(version: &str, date: &str, input: &str, options: &[(String, Value)]) -> String {
+    let options = describe_options(options);
+    let options = if options.is_empty() {
+        "the default options".to_string()
+    } else {
+        format!("options: {}", options)
+    };
+    format!(
+        "{}{} on {} from {} with {}\n",
+        HEADER_PREFIX, version, date, input, options
+    )
+}
+
+/// The content without its header (and the blank line after it), if any.
+pub fn strip_header(content: &str) -> &str {
+    if !content.starts_with(HEADER_PREFIX) {
+        return content;
+    }
+    let rest = content.find('\n').map_or("", |end| &content[end + 1..]);
+    rest.strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))
+        .unwrap_or(rest)
+}
+
+/// The content with the given header in place of its own, if any.
+pub fn replace_header(content: &str, header: Option<&str>) -> String {
+    let content = strip_header(content);
+    match header {
+        Some(header) => format!("{}\n{}", header, content),
+        None => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Value {
+        Value::Array(
+            values
+                .iter()
+                .map(|v| Value::String(v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_describe_options() {
+        let options = vec![
+            ("sort_entries".to_string(), Value::Bool(true)),
+            ("normalize".to_string(), Value::Bool(false)),
+            ("keep_field".to_string(), strings(&["author", "title"])),
+            (
+                "truncate_field".to_string(),
+                Value::Array(vec![Value::Array(vec![
+                    Value::String("abstract".into()),
+                    Value::from(100),
+                ])]),
+            ),
+            ("convert_to".to_string(), Value::String("biblatex".into())),
+        ];
+        assert_eq!(
+            describe_options(&options),
+            "sort-entries, keep-field=author,title, truncate-field=abstract=100, convert-to=biblatex"
+        );
+        assert_eq!(describe_options(&[]), "");
+    }
+
+    #[test]
+    fn test_replace_header() {
+        let options = vec![("sort_entries".to_string(), Value::Bool(true))];
+        let header = header("0.1.0", "2024-03-01", "refs.bib", &options);
+        assert_eq!(
+            header,
+            "% Generated by bibadac 0.1.0 on 2024-03-01 from refs.bib with options: sort-entries\n"
+        );
+        let content = "@misc{a,\n}\n";
+        let formatted = replace_header(content, Some(&header));
+        assert_eq!(formatted, format!("{}\n{}", header, content));
+
+        // formatting again replaces the header, rather than stacking them
+        let other = super::header("0.2.0", "2024-03-02", "refs.new.bib", &[]);
+        let again = replace_header(&formatted, Some(&other));
+        assert_eq!(again, format!("{}\n{}", other, content));
+        assert!(other.ends_with("with the default options\n"));
+        assert_eq!(replace_header(&again, None), content);
+        // a comment of the user is kept
+        let commented = "% my references\n@misc{a,\n}\n";
+        assert_eq!(strip_header(commented), commented);
+    }
+}
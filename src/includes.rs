@@ -0,0 +1,449 @@
+//! This file is responsible for the include directives, which split a
+//! bibliography across several files:
+//!
+//! ```bibtex
+//! @comment{bibadac-include: topics/logic.bib}
+//! ```
+//!
+//! The path is relative to the including file. `check` and `setup` load
+//! the included files (recursively) along with the files they are given,
+//! and `check` lints the files of an include tree as a single
+//! bibliography, so that a key defined twice in two files, a `@string`
+//! or a crossref parent of another file are seen, while the messages
+//! still point to the file and the line they are about. `format` leaves
+//! the directives as they are, unless asked to replace them by the
+//! included entries (`--inline-includes`).
+//!
+//! A file including itself, even through other files, is an error.
+//! A file included twice in the same tree is only read once.
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use crate::bibliography::{Bibliography, Diagnostic, LintConfig, Location};
+use crate::linter::LintMessage;
+use crate::metadata::comment_len;
+use crate::waivers::parse_directive;
+
+/// The directive of a `@comment` including a file.
+pub const INCLUDE_DIRECTIVE: &str = "include";
+
+/// The deepest nesting of includes.
+pub const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IncludeError {
+    #[error("{0}: cannot include {1}: {2}")]
+    Unreadable(String, String, String),
+    #[error("include cycle: {0}")]
+    Cycle(String),
+    #[error("{0}: includes nested deeper than {MAX_DEPTH} files")]
+    TooDeep(String),
+}
+
+/// A `@comment{bibadac-include: path}` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeDirective {
+    /// The path as written
+    pub path: String,
+    /// The whole `@comment`
+    pub span: Range<usize>,
+    /// From 1
+    pub line: usize,
+}
+
+/// A file of an include tree.
+#[derive(Debug, Clone)]
+pub struct Included {
+    pub path: PathBuf,
+    pub bib: Bibliography,
+    /// 1 for the files included by the root
+    pub depth: usize,
+    /// The file and the line of the directive
+    pub included_from: (PathBuf, usize),
+}
+
+/// The included path, given the text starting at the `@` of a comment.
+pub fn include_path(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix('@')?;
+    if !rest.get(..7)?.eq_ignore_ascii_case("comment") {
+        return None;
+    }
+    let body = rest[7..].trim_start().strip_prefix('{')?;
+    let (directive, path) = parse_directive(&body[..body.find('}')?])?;
+    Some(path).filter(|p| directive == INCLUDE_DIRECTIVE && !p.is_empty())
+}
+
+/// The include directives of a bibliography.
+pub fn include_directives(bib: &Bibliography) -> Vec<IncludeDirective> {
+    let source = bib.source();
+    let starts = bib.with_bibfile(|file| {
+        let mut cursor = file.tree.root_node().walk();
+        file.tree
+            .root_node()
+            .children(&mut cursor)
+            .map(|node| node.start_byte())
+            .collect::<Vec<_>>()
+    });
+    let mut directives: Vec<IncludeDirective> = vec![];
+    for start in starts {
+        if directives.last().is_some_and(|d| start < d.span.end) {
+            continue;
+        }
+        if let Some(path) = include_path(&source[start..]) {
+            directives.push(IncludeDirective {
+                path: path.to_string(),
+                span: start..start + comment_len(&source[start..]),
+                line: Location::of_span(source, start..start).line,
+            });
+        }
+    }
+    directives
+}
+
+/// The path of a file included by `from`.
+pub fn resolve(from: &Path, path: &str) -> PathBuf {
+    normalize(&from.parent().unwrap_or(Path::new("")).join(path))
+}
+
+/// The path without `.` and `..`, to compare the paths of the files.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Where a directive is, as `file:line`.
+fn position(path: &Path, directive: &IncludeDirective) -> String {
+    format!("{}:{}", path.display(), directive.line)
+}
+
+/// The files included by `root`, recursively, in the order of their
+/// directives (depth first), read with `read`.
+pub fn load_includes(
+    root: &Path,
+    bib: &Bibliography,
+    mut read: impl FnMut(&Path) -> std::io::Result<Bibliography>,
+) -> Result<Vec<Included>, IncludeError> {
+    let mut stack = vec![normalize(root)];
+    let mut found = vec![];
+    visit(bib, &mut stack, &mut found, &mut read)?;
+    Ok(found)
+}
+
+fn visit(
+    bib: &Bibliography,
+    stack: &mut Vec<PathBuf>,
+    found: &mut Vec<Included>,
+    read: &mut impl FnMut(&Path) -> std::io::Result<Bibliography>,
+) -> Result<(), IncludeError> {
+    let from = stack.last().cloned().unwrap_or_default();
+    for directive in include_directives(bib) {
+        let path = resolve(&from, &directive.path);
+        if let Some(i) = stack.iter().position(|p| *p == path) {
+            let chain = stack[i..]
+                .iter()
+                .chain([&path])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(IncludeError::Cycle(chain.join(" -> ")));
+        }
+        if found.iter().any(|f: &Included| f.path == path) {
+            continue;
+        }
+        if stack.len() > MAX_DEPTH {
+            return Err(IncludeError::TooDeep(position(&from, &directive)));
+        }
+        let included = read(&path).map_err(|e| {
+            IncludeError::Unreadable(
+                position(&from, &directive),
+                path.display().to_string(),
+                e.to_string(),
+            )
+        })?;
+        found.push(Included {
+            path: path.clone(),
+            bib: included.clone(),
+            depth: stack.len(),
+            included_from: (from.clone(), directive.line),
+        });
+        stack.push(path);
+        visit(&included, stack, found, read)?;
+        stack.pop();
+    }
+    Ok(())
+}
+
+/// The source of the bibliography, where every directive is replaced by
+/// the source of the included file, itself inlined. The directives of a
+/// file that is already inlined are removed.
+pub fn inline_includes(
+    root: &Path,
+    bib: &Bibliography,
+    mut read: impl FnMut(&Path) -> std::io::Result<Bibliography>,
+) -> Result<String, IncludeError> {
+    let mut stack = vec![normalize(root)];
+    let mut seen = vec![];
+    inline(bib, &mut stack, &mut seen, &mut read)
+}
+
+fn inline(
+    bib: &Bibliography,
+    stack: &mut Vec<PathBuf>,
+    seen: &mut Vec<PathBuf>,
+    read: &mut impl FnMut(&Path) -> std::io::Result<Bibliography>,
+) -> Result<String, IncludeError> {
+    let from = stack.last().cloned().unwrap_or_default();
+    let source = bib.source();
+    let mut out = String::new();
+    let mut copied = 0;
+    for directive in include_directives(bib) {
+        out.push_str(&source[copied..directive.span.start]);
+        copied = directive.span.end;
+        let path = resolve(&from, &directive.path);
+        if let Some(i) = stack.iter().position(|p| *p == path) {
+            let chain = stack[i..]
+                .iter()
+                .chain([&path])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(IncludeError::Cycle(chain.join(" -> ")));
+        }
+        if seen.contains(&path) {
+            continue;
+        }
+        if stack.len() > MAX_DEPTH {
+            return Err(IncludeError::TooDeep(position(&from, &directive)));
+        }
+        let included = read(&path).map_err(|e| {
+            IncludeError::Unreadable(
+                position(&from, &directive),
+                path.display().to_string(),
+                e.to_string(),
+            )
+        })?;
+        seen.push(path.clone());
+        stack.push(path);
+        out.push_str(inline(&included, stack, seen, read)?.trim_end());
+        stack.pop();
+    }
+    out.push_str(&source[copied..]);
+    Ok(out)
+}
+
+/// The diagnostics of the files of an include tree, linted as a single
+/// bibliography, by file. A diagnostic about several files (e.g. a key
+/// defined in two of them) is given to each of them, with its locations
+/// in that file. The files are linted one by one when one of them has a
+/// syntax error, which could swallow the beginning of the next one.
+pub fn lint_together(bibs: &[&Bibliography], config: &LintConfig) -> Vec<Vec<Diagnostic>> {
+    if bibs.iter().any(|bib| bib.first_syntax_error().is_some()) {
+        return bibs.iter().map(|bib| bib.lint(config)).collect();
+    }
+    let mut source = String::new();
+    let mut starts = vec![];
+    for bib in bibs {
+        starts.push(source.len());
+        source.push_str(bib.source());
+        if !source.ends_with('\n') {
+            source.push('\n');
+        }
+    }
+    let mut diagnostics = vec![vec![]; bibs.len()];
+    // the linter only sees the decoded text
+    for (bib, diagnostics) in bibs.iter().zip(diagnostics.iter_mut()) {
+        if let Some(encoding) = bib.encoding() {
+            let msg = LintMessage::NonUtf8Encoding(encoding.to_string());
+            let diagnostic = Diagnostic {
+                severity: config.severity(&msg, None),
+                msg,
+                loc: vec![Location::of_span(bib.source(), 0..0)],
+                fixable: false,
+            };
+            if !config.concise || diagnostic.is_crucial() {
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+    for diagnostic in Bibliography::from(source).lint(config) {
+        // the locations, by file, in the order of their first one
+        let mut by_file: Vec<(usize, Vec<Location>)> = vec![];
+        for loc in diagnostic.loc.iter() {
+            let i = starts.partition_point(|start| *start <= loc.start_byte) - 1;
+            let len = bibs[i].source().len();
+            let start = (loc.start_byte - starts[i]).min(len);
+            let end = (loc.end_byte - starts[i]).clamp(start, len);
+            let loc = Location::of_span(bibs[i].source(), start..end);
+            match by_file.iter_mut().find(|(file, _)| *file == i) {
+                Some((_, locs)) => locs.push(loc),
+                None => by_file.push((i, vec![loc])),
+            }
+        }
+        if by_file.is_empty() {
+            diagnostics[0].push(diagnostic);
+            continue;
+        }
+        for (i, loc) in by_file {
+            diagnostics[i].push(Diagnostic {
+                loc,
+                ..diagnostic.clone()
+            });
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Reads the files from a map, by path.
+    fn reader(
+        files: &[(&str, &str)],
+    ) -> impl FnMut(&Path) -> std::io::Result<Bibliography> + use<> {
+        let files = files
+            .iter()
+            .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |path: &Path| {
+            files
+                .get(path)
+                .map(|content| Bibliography::from(content.clone()))
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+        }
+    }
+
+    #[test]
+    fn test_include_path() {
+        assert_eq!(
+            include_path("@comment{bibadac-include: topics/logic.bib}"),
+            Some("topics/logic.bib")
+        );
+        assert_eq!(
+            include_path("@Comment { bibadac-include:a.bib }\n@misc{a}"),
+            Some("a.bib")
+        );
+        assert_eq!(include_path("@comment{bibadac-include: }"), None);
+        assert_eq!(include_path("@comment{jabref-meta: x}"), None);
+        assert_eq!(include_path("@misc{bibadac-include: a.bib}"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve(Path::new("refs.bib"), "a.bib"),
+            PathBuf::from("a.bib")
+        );
+        assert_eq!(
+            resolve(Path::new("bib/refs.bib"), "./topics/../a.bib"),
+            PathBuf::from("bib/a.bib")
+        );
+        assert_eq!(
+            resolve(Path::new("refs.bib"), "../a.bib"),
+            PathBuf::from("../a.bib")
+        );
+    }
+
+    #[test]
+    fn test_load_includes() {
+        let root = Bibliography::from(
+            "@comment{bibadac-include: topics/a.bib}\n\
+             @comment{bibadac-include: b.bib}\n"
+                .to_string(),
+        );
+        let read = reader(&[
+            (
+                "topics/a.bib",
+                "@comment{bibadac-include: ../b.bib}\n@misc{a}\n",
+            ),
+            ("b.bib", "@misc{b}\n"),
+        ]);
+        let included = load_includes(Path::new("refs.bib"), &root, read).unwrap();
+        let files = included
+            .iter()
+            .map(|f| (f.path.display().to_string(), f.depth, f.included_from.1))
+            .collect::<Vec<_>>();
+        // b.bib is only read once, from the first directive that includes it
+        assert_eq!(
+            files,
+            vec![
+                ("topics/a.bib".to_string(), 1, 1),
+                ("b.bib".to_string(), 2, 1)
+            ]
+        );
+
+        let missing = load_includes(Path::new("refs.bib"), &root, reader(&[]));
+        assert!(matches!(missing, Err(IncludeError::Unreadable(..))));
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let root = Bibliography::from("@comment{bibadac-include: a.bib}\n".to_string());
+        let read = reader(&[
+            ("a.bib", "@comment{bibadac-include: b.bib}\n"),
+            ("b.bib", "@comment{bibadac-include: refs.bib}\n"),
+        ]);
+        let error = load_includes(Path::new("./refs.bib"), &root, read).unwrap_err();
+        assert_eq!(
+            error,
+            IncludeError::Cycle("refs.bib -> a.bib -> b.bib -> refs.bib".to_string())
+        );
+        let read = reader(&[("a.bib", "@comment{bibadac-include: a.bib}\n")]);
+        assert!(inline_includes(Path::new("refs.bib"), &root, read).is_err());
+    }
+
+    #[test]
+    fn test_inline_includes() {
+        let root = Bibliography::from(
+            "@misc{r}\n\
+             @comment{bibadac-include: a.bib}\n\
+             @comment{bibadac-include: b.bib}\n\
+             @misc{s}\n"
+                .to_string(),
+        );
+        let read = reader(&[
+            ("a.bib", "@misc{a}\n@comment{bibadac-include: b.bib}\n"),
+            ("b.bib", "@misc{b}\n"),
+        ]);
+        let inlined = inline_includes(Path::new("refs.bib"), &root, read).unwrap();
+        assert_eq!(inlined, "@misc{r}\n@misc{a}\n@misc{b}\n\n@misc{s}\n");
+    }
+
+    #[test]
+    fn test_lint_together() {
+        let root = Bibliography::from(
+            "@string{ieee = \"IEEE\"}\n\
+             @comment{bibadac-include: a.bib}\n\
+             @misc{dup, title = {A}, author = {Doe, Jane}, year = 2020}\n"
+                .to_string(),
+        );
+        let included = Bibliography::from(
+            "\n@misc{dup, title = {B}, author = {Roe, Rick}, year = 2021, publisher = ieee}\n"
+                .to_string(),
+        );
+        let diagnostics = lint_together(&[&root, &included], &LintConfig::default());
+        let duplicate = |diagnostics: &[Diagnostic]| {
+            diagnostics
+                .iter()
+                .find(|d| matches!(&d.msg, LintMessage::DuplicateKey { key, .. } if key == "dup"))
+                .map(|d| d.loc.iter().map(|l| l.line).collect::<Vec<_>>())
+        };
+        // the key is reported in both files, at their own lines
+        assert_eq!(duplicate(&diagnostics[0]), Some(vec![3]));
+        assert_eq!(duplicate(&diagnostics[1]), Some(vec![2]));
+        assert_eq!(diagnostics[1][0].loc[0].start_byte, 1);
+    }
+}
@@ -0,0 +1,34 @@
+//! This file is responsible for stopping long commands (`bibadac check`)
+//! cleanly on Ctrl-C: the first Ctrl-C only sets a flag, that the command
+//! checks between two files (and between two dois with `--online`) to stop
+//! and report what it has done so far, and a second Ctrl-C exits right away.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The exit code of an interrupted command (128 + SIGINT).
+pub const EXIT_CODE: u8 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Listens to Ctrl-C on a thread of its own.
+pub fn install() -> std::io::Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::spawn(move || {
+        rt.block_on(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                    std::process::exit(EXIT_CODE.into());
+                }
+                eprintln!(
+                    "Interrupted, finishing the current file (press Ctrl-C again to exit now)"
+                );
+            }
+        })
+    });
+    Ok(())
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,377 @@
+//! This file is responsible for generating the keys of the entries
+//! from their fields (`bibadac keygen`), following a pattern such as
+//! `{author:last:lower}{year}{title:word1}`.
+//!
+//! A placeholder `{field:modifier:...}` is replaced by the value of the
+//! field, without its braces and LaTeX accents, transformed by each
+//! modifier in turn:
+//!
+//! - `last`: the last name of the first person (the default for the
+//!   `author` and `editor` fields)
+//! - `lower`, `upper`: the case of the value
+//! - `wordN`: the first N words, ignoring short words such as `the`
+//!   (`word` is `word1`)
+//! - `hashN`: the first N hexadecimal digits of the sha256 of the value
+//!
+//! The result is transliterated to ASCII (`Gödel` is `Godel`, `Łukasiewicz`
+//! is `Lukasiewicz`), keeping only letters and digits, so that keys are
+//! safe for every BibTeX implementation. The text outside the placeholders
+//! is copied as is.
+use std::collections::HashMap;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// The words skipped by `wordN`.
+pub const STOP_WORDS: [&str; 15] = [
+    "a", "an", "and", "at", "by", "for", "from", "in", "of", "on", "or", "the", "to", "with", "via",
+];
+
+/// The fields whose placeholders are the last name of the first person by default.
+const NAME_FIELDS: [&str; 2] = ["author", "editor"];
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum KeyGenError {
+    #[error("unclosed placeholder at position {0}")]
+    Unclosed(usize),
+    #[error("unexpected `}}` at position {0}")]
+    UnexpectedBrace(usize),
+    #[error("empty placeholder at position {0}")]
+    EmptyPlaceholder(usize),
+    #[error("unknown modifier `{0}`")]
+    UnknownModifier(String),
+    #[error("no {0} field")]
+    MissingField(String),
+    #[error("the generated key is empty")]
+    EmptyKey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Last,
+    Lower,
+    Upper,
+    Words(usize),
+    Hash(usize),
+}
+
+impl std::str::FromStr for Modifier {
+    type Err = KeyGenError;
+
+    fn from_str(s: &str) -> Result<Self, KeyGenError> {
+        let count = |rest: &str, default: usize| match rest {
+            "" => Some(default),
+            n => n.parse::<usize>().ok().filter(|n| *n > 0),
+        };
+        let modifier = match s {
+            "last" => Some(Modifier::Last),
+            "lower" => Some(Modifier::Lower),
+            "upper" => Some(Modifier::Upper),
+            _ => match (s.strip_prefix("word"), s.strip_prefix("hash")) {
+                (Some(n), _) => count(n, 1).map(Modifier::Words),
+                (_, Some(n)) => count(n, 8).filter(|n| *n <= 64).map(Modifier::Hash),
+                _ => None,
+            },
+        };
+        modifier.ok_or_else(|| KeyGenError::UnknownModifier(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Literal(String),
+    Field {
+        /// The lowercase name of the field
+        name: String,
+        modifiers: Vec<Modifier>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub parts: Vec<Part>,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self, KeyGenError> {
+        let mut parts = vec![];
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            let position = pattern.len() - rest.len();
+            let Some(placeholder) = rest.strip_prefix('{') else {
+                let end = rest.find(['{', '}']).unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(KeyGenError::UnexpectedBrace(position));
+                }
+                parts.push(Part::Literal(rest[..end].to_string()));
+                rest = &rest[end..];
+                continue;
+            };
+            let Some(end) = placeholder.find('}') else {
+                return Err(KeyGenError::Unclosed(position));
+            };
+            let mut names = placeholder[..end].split(':').map(str::trim);
+            let name = names.next().unwrap_or("").to_lowercase();
+            if name.is_empty() || name.contains('{') {
+                return Err(if name.contains('{') {
+                    KeyGenError::Unclosed(position)
+                } else {
+                    KeyGenError::EmptyPlaceholder(position)
+                });
+            }
+            let modifiers = names.map(str::parse).collect::<Result<Vec<_>, _>>()?;
+            parts.push(Part::Field { name, modifiers });
+            rest = &placeholder[end + 1..];
+        }
+        Ok(Pattern { parts })
+    }
+
+    /// The key of an entry, given its fields by lowercase name.
+    pub fn generate(&self, fields: &HashMap<String, String>) -> Result<String, KeyGenError> {
+        let mut key = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Literal(text) => key.push_str(text),
+                Part::Field { name, modifiers } => {
+                    let value = fields
+                        .get(name)
+                        .map(|value| plain_text(value))
+                        .filter(|value| !value.is_empty())
+                        .ok_or_else(|| KeyGenError::MissingField(name.clone()))?;
+                    let mut value = value.trim().to_string();
+                    if NAME_FIELDS.contains(&name.as_str()) && !modifiers.contains(&Modifier::Last)
+                    {
+                        value = apply(Modifier::Last, &value);
+                    }
+                    for modifier in modifiers {
+                        value = apply(*modifier, &value);
+                    }
+                    key.push_str(&ascii(&value));
+                }
+            }
+        }
+        if key.is_empty() {
+            return Err(KeyGenError::EmptyKey);
+        }
+        Ok(key)
+    }
+}
+
+/// The key of an entry, given its fields by lowercase name and a pattern
+/// such as `{author:last:lower}{year}{title:word1}`.
+pub fn generate_key(
+    fields: &HashMap<String, String>,
+    pattern: &str,
+) -> Result<String, KeyGenError> {
+    Pattern::parse(pattern)?.generate(fields)
+}
+
+fn apply(modifier: Modifier, value: &str) -> String {
+    match modifier {
+        Modifier::Last => {
+            let first = value.split(" and ").next().unwrap_or("").trim();
+            match first.split_once(',') {
+                Some((last, _)) => last.trim().to_string(),
+                None => first.split_whitespace().last().unwrap_or("").to_string(),
+            }
+        }
+        Modifier::Lower => value.to_lowercase(),
+        Modifier::Upper => value.to_uppercase(),
+        Modifier::Words(n) => value
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty() && !STOP_WORDS.contains(&w.to_lowercase().as_str()))
+            .take(n)
+            .collect(),
+        Modifier::Hash(n) => {
+            use sha2::Digest;
+            let hash = format!("{:x}", sha2::Sha256::digest(value.as_bytes()));
+            hash[..n.min(hash.len())].to_string()
+        }
+    }
+}
+
+/// The text of a field value, without its braces, quotes, and LaTeX
+/// accents (`G{\"o}del` is `Godel`). The commands of letters such as
+/// `\o` or `\ss` are kept as their letters, and the others are dropped.
+pub fn plain_text(value: &str) -> String {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    let mut plain = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '\\' => {
+                let mut command = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    command.push(c);
+                }
+                match command.as_str() {
+                    // an accent, or an escaped character such as \&
+                    "" => {
+                        chars.next_if(|c| !c.is_alphanumeric() && *c != '{');
+                    }
+                    "o" | "O" | "l" | "L" | "ss" | "ae" | "AE" | "oe" | "OE" | "aa" | "AA"
+                    | "i" | "j" => plain.push_str(&command),
+                    // a one-letter accent such as \c{c} or \H{o}
+                    c if c.len() == 1 => {}
+                    _ => plain.push(' '),
+                }
+            }
+            _ => plain.push(c),
+        }
+    }
+    plain
+}
+
+/// The letters and digits of a text, transliterated to ASCII.
+pub fn ascii(text: &str) -> String {
+    text.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(|c| {
+            let s = match c {
+                'ß' => "ss",
+                'æ' => "ae",
+                'Æ' => "AE",
+                'œ' => "oe",
+                'Œ' => "OE",
+                'ø' => "o",
+                'Ø' => "O",
+                'ł' => "l",
+                'Ł' => "L",
+                'đ' | 'ð' => "d",
+                'Đ' | 'Ð' => "D",
+                'þ' => "th",
+                'Þ' => "Th",
+                'ı' => "i",
+                _ => "",
+            };
+            if s.is_empty() {
+                vec![c]
+            } else {
+                s.chars().collect()
+            }
+        })
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(
+            Pattern::parse("{Author:last:lower}-{year}").unwrap().parts,
+            vec![
+                Part::Field {
+                    name: "author".to_string(),
+                    modifiers: vec![Modifier::Last, Modifier::Lower],
+                },
+                Part::Literal("-".to_string()),
+                Part::Field {
+                    name: "year".to_string(),
+                    modifiers: vec![],
+                },
+            ]
+        );
+        assert_eq!(
+            Pattern::parse("{title:word2}{doi:hash}").unwrap().parts[1],
+            Part::Field {
+                name: "doi".to_string(),
+                modifiers: vec![Modifier::Hash(8)],
+            }
+        );
+        assert_eq!(Pattern::parse("{year"), Err(KeyGenError::Unclosed(0)));
+        assert_eq!(Pattern::parse("a{b{c}}"), Err(KeyGenError::Unclosed(1)));
+        assert_eq!(Pattern::parse("a}"), Err(KeyGenError::UnexpectedBrace(1)));
+        assert_eq!(
+            Pattern::parse("{:lower}"),
+            Err(KeyGenError::EmptyPlaceholder(0))
+        );
+        assert_eq!(
+            Pattern::parse("{year:shout}"),
+            Err(KeyGenError::UnknownModifier("shout".to_string()))
+        );
+        assert_eq!(
+            Pattern::parse("{title:word0}"),
+            Err(KeyGenError::UnknownModifier("word0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(
+            apply(Modifier::Last, "Knuth, Donald E. and Other, A."),
+            "Knuth"
+        );
+        assert_eq!(
+            apply(Modifier::Last, "Donald E. Knuth and A. Other"),
+            "Knuth"
+        );
+        assert_eq!(apply(Modifier::Lower, "Knuth"), "knuth");
+        assert_eq!(apply(Modifier::Upper, "Knuth"), "KNUTH");
+        assert_eq!(
+            apply(Modifier::Words(1), "The Art of Computer Programming"),
+            "Art"
+        );
+        assert_eq!(
+            apply(Modifier::Words(3), "The Art of Computer Programming"),
+            "ArtComputerProgramming"
+        );
+        assert_eq!(apply(Modifier::Hash(6), "10.1145/3290383"), "5eb21c");
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(plain_text("{G{\\\"o}del}"), "Godel");
+        assert_eq!(plain_text("\"Erd\\H{o}s\""), "Erdos");
+        assert_eq!(plain_text("{\\L}ukasiewicz"), "Lukasiewicz");
+        assert_eq!(plain_text("{\\emph{Deep} learning}"), " Deep learning");
+        assert_eq!(
+            ascii("Gödel, Straße, Łukasiewicz"),
+            "GodelStrasseLukasiewicz"
+        );
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let entry = fields(&[
+            ("author", "{Ésik, Zoltán and Kuich, Werner}"),
+            ("year", "{2020}"),
+            ("title", "{On the {Theory} of Semirings}"),
+            ("doi", "{10.1145/3290383}"),
+        ]);
+        assert_eq!(
+            generate_key(&entry, "{author}{year}{title:word1}").unwrap(),
+            "Esik2020Theory"
+        );
+        assert_eq!(
+            generate_key(&entry, "{author:last:lower}:{year}:{doi:hash6}").unwrap(),
+            "esik:2020:5eb21c"
+        );
+        assert_eq!(
+            generate_key(&entry, "{editor}{year}"),
+            Err(KeyGenError::MissingField("editor".to_string()))
+        );
+        assert_eq!(
+            generate_key(&fields(&[("title", "{}")]), "{title}"),
+            Err(KeyGenError::MissingField("title".to_string()))
+        );
+        assert_eq!(
+            generate_key(&fields(&[("title", "{$\\alpha$}")]), "{title}"),
+            Err(KeyGenError::EmptyKey)
+        );
+    }
+}
@@ -1,8 +1,104 @@
+pub mod against;
+pub mod append;
+pub mod arxiv_feed;
 pub mod arxiv_identifiers;
+pub mod assertions;
 pub mod author_format;
+pub mod authors;
+pub mod baseline;
 pub mod bibdb;
+pub mod bibliography;
 pub mod bibtex;
 pub mod bibtex_spec;
+pub mod cache;
+pub mod collation;
+pub mod conflicts;
+pub mod convert;
+pub mod datacite;
+pub mod dates;
+pub mod doctor;
+pub mod doi_identifiers;
+pub mod doi_targets;
+pub mod encoding;
+pub mod eprints;
+pub mod extract;
+pub mod file_index;
+pub mod field_order;
+pub mod field_patterns;
+pub mod filter;
+pub mod fixes;
 pub mod format;
+pub mod header;
+pub mod includes;
+pub mod interrupt;
+pub mod keys;
+pub mod line_endings;
 pub mod linter;
+pub mod logging;
+pub mod manifest;
+pub mod markdown;
+pub mod merge;
+pub mod metadata;
+pub mod ndjson;
+pub mod network;
+pub mod online;
+pub mod pages;
+pub mod pdf_identifiers;
+pub mod preprints;
+pub mod provenance;
+pub mod profiles;
+pub mod progress;
+pub mod registration;
+pub mod related;
+pub mod report;
+pub mod resolver;
+pub mod results_cache;
+pub mod score;
+pub mod scholar;
 pub mod setup;
+pub mod severity;
+pub mod similar_keys;
+#[cfg(feature = "spellcheck")]
+pub mod spellcheck;
+pub mod split;
+pub mod sync;
+pub mod timings;
+pub mod title_case;
+pub mod trend;
+pub mod values;
+pub mod verification;
+pub mod waivers;
+
+pub use bibliography::Bibliography;
+
+/// The path of a file of `tests/fixtures`, for the unit tests.
+#[cfg(test)]
+pub(crate) fn fixture(path: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(path)
+}
+
+/// The contents of the file `name` of the fixture directory `dir`, for the
+/// unit tests.
+#[cfg(test)]
+pub(crate) fn read_fixture(dir: &str, name: &str) -> String {
+    String::from_utf8(read_fixture_bytes(dir, name)).unwrap()
+}
+
+/// The bytes of the file `name` of the fixture directory `dir`, for the
+/// fixtures that are not in UTF-8.
+#[cfg(test)]
+pub(crate) fn read_fixture_bytes(dir: &str, name: &str) -> Vec<u8> {
+    std::fs::read(fixture(dir).join(name)).unwrap()
+}
+
+/// An empty directory of the system temporary directory, for the unit
+/// tests, named after `name` and the process.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("bibadac-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
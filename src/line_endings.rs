@@ -0,0 +1,164 @@
+//! This file is responsible for the line endings of the input files.
+//!
+//! The files edited on Windows end their lines with `\r\n` (CRLF), and
+//! some files mix both endings. Their content is read with `\n` only
+//! (see `normalize`), so that the parser, the linter, and the formatter
+//! never see a `\r`, and the formatted files are written back with the
+//! ending that most of their lines had (see `detect`), unless `format
+//! --line-ending` asks for another one.
+//!
+//! Where the `\r\n` were is recorded (see `LineEndings`), so that the
+//! offsets of the JSON reports are the ones of the bytes of the file,
+//! and that the fixes of `check --fix` keep the ending of every line.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// The ending of the platform.
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// The ending of most lines of the text, `Lf` for a tie
+    /// (or a single line).
+    pub fn detect(text: &str) -> Self {
+        let newlines = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        if crlf > newlines - crlf {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// The text (with `\n` endings) with this ending.
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            LineEnding::Lf => text.to_string(),
+            LineEnding::Crlf => text.replace('\n', "\r\n"),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// The lines of a text that ended with `\r\n`, as the offsets of their
+/// `\n` in the text once normalized (see `normalize`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineEndings {
+    crlf: Vec<usize>,
+}
+
+impl LineEndings {
+    /// The endings of a text, before it is normalized.
+    pub fn of(text: &str) -> Self {
+        LineEndings {
+            crlf: text
+                .match_indices("\r\n")
+                .enumerate()
+                .map(|(i, (at, _))| at - i)
+                .collect(),
+        }
+    }
+
+    /// The offset in the text of an offset of the normalized text.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        offset + self.crlf.partition_point(|newline| *newline < offset)
+    }
+
+    /// The ending of the line of the normalized text whose `\n` is at
+    /// the offset `newline`.
+    pub fn ending_at(&self, newline: usize) -> LineEnding {
+        if self.crlf.binary_search(&newline).is_ok() {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// The normalized text with the ending of every line put back.
+    pub fn restore(&self, normalized: &str) -> String {
+        let mut text = String::with_capacity(normalized.len() + self.crlf.len());
+        let mut last = 0;
+        for newline in self.crlf.iter().copied() {
+            text.push_str(&normalized[last..newline]);
+            text.push('\r');
+            last = newline;
+        }
+        text.push_str(&normalized[last..]);
+        text
+    }
+}
+
+/// The text with `\n` in place of its `\r\n`. The lone `\r` are
+/// kept, as they are not line endings for the parser either.
+pub fn normalize(text: String) -> String {
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(LineEnding::detect("@misc{a,}\n@misc{b,}\n"), LineEnding::Lf);
+        assert_eq!(
+            LineEnding::detect("@misc{a,}\r\n@misc{b,}\r\n"),
+            LineEnding::Crlf
+        );
+        // the dominant ending of mixed files
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\r\nb\nc\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("@misc{a,}"), LineEnding::Lf);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let text = "@misc{a,\r\n  title = {A\r\n  B},\r\n}\r\n";
+        let normalized = normalize(text.to_string());
+        assert_eq!(normalized, "@misc{a,\n  title = {A\n  B},\n}\n");
+        assert_eq!(LineEnding::Crlf.apply(&normalized), text);
+        assert_eq!(normalize("a\rb\n".to_string()), "a\rb\n");
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let text = "a\r\nbc\nd\r\n\r\ne";
+        let endings = LineEndings::of(text);
+        let normalized = normalize(text.to_string());
+        assert_eq!(normalized, "a\nbc\nd\n\ne");
+        assert_eq!(endings.restore(&normalized), text);
+        for (offset, c) in normalized.char_indices().filter(|(_, c)| *c != '\n') {
+            assert_eq!(
+                text[endings.original_offset(offset)..].chars().next(),
+                Some(c)
+            );
+        }
+        assert_eq!(endings.original_offset(normalized.len()), text.len());
+        assert_eq!(endings.ending_at(1), LineEnding::Crlf);
+        assert_eq!(endings.ending_at(4), LineEnding::Lf);
+        assert_eq!(endings.ending_at(6), LineEnding::Crlf);
+        assert_eq!(endings.ending_at(7), LineEnding::Crlf);
+        assert_eq!(LineEndings::of("a\nb\n").restore("a\nb\n"), "a\nb\n");
+    }
+}
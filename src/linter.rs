@@ -7,22 +7,58 @@
 ///
 /// field level lint warnings:
 /// - empty key (location: key)
-/// - using weird characters (location: field value)
+/// - using weird characters (location: the offending characters)
 /// - author writing is not "Last, First" (location: field_value)
 /// - using "arxiv" as a DOI (bad practice) (location: field_value)
 /// - using "http" as a DOI (bad practice) (location: field_value)
+/// - unbalanced braces in a value, which hide the missing fields
+///   they swallow (location: the first unmatched brace)
 ///
 /// entry level lint warnings:
 /// - missing important fields (author, title, year) (location: entry)
 /// - uncheckable entry (no url, nor doi, nor isbn, nor issn, nor arxiv, nor pmid) (location: entry)
-/// - missing optional fields (sha256) (location: entry)
+/// - missing required fields, opt-in (e.g. sha256, doi) (location: entry)
 /// - duplicate field name (location: Vec<field_key>)
+/// - identifiers of the url field disagreeing with doi/eprint (location: Vec<field>)
+/// - url field that is only a resolver link for the doi (location: field)
+/// - field longer than a configured maximum, opt-in (e.g. abstract) (location: field)
+/// - key encoding a year different from the year field (location: key, field)
+/// - date field that is not ISO-8601 (location: field)
+/// - year field different from the year of the date field (location: Vec<field>)
+/// - year field that is neither a year, a range, nor an accepted status (location: field)
+/// - year field holding a status (`forthcoming`), to update before publication (location: field)
+/// - verification older than a configured date, opt-in (location: field)
+/// - entry copied from the BibTeX export of Google Scholar (location: entry)
+/// - possible typo in a title, booktitle, or journal, opt-in (location: field)
+/// - author of proceedings (editors), or editor of a chapter without author (location: field)
+/// - article number (`articleno`) without pages (location: field)
+/// - pages with a `pp.` prefix, a Unicode dash, or no number (location: field)
+/// - title entirely in uppercase or in lowercase (location: field)
+/// - entry already in the `--against` bibliography, or missing from it
+///   with `--against-invert`, opt-in (location: entry)
+/// - entry type not in lowercase, as `@ARTICLE` (location: entry type)
+/// - spaces around the brace or the key of the header, as `@article {key ,`
+///   (location: the header after the type)
 ///
 /// file level lint warnings:
+/// - `@string` defined with different values, also across files (location: Vec<definition>)
+/// - `@string` shadowing a month macro (location: Vec<definition>)
+/// - byte order mark at the start of the file (location: the mark)
+/// - merge conflict markers (`<<<<<<<`, `=======`, `>>>>>>>`) (location: the marker)
+/// - text outside of the entries, comments, and preambles, opt-in (location: the text)
+/// - file not encoded in UTF-8 (reported by `Bibliography::lint`, which decodes it)
 /// - duplicate entries (same key) (location: Vec<entry>)
-/// - duplicate entries (same DOI/ARXIV/SHA256 pair) (location: Vec<entry>)
+/// - keys that are likely typos of each other, of entries with different
+///   DOIs and titles (see `similar_keys`) (location: Vec<key>)
+/// - duplicate entries (same DOI/ARXIV/SHA256 pair, see `values`) (location: Vec<entry>)
+/// - duplicates of a work (same DOI, or same title and author) disagreeing on a field (location: Vec<field>)
 /// - outdated entries  (arxiv versions) (location: Vec<entry>)
 /// - published equivalents (arxiv -> doi / doi -> arxiv) (location: Vec<entry>)
+/// - preprint and published version of the same paper, unless an entry
+///   relates to the other with biblatex's `related` field (location: Vec<entry>)
+/// - `related` key that is not an entry of the loaded files (location: field)
+/// - field of an entry disagreeing with the same field of its `crossref`
+///   parent, such as the booktitle (location: Vec<field>)
 /// - revoked entries   (doi revoked) (location: Vec<entry>)
 ///
 ///
@@ -37,15 +73,40 @@
 /// every field_value, and every entry. This means that the abstraction of BibEntry / BibFile
 /// should keepd this information available.
 ///
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::against::ReferenceIndex;
 use crate::arxiv_identifiers::ArxivId;
 use crate::author_format::check_authors;
+use crate::bibtex_spec::{confused_people_field, is_crossref_inherited, people_field};
+use crate::collation::collation_key;
+use crate::conflicts::{conflict_markers, without_conflict_markers};
+use crate::dates::{normalize_date, Date, DateValue, YearValue, DATE_FIELDS, YEAR_STATUS_WORDS};
+use crate::doi_identifiers::{extract_doi, is_resolver_url, normalize_doi};
+use crate::encoding::BYTE_ORDER_MARK;
+use crate::eprints::EprintType;
+use crate::header::strip_header;
+use crate::pages::{normalize_pages, pages_issues};
+use crate::preprints::{pair_preprints, paper_key, Version};
+use crate::related::{related_keys, RELATED_FIELD};
+use crate::scholar::{is_likely_scholar_export, scholar_signals};
+use crate::similar_keys::similar_keys;
+use crate::timings::{Clock, FileTimings};
+use crate::title_case::{title_case_kind, TitleCaseKind};
+use crate::values::{builtin_string, normalize_value, same_value, VERBATIM_FIELDS};
+#[cfg(feature = "spellcheck")]
+use crate::spellcheck::{value_words, SpellChecker, SPELLCHECKED_FIELDS};
+use crate::verification::{parse_verification, VERIFIED_FIELD};
 use crate::bibtex::tree_sitter::Node;
-use crate::bibtex::{BibEntry, BibFile};
+use crate::bibtex::{
+    tokenize_value, unbalanced_brace, value_tokens, BibEntry, BibField, BibFile, BibString,
+    ValueTokenKind,
+};
 use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
 
 #[derive(Debug, Clone, Default)]
 pub struct LinterState<'a> {
@@ -53,13 +114,135 @@ pub struct LinterState<'a> {
     pub arxiv_latest: HashMap<&'a str, usize>,
     pub doi_arxiv: HashMap<&'a str, &'a str>,
     pub arxiv_doi: HashMap<&'a str, &'a str>,
+    /// Fields that every entry should have on top of
+    /// author/title/year (none by default).
+    pub required_fields: Vec<RequiredField>,
+    /// Maximal length (in characters) of the given
+    /// fields, e.g. `abstract` (none by default).
+    pub max_field_length: HashMap<String, usize>,
+    /// Values of the `@string` definitions of all the loaded
+    /// files, by lowercase name (none by default).
+    pub string_definitions: HashMap<&'a str, HashSet<&'a str>>,
+    /// The `verified` fields dated before this day
+    /// are stale (not checked by default).
+    pub verified_before: Option<Date>,
+    /// Fields of `CONFLICT_FIELDS` that the duplicates of a work
+    /// may disagree on (none when not set).
+    pub conflict_ignored_fields: Option<Vec<String>>,
+    /// Statuses accepted in the `year` field
+    /// (`YEAR_STATUS_WORDS` when not set).
+    pub year_status_words: Option<Vec<String>>,
+    /// The spell-check of the titles (not checked by default).
+    #[cfg(feature = "spellcheck")]
+    pub spellcheck: Option<SpellChecker>,
+    /// Report the text between the entries (`check --strict`).
+    pub strict: bool,
+    /// The keys of the entries of all the loaded files,
+    /// that the `related` fields may refer to (none by default).
+    pub entry_keys: HashSet<&'a str>,
+    /// The works of the `--against` bibliography (not checked by default).
+    pub against: Option<&'a ReferenceIndex>,
+    /// Report the entries missing from the `--against`
+    /// bibliography, instead of those already in it.
+    pub against_invert: bool,
+    /// The fields (lowercase) that the database adds to the entries,
+    /// by start byte of the entry, whose values are linted as well
+    /// (`check --lint-after-completion`, none by default).
+    pub completed: HashMap<usize, HashMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Fields that the duplicates of a work are compared on: the others
+/// (authors, notes, files, ...) are expected to be written differently.
+pub const CONFLICT_FIELDS: [&str; 4] = ["year", "pages", "volume", "booktitle"];
+
+/// The value of a `@string` definition, without its delimiters,
+/// so that `{IEEE}` and `"IEEE"` are the same value.
+pub fn string_value<'a>(file: &BibFile<'a>, string: &BibString<'a>) -> &'a str {
+    let value = file.get_slice(string.value).trim();
+    let inner = value
+        .strip_prefix('{')
+        .and_then(|v| v.strip_suffix('}'))
+        .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')));
+    inner.unwrap_or(value).trim()
+}
+
+/// The characters of the titles quoted by `DuplicateKey`.
+pub const OCCURRENCE_TITLE_LEN: usize = 40;
+
+/// An entry of a key defined several times.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct KeyOccurrence {
+    /// As written, without the `@`
+    pub entry_type: String,
+    /// On a single line, cut after `OCCURRENCE_TITLE_LEN` characters
+    pub title: String,
+}
+
+impl KeyOccurrence {
+    pub fn new(entry_type: &str, title: &str) -> Self {
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        let title = match title.char_indices().nth(OCCURRENCE_TITLE_LEN) {
+            Some((end, _)) => format!("{}…", title[..end].trim_end()),
+            None => title,
+        };
+        KeyOccurrence {
+            entry_type: entry_type.trim_start_matches('@').to_string(),
+            title,
+        }
+    }
+}
+
+/// A field required by the user, written `name`
+/// (reported as a warning when missing) or `name:error`
+/// (reported as a crucial error when missing).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequiredField {
+    pub name: String,
+    pub crucial: bool,
+}
+
+impl std::str::FromStr for RequiredField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, severity) = s.split_once(':').unwrap_or((s, "warning"));
+        let crucial = match severity {
+            "warning" => false,
+            "error" => true,
+            _ => {
+                return Err(format!(
+                    "Invalid severity {:?} for required field {:?} (expected warning or error)",
+                    severity, name
+                ));
+            }
+        };
+        Ok(RequiredField {
+            name: name.to_lowercase(),
+            crucial,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum LintMessage {
     SyntaxError(String),
+    /// The file starts with a byte order mark
+    /// (see the `encoding` module).
+    ByteOrderMark,
+    /// A line left by a merge conflict, such as `<<<<<<< HEAD`
+    /// (see the `conflicts` module).
+    MergeConflictMarker(String),
+    /// Text between the entries, which BibTeX ignores,
+    /// but is most likely an accident (`check --strict`).
+    StrayTopLevelText(String),
+    /// The file was decoded from the given encoding
+    /// instead of UTF-8 (see the `encoding` module).
+    NonUtf8Encoding(String),
     EmptyKey,
     WeirdCharacters(String),
+    /// A `%` or a `&` that is not escaped, which LaTeX reads as
+    /// a comment or an alignment tab (see `unescaped_specials`).
+    UnescapedSpecialCharacter(String),
     AuthorFormat,
     ArxivAsDoi,
     HttpDoi,
@@ -67,19 +250,108 @@ pub enum LintMessage {
     UncheckableEntry,
     MissingOptionalField(String),
     DuplicateFieldName(String),
-    DuplicateKey(String),
+    InconsistentIdentifiers { field_a: String, field_b: String },
+    RedundantUrl,
+    FieldTooLong(String, usize, usize),
+    /// The key contains a year (see `key_year`)
+    /// that differs from the year field.
+    KeyYearMismatch { key_year: u32, field_year: u32 },
+    /// A date field (name, value) that is not ISO-8601
+    /// (see the `dates` module).
+    MalformedDate(String, String),
+    DateYearMismatch { date_year: i32, field_year: i32 },
+    /// A year field that is neither a year, a range,
+    /// nor an accepted status (see `YearValue`).
+    InvalidYear(String),
+    /// A year field holding an accepted status, such as
+    /// `forthcoming`, to replace before publication.
+    ProvisionalYear(String),
+    /// The value of a `verified` field that is too old, or
+    /// has no date (see the `verification` module).
+    StaleVerification(String),
+    /// An entry that looks copied from Google Scholar, with the
+    /// names of its signals (see the `scholar` module).
+    LikelyScholarExport { key: String, signals: Vec<String> },
+    /// A word of a title that is likely misspelled, with the
+    /// closest known words (see the `spellcheck` module).
+    PossibleTypo(String, Vec<String>),
+    /// The preprint and published versions of the same paper
+    /// (see the `preprints` module).
+    PreprintPublishedPair { preprint: String, published: String },
+    /// A key of a `related` field that is not an entry
+    /// of the loaded files (see the `related` module).
+    UnresolvedRelated(String),
+    /// The people of the entry are in the `found` field instead of
+    /// the `expected` one of its type (see `bibtex_spec::PEOPLE_FIELDS`).
+    AuthorEditorConfusion { found: String, expected: String },
+    /// An ACM-style `articleno` without `pages`,
+    /// that some styles render as empty pages.
+    MissingPagesWithArticleNo,
+    /// A `pages` value, and its issues (see the `pages` module).
+    MalformedPages(String, Vec<String>),
+    /// A title whose letters, outside of the protected groups,
+    /// are all in the same case (see the `title_case` module).
+    TitleCaseSuspicious(TitleCaseKind),
+    /// A field whose value has an unmatched brace, that
+    /// may swallow the following fields (see `unbalanced_brace`).
+    UnbalancedBraces(String),
+    /// The work of the entry is already in the `--against`
+    /// bibliography, with the given key (see the `against` module).
+    AlreadyInDatabase(String),
+    /// The work of the entry is not in the `--against`
+    /// bibliography (`--against-invert`).
+    NotInDatabase,
+    /// An entry type that is not in lowercase (as written, `@Article`),
+    /// which `bibadac format` lowercases.
+    EntryTypeCase(String),
+    /// Spaces between the type, the brace, the key, and the comma
+    /// of the header of an entry, which `bibadac format` removes.
+    StraySpacingInHeader,
+    /// A key defined by several entries, quoted in the order of the
+    /// locations of the message.
+    DuplicateKey {
+        key: String,
+        #[serde(default)]
+        occurrences: Vec<KeyOccurrence>,
+    },
+    /// Two keys that are likely typos of each other (`vanbenthem1984`
+    /// and `vanbethem1984`), of entries of different works.
+    SimilarKeys(String, String),
+    /// A `@string` name defined with different values,
+    /// in this file or in the other loaded files.
+    DuplicateStringDefinition(String),
+    /// A `@string` name that redefines a month macro (`jan`..`dec`).
+    ShadowedMonthMacro(String),
     DuplicateDoiArxivSha256(String, String, String),
+    /// Two entries of the same work (same doi, or same title and
+    /// author) with different values (field, value, other value).
+    ConflictingDuplicateData(String, String, String),
+    /// A field inherited from the `crossref` parent (see
+    /// `bibtex_spec::CROSSREF_INHERITED_FIELDS`) with different
+    /// values (field, value of the entry, value of the parent).
+    CrossrefFieldConflict(String, String, String),
     OutdatedEntry(String, usize, usize),
     PublishedEquivalent,
     RevokedEntry,
+    /// The host a doi resolves to (`check --online`), which is not the
+    /// domain of the publisher or journal (see the `doi_targets` module).
+    UnexpectedDoiTarget(String),
+    /// A doi that resolves through this number of redirects
+    /// (`check --online`, see `doi_targets::FRAGILE_HOPS`).
+    FragileDoiRedirect(usize),
 }
 
 impl LintMessage {
     pub fn is_crucial(&self) -> bool {
         match self {
             LintMessage::SyntaxError(_) => true,
+            LintMessage::ByteOrderMark => true,
+            LintMessage::MergeConflictMarker(_) => true,
+            LintMessage::StrayTopLevelText(_) => true,
+            LintMessage::NonUtf8Encoding(_) => false,
             LintMessage::EmptyKey => true,
             LintMessage::WeirdCharacters(_) => false,
+            LintMessage::UnescapedSpecialCharacter(_) => false,
             LintMessage::AuthorFormat => false,
             LintMessage::ArxivAsDoi => false,
             LintMessage::HttpDoi => false,
@@ -87,25 +359,420 @@ impl LintMessage {
             LintMessage::UncheckableEntry => true,
             LintMessage::MissingOptionalField(_) => false,
             LintMessage::DuplicateFieldName(_) => true,
-            LintMessage::DuplicateKey(_) => true,
+            LintMessage::InconsistentIdentifiers { .. } => true,
+            LintMessage::RedundantUrl => false,
+            LintMessage::FieldTooLong(_, _, _) => false,
+            LintMessage::KeyYearMismatch { .. } => false,
+            LintMessage::MalformedDate(_, _) => false,
+            LintMessage::DateYearMismatch { .. } => false,
+            LintMessage::InvalidYear(_) => false,
+            LintMessage::ProvisionalYear(_) => false,
+            LintMessage::StaleVerification(_) => false,
+            LintMessage::LikelyScholarExport { .. } => false,
+            LintMessage::PossibleTypo(_, _) => false,
+            LintMessage::PreprintPublishedPair { .. } => false,
+            LintMessage::UnresolvedRelated(_) => true,
+            LintMessage::AuthorEditorConfusion { .. } => false,
+            LintMessage::MissingPagesWithArticleNo => false,
+            LintMessage::MalformedPages(_, _) => false,
+            LintMessage::TitleCaseSuspicious(_) => false,
+            LintMessage::UnbalancedBraces(_) => true,
+            LintMessage::AlreadyInDatabase(_) => false,
+            LintMessage::NotInDatabase => false,
+            LintMessage::EntryTypeCase(_) => false,
+            LintMessage::StraySpacingInHeader => false,
+            LintMessage::DuplicateKey { .. } => true,
+            LintMessage::SimilarKeys(_, _) => false,
+            LintMessage::DuplicateStringDefinition(_) => true,
+            LintMessage::ShadowedMonthMacro(_) => false,
             LintMessage::DuplicateDoiArxivSha256(_, _, _) => true,
+            LintMessage::ConflictingDuplicateData(_, _, _) => false,
+            LintMessage::CrossrefFieldConflict(_, _, _) => false,
             LintMessage::OutdatedEntry(_,_,_) => true,
             LintMessage::PublishedEquivalent => false,
             LintMessage::RevokedEntry => false,
+            LintMessage::UnexpectedDoiTarget(_) => false,
+            LintMessage::FragileDoiRedirect(_) => false,
+        }
+    }
+
+    /// A stable, human readable, name for the rule
+    /// that produced the message.
+    pub fn rule_name(&self) -> &'static str {
+        match self {
+            LintMessage::SyntaxError(_) => "syntax-error",
+            LintMessage::ByteOrderMark => "byte-order-mark",
+            LintMessage::MergeConflictMarker(_) => "merge-conflict-marker",
+            LintMessage::StrayTopLevelText(_) => "stray-top-level-text",
+            LintMessage::NonUtf8Encoding(_) => "non-utf8-encoding",
+            LintMessage::EmptyKey => "empty-key",
+            LintMessage::WeirdCharacters(_) => "weird-characters",
+            LintMessage::UnescapedSpecialCharacter(_) => "unescaped-special-character",
+            LintMessage::AuthorFormat => "author-format",
+            LintMessage::ArxivAsDoi => "arxiv-as-doi",
+            LintMessage::HttpDoi => "http-doi",
+            LintMessage::MissingField(_) => "missing-field",
+            LintMessage::UncheckableEntry => "uncheckable-entry",
+            LintMessage::MissingOptionalField(_) => "missing-optional-field",
+            LintMessage::DuplicateFieldName(_) => "duplicate-field-name",
+            LintMessage::InconsistentIdentifiers { .. } => "inconsistent-identifiers",
+            LintMessage::RedundantUrl => "redundant-url",
+            LintMessage::FieldTooLong(_, _, _) => "field-too-long",
+            LintMessage::KeyYearMismatch { .. } => "key-year-mismatch",
+            LintMessage::MalformedDate(_, _) => "malformed-date",
+            LintMessage::DateYearMismatch { .. } => "date-year-mismatch",
+            LintMessage::InvalidYear(_) => "invalid-year",
+            LintMessage::ProvisionalYear(_) => "provisional-year",
+            LintMessage::StaleVerification(_) => "stale-verification",
+            LintMessage::LikelyScholarExport { .. } => "likely-scholar-export",
+            LintMessage::PossibleTypo(_, _) => "possible-typo",
+            LintMessage::PreprintPublishedPair { .. } => "preprint-published-pair",
+            LintMessage::UnresolvedRelated(_) => "unresolved-related",
+            LintMessage::AuthorEditorConfusion { .. } => "author-editor-confusion",
+            LintMessage::MissingPagesWithArticleNo => "missing-pages-with-articleno",
+            LintMessage::MalformedPages(_, _) => "malformed-pages",
+            LintMessage::TitleCaseSuspicious(_) => "title-case-suspicious",
+            LintMessage::UnbalancedBraces(_) => "unbalanced-braces",
+            LintMessage::AlreadyInDatabase(_) => "already-in-database",
+            LintMessage::NotInDatabase => "not-in-database",
+            LintMessage::EntryTypeCase(_) => "entry-type-case",
+            LintMessage::StraySpacingInHeader => "stray-spacing-in-header",
+            LintMessage::DuplicateKey { .. } => "duplicate-key",
+            LintMessage::SimilarKeys(_, _) => "similar-keys",
+            LintMessage::DuplicateStringDefinition(_) => "duplicate-string-definition",
+            LintMessage::ShadowedMonthMacro(_) => "shadowed-month-macro",
+            LintMessage::DuplicateDoiArxivSha256(_, _, _) => "duplicate-doi-arxiv-sha256",
+            LintMessage::ConflictingDuplicateData(_, _, _) => "conflicting-duplicate-data",
+            LintMessage::CrossrefFieldConflict(_, _, _) => "crossref-field-conflict",
+            LintMessage::OutdatedEntry(_, _, _) => "outdated-entry",
+            LintMessage::PublishedEquivalent => "published-equivalent",
+            LintMessage::RevokedEntry => "revoked-entry",
+            LintMessage::UnexpectedDoiTarget(_) => "unexpected-doi-target",
+            LintMessage::FragileDoiRedirect(_) => "fragile-doi-redirect",
+        }
+    }
+
+    /// How to fix the issue, when the tool can do it.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            LintMessage::ByteOrderMark | LintMessage::NonUtf8Encoding(_) => {
+                Some("`bibadac format --in-place` writes the file back in UTF-8, without byte order mark")
+            }
+            LintMessage::MergeConflictMarker(_) => {
+                Some("resolve the merge conflict, and remove its markers")
+            }
+            LintMessage::StrayTopLevelText(_) => {
+                Some("remove the text, or move it to a @comment{...}")
+            }
+            LintMessage::UnescapedSpecialCharacter(_) => {
+                Some("escape the character (`\\%`, `\\&`), which `bibadac check --fix` does")
+            }
+            LintMessage::ArxivAsDoi => Some(
+                "move the arXiv identifier to `eprint` (with `eprinttype = {arxiv}`), or give the doi of the published version",
+            ),
+            LintMessage::HttpDoi => {
+                Some("`bibadac format --normalize` rewrites the doi to its bare `10.x/...` form")
+            }
+            LintMessage::FieldTooLong(_, _, _) => {
+                Some("`bibadac format --truncate-field name=length` shortens the field")
+            }
+            LintMessage::MalformedDate(_, value) if normalize_date(value).is_some() => {
+                Some("`bibadac format --normalize` rewrites the date to ISO-8601")
+            }
+            LintMessage::MalformedDate(_, _) => {
+                Some("dates are written YYYY-MM-DD (ranges YYYY/YYYY, open ranges YYYY/..)")
+            }
+            LintMessage::InvalidYear(_) => {
+                Some("years are written YYYY, ranges YYYY--YYYY, or a status (see --year-status-word)")
+            }
+            LintMessage::LikelyScholarExport { .. } => {
+                Some("fetch the entry again from its DOI or DBLP, e.g. with `bibadac setup --refetch-flagged --report <json>`")
+            }
+            LintMessage::PossibleTypo(_, _) => {
+                Some("protect the word with braces, or add it to the file of --dictionary")
+            }
+            LintMessage::PreprintPublishedPair { .. } => {
+                Some("`bibadac merge --collapse-preprints` folds the preprint into the published entry, or `related = {key}` links them")
+            }
+            LintMessage::UnresolvedRelated(_) => {
+                Some("add the entry, or fix the key in the `related` field")
+            }
+            LintMessage::AuthorEditorConfusion { expected, .. } if expected == "editor" => {
+                Some("`bibadac format --fix-editors` renames the field to `editor`")
+            }
+            LintMessage::AuthorEditorConfusion { .. } => {
+                Some("the `editor` field holds the editors of the book, add the authors of the part")
+            }
+            LintMessage::MissingPagesWithArticleNo => {
+                Some("`bibadac format --synthesize-pages` writes the pages from the articleno and numpages")
+            }
+            LintMessage::MalformedPages(value, _) if normalize_pages(value).is_some() => {
+                Some("`bibadac format --normalize` removes the prefix and writes the ranges with `--`")
+            }
+            LintMessage::MalformedPages(_, _) => {
+                Some("leave the pages out, or give the article number (`articleno`)")
+            }
+            LintMessage::TitleCaseSuspicious(_) => {
+                Some("protect the acronyms with braces, then `bibadac format --fix-title-case sentence` (or `title`) recases the title")
+            }
+            LintMessage::UnbalancedBraces(_) => {
+                Some("close the brace, or escape it (`\\{`, `\\}`) when it is part of the text")
+            }
+            LintMessage::AlreadyInDatabase(_) => {
+                Some("cite the entry of the database, or remove this one")
+            }
+            LintMessage::EntryTypeCase(_) | LintMessage::StraySpacingInHeader => {
+                Some("`bibadac format` writes the header of the entries as `@type{key,`")
+            }
+            LintMessage::SimilarKeys(_, _) => {
+                Some("check that the citations use the intended key, and rename one of the entries")
+            }
+            LintMessage::CrossrefFieldConflict(_, _, _) => {
+                Some("remove the field from the entry to inherit it from its crossref parent, or fix one of the values")
+            }
+            LintMessage::UnexpectedDoiTarget(_) => {
+                Some("check that the doi is the one of the entry, or map its publisher to the domain in [check.doi_domains]")
+            }
+            LintMessage::FragileDoiRedirect(_) => {
+                Some("check that the doi still leads to the entry, and keep its landing page in the `url` field")
+            }
+            LintMessage::RedundantUrl => {
+                Some("remove the url, which only links to the doi (`bibadac check --fix` does)")
+            }
+            _ => None,
         }
     }
+
+    /// A message of every rule, in the order of `rule_name`,
+    /// e.g. to list the rules with their default severity.
+    pub fn examples() -> Vec<LintMessage> {
+        let s = String::new;
+        vec![
+            LintMessage::SyntaxError(s()),
+            LintMessage::ByteOrderMark,
+            LintMessage::MergeConflictMarker(s()),
+            LintMessage::StrayTopLevelText(s()),
+            LintMessage::NonUtf8Encoding(s()),
+            LintMessage::EmptyKey,
+            LintMessage::WeirdCharacters(s()),
+            LintMessage::UnescapedSpecialCharacter(s()),
+            LintMessage::AuthorFormat,
+            LintMessage::ArxivAsDoi,
+            LintMessage::HttpDoi,
+            LintMessage::MissingField(s()),
+            LintMessage::UncheckableEntry,
+            LintMessage::MissingOptionalField(s()),
+            LintMessage::DuplicateFieldName(s()),
+            LintMessage::InconsistentIdentifiers {
+                field_a: s(),
+                field_b: s(),
+            },
+            LintMessage::RedundantUrl,
+            LintMessage::FieldTooLong(s(), 0, 0),
+            LintMessage::KeyYearMismatch {
+                key_year: 0,
+                field_year: 0,
+            },
+            LintMessage::MalformedDate(s(), s()),
+            LintMessage::DateYearMismatch {
+                date_year: 0,
+                field_year: 0,
+            },
+            LintMessage::InvalidYear(s()),
+            LintMessage::ProvisionalYear(s()),
+            LintMessage::StaleVerification(s()),
+            LintMessage::LikelyScholarExport {
+                key: s(),
+                signals: vec![],
+            },
+            LintMessage::PossibleTypo(s(), vec![]),
+            LintMessage::PreprintPublishedPair {
+                preprint: s(),
+                published: s(),
+            },
+            LintMessage::UnresolvedRelated(s()),
+            LintMessage::AuthorEditorConfusion {
+                found: s(),
+                expected: s(),
+            },
+            LintMessage::MissingPagesWithArticleNo,
+            LintMessage::MalformedPages(s(), vec![]),
+            LintMessage::TitleCaseSuspicious(TitleCaseKind::AllUpper),
+            LintMessage::UnbalancedBraces(s()),
+            LintMessage::AlreadyInDatabase(s()),
+            LintMessage::NotInDatabase,
+            LintMessage::EntryTypeCase(s()),
+            LintMessage::StraySpacingInHeader,
+            LintMessage::DuplicateKey {
+                key: s(),
+                occurrences: vec![],
+            },
+            LintMessage::SimilarKeys(s(), s()),
+            LintMessage::DuplicateStringDefinition(s()),
+            LintMessage::ShadowedMonthMacro(s()),
+            LintMessage::DuplicateDoiArxivSha256(s(), s(), s()),
+            LintMessage::ConflictingDuplicateData(s(), s(), s()),
+            LintMessage::CrossrefFieldConflict(s(), s(), s()),
+            LintMessage::OutdatedEntry(s(), 0, 0),
+            LintMessage::PublishedEquivalent,
+            LintMessage::RevokedEntry,
+            LintMessage::UnexpectedDoiTarget(s()),
+            LintMessage::FragileDoiRedirect(0),
+        ]
+    }
+}
+
+/// The values of the fields of an entry (braces removed), by name.
+pub type FieldMap<'a> = HashMap<&'a str, &'a str>;
+
+/// Computed once per entry, and shared by the entry and file level checks.
+pub fn field_map<'a>(file: &BibFile<'a>, entry: &BibEntry<'a>) -> FieldMap<'a> {
+    entry
+        .fields
+        .iter()
+        .map(|field| {
+            (
+                file.get_slice(field.name),
+                file.get_braceless_slice(field.value),
+            )
+        })
+        .collect()
 }
 
 /// A message, and the *reason* why it was triggered
 pub struct Lint<'a> {
     pub msg: LintMessage,
     pub loc: Vec<Node<'a>>,
+    /// Byte range of the file, inside of the first location,
+    /// when the message is about a part of it
+    pub span: Option<Range<usize>>,
+}
+
+impl Lint<'_> {
+    /// The replacement of the span of the message that fixes it,
+    /// when there is an obvious one (the bare doi of `HttpDoi`, or
+    /// nothing for the whole field of `RedundantUrl`).
+    pub fn suggestion(&self, file: &BibFile) -> Option<(Range<usize>, String)> {
+        let span = self.span.clone()?;
+        match self.msg {
+            LintMessage::HttpDoi => {
+                let doi = normalize_doi(&file.content[span.clone()])?;
+                Some((span, doi))
+            }
+            LintMessage::EntryTypeCase(ref entrytype) => Some((span, entrytype.to_lowercase())),
+            LintMessage::UnescapedSpecialCharacter(ref c) => Some((span, format!("\\{}", c))),
+            LintMessage::StraySpacingInHeader => {
+                // the brace (or parenthesis), then the key
+                let header = file.content[span.clone()].trim();
+                let key = header[1..].trim_start();
+                Some((span, format!("{}{}", &header[..1], key)))
+            }
+            LintMessage::RedundantUrl => Some((field_removal(&file.content, span), String::new())),
+            _ => None,
+        }
+    }
 }
 
 impl Debug for Lint<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?} at {:?}", self.msg, self.loc)
+        write!(f, "{:?} at {:?}", self.msg, self.loc)?;
+        match &self.span {
+            Some(span) => write!(f, " ({:?})", span),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The bytes to remove to drop the field at `span`: the field, its
+/// comma, and its line when it is alone on it.
+fn field_removal(content: &str, span: Range<usize>) -> Range<usize> {
+    let after = &content[span.end..];
+    let mut end = span.end + (after.len() - after.trim_start().len());
+    if content[end..].starts_with(',') {
+        end += 1;
+    } else {
+        end = span.end;
+    }
+    let line_start = content[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let rest = &content[end..];
+    let line_end = rest.find('\n').map(|i| end + i + 1);
+    match line_end {
+        Some(line_end)
+            if content[line_start..span.start].trim().is_empty()
+                && content[end..line_end].trim().is_empty() =>
+        {
+            line_start..line_end
+        }
+        _ => span.start..end,
+    }
+}
+
+/// Extracts the year encoded in an entry key (`smith2019robust`).
+///
+/// The key is cut into maximal runs of digits, and a run is a year
+/// candidate when it has exactly 4 digits between 1800 and 2099, and
+/// is not part of a dotted number (`arxiv2301.00001`). The year is only
+/// returned when there is a single candidate: keys with several
+/// 4-digit sequences are ambiguous and ignored.
+fn key_year(key: &str) -> Option<u32> {
+    let bytes = key.as_bytes();
+    let mut candidates = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        let dotted = (start > 0 && bytes[start - 1] == b'.')
+            || (i + 1 < bytes.len() && bytes[i] == b'.' && bytes[i + 1].is_ascii_digit());
+        if i - start == 4 && !dotted {
+            let year: u32 = key[start..i].parse().ok()?;
+            if (1800..2100).contains(&year) {
+                candidates.push(year);
+            }
+        }
+    }
+    match candidates[..] {
+        [year] => Some(year),
+        _ => None,
+    }
+}
+
+/// The special characters that LaTeX reads as a comment (`%`) and an
+/// alignment tab (`&`) when they are not escaped.
+const UNESCAPED_SPECIALS: &[char] = &['%', '&'];
+
+/// The bare `%` and `&` of a field value, found at the given offset of
+/// the file, with their byte ranges, but in the arguments of `\url` and
+/// `\href`, which take them as they are.
+pub fn unescaped_specials(value: &str, offset: usize) -> Vec<(Range<usize>, char)> {
+    let tokens = tokenize_value(value, 0);
+    let verbatim = tokens
+        .iter()
+        .filter(|t| t.kind == ValueTokenKind::BraceGroup)
+        .filter(|t| {
+            let before = &value[..t.span.start];
+            before.ends_with("\\url") || before.ends_with("\\href")
+        })
+        .map(|t| t.span.clone())
+        .collect::<Vec<_>>();
+    let mut specials = vec![];
+    for t in tokens.iter().filter(|t| t.kind == ValueTokenKind::Text) {
+        if verbatim.iter().any(|v| v.contains(&t.span.start)) {
+            continue;
+        }
+        for (i, c) in value[t.span.clone()].char_indices() {
+            if UNESCAPED_SPECIALS.contains(&c) {
+                let start = offset + t.span.start + i;
+                specials.push((start..start + 1, c));
+            }
+        }
     }
+    specials
 }
 
 impl<'a> LinterState<'a> {
@@ -126,45 +793,320 @@ impl<'a> LinterState<'a> {
         if key == "doi" && self.revoked_dois.contains(value) {
             return Some(LintMessage::RevokedEntry);
         }
-        // we allow "{", "}", and ","
-        if key != "doi"
-            && key != "eprint"
-            && key != "url"
-            && value.contains(|c: char| c != '\n' && (c.is_control() || c == '\\'))
-        {
-            return Some(LintMessage::WeirdCharacters(value.to_string()));
-        }
         None
     }
 
-    pub fn lint_entry(&self, file: &BibFile<'a>, entry: BibEntry<'a>) -> Vec<Lint<'a>> {
+    /// Finds the first weird character of a field value (a control
+    /// character, or a backslash) outside of the group delimiters,
+    /// and reports it along with the rest of its word (`\'e`, `\emph`).
+    fn lint_weird_characters(&self, file: &BibFile<'a>, field: &BibField<'a>) -> Option<Lint<'a>> {
+        let key = file.get_slice(field.name);
+        if key == "doi" || key == "eprint" || key == "url" {
+            return None;
+        }
+        let is_weird = |c: char| c != '\n' && (c.is_control() || c == '\\');
+        value_tokens(file, field.value)
+            .into_iter()
+            .filter(|t| t.kind == ValueTokenKind::Text)
+            .find_map(|t| {
+                let text = &file.content[t.span.clone()];
+                let (start, weird) = text.char_indices().find(|&(_, c)| is_weird(c))?;
+                // the control characters may span several bytes, e.g. U+0085
+                let rest = start + weird.len_utf8();
+                let end = text[rest..]
+                    .find(|c: char| c.is_whitespace() || is_weird(c))
+                    .map_or(text.len(), |e| rest + e);
+                Some(Lint {
+                    msg: LintMessage::WeirdCharacters(text[start..end].to_string()),
+                    loc: vec![field.loc],
+                    span: Some(t.span.start + start..t.span.start + end),
+                })
+            })
+    }
+
+    /// Reports the bare `%` and `&` of a field value (see
+    /// `unescaped_specials`), outside of the verbatim fields.
+    fn lint_unescaped_specials(&self, file: &BibFile<'a>, field: &BibField<'a>) -> Vec<Lint<'a>> {
+        let key = file.get_slice(field.name).to_lowercase();
+        if key == "doi" || VERBATIM_FIELDS.contains(&key.as_str()) {
+            return vec![];
+        }
+        unescaped_specials(file.get_slice(field.value), field.value.start_byte())
+            .into_iter()
+            .map(|(span, c)| Lint {
+                msg: LintMessage::UnescapedSpecialCharacter(c.to_string()),
+                loc: vec![field.loc],
+                span: Some(span),
+            })
+            .collect()
+    }
+
+    /// Reports the words of the titles that are likely misspelled,
+    /// at most once per word and field.
+    #[cfg(feature = "spellcheck")]
+    fn lint_spelling(&self, file: &BibFile<'a>, entry: &BibEntry<'a>) -> Vec<Lint<'a>> {
+        let Some(checker) = &self.spellcheck else {
+            return vec![];
+        };
         let mut messages = vec![];
-        let fields = entry
-            .fields
-            .iter()
-            .map(|field| {
-                (
-                    file.get_slice(field.name),
-                    file.get_braceless_slice(field.value),
-                )
+        for f in entry.fields.iter() {
+            let name = file.get_slice(f.name);
+            if !SPELLCHECKED_FIELDS.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let mut seen = HashSet::new();
+            for (span, word) in value_words(file.get_slice(f.value), f.value.start_byte()) {
+                if !seen.insert(word.to_lowercase()) {
+                    continue;
+                }
+                if let Some(suggestions) = checker.check(word) {
+                    messages.push(Lint {
+                        msg: LintMessage::PossibleTypo(word.to_string(), suggestions),
+                        loc: vec![f.loc],
+                        span: Some(span),
+                    });
+                }
+            }
+        }
+        messages
+    }
+
+    /// Compares the identifiers that can be extracted from
+    /// the url field with the explicit doi/eprint fields.
+    fn lint_identifiers(&self, file: &BibFile<'a>, entry: &BibEntry<'a>) -> Vec<Lint<'a>> {
+        let mut messages = vec![];
+        let field = |name: &str| {
+            entry
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(name))
+                .map(|f| (file.get_braceless_slice(f.value), f.loc))
+        };
+        let Some((url, url_loc)) = field("url") else {
+            return messages;
+        };
+        if let (Some(url_doi), Some((doi, doi_loc))) = (extract_doi(url), field("doi")) {
+            let doi = extract_doi(doi).unwrap_or(doi);
+            if !url_doi.eq_ignore_ascii_case(doi) {
+                messages.push(Lint {
+                    msg: LintMessage::InconsistentIdentifiers {
+                        field_a: "doi".into(),
+                        field_b: "url".into(),
+                    },
+                    loc: vec![doi_loc, url_loc],
+                    span: None,
+                });
+            } else if is_resolver_url(url, doi) {
+                messages.push(Lint {
+                    msg: LintMessage::RedundantUrl,
+                    loc: vec![url_loc],
+                    span: Some(url_loc.byte_range()),
+                });
+            }
+        }
+        if let (Some(url_id), Some((eprint, eprint_loc))) = (ArxivId::extract(url), field("eprint"))
+            && EprintType::of_entry(file, entry) == EprintType::Arxiv
+            && let Ok(eprint_id) = ArxivId::try_from(eprint)
+        {
+            let same_version = match (url_id.version, eprint_id.version) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            };
+            if url_id.id != eprint_id.id || !same_version {
+                messages.push(Lint {
+                    msg: LintMessage::InconsistentIdentifiers {
+                        field_a: "eprint".into(),
+                        field_b: "url".into(),
+                    },
+                    loc: vec![eprint_loc, url_loc],
+                    span: None,
+                });
+            }
+        }
+        messages
+    }
+
+    /// Whether a (lowercase) status is accepted in the year field.
+    fn is_year_status(&self, status: &str) -> bool {
+        match &self.year_status_words {
+            Some(words) => words.iter().any(|w| w.eq_ignore_ascii_case(status)),
+            None => YEAR_STATUS_WORDS.contains(&status),
+        }
+    }
+
+    /// Reports the text between the entries, apart from the
+    /// conflict markers (reported on their own) and the header
+    /// of the formatted files (see the `header` module).
+    fn lint_stray_text(&self, file: &'a BibFile<'a>) -> Vec<Lint<'a>> {
+        let mut cursor = file.tree.root_node().walk();
+        file.tree
+            .root_node()
+            .children(&mut cursor)
+            .filter(|node| node.kind() == "junk")
+            .filter_map(|node| {
+                let text = without_conflict_markers(file.get_slice(node));
+                let text = strip_header(text.trim()).trim();
+                if text.is_empty() {
+                    return None;
+                }
+                Some(Lint {
+                    msg: LintMessage::StrayTopLevelText(text.to_string()),
+                    loc: vec![node],
+                    span: None,
+                })
             })
-            .collect::<HashMap<_, _>>();
-        for f in ["author", "title", "year"].iter() {
-            if !fields.contains_key(f) {
+            .collect()
+    }
+
+    /// Lints the values that the database adds to an entry. They are
+    /// not in the file, so that the messages are about the entry.
+    pub fn lint_completed_fields(
+        &self,
+        entry: &BibEntry<'a>,
+        completed: &HashMap<String, String>,
+    ) -> Vec<Lint<'a>> {
+        let mut names = completed.keys().collect::<Vec<_>>();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let value = completed[name].trim();
+                let value = value
+                    .strip_prefix('{')
+                    .and_then(|v| v.strip_suffix('}'))
+                    .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+                    .unwrap_or(value);
+                Some(Lint {
+                    msg: self.lint_field(name, value)?,
+                    loc: vec![entry.loc],
+                    span: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Lints an entry, given the map of its fields (see `field_map`).
+    pub fn lint_entry(
+        &self,
+        file: &BibFile<'a>,
+        entry: &BibEntry<'a>,
+        fields: &FieldMap<'a>,
+    ) -> Vec<Lint<'a>> {
+        let mut messages = vec![];
+        for f in entry.fields.iter() {
+            if let Some(offset) = unbalanced_brace(file.get_slice(f.value)) {
+                let start = f.value.start_byte() + offset;
+                messages.push(Lint {
+                    msg: LintMessage::UnbalancedBraces(file.get_slice(f.name).to_lowercase()),
+                    loc: vec![f.value],
+                    span: Some(start..start + 1),
+                });
+            }
+        }
+        // the fields swallowed by an unbalanced value are not missing
+        let swallowing = !messages.is_empty();
+        let entrytype = file.get_slice(entry.entrytype);
+        // the people of the edited entries are their editors
+        let edited = people_field(entrytype) == Some("editor") && fields.contains_key("editor");
+        for f in ["author", "title", "year"].iter().filter(|_| !swallowing) {
+            let present = fields.contains_key(f) || (*f == "author" && edited);
+            if !present {
                 messages.push(Lint {
                     msg: LintMessage::MissingField(f.to_string()),
                     loc: vec![entry.loc],
+                    span: None,
                 });
             }
         }
-        for f in ["sha256"].iter() {
-            if !fields.contains_key(f) {
+        for f in self.required_fields.iter().filter(|_| !swallowing) {
+            if !fields.contains_key(f.name.as_str()) {
                 messages.push(Lint {
-                    msg: LintMessage::MissingOptionalField(f.to_string()),
+                    msg: if f.crucial {
+                        LintMessage::MissingField(f.name.clone())
+                    } else {
+                        LintMessage::MissingOptionalField(f.name.clone())
+                    },
                     loc: vec![entry.loc],
+                    span: None,
+                });
+            }
+        }
+        // most configurations have no limit, do not lowercase every name
+        if !self.max_field_length.is_empty() {
+            for f in entry.fields.iter() {
+                let name = file.get_slice(f.name).to_lowercase();
+                let length = file.get_braceless_slice(f.value).chars().count();
+                if let Some(&max) = self.max_field_length.get(&name)
+                    && length > max
+                {
+                    messages.push(Lint {
+                        msg: LintMessage::FieldTooLong(name, length, max),
+                        loc: vec![f.loc],
+                        span: None,
+                    });
+                }
+            }
+        }
+        if let Some((found, expected)) = confused_people_field(
+            entrytype,
+            fields.contains_key("author"),
+            fields.contains_key("editor"),
+        ) && let Some(field) = entry
+            .fields
+            .iter()
+            .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(found))
+        {
+            messages.push(Lint {
+                msg: LintMessage::AuthorEditorConfusion {
+                    found: found.to_string(),
+                    expected: expected.to_string(),
+                },
+                loc: vec![field.loc],
+                span: None,
+            });
+        }
+        if let Some(pages) = entry
+            .fields
+            .iter()
+            .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("pages"))
+        {
+            let (span, value) = file.get_value_span(pages.value);
+            let issues = pages_issues(value);
+            if !issues.is_empty() {
+                messages.push(Lint {
+                    msg: LintMessage::MalformedPages(
+                        value.trim().to_string(),
+                        issues.iter().map(|i| i.to_string()).collect(),
+                    ),
+                    loc: vec![pages.loc],
+                    span: Some(span),
                 });
             }
         }
+        if let Some(title) = entry
+            .fields
+            .iter()
+            .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("title"))
+            && let Some(kind) = title_case_kind(file.get_slice(title.value))
+        {
+            messages.push(Lint {
+                msg: LintMessage::TitleCaseSuspicious(kind),
+                loc: vec![title.loc],
+                span: Some(file.get_value_span(title.value).0),
+            });
+        }
+        if !fields.contains_key("pages")
+            && let Some(articleno) = entry
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("articleno"))
+        {
+            messages.push(Lint {
+                msg: LintMessage::MissingPagesWithArticleNo,
+                loc: vec![articleno.loc],
+                span: None,
+            });
+        }
         if !fields.contains_key("url")
             && !fields.contains_key("doi")
             && !fields.contains_key("isbn")
@@ -175,9 +1117,152 @@ impl<'a> LinterState<'a> {
             messages.push(Lint {
                 msg: LintMessage::UncheckableEntry,
                 loc: vec![entry.loc],
+                span: None,
+            });
+        }
+
+        messages.extend(self.lint_identifiers(file, entry));
+
+        if let Some(key_year) = key_year(file.get_slice(entry.key))
+            && let Some(year) = entry
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("year"))
+            && let Ok(field_year) = file.get_braceless_slice(year.value).trim().parse::<u32>()
+            && key_year != field_year
+        {
+            messages.push(Lint {
+                msg: LintMessage::KeyYearMismatch {
+                    key_year,
+                    field_year,
+                },
+                loc: vec![entry.key, year.loc],
+                span: None,
+            });
+        }
+
+        // macros and concatenations are not checked
+        if let Some(year) = entry
+            .fields
+            .iter()
+            .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("year"))
+            .filter(|f| {
+                let raw = file.get_slice(f.value);
+                raw.starts_with(['{', '"']) || raw.chars().all(|c| c.is_ascii_digit())
+            })
+        {
+            let value = file.get_braceless_slice(year.value).trim_matches('"');
+            let msg = match YearValue::parse(value) {
+                YearValue::Exact(_) | YearValue::Range(_, _) => None,
+                YearValue::TextualStatus(status) if self.is_year_status(&status) => {
+                    Some(LintMessage::ProvisionalYear(status))
+                }
+                YearValue::TextualStatus(_) | YearValue::Invalid(_) => {
+                    Some(LintMessage::InvalidYear(value.trim().to_string()))
+                }
+            };
+            messages.extend(msg.map(|msg| Lint {
+                msg,
+                loc: vec![year.loc],
+                span: None,
+            }));
+        }
+
+        for f in entry.fields.iter() {
+            let name = file.get_slice(f.name);
+            if !DATE_FIELDS.iter().any(|d| d.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let name = name.to_lowercase();
+            let value = file.get_braceless_slice(f.value).trim();
+            let Some(date) = DateValue::parse(value) else {
+                messages.push(Lint {
+                    msg: LintMessage::MalformedDate(name, value.to_string()),
+                    loc: vec![f.loc],
+                    span: None,
+                });
+                continue;
+            };
+            if name == "date"
+                && let Some(date_year) = date.year()
+                && let Some(year) = entry
+                    .fields
+                    .iter()
+                    .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("year"))
+                && let Ok(field_year) = file.get_braceless_slice(year.value).trim().parse::<i32>()
+                && date_year != field_year
+            {
+                messages.push(Lint {
+                    msg: LintMessage::DateYearMismatch {
+                        date_year,
+                        field_year,
+                    },
+                    loc: vec![f.loc, year.loc],
+                    span: None,
+                });
+            }
+        }
+
+        if let Some(before) = self.verified_before
+            && let Some(verified) = entry
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(VERIFIED_FIELD))
+        {
+            let value = file.get_braceless_slice(verified.value);
+            if parse_verification(value).is_none_or(|v| v.date < before) {
+                messages.push(Lint {
+                    msg: LintMessage::StaleVerification(value.to_string()),
+                    loc: vec![verified.loc],
+                    span: None,
+                });
+            }
+        }
+
+        let key = file.get_slice(entry.key);
+        let signals = scholar_signals(key, file.get_slice(entry.entrytype), fields);
+        if is_likely_scholar_export(&signals) {
+            messages.push(Lint {
+                msg: LintMessage::LikelyScholarExport {
+                    key: key.to_string(),
+                    signals: signals.iter().map(|s| s.name().to_string()).collect(),
+                },
+                loc: vec![entry.loc],
+                span: None,
             });
         }
 
+        #[cfg(feature = "spellcheck")]
+        messages.extend(self.lint_spelling(file, entry));
+
+        if entrytype.chars().any(char::is_uppercase) {
+            messages.push(Lint {
+                msg: LintMessage::EntryTypeCase(entrytype.to_string()),
+                loc: vec![entry.entrytype],
+                span: Some(entry.entrytype.byte_range()),
+            });
+        }
+        if let Some(span) = file.header_spacing(entry) {
+            messages.push(Lint {
+                msg: LintMessage::StraySpacingInHeader,
+                loc: vec![entry.loc],
+                span: Some(span),
+            });
+        }
+
+        if let Some(against) = self.against {
+            let msg = match (against.find(fields), self.against_invert) {
+                (Some(key), false) => Some(LintMessage::AlreadyInDatabase(key.to_string())),
+                (None, true) => Some(LintMessage::NotInDatabase),
+                _ => None,
+            };
+            messages.extend(msg.map(|msg| Lint {
+                msg,
+                loc: vec![entry.loc],
+                span: None,
+            }));
+        }
+
         let mut defined_keys = HashMap::new();
         for f in entry.fields.iter() {
             let k = file.get_slice(f.name);
@@ -188,61 +1273,271 @@ impl<'a> LinterState<'a> {
                 messages.push(Lint {
                     msg: LintMessage::DuplicateFieldName(k.to_string()),
                     loc: locs,
+                    span: None,
                 });
             }
         }
+        // the messages point inside of the delimiters of the value
         messages.extend(entry.fields.iter().filter_map(|f| {
             let keystr = file.get_slice(f.name);
-            let valuestr = file.get_braceless_slice(f.value);
-            let msg = self.lint_field(keystr, valuestr)?;
-            Some(Lint {
-                msg,
-                loc: vec![f.loc],
-            })
+            let (span, valuestr) = file.get_value_span(f.value);
+            match self.lint_field(keystr, valuestr) {
+                Some(msg) => Some(Lint {
+                    msg,
+                    loc: vec![f.loc],
+                    span: Some(span),
+                }),
+                None => self.lint_weird_characters(file, f),
+            }
         }));
+        messages.extend(
+            entry
+                .fields
+                .iter()
+                .flat_map(|f| self.lint_unescaped_specials(file, f)),
+        );
 
         messages
     }
 
-    pub fn lint_file(&self, file: &'a BibFile<'a>, entries: Vec<BibEntry<'a>>) -> Vec<Lint<'a>> {
+    /// The fields of `CONFLICT_FIELDS` on which two entries of the same
+    /// work disagree, ignoring case, braces, accents, and spacing.
+    fn conflicting_data(
+        &self,
+        file: &'a BibFile<'a>,
+        a: &BibEntry<'a>,
+        b: &BibEntry<'a>,
+    ) -> Vec<Lint<'a>> {
+        let ignored = |name: &str| {
+            !CONFLICT_FIELDS.contains(&name)
+                || self
+                    .conflict_ignored_fields
+                    .iter()
+                    .flatten()
+                    .any(|f| f.eq_ignore_ascii_case(name))
+        };
+        let mut messages = vec![];
+        for field_a in a.fields.iter() {
+            let name = file.get_slice(field_a.name).to_lowercase();
+            let value_a = file.get_braceless_slice(field_a.value).trim();
+            if ignored(&name) || value_a.is_empty() {
+                continue;
+            }
+            let Some(field_b) = b
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(&name))
+            else {
+                continue;
+            };
+            let value_b = file.get_braceless_slice(field_b.value).trim();
+            let same = |a: &str, b: &str| {
+                let a = collation_key(a);
+                let b = collation_key(b);
+                a.split_whitespace().eq(b.split_whitespace())
+            };
+            let raw_a = file.get_slice(field_a.value);
+            let raw_b = file.get_slice(field_b.value);
+            if !value_b.is_empty() && !same_value(&name, raw_a, raw_b) && !same(value_a, value_b) {
+                messages.push(Lint {
+                    msg: LintMessage::ConflictingDuplicateData(
+                        name,
+                        value_a.to_string(),
+                        value_b.to_string(),
+                    ),
+                    loc: vec![field_a.loc, field_b.loc],
+                    span: None,
+                });
+            }
+        }
+        messages
+    }
+
+    /// The inherited fields of `child` that its `crossref`
+    /// parent has with a different (normalized) value.
+    fn crossref_conflicts(
+        &self,
+        file: &'a BibFile<'a>,
+        child: &BibEntry<'a>,
+        parent: &BibEntry<'a>,
+    ) -> Vec<Lint<'a>> {
         let mut messages = vec![];
-        let mut used_keys: HashMap<&str, Vec<Node<'a>>> = HashMap::new();
-        let mut doi_arxiv_sha256: HashMap<(&'a str, &'a str, &'a str), Vec<Node<'a>>> =
+        for field in child.fields.iter() {
+            let name = file.get_slice(field.name).to_lowercase();
+            let value = file.get_braceless_slice(field.value).trim();
+            if !is_crossref_inherited(&name) || value.is_empty() {
+                continue;
+            }
+            let Some(inherited) = parent
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(&name))
+            else {
+                continue;
+            };
+            let parent_value = file.get_braceless_slice(inherited.value).trim();
+            let raw = file.get_slice(field.value);
+            let parent_raw = file.get_slice(inherited.value);
+            if !parent_value.is_empty() && !same_value(&name, raw, parent_raw) {
+                messages.push(Lint {
+                    msg: LintMessage::CrossrefFieldConflict(
+                        name,
+                        value.to_string(),
+                        parent_value.to_string(),
+                    ),
+                    loc: vec![field.loc, inherited.loc],
+                    span: None,
+                });
+            }
+        }
+        messages
+    }
+
+    /// Lints the file, giving the messages to `sink` as they are found:
+    /// the messages of the whole file (syntax errors, `@string` definitions)
+    /// first, then those of every entry in order, and the messages about
+    /// several entries (duplicates, preprints) at the end.
+    pub fn lint_file_streaming(
+        &self,
+        file: &'a BibFile<'a>,
+        entries: Vec<BibEntry<'a>>,
+        sink: impl FnMut(Lint<'a>),
+    ) {
+        self.lint_file_timed(file, entries, None, sink)
+    }
+
+    /// `lint_file_streaming`, adding the time spent collecting the
+    /// entries, linting them, and linting the file to `timings`.
+    pub fn lint_file_timed(
+        &self,
+        file: &'a BibFile<'a>,
+        entries: Vec<BibEntry<'a>>,
+        timings: Option<&mut FileTimings>,
+        mut sink: impl FnMut(Lint<'a>),
+    ) {
+        let mut clock = Clock::start(timings.is_some());
+        let (mut collection, mut entry_lints, mut file_lints) = (0.0, 0.0, 0.0);
+        let mut used_keys: HashMap<&str, Vec<(Node<'a>, KeyOccurrence)>> = HashMap::new();
+        let mut doi_arxiv_sha256: HashMap<(String, String, String), Vec<Node<'a>>> =
             HashMap::new();
         let mut arxiv_with_doi : HashSet<&'a str> = HashSet::new();
         let mut arxiv_usage    : HashMap<&'a str, Vec<Node<'a>>> = HashMap::new();
+        let mut papers = vec![];
+        // the key of every entry, with its doi and title
+        let mut keyed = vec![];
+        // the keys of the `related` field of every entry, and where they are
+        let mut related = vec![];
+        // entries of the same work, by doi, or by title and author
+        // along with the identifiers (eprint, doi) that the entries have
+        type Work = (String, Option<(bool, bool)>);
+        let mut works: BTreeMap<Work, Vec<usize>> = BTreeMap::new();
+        let mut linted = vec![];
 
         // 0. check for syntax errors in the file
-        // (list error nodes as "syntax errors")
+        // (list error nodes as "syntax errors", along with the
+        // zero-width nodes that tree-sitter inserts when a token
+        // is missing, such as the closing brace at the end of the file)
         for node in file.iterate() {
             if node.kind() == "ERROR" {
-                messages.push(Lint {
+                sink(Lint {
                     msg: LintMessage::SyntaxError(file.get_slice(node).to_string()),
                     loc: vec![node],
+                    span: None,
+                });
+            } else if node.is_missing() {
+                sink(Lint {
+                    msg: LintMessage::SyntaxError(format!("missing {}", node.kind())),
+                    loc: vec![node],
+                    span: None,
+                });
+            }
+        }
+        if file.content.starts_with(BYTE_ORDER_MARK) {
+            sink(Lint {
+                msg: LintMessage::ByteOrderMark,
+                loc: vec![file.tree.root_node()],
+                span: Some(0..BYTE_ORDER_MARK.len_utf8()),
+            });
+        }
+        for marker in conflict_markers(file.content) {
+            sink(Lint {
+                msg: LintMessage::MergeConflictMarker(file.content[marker.clone()].to_string()),
+                loc: vec![file.tree.root_node()],
+                span: Some(marker),
+            });
+        }
+        if self.strict {
+            self.lint_stray_text(file).into_iter().for_each(&mut sink);
+        }
+
+        // @string definitions, by lowercase name
+        let mut strings: BTreeMap<String, Vec<BibString<'a>>> = BTreeMap::new();
+        for string in file.list_string_definitions() {
+            strings
+                .entry(file.get_slice(string.name).to_lowercase())
+                .or_default()
+                .push(string);
+        }
+        for (name, definitions) in strings {
+            let mut values = definitions
+                .iter()
+                .map(|s| string_value(file, s))
+                .collect::<HashSet<_>>();
+            if let Some(others) = self.string_definitions.get(name.as_str()) {
+                values.extend(others.iter().copied());
+            }
+            let loc = definitions.iter().map(|s| s.loc).collect::<Vec<_>>();
+            // the month macros are the strings predefined by BibTeX
+            if builtin_string(&name).is_some() {
+                sink(Lint {
+                    msg: LintMessage::ShadowedMonthMacro(name.clone()),
+                    loc: loc.clone(),
+                    span: None,
+                });
+            }
+            if values.len() > 1 {
+                sink(Lint {
+                    msg: LintMessage::DuplicateStringDefinition(name),
+                    loc,
+                    span: None,
                 });
             }
         }
+        file_lints += clock.lap();
 
         // accumulate
         // 1. accumulate errors for all the entries
         // 2. check for duplicate entries (same key)
         for entry in entries {
-            let fields = entry
-                .fields
-                .iter()
-                .map(|field| {
-                    (
-                        file.get_slice(field.name),
-                        file.get_braceless_slice(field.value),
-                    )
-                })
-                .collect::<HashMap<_, _>>();
+            let fields = field_map(file, &entry);
             let key = file.get_slice(entry.key);
             let doi = fields.get("doi").map(|s| *s).unwrap_or("");
-            let arxiv = fields.get("eprint").map(|s| *s).unwrap_or("");
+            let eprint = fields.get("eprint").copied().unwrap_or("");
+            // only arXiv eprints have versions (see the `eprints` module)
+            let arxiv = match EprintType::of_entry(file, &entry) {
+                EprintType::Arxiv => eprint,
+                _ => "",
+            };
             let sha256 = fields.get("sha256").map(|s| *s).unwrap_or("");
+            // `{10.1/ABC}` and `{{10.1/abc}}` are the same doi
+            let normalized = |name: &str| {
+                entry
+                    .fields
+                    .iter()
+                    .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(name))
+                    .map_or(String::new(), |f| normalize_value(name, file.get_slice(f.value)))
+            };
+            // the same identifier in two archives names two works
+            let mut eprint_key = normalized("eprint");
+            if EprintType::of_entry(file, &entry) != EprintType::Arxiv && !eprint_key.is_empty() {
+                let archive = match normalized("eprinttype") {
+                    archive if archive.is_empty() => normalized("archiveprefix"),
+                    archive => archive,
+                };
+                eprint_key = format!("{}:{}", archive, eprint_key);
+            }
             doi_arxiv_sha256
-                .entry((doi, arxiv, sha256))
+                .entry((normalized("doi"), eprint_key, normalized("sha256")))
                 .or_default()
                 .push(entry.loc);
 
@@ -251,28 +1546,143 @@ impl<'a> LinterState<'a> {
                 arxiv_with_doi.insert(arxiv);
             }
 
-            used_keys.entry(key).or_insert(vec![]).push(entry.loc);
-            messages.extend(self.lint_entry(file, entry));
+            let title_author = fields
+                .get("title")
+                .zip(fields.get("author"))
+                .and_then(|(title, author)| paper_key(title, author));
+            // a preprint and its published version are expected to differ
+            if let Some((title, author)) = &title_author {
+                let identifiers = (!eprint.is_empty(), !doi.is_empty());
+                works
+                    .entry((format!("{}\n{}", title, author), Some(identifiers)))
+                    .or_default()
+                    .push(linted.len());
+            }
+            if !doi.is_empty() {
+                works
+                    .entry((normalized("doi"), None))
+                    .or_default()
+                    .push(linted.len());
+            }
+            keyed.push((key, entry.key, normalized("doi"), normalized("title")));
+            let paper = title_author.zip(Version::of(!eprint.is_empty(), !doi.is_empty()));
+            papers.push((key, entry.loc, paper));
+            related.push(
+                entry
+                    .fields
+                    .iter()
+                    .find(|f| file.get_slice(f.name).eq_ignore_ascii_case(RELATED_FIELD))
+                    .map(|f| (related_keys(file.get_braceless_slice(f.value)), f.loc)),
+            );
+
+            let occurrence = KeyOccurrence::new(
+                file.get_slice(entry.entrytype),
+                fields.get("title").copied().unwrap_or(""),
+            );
+            used_keys
+                .entry(key)
+                .or_insert(vec![])
+                .push((entry.loc, occurrence));
+            collection += clock.lap();
+            self.lint_entry(file, &entry, &fields)
+                .into_iter()
+                .for_each(&mut sink);
+            if let Some(completed) = self.completed.get(&entry.loc.start_byte()) {
+                self.lint_completed_fields(&entry, completed)
+                    .into_iter()
+                    .for_each(&mut sink);
+            }
+            entry_lints += clock.lap();
+            linted.push(entry);
         }
+        collection += clock.lap();
 
-        for (key, locs) in used_keys {
-            if locs.len() > 1 {
-                messages.push(Lint {
-                    msg: LintMessage::DuplicateKey(key.to_string()),
-                    loc: locs,
+        for (keys, loc) in related.iter().flatten() {
+            for key in keys {
+                if !used_keys.contains_key(key) && !self.entry_keys.contains(key) {
+                    sink(Lint {
+                        msg: LintMessage::UnresolvedRelated(key.to_string()),
+                        loc: vec![*loc],
+                        span: None,
+                    });
+                }
+            }
+        }
+
+        for (key, entries) in used_keys {
+            if entries.len() > 1 {
+                let (loc, occurrences) = entries.into_iter().unzip();
+                sink(Lint {
+                    msg: LintMessage::DuplicateKey {
+                        key: key.to_string(),
+                        occurrences,
+                    },
+                    loc,
+                    span: None,
                 });
             }
         }
 
+        // 2b. keys that are likely typos of each other, unless
+        // their entries are the same work (reported above)
+        let mut seen = HashSet::new();
+        keyed.retain(|(key, _, _, _)| seen.insert(*key));
+        let keys = keyed.iter().map(|(key, _, _, _)| *key).collect::<Vec<_>>();
+        for (i, j) in similar_keys(&keys) {
+            let (a, b) = (&keyed[i], &keyed[j]);
+            let same = |x: &String, y: &String| !x.is_empty() && x == y;
+            if same(&a.2, &b.2) || same(&a.3, &b.3) {
+                continue;
+            }
+            sink(Lint {
+                msg: LintMessage::SimilarKeys(a.0.to_string(), b.0.to_string()),
+                loc: vec![a.1, b.1],
+                span: None,
+            });
+        }
+
         // 3. check for duplicate entries (same DOI/ARXIV/SHA256 pair)
         for ((doi, arxiv, sha), entries) in doi_arxiv_sha256.into_iter() {
             if !(doi.is_empty() && arxiv.is_empty() && sha.is_empty()) && entries.len() > 1 {
-                messages.push(Lint {
-                    msg: LintMessage::DuplicateDoiArxivSha256(doi.into(), arxiv.into(), sha.into()),
+                sink(Lint {
+                    msg: LintMessage::DuplicateDoiArxivSha256(doi, arxiv, sha),
                     loc: entries,
+                    span: None,
                 });
             }
         }
+        // 3b. duplicates of a work that disagree on a field
+        let mut pairs = BTreeSet::new();
+        for same in works.values() {
+            for (i, a) in same.iter().enumerate() {
+                pairs.extend(same[i + 1..].iter().map(|b| (*a, *b)));
+            }
+        }
+        for (a, b) in pairs {
+            self.conflicting_data(file, &linted[a], &linted[b])
+                .into_iter()
+                .for_each(&mut sink);
+        }
+        // 3c. entries disagreeing with their crossref parent
+        // (the keys of crossrefs are case insensitive)
+        let parents = linted
+            .iter()
+            .map(|entry| (file.get_slice(entry.key).to_lowercase(), entry))
+            .collect::<HashMap<_, _>>();
+        for child in linted.iter() {
+            let parent = child
+                .fields
+                .iter()
+                .find(|f| file.get_slice(f.name).eq_ignore_ascii_case("crossref"))
+                .map(|f| file.get_braceless_slice(f.value).trim().to_lowercase())
+                .and_then(|key| parents.get(&key));
+            if let Some(parent) = parent {
+                self.crossref_conflicts(file, child, parent)
+                    .into_iter()
+                    .for_each(&mut sink);
+            }
+        }
+
         // 4. outdated entries (arxiv versions)
         // - if the entry refers *only* to arxiv, then we can check outdatedness (but there may be
         // several versions of the same paper)
@@ -288,9 +1698,10 @@ impl<'a> LinterState<'a> {
                     if let Some(version) = parsed_id.version {
                         if let Some(latest) = self.arxiv_latest.get(parsed_id.id) {
                             if version < *latest {
-                                messages.push(Lint {
+                                sink(Lint {
                                     msg: LintMessage::OutdatedEntry(arxiv.to_string(), *latest, version),
-                                    loc: locs
+                                    loc: locs,
+                                    span: None,
                                 });
                             }
                         }
@@ -301,6 +1712,889 @@ impl<'a> LinterState<'a> {
         // 5. published equivalents (arxiv -> doi / doi -> arxiv)
         // TODO.
 
+        // 6. preprints cited along their published version,
+        // unless they are explicitly related
+        let relates = |a: usize, b: usize| {
+            related[a]
+                .as_ref()
+                .is_some_and(|(keys, _)| keys.contains(&papers[b].0))
+        };
+        let versions = papers.iter().map(|(_, _, p)| p.clone()).collect::<Vec<_>>();
+        for (preprint, published) in pair_preprints(&versions) {
+            if relates(preprint, published) || relates(published, preprint) {
+                continue;
+            }
+            sink(Lint {
+                msg: LintMessage::PreprintPublishedPair {
+                    preprint: papers[preprint].0.to_string(),
+                    published: papers[published].0.to_string(),
+                },
+                loc: vec![papers[preprint].1, papers[published].1],
+                span: None,
+            });
+        }
+        file_lints += clock.lap();
+
+        if let Some(timings) = timings {
+            timings.collection_ms += collection;
+            timings.entry_lints_ms += entry_lints;
+            timings.file_lints_ms += file_lints;
+        }
+    }
+
+    /// All the messages of the file, in the order of `lint_file_streaming`.
+    pub fn lint_file(&self, file: &'a BibFile<'a>, entries: Vec<BibEntry<'a>>) -> Vec<Lint<'a>> {
+        let mut messages = vec![];
+        self.lint_file_streaming(file, entries, |lint| messages.push(lint));
         messages
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_year() {
+        assert_eq!(key_year("smith2019robust"), Some(2019));
+        assert_eq!(key_year("DBLP:conf/lics/Smith21"), None);
+        assert_eq!(key_year("smith1999-2001"), None);
+        assert_eq!(key_year("arxiv2301.00001"), None);
+        assert_eq!(key_year("arxiv.2301.00001"), None);
+        assert_eq!(key_year("smith12345"), None);
+        assert_eq!(key_year("iso9001"), None);
+    }
+
+    #[test]
+    fn test_key_year_mismatch() {
+        let content = "@misc{smith2019robust, YEAR = 2021}\n\
+                       @misc{smith2021robust, year = 2021}\n\
+                       @misc{smith1999-2001, year = 2021}\n";
+        let file = BibFile::new(content);
+        let lints = file
+            .list_entries()
+            .flat_map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default().lint_entry(&file, &entry, &fields)
+            })
+            .filter(|lint| matches!(lint.msg, LintMessage::KeyYearMismatch { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(
+            format!("{:?}", lints[0].msg),
+            "KeyYearMismatch { key_year: 2019, field_year: 2021 }"
+        );
+        let locs = lints[0]
+            .loc
+            .iter()
+            .map(|loc| &content[loc.byte_range()])
+            .collect::<Vec<_>>();
+        assert_eq!(locs, vec!["smith2019robust", "YEAR = 2021"]);
+    }
+
+    #[test]
+    fn test_dates() {
+        let file = BibFile::new(
+            "@misc{a, urldate = {13/04/2023}}\n\
+             @misc{b, date = {2020-04-01}, YEAR = 2021}\n\
+             @misc{c, date = {2020/..}, year = 2020}\n\
+             @misc{d, date = {2020/..}, year = 2022}\n\
+             @misc{e, Date = {2020-04}, year = 2020}\n",
+        );
+        let dates = file
+            .list_entries()
+            .map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default()
+                    .lint_entry(&file, &entry, &fields)
+                    .into_iter()
+                    .filter(|lint| {
+                        matches!(
+                            lint.msg,
+                            LintMessage::MalformedDate(_, _) | LintMessage::DateYearMismatch { .. }
+                        )
+                    })
+                    .map(|lint| format!("{:?}", lint.msg))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dates,
+            vec![
+                vec!["MalformedDate(\"urldate\", \"13/04/2023\")"],
+                vec!["DateYearMismatch { date_year: 2020, field_year: 2021 }"],
+                vec![],
+                vec!["DateYearMismatch { date_year: 2020, field_year: 2022 }"],
+                vec![],
+            ]
+        );
+        // the malformed date can be rewritten
+        let malformed = LintMessage::MalformedDate("urldate".into(), "13/04/2023".into());
+        assert!(malformed.hint().unwrap().contains("--normalize"));
+    }
+
+    #[test]
+    fn test_field_too_long() {
+        let file = BibFile::new(
+            "@misc{a, ABSTRACT = {ééééé}}\n\
+             @misc{b, Abstract = {éééééé}, note = {éééééé}}\n",
+        );
+        let linter = LinterState {
+            max_field_length: HashMap::from([("abstract".to_string(), 5)]),
+            ..LinterState::default()
+        };
+        let lints = file
+            .list_entries()
+            .flat_map(|entry| {
+                let fields = field_map(&file, &entry);
+                linter.lint_entry(&file, &entry, &fields)
+            })
+            .filter_map(|lint| match lint.msg {
+                LintMessage::FieldTooLong(name, length, max) => Some((name, length, max)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // 6 characters, but 12 bytes, and the braces are not counted
+        assert_eq!(lints, vec![("abstract".to_string(), 6, 5)]);
+    }
+
+    #[test]
+    fn test_examples() {
+        let names = LintMessage::examples()
+            .iter()
+            .map(|msg| msg.rule_name())
+            .collect::<Vec<_>>();
+        let unique = names.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), names.len());
+        assert_eq!(names[0], "syntax-error");
+        assert_eq!(names.last(), Some(&"fragile-doi-redirect"));
+    }
+
+    #[test]
+    fn test_lint_file_streaming() {
+        let file = BibFile::new(
+            "@article{a, title = {T}, year = 2020}\n\
+             @article{a, title = {T}, author = {Doe, Jane}, year = 2020, doi = {http://doi.org/10.1/x}}\n",
+        );
+        let linter = LinterState::default();
+        let mut streamed = vec![];
+        linter.lint_file_streaming(&file, file.list_entries().collect(), |lint| {
+            streamed.push(lint.msg.rule_name())
+        });
+        let collected = linter
+            .lint_file(&file, file.list_entries().collect())
+            .iter()
+            .map(|lint| lint.msg.rule_name())
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, collected);
+        // the duplicate keys come after the messages of the entries
+        assert_eq!(streamed.last(), Some(&"duplicate-key"));
+        assert!(streamed.contains(&"http-doi"));
+    }
+
+    #[test]
+    fn test_duplicate_eprints_of_archives() {
+        let file = BibFile::new(
+            "@misc{a, eprint = {2101.00001v2}}\n\
+             @misc{b, eprint = {2101.00001}, eprinttype = {arXiv}}\n\
+             @misc{c, eprint = {2101.00001}, eprinttype = {jstor}}\n",
+        );
+        let lints = LinterState::default().lint_file(&file, file.list_entries().collect());
+        let duplicates = lints
+            .iter()
+            .filter_map(|lint| match &lint.msg {
+                LintMessage::DuplicateDoiArxivSha256(_, eprint, _) => {
+                    Some((eprint.as_str(), lint.loc.len()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(duplicates, vec![("2101.00001", 2)]);
+    }
+
+    #[test]
+    fn test_duplicate_key_occurrences() {
+        let file = BibFile::new(
+            "@article{a, title = {Robust parsing of\n  bibliographies}, year = 2020}\n\
+             @Misc{a, title = {Robust parsing of bibliographies, with a very long subtitle}}\n",
+        );
+        let lints = LinterState::default().lint_file(&file, file.list_entries().collect());
+        let duplicate = lints
+            .iter()
+            .find_map(|lint| match &lint.msg {
+                LintMessage::DuplicateKey { key, occurrences } => Some((key, occurrences)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(duplicate.0, "a");
+        assert_eq!(
+            duplicate.1,
+            &vec![
+                KeyOccurrence::new("article", "Robust parsing of bibliographies"),
+                KeyOccurrence {
+                    entry_type: "Misc".to_string(),
+                    title: "Robust parsing of bibliographies, with a…".to_string(),
+                },
+            ]
+        );
+        assert_eq!(KeyOccurrence::new("@book", "").entry_type, "book");
+    }
+
+    #[test]
+    fn test_missing_pages_with_articleno() {
+        let file = BibFile::new(
+            "@article{a, title = {T}, author = {Doe, Jane}, year = 2020, ArticleNo = {17}, numpages = {25}}\n\
+             @article{b, title = {T}, author = {Doe, Jane}, year = 2020, articleno = {17}, pages = {17:1--17:25}}\n",
+        );
+        let lints = file
+            .list_entries()
+            .map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default()
+                    .lint_entry(&file, &entry, &fields)
+                    .iter()
+                    .any(|lint| matches!(lint.msg, LintMessage::MissingPagesWithArticleNo))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lints, vec![true, false]);
+    }
+
+    #[test]
+    fn test_unbalanced_braces() {
+        let content = "@article{a, title = {The {L TL problem},\n  author = {Doe, J.},\n  year = 2020,\n}\n\
+                       @article{b, title = {The \\{L\\} TL problem}, author = {Doe, J.}, year = 2020}\n";
+        let file = BibFile::new(content);
+        let lints = file
+            .list_entries()
+            .map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default().lint_entry(&file, &entry, &fields)
+            })
+            .collect::<Vec<_>>();
+        // the missing author and year are a symptom
+        let rules = lints[0]
+            .iter()
+            .map(|lint| lint.msg.rule_name())
+            .collect::<Vec<_>>();
+        assert!(rules.contains(&"unbalanced-braces"));
+        assert!(!rules.contains(&"missing-field"));
+        let lint = lints[0]
+            .iter()
+            .find(|lint| matches!(lint.msg, LintMessage::UnbalancedBraces(_)))
+            .unwrap();
+        assert_eq!(lint.span, Some(20..21));
+        // escaped braces are balanced
+        assert!(
+            lints[1]
+                .iter()
+                .all(|lint| !matches!(lint.msg, LintMessage::UnbalancedBraces(_)))
+        );
+    }
+
+    #[test]
+    fn test_against() {
+        let mut library = ReferenceIndex::default();
+        library.import(&BibFile::new("@article{doe2020, doi = {10.1145/ABC}}\n"));
+        let content =
+            "@misc{a, doi = {https://doi.org/10.1145/abc}}\n@misc{b, doi = {10.1145/abd}}\n";
+        let file = BibFile::new(content);
+        let lint = |against_invert: bool| {
+            let linter = LinterState {
+                against: Some(&library),
+                against_invert,
+                ..LinterState::default()
+            };
+            file.list_entries()
+                .map(|entry| {
+                    let fields = field_map(&file, &entry);
+                    linter
+                        .lint_entry(&file, &entry, &fields)
+                        .into_iter()
+                        .map(|lint| lint.msg)
+                        .find(|msg| msg.rule_name().ends_with("in-database"))
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(matches!(
+            lint(false)[..],
+            [Some(LintMessage::AlreadyInDatabase(ref key)), None] if key == "doe2020"
+        ));
+        assert!(matches!(
+            lint(true)[..],
+            [None, Some(LintMessage::NotInDatabase)]
+        ));
+    }
+
+    #[test]
+    fn test_header_style() {
+        let content = crate::read_fixture("syntax", "ugly_headers.bib");
+        let file = BibFile::new(&content);
+        let lints = file
+            .list_entries()
+            .flat_map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default().lint_entry(&file, &entry, &fields)
+            })
+            .filter(|lint| {
+                matches!(
+                    lint.msg,
+                    LintMessage::EntryTypeCase(_) | LintMessage::StraySpacingInHeader
+                )
+            })
+            .collect::<Vec<_>>();
+        let spans = lints
+            .iter()
+            .map(|lint| (lint.msg.rule_name(), &content[lint.span.clone().unwrap()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                ("entry-type-case", "@ARTICLE"),
+                ("stray-spacing-in-header", " {doe2020"),
+                ("stray-spacing-in-header", "  { roe2021 "),
+                ("entry-type-case", "@InProceedings"),
+                ("stray-spacing-in-header", "( poe2022"),
+            ]
+        );
+        // the suggestions fix every header, as the formatter does
+        let mut fixed = content.clone();
+        for lint in lints.iter().rev() {
+            let (span, replacement) = lint.suggestion(&file).unwrap();
+            fixed.replace_range(span, &replacement);
+        }
+        assert!(fixed.starts_with("@article{doe2020,\n"));
+        assert!(fixed.contains("\n@article{roe2021,\n"));
+        assert!(fixed.contains("\n@inproceedings(poe2022,\n"));
+    }
+
+    #[test]
+    fn test_malformed_pages() {
+        let file = BibFile::new(
+            "@article{a, title = {T}, author = {Doe, Jane}, year = 2020, pages = {pp. 101–115}}\n\
+             @article{b, title = {T}, author = {Doe, Jane}, year = 2020, pages = {xi--xv}}\n\
+             @article{c, title = {T}, author = {Doe, Jane}, year = 2020, pages = {electronic}}\n",
+        );
+        let lints = file
+            .list_entries()
+            .map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default()
+                    .lint_entry(&file, &entry, &fields)
+                    .into_iter()
+                    .find_map(|lint| match lint.msg {
+                        LintMessage::MalformedPages(value, issues) => Some((value, issues)),
+                        _ => None,
+                    })
+            })
+            .collect::<Vec<_>>();
+        let owned = |value: &str, issues: &[&str]| {
+            Some((
+                value.to_string(),
+                issues.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+            ))
+        };
+        assert_eq!(
+            lints,
+            vec![
+                owned("pp. 101–115", &["prefix", "unicode-dash"]),
+                None,
+                owned("electronic", &["textual"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_title_case_suspicious() {
+        let content = "@article{a, title = {THE COMPLEXITY OF {LTL}}, author = {Doe, Jane}, year = 2020}
+                       @article{b, title = \"the complexity of ltl\", author = {Doe, Jane}, year = 2020}
+                       @article{c, title = {The Complexity of {LTL}}, author = {Doe, Jane}, year = 2020}
+                       @article{d, title = {{THE COMPLEXITY OF LTL}}, author = {Doe, Jane}, year = 2020}
+";
+        let file = BibFile::new(content);
+        let lints = file
+            .list_entries()
+            .filter_map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default()
+                    .lint_entry(&file, &entry, &fields)
+                    .into_iter()
+                    .find(|lint| matches!(lint.msg, LintMessage::TitleCaseSuspicious(_)))
+            })
+            .collect::<Vec<_>>();
+        let found = lints
+            .iter()
+            .map(|lint| {
+                (
+                    format!("{:?}", lint.msg),
+                    &content[lint.span.clone().unwrap()],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "TitleCaseSuspicious(AllUpper)".to_string(),
+                    "THE COMPLEXITY OF {LTL}"
+                ),
+                (
+                    "TitleCaseSuspicious(AllLower)".to_string(),
+                    "the complexity of ltl"
+                ),
+            ]
+        );
+        assert!(!lints[0].msg.is_crucial());
+    }
+
+    #[test]
+    fn test_http_doi_suggestion() {
+        let content = "@article{a, title = {T}, author = {Doe, Jane}, year = 2020, doi = {https://doi.org/10.x/y}}";
+        let file = BibFile::new(content);
+        let entry = file.list_entries().next().unwrap();
+        let fields = field_map(&file, &entry);
+        let lints = LinterState::default().lint_entry(&file, &entry, &fields);
+        let lint = lints
+            .iter()
+            .find(|lint| matches!(lint.msg, LintMessage::HttpDoi))
+            .unwrap();
+        // the span is the inside of the braces
+        let span = lint.span.clone().unwrap();
+        assert_eq!(&content[span], "https://doi.org/10.x/y");
+        let (span, replacement) = lint.suggestion(&file).unwrap();
+        let mut fixed = content.to_string();
+        fixed.replace_range(span, &replacement);
+        assert!(fixed.ends_with("doi = {10.x/y}}"));
+        // normalizing an arXiv identifier does not make it a doi
+        assert!(LintMessage::ArxivAsDoi.hint().unwrap().contains("eprint"));
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let lints = |content: &str| {
+            let file = BibFile::new(content);
+            let entry = file.list_entries().next().unwrap();
+            let fields = field_map(&file, &entry);
+            LinterState::default()
+                .lint_entry(&file, &entry, &fields)
+                .into_iter()
+                .filter(|lint| {
+                    matches!(
+                        lint.msg,
+                        LintMessage::InconsistentIdentifiers { .. } | LintMessage::RedundantUrl
+                    )
+                })
+                .map(|lint| format!("{:?}", lint.msg))
+                .collect::<Vec<_>>()
+        };
+        let mismatch = |a: &str| {
+            vec![format!(
+                "{:?}",
+                LintMessage::InconsistentIdentifiers {
+                    field_a: a.to_string(),
+                    field_b: "url".to_string(),
+                }
+            )]
+        };
+        // the field names are matched up to case
+        assert_eq!(
+            lints("@misc{a, DOI = {10.1/Y}, URL = {https://doi.org/10.1/X}}"),
+            mismatch("doi")
+        );
+        assert_eq!(
+            lints("@misc{a, doi = {10.1/x}, url = {https://doi.org/10.1/X}}"),
+            vec!["RedundantUrl"]
+        );
+        // a landing page under the doi is not redundant
+        assert!(
+            lints("@misc{a, doi = {10.1/x}, url = {https://doi.org/10.1/x?page=2}}").is_empty()
+        );
+        assert_eq!(
+            lints("@misc{a, eprint = {2101.00001}, url = {https://arxiv.org/abs/2101.00002}}"),
+            mismatch("eprint")
+        );
+        assert_eq!(
+            lints("@misc{a, eprint = {2101.00001v2}, url = {https://arxiv.org/abs/2101.00001v1}}"),
+            mismatch("eprint")
+        );
+        // a url without version matches every version
+        assert!(
+            lints("@misc{a, eprint = {2101.00001v2}, url = {https://arxiv.org/abs/2101.00001}}")
+                .is_empty()
+        );
+        // the eprints of other archives are not arXiv identifiers
+        assert!(
+            lints(
+                "@misc{a, eprint = {2101.00001}, eprinttype = {hal}, url = {https://arxiv.org/abs/2101.00002}}"
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_redundant_url_suggestion() {
+        let content =
+            "@misc{a,\n  doi = {10.1/x},\n  url = {https://doi.org/10.1/x},\n  year = 2020,\n}\n";
+        let file = BibFile::new(content);
+        let entry = file.list_entries().next().unwrap();
+        let fields = field_map(&file, &entry);
+        let lints = LinterState::default().lint_entry(&file, &entry, &fields);
+        let lint = lints
+            .iter()
+            .find(|lint| matches!(lint.msg, LintMessage::RedundantUrl))
+            .unwrap();
+        assert!(lint.msg.hint().unwrap().contains("remove the url"));
+        let (span, replacement) = lint.suggestion(&file).unwrap();
+        let mut fixed = content.to_string();
+        fixed.replace_range(span, &replacement);
+        assert_eq!(fixed, "@misc{a,\n  doi = {10.1/x},\n  year = 2020,\n}\n");
+    }
+
+    #[test]
+    fn test_unescaped_specials() {
+        let specials = |value: &str| {
+            unescaped_specials(value, 0)
+                .into_iter()
+                .map(|(span, c)| (span.start, c))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(specials("{Accuracy 95\\% on A\\&B}"), vec![]);
+        assert_eq!(specials("{95% on A & B}"), vec![(3, '%'), (10, '&')]);
+        assert_eq!(specials("\"R&D {in 100%}\""), vec![(2, '&'), (12, '%')]);
+        // a line break, then a comment
+        assert_eq!(specials("{a\\\\% b}"), vec![(4, '%')]);
+        // the urls are taken as they are
+        assert_eq!(
+            specials("{\\url{https://a.org/?x=1&y=%20} & \\href{https://a.org/#%20}{a}}"),
+            vec![(32, '&')]
+        );
+    }
+
+    #[test]
+    fn test_escaped_specials_lints() {
+        let content = "@article{a, title = {T}, author = {Doe, Jane}, year = 2020,\n\
+                       note = {Accuracy 95\\% on R\\&D}, journal = {Taylor & Francis},\n\
+                       url = {https://a.org/?x=1&y=%20}}";
+        let file = BibFile::new(content);
+        let entry = file.list_entries().next().unwrap();
+        let fields = field_map(&file, &entry);
+        let lints = LinterState::default().lint_entry(&file, &entry, &fields);
+        // the escapes are not weird
+        assert!(
+            !lints
+                .iter()
+                .any(|lint| matches!(lint.msg, LintMessage::WeirdCharacters(_)))
+        );
+        let unescaped = lints
+            .iter()
+            .filter(|lint| matches!(lint.msg, LintMessage::UnescapedSpecialCharacter(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(unescaped.len(), 1);
+        assert!(!unescaped[0].msg.is_crucial());
+        let (span, replacement) = unescaped[0].suggestion(&file).unwrap();
+        let mut fixed = content.to_string();
+        fixed.replace_range(span, &replacement);
+        assert!(fixed.contains("journal = {Taylor \\& Francis}"));
+    }
+
+    #[test]
+    fn test_multibyte_weird_characters() {
+        // U+0085 is a control character of two bytes
+        let content = "@misc{a, title = {Next\u{85}line here}}";
+        let file = BibFile::new(content);
+        let entry = file.list_entries().next().unwrap();
+        let fields = field_map(&file, &entry);
+        let lints = LinterState::default().lint_entry(&file, &entry, &fields);
+        let weird = lints
+            .iter()
+            .find_map(|lint| match &lint.msg {
+                LintMessage::WeirdCharacters(s) => Some((s, lint.span.clone().unwrap())),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(weird.0, "\u{85}line");
+        assert_eq!(&content[weird.1], "\u{85}line");
+    }
+
+    #[cfg(feature = "spellcheck")]
+    #[test]
+    fn test_possible_typo() {
+        use crate::spellcheck::SpellChecker;
+        let file = BibFile::new(
+            "@misc{a, title = {Deep lerning of {Lerning} and $lerning$}, note = {lerning}}\n",
+        );
+        let typos = |linter: &LinterState| {
+            file.list_entries()
+                .flat_map(|entry| {
+                    let fields = field_map(&file, &entry);
+                    linter.lint_entry(&file, &entry, &fields)
+                })
+                .filter_map(|lint| match lint.msg {
+                    LintMessage::PossibleTypo(word, suggestions) => {
+                        Some((file.content[lint.span.unwrap()].to_string(), word, suggestions))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(typos(&LinterState::default()), vec![]);
+        let linter = LinterState {
+            spellcheck: Some(SpellChecker::new(vec![])),
+            ..LinterState::default()
+        };
+        assert_eq!(
+            typos(&linter),
+            vec![(
+                "lerning".to_string(),
+                "lerning".to_string(),
+                vec!["learning".to_string()]
+            )]
+        );
+        let linter = LinterState {
+            spellcheck: Some(SpellChecker::new(vec!["lerning".to_string()])),
+            ..LinterState::default()
+        };
+        assert_eq!(typos(&linter), vec![]);
+    }
+
+    #[test]
+    fn test_conflict_markers_and_stray_text() {
+        let lints = |content: &str, linter: &LinterState| {
+            let file = BibFile::new(content);
+            linter
+                .lint_file(&file, file.list_entries().collect())
+                .into_iter()
+                .filter_map(|lint| match lint.msg {
+                    LintMessage::MergeConflictMarker(marker) => Some(("marker", marker)),
+                    LintMessage::StrayTopLevelText(text) => Some(("stray", text)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let strict = LinterState {
+            strict: true,
+            ..LinterState::default()
+        };
+
+        // between the entries
+        let outside = crate::read_fixture("conflicts", "outside.bib");
+        let markers = vec![
+            ("marker", "<<<<<<< HEAD".to_string()),
+            ("marker", "=======".to_string()),
+            ("marker", ">>>>>>> other".to_string()),
+        ];
+        assert_eq!(lints(&outside, &LinterState::default()), markers);
+        let mut expected = markers.clone();
+        expected.push(("stray", "Some notes left between the entries.".to_string()));
+        assert_eq!(lints(&outside, &strict), expected);
+
+        // inside of an entry, the markers are not stray text
+        let inside = crate::read_fixture("conflicts", "inside.bib");
+        let found = lints(&inside, &strict);
+        assert_eq!(found.iter().filter(|(kind, _)| *kind == "marker").count(), 3);
+        assert!(found[0].1.starts_with("<<<<<<<"));
+    }
+
+    #[test]
+    fn test_missing_nodes() {
+        let syntax_errors = |content: &str| {
+            let file = BibFile::new(content);
+            LinterState::default()
+                .lint_file(&file, file.list_entries().collect())
+                .into_iter()
+                .filter_map(|lint| match lint.msg {
+                    LintMessage::SyntaxError(error) => {
+                        Some((error, lint.loc[0].start_position().row))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the closing brace of the last entry is missing
+        let errors = syntax_errors(&crate::read_fixture("syntax", "unclosed_entry.bib"));
+        assert!(
+            errors
+                .iter()
+                .any(|(e, line)| e == "missing }" && *line >= 5),
+            "{:?}",
+            errors
+        );
+
+        // a value that is not closed in the middle of the file
+        let errors = syntax_errors(&crate::read_fixture("syntax", "unclosed_value.bib"));
+        assert!(!errors.is_empty());
+        assert!(syntax_errors("@misc{a, title = {A}}\n").is_empty());
+    }
+
+    #[test]
+    fn test_author_editor_confusion() {
+        let file = BibFile::new(
+            "@proceedings{a, Author = {Doe, Jane}, title = {Proc.}, year = 2020}\n\
+             @proceedings{b, editor = {Doe, Jane}, title = {Proc.}, year = 2020}\n\
+             @incollection{c, editor = {Doe, Jane}, title = {Part}, year = 2020}\n\
+             @incollection{d, author = {Roe, Rick}, editor = {Doe, Jane}, title = {Part}, year = 2020}\n\
+             @book{e, editor = {Doe, Jane}, title = {Book}, year = 2020}\n",
+        );
+        let lints = file
+            .list_entries()
+            .map(|entry| {
+                let fields = field_map(&file, &entry);
+                LinterState::default()
+                    .lint_entry(&file, &entry, &fields)
+                    .into_iter()
+                    .filter_map(|lint| match lint.msg {
+                        LintMessage::AuthorEditorConfusion { found, expected } => {
+                            Some(format!("{} -> {}", found, expected))
+                        }
+                        LintMessage::MissingField(f) => Some(format!("missing {}", f)),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lints,
+            vec![
+                vec!["author -> editor"],
+                // the editors of proceedings stand for their authors
+                vec![],
+                vec!["missing author", "editor -> author"],
+                vec![],
+                vec!["missing author"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_year_values() {
+        let file = BibFile::new(
+            "@misc{a, year = 2020}\n\
+             @misc{b, year = {2023--2024}}\n\
+             @misc{c, year = {Forthcoming}}\n\
+             @misc{d, year = \"in press\"}\n\
+             @misc{e, year = {unpublished}}\n\
+             @misc{f, year = {2024--2023}}\n\
+             @misc{g, year = thisyear}\n",
+        );
+        let years = |linter: &LinterState| {
+            file.list_entries()
+                .flat_map(|entry| {
+                    let fields = field_map(&file, &entry);
+                    linter.lint_entry(&file, &entry, &fields)
+                })
+                .filter_map(|lint| match lint.msg {
+                    LintMessage::InvalidYear(v) => Some(format!("invalid {}", v)),
+                    LintMessage::ProvisionalYear(v) => Some(format!("provisional {}", v)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            years(&LinterState::default()),
+            vec![
+                "provisional forthcoming",
+                "provisional in press",
+                "invalid unpublished",
+                "invalid 2024--2023",
+            ]
+        );
+        let linter = LinterState {
+            year_status_words: Some(vec!["Unpublished".to_string()]),
+            ..LinterState::default()
+        };
+        assert_eq!(
+            years(&linter),
+            vec![
+                "invalid Forthcoming",
+                "invalid in press",
+                "provisional unpublished",
+                "invalid 2024--2023",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_similar_keys() {
+        let file = BibFile::new(
+            "@book{vanbenthem1984, title = {Correspondence Theory}}\n\
+             @book{vanbethem1984, title = {Modal Logic}}\n\
+             @article{turing1936, title = {On Computable Numbers}, doi = {10.1112/plms/s2-42.1.230}}\n\
+             @article{turng1936, title = {On computable numbers}, doi = {10.1112/PLMS/s2-42.1.230}}\n\
+             @misc{smith2020a, title = {A}}\n\
+             @misc{smith2020b, title = {B}}\n",
+        );
+        let similar = LinterState::default()
+            .lint_file(&file, file.list_entries().collect())
+            .into_iter()
+            .filter_map(|lint| match lint.msg {
+                LintMessage::SimilarKeys(a, b) => Some((a, b, lint.loc.len())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // the same work under two keys is reported as a duplicate
+        assert_eq!(
+            similar,
+            vec![("vanbenthem1984".to_string(), "vanbethem1984".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_duplicate_data() {
+        let file = BibFile::new(
+            "@article{a, title = {On {T}hings}, author = {Doe, Jane}, doi = {10.1/X}, year = 2020, pages = {1--10}, note = {A}}\n\
+             @article{b, title = {On things}, author = {Doe, J.}, doi = {10.1/x}, year = 2021, pages = {1--10}, note = {B}}\n\
+             @inproceedings{c, title = {Other}, author = {Roe, Rick}, booktitle = {LICS}}\n\
+             @inproceedings{d, title = {Other}, author = {Roe, R.}, booktitle = {ICALP}, volume = {}}\n\
+             @misc{e, title = {Other}, author = {Roe, Rick}, eprint = {2101.00001}, year = 2019}\n",
+        );
+        let conflicts = |linter: &LinterState| {
+            linter
+                .lint_file(&file, file.list_entries().collect())
+                .into_iter()
+                .filter_map(|lint| match lint.msg {
+                    LintMessage::ConflictingDuplicateData(field, a, b) => Some((field, a, b)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let owned =
+            |field: &str, a: &str, b: &str| (field.to_string(), a.to_string(), b.to_string());
+        // the authors (written with their initials) and the notes are not
+        // compared, nor the preprint `e` to its published version
+        assert_eq!(
+            conflicts(&LinterState::default()),
+            vec![
+                owned("year", "2020", "2021"),
+                owned("booktitle", "LICS", "ICALP"),
+            ]
+        );
+        let linter = LinterState {
+            conflict_ignored_fields: Some(vec!["author".to_string(), "booktitle".to_string()]),
+            ..LinterState::default()
+        };
+        assert_eq!(conflicts(&linter), vec![owned("year", "2020", "2021")]);
+    }
+
+    #[test]
+    fn test_crossref_field_conflict() {
+        let file = BibFile::new(
+            "@inproceedings{a, title = {On Things}, author = {Doe, Jane}, crossref = {ICALP20}, booktitle = {ICALP 2020}, year = 2020}\n\
+             @inproceedings{b, title = {Other}, author = {Roe, Rick}, crossref = {icalp20}, booktitle = {{47th ICALP}}, pages = {1--10}}\n\
+             @inproceedings{c, title = {Lost}, author = {Roe, Rick}, crossref = {lics20}, booktitle = {LICS}}\n\
+             @proceedings{icalp20, title = {Proceedings of ICALP}, booktitle = {47th {ICALP}}, year = {2020}, pages = {1--100}}\n",
+        );
+        let conflicts = LinterState::default()
+            .lint_file(&file, file.list_entries().collect())
+            .into_iter()
+            .filter_map(|lint| match lint.msg {
+                LintMessage::CrossrefFieldConflict(field, child, parent) => {
+                    Some(format!("{}: {} / {}", field, child, parent))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // the titles and pages are not inherited,
+        // and the parent of `c` is not in the file
+        assert_eq!(conflicts, vec!["booktitle: ICALP 2020 / 47th {ICALP}"]);
+    }
+}
@@ -0,0 +1,146 @@
+//! This file is responsible for the logs of `bibadac`.
+//!
+//! Three outputs are configured:
+//! - human readable logs on stderr, filtered by `--log-level`
+//!   (or `RUST_LOG`, and `warn` by default)
+//! - the progress of long running commands on stderr too (stdout
+//!   being the output of the commands), that is, the info events
+//!   of the `PROGRESS` target
+//! - optionally, the same logs as stderr as JSON lines in a file
+//!
+//! When the progress is displayed, it is not repeated on stderr. The
+//! progress events start with a plain marker such as `[ERR]`, colored
+//! by the progress layer only, so that the JSON logs hold no escapes.
+use std::fmt::Write;
+use std::path::Path;
+
+use colored::Colorize;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, fmt};
+
+/// Target of the events describing the progress of a command.
+pub const PROGRESS: &str = "bibadac::progress";
+
+/// The line of a progress event, its leading `[...]` marker colored.
+fn colored_marker(message: &str) -> String {
+    let Some(end) = message.strip_prefix('[').and_then(|m| m.find(']')) else {
+        return message.to_string();
+    };
+    let (marker, rest) = message.split_at(end + 2);
+    let marker = match marker {
+        "[ERR]" => marker.red(),
+        "[WARN]" => marker.yellow(),
+        "[TOTAL]" | "[TOTAL BIB]" | "[TOTAL PDF]" | "[RESUME]" => marker.blue(),
+        _ => marker.green(),
+    };
+    format!("{}{}", marker, rest)
+}
+
+/// The message of an event.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+/// Writes the message of the progress events, their marker colored
+/// when the layer writes ANSI escapes.
+struct ProgressFormat;
+
+impl<S, N> FormatEvent<S, N> for ProgressFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let mut message = Message::default();
+        event.record(&mut message);
+        if writer.has_ansi_escapes() {
+            writeln!(writer, "{}", colored_marker(&message.0))
+        } else {
+            writeln!(writer, "{}", message.0)
+        }
+    }
+}
+
+pub fn init(level: Option<&str>, json: Option<&Path>, progress: bool) -> std::io::Result<()> {
+    // --log-level takes precedence over RUST_LOG
+    let filter = || match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+    };
+
+    let stderr_filter = if progress {
+        filter().add_directive(
+            format!("{}=off", PROGRESS)
+                .parse()
+                .expect("Invalid progress directive"),
+        )
+    } else {
+        filter()
+    };
+    let stderr = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(stderr_filter);
+
+    let progress = progress.then(|| {
+        fmt::layer()
+            .with_writer(std::io::stderr)
+            .event_format(ProgressFormat)
+            .with_filter(Targets::new().with_target(PROGRESS, LevelFilter::INFO))
+    });
+
+    let json = match json {
+        Some(path) => {
+            let file = std::fs::File::create(path)?;
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_writer(std::sync::Mutex::new(file))
+                    .with_filter(filter()),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(progress)
+        .with(json)
+        .init();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colored_marker() {
+        let line = colored_marker("[ERR] Could not find pdf for knuth84");
+        assert_eq!(
+            line,
+            format!("{} Could not find pdf for knuth84", "[ERR]".red())
+        );
+        // the padding of the marker is kept out of the escapes
+        let line = colored_marker("[PDF]     \t10.1/x");
+        assert_eq!(line, format!("{}     \t10.1/x", "[PDF]".green()));
+        assert_eq!(colored_marker("no marker"), "no marker");
+    }
+}
@@ -1,12 +1,28 @@
 /// This is the `bibadac` program to handle bibliographic data
 /// written using the BibTeX/BibLaTeX formats.
 ///
-/// The program contains 3 subcommands:
+/// The program contains the subcommands:
 /// - `check`: check the validity of a BibTeX/BibLaTeX file
 /// - `format`: format a BibTeX/BibLaTeX file
 /// - `setup`: download pdfs that are mentionned in the file
+/// - `merge`: merge BibTeX/BibLaTeX files, resolving duplicate entries
+/// - `split`: split a BibTeX/BibLaTeX file into several files
+/// - `extract`: extract the entries cited by LaTeX files
+/// - `keygen`: print the keys generated from a pattern
+/// - `authors`: print the people whose name is spelled in different ways
+/// - `fingerprint`: print the fingerprint of every entry
+/// - `score`: print the completeness score of every entry
+/// - `export`: print the entries one per line (NDJSON)
+/// - `import`: write the entries printed by `export` as BibTeX
+/// - `assert`: check that the entries satisfy conditions
+/// - `sync`: compare the pdfs of a directory with the files of a BibTeX file
+/// - `trend`: print the number of errors of every rule across JSON reports
+/// - `cache`: inspect and clear the cache of downloaded metadata
+/// - `doctor`: check the environment and the input files
+/// - `debug`: inspect how bibadac reads a file
+/// - `schema`: print the JSON Schema of the report of `check --to-json`
 ///
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::io::Read;
 
 use colored::Colorize;
@@ -15,38 +31,69 @@ use std::process::ExitCode;
 
 use bibadac::arxiv_identifiers::ArxivId;
 use bibadac::bibdb::LocalBibDb;
-use bibadac::bibtex::BibFile;
-use bibadac::format::{write_bibfile, FormatOptions};
-use bibadac::linter::{Lint, LintMessage, LinterState};
+use bibadac::bibliography::{Diagnostic, FormatSettings, LintConfig};
+use bibadac::eprints::EprintType;
+use bibadac::field_order::FieldOrder;
+use bibadac::field_patterns::FieldPatterns;
+use bibadac::format::FormatOptions;
+use bibadac::interrupt;
+use bibadac::linter::LintMessage;
+use bibadac::profiles::{changed_settings, Profiles};
+use bibadac::Bibliography;
+use bibadac::logging::PROGRESS;
+use bibadac::report::{excerpt, excerpt_around, fixable_tag, lint_message, ruler, EXCERPT_WIDTH};
+use bibadac::report::{InputHash, JsonReport, ReportedFile, SCHEMA_VERSION};
+use bibadac::severity::RuleSeverities;
+use bibadac::timings::{timings_table, FileTimings};
 
 use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
-fn windowed(s: &str, start: usize, end: usize, window_size: usize) -> (&str, &str, &str) {
-    let new_start_attempt = start.saturating_sub(window_size);
-    let new_end_attempt = end + window_size;
-    let new_start = s
-        .char_indices()
-        .nth(new_start_attempt)
-        .map(|(i, _)| i)
-        .unwrap_or(0);
-    let new_end = s
-        .char_indices()
-        .nth(new_end_attempt)
-        .map(|(i, _)| i)
-        .unwrap_or(s.len());
-    (&s[new_start..start], &s[start..end], &s[end..new_end])
-}
-
 #[derive(Debug, Clone, Parser)]
 #[command(name = "bibadac")]
 #[command(about = "A tool to handle bibliographic data")]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Verbosity of the logs on stderr (defaults to RUST_LOG, or warn)"
+    )]
+    log_level: Option<LogLevel>,
+    #[arg(long, global = true, help = "Also write the logs as JSON lines to a file")]
+    log_json: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "Refuse every network request (also BIBADAC_OFFLINE=1)"
+    )]
+    offline: bool,
     #[command(subcommand)]
     command: SubCommand,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Subcommand)]
 enum SubCommand {
     #[command(
@@ -60,112 +107,1314 @@ enum SubCommand {
         about = "Download pdfs that are mentionned in the file",
         arg_required_else_help = true
     )]
-    Setup(SetupArgs),
+    Setup(SetupArgs),
+    #[command(
+        about = "Merge BibTeX/BibLaTeX files, resolving duplicate entries",
+        arg_required_else_help = true
+    )]
+    Merge(MergeArgs),
+    #[command(
+        about = "Split a BibTeX/BibLaTeX file by year, type, or field",
+        arg_required_else_help = true
+    )]
+    Split(SplitArgs),
+    #[command(
+        about = "Extract the entries cited by LaTeX files from a BibTeX/BibLaTeX file",
+        arg_required_else_help = true
+    )]
+    Extract(ExtractArgs),
+    #[command(
+        about = "Print the keys generated from a pattern next to the current keys",
+        arg_required_else_help = true
+    )]
+    Keygen(KeygenArgs),
+    #[command(
+        about = "Print the people whose name is spelled in different ways across the files",
+        arg_required_else_help = true
+    )]
+    Authors(AuthorsArgs),
+    #[command(
+        about = "Print the fingerprint of every entry, which only changes with its content",
+        arg_required_else_help = true
+    )]
+    Fingerprint(FingerprintArgs),
+    #[command(
+        about = "Print the completeness score of every entry, the worst first, to decide what to clean",
+        arg_required_else_help = true
+    )]
+    Score(ScoreArgs),
+    #[command(
+        about = "Print the entries one per line (NDJSON), with their fields as written, for scripts",
+        arg_required_else_help = true
+    )]
+    Export(ExportArgs),
+    #[command(
+        about = "Write the entries printed by `export` (read from stdin by default) as BibTeX"
+    )]
+    Import(ImportArgs),
+    #[command(
+        about = "Check that the entries satisfy conditions, e.g. before a release",
+        arg_required_else_help = true
+    )]
+    Assert(AssertArgs),
+    #[command(
+        about = "Compare the pdfs of a directory with the files of a BibTeX file",
+        arg_required_else_help = true
+    )]
+    Sync(SyncArgs),
+    #[command(
+        about = "Print the number of errors of every rule across the JSON reports of a directory",
+        arg_required_else_help = true
+    )]
+    Trend(TrendArgs),
+    #[command(
+        about = "Inspect and clear the cache of downloaded metadata",
+        arg_required_else_help = true
+    )]
+    Cache(CacheArgs),
+    #[command(
+        about = "Check the environment and the input files, suggesting fixes (exit code 1 for warnings, 2 for failures)"
+    )]
+    Doctor(DoctorArgs),
+    #[command(
+        about = "Inspect how bibadac reads a file, e.g. to understand a syntax error",
+        arg_required_else_help = true
+    )]
+    Debug(DebugArgs),
+    #[command(about = "Print the JSON Schema of the report of `check --to-json`")]
+    Schema,
+}
+
+#[derive(Debug, Clone, Args)]
+struct FileArgs {
+    #[arg(
+        short,
+        long,
+        help = "Read BibTeX from stdin, set to true in case no bibfiles are provided"
+    )]
+    stdin: bool,
+    #[arg(
+        long,
+        requires = "stdin",
+        help = "Path of the file read from stdin, used in the reports (repeat it to read several documents separated by NUL bytes)"
+    )]
+    stdin_filename: Vec<std::path::PathBuf>,
+    #[arg(
+        long = "format",
+        value_enum,
+        help = "Format of the inputs (guessed from the extension by default)"
+    )]
+    input_format: Option<InputFormat>,
+    /// BibTeX/BibLaTeX files to read
+    bib: Vec<std::path::PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+    /// BibTeX/BibLaTeX files
+    Bib,
+    /// Markdown/Quarto documents, with entries in ```{=bibtex} code blocks
+    Md,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Config {
+    check: CheckConfig,
+    format: FormatConfig,
+    setup: SetupConfig,
+    merge: MergeConfig,
+    split: SplitConfig,
+    extract: ExtractConfig,
+    keygen: KeygenConfig,
+    assert: AssertConfig,
+    sync: SyncConfig,
+    trend: TrendConfig,
+    doctor: DoctorConfig,
+}
+
+/// The configuration file (`bibadac.toml`), whose sections
+/// hold the options of the commands.
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    check: CheckSection,
+    #[serde(default)]
+    assert: AssertConfig,
+    #[serde(default)]
+    format: FormatSection,
+    #[serde(default)]
+    score: ScoreSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CheckSection {
+    /// The `[check.rules.<rule>]` sections
+    #[serde(default)]
+    rules: RuleSeverities,
+    /// The `[check.doi_domains]` section, for `check --online`
+    #[serde(default)]
+    doi_domains: bibadac::doi_targets::DoiDomains,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FormatSection {
+    /// The `[format.profiles.<name>]` sections
+    #[serde(default)]
+    profiles: Profiles,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ScoreSection {
+    /// The `[score.weights]` section
+    #[serde(default)]
+    weights: bibadac::score::ScoreWeights,
+}
+
+impl ConfigFile {
+    /// The configuration file at `path`, or `bibadac.toml` when it exists.
+    fn find(path: Option<&std::path::Path>) -> Result<ConfigFile, String> {
+        let default = std::path::Path::new("bibadac.toml");
+        let path = match path {
+            Some(path) => path,
+            None if default.exists() => default,
+            None => return Ok(ConfigFile::default()),
+        };
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let content = std::fs::read_to_string(path).map_err(|e| error(&e))?;
+        toml::from_str(&content).map_err(|e| error(&e))
+    }
+}
+
+#[derive(Debug, Default, Clone, Args, Serialize, Deserialize)]
+struct CheckConfig {
+    #[arg(short, long, help = "Show only important errors")]
+    concise: bool,
+    #[arg(short, long, help = "Hide location of errors to symplify output")]
+    executive_summary: bool,
+    #[arg(short, long, help = "Output the errors in JSON format")]
+    to_json: bool,
+    #[arg(
+        long,
+        requires = "to_json",
+        default_value_t = SCHEMA_VERSION,
+        value_parser = clap::value_parser!(u32).range(1..=SCHEMA_VERSION as i64),
+        help = "Version of the shape of the JSON output (1 for the bare list of files)"
+    )]
+    schema: u32,
+    #[arg(long, help = "Write a self-contained HTML report to the given path")]
+    to_html: Option<std::path::PathBuf>,
+    #[arg(short, long, help = "Use a helper bibfile to check semantic errors")]
+    file_db: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        requires = "file_db",
+        help = "Complete the entries from the --file-db before linting them, as format does"
+    )]
+    lint_after_completion: bool,
+    #[arg(
+        long,
+        default_value_t = DB_MAX_SIZE,
+        help = "Size in bytes above which the --file-db is refused (check reads it whole, only format can --db-stream it, and --lint-after-completion holds a second copy of its fields)"
+    )]
+    db_max_size: u64,
+    #[arg(
+        long,
+        help = "Report the entries that are already in this bibfile (same doi, eprint, or title and author)"
+    )]
+    against: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        requires = "against",
+        help = "Report the entries that are not in the --against bibfile instead"
+    )]
+    against_invert: bool,
+    #[arg(
+        short,
+        long,
+        help = "Require a field in every entry (`name` for a warning, `name:error` for an error)"
+    )]
+    require_field: Vec<String>,
+    #[arg(
+        long,
+        value_parser = parse_field_limit,
+        help = "Maximal length of a field, in characters (e.g. `abstract=1500`)"
+    )]
+    max_field_len: Vec<(String, usize)>,
+    #[arg(
+        long,
+        value_parser = bibadac::cache::parse_age,
+        help = "Report the `verified` fields older than this age (e.g. `180d`)"
+    )]
+    verified_max_age: Option<std::time::Duration>,
+    #[arg(
+        long,
+        help = "Field that the duplicates of a work may disagree on, among year, pages, volume, and booktitle"
+    )]
+    conflict_ignore_field: Vec<String>,
+    #[arg(
+        long,
+        help = "Status accepted in the year field (default: forthcoming, in press, to appear, submitted, accepted)"
+    )]
+    year_status_word: Vec<String>,
+    #[arg(
+        long,
+        help = "Report the text between the entries, which BibTeX ignores"
+    )]
+    strict: bool,
+    #[cfg(feature = "spellcheck")]
+    #[arg(
+        long,
+        help = "Report the likely typos of the titles, booktitles, and journals"
+    )]
+    spellcheck: bool,
+    #[cfg(feature = "spellcheck")]
+    #[arg(
+        long,
+        requires = "spellcheck",
+        help = "File of words accepted by --spellcheck, one per line"
+    )]
+    dictionary: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        help = "Reuse the results of the previous runs for unchanged files, stored in the given file"
+    )]
+    cache_results: Option<std::path::PathBuf>,
+    #[arg(long, help = "Ignore --cache-results")]
+    no_cache_results: bool,
+    #[arg(
+        long,
+        help = "Only report the errors introduced since a git revision (e.g. `HEAD~1`)"
+    )]
+    since: Option<String>,
+    #[arg(
+        long,
+        conflicts_with = "since",
+        help = "Only report the errors that are not in the JSON report of a previous run (see --to-json)"
+    )]
+    baseline: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        requires = "baseline",
+        help = "Write the JSON report of all the errors to the --baseline file"
+    )]
+    update_baseline: bool,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Print at most this number of errors per file (0 to print all of them)"
+    )]
+    max_lints_per_file: usize,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = GroupBy::None,
+        help = "Group the errors of a file by entry or by rule (the JSON output is not grouped)"
+    )]
+    group_by: GroupBy,
+    #[arg(
+        long,
+        help = "Only report the errors of the entries of this type (e.g. `article`)"
+    )]
+    entry_type: Vec<String>,
+    #[arg(
+        long,
+        default_value_t = EXCERPT_WIDTH,
+        help = "Number of characters of the source shown around an error"
+    )]
+    excerpt_width: usize,
+    #[arg(
+        long,
+        help = "Apply the obvious fixes to the files (bare dois, lowercase entry types, spacing of the headers, escapes of % and &)"
+    )]
+    fix: bool,
+    #[arg(long, requires = "fix", help = "Do not write the files fixed by --fix")]
+    dry_run: bool,
+    #[arg(
+        long,
+        requires = "fix",
+        help = "Write the fixes of --fix as a unified diff to this file, for `git apply`"
+    )]
+    patch_out: Option<std::path::PathBuf>,
+    #[arg(long, help = "Print the time spent on every file, by phase")]
+    timings: bool,
+    #[arg(
+        long,
+        help = "Print the `% bibadac-accept: ...` comments waiving the errors about values, to paste above their entries"
+    )]
+    emit_waivers: bool,
+    #[arg(
+        long,
+        help = "Follow the dois to their landing pages, and report the unexpected domains and the long chains of redirects"
+    )]
+    online: bool,
+    #[arg(
+        long,
+        requires = "online",
+        help = "Send at most this many requests, the dois never verified first (the others are reported as not verified)"
+    )]
+    max_requests: Option<usize>,
+    #[arg(
+        long,
+        requires = "online",
+        value_enum,
+        value_delimiter = ',',
+        help = "Only verify these kinds of identifiers"
+    )]
+    online_only: Vec<OnlineOnly>,
+    #[arg(long, requires = "online", help = "Be polite when talking to doi.org")]
+    polite_email: Option<String>,
+    #[arg(
+        long,
+        requires = "online",
+        help = "Identify the requests with this User-Agent (by default `bibadac/<version> (mailto:<polite email>)`)"
+    )]
+    user_agent: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GroupBy {
+    /// The errors of an entry together, under its key
+    Entry,
+    /// The errors of a rule together, under its name
+    Rule,
+    /// The errors in the order of the checks
+    #[default]
+    None,
+}
+
+impl From<GroupBy> for bibadac::report::Grouping {
+    fn from(group_by: GroupBy) -> Self {
+        use bibadac::report::Grouping;
+        match group_by {
+            GroupBy::Entry => Grouping::Entry,
+            GroupBy::Rule => Grouping::Rule,
+            GroupBy::None => Grouping::None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum PdfLayout {
+    /// All the pdfs in the working directory
+    #[default]
+    Flat,
+    /// One directory per year (`2023/`)
+    ByYear,
+    /// One directory per first author (`knuth/`)
+    ByAuthor,
+    /// One directory per entry type (`article/`)
+    ByType,
+}
+
+impl From<PdfLayout> for bibadac::setup::Layout {
+    fn from(layout: PdfLayout) -> Self {
+        use bibadac::setup::Layout;
+        match layout {
+            PdfLayout::Flat => Layout::Flat,
+            PdfLayout::ByYear => Layout::ByYear,
+            PdfLayout::ByAuthor => Layout::ByAuthor,
+            PdfLayout::ByType => Layout::ByType,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ConvertTo {
+    /// `@mastersthesis`, `journal`, `year` and `month`
+    Bibtex,
+    /// `@thesis`, `journaltitle`, `date`
+    Biblatex,
+}
+
+impl From<ConvertTo> for bibadac::bibtex_spec::Flavor {
+    fn from(flavor: ConvertTo) -> Self {
+        use bibadac::bibtex_spec::Flavor;
+        match flavor {
+            ConvertTo::Bibtex => Flavor::Bibtex,
+            ConvertTo::Biblatex => Flavor::Biblatex,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TitleCaseOption {
+    /// `The complexity of distributed algorithms`
+    Sentence,
+    /// `The Complexity of Distributed Algorithms`
+    Title,
+}
+
+impl From<TitleCaseOption> for bibadac::title_case::TitleCase {
+    fn from(style: TitleCaseOption) -> Self {
+        use bibadac::title_case::TitleCase;
+        match style {
+            TitleCaseOption::Sentence => TitleCase::Sentence,
+            TitleCaseOption::Title => TitleCase::Title,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum EntryFormat {
+    /// One JSON object per entry and per line, see `bibadac::ndjson`
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LineEndingOption {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+    /// The ending of the platform
+    Native,
+}
+
+impl From<LineEndingOption> for bibadac::line_endings::LineEnding {
+    fn from(ending: LineEndingOption) -> Self {
+        use bibadac::line_endings::LineEnding;
+        match ending {
+            LineEndingOption::Lf => LineEnding::Lf,
+            LineEndingOption::Crlf => LineEnding::Crlf,
+            LineEndingOption::Native => LineEnding::native(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OnlineOnly {
+    Doi,
+    Arxiv,
+    Url,
+}
+
+impl From<OnlineOnly> for bibadac::online::IdentifierKind {
+    fn from(kind: OnlineOnly) -> Self {
+        use bibadac::online::IdentifierKind;
+        match kind {
+            OnlineOnly::Doi => IdentifierKind::Doi,
+            OnlineOnly::Arxiv => IdentifierKind::Arxiv,
+            OnlineOnly::Url => IdentifierKind::Url,
+        }
+    }
+}
+
+/// The budget of the online verification (`--max-requests`, `--online-only`).
+fn online_budget(max_requests: Option<usize>, only: &[OnlineOnly]) -> bibadac::online::Budget {
+    bibadac::online::Budget {
+        kinds: only.iter().map(|&kind| kind.into()).collect(),
+        max_requests,
+    }
+}
+
+const DB_MAX_SIZE: u64 = 256_000_000;
+
+/// Refuses a `--file-db` larger than `--db-max-size`.
+fn check_db_size(path: &std::path::Path, max_size: u64) -> Result<(), String> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .len();
+    if size > max_size {
+        return Err(format!(
+            "{} is {} bytes, above --db-max-size {}",
+            path.display(),
+            size,
+            max_size
+        ));
+    }
+    Ok(())
+}
+
+/// The options of `format` rewriting the entries, shared with
+/// the commands writing entries (`split`, `extract`).
+#[derive(Debug, Default, Clone, Args, Serialize, Deserialize)]
+struct EntryFormatConfig {
+    #[arg(short, long, help = "Autocomplete entries using an existing bibfile")]
+    file_db: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        help = "Read the --file-db a chunk at a time, only keeping the doi, eprint, title, sha256, url, and --db-fields"
+    )]
+    db_stream: bool,
+    #[arg(
+        long,
+        requires = "db_stream",
+        help = "Field of the --file-db completing the entries, with --db-stream (e.g. `pages`)"
+    )]
+    db_fields: Vec<String>,
+    #[arg(
+        long,
+        default_value_t = DB_MAX_SIZE,
+        help = "Size in bytes above which the --file-db is only read with --db-stream"
+    )]
+    db_max_size: u64,
+    #[arg(
+        short,
+        long,
+        help = "Remove the corresponding fields from the output (e.g. `bdsk-*` or `abstract,note`)"
+    )]
+    remove_field: Vec<FieldPatterns>,
+    #[arg(
+        short,
+        long,
+        help = "Only keep the corresponding fields in the output (e.g. `author|editor`)"
+    )]
+    keep_field: Vec<FieldPatterns>,
+    #[arg(
+        short,
+        long,
+        help = "Only keep entries containing one of the following fields"
+    )]
+    entry_field: Vec<FieldPatterns>,
+    #[arg(
+        long,
+        help = "Only format the entries of this type (e.g. `article`), writing the others as they are"
+    )]
+    entry_type: Vec<String>,
+    #[arg(short = 'l', long, help = "Order the fields alphabetically")]
+    sort_fields: bool,
+    #[arg(
+        short = 'g',
+        long,
+        help = "Order the entries by year (most recent first), then by author and key"
+    )]
+    sort_entries: bool,
+    #[arg(
+        short,
+        long,
+        help = "Rewrite identifiers (e.g. DOIs), dates, and pages to their canonical form"
+    )]
+    normalize: bool,
+    #[arg(
+        long,
+        value_parser = parse_field_limit,
+        help = "Truncate a field to a maximal number of characters (e.g. `abstract=1500`)"
+    )]
+    truncate_field: Vec<(String, usize)>,
+    #[arg(long, help = "Remove the @string definitions that no entry refers to")]
+    prune_strings: bool,
+    #[arg(
+        long,
+        help = "Always write this field last in the entries, even with --sort-fields (e.g. `verified`)"
+    )]
+    pin_last_field: Vec<String>,
+    #[arg(
+        long,
+        help = "Order of the fields, `*` standing for the others and `+` keeping fields together (e.g. `author,title,*,year+month`)"
+    )]
+    field_order: Option<FieldOrder>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Convert the entry types and fields to the conventions of BibTeX or BibLaTeX"
+    )]
+    convert_to: Option<ConvertTo>,
+    #[arg(
+        long,
+        help = "Rename the author field of the proceedings and collections without editor to editor"
+    )]
+    fix_editors: bool,
+    #[arg(
+        long,
+        help = "Remove the % bibadac: comments written by merge --annotate-merge"
+    )]
+    strip_annotations: bool,
+    #[arg(
+        long,
+        help = "Write the pages of the entries with an articleno and numpages but no pages (ACM style, e.g. 17:1--17:25)"
+    )]
+    synthesize_pages: bool,
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Keep the first N people of the longer author fields followed by `and others`, the full field being kept in --backup-authors"
+    )]
+    max_authors: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with = "max_authors",
+        help = "Write the full author fields kept by --max-authors back to the author fields"
+    )]
+    restore_authors: bool,
+    #[arg(
+        long,
+        help = "Field keeping the full author fields of --max-authors (default: authorsfull)"
+    )]
+    backup_authors: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Recase the titles entirely in uppercase or in lowercase, keeping the groups protected by braces"
+    )]
+    fix_title_case: Option<TitleCaseOption>,
+}
+
+#[derive(Debug, Default, Clone, Args, Serialize, Deserialize)]
+struct FormatConfig {
+    #[arg(short, long, help = "Create a new file with the formatted content")]
+    to_file: bool,
+    #[arg(short, long, help = "Update the files *in place* (dangerous)")]
+    in_place: bool,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    entries: EntryFormatConfig,
+    #[arg(
+        long,
+        value_enum,
+        help = "Line ending of the output (default: the ending of most lines of each file)"
+    )]
+    line_ending: Option<LineEndingOption>,
+    #[arg(
+        long,
+        help = "Replace the @comment{bibadac-include: ...} directives by the entries of the included files"
+    )]
+    inline_includes: bool,
+    #[arg(
+        long,
+        help = "Allow --in-place to drop fields or entries with --keep-field or --entry-field"
+    )]
+    allow_data_loss: bool,
+    #[arg(
+        long,
+        help = "Do not start the files written by --to-file with a comment recording how they were generated"
+    )]
+    no_header: bool,
+    #[arg(
+        long,
+        help = "Format the output again, and fail without writing anything if it changes (always on in debug builds)"
+    )]
+    verify_idempotent: bool,
+}
+
+impl FormatConfig {
+    /// The options when none is given on the command line.
+    fn defaults() -> FormatConfig {
+        FormatConfig {
+            entries: EntryFormatConfig::defaults(),
+            ..FormatConfig::default()
+        }
+    }
+}
+
+impl EntryFormatConfig {
+    /// The options when none is given on the command line.
+    fn defaults() -> EntryFormatConfig {
+        EntryFormatConfig {
+            db_max_size: DB_MAX_SIZE,
+            ..EntryFormatConfig::default()
+        }
+    }
+
+    /// The database of `--file-db` (at the resolved `path`).
+    fn database(&self, path: &std::path::Path) -> Result<LocalBibDb, String> {
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        if self.db_stream {
+            let file = std::fs::File::open(path).map_err(error)?;
+            return LocalBibDb::new()
+                .import_reader(std::io::BufReader::new(file), &self.db_fields)
+                .map_err(error);
+        }
+        check_db_size(path, self.db_max_size)
+            .map_err(|e| format!("{}, use --db-stream to read it", e))?;
+        let content = std::fs::read_to_string(path).map_err(error)?;
+        Ok(LocalBibDb::new().import_bibtex(&content))
+    }
+
+    /// The formatting options selected on the command line,
+    /// except for the field alignment that depends on the file.
+    fn options<T>(&self, db: T) -> FormatOptions<T> {
+        let mut format_options = FormatOptions::new(db);
+        if !self.remove_field.is_empty() {
+            format_options.blacklist = Some(self.remove_field.iter().collect());
+        }
+        if !self.keep_field.is_empty() {
+            format_options.whitelist = Some(self.keep_field.iter().collect());
+        }
+        if !self.entry_field.is_empty() {
+            format_options.field_filter = Some(self.entry_field.iter().collect());
+        }
+        if !self.entry_type.is_empty() {
+            format_options.entry_types = Some(self.entry_type.clone());
+        }
+
+        format_options.sort_fields = self.sort_fields;
+        format_options.sort_entries = self.sort_entries;
+        format_options.normalize_doi = self.normalize;
+        format_options.normalize_dates = self.normalize;
+        format_options.normalize_pages = self.normalize;
+        format_options.truncate_fields = self.truncate_field.iter().cloned().collect();
+        format_options.prune_strings = self.prune_strings;
+        format_options.pinned_last_fields = self
+            .pin_last_field
+            .iter()
+            .map(|f| f.to_lowercase())
+            .collect();
+        format_options.field_order = self.field_order.clone();
+        format_options.convert_types = self.convert_to.map(Into::into);
+        format_options.fix_editors = self.fix_editors;
+        format_options.strip_annotations = self.strip_annotations;
+        format_options.synthesize_pages = self.synthesize_pages;
+        format_options.max_authors = self.max_authors.map(|n| n as usize);
+        format_options.restore_authors = self.restore_authors;
+        if let Some(backup) = &self.backup_authors {
+            format_options.backup_authors = backup.to_lowercase();
+        }
+        format_options.fix_title_case = self.fix_title_case.map(Into::into);
+        format_options
+    }
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct SetupConfig {
+    #[arg(short = 'o', long, help = "Save bibentries to a file")]
+    to_file: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        conflicts_with = "to_file",
+        help = "Save bibentries to a new file of this directory, and print its path (for parallel runs)"
+    )]
+    to_file_per_run: Option<std::path::PathBuf>,
+    #[arg(short = 'b', long, help = "Print the bibentries")]
+    no_output: bool,
+    #[arg(short = 't', long, help = "Download the pdfs")]
+    documents: bool,
+    #[arg(short, long, help = "Directory to save the pdfs")]
+    working_directory: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = PdfLayout::Flat,
+        help = "Subdirectories of the working directory where the pdfs are saved"
+    )]
+    layout: PdfLayout,
+    #[arg(short = 'p', long, help = "Do not show progress of the downloads")]
+    no_progress: bool,
+    #[arg(
+        long,
+        help = "Write the progress as JSON lines on stderr, for graphical front-ends (see `bibadac::progress`)"
+    )]
+    progress_json: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["refetch_flagged", "mirror_record"],
+        help = "Print the url of every pdf to download (`identifier<TAB>url`) instead of downloading anything"
+    )]
+    print_urls: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["refetch_flagged", "print_urls"],
+        help = "Skip the identifiers done by the previous run, as recorded in the .bibadac-setup.json of the working directory"
+    )]
+    resume: bool,
+    #[arg(short = 'm', long, help = "Be polite when talking to CrossRef APIs")]
+    polite_email: Option<String>,
+    #[arg(
+        long,
+        help = "Identify the requests with this User-Agent (by default `bibadac/<version> (mailto:<polite email>)`)"
+    )]
+    user_agent: Option<String>,
+    #[arg(
+        long,
+        value_parser = bibadac::cache::parse_age,
+        help = "Give up on a request after this time (e.g. `30s`)"
+    )]
+    timeout: Option<std::time::Duration>,
+    #[arg(long, help = "Send the requests through this proxy (e.g. `http://proxy:8080`)")]
+    proxy: Option<String>,
+    #[arg(
+        long,
+        help = "Fetch at most this many identifiers, the ones never fetched first (the others are reported as not verified)"
+    )]
+    max_requests: Option<usize>,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        help = "Only fetch these kinds of identifiers"
+    )]
+    online_only: Vec<OnlineOnly>,
+    #[arg(short = 'a', long, help = "Directly import from arxiv")]
+    arxiv: Vec<String>,
+    #[arg(
+        long,
+        help = "Note the version of the arXiv entries and the day they were fetched"
+    )]
+    arxiv_note: bool,
+    #[arg(short = 'd', long, help = "Directly import from doi")]
+    doi: Vec<String>,
+    #[arg(
+        long,
+        help = "Import the pdfs of a directory, using the doi or arXiv identifier they contain"
+    )]
+    from_pdf: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        help = "Read the responses from this directory instead of the network (see --mirror-record)"
+    )]
+    mirror: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        requires = "mirror",
+        help = "Save the responses of the network in the --mirror directory"
+    )]
+    mirror_record: bool,
+    #[arg(
+        long,
+        requires = "report",
+        help = "Only fetch again the entries reported as copied from Google Scholar"
+    )]
+    refetch_flagged: bool,
+    #[arg(long, help = "JSON report of `bibadac check --to-json`")]
+    report: Option<std::path::PathBuf>,
+}
+
+impl SetupConfig {
+    /// Saves the fetched entries to the output file, if any.
+    fn save<S: AsRef<str>>(&self, entries: &[S]) -> Result<(), String> {
+        if let Some(path) = &self.to_file {
+            bibadac::append::append_entries(path, entries)
+                .map_err(|e| format!("Could not write to {}: {}", path.display(), e))?;
+        }
+        if let Some(dir) = &self.to_file_per_run {
+            let path = bibadac::append::write_per_run(dir, entries)
+                .map_err(|e| format!("Could not write to {}: {}", dir.display(), e))?;
+            println!("{}", path.display());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct MergeConfig {
+    #[arg(short, long, help = "Resolve conflicting entries interactively")]
+    interactive: bool,
+    #[arg(short = 'o', long, help = "Save the merged entries to a file")]
+    to_file: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        help = "Annotate the fields taken from another file than their entry with a % bibadac: comment"
+    )]
+    annotate_merge: bool,
+    #[arg(
+        long,
+        help = "Fold arXiv preprints into the published version of the same paper"
+    )]
+    collapse_preprints: bool,
+    #[arg(
+        long,
+        requires = "collapse_preprints",
+        help = "LaTeX files whose citations of the folded preprints are updated"
+    )]
+    tex: Vec<std::path::PathBuf>,
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct ExtractConfig {
+    #[arg(
+        long,
+        required = true,
+        help = "LaTeX files whose citations are extracted"
+    )]
+    tex: Vec<std::path::PathBuf>,
+    #[arg(long, help = "The BibTeX file the entries are extracted from")]
+    from: std::path::PathBuf,
+    #[arg(short, long, help = "Write the entries to this file instead of stdout")]
+    output: Option<std::path::PathBuf>,
+    #[arg(long, help = "Fail when a cited key is not in the --from file")]
+    strict: bool,
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct SplitConfig {
+    #[arg(short, long, help = "Split by `year`, `type`, or `field:<name>`")]
+    by: String,
+    #[arg(short, long, help = "Directory where the files are written")]
+    out_dir: std::path::PathBuf,
+    #[arg(long, help = "Overwrite existing files")]
+    force: bool,
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct KeygenConfig {
+    #[arg(
+        short,
+        long,
+        default_value = "{author:last:lower}{year}{title:word1:lower}",
+        help = "Pattern of the keys, with placeholders such as `{author:last:lower}`, `{year}`, `{title:word1}`, or `{doi:hash6}`"
+    )]
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct AssertConfig {
+    #[arg(long, help = "Fail when there are fewer entries, in all the files")]
+    min_entries: Option<usize>,
+    #[arg(
+        long,
+        help = "Condition that every entry satisfies, e.g. `has(doi) || has(eprint)` (repeatable)"
+    )]
+    every: Vec<String>,
+    #[arg(
+        long,
+        help = "Fail on the entries of this type, e.g. `misc` (repeatable)"
+    )]
+    forbid_type: Vec<String>,
+}
+
+impl AssertConfig {
+    /// The assertions of both configurations, the minimal
+    /// number of entries of `other` taking precedence.
+    fn extend(&mut self, other: AssertConfig) {
+        self.min_entries = other.min_entries.or(self.min_entries);
+        self.every.extend(other.every);
+        self.forbid_type.extend(other.forbid_type);
+    }
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct SyncConfig {
+    #[arg(short, long, help = "Directory of the pdfs")]
+    dir: std::path::PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Update the names of the renamed pdfs, and add entries for the unreferenced pdfs"
+    )]
+    write: bool,
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct TrendConfig {
+    #[arg(
+        long,
+        help = "Print csv rows (report,generated_at,rule,count,delta) instead of a table"
+    )]
+    csv: bool,
+}
+
+#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
+struct DoctorConfig {
+    #[arg(
+        long,
+        value_parser = clap::builder::PossibleValuesParser::new(bibadac::doctor::CHECKS),
+        help = "Skip a check (repeatable)"
+    )]
+    skip: Vec<String>,
+    #[arg(short, long, help = "Directory where setup saves the pdfs")]
+    working_directory: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        default_value_t = 10_000_000,
+        help = "Size in bytes above which an input file is reported"
+    )]
+    max_file_size: u64,
+}
+
+#[derive(Debug, Clone, Args)]
+struct CheckArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[clap(flatten)]
+    config: CheckConfig,
+    #[arg(
+        long,
+        help = "Print the rules with their severity, and the overrides of the configuration file"
+    )]
+    list_rules: bool,
+    #[arg(
+        long,
+        help = "Configuration file with the [check.rules] sections (bibadac.toml when it exists)"
+    )]
+    config_file: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+struct FormatArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[clap(flatten)]
+    config: FormatConfig,
+    #[arg(
+        long,
+        help = "Use the options of a [format.profiles.<name>] section of the configuration file"
+    )]
+    profile: Option<String>,
+    #[arg(
+        long,
+        help = "Print the profiles of the configuration file with their options"
+    )]
+    list_profiles: bool,
+    #[arg(
+        long,
+        help = "Configuration file with the profiles (bibadac.toml when it exists)"
+    )]
+    config_file: Option<std::path::PathBuf>,
+}
+
+impl FormatArgs {
+    /// The options of the profile `name` (if any), overridden
+    /// by the options given on the command line.
+    fn settings(
+        &self,
+        profiles: &Profiles,
+        name: Option<&str>,
+    ) -> Result<serde_json::Value, String> {
+        let defaults = serde_json::to_value(FormatConfig::defaults()).map_err(|e| e.to_string())?;
+        let cli = serde_json::to_value(&self.config).map_err(|e| e.to_string())?;
+        bibadac::profiles::resolve(&defaults, profiles, name, &cli).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+struct SetupArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[clap(flatten)]
+    config: SetupConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct MergeArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[clap(flatten)]
+    config: MergeConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct SplitArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[clap(flatten)]
+    config: SplitConfig,
+    #[clap(flatten)]
+    format: EntryFormatConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct ExtractArgs {
+    #[clap(flatten)]
+    config: ExtractConfig,
+    #[clap(flatten)]
+    format: EntryFormatConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct KeygenArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[clap(flatten)]
+    config: KeygenConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct AuthorsArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[arg(short, long, help = "Output the clusters of spellings in JSON format")]
+    to_json: bool,
+    #[arg(
+        long,
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Print the people with at least this number of spellings"
+    )]
+    min_cluster: u64,
+}
+
+#[derive(Debug, Clone, Args)]
+struct FingerprintArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[arg(short, long, help = "Output the fingerprints in JSON format")]
+    to_json: bool,
+}
+
+/// The fingerprint of an entry, see `bibtex::entry_fingerprint`.
+#[derive(Debug, Clone, Serialize)]
+struct EntryFingerprint {
+    file: String,
+    key: String,
+    /// The sha256, in hexadecimal
+    fingerprint: String,
+}
+
+#[derive(Debug, Clone, Args)]
+struct ScoreArgs {
+    #[clap(flatten)]
+    files: FileArgs,
+    #[arg(
+        long,
+        help = "Only print the entries whose score is below this one (from 0 to 100)"
+    )]
+    below: Option<u32>,
+    #[arg(short, long, help = "Output the scores in JSON format")]
+    to_json: bool,
+    #[arg(
+        long,
+        help = "Configuration file with the [score.weights] and [check.rules] sections (bibadac.toml when it exists)"
+    )]
+    config_file: Option<std::path::PathBuf>,
 }
 
+/// The options of `FileArgs`, but `--format`, which is the format of
+/// the output.
 #[derive(Debug, Clone, Args)]
-struct FileArgs {
+struct ExportArgs {
+    #[arg(short, long, help = "Read BibTeX from stdin")]
+    stdin: bool,
     #[arg(
-        short,
         long,
-        help = "Read BibTeX from stdin, set to true in case no bibfiles are provided"
+        value_enum,
+        default_value = "ndjson",
+        help = "Format of the entries"
     )]
-    stdin: bool,
+    format: EntryFormat,
     /// BibTeX/BibLaTeX files to read
     bib: Vec<std::path::PathBuf>,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-struct Config {
-    check: CheckConfig,
-    format: FormatConfig,
-    setup: SetupConfig,
-}
-
-#[derive(Debug, Default, Clone, Args, Serialize, Deserialize)]
-struct CheckConfig {
-    #[arg(short, long, help = "Show only important errors")]
-    concise: bool,
-    #[arg(short, long, help = "Hide location of errors to symplify output")]
-    executive_summary: bool,
-    #[arg(short, long, help = "Output the errors in JSON format")]
-    to_json: bool,
-    #[arg(short, long, help = "Use a helper bibfile to check semantic errors")]
-    file_db: Option<std::path::PathBuf>,
-}
-
-#[derive(Debug, Default, Clone, Args, Serialize, Deserialize)]
-struct FormatConfig {
-    #[arg(short, long, help = "Create a new file with the formatted content")]
-    to_file: bool,
-    #[arg(short, long, help = "Update the files *in place* (dangerous)")]
-    in_place: bool,
-    #[arg(short, long, help = "Autocomplete entries using an existing bibfile")]
-    file_db: Option<std::path::PathBuf>,
-    #[arg(short, long, help = "Remove the corresponding fields from the output")]
-    remove_field: Vec<String>,
-    #[arg(short, long, help = "Only keep the corresponding fields in the output")]
-    keep_field: Vec<String>,
+#[derive(Debug, Clone, Args)]
+struct ImportArgs {
     #[arg(
-        short,
         long,
-        help = "Only keep entries containing one of the following fields"
+        value_enum,
+        default_value = "ndjson",
+        help = "Format of the entries"
     )]
-    entry_field: Vec<String>,
-    #[arg(short = 'l', long, help = "Order the fields alphabetically")]
-    sort_fields: bool,
-    #[arg(short = 'g', long, help = "Order the entries alphabetically")]
-    sort_entries: bool,
-}
-
-#[derive(Debug, Clone, Args, Default, Serialize, Deserialize)]
-struct SetupConfig {
-    #[arg(short = 'o', long, help = "Save bibentries to a file")]
-    to_file: Option<std::path::PathBuf>,
-    #[arg(short = 'b', long, help = "Print the bibentries")]
-    no_output: bool,
-    #[arg(short = 't', long, help = "Download the pdfs")]
-    documents: bool,
-    #[arg(short, long, help = "Directory to save the pdfs")]
-    working_directory: Option<std::path::PathBuf>,
-    #[arg(short = 'p', long, help = "Do not show progress of the downloads")]
-    no_progress: bool,
-    #[arg(short = 'm', long, help = "Be polite when talking to CrossRef APIs")]
-    polite_email: Option<String>,
-    #[arg(short = 'a', long, help = "Directly import from arxiv")]
-    arxiv: Vec<String>,
-    #[arg(short = 'd', long, help = "Directly import from doi")]
-    doi: Vec<String>,
+    format: EntryFormat,
+    /// File of entries to read (stdin by default)
+    input: Option<std::path::PathBuf>,
 }
 
 #[derive(Debug, Clone, Args)]
-struct CheckArgs {
+struct AssertArgs {
     #[clap(flatten)]
     files: FileArgs,
     #[clap(flatten)]
-    config: CheckConfig,
+    config: AssertConfig,
+    #[arg(
+        long,
+        help = "Configuration file with an [assert] section (bibadac.toml when it exists)"
+    )]
+    config_file: Option<std::path::PathBuf>,
 }
 
 #[derive(Debug, Clone, Args)]
-struct FormatArgs {
-    #[clap(flatten)]
-    files: FileArgs,
+struct SyncArgs {
+    /// BibTeX/BibLaTeX file whose entries point to the pdfs
+    bib: std::path::PathBuf,
     #[clap(flatten)]
-    config: FormatConfig,
+    config: SyncConfig,
 }
 
 #[derive(Debug, Clone, Args)]
-struct SetupArgs {
+struct TrendArgs {
+    /// Directory of the JSON reports of `bibadac check --to-json`
+    dir: std::path::PathBuf,
     #[clap(flatten)]
-    files: FileArgs,
+    config: TrendConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct DoctorArgs {
+    /// BibTeX/BibLaTeX files to inspect
+    bib: Vec<std::path::PathBuf>,
     #[clap(flatten)]
-    config: SetupConfig,
+    config: DoctorConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+struct DebugArgs {
+    #[command(subcommand)]
+    command: DebugCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum DebugCommand {
+    #[command(about = "Print the syntax tree of a file, with its ERROR and MISSING nodes")]
+    Parse {
+        /// BibTeX/BibLaTeX file to parse
+        file: std::path::PathBuf,
+        #[arg(long, help = "Only print the tree of the entry with this key")]
+        entry: Option<String>,
+        #[arg(long, help = "Print the S-expression of tree-sitter instead")]
+        sexp: bool,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCommand,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum CacheCommand {
+    #[command(about = "Show the number of entries, size, and age of the cache")]
+    Stats,
+    #[command(about = "Remove the entries of the cache")]
+    Clear {
+        #[arg(long, help = "Only remove the entries older than this age (e.g. `30d`)")]
+        older_than: Option<String>,
+    },
+    #[command(about = "Print a cached entry (e.g. `doi:10.1145/123`)")]
+    Get { key: String },
 }
 
 #[derive(Debug, Clone)]
 struct InputFile {
     name: std::path::PathBuf,
-    content: String,
+    bib: Bibliography,
+    /// Read from stdin, `name` is only a label
+    from_stdin: bool,
+    /// The bytes read from stdin, given back as they
+    /// are when they cannot be formatted
+    raw: Option<Vec<u8>>,
+    /// The file and the line of the include directive
+    /// that brought the file in (see `bibadac::includes`)
+    included_from: Option<(std::path::PathBuf, usize)>,
+}
+
+impl InputFile {
+    fn new(name: std::path::PathBuf, content: Vec<u8>, format: Option<InputFormat>) -> Self {
+        let is_markdown = match format {
+            Some(format) => format == InputFormat::Md,
+            None => name
+                .extension()
+                .is_some_and(|ext| ext == "md" || ext == "qmd"),
+        };
+        let bib = Bibliography::from_bytes(content, is_markdown);
+        InputFile {
+            name,
+            bib,
+            from_stdin: false,
+            raw: None,
+            included_from: None,
+        }
+    }
+}
+
+/// Writes the documents read from stdin back to stdout, as they were
+/// read (separated by NUL bytes), so that an editor replacing its buffer
+/// by the output of `format` keeps it unchanged when formatting fails.
+fn give_back_stdin(inputs: &[InputFile]) -> ExitCode {
+    use std::io::Write;
+    let documents = inputs
+        .iter()
+        .filter_map(|bib| bib.raw.as_deref())
+        .collect::<Vec<_>>();
+    if let Err(e) = std::io::stdout().write_all(&documents.join(&b'\0')) {
+        eprintln!("{} Could not write to stdout: {}", "[ERR]".red(), e);
+    }
+    ExitCode::FAILURE
 }
 
 trait InputFiles {
@@ -182,21 +1431,15 @@ impl InputFiles for FileArgs {
                     eprintln!("File {:?} does not exist", name);
                     return None;
                 }
-                let content = std::fs::read_to_string(name).expect("Could not read input file");
-                Some(InputFile {
-                    name: name.clone(),
-                    content,
-                })
+                let content = std::fs::read(name).expect("Could not read input file");
+                Some(InputFile::new(name.clone(), content, self.input_format))
             })
             .chain(if use_stdin {
-                let mut content = String::new();
+                let mut content = vec![];
                 std::io::stdin()
-                    .read_to_string(&mut content)
+                    .read_to_end(&mut content)
                     .expect("Could not read stdin");
-                vec![InputFile {
-                    name: "stdin".into(),
-                    content,
-                }]
+                self.stdin_documents(content)
             } else {
                 vec![]
             })
@@ -204,72 +1447,253 @@ impl InputFiles for FileArgs {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct JsonReportEntry {
-    file: String,
-    errors: Vec<JsonReportLint>,
+impl FileArgs {
+    /// The files, followed by the files they include (recursively), and
+    /// the include trees with more than one file, as indices of the files
+    /// (a file included by several trees belongs to the first one).
+    fn list_files_with_includes(&self) -> Result<(Vec<InputFile>, Vec<Vec<usize>>), String> {
+        use bibadac::includes::{load_includes, normalize};
+        let mut files = self.list_files();
+        let mut trees = vec![];
+        for root in 0..files.len() {
+            let included = load_includes(&files[root].name, &files[root].bib, |path| {
+                Bibliography::from_path(path)
+            })
+            .map_err(|e| e.to_string())?;
+            let mut tree = vec![root];
+            for file in included {
+                let known = files.iter().position(|f| normalize(&f.name) == file.path);
+                let i = match known {
+                    Some(i) => i,
+                    None => {
+                        files.push(InputFile {
+                            name: file.path,
+                            bib: file.bib,
+                            from_stdin: false,
+                            raw: None,
+                            included_from: Some(file.included_from),
+                        });
+                        files.len() - 1
+                    }
+                };
+                let grouped = trees.iter().any(|t: &Vec<usize>| t.contains(&i));
+                if i != root && !grouped && !tree.contains(&i) {
+                    tree.push(i);
+                }
+            }
+            if tree.len() > 1 && !trees.iter().any(|t| t.contains(&root)) {
+                trees.push(tree);
+            }
+        }
+        Ok((files, trees))
+    }
+
+    /// The documents read from stdin, labelled with `--stdin-filename`.
+    /// Several documents are separated by NUL bytes.
+    fn stdin_documents(&self, content: Vec<u8>) -> Vec<InputFile> {
+        let documents = if self.stdin_filename.len() > 1 {
+            let content = content.strip_suffix(b"\0").unwrap_or(&content);
+            content.split(|b| *b == 0).map(|d| d.to_vec()).collect()
+        } else {
+            vec![content]
+        };
+        let names = self.stdin_filename.iter().cloned().map(Some);
+        documents
+            .into_iter()
+            .zip(names.chain(std::iter::repeat(None)))
+            .map(|(document, name)| InputFile {
+                from_stdin: true,
+                raw: Some(document.clone()),
+                ..InputFile::new(name.unwrap_or("stdin".into()), document, self.input_format)
+            })
+            .collect()
+    }
+
+    /// A relative path of the command line, such as `--file-db`, which is
+    /// looked up next to the `--stdin-filename` when it is not found in
+    /// the current directory (editors do not run in the project directory).
+    fn resolve(&self, path: &std::path::Path) -> std::path::PathBuf {
+        if path.is_absolute() || path.exists() {
+            return path.to_path_buf();
+        }
+        self.stdin_filename
+            .iter()
+            .filter_map(|name| name.parent())
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.exists())
+            .unwrap_or_else(|| path.to_path_buf())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct JsonReportLoc {
-    line: usize,
-    column: usize,
-    start_byte: usize,
-    end_byte: usize,
+/// The JSON report of the files checked (so far, when `interrupted`).
+fn json_report(lints: &[(&InputFile, Vec<Diagnostic>)], interrupted: bool) -> JsonReport {
+    let files = lints
+        .iter()
+        .map(|(bib, lints)| ReportedFile {
+            file: bib.name.to_string_lossy().to_string(),
+            errors: bibadac::baseline::reported(&bib.bib, lints),
+        })
+        .collect();
+    let inputs = lints
+        .iter()
+        .map(|(bib, _)| InputHash {
+            file: bib.name.to_string_lossy().to_string(),
+            sha256: bibadac::results_cache::content_hash(bib.bib.source()),
+        })
+        .collect();
+    JsonReport::new(files, inputs, interrupted)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct JsonReportLint {
-    msg: LintMessage,
-    loc: Vec<JsonReportLoc>,
+fn print_json_lints(
+    lints: &[(&InputFile, Vec<Diagnostic>)],
+    interrupted: bool,
+    schema: u32,
+    timings: Vec<FileTimings>,
+) {
+    let mut report = json_report(lints, interrupted);
+    report.timings = timings;
+    report
+        .write(std::io::stdout(), schema)
+        .expect("Could not write json report");
 }
 
-fn print_json_lints(lints: Vec<(&InputFile, &BibFile, Vec<Lint>)>) {
-    let mut out = std::io::stdout();
-    let json_report = lints
+fn write_html_report(
+    path: &std::path::Path,
+    lints: &[(&InputFile, Vec<Diagnostic>)],
+    width: usize,
+) {
+    use bibadac::report::{html_report, ReportRow};
+    let names = lints
         .iter()
-        .map(|(bib, _, lints)| JsonReportEntry {
-            file: bib.name.to_string_lossy().to_string(),
-            errors: lints
+        .map(|(bib, _)| bib.name.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    let files = names
+        .iter()
+        .zip(lints.iter())
+        .map(|(name, (_, lints))| (name.as_str(), lints.len()))
+        .collect::<Vec<_>>();
+    let rows = names
+        .iter()
+        .zip(lints.iter())
+        .flat_map(|(name, (bib, lints))| {
+            lints
                 .iter()
-                .map(|l| JsonReportLint {
-                    msg: l.msg.clone(),
-                    loc: l
-                        .loc
-                        .iter()
-                        .map(|n| JsonReportLoc {
-                            line: n.start_position().row + 1,
-                            column: n.start_position().column + 1,
-                            start_byte: n.start_byte(),
-                            end_byte: n.end_byte(),
-                        })
-                        .collect(),
-                })
-                .collect(),
+                .map(|l| ReportRow::of(name, bib.bib.source(), l, width))
         })
         .collect::<Vec<_>>();
-    serde_json::to_writer_pretty(&mut out, &json_report).expect("Could not write json report");
+    std::fs::write(path, html_report(&files, &rows)).expect("Could not write the html report");
+}
+
+/// Applies the fixes of `check --fix` to the files (unless `dry_run`),
+/// and writes them as a patch to `patch_out`. The fixes that overlap
+/// an earlier one are left out with a warning.
+fn fix_files(
+    files: &[InputFile],
+    config: &LintConfig,
+    dry_run: bool,
+    patch_out: Option<&std::path::Path>,
+) -> Result<(), String> {
+    use bibadac::fixes::{apply_with_endings, patch_path, select, unified_diff};
+    let mut patch = String::new();
+    for f in files {
+        // the patch and the file must have the bytes of the fixes
+        let skipped = match f.bib.encoding() {
+            _ if f.from_stdin => Some("it is read from stdin"),
+            Some(_) => Some("it is not in UTF-8"),
+            None => None,
+        };
+        if let Some(reason) = skipped {
+            eprintln!(
+                "{} {:?} is not fixed, as {}",
+                "[WARN]".yellow(),
+                f.name,
+                reason
+            );
+            continue;
+        }
+        let original = f.bib.original();
+        let (fixes, dropped) = select(f.bib.fixes(config));
+        for fix in dropped {
+            let line = original[..fix.span.start].matches('\n').count() + 1;
+            eprintln!(
+                "{} {:?}:{}: the fix of `{}` overlaps another fix, and is left out",
+                "[WARN]".yellow(),
+                f.name,
+                line,
+                fix.rule
+            );
+        }
+        if fixes.is_empty() {
+            continue;
+        }
+        let endings = f.bib.line_endings();
+        let cwd = std::env::current_dir().unwrap_or_default();
+        patch += &unified_diff(&patch_path(&f.name, &cwd), original, &fixes, endings);
+        if !dry_run {
+            std::fs::write(&f.name, apply_with_endings(original, &fixes, endings))
+                .map_err(|e| format!("{}: {}", f.name.display(), e))?;
+        }
+        eprintln!(
+            "{} {:?}: {} fix(es)",
+            if dry_run { "[FIXABLE]" } else { "[FIXED]" }.green(),
+            f.name,
+            fixes.len()
+        );
+    }
+    if let Some(path) = patch_out {
+        std::fs::write(path, patch).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Parses a `name=length` pair.
+fn parse_field_limit(s: &str) -> Result<(String, usize), String> {
+    let (name, length) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected `name=length`, found {:?}", s))?;
+    let length = length
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid length {:?} for field {:?}", length, name))?;
+    Ok((name.trim().to_lowercase(), length))
 }
 
-fn print_bib_lint(bibtex: &BibFile, bib: &InputFile, l: &Lint) {
+/// The tag of the messages that the completion from
+/// the `--file-db` resolves (`--lint-after-completion`).
+fn print_bib_lint(bib: &InputFile, l: &Diagnostic, width: usize) {
+    let source = bib.bib.source();
     println!(
-        "{}\n<{:?}:L{}:C{}>\n{:?}",
+        "{}\n<{:?}:L{}:C{}>\n{}{}",
         "Error".red(),
         bib.name,
-        l.loc[0].start_position().row + 1,
-        l.loc[0].start_position().column + 1,
-        l.msg
+        l.loc[0].line,
+        l.loc[0].column,
+        lint_message(l),
+        fixable_tag(l)
     );
+    if let Some(hint) = l.msg.hint() {
+        println!("{} {}", "Hint:".yellow(), hint);
+    }
     println!(
         "{}",
         l.loc
             .iter()
             .map(|n| {
-                let s = bibtex.get_slice(*n);
+                let s = &source[n.start_byte..n.end_byte];
                 s.lines()
                     .take(3)
-                    .zip(1..)
-                    .map(|(l, i)| format!("{:>4}| {}", i + n.start_position().row, l))
+                    .zip(n.line..)
+                    .map(|(l, i)| {
+                        // the ruler points to the first character of the line
+                        let first = l.chars().next().map_or(0, char::len_utf8);
+                        let line = excerpt(l, 0, first, width);
+                        let text = format!("{}{}", line.span, line.after);
+                        let column = if i == n.line { n.column } else { 1 };
+                        match ruler(&line, column) {
+                            Some(ruler) => format!("{:>4}| {}\n    | {}", i, text, ruler),
+                            None => format!("{:>4}| {}", i, text),
+                        }
+                    })
                     .collect::<Vec<_>>()
                     .join("\n")
             })
@@ -280,97 +1704,491 @@ fn print_bib_lint(bibtex: &BibFile, bib: &InputFile, l: &Lint) {
     if let LintMessage::SyntaxError(_) = l.msg {
         // print a bit before and a bit after
         // using colors to highlight the error
-        let start = l.loc[0].start_byte();
-        let end = l.loc[0].end_byte();
-        let (before, error, after) = windowed(&bibtex.content, start, end, 20);
+        let start = l.loc[0].start_byte;
+        let end = l.loc[0].end_byte;
+        let context = excerpt_around(source, start, end, 20, width);
 
-        print!("{}", before);
-        print!("{}", error.red());
-        print!("{}", after);
+        print!("{}", context.before);
+        print!("{}", context.span.red());
+        print!("{}", context.after);
+        if let Some(ruler) = ruler(&context, l.loc[0].column) {
+            print!("\n{}", ruler);
+        }
     }
     println!();
 }
 
+/// Reports the requests refused in offline mode when the command returns.
+struct OfflineSummary;
+
+impl Drop for OfflineSummary {
+    fn drop(&mut self) {
+        let skipped = bibadac::network::skipped();
+        if skipped > 0 {
+            eprintln!("{} {} network operations skipped", "[OFFLINE]".yellow(), skipped);
+        }
+    }
+}
+
 fn main() -> ExitCode {
     let args = Cli::parse();
 
+    let progress = match &args.command {
+        // the lines would be mixed with the JSON lines
+        SubCommand::Setup(cargs) => !cargs.config.no_progress && !cargs.config.progress_json,
+        _ => true,
+    };
+    if let Err(e) = bibadac::logging::init(
+        args.log_level.map(|l| l.as_str()),
+        args.log_json.as_deref(),
+        progress,
+    ) {
+        eprintln!("{} Could not create the log file: {}", "[ERR]".red(), e);
+        return ExitCode::FAILURE;
+    }
+    let offline = std::env::var(bibadac::network::OFFLINE_ENV).ok();
+    if args.offline || bibadac::network::offline_from_env(offline.as_deref()) {
+        bibadac::network::set_offline(true);
+    }
+    let _summary = OfflineSummary;
+
     match args.command {
         SubCommand::Check(cargs) => {
-            use std::collections::HashSet;
-
             let mut exit_code = ExitCode::SUCCESS;
 
-            let mut linter = LinterState::default();
+            let mut config = LintConfig::default();
+            let check_section = match ConfigFile::find(cargs.config_file.as_deref()) {
+                Ok(file) => file.check,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            config.severities = check_section.rules;
+            for rule in bibadac::severity::unknown_rules(&config.severities) {
+                eprintln!(
+                    "{} unknown rule `{}` in [check.rules]",
+                    "[WARN]".yellow(),
+                    rule
+                );
+            }
+            if cargs.list_rules {
+                use bibadac::severity::{describe, Severity};
+                for msg in LintMessage::examples() {
+                    let rule = msg.rule_name();
+                    let severity = describe(&config.severities, rule, Severity::of(&msg));
+                    println!("{:<30} {}", rule, severity);
+                }
+                return ExitCode::SUCCESS;
+            }
+            for field in cargs.config.require_field.iter() {
+                match field.parse() {
+                    Ok(field) => config.required_fields.push(field),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+
+            config.max_field_length = cargs.config.max_field_len.iter().cloned().collect();
+            if let Some(age) = cargs.config.verified_max_age {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let days = now.saturating_sub(age).as_secs() / 86400;
+                config.verified_before = Some(bibadac::dates::Date::from_unix_days(days as i64));
+            }
+            if !cargs.config.conflict_ignore_field.is_empty() {
+                config.conflict_ignored_fields = Some(cargs.config.conflict_ignore_field.clone());
+            }
+            if !cargs.config.year_status_word.is_empty() {
+                config.year_status_words = Some(cargs.config.year_status_word.clone());
+            }
+            #[cfg(feature = "spellcheck")]
+            if cargs.config.spellcheck {
+                let words = match &cargs.config.dictionary {
+                    Some(path) => match std::fs::read_to_string(path) {
+                        Ok(content) => bibadac::spellcheck::parse_dictionary(&content),
+                        Err(e) => {
+                            eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                            return ExitCode::FAILURE;
+                        }
+                    },
+                    None => vec![],
+                };
+                config.dictionary = Some(words);
+            }
+            config.concise = cargs.config.concise;
+            config.strict = cargs.config.strict;
 
-            let mut start_bib = String::new();
-            if let Some(path) = cargs.config.file_db {
-                start_bib =
-                    std::fs::read_to_string(path).expect("Could not read the helper bibfile");
+            let helper = match &cargs.config.file_db {
+                Some(path) => {
+                    let path = cargs.files.resolve(path);
+                    if let Err(e) = check_db_size(&path, cargs.config.db_max_size) {
+                        eprintln!("{} {}", "[ERR]".red(), e);
+                        return ExitCode::FAILURE;
+                    }
+                    match Bibliography::from_path(&path) {
+                        Ok(helper) => Some(helper),
+                        Err(e) => {
+                            eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+                None => None,
+            };
+            if let Some(helper) = &helper {
+                config.import_helper(helper);
+                if cargs.config.lint_after_completion {
+                    let database = LocalBibDb::new().import_bibtex(helper.source());
+                    config.completion = Some(std::sync::Arc::new(database));
+                }
+            }
+            if let Some(path) = &cargs.config.against {
+                match Bibliography::from_path(cargs.files.resolve(path)) {
+                    Ok(against) => config.import_against(&against),
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+                config.against_invert = cargs.config.against_invert;
             }
 
-            let bibtex = BibFile::new(&start_bib);
-            let eprints = bibtex
-                .list_entries()
-                .flat_map(|entry| {
-                    entry
-                        .fields
-                        .into_iter()
-                        .filter(|f| bibtex.get_slice(f.name) == "eprint")
-                        .map(|f| bibtex.get_braceless_slice(f.value))
-                        .filter_map(|e| ArxivId::try_from(e).ok())
-                })
-                .collect::<HashSet<_>>();
-            for eprint in eprints {
-                if let Some(v) = eprint.version {
-                    linter
-                        .arxiv_latest
-                        .entry(eprint.id)
-                        .and_modify(|u| *u = std::cmp::max(*u, v))
-                        .or_insert(v);
+            let (files, trees) = match cargs.files.list_files_with_includes() {
+                Ok(listed) => listed,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            for f in files.iter() {
+                config.import_strings(&f.bib);
+                config.import_keys(&f.bib);
+            }
+            // the files of an include tree are linted as one bibliography
+            let mut together = vec![None; files.len()];
+            for tree in trees.iter() {
+                let bibs = tree.iter().map(|i| &files[*i].bib).collect::<Vec<_>>();
+                let diagnostics = bibadac::includes::lint_together(&bibs, &config);
+                for (i, diagnostics) in tree.iter().zip(diagnostics) {
+                    together[*i] = Some(diagnostics);
                 }
             }
+            let mut cache = match &cargs.config.cache_results {
+                Some(path) if !cargs.config.no_cache_results => {
+                    use bibadac::results_cache::{fingerprint, ResultsCache};
+                    let cache = ResultsCache::load(path, &fingerprint(&config, helper.as_ref()));
+                    Some((path, cache))
+                }
+                _ => None,
+            };
 
-            let files = cargs.files.list_files();
-            let inputs = files
-                .iter()
-                .map(|f| {
-                    let bibtex = BibFile::new(&f.content);
-                    (f, bibtex)
-                })
-                .collect::<Vec<_>>();
+            // on Ctrl-C, the files checked so far are reported
+            if let Err(e) = interrupt::install() {
+                eprintln!("{} Could not listen to Ctrl-C: {}", "[WARN]".yellow(), e);
+            }
+            // the errors are printed as they are found, unless
+            // they are filtered, grouped, timed, or reported otherwise
+            let streaming = cache.is_none()
+                && !cargs.config.to_json
+                && cargs.config.to_html.is_none()
+                && cargs.config.since.is_none()
+                && cargs.config.baseline.is_none()
+                && !cargs.config.executive_summary
+                && cargs.config.group_by == GroupBy::None
+                && cargs.config.max_lints_per_file == 0
+                && cargs.config.entry_type.is_empty()
+                && !cargs.config.timings
+                && !cargs.config.emit_waivers
+                && !cargs.config.online;
+            // the dois of a file are followed once it is linted
+            let mut online = None;
+            if cargs.config.online && bibadac::network::is_offline() {
+                eprintln!("{} --online is ignored in offline mode", "[WARN]".yellow());
+            } else if cargs.config.online {
+                use bibadac::doi_targets::{doi_fields, DoiResolver, DomainMap};
+                let user_agent = bibadac::setup::user_agent(
+                    cargs.config.user_agent.as_deref(),
+                    cargs.config.polite_email.as_deref(),
+                );
+                let mut resolver = match user_agent.and_then(|agent| DoiResolver::new(&agent)) {
+                    Ok(resolver) => resolver,
+                    Err(e) => {
+                        eprintln!("{} {}", "[ERR]".red(), e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                // the budget is shared by all the files
+                let dois = files
+                    .iter()
+                    .flat_map(|f| doi_fields(&f.bib))
+                    .map(|field| field.doi)
+                    .collect::<Vec<_>>();
+                let verified = bibadac::cache::Cache::default_dir()
+                    .and_then(|dir| bibadac::cache::Cache::existing(dir).ok());
+                resolver.limit(
+                    &dois,
+                    &online_budget(cargs.config.max_requests, &cargs.config.online_only),
+                    verified.as_ref(),
+                );
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_io()
+                    .enable_time()
+                    .build()
+                    .expect("Unable to create the asynchronous runtime");
+                online = Some((rt, resolver, DomainMap::new(&check_section.doi_domains)));
+            }
             let mut lints = vec![];
-            for (bib, tex) in inputs.iter() {
-                if !cargs.config.concise {
-                    lints.push((
-                        *bib,
-                        tex,
-                        linter.lint_file(&tex, tex.list_entries().collect()),
-                    ));
+            let mut cached = vec![];
+            // the files whose results are cached are not timed
+            let mut timings = vec![];
+            // the errors accepted by a `% bibadac-accept` comment
+            let mut waived = vec![];
+            for (i, f) in files.iter().enumerate() {
+                if interrupt::is_interrupted() {
+                    break;
+                }
+                let mut timing = cargs.config.timings.then(|| FileTimings {
+                    file: f.name.to_string_lossy().to_string(),
+                    ..FileTimings::default()
+                });
+                let waivers = bibadac::waivers::Waivers::of(&f.bib);
+                let (mut diagnostics, from_cache) = match &mut cache {
+                    _ if together[i].is_some() => {
+                        let diagnostics = together[i].take().unwrap_or_default();
+                        if streaming {
+                            for d in diagnostics.iter().filter(|d| !waivers.accepts(d)) {
+                                print_bib_lint(f, d, cargs.config.excerpt_width);
+                            }
+                        }
+                        (diagnostics, false)
+                    }
+                    Some((_, cache)) => cache.lint_one(&f.bib, &config),
+                    None if streaming => {
+                        let mut diagnostics = vec![];
+                        f.bib.lint_streaming(&config, |d| {
+                            if !waivers.accepts(&d) {
+                                print_bib_lint(f, &d, cargs.config.excerpt_width);
+                            }
+                            diagnostics.push(d);
+                        });
+                        (diagnostics, false)
+                    }
+                    None => {
+                        let mut diagnostics = vec![];
+                        f.bib.lint_timed(&config, timing.as_mut(), |d| diagnostics.push(d));
+                        timings.extend(timing);
+                        (diagnostics, false)
+                    }
+                };
+                if let Some((rt, resolver, domains)) = &mut online {
+                    use bibadac::doi_targets::online_diagnostics;
+                    let (found, failures) =
+                        rt.block_on(online_diagnostics(&f.bib, resolver, domains, &config));
+                    for (doi, e) in failures {
+                        eprintln!("{} {:?}: doi {}: {}", "[WARN]".yellow(), f.name, doi, e);
+                    }
+                    diagnostics.extend(found);
+                }
+                let before = diagnostics.len();
+                diagnostics.retain(|d| !waivers.accepts(d));
+                waived.push(before - diagnostics.len());
+                lints.push((f, diagnostics));
+                cached.push(from_cache);
+            }
+            if cargs.config.timings {
+                eprint!("{}", timings_table(&timings));
+            }
+            if cargs.config.emit_waivers {
+                use bibadac::waivers::emit_waivers;
+                for (bib, diagnostics) in lints.iter() {
+                    for (line, key, waiver) in emit_waivers(&bib.bib, diagnostics) {
+                        println!("{} {}:{} {}", "==>".blue(), bib.name.display(), line, key);
+                        println!("{}", waiver.to_comment());
+                    }
+                }
+                return exit_code;
+            }
+            let interrupted = lints.len() < files.len();
+            if interrupted {
+                exit_code = ExitCode::from(interrupt::EXIT_CODE);
+            }
+            if cargs.config.fix && !interrupted {
+                let patch_out = cargs.config.patch_out.as_deref();
+                if let Err(e) = fix_files(&files, &config, cargs.config.dry_run, patch_out) {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            if let Some((path, mut cache)) = cache {
+                // the results of the files left out are kept for the next run
+                if !interrupted {
+                    cache.prune();
+                }
+                if let Err(e) = cache.save(path) {
+                    eprintln!(
+                        "{} Could not save the results cache: {}",
+                        "[WARN]".yellow(),
+                        e
+                    );
+                }
+            }
+
+            // the errors of the entries of other types
+            let mut other_types = vec![0; lints.len()];
+            if !cargs.config.entry_type.is_empty() {
+                use bibadac::report::suppress_entries;
+                for ((bib, diagnostics), other_types) in
+                    lints.iter_mut().zip(other_types.iter_mut())
+                {
+                    let entries = bib.bib.other_entry_types(&cargs.config.entry_type);
+                    *other_types = suppress_entries(diagnostics, &entries);
+                }
+            }
+
+            let mut suppressed = vec![0; lints.len()];
+            if let Some(rev) = &cargs.config.since {
+                use bibadac::baseline::{since, Git};
+                for ((bib, diagnostics), suppressed) in lints.iter_mut().zip(suppressed.iter_mut())
+                {
+                    match since(
+                        &Git,
+                        rev,
+                        &bib.name,
+                        &bib.bib,
+                        std::mem::take(diagnostics),
+                        &config,
+                    ) {
+                        Ok((new, count)) => {
+                            *diagnostics = new;
+                            *suppressed = count;
+                        }
+                        Err(e) => {
+                            eprintln!("{} {:?}: {}", "[ERR]".red(), bib.name, e);
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+            }
+
+            let mut fixed = vec![0; lints.len()];
+            if interrupted && cargs.config.update_baseline {
+                eprintln!(
+                    "{} The check was interrupted, the baseline is not updated",
+                    "[WARN]".yellow()
+                );
+            } else if let Some(path) = &cargs.config.baseline {
+                use bibadac::baseline::new_since_report;
+                let report = if cargs.config.update_baseline {
+                    let report = json_report(&lints, false);
+                    let written = serde_json::to_string_pretty(&report)
+                        .map_err(|e| e.to_string())
+                        .and_then(|s| std::fs::write(path, s).map_err(|e| e.to_string()));
+                    if let Err(e) = written {
+                        eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                    Ok(report)
                 } else {
-                    lints.push((
-                        *bib,
-                        tex,
-                        linter
-                            .lint_file(&tex, tex.list_entries().collect())
-                            .into_iter()
-                            .filter(|l| l.msg.is_crucial())
-                            .collect(),
-                    ));
+                    std::fs::read_to_string(path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|s| JsonReport::parse(&s).map_err(|e| e.to_string()))
+                };
+                let report = match report {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                for (((bib, diagnostics), suppressed), fixed) in lints
+                    .iter_mut()
+                    .zip(suppressed.iter_mut())
+                    .zip(fixed.iter_mut())
+                {
+                    let name = bib.name.to_string_lossy();
+                    let errors = report
+                        .files
+                        .iter()
+                        .find(|entry| entry.file == name)
+                        .map_or(&[][..], |entry| &entry.errors);
+                    let (new, count, gone) =
+                        new_since_report(&bib.bib, std::mem::take(diagnostics), errors);
+                    *diagnostics = new;
+                    *suppressed = count;
+                    *fixed = gone;
                 }
             }
 
+            if let Some(path) = &cargs.config.to_html {
+                write_html_report(path, &lints, cargs.config.excerpt_width);
+            }
+
             if cargs.config.to_json {
-                print_json_lints(lints);
+                print_json_lints(&lints, interrupted, cargs.config.schema, timings);
                 return exit_code;
             }
 
+            if interrupted {
+                println!(
+                    "{} interrupted: {} of {} files checked",
+                    "[WARN]".yellow(),
+                    lints.len(),
+                    files.len()
+                );
+            }
+
             // 1. print the number of errors for every input
-            for (bib, _, lints) in lints.iter() {
+            for ((((((bib, lints), cached), suppressed), fixed), other_types), waived) in lints
+                .iter()
+                .zip(cached)
+                .zip(suppressed)
+                .zip(fixed)
+                .zip(other_types)
+                .zip(waived)
+            {
+                let mut notes = vec![];
+                if let Some((from, line)) = &bib.included_from {
+                    notes.push(format!("included from {}:{}", from.display(), line));
+                }
+                if cached {
+                    notes.push("cached".to_string());
+                }
+                if other_types > 0 {
+                    notes.push(format!(
+                        "{} issues of other entry types suppressed",
+                        other_types
+                    ));
+                }
+                if suppressed > 0 {
+                    notes.push(format!("{} baseline issues suppressed", suppressed));
+                }
+                if fixed > 0 {
+                    notes.push(format!("{} baseline issues fixed", fixed));
+                }
+                if waived > 0 {
+                    notes.push(format!("{} issues waived", waived));
+                }
+                let cached = if notes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", notes.join(", "))
+                };
                 if lints.len() == 0 {
-                    println!("{} \t\t {:?}", "[OK]".green(), bib.name);
+                    println!("{} \t\t {:?}{}", "[OK]".green(), bib.name, cached);
                 } else {
                     let err = if lints.len() > 1 { "errors" } else { "error" };
-                    println!("{} {} {} \t {:?}", "[KO]".red(), lints.len(), err, bib.name);
+                    println!(
+                        "{} {} {} \t {:?}{}",
+                        "[KO]".red(),
+                        lints.len(),
+                        err,
+                        bib.name,
+                        cached
+                    );
                 }
             }
 
@@ -379,108 +2197,340 @@ fn main() -> ExitCode {
                 return exit_code;
             }
 
-            for (bib, bibtex, lints) in lints.iter() {
-                for l in lints {
-                    print_bib_lint(bibtex, bib, l);
+            // (the errors were already printed when streaming)
+            for (bib, lints) in lints.iter().filter(|_| !streaming) {
+                use bibadac::report::{group_lints, limit_lints, thousands};
+                let mut groups =
+                    group_lints(lints, &bib.bib.entry_keys(), cargs.config.group_by.into());
+                let hidden = limit_lints(&mut groups, cargs.config.max_lints_per_file);
+                for group in groups {
+                    if let Some(heading) = &group.heading {
+                        println!("{} {}", "==>".blue(), heading.bold());
+                    }
+                    for i in group.lints {
+                        print_bib_lint(bib, &lints[i], cargs.config.excerpt_width);
+                    }
+                }
+                if hidden > 0 {
+                    println!(
+                        "… and {} more (use --max-lints-per-file 0 to show all)",
+                        thousands(hidden)
+                    );
                 }
             }
             
             // if we are not outputting JSON, nor having an 
             // “executive summary” then we probably want to
             // have the correct exit code.
-            if lints.len() > 0 {
+            if lints.iter().any(|(_, d)| !d.is_empty()) && !interrupted {
                 exit_code = ExitCode::FAILURE;
             }
 
-            return exit_code;
-        }
-        SubCommand::Format(cargs) => {
-            let mut db = LocalBibDb::new();
-            if let Some(path) = cargs.config.file_db {
-                let start_bib =
-                    std::fs::read_to_string(path).expect("Could not read the helper bibfile");
-                db = db.import_bibtex(&start_bib);
+            return exit_code;
+        }
+        SubCommand::Format(cargs) => {
+            let profiles = match ConfigFile::find(cargs.config_file.as_deref()) {
+                Ok(file) => file.format.profiles,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    // the documents of stdin are given back on any error
+                    return if cargs.list_profiles {
+                        ExitCode::FAILURE
+                    } else {
+                        give_back_stdin(&cargs.files.list_files())
+                    };
+                }
+            };
+            if cargs.list_profiles {
+                let defaults = serde_json::to_value(FormatConfig::defaults()).unwrap_or_default();
+                for name in profiles.keys() {
+                    println!("{}", name.bold());
+                    match cargs.settings(&profiles, Some(name)) {
+                        Ok(settings) => {
+                            for (setting, value) in changed_settings(&defaults, &settings) {
+                                println!("  {} = {}", setting, value);
+                            }
+                        }
+                        Err(e) => println!("  {} {}", "[ERR]".red(), e),
+                    }
+                }
+                return ExitCode::SUCCESS;
+            }
+            // the documents of stdin are given back on any error
+            let mut inputs = cargs.files.list_files();
+            let config: FormatConfig = match cargs
+                .settings(&profiles, cargs.profile.as_deref())
+                .and_then(|settings| serde_json::from_value(settings).map_err(|e| e.to_string()))
+            {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return give_back_stdin(&inputs);
+                }
+            };
+
+            let mut db = LocalBibDb::new();
+            if let Some(path) = &config.entries.file_db {
+                match config.entries.database(&cargs.files.resolve(path)) {
+                    Ok(database) => db = database,
+                    Err(e) => {
+                        eprintln!("{} {}", "[ERR]".red(), e);
+                        return give_back_stdin(&inputs);
+                    }
+                }
+            }
+
+            if config.in_place
+                && (!config.entries.keep_field.is_empty() || !config.entries.entry_field.is_empty())
+                && !config.allow_data_loss
+            {
+                eprintln!(
+                    "{} --in-place with --keep-field or --entry-field removes data from the files, use --allow-data-loss to proceed",
+                    "[ERR]".red()
+                );
+                return give_back_stdin(&inputs);
             }
 
-            let inputs = cargs.files.list_files();
+            // refuse to write anything if a single file has conflict markers
+            if config.in_place {
+                use bibadac::conflicts::conflict_markers;
+                let conflicted = inputs
+                    .iter()
+                    .filter(|bib| !bib.from_stdin && !conflict_markers(bib.bib.source()).is_empty())
+                    .collect::<Vec<_>>();
+                for bib in conflicted.iter() {
+                    eprintln!(
+                        "{} {:?} contains merge conflict markers, resolve the conflict before formatting it in place",
+                        "[ERR]".red(),
+                        bib.name
+                    );
+                }
+                if !conflicted.is_empty() {
+                    return give_back_stdin(&inputs);
+                }
+            }
 
-            let mut format_options = FormatOptions::new(&mut db);
-            if !cargs.config.remove_field.is_empty() {
-                format_options.blacklist = Some(cargs.config.remove_field);
+            // the directives are replaced by the entries of the included files
+            if config.inline_includes {
+                use bibadac::includes::inline_includes;
+                for input in inputs.iter_mut() {
+                    if input.bib.markdown().is_some() {
+                        continue;
+                    }
+                    let inlined = inline_includes(&input.name, &input.bib, |path| {
+                        Bibliography::from_path(path)
+                    });
+                    match inlined {
+                        Ok(inlined) => {
+                            let inlined = input.bib.line_ending().apply(&inlined);
+                            input.bib = Bibliography::from(inlined);
+                        }
+                        Err(e) => {
+                            eprintln!("{} {}", "[ERR]".red(), e);
+                            return give_back_stdin(&inputs);
+                        }
+                    }
+                }
             }
-            if !cargs.config.keep_field.is_empty() {
-                format_options.whitelist = Some(cargs.config.keep_field);
+
+            // a document with syntax errors would only be partly formatted
+            for bib in inputs.iter().filter(|bib| bib.from_stdin) {
+                if let Some((line, column)) = bib.bib.first_syntax_error() {
+                    eprintln!(
+                        "{} {:?}:{}:{}: syntax error, the document is left as is",
+                        "[ERR]".red(),
+                        bib.name,
+                        line,
+                        column
+                    );
+                    return give_back_stdin(&inputs);
+                }
             }
-            if !cargs.config.entry_field.is_empty() {
-                format_options.field_filter = Some(cargs.config.entry_field);
+
+            let mut settings = config.entries.options(db);
+            // a file may use the strings of another one
+            if inputs.len() > 1 {
+                let mut usage = bibadac::format::StringUsage::default();
+                for bib in inputs.iter() {
+                    bib.bib.with_bibfile(|file| usage.add(file));
+                }
+                settings.used_strings = Some(usage.used());
             }
+            // the options recorded in the header of the written files
+            let header_options = {
+                let defaults = serde_json::to_value(FormatConfig::defaults()).unwrap_or_default();
+                let current = serde_json::to_value(&config).unwrap_or_default();
+                changed_settings(&defaults, &current)
+                    .into_iter()
+                    .filter(|(setting, _)| {
+                        ![
+                            "to_file",
+                            "in_place",
+                            "allow_data_loss",
+                            "no_header",
+                            "verify_idempotent",
+                        ]
+                        .contains(&setting.as_str())
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let today = {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                bibadac::dates::Date::from_unix_days((now.as_secs() / 86400) as i64).to_string()
+            };
 
-            format_options.sort_fields = cargs.config.sort_fields;
-            format_options.sort_entries = cargs.config.sort_entries;
+            // a bug of the formatter must not reach the files
+            if config.verify_idempotent || cfg!(debug_assertions) {
+                for bib in inputs.iter() {
+                    if let Err(diff) = bib.bib.verify_idempotent(&settings) {
+                        eprintln!(
+                            "{} {:?}: formatting the output again changes it, nothing was written:\n{}",
+                            "[ERR]".red(),
+                            bib.name,
+                            diff
+                        );
+                        eprintln!(
+                            "This is a bug of bibadac, please report it along with the smallest entry that reproduces it"
+                        );
+                        return give_back_stdin(&inputs);
+                    }
+                }
+            }
 
+            // stdout is written once everything is formatted, the
+            // documents of stdin separated by NUL bytes as they were read
+            let mut printed = String::new();
+            let mut stdin_documents = 0;
             for bib in inputs {
-                let bibtex = BibFile::new(&bib.content);
-                let max_field_length = bibtex
-                    .list_entries()
-                    .map(|entry| {
-                        entry
-                            .fields
-                            .iter()
-                            .map(|field| bibtex.get_slice(field.name).len())
-                            .max()
-                            .unwrap_or(0)
-                    })
-                    .max()
-                    .unwrap_or(0);
-                format_options.min_field_length = Some(max_field_length);
+                use bibadac::header::{header, replace_header, HEADER_PREFIX};
+                let (formatted, stats) = bib.bib.format_with_stats(&settings);
+                // the header is replaced in the files, and never written to stdout
+                let formatted = if bib.bib.markdown().is_some() {
+                    formatted
+                } else {
+                    let written = !bib.from_stdin
+                        && (config.to_file
+                            || (config.in_place && bib.bib.source().starts_with(HEADER_PREFIX)));
+                    let header = (written && !config.no_header).then(|| {
+                        header(
+                            env!("CARGO_PKG_VERSION"),
+                            &today,
+                            &bib.name.display().to_string(),
+                            &header_options,
+                        )
+                    });
+                    replace_header(&formatted, header.as_deref())
+                };
+                let ending = config.line_ending.map_or(bib.bib.line_ending(), Into::into);
+                let formatted = ending.apply(&formatted);
+                if !stats.is_lossless() {
+                    eprintln!(
+                        "{} {:?}: dropped {} fields across {} entries; {} entries skipped by --entry-field",
+                        "[WARN]".yellow(),
+                        bib.name,
+                        stats.dropped_fields,
+                        stats.trimmed_entries,
+                        stats.skipped_entries
+                    );
+                }
+                if let Some(flavor) = config.entries.convert_to
+                    && stats.unconverted_entries > 0
+                {
+                    for (key, issue) in bib.bib.conversion_issues(flavor.into()) {
+                        eprintln!(
+                            "{} {:?}: {}: {}, left as is",
+                            "[WARN]".yellow(),
+                            bib.name,
+                            key,
+                            issue
+                        );
+                    }
+                }
+                if stats.unused_strings > 0 {
+                    if config.entries.prune_strings {
+                        eprintln!(
+                            "{:?}: removed {} unused @string definitions",
+                            bib.name, stats.unused_strings
+                        );
+                    } else {
+                        eprintln!(
+                            "{} {:?}: {} unused @string definitions (use --prune-strings to remove them)",
+                            "[WARN]".yellow(),
+                            bib.name,
+                            stats.unused_strings
+                        );
+                    }
+                }
                 use std::io::Write;
-                if cargs.config.to_file {
-                    let newpath = bib.name.with_extension("new.bib");
+                // the files of the editor are not written behind its back
+                if bib.from_stdin {
+                    if stdin_documents > 0 {
+                        printed.push('\0');
+                    }
+                    stdin_documents += 1;
+                    printed.push_str(&formatted);
+                } else if config.to_file {
+                    let newpath = match bib.name.extension() {
+                        Some(ext) if bib.bib.markdown().is_some() => {
+                            bib.name.with_extension(format!("new.{}", ext.to_string_lossy()))
+                        }
+                        _ => bib.name.with_extension("new.bib"),
+                    };
                     let mut out =
                         std::fs::File::create(newpath).expect("Could not create the output file");
-                    write!(
-                        out,
-                        "{}",
-                        bibadac::format::BibFormat {
-                            bib: &bibtex,
-                            options: &format_options
-                        }
-                    )
-                    .expect("Could not write to the output file");
-                } else if cargs.config.in_place {
+                    write!(out, "{}", formatted).expect("Could not write to the output file");
+                } else if config.in_place {
                     let mut out =
                         std::fs::File::create(&bib.name).expect("Could not create the output file");
-                    write!(
-                        out,
-                        "{}",
-                        bibadac::format::BibFormat {
-                            bib: &bibtex,
-                            options: &format_options
-                        }
-                    )
-                    .expect("Could not write to the output file");
+                    write!(out, "{}", formatted).expect("Could not write to the output file");
                 } else {
-                    write!(
-                        std::io::stdout(),
-                        "{}",
-                        bibadac::format::BibFormat {
-                            bib: &bibtex,
-                            options: &format_options
-                        }
-                    )
-                    .expect("Could not write to the output file");
+                    printed.push_str(&formatted);
                 }
             }
+            use std::io::Write;
+            write!(std::io::stdout(), "{}", printed).expect("Could not write to the output file");
         }
         SubCommand::Setup(cargs) => {
             use bibadac::setup::SetupConfig;
 
-            let files = cargs.files.list_files();
+            let files = match cargs.files.list_files_with_includes() {
+                Ok((files, _)) => files,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
 
             let mut config = SetupConfig::default();
-            config.progress = !cargs.config.no_progress;
             config.download_pdf = cargs.config.documents;
-            config.polite_email = cargs.config.polite_email;
+            config.polite_email = cargs.config.polite_email.clone();
+            config.user_agent = cargs.config.user_agent.clone();
+            config.timeout = cargs.config.timeout;
+            config.proxy = cargs.config.proxy.clone();
+            config.arxiv_note = cargs.config.arxiv_note;
+            config.budget = online_budget(cargs.config.max_requests, &cargs.config.online_only);
+            if cargs.config.progress_json {
+                config.progress = bibadac::progress::Progress::json_lines();
+            }
+            if let Err(e) = config.client() {
+                eprintln!("{} {}", "[ERR]".red(), e);
+                return ExitCode::FAILURE;
+            }
+            config.layout = cargs.config.layout.into();
+            config.resume = cargs.config.resume;
+            config.cache = bibadac::cache::Cache::default_dir()
+                .and_then(|dir| bibadac::cache::Cache::open(dir).ok());
+            config.mirror = cargs.config.mirror.clone().map(|dir| {
+                use bibadac::resolver::{Mirror, MirrorMode};
+                let mode = if cargs.config.mirror_record {
+                    MirrorMode::Record
+                } else {
+                    MirrorMode::Replay
+                };
+                Mirror::new(dir, mode)
+            });
             if let Some(path) = &cargs.config.working_directory {
                 config.working_directory = path.clone();
             } else {
@@ -491,35 +2541,127 @@ fn main() -> ExitCode {
             if let Some(database) = &cargs.config.to_file {
                 config.import_bibfile(database);
             }
+            // nothing is written when only printing the urls
+            config.dry_run = cargs.config.print_urls;
+            if (config.download_pdf || config.dry_run) && config.working_directory.is_dir() {
+                use bibadac::file_index::FileIndex;
+                let index = if config.dry_run {
+                    FileIndex::scan(&config.working_directory)
+                } else {
+                    FileIndex::open(&config.working_directory)
+                };
+                match index {
+                    Ok(index) => config.file_index = Some(index),
+                    Err(e) => eprintln!(
+                        "{} could not index the pdfs of {}: {}",
+                        "[WARN]".yellow(),
+                        config.working_directory.display(),
+                        e
+                    ),
+                }
+            }
+
+            if cargs.config.refetch_flagged {
+                use bibadac::linter::LintMessage;
+                use bibadac::setup::RefetchRequest;
+
+                let path = cargs.config.report.as_ref().expect("--report is required");
+                let report = std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| JsonReport::parse(&s).map_err(|e| e.to_string()));
+                let report = match report {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                let flagged = report
+                    .files
+                    .iter()
+                    .flat_map(|entry| entry.errors.iter())
+                    .filter_map(|d| match &d.diagnostic.msg {
+                        LintMessage::LikelyScholarExport { key, .. } => Some(key.as_str()),
+                        _ => None,
+                    })
+                    .collect::<HashSet<_>>();
+                let requests = files
+                    .iter()
+                    .flat_map(|bib| bib.bib.entries())
+                    .filter(|entry| flagged.contains(entry.key.as_str()))
+                    .map(|entry| RefetchRequest::of_entry(&entry))
+                    .collect::<Vec<_>>();
+                if requests.len() < flagged.len() {
+                    eprintln!(
+                        "{} {} flagged entries are not in the given files",
+                        "[WARN]".yellow(),
+                        flagged.len() - requests.len()
+                    );
+                }
+
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_io()
+                    .enable_time()
+                    .build()
+                    .expect("Unable to create the asynchronous runtime");
+                let entries = rt.block_on(config.refetch(&requests));
+                let mut failed = false;
+                for (key, entry) in entries.iter() {
+                    match entry {
+                        Some(entry) if !cargs.config.no_output => println!("{}", entry),
+                        Some(_) => {}
+                        None => {
+                            eprintln!("{} Could not fetch {} again", "[ERR]".red(), key.yellow());
+                            failed = true;
+                        }
+                    }
+                }
+                let fetched = entries
+                    .iter()
+                    .filter_map(|(_, e)| e.as_ref())
+                    .collect::<Vec<_>>();
+                if let Err(e) = cargs.config.save(&fetched) {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+                return if failed {
+                    ExitCode::FAILURE
+                } else {
+                    ExitCode::SUCCESS
+                };
+            }
 
             let mut dois: HashSet<String> = HashSet::new();
             let mut eprints: HashSet<String> = HashSet::new();
             let mut sha256s: HashSet<String> = HashSet::new();
 
             for bib in files {
-                let bibtex = BibFile::new(&bib.content);
-                for entry in bibtex.list_entries() {
-                    for field in entry.fields.iter() {
-                        let key = bibtex.get_slice(field.name);
-                        let value = bibtex.get_braceless_slice(field.value);
-                        match key {
-                            "doi" => {
-                                dois.insert(value.to_string());
-                            }
-                            "eprint" => {
-                                eprints.insert(value.to_string());
-                                // add the "non pinned" version of the eprint
-                                if let Ok(e) = ArxivId::try_from(value) {
-                                    eprints.insert(e.id.to_string());
+                bib.bib.with_bibfile(|bibtex| {
+                    for entry in bibtex.list_entries() {
+                        // HAL, bioRxiv, ... eprints are not downloaded
+                        let is_arxiv = EprintType::of_entry(bibtex, &entry) == EprintType::Arxiv;
+                        for field in entry.fields.iter() {
+                            let key = bibtex.get_slice(field.name);
+                            let value = bibtex.get_braceless_slice(field.value);
+                            match key {
+                                "doi" => {
+                                    dois.insert(value.to_string());
                                 }
+                                "eprint" if is_arxiv => {
+                                    eprints.insert(value.to_string());
+                                    // add the "non pinned" version of the eprint
+                                    if let Ok(e) = ArxivId::try_from(value) {
+                                        eprints.insert(e.id.to_string());
+                                    }
+                                }
+                                "sha256" => {
+                                    sha256s.insert(value.to_string());
+                                }
+                                _ => {}
                             }
-                            "sha256" => {
-                                sha256s.insert(value.to_string());
-                            }
-                            _ => {}
                         }
                     }
-                }
+                });
             }
 
             for arxiv in &cargs.config.arxiv {
@@ -530,16 +2672,63 @@ fn main() -> ExitCode {
                 dois.insert(doi.to_string());
             }
 
+            let scans = match &cargs.config.from_pdf {
+                Some(dir) => match bibadac::pdf_identifiers::scan_dir(dir) {
+                    Ok(scans) => scans,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "[ERR]".red(), dir.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => vec![],
+            };
+            // the published version is preferred to the preprint
+            for scan in scans.iter() {
+                if let Some(doi) = &scan.doi {
+                    dois.insert(doi.clone());
+                } else if let Some(arxiv) = &scan.arxiv {
+                    eprints.insert(arxiv.clone());
+                }
+            }
+
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_io()
                 .enable_time()
                 .build()
                 .expect("Unable to create the asynchronous runtime");
 
-            rt.block_on(async {
+            if cargs.config.print_urls {
+                let urls = rt.block_on(config.pdf_urls(&dois, &eprints));
+                for (identifier, url) in urls {
+                    println!("{}\t{}", identifier, url.as_deref().unwrap_or("-"));
+                }
+                return ExitCode::SUCCESS;
+            }
+
+            let saved = rt.block_on(async {
                 let response = config.run(dois, eprints, sha256s).await;
+                // the entries of the pdfs point to them
+                let entries = response
+                    .entries
+                    .iter()
+                    .map(|(key, entry)| {
+                        let scan = scans.iter().find(|s| s.identifier() == Some(key.as_str()));
+                        match (entry, scan) {
+                            (Some(entry), Some(scan)) => {
+                                Some(bibadac::pdf_identifiers::annotate_entry(
+                                    entry,
+                                    &[
+                                        ("sha256", &scan.sha256),
+                                        ("filename", &scan.path.display().to_string()),
+                                    ],
+                                ))
+                            }
+                            (entry, _) => entry.clone(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
                 if !cargs.config.no_output {
-                    for (_, result) in response.entries.iter() {
+                    for result in entries.iter() {
                         if let Some(entry) = result {
                             println!("{}", entry);
                         }
@@ -550,44 +2739,806 @@ fn main() -> ExitCode {
                         }
                     }
                 }
-                if let Some(path) = &cargs.config.to_file {
-                    use std::io::Write;
-                    // create the file if it does not exist already
-                    // otherwise *append* to it
-                    let file = std::fs::OpenOptions::new()
-                        .write(true)
-                        .append(true)
-                        .create(true)
-                        .open(path)
-                        .expect("Could not open the output file");
-                    let mut file = std::io::BufWriter::new(file);
-                    for (_, result) in response.entries.iter() {
-                        if let Some(entry) = result {
-                            writeln!(file, "{}", entry)
-                                .expect("Could not write to the output file");
+                let pdfs = response.pdfs.iter().filter_map(|(_, pdf)| pdf.as_ref());
+                let fetched = entries
+                    .iter()
+                    .flatten()
+                    .chain(pdfs.map(|pdf| &pdf.entry))
+                    .collect::<Vec<_>>();
+                let saved = cargs.config.save(&fetched);
+                for (key, res) in response.entries.iter() {
+                    if res.is_none() {
+                        tracing::info!(target: PROGRESS, "[ERR] Could not find entry for {}", key);
+                    }
+                }
+                for (key, res) in response.pdfs.iter() {
+                    if res.is_none() {
+                        tracing::info!(target: PROGRESS, "[ERR] Could not find pdf for {}", key);
+                    }
+                }
+                for identifier in response.exhausted.iter() {
+                    eprintln!(
+                        "{} {} {}",
+                        "[WARN]".yellow(),
+                        identifier.cache_key(),
+                        bibadac::online::BUDGET_EXHAUSTED
+                    );
+                }
+                saved
+            });
+            if let Err(e) = saved {
+                eprintln!("{} {}", "[ERR]".red(), e);
+                return ExitCode::FAILURE;
+            }
+
+            let unidentified = scans
+                .iter()
+                .filter(|s| s.identifier().is_none())
+                .collect::<Vec<_>>();
+            if !unidentified.is_empty() {
+                eprintln!(
+                    "{} {} pdfs without a doi or arXiv identifier:",
+                    "[WARN]".yellow(),
+                    unidentified.len()
+                );
+                for scan in unidentified {
+                    eprintln!(
+                        "  {}\t{}",
+                        scan.path.display(),
+                        scan.title.as_deref().unwrap_or("(no title found)")
+                    );
+                }
+            }
+        }
+        SubCommand::Merge(cargs) => {
+            use bibadac::merge::{NonInteractive, Responder, TerminalResponder};
+            use std::io::IsTerminal;
+
+            let files = cargs.files.list_files();
+
+            // never wait for an answer that cannot come
+            let mut responder: Box<dyn Responder> =
+                if cargs.config.interactive && std::io::stdin().is_terminal() {
+                    Box::new(TerminalResponder)
+                } else {
+                    if cargs.config.interactive {
+                        eprintln!(
+                            "Not running in a terminal, keeping the first entry of each conflict"
+                        );
+                    }
+                    Box::new(NonInteractive)
+                };
+            let names = files
+                .iter()
+                .map(|f| f.name.display().to_string())
+                .collect::<Vec<_>>();
+            let bibliographies = files.into_iter().map(|f| f.bib);
+            let mut merged = if cargs.config.annotate_merge {
+                Bibliography::merge_all_annotated(bibliographies, &names, responder.as_mut())
+            } else {
+                Bibliography::merge_all(bibliographies, responder.as_mut())
+            };
+
+            if cargs.config.collapse_preprints {
+                let (collapsed, renames) = bibadac::merge::collapse_preprints(merged.entries());
+                merged = Bibliography::from_entries(&collapsed);
+                eprintln!("Folded {} preprints into their published version", renames.len());
+                for path in cargs.config.tex.iter() {
+                    let tex = std::fs::read_to_string(path).expect("Could not read the tex file");
+                    let renamed = bibadac::preprints::rename_cite_keys(&tex, &renames);
+                    if renamed != tex {
+                        std::fs::write(path, renamed).expect("Could not write the tex file");
+                    }
+                }
+            }
+
+            use std::io::Write;
+            let formatted = merged.format(&FormatSettings::default());
+            if let Some(path) = &cargs.config.to_file {
+                let mut out =
+                    std::fs::File::create(path).expect("Could not create the output file");
+                write!(out, "{}", formatted).expect("Could not write to the output file");
+            } else {
+                write!(std::io::stdout(), "{}", formatted)
+                    .expect("Could not write to the output file");
+            }
+        }
+        SubCommand::Split(cargs) => {
+            use bibadac::split::{Parts, SplitBy};
+
+            let by = match cargs.config.by.parse::<SplitBy>() {
+                Ok(by) => by,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let mut parts = Parts::default();
+            for bib in cargs.files.list_files() {
+                bib.bib.with_bibfile(|bibtex| parts.add(bibtex, &by));
+            }
+            for (part, name) in parts.conflicts() {
+                eprintln!(
+                    "{} the entries of {}.bib need several definitions of @string {}, the last one is written",
+                    "[WARN]".yellow(),
+                    part,
+                    name.yellow()
+                );
+            }
+            let parts = parts.contents();
+
+            // refuse to write anything if a single file would be overwritten
+            let targets = parts
+                .keys()
+                .map(|name| cargs.config.out_dir.join(format!("{}.bib", name)))
+                .collect::<Vec<_>>();
+            let existing = targets.iter().filter(|p| p.exists()).collect::<Vec<_>>();
+            if !cargs.config.force && !existing.is_empty() {
+                for path in existing {
+                    eprintln!(
+                        "{} {} already exists (use --force to overwrite)",
+                        "[ERR]".red(),
+                        path.display()
+                    );
+                }
+                return ExitCode::FAILURE;
+            }
+            std::fs::create_dir_all(&cargs.config.out_dir)
+                .expect("Could not create the output directory");
+
+            let mut db = LocalBibDb::new();
+            if let Some(path) = &cargs.format.file_db {
+                match cargs.format.database(&cargs.files.resolve(path)) {
+                    Ok(database) => db = database,
+                    Err(e) => {
+                        eprintln!("{} {}", "[ERR]".red(), e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            let settings = cargs.format.options(db);
+
+            use std::io::Write;
+            for (path, content) in targets.iter().zip(parts.values()) {
+                let formatted = Bibliography::from(content.clone()).format(&settings);
+                let mut out =
+                    std::fs::File::create(path).expect("Could not create the output file");
+                write!(out, "{}", formatted).expect("Could not write to the output file");
+            }
+        }
+        SubCommand::Extract(cargs) => {
+            use bibadac::extract::{cited_keys, extract};
+
+            let config = &cargs.config;
+            // BibTeX requires the crossref parents after their children
+            if cargs.format.sort_entries {
+                eprintln!(
+                    "{} --sort-entries would reorder the crossref parents written after their children",
+                    "[ERR]".red()
+                );
+                return ExitCode::FAILURE;
+            }
+            let mut keys: Vec<String> = vec![];
+            for path in config.tex.iter() {
+                let tex = match std::fs::read_to_string(path) {
+                    Ok(tex) => tex,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                for key in cited_keys(&tex) {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+            let master = match Bibliography::from_path(&config.from) {
+                Ok(master) => master,
+                Err(e) => {
+                    eprintln!("{} {}: {}", "[ERR]".red(), config.from.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let extraction = master.with_bibfile(|bib| extract(bib, &keys));
+            for key in extraction.missing.iter() {
+                eprintln!(
+                    "{} {} is not in {}",
+                    "[WARN]".yellow(),
+                    key.yellow(),
+                    config.from.display()
+                );
+            }
+
+            let mut db = LocalBibDb::new();
+            if let Some(path) = &cargs.format.file_db {
+                match cargs.format.database(path) {
+                    Ok(database) => db = database,
+                    Err(e) => {
+                        eprintln!("{} {}", "[ERR]".red(), e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            let settings = cargs.format.options(db);
+            let formatted = Bibliography::from(extraction.content).format(&settings);
+            match &config.output {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, formatted) {
+                        eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+                None => print!("{}", formatted),
+            }
+            if config.strict && !extraction.missing.is_empty() {
+                return ExitCode::FAILURE;
+            }
+        }
+        SubCommand::Keygen(cargs) => {
+            use bibadac::keys::Pattern;
+            use std::collections::HashMap;
+
+            let pattern = match Pattern::parse(&cargs.config.pattern) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    eprintln!("{} {}: {}", "[ERR]".red(), cargs.config.pattern, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let files = cargs.files.list_files();
+            for file in files.iter() {
+                if files.len() > 1 {
+                    println!("{}", file.name.display().to_string().bold());
+                }
+                file.bib.with_bibfile(|bibtex| {
+                    let proposed = bibtex
+                        .list_entries()
+                        .map(|entry| {
+                            let fields = entry
+                                .fields
+                                .iter()
+                                .map(|f| {
+                                    let name = bibtex.get_slice(f.name).to_lowercase();
+                                    (name, bibtex.get_slice(f.value).to_string())
+                                })
+                                .collect::<HashMap<_, _>>();
+                            (bibtex.get_slice(entry.key), pattern.generate(&fields))
+                        })
+                        .collect::<Vec<_>>();
+                    let mut counts: HashMap<&str, usize> = HashMap::new();
+                    for key in proposed.iter().filter_map(|(_, k)| k.as_deref().ok()) {
+                        *counts.entry(key).or_default() += 1;
+                    }
+                    for (current, key) in proposed.iter() {
+                        match key {
+                            Ok(key) if key == current => println!("{} -> {}", current, key),
+                            Ok(key) if counts[key.as_str()] > 1 => println!(
+                                "{} -> {} {}",
+                                current,
+                                key.green(),
+                                "(duplicate)".yellow()
+                            ),
+                            Ok(key) => println!("{} -> {}", current, key.green()),
+                            Err(e) => println!("{} -> {} {}", current, "[WARN]".yellow(), e),
                         }
                     }
-                    for (_, result) in response.pdfs.iter() {
-                        if let Some(pdf) = result {
-                            writeln!(file, "{}", pdf.entry)
-                                .expect("Could not write to the output file");
+                });
+            }
+        }
+        SubCommand::Authors(cargs) => {
+            use bibadac::authors::author_clusters;
+
+            let mut people = vec![];
+            for file in cargs.files.list_files() {
+                file.bib.with_bibfile(|bibtex| {
+                    for entry in bibtex.list_entries() {
+                        let key = bibtex.get_slice(entry.key).trim();
+                        for f in entry.fields.iter() {
+                            let name = bibtex.get_slice(f.name).to_lowercase();
+                            if name == "author" || name == "editor" {
+                                let value = bibtex.get_braceless_slice(f.value);
+                                people.push((key.to_string(), value.to_string()));
+                            }
+                        }
+                    }
+                });
+            }
+            let clusters = author_clusters(people.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+                .into_iter()
+                .filter(|cluster| cluster.spellings.len() as u64 >= cargs.min_cluster)
+                .collect::<Vec<_>>();
+            if cargs.to_json {
+                serde_json::to_writer_pretty(std::io::stdout(), &clusters)
+                    .expect("Could not write json report");
+                return ExitCode::SUCCESS;
+            }
+            for cluster in clusters.iter() {
+                println!("{}", cluster.spellings[0].name.bold());
+                for spelling in cluster.spellings.iter() {
+                    println!("  {}: {}", spelling.name, spelling.keys.join(", "));
+                }
+            }
+            let count = if clusters.is_empty() {
+                "No".green()
+            } else {
+                clusters.len().to_string().yellow()
+            };
+            println!(
+                "{} people with at least {} spellings",
+                count, cargs.min_cluster
+            );
+        }
+        SubCommand::Fingerprint(cargs) => {
+            use bibadac::bibtex::entry_fingerprint;
+
+            let mut fingerprints = vec![];
+            for file in cargs.files.list_files() {
+                file.bib.with_bibfile(|bibtex| {
+                    for entry in bibtex.list_entries() {
+                        let fingerprint = entry_fingerprint(bibtex, &entry)
+                            .iter()
+                            .map(|b| format!("{:02x}", b))
+                            .collect::<String>();
+                        fingerprints.push(EntryFingerprint {
+                            file: file.name.display().to_string(),
+                            key: bibtex.get_slice(entry.key).trim().to_string(),
+                            fingerprint,
+                        });
+                    }
+                });
+            }
+            if cargs.to_json {
+                serde_json::to_writer_pretty(std::io::stdout(), &fingerprints)
+                    .expect("Could not write json report");
+                return ExitCode::SUCCESS;
+            }
+            for f in fingerprints.iter() {
+                println!("{}\t{}\t{}", f.fingerprint, f.key, f.file);
+            }
+        }
+        SubCommand::Score(cargs) => {
+            use bibadac::score::{score_file, sort_scores};
+
+            let file = match ConfigFile::find(cargs.config_file.as_deref()) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            // the severities of the rules decide what an error is
+            let mut config = LintConfig {
+                severities: file.check.rules,
+                ..LintConfig::default()
+            };
+            let files = cargs.files.list_files();
+            for f in files.iter() {
+                config.import_strings(&f.bib);
+                config.import_keys(&f.bib);
+            }
+            let mut scores = files
+                .iter()
+                .flat_map(|f| {
+                    let name = f.name.display().to_string();
+                    score_file(&file.score.weights, &name, &f.bib, &config)
+                })
+                .filter(|s| cargs.below.is_none_or(|below| s.score < below))
+                .collect::<Vec<_>>();
+            sort_scores(&mut scores);
+            if cargs.to_json {
+                serde_json::to_writer_pretty(std::io::stdout(), &scores)
+                    .expect("Could not write json report");
+                return ExitCode::SUCCESS;
+            }
+            for s in scores.iter() {
+                let problems = s.problems.iter().take(3).cloned().collect::<Vec<_>>();
+                println!(
+                    "{:>3}\t{}\t{}\t{}",
+                    s.score,
+                    s.key,
+                    s.file,
+                    problems.join(", ")
+                );
+            }
+        }
+        SubCommand::Export(cargs) => {
+            use std::io::Write;
+
+            let files = FileArgs {
+                stdin: cargs.stdin || cargs.bib.is_empty(),
+                stdin_filename: vec![],
+                input_format: None,
+                bib: cargs.bib.clone(),
+            };
+            let mut stdout = std::io::stdout().lock();
+            for file in files.list_files() {
+                let entries = match cargs.format {
+                    EntryFormat::Ndjson => file.bib.with_bibfile(bibadac::ndjson::export),
+                };
+                for entry in entries {
+                    serde_json::to_writer(&mut stdout, &entry).expect("Could not write entry");
+                    writeln!(stdout).expect("Could not write entry");
+                }
+            }
+        }
+        SubCommand::Import(cargs) => {
+            let content = match &cargs.input {
+                Some(path) => std::fs::read_to_string(path),
+                None => std::io::read_to_string(std::io::stdin()),
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("{} Could not read the entries: {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let bibtex = match cargs.format {
+                EntryFormat::Ndjson => bibadac::ndjson::import(&content),
+            };
+            let bibtex = match bibtex {
+                Ok(bibtex) => bibtex,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            // the values are written as they are
+            let settings = FormatSettings {
+                format_author: false,
+                ..FormatSettings::default()
+            };
+            print!("{}", Bibliography::from(bibtex).format(&settings));
+        }
+        SubCommand::Assert(cargs) => {
+            use bibadac::assertions::Assertions;
+
+            let mut config = match ConfigFile::find(cargs.config_file.as_deref()) {
+                Ok(file) => file.assert,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            config.extend(cargs.config.clone());
+
+            let mut assertions = Assertions {
+                min_entries: config.min_entries,
+                ..Assertions::default()
+            };
+            for condition in config.every.iter() {
+                if let Err(e) = assertions.require(condition) {
+                    eprintln!("{} {}: {}", "[ERR]".red(), condition, e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            for entrytype in config.forbid_type.iter() {
+                assertions.forbid_type(entrytype);
+            }
+
+            let mut count = 0;
+            let mut failed = 0;
+            for file in cargs.files.list_files() {
+                for entry in file.bib.entries() {
+                    count += 1;
+                    for violation in assertions.check_entry(&entry) {
+                        failed += 1;
+                        println!("{} {}: {}", "[ERR]".red(), file.name.display(), violation);
+                    }
+                }
+            }
+            if let Some(violation) = assertions.check_count(count) {
+                failed += 1;
+                println!("{} {}", "[ERR]".red(), violation);
+            }
+            if failed > 0 {
+                eprintln!("{} assertions do not hold", failed);
+                return ExitCode::FAILURE;
+            }
+            eprintln!(
+                "{} {} entries satisfy the assertions",
+                "[OK]".green(),
+                count
+            );
+        }
+        SubCommand::Sync(cargs) => {
+            use bibadac::sync::{linked_entries, list_dir, plan, rename_files};
+
+            let source = match std::fs::read_to_string(&cargs.bib) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("{} {}: {}", "[ERR]".red(), cargs.bib.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let dir = &cargs.config.dir;
+            let files = match list_dir(dir) {
+                Ok(files) => files,
+                Err(e) => {
+                    eprintln!("{} {}: {}", "[ERR]".red(), dir.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let entries = Bibliography::from(source.clone()).with_bibfile(linked_entries);
+            let report = plan(&entries, &files);
+
+            for entry in report.missing.iter() {
+                let filename = entry.filename.as_ref().map_or("", |(f, _)| f.as_str());
+                println!("{} {}\t{}", "[MISSING]".red(), entry.key, filename);
+            }
+            for (entry, file) in report.changed.iter() {
+                println!(
+                    "{} {}\t{} does not match its sha256",
+                    "[CHANGED]".red(),
+                    entry.key,
+                    file.name
+                );
+            }
+            for (entry, file) in report.renamed.iter() {
+                let filename = entry.filename.as_ref().map_or("", |(f, _)| f.as_str());
+                println!(
+                    "{} {}\t{} -> {}",
+                    "[RENAMED]".yellow(),
+                    entry.key,
+                    filename,
+                    file.name
+                );
+            }
+            for file in report.unreferenced.iter() {
+                println!("{} {}", "[UNREFERENCED]".yellow(), file.name);
+            }
+            if report.is_empty() {
+                println!(
+                    "{} {:?} and {:?} are in sync",
+                    "[OK]".green(),
+                    cargs.bib,
+                    dir
+                );
+            }
+
+            if cargs.config.write {
+                let mut content = rename_files(&source, &report);
+                for file in report.unreferenced.iter() {
+                    let path = dir.join(&file.name);
+                    match bibadac::pdf_identifiers::scan_pdf(&path) {
+                        Ok(scan) => {
+                            if !content.is_empty() && !content.ends_with('\n') {
+                                content.push('\n');
+                            }
+                            content.push_str(&format!("\n{}\n", scan.mapping_entry()));
+                        }
+                        Err(e) => {
+                            eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                            return ExitCode::FAILURE;
                         }
                     }
                 }
-                if !cargs.config.no_progress {
-                    for (key, res) in response.entries.iter() {
-                        if res.is_none() {
-                            println!("[ERR] Could not find entry for {}", key);
+                if let Err(e) = std::fs::write(&cargs.bib, content) {
+                    eprintln!("{} {}: {}", "[ERR]".red(), cargs.bib.display(), e);
+                    return ExitCode::FAILURE;
+                }
+                println!(
+                    "Renamed {} files and added {} entries to {:?}",
+                    report.renamed.len(),
+                    report.unreferenced.len(),
+                    cargs.bib
+                );
+            } else if !report.renamed.is_empty() || !report.unreferenced.is_empty() {
+                println!("Use --write to update the renamed files and add the unreferenced ones");
+            }
+
+            // the pdfs of these entries must be found by hand
+            if !report.missing.is_empty() || !report.changed.is_empty() {
+                return ExitCode::FAILURE;
+            }
+        }
+        SubCommand::Cache(cargs) => {
+            use bibadac::cache::{format_timestamp, parse_age, Cache};
+
+            let Some(dir) = Cache::default_dir() else {
+                eprintln!(
+                    "{} Could not find the cache directory, set {}",
+                    "[ERR]".red(),
+                    bibadac::cache::CACHE_DIR_ENV
+                );
+                return ExitCode::FAILURE;
+            };
+            if !dir.exists() {
+                println!("The cache {} is empty", dir.display());
+                return ExitCode::SUCCESS;
+            }
+            let cache = match Cache::existing(&dir) {
+                Ok(cache) => cache,
+                Err(e) => {
+                    eprintln!("{} {}", "[ERR]".red(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            match cargs.command {
+                CacheCommand::Stats => match cache.stats() {
+                    Ok(stats) => {
+                        println!("{:<10}{}", "Directory", cache.dir().display());
+                        println!("{:<10}{}", "Entries", stats.entries);
+                        println!("{:<10}{} bytes", "Size", stats.size);
+                        if let (Some(oldest), Some(newest)) = (stats.oldest, stats.newest) {
+                            println!("{:<10}{}", "Oldest", format_timestamp(oldest));
+                            println!("{:<10}{}", "Newest", format_timestamp(newest));
                         }
                     }
-                    for (key, res) in response.pdfs.iter() {
-                        if res.is_none() {
-                            println!("{} Could not find pdf for {}", "[ERR]".red(), key.yellow());
+                    Err(e) => {
+                        eprintln!("{} {}", "[ERR]".red(), e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                CacheCommand::Clear { older_than } => {
+                    let older_than = match older_than.as_deref().map(parse_age).transpose() {
+                        Ok(age) => age,
+                        Err(e) => {
+                            eprintln!("{} {}", "[ERR]".red(), e);
+                            return ExitCode::FAILURE;
+                        }
+                    };
+                    match cache.clear(older_than) {
+                        Ok(count) => println!("Removed {} entries", count),
+                        Err(e) => {
+                            eprintln!("{} {}", "[ERR]".red(), e);
+                            return ExitCode::FAILURE;
                         }
                     }
                 }
-            });
+                CacheCommand::Get { key } => match cache.get(&key) {
+                    Some(entry) => println!("{}", entry.payload),
+                    None => {
+                        eprintln!("{} No cached entry for {}", "[ERR]".red(), key.yellow());
+                        return ExitCode::FAILURE;
+                    }
+                },
+            }
+        }
+        SubCommand::Trend(cargs) => {
+            use bibadac::trend::Trend;
+
+            let entries = match std::fs::read_dir(&cargs.dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("{} {}: {}", "[ERR]".red(), cargs.dir.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut reports = vec![];
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                // the reports of older versions are dated by their file
+                let mtime = std::fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs());
+                let report = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| JsonReport::parse(&s).map_err(|e| e.to_string()));
+                match report {
+                    Ok(report) => {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        reports.push((name.to_string(), mtime, report));
+                    }
+                    Err(e) => eprintln!("{} {}: {}", "[WARN]".yellow(), path.display(), e),
+                }
+            }
+            if reports.is_empty() {
+                eprintln!(
+                    "{} no JSON report in {}",
+                    "[ERR]".red(),
+                    cargs.dir.display()
+                );
+                return ExitCode::FAILURE;
+            }
+            let trend = Trend::new(reports);
+            if cargs.config.csv {
+                print!("{}", trend.csv());
+            } else {
+                print!("{}", trend.table());
+            }
+        }
+        SubCommand::Doctor(cargs) => {
+            use bibadac::cache::Cache;
+            use bibadac::doctor::{Status, check_cache, check_grammar, check_inputs};
+            use bibadac::doctor::{NETWORK_URL, check_network, check_working_directory, worst};
+
+            let config = &cargs.config;
+            let enabled = |check: &str| !config.skip.iter().any(|s| s == check);
+            let mut findings = vec![];
+
+            if enabled("working-directory") {
+                let dir = match &config.working_directory {
+                    Some(dir) => dir.clone(),
+                    None => std::env::current_dir().expect("Could not get the current directory"),
+                };
+                findings.push(check_working_directory(&dir));
+            }
+            if enabled("network") && !bibadac::network::is_offline() {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_io()
+                    .enable_time()
+                    .build()
+                    .expect("Unable to create the asynchronous runtime");
+                findings.push(rt.block_on(check_network(NETWORK_URL)));
+            }
+            if enabled("cache") {
+                findings.push(check_cache(Cache::default_dir().as_deref()));
+            }
+            if enabled("inputs") {
+                let mut inputs = vec![];
+                for path in cargs.bib.iter() {
+                    match std::fs::read(path) {
+                        Ok(bytes) => inputs.push((path.display().to_string(), bytes)),
+                        Err(e) => {
+                            eprintln!("{} {}: {}", "[ERR]".red(), path.display(), e);
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+                findings.extend(check_inputs(&inputs, config.max_file_size));
+            }
+            if enabled("grammar") {
+                findings.push(check_grammar());
+            }
+
+            for finding in findings.iter() {
+                let status = match finding.status {
+                    Status::Ok => "[OK]".green(),
+                    Status::Warn => "[WARN]".yellow(),
+                    Status::Fail => "[FAIL]".red(),
+                };
+                println!("{} {}: {}", status, finding.check.bold(), finding.message);
+                if let Some(remedy) = finding.remedy {
+                    println!("       {}", remedy);
+                }
+            }
+            return ExitCode::from(worst(&findings).exit_code());
         }
+        SubCommand::Schema => {
+            println!("{}", JsonReport::json_schema());
+        }
+        SubCommand::Debug(cargs) => match cargs.command {
+            DebugCommand::Parse { file, entry, sexp } => {
+                let bib = match Bibliography::from_path(&file) {
+                    Ok(bib) => bib,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "[ERR]".red(), file.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                let tree = bib.with_bibfile(|bibtex| {
+                    let node = match &entry {
+                        Some(key) => bibtex.find_entry(key)?.loc,
+                        None => bibtex.tree.root_node(),
+                    };
+                    Some(if sexp {
+                        node.to_sexp()
+                    } else {
+                        bibtex.debug_tree(node)
+                    })
+                });
+                match tree {
+                    Some(tree) => println!("{}", tree.trim_end()),
+                    None => {
+                        eprintln!(
+                            "{} No entry {:?} in {}",
+                            "[ERR]".red(),
+                            entry.unwrap_or_default(),
+                            file.display()
+                        );
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+        },
     };
     return ExitCode::SUCCESS;
 }
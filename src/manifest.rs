@@ -0,0 +1,232 @@
+//! This file is responsible for the manifest of `bibadac setup`, the
+//! `.bibadac-setup.json` file of the working directory that records the
+//! state of every identifier of a run, as the results arrive:
+//!
+//! ```json
+//! {"items": {"doi:10.1000/x": {"state": "pdf-done", "entry": "@article{...}",
+//!   "path": "/papers/doi_10_1000_x.pdf", "sha256": "...", "mapping": "@mapping{...}"}}}
+//! ```
+//!
+//! With `setup --resume`, a run skips the identifiers that are done,
+//! reusing their entries and pdfs, and retries the failed ones. The
+//! manifest is rewritten (temporary file + rename) after every update,
+//! so that a run that dies leaves the manifest of its last result.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest, in the working directory.
+pub const MANIFEST: &str = ".bibadac-setup.json";
+
+/// Where an identifier is in the pipeline of `setup`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum ItemState {
+    Pending,
+    /// The BibTeX entry was fetched
+    MetadataDone {
+        entry: String,
+    },
+    /// The pdf was written at `path`, with its `@mapping` entry
+    PdfDone {
+        entry: Option<String>,
+        path: PathBuf,
+        sha256: String,
+        mapping: String,
+    },
+    /// The entry (if fetched before failing) is kept for the next run
+    Failed {
+        reason: String,
+        entry: Option<String>,
+        /// The number of runs that failed on the identifier
+        #[serde(default)]
+        attempts: usize,
+    },
+}
+
+impl ItemState {
+    /// The BibTeX entry of the identifier, once fetched.
+    pub fn entry(&self) -> Option<&str> {
+        match self {
+            ItemState::Pending => None,
+            ItemState::MetadataDone { entry } => Some(entry),
+            ItemState::PdfDone { entry, .. } | ItemState::Failed { entry, .. } => entry.as_deref(),
+        }
+    }
+
+    /// The number of the next attempt at the identifier, which is
+    /// a retry after the failed runs.
+    pub fn attempt(&self) -> usize {
+        match self {
+            ItemState::Failed { attempts, .. } => attempts.max(&1) + 1,
+            _ => 1,
+        }
+    }
+
+    /// Whether there is nothing left to do for the identifier,
+    /// with or without its pdf.
+    pub fn is_done(&self, download_pdf: bool) -> bool {
+        match self {
+            ItemState::PdfDone { .. } => true,
+            ItemState::MetadataDone { .. } => !download_pdf,
+            ItemState::Pending | ItemState::Failed { .. } => false,
+        }
+    }
+}
+
+/// The states of the identifiers of a run, by request
+/// (`doi:10.1000/x`, `arxiv:2101.00001`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    items: BTreeMap<String, ItemState>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Manifest {
+    /// An empty manifest, in the working directory.
+    pub fn new(dir: &Path) -> Manifest {
+        Manifest {
+            items: BTreeMap::new(),
+            path: dir.join(MANIFEST),
+        }
+    }
+
+    /// The manifest of the working directory, or an empty one
+    /// when it is missing or malformed (everything is fetched again).
+    pub fn load(dir: &Path) -> Manifest {
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST))
+            .ok()
+            .and_then(|content| serde_json::from_str::<Manifest>(&content).ok());
+        Manifest {
+            path: dir.join(MANIFEST),
+            ..manifest.unwrap_or_default()
+        }
+    }
+
+    /// Keeps the states of the identifiers of this run, which are
+    /// pending when the manifest does not know them, and forgets the
+    /// others, so that a manifest of another run is not an error.
+    pub fn reconcile<'a>(&mut self, requests: impl IntoIterator<Item = &'a str>) {
+        let mut items = BTreeMap::new();
+        for request in requests {
+            let state = self.items.remove(request).unwrap_or(ItemState::Pending);
+            items.insert(request.to_string(), state);
+        }
+        self.items = items;
+    }
+
+    pub fn get(&self, request: &str) -> Option<&ItemState> {
+        self.items.get(request)
+    }
+
+    /// The number of identifiers with nothing left to do.
+    pub fn done(&self, download_pdf: bool) -> usize {
+        self.items
+            .values()
+            .filter(|state| state.is_done(download_pdf))
+            .count()
+    }
+
+    /// Updates the state of an identifier, and writes the manifest.
+    pub fn record(&mut self, request: &str, state: ItemState) -> io::Result<()> {
+        self.items.insert(request.to_string(), state);
+        self.save()
+    }
+
+    /// Writes the manifest atomically (temporary file + rename).
+    pub fn save(&self) -> io::Result<()> {
+        let tmp = self
+            .path
+            .with_extension(format!("tmp.{}", std::process::id()));
+        std::fs::write(
+            &tmp,
+            serde_json::to_string_pretty(self).map_err(io::Error::other)?,
+        )?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetched(request: &str) -> ItemState {
+        ItemState::MetadataDone {
+            entry: format!("@misc{{{},}}", request),
+        }
+    }
+
+    #[test]
+    fn test_item_state() {
+        let pdf = ItemState::PdfDone {
+            entry: None,
+            path: PathBuf::from("a.pdf"),
+            sha256: "ab".into(),
+            mapping: "@mapping{ab,}".into(),
+        };
+        let failed = ItemState::Failed {
+            reason: "no pdf found".into(),
+            entry: Some("@misc{a,}".into()),
+            attempts: 2,
+        };
+        assert!(pdf.is_done(true));
+        assert!(fetched("a").is_done(false));
+        assert!(!fetched("a").is_done(true));
+        assert!(!failed.is_done(false));
+        assert!(!ItemState::Pending.is_done(false));
+        assert_eq!(failed.entry(), Some("@misc{a,}"));
+        assert_eq!(pdf.entry(), None);
+        assert_eq!(failed.attempt(), 3);
+        assert_eq!(pdf.attempt(), 1);
+    }
+
+    #[test]
+    fn test_interrupted_run() {
+        let dir = crate::temp_dir("manifest");
+        let requests = (0..10)
+            .map(|i| format!("doi:10.1000/{}", i))
+            .collect::<Vec<_>>();
+        let mut manifest = Manifest::new(&dir);
+        manifest.reconcile(requests.iter().map(String::as_str));
+        // the run dies after 4 items, the third one failed
+        for (i, request) in requests.iter().enumerate().take(4) {
+            let state = if i == 2 {
+                ItemState::Failed {
+                    reason: "no bibtex entry found".into(),
+                    entry: None,
+                    attempts: 1,
+                }
+            } else {
+                fetched(request)
+            };
+            manifest.record(request, state).unwrap();
+        }
+        drop(manifest);
+
+        let mut resumed = Manifest::load(&dir);
+        resumed.reconcile(requests.iter().map(String::as_str));
+        assert_eq!(resumed.done(false), 3);
+        assert_eq!(
+            resumed.get("doi:10.1000/1"),
+            Some(&fetched("doi:10.1000/1"))
+        );
+        assert!(!resumed.get("doi:10.1000/2").unwrap().is_done(false));
+        assert_eq!(resumed.get("doi:10.1000/9"), Some(&ItemState::Pending));
+        // no temporary file is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // another set of identifiers keeps what they have in common
+        let mut other = Manifest::load(&dir);
+        other.reconcile(["doi:10.1000/0", "doi:10.1000/new"]);
+        assert_eq!(other.done(false), 1);
+        assert_eq!(other.get("doi:10.1000/new"), Some(&ItemState::Pending));
+        assert_eq!(other.get("doi:10.1000/1"), None);
+
+        // a malformed manifest starts over
+        std::fs::write(dir.join(MANIFEST), "{").unwrap();
+        assert_eq!(Manifest::load(&dir).done(false), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! This file is responsible for reading BibTeX
+//! entries embedded in Markdown/Quarto documents,
+//! inside of fenced code blocks such as
+//!
+//! ````markdown
+//! ```{=bibtex}
+//! @article{key, ...}
+//! ```
+//! ````
+//!
+//! Instead of extracting the fenced blocks (and mapping
+//! the locations back to the document), the prose around
+//! the blocks is blanked out: the resulting text has the
+//! same bytes and lines as the document, so that every
+//! location found in it is a location in the document.
+use std::ops::Range;
+
+/// Info strings of the code blocks containing BibTeX.
+const BIBTEX_INFO: [&str; 5] = ["{=bibtex}", "{=biblatex}", "bibtex", "biblatex", "bib"];
+
+/// Byte ranges of the contents of the BibTeX
+/// code blocks, in the order of the document.
+///
+/// Fences follow CommonMark: at least three backticks or tildes,
+/// indented by at most three spaces, closed by a fence of the same
+/// character that is at least as long and has no info string.
+/// Code blocks nested in other code blocks are not BibTeX blocks,
+/// and an unclosed block runs to the end of the document.
+pub fn bibtex_fences(md: &str) -> Vec<Range<usize>> {
+    let mut fences = vec![];
+    // fence character, fence length, start of the content if it is BibTeX
+    let mut open: Option<(char, usize, Option<usize>)> = None;
+    let mut offset = 0;
+    for line in md.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let text = line.trim_end_matches(['\n', '\r']);
+        let unindented = text.trim_start_matches(' ');
+        if text.len() - unindented.len() > 3 {
+            continue;
+        }
+        let Some(c) = unindented.chars().next().filter(|c| *c == '`' || *c == '~') else {
+            continue;
+        };
+        let info = unindented.trim_start_matches(c);
+        let len = unindented.len() - info.len();
+        if len < 3 {
+            continue;
+        }
+        let info = info.trim();
+        match open {
+            None => {
+                let bibtex = info
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|word| BIBTEX_INFO.contains(&word.to_lowercase().as_str()));
+                open = Some((c, len, bibtex.then_some(offset)));
+            }
+            Some((oc, olen, content)) if c == oc && len >= olen && info.is_empty() => {
+                if let Some(content) = content {
+                    fences.push(content..start);
+                }
+                open = None;
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some((_, _, Some(content))) = open {
+        fences.push(content..md.len());
+    }
+    fences
+}
+
+/// Replaces everything outside of the fences with spaces,
+/// keeping line breaks and byte offsets untouched.
+pub fn mask_outside(md: &str, fences: &[Range<usize>]) -> String {
+    let mut out = String::with_capacity(md.len());
+    let mut fences = fences.iter().peekable();
+    for (i, c) in md.char_indices() {
+        while fences.peek().is_some_and(|r| r.end <= i) {
+            fences.next();
+        }
+        if fences.peek().is_some_and(|r| r.contains(&i)) || c == '\n' || c == '\r' {
+            out.push(c);
+        } else {
+            out.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    }
+    out
+}
+
+/// Rewrites the content of every fence, leaving
+/// the rest of the document untouched.
+pub fn replace_fences<F>(md: &str, fences: &[Range<usize>], mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> String,
+{
+    let mut out = String::with_capacity(md.len());
+    let mut last = 0;
+    for fence in fences {
+        out.push_str(&md[last..fence.start]);
+        out.push_str(&rewrite(&md[fence.clone()]));
+        last = fence.end;
+    }
+    out.push_str(&md[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(md: &str) -> Vec<&str> {
+        bibtex_fences(md).into_iter().map(|r| &md[r]).collect()
+    }
+
+    #[test]
+    fn test_fences() {
+        let md = "# Notes\n\n```{=bibtex}\n@misc{a,}\n```\n\ntext\n\n~~~ bibtex\n@misc{b,}\n~~~\n";
+        assert_eq!(contents(md), vec!["@misc{a,}\n", "@misc{b,}\n"]);
+        // other languages are ignored
+        let md = "```rust\nfn main() {}\n```\n```bib\n@misc{a,}\n```";
+        assert_eq!(contents(md), vec!["@misc{a,}\n"]);
+        // unclosed fences run to the end of the document
+        let md = "```bibtex\n@misc{a,}\n";
+        assert_eq!(contents(md), vec!["@misc{a,}\n"]);
+        // indented code is not a fence
+        let md = "    ```bibtex\n    @misc{a,}\n    ```\n";
+        assert!(contents(md).is_empty());
+    }
+
+    #[test]
+    fn test_fences_crlf() {
+        let md = "text\r\n```{=bibtex}\r\n@misc{a,}\r\n```\r\nmore\r\n";
+        assert_eq!(contents(md), vec!["@misc{a,}\r\n"]);
+    }
+
+    #[test]
+    fn test_nested_fences() {
+        // a bibtex block shown as an example inside of a markdown block
+        let md = "````markdown\n```bibtex\n@misc{a,}\n```\n````\n```bibtex\n@misc{b,}\n```\n";
+        assert_eq!(contents(md), vec!["@misc{b,}\n"]);
+        // a shorter fence does not close a longer one
+        let md = "````bibtex\n@misc{a,}\n```\n@misc{b,}\n````\n";
+        assert_eq!(contents(md), vec!["@misc{a,}\n```\n@misc{b,}\n"]);
+        // a fence with an info string does not close a block
+        let md = "```bibtex\n@misc{a,}\n```rust\n```\n";
+        assert_eq!(contents(md), vec!["@misc{a,}\n```rust\n"]);
+    }
+
+    #[test]
+    fn test_mask_keeps_locations() {
+        let md = "# Héllo\r\n\r\n```{=bibtex}\r\n@misc{a,\r\n  title = {Ü},\r\n}\r\n```\r\nbye @misc{b,}\r\n";
+        let masked = mask_outside(md, &bibtex_fences(md));
+        assert_eq!(masked.len(), md.len());
+        assert_eq!(masked.lines().count(), md.lines().count());
+        assert_eq!(masked.find("@misc{a,"), md.find("@misc{a,"));
+        assert_eq!(masked.find("{Ü}"), md.find("{Ü}"));
+        // the prose is gone, including entries outside of the fences
+        assert!(!masked.contains("Héllo"));
+        assert!(!masked.contains("@misc{b,"));
+    }
+
+    #[test]
+    fn test_replace_fences() {
+        let md = "a\n```bibtex\nx\n```\nb\n```bibtex\ny\n```\n";
+        let out = replace_fences(md, &bibtex_fences(md), |s| s.to_uppercase());
+        assert_eq!(out, "a\n```bibtex\nX\n```\nb\n```bibtex\nY\n```\n");
+    }
+}
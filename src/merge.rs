@@ -0,0 +1,551 @@
+/// This file is responsible for merging
+/// several bibliographies together, and
+/// resolving the conflicts that arise when
+/// two entries share the same key.
+///
+/// Entries that are exactly the same (up to field order
+/// and surrounding whitespace) are collapsed silently.
+/// Otherwise, a `Responder` is asked what to do: keep the
+/// first entry, keep the second one, merge them field-by-field,
+/// or skip the conflict (keeping both entries).
+///
+/// The `Responder` trait is the only place where the
+/// user is involved, so that the resolution logic can be
+/// tested with a scripted responder, and so that non-interactive
+/// invocations never wait for an input.
+use colored::Colorize;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::bibtex::{BibEntry, BibFile};
+use crate::preprints::{pair_preprints, paper_key, Version};
+use crate::provenance::{field_annotations, Provenance, ANNOTATION_PREFIX};
+use crate::related::{rename_related, RELATED_FIELD, RELATED_TYPE_FIELD};
+use crate::values::{normalize_value, same_value};
+
+/// An entry that does not depend on the
+/// underlying file, so that entries from several
+/// files can be compared and merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedEntry {
+    pub key: String,
+    pub entrytype: String,
+    pub fields: Vec<(String, String)>,
+    /// The `% bibadac:` comments written after the fields, by field
+    /// name (see the `provenance` module).
+    pub annotations: Vec<(String, String)>,
+}
+
+impl OwnedEntry {
+    pub fn from_entry(bib: &BibFile, entry: &BibEntry) -> Self {
+        OwnedEntry {
+            key: bib.get_slice(entry.key).to_string(),
+            entrytype: bib.get_slice(entry.entrytype).to_string(),
+            fields: entry
+                .fields
+                .iter()
+                .map(|f| {
+                    (
+                        bib.get_slice(f.name).to_string(),
+                        bib.get_slice(f.value).to_string(),
+                    )
+                })
+                .collect(),
+            annotations: entry
+                .fields
+                .iter()
+                .zip(field_annotations(bib, entry))
+                .flat_map(|(f, annotations)| {
+                    let name = bib.get_slice(f.name);
+                    annotations
+                        .into_iter()
+                        .map(move |a| (name.to_string(), a.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Same entry type, and same set of fields (field names are case
+    /// insensitive, and values are compared with `values::normalize_value`).
+    pub fn same_content(&self, other: &OwnedEntry) -> bool {
+        let normalize = |e: &OwnedEntry| {
+            let mut fields = e
+                .fields
+                .iter()
+                .map(|(n, v)| (n.to_lowercase(), normalize_value(n, v)))
+                .collect::<Vec<_>>();
+            fields.sort();
+            fields
+        };
+        self.entrytype.eq_ignore_ascii_case(&other.entrytype) && normalize(self) == normalize(other)
+    }
+}
+
+impl Display for OwnedEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{{{},", self.entrytype, self.key)?;
+        for (name, value) in self.fields.iter() {
+            writeln!(f, "  {} = {},", name, value)?;
+            for (_, annotation) in self
+                .annotations
+                .iter()
+                .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            {
+                writeln!(f, "  % {} {}", ANNOTATION_PREFIX, annotation)?;
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    KeepFirst,
+    KeepSecond,
+    Merge,
+    Skip,
+}
+
+pub trait Responder {
+    /// Displays the question and returns the answer,
+    /// or `None` if no answer can be obtained
+    /// (end of input, not a terminal, etc.).
+    fn ask(&mut self, question: &str) -> Option<String>;
+}
+
+/// The non-interactive strategy: never answers,
+/// so that the first entry is always kept.
+pub struct NonInteractive;
+
+impl Responder for NonInteractive {
+    fn ask(&mut self, _: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Asks the questions on stderr and reads
+/// the answers from stdin, one per line.
+pub struct TerminalResponder;
+
+impl Responder for TerminalResponder {
+    fn ask(&mut self, question: &str) -> Option<String> {
+        use std::io::{BufRead, Write};
+        eprint!("{}", question);
+        std::io::stderr().flush().ok()?;
+        let mut answer = String::new();
+        let read = std::io::stdin().lock().read_line(&mut answer).ok()?;
+        if read == 0 { None } else { Some(answer) }
+    }
+}
+
+fn truncate(value: &str, width: usize) -> String {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    if value.chars().count() <= width {
+        value
+    } else {
+        value.chars().take(width - 1).collect::<String>() + "…"
+    }
+}
+
+/// Shows two entries side by side,
+/// highlighting the fields that differ.
+pub fn render_conflict(first: &OwnedEntry, second: &OwnedEntry) -> String {
+    const COLUMN: usize = 38;
+    let mut names: Vec<&str> = first.fields.iter().map(|(n, _)| n.as_str()).collect();
+    for (name, _) in second.fields.iter() {
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(name);
+        }
+    }
+    let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+
+    let mut rows = vec![("type", first.entrytype.as_str(), second.entrytype.as_str())];
+    rows.extend(names.iter().map(|name| {
+        (
+            *name,
+            first.get(name).unwrap_or(""),
+            second.get(name).unwrap_or(""),
+        )
+    }));
+
+    let mut out = format!("Conflicting entries for key {}\n", first.key.yellow());
+    for (name, a, b) in rows {
+        let left = format!("{:<COLUMN$}", truncate(a, COLUMN));
+        let right = truncate(b, COLUMN);
+        if a.trim().eq_ignore_ascii_case(b.trim()) {
+            out += &format!("{:>width$} : {} | {}\n", name, left, right);
+        } else {
+            out += &format!(
+                "{:>width$} : {} | {}\n",
+                name,
+                left.as_str().red(),
+                right.as_str().green()
+            );
+        }
+    }
+    out
+}
+
+/// Asks how to resolve a conflict until a valid answer
+/// is given, falling back to keeping the first entry.
+pub fn resolve_conflict<R: Responder + ?Sized>(
+    first: &OwnedEntry,
+    second: &OwnedEntry,
+    responder: &mut R,
+) -> Resolution {
+    let question = format!(
+        "{}[1] keep first, [2] keep second, [m] merge field-by-field, [s] skip: ",
+        render_conflict(first, second)
+    );
+    loop {
+        let Some(answer) = responder.ask(&question) else {
+            return Resolution::KeepFirst;
+        };
+        match answer.trim() {
+            "1" => return Resolution::KeepFirst,
+            "2" => return Resolution::KeepSecond,
+            "m" | "M" => return Resolution::Merge,
+            "s" | "S" => return Resolution::Skip,
+            _ => continue,
+        }
+    }
+}
+
+/// Merges the fields of `second` into `first`,
+/// asking which value to keep whenever both entries
+/// define the same field differently.
+pub fn merge_fields<R: Responder + ?Sized>(
+    first: &OwnedEntry,
+    second: &OwnedEntry,
+    responder: &mut R,
+) -> OwnedEntry {
+    let mut merged = first.clone();
+    for (name, value) in second.fields.iter() {
+        match merged
+            .fields
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            None => merged.fields.push((name.clone(), value.clone())),
+            Some((_, current)) if same_value(name, current, value) => {}
+            Some((_, current)) => {
+                let question = format!(
+                    "{}\n[1] {}\n[2] {}\n: ",
+                    name.yellow(),
+                    truncate(current, 70),
+                    truncate(value, 70)
+                );
+                loop {
+                    match responder.ask(&question).as_deref().map(str::trim) {
+                        None | Some("1") => break,
+                        Some("2") => {
+                            *current = value.clone();
+                            break;
+                        }
+                        _ => continue,
+                    }
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// Merges a list of entries, keeping the order of first
+/// occurrence and resolving conflicting keys with the responder.
+pub fn merge_entries<R: Responder + ?Sized>(
+    entries: Vec<OwnedEntry>,
+    responder: &mut R,
+) -> Vec<OwnedEntry> {
+    let entries = entries.into_iter().map(|e| (e, 0)).collect();
+    merge_entries_traced(entries, responder)
+        .into_iter()
+        .map(|(e, _)| e)
+        .collect()
+}
+
+/// `merge_entries`, for the entries of several inputs (given with the
+/// index of their input), telling where the fields of every merged
+/// entry come from (see the `provenance` module).
+pub fn merge_entries_traced<R: Responder + ?Sized>(
+    entries: Vec<(OwnedEntry, usize)>,
+    responder: &mut R,
+) -> Vec<(OwnedEntry, Provenance)> {
+    let mut merged: Vec<(OwnedEntry, Provenance)> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (entry, origin) in entries {
+        let Some(&pos) = positions.get(&entry.key) else {
+            positions.insert(entry.key.clone(), merged.len());
+            merged.push((entry, Provenance::new(origin)));
+            continue;
+        };
+        let (first, provenance) = &mut merged[pos];
+        if first.same_content(&entry) {
+            continue;
+        }
+        match resolve_conflict(first, &entry, responder) {
+            Resolution::KeepFirst => {}
+            Resolution::KeepSecond => merged[pos] = (entry, Provenance::new(origin)),
+            Resolution::Merge => {
+                let fields = merge_fields(first, &entry, responder);
+                provenance.record(first, &fields, origin);
+                *first = fields;
+            }
+            Resolution::Skip => merged.push((entry, Provenance::new(origin))),
+        }
+    }
+    merged
+}
+
+/// Folds the eprint of every preprint into the published version of
+/// the same paper (see the `preprints` module) and removes the preprint.
+/// Returns the remaining entries, and the renamed keys (preprint -> published).
+pub fn collapse_preprints(entries: Vec<OwnedEntry>) -> (Vec<OwnedEntry>, HashMap<String, String>) {
+    let versions = entries
+        .iter()
+        .map(|e| {
+            let key = paper_key(e.get("title")?, e.get("author")?)?;
+            Some((key, Version::of(e.get("eprint").is_some(), e.get("doi").is_some())?))
+        })
+        .collect::<Vec<_>>();
+    let pairs = pair_preprints(&versions);
+
+    let mut entries = entries.into_iter().map(Some).collect::<Vec<_>>();
+    let mut renames = HashMap::new();
+    for (preprint, published) in pairs {
+        let Some(preprint) = entries[preprint].take() else {
+            continue;
+        };
+        let Some(published) = entries[published].as_mut() else {
+            continue;
+        };
+        for name in ["eprint", "archiveprefix", "eprinttype", "primaryclass"] {
+            if let Some(value) = preprint.get(name)
+                && published.get(name).is_none()
+            {
+                let field = preprint
+                    .fields
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map_or(name, |(n, _)| n.as_str());
+                published.fields.push((field.to_string(), value.to_string()));
+            }
+        }
+        renames.insert(preprint.key, published.key.clone());
+    }
+    let mut entries = entries.into_iter().flatten().collect::<Vec<_>>();
+    rename_related_keys(&mut entries, &renames);
+    (entries, renames)
+}
+
+/// Renames the keys of the `related` fields (see the `related` module),
+/// and removes the fields (with their `relatedtype`) that relate an
+/// entry to nothing but itself.
+pub fn rename_related_keys(entries: &mut [OwnedEntry], renames: &HashMap<String, String>) {
+    for entry in entries.iter_mut() {
+        let Some(pos) = entry
+            .fields
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(RELATED_FIELD))
+        else {
+            continue;
+        };
+        match rename_related(&entry.fields[pos].1, &entry.key, renames) {
+            Some(value) => entry.fields[pos].1 = value,
+            None => entry.fields.retain(|(n, _)| {
+                !n.eq_ignore_ascii_case(RELATED_FIELD)
+                    && !n.eq_ignore_ascii_case(RELATED_TYPE_FIELD)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct ScriptedResponder {
+        answers: VecDeque<&'static str>,
+    }
+
+    impl Responder for ScriptedResponder {
+        fn ask(&mut self, _: &str) -> Option<String> {
+            self.answers.pop_front().map(|a| a.to_string())
+        }
+    }
+
+    fn scripted(answers: &[&'static str]) -> ScriptedResponder {
+        ScriptedResponder {
+            answers: answers.iter().cloned().collect(),
+        }
+    }
+
+    fn entry(key: &str, fields: &[(&str, &str)]) -> OwnedEntry {
+        OwnedEntry {
+            key: key.to_string(),
+            entrytype: "@article".to_string(),
+            fields: fields
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            annotations: vec![],
+        }
+    }
+
+    #[test]
+    fn test_identical_entries_are_collapsed() {
+        let a = entry("k", &[("title", "{A}"), ("year", "2020")]);
+        let b = entry("k", &[("YEAR", "2020"), ("title", "{A} ")]);
+        let merged = merge_entries(vec![a.clone(), b], &mut scripted(&[]));
+        assert_eq!(merged, vec![a]);
+    }
+
+    #[test]
+    fn test_same_content_up_to_braces_and_case() {
+        let a = entry("k", &[("title", "{Model Checking}"), ("doi", "{10.1/ABC}")]);
+        let b = entry("k", &[("TITLE", "{{model checking}}"), ("doi", "10.1/abc")]);
+        assert!(a.same_content(&b));
+        let merged = merge_entries(vec![a.clone(), b], &mut scripted(&[]));
+        assert_eq!(merged, vec![a.clone()]);
+        let c = entry("k", &[("title", "{Model-Checking}"), ("doi", "{10.1/ABC}")]);
+        assert!(!a.same_content(&c));
+    }
+
+    #[test]
+    fn test_same_content_up_to_months_and_concatenations() {
+        let a = entry("k", &[("title", "\"Part \" # \"One\""), ("month", "jan")]);
+        let b = entry("k", &[("title", "{Part One}"), ("month", "{January}")]);
+        let c = entry("k", &[("title", "{Part One}"), ("month", "{1}")]);
+        assert!(a.same_content(&b) && b.same_content(&c));
+        let merged = merge_entries(vec![a.clone(), b, c], &mut scripted(&[]));
+        assert_eq!(merged, vec![a.clone()]);
+        let d = entry("k", &[("title", "{Part One}"), ("month", "feb")]);
+        assert!(!a.same_content(&d));
+    }
+
+    #[test]
+    fn test_keep_and_skip() {
+        let a = entry("k", &[("year", "2020")]);
+        let b = entry("k", &[("year", "2021")]);
+        let merged = merge_entries(vec![a.clone(), b.clone()], &mut scripted(&["2"]));
+        assert_eq!(merged, vec![b.clone()]);
+        let merged = merge_entries(vec![a.clone(), b.clone()], &mut scripted(&["x", "s"]));
+        assert_eq!(merged, vec![a.clone(), b.clone()]);
+        // no answer: falls back to keeping the first entry
+        let merged = merge_entries(vec![a.clone(), b], &mut NonInteractive);
+        assert_eq!(merged, vec![a]);
+    }
+
+    #[test]
+    fn test_collapse_preprints() {
+        let preprint = OwnedEntry {
+            key: "doe2020arxiv".to_string(),
+            entrytype: "@misc".to_string(),
+            fields: vec![
+                ("title".to_string(), "{On Things}".to_string()),
+                ("author".to_string(), "{Doe, Jane}".to_string()),
+                ("eprint".to_string(), "{2001.00001}".to_string()),
+                ("archivePrefix".to_string(), "{arXiv}".to_string()),
+            ],
+            annotations: vec![],
+        };
+        let published = OwnedEntry {
+            key: "doe2021things".to_string(),
+            entrytype: "@inproceedings".to_string(),
+            fields: vec![
+                ("title".to_string(), "{On {T}hings}".to_string()),
+                ("author".to_string(), "{Jane Doe}".to_string()),
+                ("doi".to_string(), "{10.1/x}".to_string()),
+            ],
+            annotations: vec![],
+        };
+        let other = entry("other", &[("title", "{On Things}"), ("author", "{Roe, R.}")]);
+        let (entries, renames) =
+            collapse_preprints(vec![preprint, other.clone(), published.clone()]);
+        let mut folded = published;
+        folded
+            .fields
+            .push(("eprint".to_string(), "{2001.00001}".to_string()));
+        folded
+            .fields
+            .push(("archivePrefix".to_string(), "{arXiv}".to_string()));
+        assert_eq!(entries, vec![other, folded]);
+        assert_eq!(
+            renames,
+            HashMap::from([("doe2020arxiv".to_string(), "doe2021things".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_collapse_preprints_related() {
+        let preprint = entry(
+            "doe2020arxiv",
+            &[
+                ("title", "{On Things}"),
+                ("author", "{Doe, Jane}"),
+                ("eprint", "{2001.00001}"),
+                ("related", "{doe2021things}"),
+                ("relatedtype", "{publishedas}"),
+            ],
+        );
+        let published = entry(
+            "doe2021things",
+            &[
+                ("title", "{On Things}"),
+                ("author", "{Doe, Jane}"),
+                ("doi", "{10.1/x}"),
+                ("related", "{doe2020arxiv}"),
+                ("relatedtype", "{origpubas}"),
+            ],
+        );
+        let survey = entry("survey", &[("related", "{doe2020arxiv, other}")]);
+        let (entries, _) = collapse_preprints(vec![preprint, published, survey]);
+        assert_eq!(entries[0].get("related"), None);
+        assert_eq!(entries[0].get("relatedtype"), None);
+        assert_eq!(entries[1].get("related"), Some("{doe2021things,other}"));
+    }
+
+    #[test]
+    fn test_merge_field_by_field() {
+        let a = entry("k", &[("title", "{A}"), ("year", "2020")]);
+        let b = entry(
+            "k",
+            &[("title", "{B}"), ("year", "2021"), ("doi", "{10.1/x}")],
+        );
+        let merged = merge_entries(vec![a, b], &mut scripted(&["m", "2", "1"]));
+        assert_eq!(
+            merged,
+            vec![entry(
+                "k",
+                &[("title", "{B}"), ("year", "2020"), ("doi", "{10.1/x}")]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_merge_provenance() {
+        let a = entry("k", &[("title", "{A}"), ("year", "2020")]);
+        let b = entry("k", &[("title", "{B}"), ("year", "2021")]);
+        let c = entry("k", &[("title", "{A}"), ("doi", "{10.1/x}")]);
+        let merged = merge_entries_traced(
+            vec![(a, 0), (b, 1), (c, 2)],
+            &mut scripted(&["m", "2", "1", "m", "1"]),
+        );
+        assert_eq!(merged.len(), 1);
+        let (entry, provenance) = &merged[0];
+        assert_eq!(entry.get("title"), Some("{B}"));
+        assert_eq!(provenance.origin, 0);
+        assert_eq!(
+            provenance.fields,
+            vec![("title".to_string(), 1), ("doi".to_string(), 2)]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! This file is responsible for the `@comment` blocks where other
+//! reference managers store their metadata, and that they read back:
+//! JabRef writes `@comment{jabref-meta: ...}` (its groups, its settings),
+//! and BibDesk appends `@comment{BibDesk Static Groups{...}}` blocks at
+//! the end of the file. The formatter writes them back byte-for-byte.
+//!
+//! The groups of JabRef are written as a tree, one group per line,
+//! preceded by its level (`1 StaticGroup:Reading\;0\;1\;;`). The fields
+//! of a group are separated by `\;`, and the group ends with `;`. The
+//! members of a static group are the entries whose `groups` field names
+//! it, except in the `groupstree` of JabRef 3, where an `ExplicitGroup`
+//! lists the keys of its members after its name and context.
+use std::ops::Range;
+
+/// The tools whose `@comment` blocks are kept as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataComment {
+    Jabref,
+    Bibdesk,
+}
+
+impl MetadataComment {
+    /// The tool that wrote a `@comment`, given the text starting at its `@`.
+    pub fn of(text: &str) -> Option<Self> {
+        let rest = text.strip_prefix('@')?;
+        if !rest.get(..7)?.eq_ignore_ascii_case("comment") {
+            return None;
+        }
+        let body = rest[7..].trim_start().strip_prefix('{')?.trim_start();
+        if body.starts_with("jabref-meta:") {
+            Some(MetadataComment::Jabref)
+        } else if body.starts_with("BibDesk ") {
+            Some(MetadataComment::Bibdesk)
+        } else {
+            None
+        }
+    }
+}
+
+/// The length of a `@comment{...}` up to its closing brace
+/// (the whole text when the braces are not balanced).
+pub fn comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 1 => return i + 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// A group of the groups tree of JabRef.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JabrefGroup {
+    /// The depth in the tree (0 for `AllEntriesGroup`)
+    pub level: usize,
+    /// `StaticGroup`, `ExplicitGroup`, `KeywordGroup`, ...
+    pub kind: String,
+    pub name: String,
+    /// The keys listed by an `ExplicitGroup`
+    pub keys: Vec<String>,
+}
+
+/// The fields of a group (separated by `\;`), without their escapes.
+fn group_fields(text: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(';')) => {
+                chars.next();
+                fields.push(String::new());
+            }
+            ('\\', Some(escaped)) => {
+                chars.next();
+                fields.last_mut().unwrap().push(escaped);
+            }
+            (c, _) => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// The groups of a `jabref-meta: groupstree:` (or `grouping:`)
+/// comment, given its text, or `None` for other metadata.
+pub fn parse_jabref_groups(comment: &str) -> Option<Vec<JabrefGroup>> {
+    let body = comment.split_once("jabref-meta:")?.1.trim_start();
+    let body = body
+        .strip_prefix("groupstree:")
+        .or_else(|| body.strip_prefix("grouping:"))?;
+    let body = &body[..body.rfind('}').unwrap_or(body.len())];
+
+    // the groups end with a `;` that is not escaped
+    let mut groups = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ';' => {
+                groups.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let groups = groups
+        .into_iter()
+        .filter_map(|group| {
+            let (level, group) = group.trim().split_once(' ')?;
+            let (kind, rest) = group.split_once(':')?;
+            let mut fields = group_fields(rest).into_iter();
+            let name = fields.next().unwrap_or_default();
+            let keys = match kind {
+                // the context, then the keys
+                "ExplicitGroup" => fields.skip(1).filter(|k| !k.is_empty()).collect(),
+                _ => vec![],
+            };
+            Some(JabrefGroup {
+                level: level.parse().ok()?,
+                kind: kind.to_string(),
+                name,
+                keys,
+            })
+        })
+        .collect();
+    Some(groups)
+}
+
+/// The names of the groups listed in the `groups` field of an entry.
+pub fn entry_groups(value: &str) -> Vec<&str> {
+    value
+        .trim_matches(|c| c == '{' || c == '}' || c == '"')
+        .split(',')
+        .map(str::trim)
+        .filter(|g| !g.is_empty())
+        .collect()
+}
+
+/// The byte ranges of the metadata comments of a file, given the
+/// starts of its top-level items, as the comments may span several.
+pub fn metadata_spans(
+    content: &str,
+    starts: impl IntoIterator<Item = usize>,
+) -> Vec<(MetadataComment, Range<usize>)> {
+    let mut spans: Vec<(MetadataComment, Range<usize>)> = vec![];
+    for start in starts {
+        if spans.last().is_some_and(|(_, span)| start < span.end) {
+            continue;
+        }
+        if let Some(dialect) = MetadataComment::of(&content[start..]) {
+            spans.push((dialect, start..start + comment_len(&content[start..])));
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_comment() {
+        assert_eq!(
+            MetadataComment::of("@Comment{jabref-meta: databaseType:bibtex;}"),
+            Some(MetadataComment::Jabref)
+        );
+        assert_eq!(
+            MetadataComment::of("@comment { BibDesk Static Groups{\n}}"),
+            Some(MetadataComment::Bibdesk)
+        );
+        assert_eq!(MetadataComment::of("@comment{a note}"), None);
+        assert_eq!(MetadataComment::of("@misc{jabref-meta:}"), None);
+        assert_eq!(MetadataComment::of("@com"), None);
+    }
+
+    #[test]
+    fn test_metadata_spans() {
+        let content =
+            "@misc{a}\n@comment{BibDesk Static Groups{\n<plist>{x}</plist>\n}}\n@comment{note}";
+        let starts = content.match_indices('@').map(|(i, _)| i);
+        let spans = metadata_spans(content, starts);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(
+            &content[spans[0].1.clone()],
+            "@comment{BibDesk Static Groups{\n<plist>{x}</plist>\n}}"
+        );
+        assert_eq!(comment_len("@comment{open"), 13);
+    }
+
+    #[test]
+    fn test_parse_grouping() {
+        let comment = "@Comment{jabref-meta: grouping:\n\
+                       0 AllEntriesGroup:;\n\
+                       1 StaticGroup:Reading\\;0\\;1\\;0x8a8a8aff\\;\\;\\;;\n\
+                       2 StaticGroup:To\\\\do\\;0\\;1\\;\\;\\;\\;;\n\
+                       1 KeywordGroup:Logic\\;0\\;keywords\\;logic\\;0\\;0\\;1\\;\\;\\;\\;;\n\
+                       }";
+        let groups = parse_jabref_groups(comment).unwrap();
+        let names = groups
+            .iter()
+            .map(|g| (g.level, g.kind.as_str(), g.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                (0, "AllEntriesGroup", ""),
+                (1, "StaticGroup", "Reading"),
+                (2, "StaticGroup", "To\\do"),
+                (1, "KeywordGroup", "Logic"),
+            ]
+        );
+        assert!(groups.iter().all(|g| g.keys.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_groupstree() {
+        let comment = "@comment{jabref-meta: groupstree:\n\
+                       0 AllEntriesGroup:;\n\
+                       1 ExplicitGroup:Reading\\;0\\;knuth1984\\;esik2020\\;;\n\
+                       1 ExplicitGroup:Empty\\;0\\;;\n\
+                       }";
+        let groups = parse_jabref_groups(comment).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[1].name, "Reading");
+        assert_eq!(groups[1].keys, vec!["knuth1984", "esik2020"]);
+        assert!(groups[2].keys.is_empty());
+
+        assert_eq!(
+            parse_jabref_groups("@comment{jabref-meta: databaseType:bibtex;}"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_entry_groups() {
+        assert_eq!(
+            entry_groups("{Reading, To read}"),
+            vec!["Reading", "To read"]
+        );
+        assert_eq!(entry_groups("{}"), Vec::<&str>::new());
+    }
+}
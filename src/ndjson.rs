@@ -0,0 +1,313 @@
+//! This file is responsible for the NDJSON form of the entries of
+//! `bibadac export` and `bibadac import`, one JSON object per line,
+//! for pipelines such as `bibadac export refs.bib | jq ... | bibadac
+//! import`:
+//!
+//! ```json
+//! {"key":"doe2020","type":"article","fields":[{"name":"title","value":"A {B}","delimiter":"braces"}],"span":{"start":0,"end":42,"line":1}}
+//! ```
+//!
+//! The values are kept as written, without their delimiters (braces,
+//! quotes, or none for numbers, macros, and concatenations), so that
+//! an entry is written back with the same fields, in the same order,
+//! and with the same delimiters. The `@string` definitions are objects
+//! of type `string`, with a single field. The comments and preambles
+//! are left out.
+use serde::{Deserialize, Serialize};
+
+use crate::bibtex::{
+    tokenize_value, unbalanced_brace, BibEntry, BibFile, BibString, ValueTokenKind,
+};
+
+/// How a value is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Delimiter {
+    /// `{...}`
+    Braces,
+    /// `"..."`
+    Quotes,
+    /// A number, a macro, or a concatenation (`jan # " 1"`)
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NdjsonField {
+    pub name: String,
+    /// The value as written, inside of its delimiters
+    pub value: String,
+    pub delimiter: Delimiter,
+}
+
+impl NdjsonField {
+    /// The field of a value as written in a file, with its delimiters.
+    pub fn new(name: &str, raw: &str) -> Self {
+        let (delimiter, value) = split_delimiters(raw);
+        NdjsonField {
+            name: name.to_string(),
+            value: value.to_string(),
+            delimiter,
+        }
+    }
+
+    /// The value with its delimiters.
+    pub fn raw(&self) -> String {
+        match self.delimiter {
+            Delimiter::Braces => format!("{{{}}}", self.value),
+            Delimiter::Quotes => format!("\"{}\"", self.value),
+            Delimiter::None => self.value.clone(),
+        }
+    }
+}
+
+/// Where the entry was in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// From 1
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NdjsonEntry {
+    pub key: String,
+    /// The entry type as written, without `@`
+    #[serde(rename = "type")]
+    pub entrytype: String,
+    pub fields: Vec<NdjsonField>,
+    /// Not needed by `import`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+}
+
+/// The delimiters of a value, and what is inside of them.
+fn split_delimiters(raw: &str) -> (Delimiter, &str) {
+    let tokens = tokenize_value(raw, 0);
+    let outer = tokens.iter().filter(|t| t.depth == 0).collect::<Vec<_>>();
+    let closed = |close: char| raw.len() >= 2 && raw.ends_with(close);
+    match outer[..] {
+        [token] if token.span == (0..raw.len()) => match token.kind {
+            ValueTokenKind::BraceGroup if closed('}') => {
+                (Delimiter::Braces, &raw[1..raw.len() - 1])
+            }
+            ValueTokenKind::QuotedGroup if closed('"') => {
+                (Delimiter::Quotes, &raw[1..raw.len() - 1])
+            }
+            _ => (Delimiter::None, raw),
+        },
+        _ => (Delimiter::None, raw),
+    }
+}
+
+/// The entries and `@string` definitions of the file, in their order.
+pub fn export(file: &BibFile) -> Vec<NdjsonEntry> {
+    let span = |node: tree_sitter::Node| Span {
+        start: node.start_byte(),
+        end: node.end_byte(),
+        line: node.start_position().row + 1,
+    };
+    let mut cursor = file.tree.root_node().walk();
+    let mut entries = vec![];
+    for node in file.tree.root_node().children(&mut cursor) {
+        if let Some(entry) = BibEntry::from_node(node) {
+            entries.push(NdjsonEntry {
+                key: file.get_slice(entry.key).trim().to_string(),
+                entrytype: file
+                    .get_slice(entry.entrytype)
+                    .trim_start_matches('@')
+                    .to_string(),
+                fields: entry
+                    .fields
+                    .iter()
+                    .map(|f| NdjsonField::new(file.get_slice(f.name), file.get_slice(f.value)))
+                    .collect(),
+                span: Some(span(node)),
+            });
+        } else if let Some(string) = BibString::from_node(node) {
+            let name = file.get_slice(string.name);
+            entries.push(NdjsonEntry {
+                key: name.to_string(),
+                entrytype: "string".to_string(),
+                fields: vec![NdjsonField::new(name, file.get_slice(string.value))],
+                span: Some(span(node)),
+            });
+        }
+    }
+    entries
+}
+
+/// Whether the name can be written as a key, a type, or a field name.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "{}(),=\"#%@\\".contains(c))
+}
+
+impl NdjsonEntry {
+    /// Reads an entry, checking that it can be written back.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let entry: NdjsonEntry = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        if !is_name(&entry.entrytype) {
+            return Err(format!("invalid entry type {:?}", entry.entrytype));
+        }
+        if !is_name(&entry.key) {
+            return Err(format!("invalid key {:?}", entry.key));
+        }
+        if entry.is_string() && entry.fields.len() != 1 {
+            return Err(format!("@string {:?} must have a single field", entry.key));
+        }
+        for field in entry.fields.iter() {
+            if !is_name(&field.name) {
+                return Err(format!("invalid field name {:?}", field.name));
+            }
+            let value = field.raw();
+            let (delimiter, _) = split_delimiters(&value);
+            if unbalanced_brace(&value).is_some()
+                || (field.delimiter != Delimiter::None && delimiter != field.delimiter)
+            {
+                return Err(format!(
+                    "the value of {} does not fit in its delimiters",
+                    field.name
+                ));
+            }
+        }
+        Ok(entry)
+    }
+
+    fn is_string(&self) -> bool {
+        self.entrytype.eq_ignore_ascii_case("string")
+    }
+
+    /// The entry in BibTeX, with its fields in order, to be formatted.
+    pub fn to_bibtex(&self) -> String {
+        if self.is_string() {
+            let field = &self.fields[0];
+            return format!("@{}{{{} = {}}}\n", self.entrytype, field.name, field.raw());
+        }
+        let mut out = format!("@{}{{{},\n", self.entrytype, self.key);
+        for field in self.fields.iter() {
+            out.push_str(&format!("  {} = {},\n", field.name, field.raw()));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// The BibTeX of the entries of an NDJSON document (the empty lines
+/// are skipped), or the error of the first line that cannot be read.
+pub fn import(ndjson: &str) -> Result<String, String> {
+    let mut out = String::new();
+    for (i, line) in ndjson.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = NdjsonEntry::parse(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        out.push_str(&entry.to_bibtex());
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::{Bibliography, FormatSettings};
+    use crate::bibtex::entry_fingerprint;
+
+    #[test]
+    fn test_split_delimiters() {
+        assert_eq!(split_delimiters("{A {B}}"), (Delimiter::Braces, "A {B}"));
+        assert_eq!(
+            split_delimiters("\"A {\"}B\""),
+            (Delimiter::Quotes, "A {\"}B")
+        );
+        assert_eq!(split_delimiters("2020"), (Delimiter::None, "2020"));
+        assert_eq!(
+            split_delimiters("jan # \" 1\""),
+            (Delimiter::None, "jan # \" 1\"")
+        );
+        assert_eq!(
+            split_delimiters("{A} # {B}"),
+            (Delimiter::None, "{A} # {B}")
+        );
+        assert_eq!(split_delimiters("{}"), (Delimiter::Braces, ""));
+    }
+
+    #[test]
+    fn test_parse() {
+        let line = r#"{"key":"a","type":"misc","fields":[{"name":"title","value":"A {B}","delimiter":"braces"}]}"#;
+        let entry = NdjsonEntry::parse(line).unwrap();
+        assert_eq!(entry.to_bibtex(), "@misc{a,\n  title = {A {B}},\n}\n");
+        let error = |line: &str| NdjsonEntry::parse(line).unwrap_err();
+        assert!(error(&line.replace("\"a\"", "\"a b\"")).contains("invalid key"));
+        assert!(error(&line.replace("A {B}", "A {B")).contains("title"));
+        assert!(error(&line.replace("A {B}", "A} {B")).contains("title"));
+        assert!(error("{}").contains("missing field"));
+    }
+
+    #[test]
+    fn test_import() {
+        let ndjson = "{\"key\":\"ieee\",\"type\":\"string\",\"fields\":[{\"name\":\"ieee\",\"value\":\"IEEE\",\"delimiter\":\"quotes\"}]}\n\n\
+                      {\"key\":\"a\",\"type\":\"Article\",\"fields\":[{\"name\":\"journal\",\"value\":\"ieee\",\"delimiter\":\"none\"}]}\n";
+        assert_eq!(
+            import(ndjson).unwrap(),
+            "@string{ieee = \"IEEE\"}\n\n@Article{a,\n  journal = ieee,\n}\n\n"
+        );
+        assert!(import("{}\n").unwrap_err().starts_with("line 1: "));
+    }
+
+    /// The key and fingerprint of every entry.
+    fn fingerprints(bibliography: &Bibliography) -> Vec<(String, [u8; 32])> {
+        bibliography.with_bibfile(|file| {
+            file.list_entries()
+                .map(|e| {
+                    let key = file.get_slice(e.key).trim().to_string();
+                    (key, entry_fingerprint(file, &e))
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_round_trip_fixtures() {
+        let settings = FormatSettings {
+            format_author: false,
+            ..FormatSettings::default()
+        };
+        let mut dirs = vec![crate::fixture(".")];
+        let mut checked = 0;
+        while let Some(dir) = dirs.pop() {
+            for path in std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()) {
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.extension().is_none_or(|ext| ext != "bib") {
+                    continue;
+                }
+                let original = Bibliography::from_path(&path).unwrap();
+                if original.first_syntax_error().is_some() {
+                    continue;
+                }
+                let ndjson = original.with_bibfile(|file| {
+                    export(file)
+                        .iter()
+                        .map(|e| serde_json::to_string(e).unwrap() + "\n")
+                        .collect::<String>()
+                });
+                let imported = Bibliography::from(import(&ndjson).unwrap());
+                let formatted = Bibliography::from(imported.format(&settings));
+                assert_eq!(
+                    fingerprints(&formatted),
+                    fingerprints(&original),
+                    "{}",
+                    path.display()
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 5);
+    }
+}
@@ -0,0 +1,126 @@
+//! This file is responsible for building the http clients, and for the
+//! `--offline` mode (or `BIBADAC_OFFLINE=1`), in which no request ever
+//! reaches the network.
+//!
+//! The mode is enforced in the clients rather than before each request:
+//! an offline client resolves every host name to an `OfflineMode` error,
+//! and sends every request through a proxy whose name cannot be resolved
+//! either, so that urls with an IP address are refused as well. The
+//! refused requests are counted, for the command to report them.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Enables the offline mode when set to `1` (or `true`).
+pub const OFFLINE_ENV: &str = "BIBADAC_OFFLINE";
+
+/// The proxy of the offline clients, that is never resolved.
+const OFFLINE_PROXY: &str = "http://offline.invalid";
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// The error of a request refused in offline mode.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("network access is disabled (--offline)")]
+pub struct OfflineMode;
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::SeqCst);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::SeqCst)
+}
+
+/// Whether the value of `BIBADAC_OFFLINE` enables the offline mode.
+pub fn offline_from_env(value: Option<&str>) -> bool {
+    matches!(value.map(str::trim), Some("1" | "true"))
+}
+
+/// The number of requests refused so far.
+pub fn skipped() -> usize {
+    SKIPPED.load(Ordering::SeqCst)
+}
+
+/// Whether the error, or one of its sources, is an `OfflineMode`.
+pub fn is_offline_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut error = Some(error);
+    while let Some(e) = error {
+        if e.is::<OfflineMode>() {
+            return true;
+        }
+        error = e.source();
+    }
+    false
+}
+
+/// Resolves no name at all.
+struct RefuseAll;
+
+impl Resolve for RefuseAll {
+    fn resolve(&self, _name: Name) -> Resolving {
+        SKIPPED.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async {
+            Err::<Addrs, _>(Box::new(OfflineMode) as Box<dyn std::error::Error + Send + Sync>)
+        })
+    }
+}
+
+/// The builder of every http client, which refuses all the requests
+/// in offline mode. A proxy added afterwards is not used then.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    offline_client_builder(is_offline())
+}
+
+/// The builder of a client refusing all the requests when `offline`,
+/// or in the offline mode of the command.
+pub fn offline_client_builder(offline: bool) -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    if !offline && !is_offline() {
+        return builder;
+    }
+    builder
+        .dns_resolver(Arc::new(RefuseAll))
+        .proxy(reqwest::Proxy::custom(|_| Some(OFFLINE_PROXY)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_from_env() {
+        assert!(offline_from_env(Some("1")));
+        assert!(offline_from_env(Some("true ")));
+        assert!(!offline_from_env(Some("0")));
+        assert!(!offline_from_env(Some("")));
+        assert!(!offline_from_env(None));
+    }
+
+    #[test]
+    fn test_is_offline_error() {
+        #[derive(Debug, thiserror::Error)]
+        #[error("could not connect")]
+        struct Wrapper(#[source] OfflineMode);
+
+        assert!(is_offline_error(&OfflineMode));
+        assert!(is_offline_error(&Wrapper(OfflineMode)));
+        let other = std::io::Error::other("could not connect");
+        assert!(!is_offline_error(&other));
+    }
+
+    #[test]
+    fn test_refuse_all() {
+        let before = skipped();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let name = "example.org".parse::<Name>().unwrap();
+        let result = rt.block_on(RefuseAll.resolve(name));
+        let error = result.err().unwrap();
+        assert!(is_offline_error(error.as_ref()));
+        assert!(skipped() > before);
+    }
+}
@@ -0,0 +1,199 @@
+//! This file is responsible for the budget of an online verification
+//! of the identifiers of a bibliography (dois, arXiv eprints, and urls),
+//! so that a large file does not trip the rate limits of the services.
+//!
+//! The verification is scoped to some kinds of identifiers, and capped
+//! to a number of requests. Within the budget, the identifiers that were
+//! never verified (absent from the on-disk cache) come first, in the order
+//! of the file, followed by the others from the least recently verified.
+//! The identifiers beyond the budget are reported as not verified, rather
+//! than silently skipped.
+use std::collections::HashSet;
+
+use crate::cache::Cache;
+
+/// How the identifiers beyond the budget are reported.
+pub const BUDGET_EXHAUSTED: &str = "not verified (budget exhausted)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdentifierKind {
+    Doi,
+    Arxiv,
+    Url,
+}
+
+impl IdentifierKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IdentifierKind::Doi => "doi",
+            IdentifierKind::Arxiv => "arxiv",
+            IdentifierKind::Url => "url",
+        }
+    }
+}
+
+impl std::str::FromStr for IdentifierKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "doi" => Ok(IdentifierKind::Doi),
+            "arxiv" => Ok(IdentifierKind::Arxiv),
+            "url" => Ok(IdentifierKind::Url),
+            _ => Err(format!(
+                "Invalid identifier kind {:?} (expected doi, arxiv, or url)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Identifier {
+    pub kind: IdentifierKind,
+    pub value: String,
+}
+
+impl Identifier {
+    /// The key of the identifier in the cache (`doi:10.1145/123`).
+    pub fn cache_key(&self) -> String {
+        format!("{}:{}", self.kind.name(), self.value)
+    }
+}
+
+/// The identifiers to verify, as indices in the list of identifiers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Verified, in the order of the requests
+    pub requests: Vec<usize>,
+    /// Not verified, for lack of budget
+    pub exhausted: Vec<usize>,
+    /// Not verified, as their kind is not selected
+    pub out_of_scope: Vec<usize>,
+}
+
+/// The scope and the cap of an online verification
+/// (`--online-only` and `--max-requests`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The kinds verified, all of them when empty
+    pub kinds: Vec<IdentifierKind>,
+    pub max_requests: Option<usize>,
+}
+
+impl Budget {
+    /// Plans the verification of identifiers (see `plan_requests`), an
+    /// identifier being last verified when its entry of the cache was written.
+    pub fn plan(&self, identifiers: &[Identifier], cache: Option<&Cache>) -> Plan {
+        let last_verified = identifiers
+            .iter()
+            .map(|id| Some(cache?.get(&id.cache_key())?.created))
+            .collect::<Vec<_>>();
+        plan_requests(identifiers, &last_verified, &self.kinds, self.max_requests)
+    }
+}
+
+/// Plans the verification of identifiers, given the time (in seconds
+/// since the unix epoch) of their last verification according to the
+/// cache, the kinds to verify (all of them when empty), and the maximal
+/// number of requests. The repeated identifiers are verified once.
+pub fn plan_requests(
+    identifiers: &[Identifier],
+    last_verified: &[Option<u64>],
+    kinds: &[IdentifierKind],
+    max_requests: Option<usize>,
+) -> Plan {
+    let mut plan = Plan::default();
+    let mut seen = HashSet::new();
+    let mut candidates = vec![];
+    for (i, identifier) in identifiers.iter().enumerate() {
+        if !kinds.is_empty() && !kinds.contains(&identifier.kind) {
+            plan.out_of_scope.push(i);
+        } else if seen.insert(identifier) {
+            candidates.push((last_verified.get(i).copied().flatten(), i));
+        }
+    }
+    // `None` (never verified) comes first, then the oldest verifications
+    candidates.sort();
+    let budget = max_requests.unwrap_or(candidates.len());
+    for (n, (_, i)) in candidates.into_iter().enumerate() {
+        if n < budget {
+            plan.requests.push(i);
+        } else {
+            plan.exhausted.push(i);
+        }
+    }
+    plan.exhausted.sort();
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(kind: IdentifierKind, value: &str) -> Identifier {
+        Identifier {
+            kind,
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(
+            id(IdentifierKind::Doi, "10.1145/123").cache_key(),
+            "doi:10.1145/123"
+        );
+        assert_eq!("arxiv".parse(), Ok(IdentifierKind::Arxiv));
+        assert!("isbn".parse::<IdentifierKind>().is_err());
+    }
+
+    #[test]
+    fn test_plan_without_budget() {
+        let ids = vec![
+            id(IdentifierKind::Doi, "10.1/a"),
+            id(IdentifierKind::Url, "https://example.org"),
+            id(IdentifierKind::Doi, "10.1/a"),
+        ];
+        let plan = plan_requests(&ids, &[None, None, None], &[], None);
+        assert_eq!(plan.requests, vec![0, 1]);
+        assert!(plan.exhausted.is_empty() && plan.out_of_scope.is_empty());
+    }
+
+    #[test]
+    fn test_plan_prioritizes_unverified() {
+        let ids = vec![
+            id(IdentifierKind::Doi, "10.1/recent"),
+            id(IdentifierKind::Doi, "10.1/never"),
+            id(IdentifierKind::Arxiv, "2101.00001"),
+            id(IdentifierKind::Doi, "10.1/old"),
+            id(IdentifierKind::Doi, "10.1/never-too"),
+        ];
+        let verified = [Some(300), None, Some(200), Some(100), None];
+        let plan = plan_requests(&ids, &verified, &[], Some(3));
+        assert_eq!(plan.requests, vec![1, 4, 3]);
+        assert_eq!(plan.exhausted, vec![0, 2]);
+
+        let plan = plan_requests(&ids, &verified, &[], Some(0));
+        assert!(plan.requests.is_empty());
+        assert_eq!(plan.exhausted, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_plan_scope() {
+        let ids = vec![
+            id(IdentifierKind::Doi, "10.1/a"),
+            id(IdentifierKind::Url, "https://example.org"),
+            id(IdentifierKind::Arxiv, "2101.00001"),
+        ];
+        let plan = plan_requests(
+            &ids,
+            &[None, None, None],
+            &[IdentifierKind::Doi, IdentifierKind::Arxiv],
+            Some(1),
+        );
+        assert_eq!(plan.requests, vec![0]);
+        assert_eq!(plan.exhausted, vec![2]);
+        assert_eq!(plan.out_of_scope, vec![1]);
+    }
+}
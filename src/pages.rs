@@ -0,0 +1,155 @@
+//! This file is responsible for the `pages` field, which BibTeX
+//! expects as bare numbers and ranges (`101--115`, `xi--xv`, `e12`).
+//!
+//! Entries pasted from the references section of a paper often carry
+//! the `pp.` prefix of ISO 690 (`pp. 101--115`), or a range written
+//! with a Unicode dash (`101–115`): both are rewritten to `101--115`.
+//! A textual value (`electronic`, `online`) is reported, but left as is,
+//! since there is no page number to write instead.
+use std::fmt::{self, Display, Formatter};
+
+/// The prefixes of a page number, in lowercase.
+const PREFIXES: [&str; 4] = ["pages", "pp.", "pp", "p."];
+
+/// The dashes of page ranges, other than the hyphen of BibTeX.
+const UNICODE_DASHES: [char; 5] = ['\u{2010}', '\u{2012}', '\u{2013}', '\u{2014}', '\u{2212}'];
+
+/// What is wrong with the value of a `pages` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagesIssue {
+    /// A `pp.` (or `p.`, `pages`) prefix
+    Prefix,
+    /// A range written with a Unicode dash (`101–115`)
+    UnicodeDash,
+    /// No page number at all (`electronic`, `online`)
+    Textual,
+}
+
+impl Display for PagesIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PagesIssue::Prefix => "prefix",
+            PagesIssue::UnicodeDash => "unicode-dash",
+            PagesIssue::Textual => "textual",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The value without its prefix, if it has one.
+fn strip_prefix(value: &str) -> Option<&str> {
+    let lowercase = value.to_lowercase();
+    PREFIXES.iter().find_map(|prefix| {
+        let rest = value.get(prefix.len()..)?;
+        let separated = prefix.ends_with('.')
+            || rest.starts_with(|c: char| c.is_whitespace() || c == '~' || c.is_ascii_digit());
+        (lowercase.starts_with(prefix) && separated)
+            .then(|| rest.trim_start_matches(|c: char| c.is_whitespace() || c == '~'))
+            .filter(|rest| !rest.is_empty())
+    })
+}
+
+/// Whether the word is a roman numeral (`xi`, `XV`).
+fn is_roman(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| "ivxlcdm".contains(c.to_ascii_lowercase()))
+}
+
+/// The issues of a `pages` value (without its delimiters).
+pub fn pages_issues(value: &str) -> Vec<PagesIssue> {
+    let value = value.trim();
+    let mut issues = vec![];
+    let pages = match strip_prefix(value) {
+        Some(pages) => {
+            issues.push(PagesIssue::Prefix);
+            pages
+        }
+        None => value,
+    };
+    if pages.contains(UNICODE_DASHES) {
+        issues.push(PagesIssue::UnicodeDash);
+    }
+    let numbered = pages.contains(|c: char| c.is_ascii_digit())
+        || pages
+            .split(|c: char| {
+                c == '-' || c == ',' || c.is_whitespace() || UNICODE_DASHES.contains(&c)
+            })
+            .filter(|word| !word.is_empty())
+            .all(is_roman);
+    if !pages.is_empty() && !numbered {
+        issues.push(PagesIssue::Textual);
+    }
+    issues
+}
+
+/// The value without its prefix, and with its Unicode dashes replaced
+/// by `--`, or `None` when it is fine as is, or textual.
+pub fn normalize_pages(value: &str) -> Option<String> {
+    let issues = pages_issues(value);
+    if issues.is_empty() || issues.contains(&PagesIssue::Textual) {
+        return None;
+    }
+    let value = value.trim();
+    let pages = strip_prefix(value).unwrap_or(value);
+    let mut normalized = String::with_capacity(pages.len());
+    let mut chars = pages.chars().peekable();
+    while let Some(c) = chars.next() {
+        if UNICODE_DASHES.contains(&c) {
+            // `101 – 115` is written `101--115`
+            let trimmed = normalized.trim_end().len();
+            normalized.truncate(trimmed);
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            normalized.push_str("--");
+        } else {
+            normalized.push(c);
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages_issues() {
+        use PagesIssue::*;
+        // (value, issues)
+        let matrix: [(&str, &[PagesIssue]); 12] = [
+            ("101--115", &[]),
+            ("e12--e14", &[]),
+            ("17:1--17:25", &[]),
+            ("xi--xv", &[]),
+            ("XI-XV", &[]),
+            ("pp. 101--115", &[Prefix]),
+            ("pp.~101--115", &[Prefix]),
+            ("p. 7", &[Prefix]),
+            ("101–115", &[UnicodeDash]),
+            ("pp. 101 — 115", &[Prefix, UnicodeDash]),
+            ("electronic", &[Textual]),
+            ("Online", &[Textual]),
+        ];
+        for (value, issues) in matrix {
+            assert_eq!(pages_issues(value), issues, "{}", value);
+        }
+        // a word starting like a prefix is not one
+        assert_eq!(pages_issues("pages"), vec![Textual]);
+        assert_eq!(pages_issues("ppx"), vec![Textual]);
+    }
+
+    #[test]
+    fn test_normalize_pages() {
+        assert_eq!(normalize_pages("pp. 101--115"), Some("101--115".into()));
+        assert_eq!(normalize_pages("PP 101–115"), Some("101--115".into()));
+        assert_eq!(normalize_pages("101 – 115"), Some("101--115".into()));
+        assert_eq!(normalize_pages("p.~7"), Some("7".into()));
+        assert_eq!(normalize_pages("pages 1–3, 7–9"), Some("1--3, 7--9".into()));
+        // fine as is, or nothing to write instead
+        assert_eq!(normalize_pages("101--115"), None);
+        assert_eq!(normalize_pages("xi--xv"), None);
+        assert_eq!(normalize_pages("electronic"), None);
+        assert_eq!(normalize_pages("pp. online"), None);
+    }
+}
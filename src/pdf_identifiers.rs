@@ -0,0 +1,208 @@
+//! This file is responsible for `bibadac setup --from-pdf`:
+//! finding the DOI or arXiv identifier of downloaded PDFs,
+//! so that their entries can be downloaded.
+//!
+//! Only the text of the first two pages is searched, where
+//! publishers and arXiv print the identifiers. When none is found,
+//! the first line that looks like a sentence is given as a guess of
+//! the title: the extracted text has no font sizes, but titles are
+//! almost always the first long line of the first page.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use tracing::warn;
+
+fn doi_regex() -> &'static regex::Regex {
+    static INIT: OnceLock<regex::Regex> = OnceLock::new();
+    INIT.get_or_init(|| regex::Regex::new(r"\b10\.\d{4,9}/[^\s\x22<>]+").unwrap())
+}
+
+fn arxiv_regex() -> &'static regex::Regex {
+    static INIT: OnceLock<regex::Regex> = OnceLock::new();
+    INIT.get_or_init(|| {
+        regex::Regex::new(
+            r"(?i)arxiv:\s*(\d{4}\.\d{4,5}(v\d+)?|[a-z\-]+(\.[a-z]{2})?/\d{7}(v\d+)?)",
+        )
+        .unwrap()
+    })
+}
+
+/// The first DOI of the text, without the trailing punctuation.
+pub fn find_doi(text: &str) -> Option<String> {
+    let doi = doi_regex().find(text)?.as_str();
+    Some(
+        doi.trim_end_matches(['.', ',', ';', ':', ')', ']'])
+            .to_string(),
+    )
+}
+
+/// The first `arXiv:<id>` of the text.
+pub fn find_arxiv(text: &str) -> Option<String> {
+    Some(arxiv_regex().captures(text)?.get(1)?.as_str().to_string())
+}
+
+/// The first line of at least three words that is not an identifier.
+pub fn guess_title(text: &str) -> Option<String> {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|line| {
+            line.split(' ').count() >= 3
+                && find_doi(line).is_none()
+                && find_arxiv(line).is_none()
+                && line.chars().filter(|c| c.is_alphabetic()).count() * 2 > line.len()
+        })
+}
+
+#[derive(Debug, Clone)]
+pub struct PdfScan {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub doi: Option<String>,
+    pub arxiv: Option<String>,
+    pub title: Option<String>,
+}
+
+impl PdfScan {
+    /// The identifier of the PDF, as given to the downloaders.
+    pub fn identifier(&self) -> Option<&str> {
+        self.doi.as_deref().or(self.arxiv.as_deref())
+    }
+
+    /// A `@mapping` entry for the PDF, as written by `bibadac setup`,
+    /// with the guessed title when there is no identifier.
+    pub fn mapping_entry(&self) -> String {
+        let short_sha = &self.sha256[..self.sha256.len().min(10)];
+        let mut key = short_sha.to_string();
+        let mut fields = vec![
+            ("sha256", self.sha256.clone()),
+            ("filename", self.path.display().to_string()),
+        ];
+        if let Some(doi) = &self.doi {
+            key.push_str(&format!(":doi:{}", doi));
+            fields.push(("doi", doi.clone()));
+        } else if let Some(arxiv) = &self.arxiv {
+            key.push_str(&format!(":arxiv:{}", arxiv));
+            fields.push(("eprint", arxiv.clone()));
+        } else if let Some(title) = &self.title {
+            fields.push(("title", title.clone()));
+        }
+        let fields = fields
+            .iter()
+            .map(|(name, value)| format!("{} = {{{}}}", name, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("@mapping{{{}, {}}}", key, fields)
+    }
+}
+
+/// Reads a PDF: its sha256, and the identifiers found in its
+/// first two pages. A PDF whose text cannot be extracted
+/// (e.g. a scan) has no identifiers.
+pub fn scan_pdf(path: &Path) -> io::Result<PdfScan> {
+    use sha2::Digest;
+    let bytes = std::fs::read(path)?;
+    let sha256 = format!("{:x}", sha2::Sha256::digest(&bytes));
+    let text = match pdf_extract::extract_text_from_mem_by_pages(&bytes) {
+        Ok(pages) => pages.into_iter().take(2).collect::<Vec<_>>().join("\n"),
+        Err(error) => {
+            warn!(path = %path.display(), %error, "could not extract the text of the pdf");
+            String::new()
+        }
+    };
+    Ok(PdfScan {
+        path: path.to_path_buf(),
+        sha256,
+        doi: find_doi(&text),
+        arxiv: find_arxiv(&text),
+        title: guess_title(&text),
+    })
+}
+
+/// The PDFs of a directory, in alphabetical order.
+pub fn list_pdfs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for file in std::fs::read_dir(dir)? {
+        let path = file?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Scans the PDFs of a directory, in alphabetical order.
+pub fn scan_dir(dir: &Path) -> io::Result<Vec<PdfScan>> {
+    list_pdfs(dir)?.iter().map(|path| scan_pdf(path)).collect()
+}
+
+/// Adds fields at the end of a downloaded entry.
+pub fn annotate_entry(entry: &str, fields: &[(&str, &str)]) -> String {
+    let entry = entry.trim_end();
+    let Some(body) = entry.strip_suffix('}') else {
+        return entry.to_string();
+    };
+    let mut out = body.trim_end().trim_end_matches(',').to_string();
+    for (name, value) in fields {
+        out.push_str(&format!(",\n  {} = {{{}}}", name, value));
+    }
+    out.push_str("\n}");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_identifiers() {
+        let text = "Journal of Things, 2020\nhttps://doi.org/10.1145/3290355.\narXiv:2101.00001v2 [cs.LO] 1 Jan 2021";
+        assert_eq!(find_doi(text), Some("10.1145/3290355".into()));
+        assert_eq!(find_arxiv(text), Some("2101.00001v2".into()));
+        assert_eq!(
+            find_arxiv("ArXiv: hep-th/9901001"),
+            Some("hep-th/9901001".into())
+        );
+        assert_eq!(find_doi("no identifier here"), None);
+    }
+
+    #[test]
+    fn test_guess_title() {
+        let text = "LICS 2020\n\n  On the   Theory of Things \nJane Doe\n";
+        assert_eq!(guess_title(text), Some("On the Theory of Things".into()));
+        assert_eq!(guess_title("12 34 56\n"), None);
+    }
+
+    #[test]
+    fn test_mapping_entry() {
+        let mut scan = PdfScan {
+            path: PathBuf::from("papers/doe.pdf"),
+            sha256: "0123456789abcdef".into(),
+            doi: None,
+            arxiv: Some("2101.00001".into()),
+            title: Some("On the Theory of Things".into()),
+        };
+        assert_eq!(
+            scan.mapping_entry(),
+            "@mapping{0123456789:arxiv:2101.00001, sha256 = {0123456789abcdef}, filename = {papers/doe.pdf}, eprint = {2101.00001}}"
+        );
+        scan.arxiv = None;
+        assert_eq!(
+            scan.mapping_entry(),
+            "@mapping{0123456789, sha256 = {0123456789abcdef}, filename = {papers/doe.pdf}, title = {On the Theory of Things}}"
+        );
+    }
+
+    #[test]
+    fn test_annotate_entry() {
+        let entry = "@article{Doe_2020,\n  title = {T},\n  year = {2020}\n}\n";
+        assert_eq!(
+            annotate_entry(entry, &[("sha256", "abc"), ("filename", "doe.pdf")]),
+            "@article{Doe_2020,\n  title = {T},\n  year = {2020},\n  sha256 = {abc},\n  filename = {doe.pdf}\n}"
+        );
+    }
+}
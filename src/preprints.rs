@@ -0,0 +1,198 @@
+//! This file is responsible for recognising the arXiv
+//! preprint and the published version of the same paper,
+//! cited as two different entries of a bibliography.
+//!
+//! Two entries are versions of the same paper when their
+//! titles and the last names of their first authors agree
+//! (up to case, punctuation, and braces), and when one of them
+//! only has an eprint while the other only has a doi.
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// Only an eprint
+    Preprint,
+    /// Only a doi
+    Published,
+}
+
+impl Version {
+    pub fn of(has_eprint: bool, has_doi: bool) -> Option<Version> {
+        match (has_eprint, has_doi) {
+            (true, false) => Some(Version::Preprint),
+            (false, true) => Some(Version::Published),
+            _ => None,
+        }
+    }
+}
+
+/// The normalized title and last name of the first author.
+pub type PaperKey = (String, String);
+
+/// The lowercase alphanumeric words of a title
+/// (braces are dropped, so that `{T}hings` is `things`).
+pub fn normalize_title(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| *c != '{' && *c != '}')
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The normalized last name of the first author,
+/// written either `Last, First` or `First Last`.
+pub fn first_author(authors: &str) -> Option<String> {
+    let first = authors.split(" and ").next()?.trim();
+    let last = match first.split_once(',') {
+        Some((last, _)) => last,
+        None => first.split_whitespace().last()?,
+    };
+    let last = normalize_title(last);
+    if last.is_empty() { None } else { Some(last) }
+}
+
+/// What has to agree for two entries to be the same paper.
+pub fn paper_key(title: &str, authors: &str) -> Option<PaperKey> {
+    let title = normalize_title(title);
+    if title.is_empty() {
+        return None;
+    }
+    Some((title, first_author(authors)?))
+}
+
+/// Pairs the preprints with the published versions of the same paper,
+/// given the key and version of every entry (if any). Returns pairs of
+/// indices `(preprint, published)`, each entry being in at most one pair.
+pub fn pair_preprints(entries: &[Option<(PaperKey, Version)>]) -> Vec<(usize, usize)> {
+    let mut unmatched: HashMap<&PaperKey, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    let mut pairs = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let Some((key, version)) = entry else {
+            continue;
+        };
+        let (preprints, published) = unmatched.entry(key).or_default();
+        match version {
+            Version::Preprint if !published.is_empty() => pairs.push((i, published.remove(0))),
+            Version::Published if !preprints.is_empty() => pairs.push((preprints.remove(0), i)),
+            Version::Preprint => preprints.push(i),
+            Version::Published => published.push(i),
+        }
+    }
+    pairs
+}
+
+/// Renames the keys cited in a LaTeX document (`\cite{a,b}`,
+/// `\citep[p.~3]{a}`, `\autocite*{a}`, ...), dropping the keys
+/// that become duplicates within the same command.
+pub fn rename_cite_keys(tex: &str, renames: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(tex.len());
+    let mut rest = tex;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let name_len = rest[1..]
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len() - 1);
+        let name = &rest[1..1 + name_len];
+        if name_len == 0 || !name.to_ascii_lowercase().contains("cite") {
+            // not a citation: copy the backslash (and the escaped character)
+            let skip = 1 + name_len.max(rest[1..].chars().next().map_or(0, char::len_utf8));
+            out.push_str(&rest[..skip.min(rest.len())]);
+            rest = &rest[skip.min(rest.len())..];
+            continue;
+        }
+        // the command, a star, and the optional arguments
+        let mut end = 1 + name_len;
+        if rest[end..].starts_with('*') {
+            end += 1;
+        }
+        while let Some(arg) = rest[end..].trim_start().strip_prefix('[') {
+            let Some(close) = arg.find(']') else {
+                break;
+            };
+            end = rest.len() - arg.len() + close + 1;
+        }
+        let Some(keys) = rest[end..].trim_start().strip_prefix('{') else {
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        };
+        let Some(close) = keys.find('}') else {
+            out.push_str(rest);
+            rest = "";
+            continue;
+        };
+        let open = rest.len() - keys.len();
+        out.push_str(&rest[..open]);
+
+        let mut seen: Vec<&str> = vec![];
+        let mut renamed: Vec<String> = vec![];
+        for segment in keys[..close].split(',') {
+            let key = segment.trim();
+            let new_key = renames.get(key).map(String::as_str).unwrap_or(key);
+            if seen.contains(&new_key) {
+                continue;
+            }
+            seen.push(new_key);
+            renamed.push(segment.replacen(key, new_key, 1));
+        }
+        out.push_str(&renamed.join(","));
+        rest = &keys[close..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paper_key() {
+        assert_eq!(
+            paper_key("{On the {Theory} of Things}", "Doe, Jane and Roe, R."),
+            paper_key("On the theory of things.", "Jane Doe and Richard Roe"),
+        );
+        assert_ne!(
+            paper_key("On the theory of things", "Doe, Jane"),
+            paper_key("On the theory of things", "Roe, Richard"),
+        );
+        assert_eq!(paper_key("", "Doe, Jane"), None);
+    }
+
+    #[test]
+    fn test_pair_preprints() {
+        let key = |t: &str| (t.to_string(), "doe".to_string());
+        let entries = vec![
+            Some((key("a"), Version::Preprint)),
+            Some((key("b"), Version::Published)),
+            None,
+            Some((key("a"), Version::Published)),
+            Some((key("a"), Version::Published)),
+            Some((key("c"), Version::Preprint)),
+        ];
+        assert_eq!(pair_preprints(&entries), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_rename_cite_keys() {
+        let renames = HashMap::from([("pre".to_string(), "pub".to_string())]);
+        assert_eq!(
+            rename_cite_keys("See \\cite{pre} and \\citep[p.~3]{other, pre}.", &renames),
+            "See \\cite{pub} and \\citep[p.~3]{other, pub}."
+        );
+        assert_eq!(
+            rename_cite_keys("\\autocite*[see][]{pub,pre}", &renames),
+            "\\autocite*[see][]{pub}"
+        );
+        // other commands and keys are left untouched
+        assert_eq!(
+            rename_cite_keys("\\emph{pre} \\% \\cite{prefix}", &renames),
+            "\\emph{pre} \\% \\cite{prefix}"
+        );
+    }
+}
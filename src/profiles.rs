@@ -0,0 +1,208 @@
+//! This file is responsible for the format profiles of the
+//! configuration file, such as
+//!
+//! ```toml
+//! [format.profiles.acm]
+//! normalize = true
+//! keep_field = ["author", "title", "doi", "year"]
+//! ```
+//!
+//! that `bibadac format --profile acm` selects.
+//!
+//! The settings are json objects (the options of `format`), resolved
+//! in layers: the defaults, then the settings of the profile, then the
+//! options given on the command line, that is, the options whose value
+//! is not the default one. In particular, a flag of the profile cannot
+//! be turned off on the command line.
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+/// The profiles of a configuration file, by name.
+pub type Profiles = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProfileError {
+    #[error("unknown profile `{0}` (available profiles: {1})")]
+    UnknownProfile(String, String),
+    #[error("unknown setting `{1}` in the profile `{0}`")]
+    UnknownSetting(String, String),
+    #[error("the profile `{0}` is not a table")]
+    NotATable(String),
+}
+
+fn object(value: &Value) -> Map<String, Value> {
+    match value {
+        Value::Object(object) => object.clone(),
+        _ => Map::new(),
+    }
+}
+
+/// The settings of the profile `name`.
+pub fn select<'a>(profiles: &'a Profiles, name: &str) -> Result<&'a Value, ProfileError> {
+    profiles.get(name).ok_or_else(|| {
+        let available = if profiles.is_empty() {
+            "none".to_string()
+        } else {
+            profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        };
+        ProfileError::UnknownProfile(name.to_string(), available)
+    })
+}
+
+/// The settings of the profile `name` (if any) over the `defaults`,
+/// overridden by the settings of `cli` that are not the defaults.
+pub fn resolve(
+    defaults: &Value,
+    profiles: &Profiles,
+    name: Option<&str>,
+    cli: &Value,
+) -> Result<Value, ProfileError> {
+    let defaults = object(defaults);
+    let mut settings = defaults.clone();
+    if let Some(name) = name {
+        let Value::Object(profile) = select(profiles, name)? else {
+            return Err(ProfileError::NotATable(name.to_string()));
+        };
+        for (setting, value) in profile {
+            if !defaults.contains_key(setting) {
+                return Err(ProfileError::UnknownSetting(
+                    name.to_string(),
+                    setting.clone(),
+                ));
+            }
+            settings.insert(setting.clone(), value.clone());
+        }
+    }
+    for (setting, value) in object(cli) {
+        if defaults.get(&setting) != Some(&value) {
+            settings.insert(setting, value);
+        }
+    }
+    Ok(Value::Object(settings))
+}
+
+/// The settings that are not the defaults.
+pub fn changed_settings(defaults: &Value, settings: &Value) -> Vec<(String, Value)> {
+    let defaults = object(defaults);
+    object(settings)
+        .into_iter()
+        .filter(|(setting, value)| defaults.get(setting) != Some(value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(values: &[(&str, Value)]) -> Value {
+        Value::Object(
+            values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        )
+    }
+
+    fn fields(fields: &[&str]) -> Value {
+        Value::Array(
+            fields
+                .iter()
+                .map(|f| Value::String(f.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_resolve() {
+        let defaults = settings(&[
+            ("normalize", Value::Bool(false)),
+            ("sort_entries", Value::Bool(false)),
+            ("remove_field", fields(&[])),
+            ("convert_to", Value::Null),
+        ]);
+        let profiles = Profiles::from([
+            (
+                "acm".to_string(),
+                settings(&[("normalize", Value::Bool(true))]),
+            ),
+            (
+                "wiki".to_string(),
+                settings(&[
+                    ("sort_entries", Value::Bool(true)),
+                    ("remove_field", fields(&["abstract"])),
+                ]),
+            ),
+        ]);
+
+        // without a profile, the options of the command line
+        let cli = settings(&[("sort_entries", Value::Bool(true))]);
+        let resolved = resolve(&defaults, &profiles, None, &cli).unwrap();
+        assert_eq!(
+            changed_settings(&defaults, &resolved),
+            vec![("sort_entries".to_string(), Value::Bool(true))]
+        );
+
+        // the profile over the defaults
+        let resolved = resolve(&defaults, &profiles, Some("wiki"), &defaults).unwrap();
+        assert_eq!(
+            changed_settings(&defaults, &resolved),
+            vec![
+                ("remove_field".to_string(), fields(&["abstract"])),
+                ("sort_entries".to_string(), Value::Bool(true)),
+            ]
+        );
+
+        // the command line over the profile
+        let cli = settings(&[
+            ("remove_field", fields(&["note"])),
+            ("normalize", Value::Bool(true)),
+            ("sort_entries", Value::Bool(false)),
+        ]);
+        let resolved = resolve(&defaults, &profiles, Some("wiki"), &cli).unwrap();
+        assert_eq!(
+            changed_settings(&defaults, &resolved),
+            vec![
+                ("normalize".to_string(), Value::Bool(true)),
+                ("remove_field".to_string(), fields(&["note"])),
+                ("sort_entries".to_string(), Value::Bool(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let defaults = settings(&[("normalize", Value::Bool(false))]);
+        let profiles = Profiles::from([
+            (
+                "acm".to_string(),
+                settings(&[("normalise", Value::Bool(true))]),
+            ),
+            ("wiki".to_string(), Value::Bool(true)),
+        ]);
+        let error = resolve(&defaults, &profiles, Some("ieee"), &defaults).unwrap_err();
+        assert_eq!(
+            error,
+            ProfileError::UnknownProfile("ieee".into(), "acm, wiki".into())
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown profile `ieee` (available profiles: acm, wiki)"
+        );
+        assert_eq!(
+            select(&Profiles::new(), "acm"),
+            Err(ProfileError::UnknownProfile("acm".into(), "none".into()))
+        );
+        assert_eq!(
+            resolve(&defaults, &profiles, Some("acm"), &defaults),
+            Err(ProfileError::UnknownSetting(
+                "acm".into(),
+                "normalise".into()
+            ))
+        );
+        assert_eq!(
+            resolve(&defaults, &profiles, Some("wiki"), &defaults),
+            Err(ProfileError::NotATable("wiki".into()))
+        );
+    }
+}
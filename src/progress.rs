@@ -0,0 +1,217 @@
+//! This file is responsible for the progress events of `bibadac setup`,
+//! which are both the lines of its progress on stderr and, with
+//! `--progress-json`, JSON lines on stderr for a graphical front-end
+//! (instead of the lines):
+//!
+//! ```json
+//! {"event":"run-started","schema_version":1,"dois":1,"eprints":1,"pdfs":0}
+//! {"event":"item-started","item":"doi:10.1000/a","kind":"bibtex"}
+//! {"event":"item-finished","item":"doi:10.1000/a","kind":"bibtex","status":"done","elapsed_ms":120.5}
+//! {"event":"run-finished","entries":1,"entries_total":2,"pdfs":0,"pdfs_total":0,"elapsed_ms":840.2}
+//! ```
+//!
+//! Both are written by `Progress::emit`, so that they cannot drift apart.
+//!
+//! The events are stable: a field or an event may be added within a
+//! `PROGRESS_SCHEMA_VERSION`, but a field is only removed, renamed, or
+//! given another meaning along with a new version. A front-end should
+//! skip the events it does not know.
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::logging::PROGRESS;
+
+/// The version of the shape of the events.
+pub const PROGRESS_SCHEMA_VERSION: u32 = 1;
+
+/// The requests about to be made.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RunStarted {
+    pub schema_version: u32,
+    pub dois: usize,
+    pub eprints: usize,
+    /// The pdfs downloaded afterwards (0 without `--documents`)
+    pub pdfs: usize,
+}
+
+/// What an item fetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ItemKind {
+    Bibtex,
+    Pdf,
+}
+
+/// A request sent (the items done by a previous run are not).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ItemStarted {
+    /// The request, e.g. `doi:10.1000/a` or `arxiv:2101.00001`
+    pub item: String,
+    pub kind: ItemKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ItemStatus {
+    Done,
+    Failed,
+    /// Done by a previous run (`--resume`), without any request
+    Reused,
+}
+
+/// The end of an item, which every item of the run has.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ItemFinished {
+    pub item: String,
+    pub kind: ItemKind,
+    pub status: ItemStatus,
+    pub elapsed_ms: f64,
+}
+
+/// The end of the run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RunFinished {
+    /// The entries retrieved, out of `entries_total`
+    pub entries: usize,
+    pub entries_total: usize,
+    /// The pdfs downloaded, out of `pdfs_total`
+    pub pdfs: usize,
+    pub pdfs_total: usize,
+    pub elapsed_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ProgressEvent {
+    RunStarted(RunStarted),
+    ItemStarted(ItemStarted),
+    ItemFinished(ItemFinished),
+    RunFinished(RunFinished),
+}
+
+impl ProgressEvent {
+    /// The line of the event in the progress on stderr, if any.
+    pub fn line(&self) -> Option<String> {
+        match self {
+            ProgressEvent::RunStarted(e) => Some(format!(
+                "{:<10}\t{} dois / {} eprints / {} pdfs",
+                "[TOTAL]", e.dois, e.eprints, e.pdfs
+            )),
+            ProgressEvent::ItemStarted(e) => {
+                let tag = match e.kind {
+                    ItemKind::Bibtex => "[BIBTEX]",
+                    ItemKind::Pdf => "[PDF]",
+                };
+                Some(format!("{:<10}\t{}", tag, e.item))
+            }
+            ProgressEvent::ItemFinished(_) | ProgressEvent::RunFinished(_) => None,
+        }
+    }
+}
+
+type Sink = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Where the events go, on top of the progress on stderr.
+#[derive(Clone, Default)]
+pub struct Progress {
+    sink: Option<Sink>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("sink", &self.sink.is_some())
+            .finish()
+    }
+}
+
+impl Progress {
+    /// The events also go to `sink`.
+    pub fn with_sink(sink: impl Fn(&ProgressEvent) + Send + Sync + 'static) -> Self {
+        Progress {
+            sink: Some(Arc::new(sink)),
+        }
+    }
+
+    /// The events also go to stderr, as JSON lines (`--progress-json`).
+    pub fn json_lines() -> Self {
+        Progress::with_sink(|event| {
+            use std::io::Write;
+            if let Ok(line) = serde_json::to_string(event) {
+                // a single write, for the lines not to interleave
+                let _ = std::io::stderr().write_all(format!("{}\n", line).as_bytes());
+            }
+        })
+    }
+
+    pub fn emit(&self, event: ProgressEvent) {
+        if let Some(line) = event.line() {
+            info!(target: PROGRESS, "{}", line);
+        }
+        if let Some(sink) = &self.sink {
+            sink(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_event_json() {
+        let event = ProgressEvent::ItemFinished(ItemFinished {
+            item: "doi:10.1000/a".to_string(),
+            kind: ItemKind::Bibtex,
+            status: ItemStatus::Failed,
+            elapsed_ms: 1.5,
+        });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "item-finished");
+        assert_eq!(json["kind"], "bibtex");
+        assert_eq!(json["status"], "failed");
+        let line = serde_json::to_string(&event).unwrap();
+        assert_eq!(serde_json::from_str::<ProgressEvent>(&line).unwrap(), event);
+    }
+
+    #[test]
+    fn test_lines() {
+        let started = ProgressEvent::ItemStarted(ItemStarted {
+            item: "arxiv:2101.00001".to_string(),
+            kind: ItemKind::Bibtex,
+        });
+        let line = started.line().unwrap();
+        assert!(line.contains("[BIBTEX]"));
+        assert!(line.ends_with("\tarxiv:2101.00001"));
+        let finished = ProgressEvent::RunFinished(RunFinished {
+            entries: 1,
+            entries_total: 2,
+            pdfs: 0,
+            pdfs_total: 0,
+            elapsed_ms: 3.0,
+        });
+        assert_eq!(finished.line(), None);
+    }
+
+    #[test]
+    fn test_sink() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let progress = Progress::with_sink({
+            let events = events.clone();
+            move |event| events.lock().unwrap().push(event.clone())
+        });
+        progress.emit(ProgressEvent::ItemStarted(ItemStarted {
+            item: "doi:10.1000/a".to_string(),
+            kind: ItemKind::Pdf,
+        }));
+        assert_eq!(events.lock().unwrap().len(), 1);
+        Progress::default().emit(ProgressEvent::ItemStarted(ItemStarted {
+            item: "doi:10.1000/a".to_string(),
+            kind: ItemKind::Pdf,
+        }));
+    }
+}
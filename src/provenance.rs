@@ -0,0 +1,177 @@
+//! This file is responsible for the provenance of the merged entries
+//! (`merge --annotate-merge`): the fields that a merge took from another
+//! file than the one of their entry are annotated with a comment written
+//! right after them, such as
+//!
+//! ```bibtex
+//! @article{key,
+//!   title = {A Title},
+//!   pages = {1--3},
+//!   % bibadac: pages from b.bib
+//! }
+//! ```
+//!
+//! BibTeX has no comments inside of an entry, so the `% bibadac:` lines
+//! are blanked out before parsing (`mask_annotations`), keeping the byte
+//! offsets of the file. A comment belongs to the field before it: the
+//! formatter writes it back after that field, even when the fields are
+//! sorted, and `format --strip-annotations` removes it before submission.
+use std::borrow::Cow;
+
+use crate::bibtex::{BibEntry, BibFile};
+use crate::merge::OwnedEntry;
+
+/// What follows the `%` of the comments written by bibadac.
+pub const ANNOTATION_PREFIX: &str = "bibadac:";
+
+/// Where the fields of a merged entry come from, as the indices of the
+/// merged inputs: the input of the entry, and the fields (as written)
+/// taken from another input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub origin: usize,
+    pub fields: Vec<(String, usize)>,
+}
+
+impl Provenance {
+    pub fn new(origin: usize) -> Self {
+        Provenance {
+            origin,
+            fields: vec![],
+        }
+    }
+
+    /// Records the fields of `merged` whose value is not the one of
+    /// `first` as coming from the input `origin`.
+    pub fn record(&mut self, first: &OwnedEntry, merged: &OwnedEntry, origin: usize) {
+        for (name, value) in merged.fields.iter() {
+            if first.get(name) == Some(value.as_str()) {
+                continue;
+            }
+            self.fields.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+            if origin != self.origin {
+                self.fields.push((name.clone(), origin));
+            }
+        }
+    }
+}
+
+/// The text of a `% bibadac: <text>` line, or `None` for other lines.
+pub fn annotation(line: &str) -> Option<&str> {
+    let comment = line.trim().strip_prefix('%')?;
+    Some(comment.trim_start().strip_prefix(ANNOTATION_PREFIX)?.trim())
+}
+
+/// Replaces the `% bibadac:` lines with spaces, keeping line breaks
+/// and byte offsets untouched, so that the parser does not see them.
+pub fn mask_annotations(content: &str) -> Cow<'_, str> {
+    if !content.contains(ANNOTATION_PREFIX) {
+        return Cow::Borrowed(content);
+    }
+    let mut masked = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        if annotation(text).is_some() {
+            masked.extend(std::iter::repeat_n(' ', text.len()));
+            masked.push_str(&line[text.len()..]);
+        } else {
+            masked.push_str(line);
+        }
+    }
+    Cow::Owned(masked)
+}
+
+/// The annotations of every field of an entry: the `% bibadac:` lines
+/// between the field and the next one (or the end of the entry).
+pub fn field_annotations<'a>(file: &BibFile<'a>, entry: &BibEntry<'a>) -> Vec<Vec<&'a str>> {
+    let fields = &entry.fields;
+    (0..fields.len())
+        .map(|i| {
+            let start = fields[i].loc.end_byte();
+            let end = fields
+                .get(i + 1)
+                .map_or(entry.loc.end_byte(), |f| f.loc.start_byte());
+            file.content[start..end].lines().filter_map(annotation).collect()
+        })
+        .collect()
+}
+
+/// Annotates the fields of a merged entry with the input they come
+/// from, the inputs being named by `names`.
+pub fn annotate(entry: &mut OwnedEntry, provenance: &Provenance, names: &[String]) {
+    for (name, origin) in provenance.fields.iter() {
+        let input = names.get(*origin).map_or("?", |n| n.as_str());
+        entry
+            .annotations
+            .retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        entry
+            .annotations
+            .push((name.clone(), format!("{} from {}", name, input)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> OwnedEntry {
+        OwnedEntry {
+            key: "k".to_string(),
+            entrytype: "@article".to_string(),
+            fields: fields
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            annotations: vec![],
+        }
+    }
+
+    #[test]
+    fn test_record_and_annotate() {
+        let names = ["a.bib".to_string(), "b.bib".to_string()];
+        let first = entry(&[("title", "{A}"), ("pages", "{1--2}")]);
+        let merged = entry(&[("title", "{A}"), ("pages", "{1--3}"), ("doi", "{10.1/x}")]);
+        let mut provenance = Provenance::new(0);
+        provenance.record(&first, &merged, 1);
+
+        let mut annotated = merged.clone();
+        annotated
+            .annotations
+            .push(("pages".to_string(), "pages from c.bib".to_string()));
+        annotate(&mut annotated, &provenance, &names);
+        assert_eq!(
+            annotated.annotations,
+            vec![
+                ("pages".to_string(), "pages from b.bib".to_string()),
+                ("doi".to_string(), "doi from b.bib".to_string()),
+            ]
+        );
+        assert_eq!(
+            annotated.to_string(),
+            "@article{k,\n  title = {A},\n  pages = {1--3},\n  % bibadac: pages from b.bib\n  \
+             doi = {10.1/x},\n  % bibadac: doi from b.bib\n}\n"
+        );
+        // nothing from another input, no annotation
+        let mut unchanged = merged.clone();
+        annotate(&mut unchanged, &Provenance::new(0), &names);
+        assert_eq!(unchanged, merged);
+    }
+
+    #[test]
+    fn test_annotations_are_not_parsed() {
+        let source = "@misc{a,\n  title = {T},\n  % bibadac: title from b.bib\r\n  year = 2020,\n  %bibadac:year from c.bib\n}\n% bibadac-accept: other\n";
+        let masked = mask_annotations(source);
+        assert_eq!(masked.len(), source.len());
+        assert!(!masked.contains("title from"));
+        assert!(masked.contains("\r\n"));
+        assert!(masked.contains("bibadac-accept"));
+
+        let file = BibFile::new(source);
+        let entry = file.list_entries().next().unwrap();
+        assert_eq!(entry.fields.len(), 2);
+        assert_eq!(
+            field_annotations(&file, &entry),
+            vec![vec!["title from b.bib"], vec!["year from c.bib"]]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! This file is responsible for finding the registration agency of a
+//! doi (CrossRef, DataCite, ...), with the RA API of doi.org:
+//! `https://doi.org/ra/10.5281` answers
+//! `[{"DOI": "10.5281", "RA": "DataCite"}]`.
+//!
+//! The agency only depends on the prefix of the doi, so that the
+//! answers are remembered by prefix for the duration of a run, and
+//! kept in the metadata cache (under `ra:<prefix>`) across runs.
+//! The lookups are cheap to clone and share what they learn.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tracing::{debug, warn};
+
+use crate::cache::Cache;
+use crate::resolver::{Mirror, Resolver};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Agency {
+    Crossref,
+    DataCite,
+    /// The name given by doi.org, e.g. `mEDRA` or `JaLC`
+    Other(String),
+}
+
+impl Agency {
+    pub fn from_name(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "crossref" => Agency::Crossref,
+            "datacite" => Agency::DataCite,
+            _ => Agency::Other(name.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Agency::Crossref => "Crossref",
+            Agency::DataCite => "DataCite",
+            Agency::Other(name) => name,
+        }
+    }
+}
+
+/// The prefix of a doi (`10.5281` for `10.5281/zenodo.123`).
+pub fn doi_prefix(doi: &str) -> Option<&str> {
+    let (prefix, suffix) = doi.split_once('/')?;
+    (prefix.starts_with("10.") && !suffix.is_empty()).then_some(prefix)
+}
+
+/// The agency of an answer of the RA API, which is `None` for
+/// unknown prefixes (`[{"DOI": "10.9999", "status": "Prefix does not exist"}]`).
+pub fn parse_ra_response(text: &str) -> Option<Agency> {
+    let response: serde_json::Value = serde_json::from_str(text).ok()?;
+    let name = response.pointer("/0/RA")?.as_str()?;
+    Some(Agency::from_name(name))
+}
+
+/// Finds the registration agencies of dois, see the module documentation.
+#[derive(Debug, Clone, Default)]
+pub struct AgencyLookup {
+    resolver: Resolver,
+    cache: Option<Cache>,
+    known: Arc<Mutex<HashMap<String, Agency>>>,
+}
+
+impl AgencyLookup {
+    pub fn new(resolver: Resolver) -> Self {
+        AgencyLookup {
+            resolver,
+            ..AgencyLookup::default()
+        }
+    }
+
+    pub fn with_cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.resolver = self.resolver.with_mirror(mirror);
+        self
+    }
+
+    /// The agency of the doi, when doi.org knows its prefix.
+    pub async fn agency(&self, doi: &str) -> Option<Agency> {
+        let prefix = doi_prefix(doi)?;
+        if let Some(agency) = self.known.lock().unwrap().get(prefix) {
+            return Some(agency.clone());
+        }
+        let key = format!("ra:{}", prefix);
+        // a recorded mirror must see every request
+        let cache = self.cache.as_ref().filter(|_| !self.resolver.records());
+        let agency = match cache.and_then(|c| c.get(&key)) {
+            Some(entry) => {
+                debug!(%prefix, "registration agency cache hit");
+                Agency::from_name(&entry.payload)
+            }
+            None => {
+                let url = format!("https://doi.org/ra/{}", prefix);
+                let text = self.resolver.get_text(&key, &url).await?;
+                let Some(agency) = parse_ra_response(&text) else {
+                    warn!(%prefix, "no registration agency for the prefix");
+                    return None;
+                };
+                if let Some(cache) = &self.cache
+                    && let Err(error) = cache.put(&key, agency.name())
+                {
+                    warn!(%error, "could not write to the cache");
+                }
+                agency
+            }
+        };
+        self.known
+            .lock()
+            .unwrap()
+            .insert(prefix.to_string(), agency.clone());
+        Some(agency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doi_prefix() {
+        assert_eq!(doi_prefix("10.5281/zenodo.1234"), Some("10.5281"));
+        assert_eq!(doi_prefix("10.1000/a/b"), Some("10.1000"));
+        assert_eq!(doi_prefix("10.1000/"), None);
+        assert_eq!(doi_prefix("zenodo.1234"), None);
+    }
+
+    #[test]
+    fn test_parse_ra_response() {
+        let datacite = r#"[{"DOI": "10.5281", "RA": "DataCite"}]"#;
+        assert_eq!(parse_ra_response(datacite), Some(Agency::DataCite));
+        let crossref = r#"[{"DOI": "10.1145", "RA": "Crossref"}]"#;
+        assert_eq!(parse_ra_response(crossref), Some(Agency::Crossref));
+        let medra = r#"[{"DOI": "10.3280", "RA": "mEDRA"}]"#;
+        assert_eq!(
+            parse_ra_response(medra),
+            Some(Agency::Other("mEDRA".to_string()))
+        );
+        let unknown = r#"[{"DOI": "10.9999", "status": "Prefix does not exist"}]"#;
+        assert_eq!(parse_ra_response(unknown), None);
+        assert_eq!(parse_ra_response("<html>"), None);
+    }
+}
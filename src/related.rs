@@ -0,0 +1,84 @@
+//! This file is responsible for the `related` field of biblatex,
+//! which links an entry to other entries by their keys, e.g. a
+//! preprint to its published version:
+//!
+//! ```bibtex
+//! @misc{doe2020arxiv, related = {doe2021things}, relatedtype = {publishedas}, ...}
+//! ```
+//!
+//! The field holds a comma separated list of keys, that have to be
+//! entries of the loaded bibliographies, and that are renamed along
+//! the entries when they are merged.
+use std::collections::HashMap;
+
+pub const RELATED_FIELD: &str = "related";
+pub const RELATED_TYPE_FIELD: &str = "relatedtype";
+
+/// The keys of a `related` field (with or without its delimiters).
+pub fn related_keys(value: &str) -> Vec<&str> {
+    value
+        .trim()
+        .trim_matches(|c| c == '{' || c == '}' || c == '"')
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+/// The `related` field of an entry (`own_key`) once the entries are
+/// renamed, dropping the keys that become duplicates or the entry itself.
+/// Returns `None` when no key remains, and the value as is when no key
+/// is renamed.
+pub fn rename_related(
+    value: &str,
+    own_key: &str,
+    renames: &HashMap<String, String>,
+) -> Option<String> {
+    let keys = related_keys(value);
+    if !keys.iter().any(|k| renames.contains_key(*k)) {
+        return (!keys.is_empty()).then(|| value.to_string());
+    }
+    let mut renamed: Vec<&str> = vec![];
+    for key in keys {
+        let key = renames.get(key).map_or(key, String::as_str);
+        if key != own_key && !renamed.contains(&key) {
+            renamed.push(key);
+        }
+    }
+    (!renamed.is_empty()).then(|| format!("{{{}}}", renamed.join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_related_keys() {
+        assert_eq!(related_keys("{a, b,c}"), vec!["a", "b", "c"]);
+        assert_eq!(related_keys("\"a\""), vec!["a"]);
+        assert!(related_keys("{ , }").is_empty());
+    }
+
+    #[test]
+    fn test_rename_related() {
+        let renames = HashMap::from([
+            ("pre".to_string(), "pub".to_string()),
+            ("old".to_string(), "new".to_string()),
+        ]);
+        assert_eq!(
+            rename_related("{other, old}", "k", &renames),
+            Some("{other,new}".to_string())
+        );
+        // untouched values are kept as written
+        assert_eq!(
+            rename_related("{ a , b }", "k", &renames),
+            Some("{ a , b }".to_string())
+        );
+        assert_eq!(
+            rename_related("{old,new}", "k", &renames),
+            Some("{new}".to_string())
+        );
+        // the preprint folded into the entry that relates to it
+        assert_eq!(rename_related("{pre}", "pub", &renames), None);
+    }
+}
@@ -0,0 +1,1038 @@
+//! This file is responsible for rendering
+//! the reports of the `check` subcommand
+//! outside of the terminal, and for the order
+//! of the lints in the terminal.
+//!
+//! The html report is a single self-contained file
+//! (inline CSS and javascript, no external assets)
+//! containing the summary counts and a sortable table
+//! of lints with the source excerpt around each location,
+//! whose entry types, field names, and delimiters are
+//! highlighted (see `highlight`).
+//!
+//! The excerpts are cut to a number of characters centered on the span
+//! of the lint, so that a minified file (a single line of megabytes)
+//! does not flood the terminal.
+//!
+//! In the terminal, the lints of a file can be grouped by entry
+//! or by rule, and limited to a number of lints per file. This
+//! only changes the display: the json output is the same.
+//!
+//! The json report (`--to-json`) records when, by which version,
+//! and from which files it was generated. Its shape is versioned by
+//! `schema_version`, and described by the JSON Schema of `bibadac
+//! schema`. The reports of the first version, a bare list of files,
+//! are still read, and written with `--schema 1`. The second version
+//! only quoted the key of the `DuplicateKey` messages, without the
+//! entries defining it (see `legacy_duplicate_keys`).
+use std::ops::Range;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::baseline::ReportedDiagnostic;
+use crate::bibliography::{Diagnostic, Location};
+use crate::linter::{KeyOccurrence, LintMessage};
+use crate::timings::FileTimings;
+
+/// The span `start..end` within `s`, moved back to the char
+/// boundaries and cut at the end of `s`: the locations of a
+/// report or a baseline may come from another version of the file.
+fn char_span(s: &str, start: usize, end: usize) -> (usize, usize) {
+    let floor = |i: usize| {
+        let mut i = i.min(s.len());
+        while !s.is_char_boundary(i) {
+            i -= 1;
+        }
+        i
+    };
+    let start = floor(start);
+    (start, floor(end).max(start))
+}
+
+/// Splits `s` into the text *before* the span `start..end`
+/// (at most `window_size` characters), the span itself,
+/// and the text *after* the span (at most `window_size` characters).
+pub fn windowed(s: &str, start: usize, end: usize, window_size: usize) -> (&str, &str, &str) {
+    let (start, end) = char_span(s, start, end);
+    // the span is given in bytes, and the window in characters
+    let new_start_attempt = s[..start].chars().count().saturating_sub(window_size);
+    let new_end_attempt = s[..end].chars().count() + window_size;
+    let new_start = s
+        .char_indices()
+        .nth(new_start_attempt)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let new_end = s
+        .char_indices()
+        .nth(new_end_attempt)
+        .map(|(i, _)| i)
+        .unwrap_or(s.len());
+    (&s[new_start..start], &s[start..end], &s[end..new_end])
+}
+
+/// The default number of characters of an excerpt.
+pub const EXCERPT_WIDTH: usize = 100;
+
+/// A span of the source and the text around it,
+/// cut with `…` on the sides that are too long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Excerpt {
+    pub before: String,
+    pub span: String,
+    pub after: String,
+    /// Whether some of the text was cut
+    pub clipped: bool,
+}
+
+impl Excerpt {
+    /// The excerpt without carriage returns, which would move the
+    /// cursor of the terminal back to the start of the line.
+    fn printable(self) -> Excerpt {
+        let printable = |s: String| s.replace('\r', "");
+        Excerpt {
+            before: printable(self.before),
+            span: printable(self.span),
+            after: printable(self.after),
+            clipped: self.clipped,
+        }
+    }
+}
+
+fn first_chars(s: &str, n: usize) -> &str {
+    s.char_indices().nth(n).map_or(s, |(i, _)| &s[..i])
+}
+
+fn last_chars(s: &str, n: usize) -> &str {
+    match n {
+        0 => "",
+        n => s
+            .char_indices()
+            .rev()
+            .nth(n - 1)
+            .map_or(s, |(i, _)| &s[i..]),
+    }
+}
+
+/// Cuts the text `s` to at most `width` characters (and the ellipses)
+/// centered on the span `start..end`. A span longer than `width` is
+/// cut at its end, and the text before it is only an ellipsis.
+pub fn excerpt(s: &str, start: usize, end: usize, width: usize) -> Excerpt {
+    let (start, end) = char_span(s, start, end);
+    let (before, span, after) = (&s[..start], &s[start..end], &s[end..]);
+    let count = |s: &str| s.chars().count();
+    let (b, n, a) = (count(before), count(span), count(after));
+    if b + n + a <= width {
+        return Excerpt {
+            before: before.to_string(),
+            span: span.to_string(),
+            after: after.to_string(),
+            clipped: false,
+        }
+        .printable();
+    }
+    let shown = n.min(width);
+    let rest = width - shown;
+    let mut left = (rest / 2).min(b);
+    let right = (rest - left).min(a);
+    left = (rest - right).min(b);
+    let ellipsis = |cut: bool| if cut { "…" } else { "" };
+    Excerpt {
+        before: format!("{}{}", ellipsis(left < b), last_chars(before, left)),
+        span: format!("{}{}", first_chars(span, shown), ellipsis(shown < n)),
+        after: format!("{}{}", first_chars(after, right), ellipsis(right < a)),
+        clipped: true,
+    }
+    .printable()
+}
+
+/// The span `start..end` of the source with at most `window_size`
+/// characters on each side (see `windowed`), cut to `width` characters.
+pub fn excerpt_around(
+    s: &str,
+    start: usize,
+    end: usize,
+    window_size: usize,
+    width: usize,
+) -> Excerpt {
+    let (start, end) = char_span(s, start, end);
+    let (before, _, after) = windowed(s, start, end, window_size);
+    let s = &s[start - before.len()..end + after.len()];
+    excerpt(s, before.len(), before.len() + end - start, width)
+}
+
+/// A line of carets under the span of an excerpt, followed by the
+/// column of the span, when the excerpt is a single line that was cut.
+pub fn ruler(excerpt: &Excerpt, column: usize) -> Option<String> {
+    if !excerpt.clipped || excerpt.before.contains('\n') || excerpt.span.contains('\n') {
+        return None;
+    }
+    Some(format!(
+        "{}{} column {}",
+        " ".repeat(excerpt.before.chars().count()),
+        "^".repeat(excerpt.span.chars().count().max(1)),
+        column
+    ))
+}
+
+/// One lint, as displayed in the html report.
+#[derive(Debug, Clone)]
+pub struct ReportRow<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    pub rule: &'static str,
+    pub crucial: bool,
+    pub message: String,
+    pub excerpt: Excerpt,
+}
+
+impl<'a> ReportRow<'a> {
+    /// The row of a diagnostic of the file `file`, whose source is `source`.
+    pub fn of(file: &'a str, source: &str, diagnostic: &Diagnostic, width: usize) -> Self {
+        let loc = &diagnostic.loc[0];
+        ReportRow {
+            file,
+            line: loc.line,
+            rule: diagnostic.msg.rule_name(),
+            crucial: diagnostic.is_crucial(),
+            message: format!(
+                "{}{}",
+                lint_message(diagnostic).replace('\n', " "),
+                fixable_tag(diagnostic)
+            ),
+            excerpt: excerpt_around(source, loc.start_byte, loc.end_byte, 40, width),
+        }
+    }
+}
+
+/// The message of a lint, where the entries of a duplicate key
+/// are listed on a line of their own, to tell them apart.
+pub fn lint_message(diagnostic: &Diagnostic) -> String {
+    match &diagnostic.msg {
+        LintMessage::DuplicateKey { key, occurrences } => format!(
+            "DuplicateKey({:?})\n{}",
+            key,
+            key_occurrences(occurrences, &diagnostic.loc)
+        ),
+        msg => format!("{:?}", msg),
+    }
+}
+
+/// Tells the messages that completing the entry would resolve.
+pub fn fixable_tag(diagnostic: &Diagnostic) -> &'static str {
+    if diagnostic.fixable {
+        " (fixable via --file-db)"
+    } else {
+        ""
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The characters of a field name (or an entry type).
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// The escaped text of an excerpt, with its BibTeX tokens in spans:
+/// the entry types (`@article`), the field names (followed by `=`),
+/// and the delimiters. The excerpt is a part of the file, so this
+/// only looks at the characters, not at the syntax tree.
+fn highlight(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let name_len = |s: &str| s.find(|c: char| !is_name_char(c)).unwrap_or(s.len());
+        if c == '@' && name_len(&rest[1..]) > 0 {
+            let end = 1 + name_len(&rest[1..]);
+            out.push_str(&format!(
+                "<span class=\"type\">{}</span>",
+                escape(&rest[..end])
+            ));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() {
+            let end = name_len(rest);
+            let word = escape(&rest[..end]);
+            rest = &rest[end..];
+            if rest.trim_start_matches([' ', '\t']).starts_with('=') {
+                out.push_str(&format!("<span class=\"field\">{}</span>", word));
+            } else {
+                out.push_str(&word);
+            }
+        } else {
+            let text = escape(&rest[..c.len_utf8()]);
+            if "{}(),=\"#".contains(c) {
+                out.push_str(&format!("<span class=\"delim\">{}</span>", text));
+            } else {
+                out.push_str(&text);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+const STYLE: &str = r#"<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #eee; cursor: pointer; user-select: none; }
+td.error { color: #b00; font-weight: bold; }
+td.warning { color: #a60; }
+pre { margin: 0; white-space: pre-wrap; font-size: 0.9em; }
+pre .context { color: #666; }
+pre mark { background: #fdd; color: #b00; }
+pre .type { color: #708; font-weight: bold; }
+pre .field { color: #05a; }
+pre .delim { color: #999; }
+</style>"#;
+
+const SCRIPT: &str = r#"<script>
+document.querySelectorAll("th").forEach((th, i) => th.addEventListener("click", () => {
+  const body = th.closest("table").tBodies[0];
+  const asc = th.dataset.asc !== "true";
+  th.dataset.asc = asc;
+  const rows = Array.from(body.rows);
+  rows.sort((a, b) => {
+    const x = a.cells[i].innerText, y = b.cells[i].innerText;
+    const c = (isNaN(x) || isNaN(y)) ? x.localeCompare(y) : x - y;
+    return asc ? c : -c;
+  });
+  rows.forEach(r => body.appendChild(r));
+}));
+</script>"#;
+
+/// Renders the html report, given the list of checked
+/// files (with their number of lints) and the lints.
+pub fn html_report(files: &[(&str, usize)], rows: &[ReportRow]) -> String {
+    let errors = rows.iter().filter(|r| r.crucial).count();
+    let warnings = rows.len() - errors;
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>bibadac report</title>\n");
+    out.push_str(STYLE);
+    out.push_str("\n</head>\n<body>\n<h1>bibadac report</h1>\n");
+
+    out.push_str(&format!(
+        "<p>{} files checked, {} errors, {} warnings.</p>\n",
+        files.len(),
+        errors,
+        warnings
+    ));
+    out.push_str("<ul>\n");
+    for (file, count) in files {
+        out.push_str(&format!("<li>{}: {} lints</li>\n", escape(file), count));
+    }
+    out.push_str("</ul>\n");
+
+    out.push_str("<table>\n<thead>\n<tr><th>File</th><th>Line</th><th>Rule</th><th>Severity</th><th>Message</th><th>Source</th></tr>\n</thead>\n<tbody>\n");
+    for row in rows {
+        let severity = if row.crucial { "error" } else { "warning" };
+        let Excerpt {
+            before,
+            span: error,
+            after,
+            ..
+        } = &row.excerpt;
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td><pre><span class=\"context\">{}</span><mark>{}</mark><span class=\"context\">{}</span></pre></td></tr>\n",
+            escape(row.file),
+            row.line,
+            row.rule,
+            severity,
+            severity,
+            escape(&row.message),
+            highlight(before),
+            highlight(error),
+            highlight(after),
+        ));
+    }
+    out.push_str("</tbody>\n</table>\n");
+    out.push_str(SCRIPT);
+    out.push_str("\n</body>\n</html>\n");
+    out
+}
+
+/// How the lints of a file are grouped in the terminal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Grouping {
+    /// The lints of an entry together, under its key
+    Entry,
+    /// The lints of a rule together, under its name
+    Rule,
+    /// The lints in the order of the linter
+    #[default]
+    None,
+}
+
+/// Lints displayed together (indices in the list of diagnostics),
+/// under the key of their entry or the name of their rule. The
+/// lints that are not about an entry have no heading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintGroup {
+    pub heading: Option<String>,
+    pub lints: Vec<usize>,
+}
+
+/// Groups the diagnostics of a file, given the keys of its entries
+/// with their byte ranges (see `Bibliography::entry_keys`). A lint
+/// about several entries is grouped with the first one.
+pub fn group_lints(
+    diagnostics: &[Diagnostic],
+    entry_keys: &[(Range<usize>, String)],
+    grouping: Grouping,
+) -> Vec<LintGroup> {
+    match grouping {
+        Grouping::None => vec![LintGroup {
+            heading: None,
+            lints: (0..diagnostics.len()).collect(),
+        }],
+        Grouping::Entry => {
+            // the lints of the file first, then the entries in source order
+            let mut file = vec![];
+            let mut entries = vec![vec![]; entry_keys.len()];
+            for (i, d) in diagnostics.iter().enumerate() {
+                let entry = d.loc.first().and_then(|loc| {
+                    entry_keys
+                        .iter()
+                        .position(|(range, _)| range.contains(&loc.start_byte))
+                });
+                match entry {
+                    Some(e) => entries[e].push(i),
+                    None => file.push(i),
+                }
+            }
+            let file = (!file.is_empty()).then_some(LintGroup {
+                heading: None,
+                lints: file,
+            });
+            file.into_iter()
+                .chain(
+                    entry_keys
+                        .iter()
+                        .zip(entries)
+                        .filter(|(_, lints)| !lints.is_empty())
+                        .map(|((_, key), lints)| LintGroup {
+                            heading: Some(key.clone()),
+                            lints,
+                        }),
+                )
+                .collect()
+        }
+        Grouping::Rule => {
+            // the rules in the order of their first lint
+            let mut groups: Vec<LintGroup> = vec![];
+            for (i, d) in diagnostics.iter().enumerate() {
+                let rule = d.msg.rule_name();
+                match groups
+                    .iter_mut()
+                    .find(|g| g.heading.as_deref() == Some(rule))
+                {
+                    Some(group) => group.lints.push(i),
+                    None => groups.push(LintGroup {
+                        heading: Some(rule.to_string()),
+                        lints: vec![i],
+                    }),
+                }
+            }
+            groups
+        }
+    }
+}
+
+/// Keeps the first `max` lints of the groups (all of them when `max`
+/// is 0), and returns the number of lints that were left out.
+pub fn limit_lints(groups: &mut Vec<LintGroup>, max: usize) -> usize {
+    let total = groups.iter().map(|g| g.lints.len()).sum::<usize>();
+    if max == 0 || total <= max {
+        return 0;
+    }
+    let mut left = max;
+    for group in groups.iter_mut() {
+        let kept = left.min(group.lints.len());
+        group.lints.truncate(kept);
+        left -= kept;
+    }
+    groups.retain(|g| !g.lints.is_empty());
+    total - max
+}
+
+/// Removes the diagnostics located in one of the `entries` (byte ranges),
+/// keeping those of the file, and returns the number of removed ones.
+pub fn suppress_entries(diagnostics: &mut Vec<Diagnostic>, entries: &[Range<usize>]) -> usize {
+    let before = diagnostics.len();
+    diagnostics.retain(|d| {
+        d.loc
+            .first()
+            .is_none_or(|loc| !entries.iter().any(|e| e.contains(&loc.start_byte)))
+    });
+    before - diagnostics.len()
+}
+
+/// The version of the shape of the json report written by default.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// The diagnostics of a file in the json report.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReportedFile {
+    pub file: String,
+    pub errors: Vec<ReportedDiagnostic>,
+}
+
+/// A checked file, and the sha256 of its content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct InputHash {
+    pub file: String,
+    pub sha256: String,
+}
+
+/// The json report of `bibadac check --to-json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct JsonReport {
+    /// The version of the shape of the report (see `SCHEMA_VERSION`)
+    #[serde(default = "wrapped_schema_version")]
+    pub schema_version: u32,
+    /// Seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<u64>,
+    /// The version of bibadac
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<InputHash>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    pub files: Vec<ReportedFile>,
+    /// The time spent on every file (`check --timings`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<FileTimings>,
+}
+
+impl JsonReport {
+    /// A report generated now by this version of bibadac.
+    pub fn new(files: Vec<ReportedFile>, inputs: Vec<InputHash>, interrupted: bool) -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        JsonReport {
+            schema_version: SCHEMA_VERSION,
+            generated_at: Some(now.as_secs()),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            inputs,
+            interrupted,
+            files,
+            timings: vec![],
+        }
+    }
+
+    /// Reads a report, also written by older versions.
+    pub fn parse(content: &str) -> Result<Self, serde_json::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum AnyReport {
+            Files(Vec<ReportedFile>),
+            Report(JsonReport),
+        }
+        let mut report: serde_json::Value = serde_json::from_str(content)?;
+        for msg in messages_mut(&mut report) {
+            if let Some(key) = msg
+                .get("DuplicateKey")
+                .filter(|key| key.is_string())
+                .cloned()
+            {
+                msg["DuplicateKey"] = serde_json::json!({ "key": key, "occurrences": [] });
+            }
+        }
+        Ok(match serde_json::from_value(report)? {
+            AnyReport::Files(files) => JsonReport {
+                schema_version: 1,
+                files,
+                ..JsonReport::default()
+            },
+            AnyReport::Report(report) => report,
+        })
+    }
+
+    /// Writes the report in the shape of a schema version: the first
+    /// one is the bare list of files, without the other information.
+    pub fn write<W: std::io::Write>(&self, out: W, schema_version: u32) -> serde_json::Result<()> {
+        let mut report = match schema_version {
+            1 => serde_json::to_value(&self.files)?,
+            _ => serde_json::to_value(self)?,
+        };
+        if schema_version < 3 {
+            legacy_duplicate_keys(&mut report);
+        }
+        if schema_version == 2 {
+            report["schema_version"] = schema_version.into();
+        }
+        serde_json::to_writer_pretty(out, &report)
+    }
+
+    /// The JSON Schema of the report (of the current schema version).
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(JsonReport);
+        serde_json::to_string_pretty(&schema).expect("the schema is valid json")
+    }
+}
+
+/// The messages of the diagnostics of a report, of any version.
+fn messages_mut(report: &mut serde_json::Value) -> Vec<&mut serde_json::Value> {
+    let files = if report.is_array() {
+        report.as_array_mut()
+    } else {
+        report
+            .get_mut("files")
+            .and_then(|files| files.as_array_mut())
+    };
+    files
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.get_mut("errors")?.as_array_mut())
+        .flatten()
+        .filter_map(|error| error.get_mut("msg"))
+        .collect()
+}
+
+/// Writes the `DuplicateKey` messages of a report as the first two
+/// versions did, with the key only.
+fn legacy_duplicate_keys(report: &mut serde_json::Value) {
+    for msg in messages_mut(report) {
+        if let Some(key) = msg.get("DuplicateKey").and_then(|d| d.get("key")).cloned() {
+            msg["DuplicateKey"] = key;
+        }
+    }
+}
+
+/// The entries of a `DuplicateKey` message, on a single line, with the
+/// line of their location when the message has one for each of them:
+/// `1) @article "Robust parsing…" (L120)  2) @misc "Robust…" (L980)`.
+pub fn key_occurrences(occurrences: &[KeyOccurrence], locs: &[Location]) -> String {
+    occurrences
+        .iter()
+        .enumerate()
+        .map(|(i, occurrence)| {
+            let line = match locs.get(i) {
+                Some(loc) if locs.len() == occurrences.len() => format!(" (L{})", loc.line),
+                _ => String::new(),
+            };
+            format!(
+                "{}) @{} \"{}\"{}",
+                i + 1,
+                occurrence.entry_type,
+                occurrence.title,
+                line
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// The version of the reports written before `schema_version`,
+/// with the same shape as the second one.
+fn wrapped_schema_version() -> u32 {
+    2
+}
+
+/// Writes a count with thousands separators (`3,812`).
+pub fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::{Bibliography, LintConfig};
+
+    fn duplicate_key(key: &str) -> LintMessage {
+        LintMessage::DuplicateKey {
+            key: key.into(),
+            occurrences: vec![],
+        }
+    }
+
+    fn diagnostic(msg: LintMessage, start_byte: usize) -> Diagnostic {
+        Diagnostic {
+            msg,
+            loc: vec![Location {
+                line: 1,
+                column: 1,
+                start_byte,
+                end_byte: start_byte + 1,
+            }],
+            severity: None,
+            fixable: false,
+        }
+    }
+
+    fn group(heading: Option<&str>, lints: Vec<usize>) -> LintGroup {
+        LintGroup {
+            heading: heading.map(|h| h.to_string()),
+            lints,
+        }
+    }
+
+    #[test]
+    fn test_group_lints() {
+        let keys = vec![(10..20, "a".to_string()), (20..30, "b".to_string())];
+        let diagnostics = vec![
+            diagnostic(LintMessage::MissingField("title".into()), 25),
+            diagnostic(duplicate_key("a"), 12),
+            diagnostic(LintMessage::SyntaxError("@".into()), 0),
+            diagnostic(LintMessage::MissingField("year".into()), 15),
+        ];
+        assert_eq!(
+            group_lints(&diagnostics, &keys, Grouping::None),
+            vec![group(None, vec![0, 1, 2, 3])]
+        );
+        assert_eq!(
+            group_lints(&diagnostics, &keys, Grouping::Entry),
+            vec![
+                group(None, vec![2]),
+                group(Some("a"), vec![1, 3]),
+                group(Some("b"), vec![0]),
+            ]
+        );
+        assert_eq!(
+            group_lints(&diagnostics, &keys, Grouping::Rule),
+            vec![
+                group(Some("missing-field"), vec![0, 3]),
+                group(Some("duplicate-key"), vec![1]),
+                group(Some("syntax-error"), vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suppress_entries() {
+        let mut diagnostics = vec![
+            diagnostic(LintMessage::MissingField("title".into()), 25),
+            diagnostic(duplicate_key("a"), 12),
+            diagnostic(LintMessage::SyntaxError("@".into()), 0),
+            diagnostic(LintMessage::MissingField("year".into()), 15),
+        ];
+        let suppressed = vec![10..20];
+        assert_eq!(suppress_entries(&mut diagnostics, &suppressed), 2);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| d.loc[0].start_byte)
+                .collect::<Vec<_>>(),
+            vec![25, 0]
+        );
+        assert_eq!(suppress_entries(&mut diagnostics, &[]), 0);
+    }
+
+    #[test]
+    fn test_limit_lints() {
+        let groups = vec![group(Some("a"), vec![0, 1]), group(Some("b"), vec![2, 3])];
+        let mut limited = groups.clone();
+        assert_eq!(limit_lints(&mut limited, 3), 1);
+        assert_eq!(
+            limited,
+            vec![group(Some("a"), vec![0, 1]), group(Some("b"), vec![2])]
+        );
+        let mut limited = groups.clone();
+        assert_eq!(limit_lints(&mut limited, 1), 3);
+        assert_eq!(limited, vec![group(Some("a"), vec![0])]);
+        let mut limited = groups.clone();
+        assert_eq!(limit_lints(&mut limited, 0), 0);
+        assert_eq!(limited, groups);
+    }
+
+    #[test]
+    fn test_thousands() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(812), "812");
+        assert_eq!(thousands(3812), "3,812");
+        assert_eq!(thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_windowed() {
+        let s = "0123456789";
+        assert_eq!(windowed(s, 4, 6, 2), ("23", "45", "67"));
+        assert_eq!(windowed(s, 1, 2, 5), ("0", "1", "23456"));
+        let s = "éééxééé";
+        assert_eq!(windowed(s, 6, 7, 2), ("éé", "x", "éé"));
+        // spans out of the text or inside of a char are moved back
+        assert_eq!(windowed(s, 5, 7, 1), ("é", "éx", "é"));
+        assert_eq!(windowed(s, 20, 30, 2), ("éé", "", ""));
+        assert_eq!(windowed(s, 6, 3, 2), ("éé", "", "xé"));
+    }
+
+    fn parts(e: &Excerpt) -> (&str, &str, &str) {
+        (&e.before, &e.span, &e.after)
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let s = "0123456789";
+        let e = excerpt(s, 4, 6, 20);
+        assert_eq!(parts(&e), ("0123", "45", "6789"));
+        assert!(!e.clipped);
+        // centered on the span
+        assert_eq!(parts(&excerpt(s, 4, 6, 6)), ("…23", "45", "67…"));
+        // at the start or the end, the other side gets the room
+        assert_eq!(parts(&excerpt(s, 0, 1, 5)), ("", "0", "1234…"));
+        assert_eq!(parts(&excerpt(s, 9, 10, 5)), ("…5678", "9", ""));
+        // a span longer than the width is cut at its end
+        assert_eq!(parts(&excerpt(s, 2, 9, 3)), ("…", "234…", "…"));
+        let s = "éééééxééééé";
+        assert_eq!(parts(&excerpt(s, 10, 11, 5)), ("…éé", "x", "éé…"));
+        // no carriage return reaches the terminal
+        assert_eq!(parts(&excerpt("a\r\nb\rc", 3, 4, 20)), ("a\n", "b", "c"));
+        // a stale span does not panic
+        assert_eq!(parts(&excerpt("éa", 1, 40, 20)), ("", "éa", ""));
+        assert_eq!(parts(&excerpt_around("éa", 1, 40, 5, 20)), ("", "éa", ""));
+    }
+
+    #[test]
+    fn test_excerpt_of_long_line() {
+        let line = "x".repeat(2_000_000);
+        let s = format!("{}@article{{key, title = {{T}}}}{}", line, line);
+        let start = s.find('@').unwrap();
+        let e = excerpt(&s, start, start + 8, 40);
+        assert_eq!(e.span, "@article");
+        assert!(e.before.chars().count() + e.span.chars().count() + e.after.chars().count() <= 42);
+
+        let e = excerpt_around(&s, start, start + 8, 20, 30);
+        assert_eq!(parts(&e), ("…xxxxxxxxxxx", "@article", "{key, title…"));
+        assert_eq!(
+            ruler(&e, start + 1).unwrap(),
+            format!("            ^^^^^^^^ column {}", start + 1)
+        );
+        assert_eq!(ruler(&excerpt(&s[..10], 0, 1, 40), 1), None);
+    }
+
+    #[test]
+    fn test_html_report_golden() {
+        let bib = Bibliography::from_path(crate::fixture("check_report.bib")).unwrap();
+        let mut diagnostics = bib
+            .lint(&LintConfig::default())
+            .into_iter()
+            .filter(|d| {
+                matches!(
+                    d.msg.rule_name(),
+                    "missing-field" | "unescaped-special-character"
+                )
+            })
+            .collect::<Vec<_>>();
+        diagnostics.sort_by_key(|d| d.loc[0].start_byte);
+        let rows = diagnostics
+            .iter()
+            .map(|d| ReportRow::of("refs.bib", bib.source(), d, EXCERPT_WIDTH))
+            .collect::<Vec<_>>();
+        let html = html_report(&[("refs.bib", 2), ("other.bib", 0)], &rows);
+        let golden = crate::fixture("check_report.html");
+        if std::env::var("BIBADAC_BLESS").is_ok() {
+            std::fs::write(&golden, &html).unwrap();
+        }
+        assert_eq!(html, std::fs::read_to_string(golden).unwrap());
+    }
+
+    #[test]
+    fn test_parse_json_report() {
+        let errors = r#"[{"msg": "ByteOrderMark", "loc": []}]"#;
+        // the reports of older versions
+        let old = format!(r#"[{{"file": "a.bib", "errors": {}}}]"#, errors);
+        let report = JsonReport::parse(&old).unwrap();
+        assert_eq!(report.schema_version, 1);
+        assert_eq!(report.generated_at, None);
+        assert_eq!(report.files[0].file, "a.bib");
+        assert_eq!(report.files[0].errors.len(), 1);
+        let interrupted = r#"{"interrupted": true, "files": []}"#;
+        assert!(JsonReport::parse(interrupted).unwrap().interrupted);
+        assert_eq!(JsonReport::parse(interrupted).unwrap().schema_version, 2);
+
+        let report = JsonReport::new(
+            report.files,
+            vec![InputHash {
+                file: "a.bib".into(),
+                sha256: "00".into(),
+            }],
+            false,
+        );
+        let written = serde_json::to_string(&report).unwrap();
+        assert!(!written.contains("interrupted"));
+        let read = JsonReport::parse(&written).unwrap();
+        assert_eq!(read.generated_at, report.generated_at);
+        assert_eq!(read.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(read.inputs, report.inputs);
+        assert_eq!(read.files[0].errors.len(), 1);
+        assert_eq!(read.schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let errors = vec![
+            ReportedDiagnostic {
+                diagnostic: diagnostic(LintMessage::MissingField("title".into()), 3),
+                key: Some("a".into()),
+            },
+            ReportedDiagnostic {
+                diagnostic: Diagnostic {
+                    severity: Some(crate::severity::Severity::Warning),
+                    ..diagnostic(LintMessage::FieldTooLong("abstract".into(), 12, 10), 7)
+                },
+                key: None,
+            },
+        ];
+        let report = JsonReport::new(
+            vec![ReportedFile {
+                file: "a.bib".into(),
+                errors,
+            }],
+            vec![],
+            true,
+        );
+        let schema: serde_json::Value = serde_json::from_str(&JsonReport::json_schema()).unwrap();
+        let mut written = vec![];
+        report.write(&mut written, SCHEMA_VERSION).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(written["schema_version"].as_u64(), Some(3));
+        assert!(jsonschema::is_valid(&schema, &written));
+        // a diagnostic of an unknown rule is not
+        let mut unknown = written.clone();
+        unknown["files"][0]["errors"][0]["msg"] = serde_json::Value::String("NoSuchRule".into());
+        assert!(!jsonschema::is_valid(&schema, &unknown));
+
+        // the first version is the bare list of files
+        let mut written = vec![];
+        report.write(&mut written, 1).unwrap();
+        let old = JsonReport::parse(std::str::from_utf8(&written).unwrap()).unwrap();
+        assert_eq!(old.schema_version, 1);
+        assert_eq!(old.files[0].errors.len(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_key_schema_versions() {
+        let occurrences = vec![
+            KeyOccurrence::new("article", "Robust parsing"),
+            KeyOccurrence::new("misc", "Robust parsing (preprint)"),
+        ];
+        let msg = LintMessage::DuplicateKey {
+            key: "smith2020".into(),
+            occurrences,
+        };
+        let report = JsonReport::new(
+            vec![ReportedFile {
+                file: "a.bib".into(),
+                errors: vec![ReportedDiagnostic {
+                    diagnostic: diagnostic(msg, 3),
+                    key: Some("smith2020".into()),
+                }],
+            }],
+            vec![],
+            false,
+        );
+        let schema: serde_json::Value = serde_json::from_str(&JsonReport::json_schema()).unwrap();
+        let mut written = vec![];
+        report.write(&mut written, SCHEMA_VERSION).unwrap();
+        let current: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        assert!(jsonschema::is_valid(&schema, &current));
+        let duplicate = &current["files"][0]["errors"][0]["msg"]["DuplicateKey"];
+        assert_eq!(duplicate["occurrences"][1]["entry_type"], "misc");
+
+        // the older versions only have the key
+        for version in [1, 2] {
+            let mut written = vec![];
+            report.write(&mut written, version).unwrap();
+            let old: serde_json::Value = serde_json::from_slice(&written).unwrap();
+            let files = if version == 1 { &old } else { &old["files"] };
+            assert_eq!(files[0]["errors"][0]["msg"]["DuplicateKey"], "smith2020");
+            if version == 2 {
+                assert_eq!(old["schema_version"], 2);
+            }
+            let read = JsonReport::parse(std::str::from_utf8(&written).unwrap()).unwrap();
+            assert!(matches!(
+                &read.files[0].errors[0].diagnostic.msg,
+                LintMessage::DuplicateKey { key, occurrences } if key == "smith2020" && occurrences.is_empty()
+            ));
+        }
+        let read = JsonReport::parse(std::str::from_utf8(&written).unwrap()).unwrap();
+        assert!(matches!(
+            &read.files[0].errors[0].diagnostic.msg,
+            LintMessage::DuplicateKey { occurrences, .. } if occurrences.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_key_occurrences() {
+        let occurrences = vec![
+            KeyOccurrence::new("article", "Robust parsing"),
+            KeyOccurrence::new("misc", "Robust parsing (preprint)"),
+        ];
+        let loc = |line| Location {
+            line,
+            column: 1,
+            start_byte: 0,
+            end_byte: 1,
+        };
+        assert_eq!(
+            key_occurrences(&occurrences, &[loc(120), loc(980)]),
+            "1) @article \"Robust parsing\" (L120)  2) @misc \"Robust parsing (preprint)\" (L980)"
+        );
+        // the locations of another file are left out (see `includes`)
+        assert_eq!(
+            key_occurrences(&occurrences, &[loc(120)]),
+            "1) @article \"Robust parsing\"  2) @misc \"Robust parsing (preprint)\""
+        );
+    }
+
+    #[test]
+    fn test_timings_in_report() {
+        let mut report = JsonReport::new(vec![], vec![], false);
+        let written = serde_json::to_value(&report).unwrap();
+        assert!(written.get("timings").is_none());
+
+        report.timings.push(FileTimings {
+            file: "a.bib".into(),
+            parse_ms: 1.0,
+            total_ms: 2.0,
+            nodes: 10,
+            entries: 1,
+            ..FileTimings::default()
+        });
+        let schema: serde_json::Value = serde_json::from_str(&JsonReport::json_schema()).unwrap();
+        let written = serde_json::to_value(&report).unwrap();
+        assert!(jsonschema::is_valid(&schema, &written));
+        let timings = &written["timings"][0];
+        assert_eq!(timings["file"], "a.bib");
+        for key in [
+            "parse_ms",
+            "collection_ms",
+            "entry_lints_ms",
+            "file_lints_ms",
+            "total_ms",
+        ] {
+            assert!(timings[key].is_f64(), "{}", key);
+        }
+        assert_eq!(timings["nodes"], 10);
+        assert_eq!(timings["entries"], 1);
+        let read = JsonReport::parse(&written.to_string()).unwrap();
+        assert_eq!(read.timings, report.timings);
+    }
+}
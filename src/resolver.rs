@@ -0,0 +1,158 @@
+//! This file is responsible for the HTTP requests of `bibadac setup`.
+//! The downloaders ask a `Resolver` for the body of a URL, and the
+//! resolver either talks to the network, or reads the responses from
+//! a mirror directory, so that `setup` can run on machines without
+//! internet access.
+//!
+//! A mirror is filled with `--mirror <dir> --mirror-record` on a
+//! connected machine: every successful response is saved under a name
+//! made of the request (e.g. `doi:10.1000/xyz`) and of the hash of the
+//! URL, since a request may need several URLs (a page, then the pdf).
+//! With `--mirror <dir>` alone, the mirror is replayed, and a response
+//! that is not in it is a failed download.
+use std::path::PathBuf;
+use std::time::Instant;
+
+use reqwest::header::HeaderMap;
+use reqwest::Client;
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorMode {
+    /// Save the responses of the network in the mirror
+    Record,
+    /// Read the responses from the mirror instead of the network
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    pub dir: PathBuf,
+    pub mode: MirrorMode,
+}
+
+impl Mirror {
+    pub fn new(dir: PathBuf, mode: MirrorMode) -> Self {
+        Mirror { dir, mode }
+    }
+
+    /// The file holding the response to `url`, fetched for `request`.
+    pub fn path(&self, request: &str, url: &str) -> PathBuf {
+        use sha2::Digest;
+        let name = request
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c.to_ascii_lowercase(),
+                _ => '_',
+            })
+            .collect::<String>();
+        let hash = format!("{:x}", sha2::Sha256::digest(url.as_bytes()));
+        self.dir.join(format!("{}-{}", name, &hash[..16]))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    client: Client,
+    /// Sent on top of the default headers of the client
+    headers: HeaderMap,
+    mirror: Option<Mirror>,
+}
+
+impl Resolver {
+    pub fn new(client: Client) -> Self {
+        Resolver {
+            client,
+            headers: HeaderMap::new(),
+            mirror: None,
+        }
+    }
+
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Whether the responses are saved in a mirror, in which case
+    /// they should not be taken from another cache.
+    pub fn records(&self) -> bool {
+        self.mirror
+            .as_ref()
+            .is_some_and(|m| m.mode == MirrorMode::Record)
+    }
+
+    /// The body of the response to `url`, fetched for `request`.
+    pub async fn get(&self, request: &str, url: &str) -> Option<Vec<u8>> {
+        if let Some(mirror) = &self.mirror
+            && mirror.mode == MirrorMode::Replay
+        {
+            let path = mirror.path(request, url);
+            return match std::fs::read(&path) {
+                Ok(body) => {
+                    debug!(path = %path.display(), "mirror hit");
+                    Some(body)
+                }
+                Err(error) => {
+                    warn!(%url, %error, "the response is not in the mirror");
+                    None
+                }
+            };
+        }
+        let start = Instant::now();
+        let builder = self.client.get(url).headers(self.headers.clone());
+        let response = match builder.send().await {
+            Ok(response) => response,
+            Err(error) if crate::network::is_offline_error(&error) => {
+                debug!(%url, "request skipped (offline)");
+                return None;
+            }
+            Err(error) => {
+                warn!(%error, elapsed = ?start.elapsed(), "request failed");
+                return None;
+            }
+        };
+        debug!(status = %response.status(), elapsed = ?start.elapsed(), "response");
+        let success = response.status().is_success();
+        let body = response.bytes().await.ok()?.to_vec();
+        if let Some(mirror) = &self.mirror
+            && success
+        {
+            let path = mirror.path(request, url);
+            // a mirror that cannot be written only misses this response
+            if let Err(error) =
+                std::fs::create_dir_all(&mirror.dir).and_then(|_| std::fs::write(&path, &body))
+            {
+                warn!(%error, path = %path.display(), "could not write to the mirror");
+            }
+        }
+        Some(body)
+    }
+
+    /// The body of the response to `url`, as text.
+    pub async fn get_text(&self, request: &str, url: &str) -> Option<String> {
+        let body = self.get(request, url).await?;
+        Some(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_path() {
+        let mirror = Mirror::new("mirror".into(), MirrorMode::Record);
+        let path = mirror.path("doi:10.1000/ABC", "https://dx.doi.org/10.1000/ABC");
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("doi_10.1000_abc-"));
+        assert_ne!(
+            path,
+            mirror.path("doi:10.1000/ABC", "https://sci-hub.se/10.1000/ABC")
+        );
+    }
+}
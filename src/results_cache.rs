@@ -0,0 +1,235 @@
+//! This file is responsible for remembering the diagnostics
+//! of `bibadac check` between runs (`--cache-results <path>`),
+//! so that unchanged files are not linted again.
+//!
+//! The cache maps the sha256 of the content of a file to its
+//! diagnostics. It is only valid for the fingerprint it was
+//! written with: the version of bibadac, the rule configuration,
+//! and the content of the helper bibliography (`--file-db`).
+//! A cache with another fingerprint is silently discarded.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use crate::bibliography::{Bibliography, Diagnostic, LintConfig};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResultsCache {
+    fingerprint: String,
+    /// Diagnostics, by hash of the content
+    files: HashMap<String, Vec<Diagnostic>>,
+    /// Hashes of the files linted since loading
+    #[serde(skip)]
+    linted: HashSet<String>,
+}
+
+pub fn content_hash(content: &str) -> String {
+    use sha2::Digest;
+    format!("{:x}", sha2::Sha256::digest(content.as_bytes()))
+}
+
+fn sorted<I: IntoIterator<Item = String>>(items: I) -> String {
+    let mut items = items.into_iter().collect::<Vec<_>>();
+    items.sort();
+    items.join(",")
+}
+
+/// What the diagnostics depend on, besides the content of the file.
+pub fn fingerprint(config: &LintConfig, helper: Option<&Bibliography>) -> String {
+    let parts = [
+        format!("bibadac {}", env!("CARGO_PKG_VERSION")),
+        sorted(
+            config
+                .required_fields
+                .iter()
+                .map(|f| format!("{}:{}", f.name, f.crucial)),
+        ),
+        sorted(
+            config
+                .max_field_length
+                .iter()
+                .map(|(name, max)| format!("{}={}", name, max)),
+        ),
+        sorted(
+            config
+                .arxiv_latest
+                .iter()
+                .map(|(id, v)| format!("{}v{}", id, v)),
+        ),
+        sorted(config.revoked_dois.iter().cloned()),
+        sorted(config.doi_arxiv.iter().map(|(d, a)| format!("{}={}", d, a))),
+        sorted(config.arxiv_doi.iter().map(|(a, d)| format!("{}={}", a, d))),
+        config.concise.to_string(),
+        sorted(config.string_definitions.iter().map(|(name, values)| {
+            format!("{}={}", name, sorted(values.iter().cloned()))
+        })),
+        config
+            .verified_before
+            .map_or(String::new(), |d| d.to_string()),
+        config
+            .conflict_ignored_fields
+            .as_ref()
+            .map_or(String::new(), |fields| sorted(fields.iter().cloned())),
+        config
+            .year_status_words
+            .as_ref()
+            .map_or(String::new(), |words| sorted(words.iter().cloned())),
+        config
+            .dictionary
+            .as_ref()
+            .map_or(String::new(), |words| sorted(words.iter().cloned())),
+        config.strict.to_string(),
+        sorted(config.entry_keys.iter().cloned()),
+        serde_json::to_string(&config.severities).unwrap_or_default(),
+        config.against.as_ref().map_or(String::new(), |against| {
+            sorted(against.iter().map(|(id, key)| format!("{:?}={}", id, key)))
+        }),
+        config.against_invert.to_string(),
+        helper.map_or(String::new(), |h| content_hash(h.source())),
+        config.completion.is_some().to_string(),
+    ];
+    content_hash(&parts.join("\n"))
+}
+
+impl ResultsCache {
+    /// Reads the cache, starting from an empty cache when the file
+    /// is missing, unreadable, or was written with another fingerprint.
+    pub fn load(path: &Path, fingerprint: &str) -> ResultsCache {
+        let cache = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<ResultsCache>(&content).ok())
+            .filter(|cache| cache.fingerprint == fingerprint);
+        cache.unwrap_or_else(|| ResultsCache {
+            fingerprint: fingerprint.to_string(),
+            files: HashMap::new(),
+            linted: HashSet::new(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string(self).map_err(io::Error::other)?)
+    }
+
+    pub fn get(&self, content: &str) -> Option<&Vec<Diagnostic>> {
+        self.files.get(&content_hash(content))
+    }
+
+    /// Lints the bibliography if it is not in the cache, returning
+    /// its diagnostics, and whether they come from the cache.
+    pub fn lint_one(&mut self, bib: &Bibliography, config: &LintConfig) -> (Vec<Diagnostic>, bool) {
+        let hash = content_hash(bib.source());
+        let result = match self.files.get(&hash) {
+            Some(diagnostics) => (diagnostics.clone(), true),
+            None => {
+                let diagnostics = bib.lint(config);
+                self.files.insert(hash.clone(), diagnostics.clone());
+                (diagnostics, false)
+            }
+        };
+        self.linted.insert(hash);
+        result
+    }
+
+    /// Forgets the files that were not linted since loading,
+    /// so that the cache does not grow forever.
+    pub fn prune(&mut self) {
+        self.files.retain(|hash, _| self.linted.contains(hash));
+    }
+
+    /// Lints the bibliographies that are not in the cache, returning
+    /// the diagnostics of every bibliography, and whether they come
+    /// from the cache. Afterwards, the cache only contains the given
+    /// bibliographies.
+    pub fn lint(
+        &mut self,
+        bibs: &[&Bibliography],
+        config: &LintConfig,
+    ) -> Vec<(Vec<Diagnostic>, bool)> {
+        let results = bibs.iter().map(|bib| self.lint_one(bib, config)).collect();
+        self.prune();
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let config = LintConfig::default();
+        let base = fingerprint(&config, None);
+        assert_eq!(base, fingerprint(&LintConfig::default(), None));
+
+        let required = LintConfig {
+            required_fields: vec!["doi".parse().unwrap()],
+            ..LintConfig::default()
+        };
+        assert_ne!(base, fingerprint(&required, None));
+
+        let helper: Bibliography = "@misc{a, eprint = {2101.00001v2}}".parse().unwrap();
+        assert_ne!(base, fingerprint(&config, Some(&helper)));
+    }
+
+    #[test]
+    fn test_load_other_fingerprint() {
+        let dir = crate::temp_dir("results-cache-fingerprint");
+        let path = dir.join("results.json");
+        let mut cache = ResultsCache::load(&path, "one");
+        cache.files.insert(content_hash("@misc{a,}"), vec![]);
+        cache.save(&path).unwrap();
+
+        assert!(ResultsCache::load(&path, "one").get("@misc{a,}").is_some());
+        assert!(ResultsCache::load(&path, "two").get("@misc{a,}").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_only_changed_files_are_linted() {
+        let dir = crate::temp_dir("results-cache-changed");
+        let path = dir.join("results.json");
+        let config = LintConfig::default();
+        let mut bibs: Vec<Bibliography> = ["@misc{a,}", "@misc{b,}", "@misc{c,}"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+
+        let mut cache = ResultsCache::load(&path, "fp");
+        let first = cache.lint(&bibs.iter().collect::<Vec<_>>(), &config);
+        assert!(first.iter().all(|(_, cached)| !cached));
+        cache.save(&path).unwrap();
+
+        bibs[1] = "@misc{b, title = {T}}".parse().unwrap();
+        let mut cache = ResultsCache::load(&path, "fp");
+        let second = cache.lint(&bibs.iter().collect::<Vec<_>>(), &config);
+        let linted = second.iter().filter(|(_, cached)| !cached).count();
+        assert_eq!(linted, 1);
+        assert!(!second[1].1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune() {
+        let config = LintConfig::default();
+        let bibs: Vec<Bibliography> = ["@misc{a,}", "@misc{b,}"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let dir = crate::temp_dir("results-cache-prune");
+        let mut cache = ResultsCache::load(&dir.join("results.json"), "fp");
+        cache.lint(&bibs.iter().collect::<Vec<_>>(), &config);
+
+        // an interrupted run keeps the results of the files left out
+        let mut cache = ResultsCache {
+            linted: HashSet::new(),
+            ..cache
+        };
+        assert!(cache.lint_one(&bibs[0], &config).1);
+        assert!(cache.get("@misc{b,}").is_some());
+        cache.prune();
+        assert!(cache.get("@misc{a,}").is_some());
+        assert!(cache.get("@misc{b,}").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
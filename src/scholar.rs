@@ -0,0 +1,179 @@
+//! This file is responsible for recognising the entries copied
+//! from the BibTeX export of Google Scholar, which have known
+//! defects: no doi, truncated page ranges, abbreviated booktitles
+//! ending with an ellipsis, and `organization = {IEEE}`.
+//!
+//! Every signal is checked on its own, and an entry is only
+//! reported when its key has the shape of the keys of Scholar
+//! (`doe2020robust`, the last name of the first author, the year,
+//! and a word of the title) *and* it has at least two other signals,
+//! so that hand-written entries are left alone.
+use crate::linter::FieldMap;
+use crate::preprints::normalize_title;
+
+/// The number of signals of a likely Scholar export, key included.
+pub const MIN_SIGNALS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScholarSignal {
+    /// `doe2020robust`
+    Key,
+    /// An article or conference paper without doi
+    MissingDoi,
+    /// `1123--45`
+    TruncatedPages,
+    /// `Proceedings of the IEEE Conference on…`
+    AbbreviatedBooktitle,
+    /// `organization = {IEEE}` on a conference paper
+    IeeeOrganization,
+}
+
+impl ScholarSignal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScholarSignal::Key => "key",
+            ScholarSignal::MissingDoi => "missing-doi",
+            ScholarSignal::TruncatedPages => "truncated-pages",
+            ScholarSignal::AbbreviatedBooktitle => "abbreviated-booktitle",
+            ScholarSignal::IeeeOrganization => "ieee-organization",
+        }
+    }
+}
+
+/// Whether a key is lowercase letters, a year, and lowercase letters
+/// that are one of the first words of the title.
+pub fn is_scholar_key(key: &str, title: &str) -> bool {
+    let Some(start) = key.find(|c: char| c.is_ascii_digit()) else {
+        return false;
+    };
+    let (name, rest) = key.split_at(start);
+    let (year, word) = rest.split_at(rest.len().min(4));
+    let lowercase = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase());
+    lowercase(name)
+        && year.chars().all(|c| c.is_ascii_digit())
+        && lowercase(word)
+        && normalize_title(title).split(' ').take(3).any(|w| w == word)
+}
+
+fn is_published(entrytype: &str) -> bool {
+    let entrytype = entrytype.trim_start_matches('@');
+    ["article", "inproceedings", "conference"]
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(entrytype))
+}
+
+/// Whether the end of a page range has lost its first digits.
+pub fn is_truncated_pages(pages: &str) -> bool {
+    let Some((start, end)) = pages.split_once('-') else {
+        return false;
+    };
+    let end = end.trim_start_matches('-');
+    match (start.trim().parse::<u32>(), end.trim().parse::<u32>()) {
+        (Ok(start), Ok(end)) => end < start,
+        _ => false,
+    }
+}
+
+pub fn is_abbreviated_booktitle(booktitle: &str) -> bool {
+    let booktitle = booktitle.trim();
+    booktitle.ends_with('…') || booktitle.ends_with("...")
+}
+
+/// The signals of an entry, given its key, type (`@article`),
+/// and the map of its fields (see `linter::field_map`).
+pub fn scholar_signals(key: &str, entrytype: &str, fields: &FieldMap) -> Vec<ScholarSignal> {
+    let mut signals = vec![];
+    if fields.get("title").is_some_and(|t| is_scholar_key(key, t)) {
+        signals.push(ScholarSignal::Key);
+    }
+    if is_published(entrytype) && !fields.contains_key("doi") {
+        signals.push(ScholarSignal::MissingDoi);
+    }
+    if fields.get("pages").is_some_and(|p| is_truncated_pages(p)) {
+        signals.push(ScholarSignal::TruncatedPages);
+    }
+    if fields
+        .get("booktitle")
+        .is_some_and(|b| is_abbreviated_booktitle(b))
+    {
+        signals.push(ScholarSignal::AbbreviatedBooktitle);
+    }
+    if is_published(entrytype)
+        && fields.contains_key("booktitle")
+        && fields
+            .get("organization")
+            .is_some_and(|o| o.trim().eq_ignore_ascii_case("ieee"))
+    {
+        signals.push(ScholarSignal::IeeeOrganization);
+    }
+    signals
+}
+
+pub fn is_likely_scholar_export(signals: &[ScholarSignal]) -> bool {
+    signals.len() >= MIN_SIGNALS && signals.contains(&ScholarSignal::Key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signals() {
+        assert!(is_scholar_key("doe2020robust", "Robust things"));
+        assert!(is_scholar_key("doe2020robust", "On {R}obust things"));
+        assert!(!is_scholar_key("doe2020robust", "Things that are robust"));
+        assert!(!is_scholar_key("Doe2020robust", "Robust things"));
+        assert!(!is_scholar_key("doe2020", "Robust things"));
+        assert!(!is_scholar_key("doe20robust", "Robust things"));
+
+        assert!(is_truncated_pages("1123--45"));
+        assert!(is_truncated_pages("1123-45"));
+        assert!(!is_truncated_pages("1123--1145"));
+        assert!(!is_truncated_pages("12"));
+        assert!(!is_truncated_pages("e12--e14"));
+
+        assert!(is_abbreviated_booktitle("Proceedings of the IEEE…"));
+        assert!(is_abbreviated_booktitle("Proceedings of the IEEE ..."));
+        assert!(!is_abbreviated_booktitle("Proceedings of LICS"));
+    }
+
+    #[test]
+    fn test_likely_scholar_export() {
+        let scholar: FieldMap = [
+            ("title", "Robust things"),
+            ("booktitle", "2020 IEEE Conference on…"),
+            ("pages", "1123--45"),
+            ("organization", "IEEE"),
+        ]
+        .into_iter()
+        .collect();
+        let signals = scholar_signals("doe2020robust", "@inproceedings", &scholar);
+        assert_eq!(
+            signals,
+            vec![
+                ScholarSignal::Key,
+                ScholarSignal::MissingDoi,
+                ScholarSignal::TruncatedPages,
+                ScholarSignal::AbbreviatedBooktitle,
+                ScholarSignal::IeeeOrganization,
+            ]
+        );
+        assert!(is_likely_scholar_export(&signals));
+
+        // hand-written: the same key, but only the doi is missing
+        let handwritten: FieldMap = [
+            ("title", "Robust things"),
+            ("booktitle", "2020 IEEE Conference on Things"),
+            ("pages", "1123--1145"),
+        ]
+        .into_iter()
+        .collect();
+        let signals = scholar_signals("doe2020robust", "@inproceedings", &handwritten);
+        assert_eq!(signals, vec![ScholarSignal::Key, ScholarSignal::MissingDoi]);
+        assert!(!is_likely_scholar_export(&signals));
+
+        // every defect, but a key chosen by hand
+        let signals = scholar_signals("Doe:2020", "@inproceedings", &scholar);
+        assert!(!is_likely_scholar_export(&signals));
+    }
+}
@@ -0,0 +1,379 @@
+//! This file is responsible for the completeness score of the entries
+//! of `bibadac score`, to decide which entries of a large bibliography
+//! to clean first.
+//!
+//! The score of an entry is the share (from 0 to 100) of the weights
+//! of the signals it has: a doi, an eprint, well-formed authors, a sane
+//! year, a venue, and no error of `bibadac check`. The weights can be
+//! changed in the `[score.weights]` section of the configuration file:
+//!
+//! ```toml
+//! [score.weights]
+//! doi = 5
+//! eprint = 0
+//! ```
+//!
+//! The scores only depend on the entries and their diagnostics, so
+//! that two runs on the same file give the same order.
+use serde::{Deserialize, Serialize};
+
+use crate::bibliography::{Bibliography, Diagnostic, LintConfig};
+use crate::merge::OwnedEntry;
+use crate::values::same_value;
+
+/// The fields that tell where an entry was published.
+const VENUE_FIELDS: &[&str] = &[
+    "journal",
+    "journaltitle",
+    "booktitle",
+    "publisher",
+    "school",
+    "institution",
+];
+
+/// The rules about the year of an entry.
+const YEAR_RULES: &[&str] = &[
+    "invalid-year",
+    "provisional-year",
+    "key-year-mismatch",
+    "date-year-mismatch",
+    "malformed-date",
+];
+
+/// The weight of every signal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoreWeights {
+    pub doi: u32,
+    pub eprint: u32,
+    pub author_format: u32,
+    pub year: u32,
+    pub venue: u32,
+    pub no_errors: u32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            doi: 3,
+            eprint: 1,
+            author_format: 2,
+            year: 2,
+            venue: 2,
+            no_errors: 4,
+        }
+    }
+}
+
+/// The signals of an entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Signals {
+    pub doi: bool,
+    pub eprint: bool,
+    /// No `author-format` message
+    pub author_format: bool,
+    /// A year (or date), and no message about it
+    pub year: bool,
+    pub venue: bool,
+    /// No message of severity error
+    pub no_errors: bool,
+}
+
+impl Signals {
+    /// The signals of an entry, and the diagnostics located in it.
+    pub fn of(entry: &OwnedEntry, diagnostics: &[&Diagnostic]) -> Self {
+        let has = |field: &str| {
+            entry
+                .get(field)
+                .is_some_and(|v| !same_value(field, v, "{}"))
+        };
+        let reported = |rules: &[&str]| {
+            diagnostics
+                .iter()
+                .any(|d| rules.contains(&d.msg.rule_name()))
+        };
+        Signals {
+            doi: has("doi"),
+            eprint: has("eprint"),
+            author_format: !reported(&["author-format"]),
+            year: (has("year") || has("date")) && !reported(YEAR_RULES),
+            venue: VENUE_FIELDS.iter().any(|field| has(field)),
+            no_errors: !diagnostics.iter().any(|d| d.is_crucial()),
+        }
+    }
+
+    /// The signals that are missing, most important first
+    /// (for the default weights).
+    pub fn missing(&self) -> Vec<&'static str> {
+        [
+            (self.no_errors, "errors"),
+            (self.doi, "no doi"),
+            (self.author_format, "author format"),
+            (self.year, "year"),
+            (self.venue, "no venue"),
+            (self.eprint, "no eprint"),
+        ]
+        .into_iter()
+        .filter(|(present, _)| !present)
+        .map(|(_, problem)| problem)
+        .collect()
+    }
+}
+
+impl ScoreWeights {
+    /// The share of the weights of the signals, from 0 to 100
+    /// (100 when all the weights are 0).
+    pub fn score(&self, signals: &Signals) -> u32 {
+        let weighted = [
+            (self.doi, signals.doi),
+            (self.eprint, signals.eprint),
+            (self.author_format, signals.author_format),
+            (self.year, signals.year),
+            (self.venue, signals.venue),
+            (self.no_errors, signals.no_errors),
+        ];
+        let total = weighted.iter().map(|(w, _)| *w as u64).sum::<u64>();
+        if total == 0 {
+            return 100;
+        }
+        let earned = weighted
+            .iter()
+            .filter(|(_, present)| *present)
+            .map(|(w, _)| *w as u64)
+            .sum::<u64>();
+        (earned * 100 / total) as u32
+    }
+}
+
+/// The score of an entry, and its problems.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryScore {
+    pub file: String,
+    pub key: String,
+    pub score: u32,
+    /// The missing signals, then the rules of the
+    /// messages of the entry, errors first
+    pub problems: Vec<String>,
+}
+
+/// The score of the entry of the `file`, with the diagnostics located in it.
+pub fn score_entry(
+    weights: &ScoreWeights,
+    file: &str,
+    entry: &OwnedEntry,
+    diagnostics: &[&Diagnostic],
+) -> EntryScore {
+    let signals = Signals::of(entry, diagnostics);
+    let mut problems = signals
+        .missing()
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let mut rules = diagnostics.to_vec();
+    rules.sort_by_key(|d| !d.is_crucial());
+    for rule in rules.iter().map(|d| d.msg.rule_name()) {
+        if !problems.iter().any(|p| p == rule) {
+            problems.push(rule.to_string());
+        }
+    }
+    EntryScore {
+        file: file.to_string(),
+        key: entry.key.clone(),
+        score: weights.score(&signals),
+        problems,
+    }
+}
+
+/// The scores of the entries of a file (named `file`), linted with `config`.
+pub fn score_file(
+    weights: &ScoreWeights,
+    file: &str,
+    bibliography: &Bibliography,
+    config: &LintConfig,
+) -> Vec<EntryScore> {
+    let diagnostics = bibliography.lint(config);
+    let ranges = bibliography.entry_keys();
+    bibliography
+        .entries()
+        .iter()
+        .zip(ranges)
+        .map(|(entry, (range, _))| {
+            let located = diagnostics
+                .iter()
+                .filter(|d| d.loc.first().is_some_and(|l| range.contains(&l.start_byte)))
+                .collect::<Vec<_>>();
+            score_entry(weights, file, entry, &located)
+        })
+        .collect()
+}
+
+/// Sorts the scores, the lowest first, then by key and file.
+pub fn sort_scores(scores: &mut [EntryScore]) {
+    scores.sort_by(|a, b| (a.score, &a.key, &a.file).cmp(&(b.score, &b.key, &b.file)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::Location;
+    use crate::linter::LintMessage;
+
+    fn entry(fields: &[(&str, &str)]) -> OwnedEntry {
+        OwnedEntry {
+            key: "k".to_string(),
+            entrytype: "article".to_string(),
+            fields: fields
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            annotations: vec![],
+        }
+    }
+
+    fn diagnostic(msg: LintMessage) -> Diagnostic {
+        Diagnostic {
+            msg,
+            loc: vec![Location {
+                line: 1,
+                column: 1,
+                start_byte: 0,
+                end_byte: 1,
+            }],
+            severity: None,
+            fixable: false,
+        }
+    }
+
+    #[test]
+    fn test_signals() {
+        let complete = entry(&[
+            ("doi", "{10.1/x}"),
+            ("eprint", "{2101.00001}"),
+            ("year", "2020"),
+            ("journal", "{J}"),
+        ]);
+        let all = Signals {
+            doi: true,
+            eprint: true,
+            author_format: true,
+            year: true,
+            venue: true,
+            no_errors: true,
+        };
+        assert_eq!(Signals::of(&complete, &[]), all);
+        assert!(all.missing().is_empty());
+
+        let year = diagnostic(LintMessage::InvalidYear("20x4".to_string()));
+        let authors = diagnostic(LintMessage::AuthorFormat);
+        let signals = Signals::of(&entry(&[("doi", "{}")]), &[&year, &authors]);
+        assert!(!signals.doi && !signals.year && !signals.author_format && !signals.venue);
+        assert_eq!(
+            signals.missing(),
+            ["no doi", "author format", "year", "no venue", "no eprint"]
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        let weights = ScoreWeights::default();
+        let all = Signals {
+            doi: true,
+            eprint: true,
+            author_format: true,
+            year: true,
+            venue: true,
+            no_errors: true,
+        };
+        assert_eq!(weights.score(&all), 100);
+        assert_eq!(weights.score(&Signals::default()), 0);
+        // 11 of the 14 points
+        let no_doi = Signals { doi: false, ..all };
+        assert_eq!(weights.score(&no_doi), 78);
+        let only_doi = ScoreWeights {
+            doi: 1,
+            eprint: 0,
+            author_format: 0,
+            year: 0,
+            venue: 0,
+            no_errors: 0,
+        };
+        assert_eq!(only_doi.score(&no_doi), 0);
+        let none = ScoreWeights { doi: 0, ..only_doi };
+        assert_eq!(none.score(&Signals::default()), 100);
+    }
+
+    #[test]
+    fn test_score_entry() {
+        let weights = ScoreWeights::default();
+        let missing = diagnostic(LintMessage::MissingField("title".to_string()));
+        let authors = diagnostic(LintMessage::AuthorFormat);
+        let entry = entry(&[("year", "2020"), ("journal", "{J}")]);
+        let score = score_entry(&weights, "refs.bib", &entry, &[&authors, &missing]);
+        // 4 of the 14 points
+        assert_eq!(score.score, 28);
+        assert_eq!(
+            score.problems,
+            [
+                "errors",
+                "no doi",
+                "author format",
+                "no eprint",
+                "missing-field",
+                "author-format"
+            ]
+        );
+        let mut scores = vec![
+            EntryScore {
+                file: "refs.bib".to_string(),
+                key: "b".to_string(),
+                score: 50,
+                problems: vec![],
+            },
+            EntryScore {
+                file: "refs.bib".to_string(),
+                key: "c".to_string(),
+                score: 10,
+                problems: vec![],
+            },
+            EntryScore {
+                file: "refs.bib".to_string(),
+                key: "a".to_string(),
+                score: 50,
+                problems: vec![],
+            },
+        ];
+        sort_scores(&mut scores);
+        let keys = scores.iter().map(|s| s.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_score_file() {
+        let bibliography = Bibliography::from(
+            "@article{good, author = {Doe, Jane}, title = {T}, journal = {J}, year = 2020, \
+             doi = {10.1/x}, eprint = {2101.00001}}\n\
+             @misc{bad, title = {T}, year = 20x4}\n"
+                .to_string(),
+        );
+        let mut scores = score_file(
+            &ScoreWeights::default(),
+            "refs.bib",
+            &bibliography,
+            &LintConfig::default(),
+        );
+        sort_scores(&mut scores);
+        let keys = scores.iter().map(|s| s.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["bad", "good"]);
+        assert_eq!(scores[1].score, 100);
+        assert!(scores[0].problems.contains(&"invalid-year".to_string()));
+    }
+
+    #[test]
+    fn test_weights_from_config() {
+        let weights: ScoreWeights = toml::from_str("doi = 5\neprint = 0\n").unwrap();
+        assert_eq!(weights.doi, 5);
+        assert_eq!(weights.eprint, 0);
+        assert_eq!(weights.venue, ScoreWeights::default().venue);
+        assert!(toml::from_str::<ScoreWeights>("dio = 5\n").is_err());
+    }
+}
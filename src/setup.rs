@@ -4,7 +4,7 @@
 
 
 /// TODO: change the way we handle downloads,
-/// the "trait" is useless (we use one client per request)
+/// the "trait" is useless
 /// -> we should be directly taking &str as input
 /// -> we should be returning 
 ///     - the corresponding bibentry 
@@ -14,12 +14,28 @@
 ///     - list dois that failed to download
 ///     - list pdfs that failed to download
 
-use colored::Colorize;
+use crate::arxiv_feed::{latest_version, parse_feed};
 use crate::arxiv_identifiers::ArxivId;
-use reqwest::Client;
+use crate::resolver::{Mirror, Resolver};
 use std::sync::OnceLock;
 use std::collections::{HashMap,HashSet};
 use crate::bibtex::BibFile;
+use crate::cache::Cache;
+use crate::datacite;
+use crate::registration::{Agency, AgencyLookup};
+use crate::eprints::EprintType;
+use crate::file_index::FileIndex;
+use crate::logging::PROGRESS;
+use crate::manifest::{ItemState, Manifest};
+use crate::online::{Budget, Identifier, IdentifierKind};
+use crate::collation::collation_key;
+use crate::dates::{Date, YearValue};
+use crate::doi_identifiers::normalize_doi;
+use crate::merge::OwnedEntry;
+use crate::preprints::{first_author, normalize_title};
+use crate::progress::{ItemFinished, ItemKind, ItemStarted, ItemStatus, Progress, ProgressEvent, RunFinished, RunStarted, PROGRESS_SCHEMA_VERSION};
+use std::time::Instant;
+use tracing::{debug, info, info_span, warn, Instrument};
 
 // typical url
 // type="application/pdf" src="//zero.sci-hub.se/407/de27ca7d3dc4c4fddd8bac961171940d/kirsten2002.pdf#
@@ -40,11 +56,168 @@ pub struct SetupConfig {
     pub arxiv_to_sha256: HashMap<String, String>,
     pub doi_to_sha256: HashMap<String, String>,
     // option flags
-    pub progress: bool,
     pub download_pdf: bool,
     pub dry_run: bool, 
     pub working_directory: std::path::PathBuf,
     pub polite_email: Option<String>,
+    /// The `User-Agent` of every request, instead of the default one
+    pub user_agent: Option<String>,
+    /// Timeout of every request (none by default)
+    pub timeout: Option<std::time::Duration>,
+    /// Proxy of every request, e.g. `http://proxy:8080`
+    pub proxy: Option<String>,
+    pub cache: Option<Cache>,
+    pub mirror: Option<Mirror>,
+    pub layout: Layout,
+    /// Skip the identifiers done in the manifest of the working directory
+    pub resume: bool,
+    /// The pdfs of the working directory, when downloading them
+    pub file_index: Option<FileIndex>,
+    /// Note the version of the arXiv entries and the day they were fetched
+    pub arxiv_note: bool,
+    /// Where the progress events go, on top of the progress on stderr
+    pub progress: Progress,
+    /// The identifiers fetched (`--online-only` and `--max-requests`)
+    pub budget: Budget,
+    /// Refuse every request, as in the offline mode (see `network`)
+    pub offline: bool,
+}
+
+/// How the pdfs are arranged in the working directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// All in the working directory
+    #[default]
+    Flat,
+    /// `2023/`
+    ByYear,
+    /// `knuth/`, the last name of the first author
+    ByAuthor,
+    /// `article/`
+    ByType,
+}
+
+/// The `User-Agent` of the requests.
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The `User-Agent` of the requests of bibadac (see `SetupConfig::user_agent`),
+/// also used by `check --online`.
+pub fn user_agent(custom: Option<&str>, polite_email: Option<&str>) -> Result<String, String> {
+    let user_agent = match (custom, polite_email) {
+        (Some(user_agent), _) => user_agent.to_string(),
+        (None, Some(email)) => {
+            validate_email(email)?;
+            format!("{} (mailto:{})", USER_AGENT, email)
+        }
+        (None, None) => USER_AGENT.to_string(),
+    };
+    reqwest::header::HeaderValue::from_str(&user_agent)
+        .map_err(|_| format!("Invalid user agent {:?}", user_agent))?;
+    Ok(user_agent)
+}
+
+/// Whether an email looks like `local@domain.tld`, without spaces
+/// or the characters that would break a header or the `User-Agent`.
+pub fn validate_email(email: &str) -> Result<(), String> {
+    let invalid = |reason: &str| Err(format!("Invalid polite email {:?}: {}", email, reason));
+    if email.chars().any(|c| c.is_whitespace() || c.is_control() || "()<>\\\",;".contains(c)) {
+        return invalid("it contains spaces or special characters");
+    }
+    let Some((local, domain)) = email.split_once('@') else {
+        return invalid("it has no `@`");
+    };
+    if local.is_empty() || domain.contains('@') {
+        return invalid("it should be of the form `name@example.org`");
+    }
+    let labels = domain.split('.').collect::<Vec<_>>();
+    if labels.len() < 2 || labels.iter().any(|label| label.is_empty()) {
+        return invalid("its domain should be of the form `example.org`");
+    }
+    Ok(())
+}
+
+/// What a request asks for, which decides the headers that
+/// are sent on top of the default headers of the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// The BibTeX entry of a doi (content negotiation of dx.doi.org)
+    Metadata,
+    /// A search in the CrossRef API (JSON)
+    Search,
+    /// The arXiv API, a sci-hub page, or a pdf
+    Document,
+}
+
+impl RequestKind {
+    /// The headers of the request that override the default headers.
+    pub fn headers(&self) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+        match self {
+            RequestKind::Metadata => {
+                headers.insert(
+                    reqwest::header::ACCEPT,
+                    reqwest::header::HeaderValue::from_static("application/x-bibtex"),
+                );
+            }
+            // the defaults of the client
+            RequestKind::Search | RequestKind::Document => {}
+        }
+        headers
+    }
+}
+
+/// The directory of the pdfs whose entry lacks the fields of the layout.
+pub const UNSORTED_DIR: &str = "unsorted";
+
+impl Layout {
+    /// The subdirectory of the working directory where the pdf
+    /// of an entry (when it was resolved) is written.
+    pub fn directory(&self, entry: Option<&str>) -> std::path::PathBuf {
+        if *self == Layout::Flat {
+            return std::path::PathBuf::new();
+        }
+        let name = entry.and_then(|entry| {
+            let bib = BibFile::new(entry);
+            let entry = bib.list_entries().next()?;
+            let field = |name: &str| {
+                entry.fields.iter()
+                    .find(|f| bib.get_slice(f.name).eq_ignore_ascii_case(name))
+                    .map(|f| bib.get_braceless_slice(f.value).trim())
+            };
+            match self {
+                Layout::Flat => None,
+                Layout::ByYear => match YearValue::parse(field("year")?) {
+                    YearValue::Exact(year) | YearValue::Range(_, year) => Some(year.to_string()),
+                    _ => None,
+                },
+                Layout::ByAuthor => first_author(&collation_key(field("author")?)),
+                Layout::ByType => Some(bib.get_slice(entry.entrytype).trim_start_matches('@').to_string()),
+            }
+        });
+        let name = name.map(|name| sanitize(&name)).filter(|name| !name.is_empty());
+        std::path::PathBuf::from(name.unwrap_or_else(|| UNSORTED_DIR.to_string()))
+    }
+}
+
+/// A file or directory name: lowercase, without separators
+/// nor dots (so that `..` cannot escape the working directory).
+pub fn sanitize(name: &str) -> String {
+    name.to_ascii_lowercase()
+        .replace(" ", "_")
+        .replace("(", "_")
+        .replace(")", "_")
+        .replace("/", "_")
+        .replace("\\", "_")
+        .replace(":", "_")
+        .replace("?", "_")
+        .replace("=", "_")
+        .replace("&", "_")
+        .replace("'", "_")
+        .replace("{", "_")
+        .replace("}", "_")
+        .replace(",", "_")
+        .replace("\"", "_")
+        .replace(".", "_")
 }
 
 #[derive(Debug)]
@@ -54,10 +227,40 @@ pub struct PdfResult {
     pub entry    : String,
 }
 
+/// An entry whose metadata is fetched again (see `SetupConfig::refetch`),
+/// by its doi, or else by its title.
+#[derive(Debug, Clone)]
+pub struct RefetchRequest {
+    pub key: String,
+    pub doi: Option<String>,
+    pub title: Option<String>,
+}
+
+impl RefetchRequest {
+    /// The request of an entry (see `Bibliography::entries`), whose
+    /// values keep their delimiters: the doi is normalized, and the
+    /// title loses its outer braces or quotes.
+    pub fn of_entry(entry: &OwnedEntry) -> Self {
+        let bare = |value: &str| {
+            let delimited = value.len() >= 2
+                && ((value.starts_with('{') && value.ends_with('}'))
+                    || (value.starts_with('"') && value.ends_with('"')));
+            if delimited { value[1..value.len() - 1].to_string() } else { value.to_string() }
+        };
+        RefetchRequest {
+            key  : entry.key.clone(),
+            doi  : entry.get("doi").map(bare).as_deref().and_then(normalize_doi),
+            title: entry.get("title").map(bare),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SetupResult {
     pub pdfs   : Vec<(String,Option<PdfResult>)>,
     pub entries: Vec<(String,Option<String>)>,
+    /// The identifiers not fetched for lack of budget
+    pub exhausted: Vec<Identifier>,
 }
 
 impl SetupConfig {
@@ -68,30 +271,64 @@ impl SetupConfig {
     pub fn already_present(&self, request: &DownloadRequest) -> bool {
         // Tries to see if the corresponding pdf is already present
         // 1. matches the request to a sha256
-        // 2 TODO: checks if the sha256 *really exists*
-        match request {
-            DownloadRequest::Arxiv(id) => {
-                if let Some(_) = self.arxiv_to_sha256.get(id.id) {
-                    true
-                } else {
-                    false
-                }
-            }
-            DownloadRequest::Doi(doi) => {
-                if let Some(_) = self.doi_to_sha256.get(*doi) {
-                    true
-                } else {
-                    false
-                }
-            }
-            _ => false,
+        // 2. checks that a file of the working directory has this sha256
+        let sha256 = match request {
+            DownloadRequest::Arxiv(id) => self.arxiv_to_sha256.get(id.id),
+            DownloadRequest::Doi(doi) => self.doi_to_sha256.get(*doi),
+            _ => None,
+        };
+        match (sha256, &self.file_index) {
+            (Some(sha256), Some(index)) => index.contains_sha256(sha256),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// The headers of every request: the polite `Mailto`, if any.
+    pub fn default_headers(&self) -> Result<reqwest::header::HeaderMap, String> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(email) = &self.polite_email {
+            validate_email(email)?;
+            let value = reqwest::header::HeaderValue::from_str(email)
+                .map_err(|_| format!("Invalid polite email {:?}", email))?;
+            headers.insert("Mailto", value);
         }
+        Ok(headers)
+    }
+
+    /// The `User-Agent` of every request: the one given by the user, or
+    /// `bibadac/<version> (mailto:<email>)` with the polite email, so that
+    /// CrossRef and arXiv know whom to contact about the requests.
+    pub fn user_agent(&self) -> Result<String, String> {
+        user_agent(self.user_agent.as_deref(), self.polite_email.as_deref())
+    }
+
+    /// The client shared by all the downloaders, so that they share
+    /// their connections, their headers, their timeout, and their proxy.
+    /// In offline mode, it refuses every request (see `network`).
+    pub fn client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = crate::network::offline_client_builder(self.offline)
+            .user_agent(self.user_agent()?)
+            .default_headers(self.default_headers()?);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy.as_str())
+                .map_err(|e| format!("Invalid proxy {:?}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+        builder
+            .build()
+            .map_err(|e| format!("Could not build the http client: {}", e))
     }
 
     pub fn import_bibfile(&mut self, path: &std::path::PathBuf) {
         let start_bib = std::fs::read_to_string(path).expect("Could not read the output bibfile");
         let bibtex = BibFile::new(&start_bib);
         for entry in bibtex.list_entries() {
+            // only arXiv eprints can be downloaded
+            let is_arxiv = EprintType::of_entry(&bibtex, &entry) == EprintType::Arxiv;
             let mut doi = None;
             let mut eprint = None;
             let mut sha256 = None;
@@ -100,7 +337,7 @@ impl SetupConfig {
                 let value = bibtex.get_braceless_slice(field.value);
                 match key.as_str() {
                     "doi" => { doi = Some(value.to_string()); self.existing_doi.insert(value.to_string()); }
-                    "eprint" => { eprint = Some(value.to_string()); self.existing_arxiv.insert(value.to_string()); }
+                    "eprint" if is_arxiv => { eprint = Some(value.to_string()); self.existing_arxiv.insert(value.to_string()); }
                     "sha256" => { sha256 = Some(value.to_string()); self.existing_sha256.insert(value.to_string()); }
                     _ => {}
                 }
@@ -114,104 +351,304 @@ impl SetupConfig {
         }
     }
 
+    /// The pdfs to download: those of the identifiers,
+    /// but the ones already present.
+    pub fn pdf_requests<'a>(&self, dois   : &'a HashSet<String>,
+                                   eprints: &'a HashSet<String>) -> Vec<DownloadRequest<'a>> {
+        dois.iter()
+            .map(|d| DownloadRequest::Doi(d))
+            .chain(eprints.iter()
+                .filter_map(|d| {
+                Some(DownloadRequest::Arxiv(ArxivId::try_from(d.as_str()).ok()?))
+            }))
+            .filter(|r| !self.already_present(r))
+            .collect()
+    }
+
+    /// The url of the pdf of every identifier to download,
+    /// without downloading anything (`setup --print-urls`).
+    pub async fn pdf_urls(&self, dois   : &HashSet<String>,
+                                 eprints: &HashSet<String>) -> Vec<(String, Option<String>)> {
+        use futures::stream::{self, StreamExt};
+
+        let client = self.client().expect("Could not build http client");
+        let pdf_downloader = PdfDownloader::new(&client, self.working_directory.clone())
+            .with_mirror(self.mirror.clone());
+        let mut pdf_requests = self.pdf_requests(dois, eprints);
+        pdf_requests.sort_by_key(|r| r.to_string());
+        let urls : Vec<Option<String>> = stream::iter(pdf_requests.iter().map(|r| pdf_downloader.pdf_url(r)))
+            .buffered(5)
+            .collect()
+            .await;
+        pdf_requests.iter()
+            .map(|r| r.to_string())
+            .zip(urls)
+            .collect()
+    }
+
+    /// The dois and the eprints within the budget, and the identifiers left
+    /// out for lack of budget. The identifiers already in the output are
+    /// not fetched, hence not counted.
+    pub fn within_budget(&self, dois: HashSet<String>, eprints: HashSet<String>)
+        -> (HashSet<String>, HashSet<String>, Vec<Identifier>) {
+        let identifier = |kind, value: &String| Identifier { kind, value: value.clone() };
+        let mut identifiers = dois.iter()
+            .filter(|d| !self.existing_doi.contains(*d))
+            .map(|d| identifier(IdentifierKind::Doi, d))
+            .chain(eprints.iter()
+                .filter(|e| !self.existing_arxiv.contains(*e))
+                .map(|e| identifier(IdentifierKind::Arxiv, e)))
+            .collect::<Vec<_>>();
+        // the sets have no order
+        identifiers.sort_by_cached_key(Identifier::cache_key);
+        let plan = self.budget.plan(&identifiers, self.cache.as_ref());
+        let skipped = plan.exhausted.iter().chain(plan.out_of_scope.iter())
+            .map(|&i| &identifiers[i])
+            .collect::<HashSet<_>>();
+        let dois = dois.iter()
+            .filter(|d| !skipped.contains(&identifier(IdentifierKind::Doi, d)))
+            .cloned().collect();
+        let eprints = eprints.iter()
+            .filter(|e| !skipped.contains(&identifier(IdentifierKind::Arxiv, e)))
+            .cloned().collect();
+        let exhausted = plan.exhausted.iter().map(|&i| identifiers[i].clone()).collect();
+        (dois, eprints, exhausted)
+    }
+
+    /// Fetches the entries (and the pdfs) of the identifiers, recording
+    /// their state in the manifest of the working directory as the results
+    /// arrive. With `resume`, the identifiers that the manifest has are not
+    /// fetched again (see the `manifest` module).
     pub async fn run(&self, dois    : HashSet<String>,
                             eprints : HashSet<String>,
                             _sha256s: HashSet<String>) -> SetupResult {
 
         use futures::stream::{self, StreamExt};
 
+        // the identifiers beyond the budget are not fetched (see the `online` module)
+        let (dois, eprints, exhausted) = self.within_budget(dois, eprints);
+
+        let started = Instant::now();
+        let progress = &self.progress;
+        let elapsed_ms = |since: Instant| since.elapsed().as_secs_f64() * 1000.0;
+
+        // the requests, along with the identifier they are made from
         let doi_requests: Vec<_> = dois.iter()
             .filter(|d| !self.existing_doi.contains(*d))
-            .map(|d| DownloadRequest::Doi(d)).collect();
+            .map(|d| (d, DownloadRequest::Doi(d))).collect();
 
         let arxiv_requests : Vec<_> = eprints.iter()
             .filter(|d| !self.existing_arxiv.contains(*d))
             .filter_map(|d| {
-                Some(DownloadRequest::Arxiv(ArxivId::try_from(d.as_str()).ok()?))
+                Some((d, DownloadRequest::Arxiv(ArxivId::try_from(d.as_str()).ok()?)))
             }).collect();
 
-        let pdf_requests: Vec<_> = dois
-            .iter()
-            .map(|d| DownloadRequest::Doi(d))
-            .chain(eprints.iter()
-                .filter_map(|d| {
-                Some(DownloadRequest::Arxiv(ArxivId::try_from(d.as_str()).ok()?))
-            }))
-            .filter(|r| !self.already_present(r))
-            .collect();
-
-        let doi_downloader = DxDoiDownloader::new(self.polite_email.clone());
-        let epr_downloader = ArxivDownloader::new();
-        let pdf_downloader = PdfDownloader::new(self.working_directory.clone());
-
-        if self.progress {
-            println!("{:<10}\t{} dois / {} eprints / {} pdfs", 
-                     "[TOTAL]".blue(), 
-                     doi_requests.len(),
-                     arxiv_requests.len(),
-                     pdf_requests.len());
+        let pdf_requests = self.pdf_requests(&dois, &eprints);
+
+        let client = self.client().expect("Could not build http client");
+        let doi_downloader = DxDoiDownloader::new(&client)
+            .with_cache(self.cache.clone())
+            .with_mirror(self.mirror.clone());
+        let epr_downloader = ArxivDownloader::new(&client)
+            .with_mirror(self.mirror.clone())
+            .with_note(self.arxiv_note);
+        let pdf_downloader = PdfDownloader::new(&client, self.working_directory.clone())
+            .with_mirror(self.mirror.clone())
+            .with_layout(self.layout);
+
+        let mut manifest = if self.resume {
+            Manifest::load(&self.working_directory)
+        } else {
+            Manifest::new(&self.working_directory)
+        };
+        let mut requests = doi_requests.iter().chain(arxiv_requests.iter())
+            .map(|(_, r)| r.to_string())
+            .collect::<Vec<_>>();
+        if self.download_pdf {
+            requests.extend(pdf_requests.iter().map(|r| r.to_string()));
+        }
+        manifest.reconcile(requests.iter().map(String::as_str));
+        let previous = manifest.clone();
+        if self.resume {
+            info!(target: PROGRESS, "{:<10}\t{} / {} already done",
+                  "[RESUME]",
+                  previous.done(self.download_pdf),
+                  requests.iter().collect::<HashSet<_>>().len());
         }
+        // a manifest that cannot be written only prevents resuming the run
+        let mut manifest = Some(manifest);
+        let mut record = |request: &str, state: ItemState| {
+            if let Some(m) = &mut manifest && let Err(error) = m.record(request, state) {
+                warn!(%error, "could not write the manifest, the run cannot be resumed");
+                manifest = None;
+            }
+        };
+
+        progress.emit(ProgressEvent::RunStarted(RunStarted {
+            schema_version: PROGRESS_SCHEMA_VERSION,
+            dois: doi_requests.len(),
+            eprints: arxiv_requests.len(),
+            pdfs: if self.download_pdf { pdf_requests.len() } else { 0 },
+        }));
 
         let mut res = vec![];
+        // the entries fetched, by request
+        let mut entries: HashMap<String, String> = HashMap::new();
 
-        let res_doi  = doi_downloader.download(&doi_requests, |url| {
-            if self.progress {
-                println!("{:<10}\t{}", "[BIBTEX]".green(),  url);
-            }
-        }).await;
+        // the failed identifiers of a resumed run are retried
+        let attempt = |key: &str| previous.get(key).map_or(1, ItemState::attempt);
 
-        let res_eprint = epr_downloader.download(&arxiv_requests, |url| {
-            if self.progress {
-                println!("{:<10}\t{}", "[BIBTEX]".green(),  url);
+        let mut fetched = stream::iter(doi_requests.iter().chain(arxiv_requests.iter()).map(|(d, r)| {
+            let done = previous.get(&r.to_string()).and_then(ItemState::entry);
+            let span = info_span!("attempt", attempt = attempt(&r.to_string()));
+            let (doi_downloader, epr_downloader) = (&doi_downloader, &epr_downloader);
+            async move {
+                let item = Instant::now();
+                if done.is_none() {
+                    progress.emit(ProgressEvent::ItemStarted(ItemStarted { item: r.to_string(), kind: ItemKind::Bibtex }));
+                }
+                let entry = match (done, r) {
+                    (Some(entry), _) => Some(entry.to_string()),
+                    (None, DownloadRequest::Doi(_)) => doi_downloader.download_one(r).instrument(span).await,
+                    (None, _) => epr_downloader.download_one(r).instrument(span).await,
+                };
+                (*d, r, entry, done.is_some(), item)
+            }
+        }))
+        .buffer_unordered(5);
+        while let Some((identifier, request, entry, reused, item)) = fetched.next().await {
+            let key = request.to_string();
+            let status = match (&entry, reused) {
+                (_, true) => ItemStatus::Reused,
+                (Some(_), false) => ItemStatus::Done,
+                (None, false) => ItemStatus::Failed,
+            };
+            progress.emit(ProgressEvent::ItemFinished(ItemFinished {
+                item: key.clone(), kind: ItemKind::Bibtex, status, elapsed_ms: elapsed_ms(item),
+            }));
+            if !reused {
+                record(&key, match &entry {
+                    Some(entry) => ItemState::MetadataDone { entry: entry.clone() },
+                    None => ItemState::Failed { reason: "no bibtex entry found".to_string(), entry: None, attempts: attempt(&key) },
+                });
+            }
+            if let Some(entry) = &entry {
+                entries.insert(key, entry.clone());
             }
-        }).await;
-        res.extend(res_doi);
-        res.extend(res_eprint);
+            res.push((identifier.clone(), entry));
+        }
+        res.sort();
 
-        let count = res.iter().filter(|r| r.is_some()).count();
+        let count = res.iter().filter(|(_, r)| r.is_some()).count();
 
-        if self.progress {
-            println!("{:<10}\t{} / {} entries retrieved", "[TOTAL BIB]".blue(), count, dois.len() + eprints.len());
-        }
+        info!(target: PROGRESS, "{:<10}\t{} / {} entries retrieved", "[TOTAL BIB]", count, dois.len() + eprints.len());
 
         if !self.download_pdf {
-            return SetupResult { pdfs: vec![], 
-                entries: res.into_iter()
-                    .zip(dois.iter().chain(eprints.iter()))
-                    .map(|(r, d)| (d.clone(), r))
-                    .collect() };
+            progress.emit(ProgressEvent::RunFinished(RunFinished {
+                entries: count, entries_total: res.len(), pdfs: 0, pdfs_total: 0, elapsed_ms: elapsed_ms(started),
+            }));
+            return SetupResult { pdfs: vec![], entries: res, exhausted };
         }
-        
-        // FIXME: avoid duplicate downloads
-        // by checking if the pdfs already are present in the current 
-        // working directory
-
-        let pdfs : Vec<Option<PdfResult>> = stream::iter(pdf_requests.iter().map(|r| {
-                if self.progress {
-                    println!("{:<10}\t{}", "[PDF]".green(), r);
+
+        let mut pdfs = vec![];
+        let mut downloads = stream::iter(pdf_requests.iter().map(|r| {
+            let key = r.to_string();
+            let done = match previous.get(&key) {
+                // the pdf may have been removed since
+                Some(ItemState::PdfDone { path, sha256, mapping, .. }) if path.exists() => {
+                    Some(PdfResult { filepath: path.clone(), sha256: sha256.clone(), entry: mapping.clone() })
                 }
-                pdf_downloader.download_one_pdf(r)
-            }))
-            .buffer_unordered(5)
-            .collect()
-            .await;
+                _ => None,
+            };
+            // the layout of the pdfs depends on the entries of their doi or eprint
+            let entry = entries.get(&key).map(String::as_str);
+            let span = info_span!("attempt", attempt = attempt(&key));
+            let pdf_downloader = &pdf_downloader;
+            async move {
+                let item = Instant::now();
+                let reused = done.is_some();
+                let pdf = match done {
+                    Some(pdf) => Some(pdf),
+                    None => {
+                        progress.emit(ProgressEvent::ItemStarted(ItemStarted { item: key.clone(), kind: ItemKind::Pdf }));
+                        pdf_downloader.download_one_pdf(r, entry).instrument(span).await
+                    }
+                };
+                (key, pdf, reused, item)
+            }
+        }))
+        .buffer_unordered(5);
+        while let Some((key, pdf, reused, item)) = downloads.next().await {
+            let status = match (&pdf, reused) {
+                (_, true) => ItemStatus::Reused,
+                (Some(_), false) => ItemStatus::Done,
+                (None, false) => ItemStatus::Failed,
+            };
+            progress.emit(ProgressEvent::ItemFinished(ItemFinished {
+                item: key.clone(), kind: ItemKind::Pdf, status, elapsed_ms: elapsed_ms(item),
+            }));
+            if !reused {
+                let entry = entries.get(&key).cloned()
+                    .or_else(|| previous.get(&key).and_then(ItemState::entry).map(str::to_string));
+                record(&key, match &pdf {
+                    Some(pdf) => ItemState::PdfDone {
+                        entry,
+                        path: pdf.filepath.clone(),
+                        sha256: pdf.sha256.clone(),
+                        mapping: pdf.entry.clone(),
+                    },
+                    None => ItemState::Failed { reason: "no pdf found".to_string(), entry, attempts: attempt(&key) },
+                });
+            }
+            pdfs.push((key, pdf));
+        }
+        pdfs.sort_by(|(a, _), (b, _)| a.cmp(b));
 
-        let pdf_count = pdfs.iter().filter(|r| r.is_some()).count();
+        let pdf_count = pdfs.iter().filter(|(_, r)| r.is_some()).count();
 
-        if self.progress {
-            println!("{:<10}\t{} / {}", "[TOTAL PDF]".blue(), pdf_count, pdf_requests.len());
+        info!(target: PROGRESS, "{:<10}\t{} / {}", "[TOTAL PDF]", pdf_count, pdf_requests.len());
+
+        progress.emit(ProgressEvent::RunFinished(RunFinished {
+            entries: count, entries_total: res.len(), pdfs: pdf_count, pdfs_total: pdfs.len(), elapsed_ms: elapsed_ms(started),
+        }));
+        SetupResult { pdfs, entries: res, exhausted }
+    }
+
+    /// Fetches the entries again from their doi, looking the doi up on
+    /// CrossRef from the title when there is none. The entries found
+    /// keep the key of the request.
+    pub async fn refetch(&self, requests: &[RefetchRequest]) -> Vec<(String, Option<String>)> {
+        let client = self.client().expect("Could not build http client");
+        let doi_downloader = DxDoiDownloader::new(&client)
+            .with_cache(self.cache.clone())
+            .with_mirror(self.mirror.clone());
+        let searcher = CrossrefSearcher::new(&client).with_mirror(self.mirror.clone());
+
+        let mut res = vec![];
+        for request in requests {
+            info!(target: PROGRESS, "{:<10}\t{}", "[REFETCH]", request.key);
+            let doi = match (&request.doi, &request.title) {
+                (Some(doi), _) => Some(doi.clone()),
+                (None, Some(title)) => searcher.search_doi(title).await,
+                (None, None) => None,
+            };
+            let entry = match &doi {
+                Some(doi) => doi_downloader.download_one(&DownloadRequest::Doi(doi)).await,
+                None => None,
+            };
+            res.push((request.key.clone(), entry.map(|e| with_key(&e, &request.key))));
         }
+        res
+    }
+}
 
-         SetupResult { 
-            pdfs: pdf_requests.into_iter()
-                .zip(pdfs.into_iter())
-                .map(|(r, p)| (format!("{}", r), p))
-                .collect(),
-            entries: res.into_iter()
-                .zip(dois.iter().chain(eprints.iter()))
-                .map(|(r, d)| (d.clone(), r))
-                .collect()
-         }
+/// Replaces the key of a BibTeX entry.
+pub fn with_key(entry: &str, key: &str) -> String {
+    match (entry.find('{'), entry.find(',')) {
+        (Some(start), Some(end)) if start < end => {
+            format!("{}{}{}", &entry[..=start], key, &entry[end..])
+        }
+        _ => entry.to_string(),
     }
 }
 
@@ -244,96 +681,180 @@ pub trait DownloadHandler<T: Fn(&str) -> ()> {
     ) -> Vec<Option<String>>;
 }
 
-#[derive(Default)]
 pub struct ArxivDownloader {
-    client: Client,
+    resolver: Resolver,
+    /// Add a `note` with the version and the day of the download
+    note: bool,
 }
 
 pub struct DxDoiDownloader {
-    client: Client,
+    resolver: Resolver,
+    cache: Option<Cache>,
+    /// The registration agencies of the dois, shared by the requests
+    agencies: AgencyLookup,
+    /// The CSL-JSON of the dois registered by DataCite
+    datacite: Resolver,
 }
 
-#[derive(Default)]
 pub struct PdfDownloader {
-    client: Client,
+    resolver: Resolver,
     cwd: std::path::PathBuf,
-}
-
-
-impl Default for DxDoiDownloader {
-    fn default() -> Self {
-        DxDoiDownloader::new(None)
-    }
+    layout: Layout,
 }
 
 impl DxDoiDownloader {
-    pub fn new(polite_email : Option<String>) -> Self {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            "Accept",
-            reqwest::header::HeaderValue::from_static("application/x-bibtex"),
-        );
-        if let Some(email) = polite_email {
-            headers.insert(
-                "Mailto",
-                reqwest::header::HeaderValue::from_str(&email).expect("Could not parse email"),
-            );
+    pub fn new(client: &reqwest::Client) -> Self {
+        let resolver = Resolver::new(client.clone()).with_headers(RequestKind::Metadata.headers());
+        let json = Resolver::new(client.clone()).with_headers(RequestKind::Search.headers());
+        DxDoiDownloader {
+            resolver,
+            cache: None,
+            agencies: AgencyLookup::new(json.clone()),
+            datacite: json,
         }
+    }
 
-        let client = reqwest::Client::builder()
-            .user_agent(concat!(
-                env!("CARGO_PKG_NAME"),
-                "/",
-                env!("CARGO_PKG_VERSION")
-            ))
-            .default_headers(headers)
-            .build()
-            .expect("Could not build http client");
-
-        DxDoiDownloader { client }
+    pub fn with_cache(mut self, cache: Option<Cache>) -> Self {
+        self.agencies = self.agencies.with_cache(cache.clone());
+        self.cache = cache;
+        self
+    }
 
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.agencies = self.agencies.with_mirror(mirror.clone());
+        self.datacite = self.datacite.with_mirror(mirror.clone());
+        self.resolver = self.resolver.with_mirror(mirror);
+        self
     }
 
     async fn download_one<'a>(&self, request: &DownloadRequest<'a>) -> Option<String> {
+        let span = info_span!("download", identifier = %request);
+        self.fetch_one(request).instrument(span).await
+    }
+
+    async fn fetch_one<'a>(&self, request: &DownloadRequest<'a>) -> Option<String> {
         if let DownloadRequest::Doi(doi) = request {
-            let url = format!("https://dx.doi.org/{}", doi);
-            let response = self.client.get(url).send().await.ok()?;
-            let text = response.text_with_charset("utf-8").await.ok()?;
-            if text.starts_with(" @") {
-                Some(text[1..].to_string())
+            let key = request.to_string();
+            // a recorded mirror must see every request
+            let cache = self.cache.as_ref().filter(|_| !self.resolver.records());
+            if let Some(entry) = cache.and_then(|c| c.get(&key)) {
+                debug!("cache hit");
+                return Some(entry.payload);
+            }
+            debug!("cache miss");
+            // the bibtex of dx.doi.org is often empty for DataCite dois,
+            // and an unknown agency is asked to dx.doi.org anyway
+            let entry = if self.agencies.agency(doi).await == Some(Agency::DataCite) {
+                let text = self.datacite.get_text(&key, &datacite::csl_url(doi)).await?;
+                let entry = datacite::csl_to_bibtex(&text);
+                if entry.is_none() {
+                    warn!("the response is not a DataCite record");
+                }
+                entry?
             } else {
-                None
+                let url = format!("https://dx.doi.org/{}", doi);
+                let text = self.resolver.get_text(&key, &url).await?;
+                if !text.starts_with(" @") {
+                    warn!("the response is not a bibtex entry");
+                    return None;
+                }
+                text[1..].to_string()
+            };
+            if let Some(cache) = &self.cache {
+                // a cache that cannot be written is only a slower cache
+                if let Err(error) = cache.put(&key, &entry) {
+                    warn!(%error, "could not write to the cache");
+                }
             }
+            Some(entry)
         } else {
             None
         }
     }
 }
 
+/// Finds the doi of a title with the search of CrossRef.
+pub struct CrossrefSearcher {
+    resolver: Resolver,
+}
+
+impl CrossrefSearcher {
+    pub fn new(client: &reqwest::Client) -> Self {
+        let resolver = Resolver::new(client.clone()).with_headers(RequestKind::Search.headers());
+        CrossrefSearcher { resolver }
+    }
+
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.resolver = self.resolver.with_mirror(mirror);
+        self
+    }
+
+    /// The doi of the best match, when its title is the given one
+    /// (up to case and punctuation).
+    pub async fn search_doi(&self, title: &str) -> Option<String> {
+        let title = normalize_title(title);
+        let url = reqwest::Url::parse_with_params(
+            "https://api.crossref.org/works",
+            &[("rows", "1"), ("query.bibliographic", title.as_str())],
+        ).ok()?;
+        let text = self.resolver.get_text(&format!("search:{}", title), url.as_str()).await?;
+        let response: serde_json::Value = serde_json::from_str(&text).ok()?;
+        let item = response.pointer("/message/items/0")?;
+        let found = item.pointer("/title/0")?.as_str()?;
+        if normalize_title(found) != title {
+            warn!(%found, "the best match has another title");
+            return None;
+        }
+        Some(item.get("DOI")?.as_str()?.to_string())
+    }
+}
+
 impl ArxivDownloader {
-    pub fn new() -> Self {
-        ArxivDownloader::default()
+    pub fn new(client: &reqwest::Client) -> Self {
+        let resolver = Resolver::new(client.clone()).with_headers(RequestKind::Document.headers());
+        ArxivDownloader { resolver, note: false }
+    }
+
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.resolver = self.resolver.with_mirror(mirror);
+        self
+    }
+
+    pub fn with_note(mut self, note: bool) -> Self {
+        self.note = note;
+        self
     }
 
     // We download the direct feed from the arxiv API
-    // -> we use an rss parser to extract a "bibtex entry"
-    // -> we output the bibtex entry
+    // -> we parse the atom feed (see `arxiv_feed`)
+    // -> we output the bibtex entry, pinned to the requested
+    //    version, or to the latest one
     // @misc{<arxivId>,
     //  title = {<title>},
     //  author = {<author>},
     //  year = {<year>},
     //  abstract = {<abstract>},
     //  archivePrefix = {arXiv},
-    //  eprint = {<arxivId>},
+    //  eprint = {<arxivId>v<version>},
     //  primaryClass = {<primaryClass>},
     //  }
     async fn download_one<'a>(&self, request: &DownloadRequest<'a>) -> Option<String> {
         if let DownloadRequest::Arxiv(id) = request {
             let url = id.to_api_url();
-            let response = self.client.get(url).send().await.ok()?;
-            let _ = response.text_with_charset("utf-8").await.ok()?;
-            // TODO: parse
-            Some("<PDF DATA>".to_string())
+            let feed = self.resolver.get_text(&request.to_string(), &url).await?;
+            let entries = parse_feed(&feed);
+            let version = id.version.or_else(|| latest_version(&entries, id.id));
+            let entry = entries
+                .iter()
+                .filter(|e| e.id == id.id)
+                .max_by_key(|e| e.version)?;
+            let retrieved = self.note.then(|| {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                Date::from_unix_days((now.as_secs() / 86400) as i64)
+            });
+            Some(entry.to_bibtex(version, retrieved.as_ref()))
         } else {
             None
         }
@@ -341,53 +862,63 @@ impl ArxivDownloader {
 }
 
 impl PdfDownloader {
-    pub fn new(working_directory : std::path::PathBuf) -> Self {
-        PdfDownloader { client: Client::default(), cwd: working_directory }
+    pub fn new(client: &reqwest::Client, working_directory : std::path::PathBuf) -> Self {
+        let resolver = Resolver::new(client.clone()).with_headers(RequestKind::Document.headers());
+        PdfDownloader { resolver, cwd: working_directory, layout: Layout::Flat }
     }
 
-    async fn download_one_pdf<'a>(&self, request: &DownloadRequest<'a>) -> Option<PdfResult> {
-        use sha2::Digest;
-        use std::io::Write;
-        let pdf_url: String = match request {
-            DownloadRequest::Arxiv(id) => id.to_pdf_url(),
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.resolver = self.resolver.with_mirror(mirror);
+        self
+    }
+
+    /// Downloads the pdf of a request, given its entry (when it
+    /// was resolved) to place it according to the layout.
+    async fn download_one_pdf<'a>(&self, request: &DownloadRequest<'a>, entry: Option<&str>) -> Option<PdfResult> {
+        let span = info_span!("download_pdf", identifier = %request);
+        self.fetch_one_pdf(request, entry).instrument(span).await
+    }
+
+    /// The url of the pdf of a request: the arXiv pdf, or the one
+    /// linked by the page of the doi, which is the only thing fetched.
+    pub async fn pdf_url<'a>(&self, request: &DownloadRequest<'a>) -> Option<String> {
+        match request {
+            DownloadRequest::Arxiv(id) => Some(id.to_pdf_url()),
             DownloadRequest::Doi(doi) => {
                 // using scihub
                 let url = format!("https://sci-hub.se/{}", doi);
-                let page = self.client.get(url).send().await.ok()?.text().await.ok()?;
+                let page = self.resolver.get_text(&request.to_string(), &url).await?;
                 let pdf_stub = sci_hub_pdf_regex().captures(&page)?.get(2)?.as_str();
-                format!("https:{}.pdf", pdf_stub)
+                Some(format!("https:{}.pdf", pdf_stub))
             }
-            DownloadRequest::Url(url) => url.to_string(),
-        };
-        let response = self.client.get(pdf_url).send().await.ok()?;
-        let pdf_bytes = response.bytes().await.ok()?;
-        let filename = format!(
-            "{}.pdf",
-            format!("{}", request)
-                .to_ascii_lowercase()
-                .replace(" ", "_")
-                .replace("(", "_")
-                .replace(")", "_")
-                .replace("/", "_")
-                .replace(":", "_")
-                .replace("?", "_")
-                .replace("=", "_")
-                .replace("&", "_")
-                .replace("'", "_")
-                .replace("{", "_")
-                .replace("}", "_")
-                .replace(",", "_")
-                .replace("\"", "_")
-                .replace(".", "_")
-        );
+            DownloadRequest::Url(url) => Some(url.to_string()),
+        }
+    }
 
-        let filename = self.cwd.join(filename);
+    async fn fetch_one_pdf<'a>(&self, request: &DownloadRequest<'a>, entry: Option<&str>) -> Option<PdfResult> {
+        use sha2::Digest;
+        use std::io::Write;
+        let pdf_url = self.pdf_url(request).await?;
+        debug!(url = %pdf_url, "fetching the pdf");
+        let pdf_bytes = self.resolver.get(&request.to_string(), &pdf_url).await?;
+        let relative = self.layout.directory(entry)
+            .join(format!("{}.pdf", sanitize(&request.to_string())));
+        let filename = self.cwd.join(&relative);
+        if let Some(dir) = filename.parent() {
+            std::fs::create_dir_all(dir).ok()?;
+        }
         let mut file = std::fs::File::create(&filename).ok()?;
         file.write_all(&pdf_bytes).ok()?;
         let sha256 = format!("{:x}", sha2::Sha256::digest(&pdf_bytes));
 
         let short_sha = &sha256[..10];
-        let display_file = filename.display();
+        // the mapping does not depend on the working directory
+        let display_file = relative.display();
         let identifier_value = match request {
             DownloadRequest::Arxiv(id) => id.to_string(),
             DownloadRequest::Doi(doi) => doi.to_string(),
@@ -405,38 +936,9 @@ impl PdfDownloader {
 
     async fn download_one<'a>(&self, request: &DownloadRequest<'a>) -> Option<String> {
         use std::io::Write;
-        let pdf_url: String = match request {
-            DownloadRequest::Arxiv(id) => id.to_pdf_url(),
-            DownloadRequest::Doi(doi) => {
-                // using scihub
-                let url = format!("https://sci-hub.se/{}", doi);
-                let page = self.client.get(url).send().await.ok()?.text().await.ok()?;
-                let pdf_stub = sci_hub_pdf_regex().captures(&page)?.get(2)?.as_str();
-                format!("https:{}.pdf", pdf_stub)
-            }
-            DownloadRequest::Url(url) => url.to_string(),
-        };
-        let response = self.client.get(pdf_url).send().await.ok()?;
-        let pdf_bytes = response.bytes().await.ok()?;
-        let filename = format!(
-            "{}.pdf",
-            format!("{:?}", request)
-                .to_ascii_lowercase()
-                .replace(" ", "_")
-                .replace("(", "_")
-                .replace(")", "_")
-                .replace("/", "_")
-                .replace(":", "_")
-                .replace("?", "_")
-                .replace("=", "_")
-                .replace("&", "_")
-                .replace("'", "_")
-                .replace("{", "_")
-                .replace("}", "_")
-                .replace(",", "_")
-                .replace("\"", "_")
-                .replace(".", "_")
-        );
+        let pdf_url = self.pdf_url(request).await?;
+        let pdf_bytes = self.resolver.get(&request.to_string(), &pdf_url).await?;
+        let filename = format!("{}.pdf", sanitize(&format!("{:?}", request)));
 
         let filename = self.cwd.join(filename);
         let mut file = std::fs::File::create(&filename).ok()?;
@@ -526,3 +1028,426 @@ where
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::Bibliography;
+    use crate::resolver::MirrorMode;
+
+    #[test]
+    fn test_refetch_request_of_entry() {
+        let bib = Bibliography::from(
+            "@inproceedings{doe2020robust,\n  title = {A {M}irrored Paper},\n  \
+             doi = \"https://doi.org/10.1000/Mirrored\",\n}\n\
+             @misc{doe2020nodoi,\n  title = \"Unknown\",\n}\n"
+                .to_string(),
+        );
+        let requests = bib.entries().iter().map(RefetchRequest::of_entry).collect::<Vec<_>>();
+        assert_eq!(requests[0].key, "doe2020robust");
+        assert_eq!(requests[0].doi.as_deref(), Some("10.1000/mirrored"));
+        assert_eq!(requests[0].title.as_deref(), Some("A {M}irrored Paper"));
+        assert_eq!(requests[1].doi, None);
+        assert_eq!(requests[1].title.as_deref(), Some("Unknown"));
+    }
+
+    #[test]
+    fn test_run_from_mirror() {
+        let mirror = crate::fixture("mirror");
+        let working_directory = crate::temp_dir("mirror");
+        let config = SetupConfig {
+            download_pdf: true,
+            working_directory: working_directory.clone(),
+            mirror: Some(Mirror::new(mirror, MirrorMode::Replay)),
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from([
+            "10.1000/mirrored".to_string(),
+            "10.1000/missing".to_string(),
+        ]);
+        let eprints = HashSet::from(["2101.00001".to_string()]);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = rt.block_on(config.run(dois, eprints, HashSet::new()));
+
+        // the entries in the mirror are found, the others are failed downloads
+        let entries = result
+            .entries
+            .iter()
+            .filter_map(|(_, entry)| entry.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.starts_with("@article{Mirrored_2020"))
+        );
+        // the eprint is pinned to the version of the feed
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.contains("eprint = {2101.00001v1}"))
+        );
+
+        let pdfs = result
+            .pdfs
+            .iter()
+            .filter_map(|(_, pdf)| pdf.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(pdfs.len(), 2);
+        for pdf in pdfs {
+            use sha2::Digest;
+            let bytes = std::fs::read(&pdf.filepath).unwrap();
+            assert!(bytes.starts_with(b"%PDF"));
+            assert_eq!(pdf.sha256, format!("{:x}", sha2::Sha256::digest(&bytes)));
+        }
+        assert!(
+            result
+                .pdfs
+                .iter()
+                .any(|(r, pdf)| r == "doi:10.1000/missing" && pdf.is_none())
+        );
+        std::fs::remove_dir_all(working_directory).unwrap();
+    }
+
+    #[test]
+    fn test_within_budget() {
+        let config = SetupConfig {
+            existing_doi: HashSet::from(["10.1/done".to_string()]),
+            budget: Budget { kinds: vec![], max_requests: Some(2) },
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from(["10.1/done", "10.1/a", "10.1/b"].map(str::to_string));
+        let eprints = HashSet::from(["2101.00001".to_string()]);
+        let (dois, eprints, exhausted) = config.within_budget(dois, eprints);
+        // the entries already in the output cost nothing
+        assert_eq!(dois, HashSet::from(["10.1/done", "10.1/a"].map(str::to_string)));
+        assert_eq!(eprints.len(), 1);
+        assert_eq!(exhausted.iter().map(Identifier::cache_key).collect::<Vec<_>>(), ["doi:10.1/b"]);
+
+        let config = SetupConfig {
+            budget: Budget { kinds: vec![IdentifierKind::Arxiv], max_requests: None },
+            ..SetupConfig::default()
+        };
+        let (dois, eprints, exhausted) = config.within_budget(
+            HashSet::from(["10.1/a".to_string()]),
+            HashSet::from(["2101.00001".to_string()]),
+        );
+        assert!(dois.is_empty() && exhausted.is_empty());
+        assert_eq!(eprints.len(), 1);
+    }
+
+    #[test]
+    fn test_run_within_budget() {
+        let mirror = crate::fixture("mirror");
+        let config = SetupConfig {
+            mirror: Some(Mirror::new(mirror, MirrorMode::Replay)),
+            budget: Budget { kinds: vec![], max_requests: Some(1) },
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from(["10.1000/mirrored".to_string()]);
+        let eprints = HashSet::from(["2101.00001".to_string()]);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = rt.block_on(config.run(dois, eprints, HashSet::new()));
+        // a single request is sent, the other identifier is reported
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.exhausted.len(), 1);
+    }
+
+    #[test]
+    fn test_progress_events() {
+        use crate::progress::{ItemFinished, ItemStatus, Progress, ProgressEvent, RunStarted};
+        use std::sync::{Arc, Mutex};
+
+        let mirror = crate::fixture("mirror");
+        let events = Arc::new(Mutex::new(vec![]));
+        let config = SetupConfig {
+            mirror: Some(Mirror::new(mirror, MirrorMode::Replay)),
+            progress: Progress::with_sink({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event.clone())
+            }),
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from([
+            "10.1000/mirrored".to_string(),
+            "10.1000/missing".to_string(),
+        ]);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(config.run(dois, HashSet::new(), HashSet::new()));
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events.len(), 6);
+        assert_eq!(
+            events[0],
+            ProgressEvent::RunStarted(RunStarted {
+                schema_version: crate::progress::PROGRESS_SCHEMA_VERSION,
+                dois: 2,
+                eprints: 0,
+                pdfs: 0,
+            })
+        );
+        // every item starts, then finishes
+        for item in ["doi:10.1000/mirrored", "doi:10.1000/missing"] {
+            let started = events.iter().position(
+                |e| matches!(e, ProgressEvent::ItemStarted(s) if s.item == item),
+            );
+            let finished = events.iter().position(
+                |e| matches!(e, ProgressEvent::ItemFinished(f) if f.item == item),
+            );
+            assert!(started.unwrap() < finished.unwrap());
+        }
+        let statuses = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::ItemFinished(ItemFinished { item, status, .. }) => {
+                    Some((item.as_str(), *status))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        assert_eq!(statuses["doi:10.1000/mirrored"], ItemStatus::Done);
+        assert_eq!(statuses["doi:10.1000/missing"], ItemStatus::Failed);
+        let ProgressEvent::RunFinished(finished) = &events[5] else {
+            panic!("the run should finish last, not with {:?}", events[5]);
+        };
+        assert_eq!((finished.entries, finished.entries_total), (1, 2));
+        assert_eq!((finished.pdfs, finished.pdfs_total), (0, 0));
+    }
+
+    #[test]
+    fn test_resume_from_manifest() {
+        use crate::manifest::{ItemState, Manifest};
+
+        let mirror = crate::fixture("mirror");
+        let working_directory = crate::temp_dir("resume");
+        // the previous run died after fetching an entry that is not in the mirror
+        let done = "@misc{Done_2020, title = {Done}}";
+        let mut manifest = Manifest::new(&working_directory);
+        manifest
+            .record(
+                "doi:10.1000/done",
+                ItemState::MetadataDone {
+                    entry: done.to_string(),
+                },
+            )
+            .unwrap();
+        let config = SetupConfig {
+            working_directory: working_directory.clone(),
+            mirror: Some(Mirror::new(mirror, MirrorMode::Replay)),
+            resume: true,
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from([
+            "10.1000/done".to_string(),
+            "10.1000/mirrored".to_string(),
+            "10.1000/missing".to_string(),
+        ]);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = rt.block_on(config.run(dois, HashSet::new(), HashSet::new()));
+        assert_eq!(result.entries[0], ("10.1000/done".to_string(), Some(done.to_string())));
+        assert!(result.entries[1].1.as_deref().unwrap().starts_with("@article{Mirrored_2020"));
+        assert_eq!(result.entries[2], ("10.1000/missing".to_string(), None));
+
+        // the manifest has the results of the run, and the failures are retried
+        let manifest = Manifest::load(&working_directory);
+        assert_eq!(manifest.done(false), 2);
+        assert!(matches!(manifest.get("doi:10.1000/missing"), Some(ItemState::Failed { .. })));
+        std::fs::remove_dir_all(working_directory).unwrap();
+    }
+
+    #[test]
+    fn test_run_offline() {
+        let working_directory = crate::temp_dir("offline");
+        let config = SetupConfig {
+            working_directory: working_directory.clone(),
+            offline: true,
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from(["10.1000/offline".to_string()]);
+        let eprints = HashSet::from(["2101.00001".to_string()]);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let before = crate::network::skipped();
+        let result = rt.block_on(config.run(dois, eprints, HashSet::new()));
+        let skipped = crate::network::skipped() - before;
+
+        // every request is refused before connecting, and is still reported
+        assert!(skipped >= 2);
+        assert_eq!(result.entries.len(), 2);
+        assert!(result.entries.iter().all(|(_, entry)| entry.is_none()));
+        assert!(result.pdfs.is_empty());
+        std::fs::remove_dir_all(working_directory).unwrap();
+    }
+
+    #[test]
+    fn test_refetch_from_mirror() {
+        let mirror = crate::fixture("mirror");
+        let config = SetupConfig {
+            mirror: Some(Mirror::new(mirror, MirrorMode::Replay)),
+            ..SetupConfig::default()
+        };
+        let request = |key: &str, doi: Option<&str>, title: Option<&str>| RefetchRequest {
+            key: key.to_string(),
+            doi: doi.map(str::to_string),
+            title: title.map(str::to_string),
+        };
+        let requests = [
+            request("doe2020mirrored", Some("10.1000/mirrored"), None),
+            request("doe2020searched", None, Some("A {M}irrored Paper")),
+            request("doe2020unknown", None, Some("Unknown")),
+        ];
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = rt.block_on(config.refetch(&requests));
+        assert_eq!(result.len(), 3);
+        assert!(result[0].1.as_deref().unwrap().starts_with("@article{doe2020mirrored,"));
+        assert!(result[1].1.as_deref().unwrap().starts_with("@article{doe2020searched,"));
+        assert_eq!(result[2].1, None);
+    }
+
+    #[test]
+    fn test_datacite_from_mirror() {
+        let mirror = crate::fixture("mirror");
+        let config = SetupConfig {
+            mirror: Some(Mirror::new(mirror, MirrorMode::Replay)),
+            ..SetupConfig::default()
+        };
+        let dois = HashSet::from(["10.5281/zenodo.4275391".to_string()]);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = rt.block_on(config.run(dois, HashSet::new(), HashSet::new()));
+        // the prefix is registered by DataCite, whose record is used
+        let entry = result.entries[0].1.as_deref().unwrap();
+        assert!(entry.starts_with("@software{Doe_2020,"));
+        assert!(entry.contains("version={v1.2.0}"));
+        assert!(entry.contains("url={https://zenodo.org/record/4275391}"));
+    }
+
+    #[test]
+    fn test_layout() {
+        let entry = "@Article{Knuth_1984, author = {Kn{\\\"u}th, Donald E. and Doe, Jane}, title = {Literate Programming}, year = {1984}}";
+        let directory = |layout: Layout, entry| layout.directory(entry).display().to_string();
+        assert_eq!(directory(Layout::Flat, Some(entry)), "");
+        assert_eq!(directory(Layout::ByYear, Some(entry)), "1984");
+        assert_eq!(directory(Layout::ByAuthor, Some(entry)), "knuth");
+        assert_eq!(directory(Layout::ByType, Some(entry)), "article");
+
+        // the missing metadata goes to `unsorted/`
+        let untitled = "@misc{a, year = {forthcoming}}";
+        assert_eq!(directory(Layout::Flat, None), "");
+        assert_eq!(directory(Layout::ByYear, None), UNSORTED_DIR);
+        assert_eq!(directory(Layout::ByYear, Some(untitled)), UNSORTED_DIR);
+        assert_eq!(directory(Layout::ByAuthor, Some(untitled)), UNSORTED_DIR);
+        assert_eq!(directory(Layout::ByType, Some(untitled)), "misc");
+
+        // the directories are sanitized like the filenames
+        let odd = "@misc{b, author = {{O'Brien}, A. and Doe, J.}, year = {2023--2024}}";
+        assert_eq!(directory(Layout::ByAuthor, Some(odd)), "o_brien");
+        assert_eq!(directory(Layout::ByYear, Some(odd)), "2024");
+        assert_eq!(sanitize("../doi:10.1000/X"), "___doi_10_1000_x");
+    }
+
+    #[test]
+    fn test_request_headers() {
+        use reqwest::header::ACCEPT;
+
+        let metadata = RequestKind::Metadata.headers();
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata.get(ACCEPT).unwrap().to_str().unwrap(), "application/x-bibtex");
+        // the pdfs and the searches are not asked for BibTeX
+        assert!(!RequestKind::Document.headers().contains_key(ACCEPT));
+        assert!(!RequestKind::Search.headers().contains_key(ACCEPT));
+
+        let config = SetupConfig::default();
+        assert_eq!(config.default_headers().unwrap().len(), 0);
+        let polite = SetupConfig {
+            polite_email: Some("me@example.org".to_string()),
+            ..SetupConfig::default()
+        };
+        let headers = polite.default_headers().unwrap();
+        assert_eq!(headers.get("Mailto").unwrap().to_str().unwrap(), "me@example.org");
+        assert!(!headers.contains_key(ACCEPT));
+        let invalid = SetupConfig {
+            polite_email: Some("me\n@example.org".to_string()),
+            ..SetupConfig::default()
+        };
+        assert!(invalid.default_headers().is_err());
+    }
+
+    #[test]
+    fn test_validate_email() {
+        assert!(validate_email("me@example.org").is_ok());
+        assert!(validate_email("first.last+tag@lab.example.ac.uk").is_ok());
+        let invalid = [
+            "", "me", "@example.org", "me@", "me@org", "me@example.",
+            "me@@example.org", "me @example.org", "me@example.org)",
+        ];
+        for email in invalid {
+            assert!(validate_email(email).is_err(), "{:?}", email);
+        }
+    }
+
+    #[test]
+    fn test_user_agent() {
+        assert_eq!(SetupConfig::default().user_agent().unwrap(), USER_AGENT);
+        let polite = SetupConfig {
+            polite_email: Some("me@example.org".to_string()),
+            ..SetupConfig::default()
+        };
+        assert_eq!(polite.user_agent().unwrap(), format!("{} (mailto:me@example.org)", USER_AGENT));
+        // the user agent of the user wins, but the email is still sent
+        let custom = SetupConfig {
+            user_agent: Some("MyLibrary/2.0 (+https://example.org)".to_string()),
+            ..polite.clone()
+        };
+        assert_eq!(custom.user_agent().unwrap(), "MyLibrary/2.0 (+https://example.org)");
+        assert_eq!(custom.default_headers().unwrap().get("Mailto").unwrap().to_str().unwrap(), "me@example.org");
+        let invalid = SetupConfig {
+            polite_email: Some("not an email".to_string()),
+            ..SetupConfig::default()
+        };
+        assert!(invalid.user_agent().is_err());
+        assert!(invalid.client().is_err());
+        let invalid = SetupConfig {
+            user_agent: Some("bad\nagent".to_string()),
+            ..SetupConfig::default()
+        };
+        assert!(invalid.user_agent().is_err());
+    }
+
+    #[test]
+    fn test_client() {
+        let config = SetupConfig {
+            timeout: Some(std::time::Duration::from_secs(30)),
+            proxy: Some("http://localhost:8080".to_string()),
+            ..SetupConfig::default()
+        };
+        assert!(config.client().is_ok());
+        let invalid = SetupConfig {
+            proxy: Some("not a proxy".to_string()),
+            ..SetupConfig::default()
+        };
+        assert!(invalid.client().is_err());
+    }
+
+    #[test]
+    fn test_with_key() {
+        assert_eq!(
+            with_key("@article{Doe_2020, title = {A}}", "doe2020a"),
+            "@article{doe2020a, title = {A}}"
+        );
+        assert_eq!(with_key("not an entry", "a"), "not an entry");
+    }
+}
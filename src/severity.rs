@@ -0,0 +1,193 @@
+//! This file is responsible for the severity of the rules of
+//! `bibadac check`, as set by the `[check.rules]` sections of the
+//! configuration file, possibly depending on the type of the entry:
+//!
+//! ```toml
+//! [check.rules."missing-field"]
+//! severity = "error"
+//!
+//! [check.rules."missing-field".overrides]
+//! misc = "warning"
+//! ```
+//!
+//! An override applies to the entries of its type (case insensitive),
+//! the `severity` to the other entries, and the default severity of
+//! the rule (see `LintMessage::is_crucial`) to what is left, such as
+//! the messages about the whole file.
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::linter::LintMessage;
+
+/// How bad a diagnostic is: errors are the crucial diagnostics,
+/// the only ones reported by `check --concise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The default severity of the rule of a message.
+    pub fn of(msg: &LintMessage) -> Severity {
+        if msg.is_crucial() {
+            Severity::Error
+        } else {
+            Severity::Warning
+        }
+    }
+
+    pub fn is_crucial(self) -> bool {
+        self == Severity::Error
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The severity of a rule, and its overrides by entry type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSeverity {
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    /// The severity for the entries of a type (e.g. `misc`)
+    #[serde(default)]
+    pub overrides: BTreeMap<String, Severity>,
+}
+
+/// The `[check.rules]` sections, by rule name (see `LintMessage::rule_name`).
+pub type RuleSeverities = BTreeMap<String, RuleSeverity>;
+
+/// The severity of a rule for an entry of the given type
+/// (`None` for the messages that are not about an entry).
+pub fn effective_severity(
+    rules: &RuleSeverities,
+    rule: &str,
+    entry_type: Option<&str>,
+    default: Severity,
+) -> Severity {
+    let Some(configured) = rules.get(rule) else {
+        return default;
+    };
+    let entry_type = entry_type.map(|t| t.trim_start_matches('@'));
+    entry_type
+        .and_then(|t| {
+            configured
+                .overrides
+                .iter()
+                .find(|(name, _)| name.trim_start_matches('@').eq_ignore_ascii_case(t))
+        })
+        .map(|(_, severity)| *severity)
+        .or(configured.severity)
+        .unwrap_or(default)
+}
+
+/// The severity of a rule, followed by its overrides,
+/// e.g. `error (misc: warning)`.
+pub fn describe(rules: &RuleSeverities, rule: &str, default: Severity) -> String {
+    let configured = rules.get(rule).cloned().unwrap_or_default();
+    let severity = configured.severity.unwrap_or(default);
+    if configured.overrides.is_empty() {
+        return severity.to_string();
+    }
+    let overrides = configured
+        .overrides
+        .iter()
+        .map(|(entry_type, severity)| format!("{}: {}", entry_type, severity))
+        .collect::<Vec<_>>();
+    format!("{} ({})", severity, overrides.join(", "))
+}
+
+/// The configured rules that do not exist, most likely typos.
+pub fn unknown_rules(rules: &RuleSeverities) -> Vec<&str> {
+    let known = LintMessage::examples()
+        .iter()
+        .map(|msg| msg.rule_name())
+        .collect::<Vec<_>>();
+    rules
+        .keys()
+        .map(|rule| rule.as_str())
+        .filter(|rule| !known.contains(rule))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> RuleSeverities {
+        let mut rules = RuleSeverities::new();
+        rules.insert(
+            "missing-field".into(),
+            RuleSeverity {
+                severity: Some(Severity::Error),
+                overrides: [("misc".into(), Severity::Warning)].into(),
+            },
+        );
+        rules.insert(
+            "http-doi".into(),
+            RuleSeverity {
+                severity: None,
+                overrides: [("@Article".into(), Severity::Error)].into(),
+            },
+        );
+        rules
+    }
+
+    #[test]
+    fn test_effective_severity() {
+        use Severity::*;
+        let rules = rules();
+        // (rule, entry type, default, severity)
+        let matrix = [
+            ("missing-field", Some("article"), Warning, Error),
+            ("missing-field", Some("misc"), Error, Warning),
+            ("missing-field", Some("@MISC"), Error, Warning),
+            ("missing-field", None, Warning, Error),
+            ("http-doi", Some("article"), Warning, Error),
+            ("http-doi", Some("book"), Warning, Warning),
+            ("http-doi", None, Warning, Warning),
+            ("redundant-url", Some("misc"), Warning, Warning),
+            ("syntax-error", None, Error, Error),
+        ];
+        for (rule, entry_type, default, severity) in matrix {
+            assert_eq!(
+                effective_severity(&rules, rule, entry_type, default),
+                severity,
+                "{} {:?}",
+                rule,
+                entry_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let rules = rules();
+        assert_eq!(
+            describe(&rules, "missing-field", Severity::Warning),
+            "error (misc: warning)"
+        );
+        assert_eq!(
+            describe(&rules, "http-doi", Severity::Warning),
+            "warning (@Article: error)"
+        );
+        assert_eq!(describe(&rules, "empty-key", Severity::Error), "error");
+    }
+
+    #[test]
+    fn test_unknown_rules() {
+        let mut rules = rules();
+        rules.insert("missing-feild".into(), RuleSeverity::default());
+        assert_eq!(unknown_rules(&rules), vec!["missing-feild"]);
+    }
+}
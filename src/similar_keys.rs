@@ -0,0 +1,251 @@
+//! This file is responsible for finding the entry keys that are likely
+//! typos of each other, such as `vanbenthem1984` and `vanbethem1984`,
+//! where one citation would silently resolve to the wrong entry.
+//!
+//! Two keys of at least 6 characters are similar when they are at edit
+//! distance 1, or 2 when both are longer than 12 characters (`a1` and
+//! `b1` are merely short), and have the same numbers (the
+//! year usually), since `knuth1984` and `knuth1986` are different works.
+//! Keys that only differ by their last letter after a number, such as
+//! `smith2020a` and `smith2020b`, disambiguate the works of a year and
+//! are not similar either.
+//!
+//! Comparing every pair of keys is too slow for large files, so the keys
+//! are bucketed by the strings obtained by deleting up to 2 of their
+//! letters: two keys at distance at most 2 share such a string, and
+//! only the keys of a bucket are compared.
+use std::collections::BTreeSet;
+
+/// The keys shorter than this (in characters) are never similar.
+pub const SHORT_KEY: usize = 6;
+
+/// The keys longer than this (in characters) may be 2 edits apart.
+pub const LONG_KEY: usize = 12;
+
+/// The maximal edit distance of a key to a similar one.
+pub fn max_distance(key: &str) -> usize {
+    if key.chars().count() > LONG_KEY { 2 } else { 1 }
+}
+
+/// The edit distance between `a` and `b` when it is at most `max`,
+/// only computing the diagonals of the table within `max`.
+pub fn bounded_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let inf = max + 1;
+    let mut previous = (0..=b.len()).map(|j| j.min(inf)).collect::<Vec<_>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![inf; b.len() + 1];
+        current[0] = (i + 1).min(inf);
+        let from = (i + 1).saturating_sub(max).max(1);
+        let to = (i + 1 + max).min(b.len());
+        for j in from..=to {
+            let substitution = previous[j - 1] + usize::from(*ca != b[j - 1]);
+            current[j] = substitution
+                .min(previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(inf);
+        }
+        if current.iter().all(|d| *d > max) {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|d| *d <= max)
+}
+
+/// Whether the edit distance between `a` and `b` is at most `max`.
+pub fn within_distance(a: &[char], b: &[char], max: usize) -> bool {
+    bounded_distance(a, b, max).is_some()
+}
+
+/// The numbers of a key, in order (`smith2020ab12` has `2020` and `12`).
+fn numbers(key: &str) -> Vec<&str> {
+    key.split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+/// Whether the keys only differ by a letter after a number
+/// (`smith2020a` and `smith2020b`, or `smith2020` and `smith2020a`).
+fn is_disambiguation(a: &[char], b: &[char]) -> bool {
+    let common = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    common > 0 && a[common - 1].is_ascii_digit() && a.len() - common <= 1 && b.len() - common <= 1
+}
+
+/// The base of the polynomial hashes of the keys.
+const BASE: u64 = 0x100000001b3;
+
+/// The hashes of the strings obtained by deleting up to
+/// `deletions` letters (not digits) of the key, itself included.
+///
+/// The hashes are polynomial, so that the hash of a key without some
+/// characters is computed from the hashes of its prefixes in constant
+/// time. Two strings may share a hash, the keys are compared anyway.
+fn variants(key: &[char], deletions: usize, out: &mut Vec<u64>) {
+    let n = key.len();
+    let mut prefix = vec![0u64; n + 1];
+    let mut power = vec![1u64; n + 1];
+    for (k, c) in key.iter().enumerate() {
+        prefix[k + 1] = prefix[k].wrapping_mul(BASE).wrapping_add(*c as u64 + 1);
+        power[k + 1] = power[k].wrapping_mul(BASE);
+    }
+    // the hash of key[from..to]
+    let part = |from: usize, to: usize| {
+        prefix[to].wrapping_sub(prefix[from].wrapping_mul(power[to - from]))
+    };
+    let join = |a: u64, b: u64, len_b: usize| a.wrapping_mul(power[len_b]).wrapping_add(b);
+    let letters = (0..n)
+        .filter(|i| !key[*i].is_ascii_digit())
+        .collect::<Vec<_>>();
+    out.push(prefix[n]);
+    for (m, &i) in letters.iter().enumerate() {
+        out.push(join(prefix[i], part(i + 1, n), n - i - 1));
+        if deletions < 2 {
+            continue;
+        }
+        for &j in &letters[m + 1..] {
+            let head = join(prefix[i], part(i + 1, j), j - i - 1);
+            out.push(join(head, part(j + 1, n), n - j - 1));
+        }
+    }
+}
+
+/// The pairs of indices of the similar keys (see the module
+/// documentation), each pair once and in increasing order.
+pub fn similar_keys(keys: &[&str]) -> Vec<(usize, usize)> {
+    let chars = keys
+        .iter()
+        .map(|k| k.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut buckets = vec![];
+    let mut hashes = vec![];
+    for (i, key) in chars.iter().enumerate() {
+        if key.len() < SHORT_KEY {
+            continue;
+        }
+        hashes.clear();
+        variants(key, max_distance(keys[i]), &mut hashes);
+        hashes.sort_unstable();
+        hashes.dedup();
+        buckets.extend(hashes.iter().map(|h| (*h, i)));
+    }
+    buckets.sort_unstable();
+
+    let mut pairs = BTreeSet::new();
+    for bucket in buckets.chunk_by(|a, b| a.0 == b.0) {
+        for (n, (_, i)) in bucket.iter().enumerate() {
+            for (_, j) in &bucket[n + 1..] {
+                let (a, b) = (&chars[*i], &chars[*j]);
+                let max = max_distance(keys[*i]).min(max_distance(keys[*j]));
+                if a != b
+                    && !pairs.contains(&(*i, *j))
+                    && !is_disambiguation(a, b)
+                    && numbers(keys[*i]) == numbers(keys[*j])
+                    && within_distance(a, b, max)
+                {
+                    pairs.insert((*i, *j));
+                }
+            }
+        }
+    }
+    pairs.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &str, b: &str, max: usize) -> bool {
+        let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+        within_distance(&a, &b, max)
+    }
+
+    #[test]
+    fn test_within_distance() {
+        assert!(close("vanbenthem", "vanbethem", 1));
+        assert!(close("kitten", "sitten", 1));
+        assert!(!close("kitten", "sitting", 2));
+        assert!(close("kitten", "sitting", 3));
+        assert!(close("", "ab", 2));
+        assert!(!close("abc", "cab", 1));
+        assert!(close("abc", "cab", 2));
+        assert!(close("same", "same", 0));
+        let (a, b) = (
+            "kitten".chars().collect::<Vec<_>>(),
+            "sitting".chars().collect::<Vec<_>>(),
+        );
+        assert_eq!(bounded_distance(&a, &b, 3), Some(3));
+        assert_eq!(bounded_distance(&a, &b, 2), None);
+    }
+
+    #[test]
+    fn test_similar_keys() {
+        let keys = [
+            "vanbenthem1984",
+            "vanbethem1984",
+            "knuth1984",
+            "knuth1986",
+            "smith2020a",
+            "smith2020b",
+            "smith2020",
+            "turing1936",
+            "turng1936",
+            "DBLP:conf/lics/Smith21",
+            "DBLP:conf/lics/Smiht21",
+            "lamport1978",
+            "lamport1978time",
+        ];
+        let pairs = similar_keys(&keys)
+            .into_iter()
+            .map(|(i, j)| (keys[i], keys[j]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            vec![
+                ("vanbenthem1984", "vanbethem1984"),
+                ("turing1936", "turng1936"),
+                ("DBLP:conf/lics/Smith21", "DBLP:conf/lics/Smiht21"),
+            ]
+        );
+        // only long keys may be 2 edits apart
+        assert!(similar_keys(&["turing1936", "turnig1936"]).is_empty());
+        assert_eq!(similar_keys(&["duplicate", "duplicate"]), vec![]);
+        assert_eq!(similar_keys(&["smith", "smyth", "a1", "b1"]), vec![]);
+    }
+
+    #[test]
+    fn test_similar_keys_large() {
+        // keys sharing prefixes and years, as in large files (the
+        // benchmarks measure 50k of them, see `benches/`)
+        let names = ["author", "smith", "vanbenthem", "dijkstra", "lamport"];
+        let words = ["logic", "types", "games", "graphs", "proofs", "automata"];
+        let mut keys = (0..10_000)
+            .map(|i| {
+                format!(
+                    "{}{}{}{}",
+                    names[i % names.len()],
+                    1950 + i % 70,
+                    words[(i / 70) % words.len()],
+                    i / 420
+                )
+            })
+            .collect::<Vec<_>>();
+        // typos of `vanbenthem1952logic0` and `dijkstra1953logic0`
+        keys.push("vanbnthem1952logic0".to_string());
+        keys.push("dijkstra1953lgoic0".to_string());
+        let keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+        let pairs = similar_keys(&keys)
+            .into_iter()
+            .map(|(i, j)| (keys[i], keys[j]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            vec![
+                ("vanbenthem1952logic0", "vanbnthem1952logic0"),
+                ("dijkstra1953logic0", "dijkstra1953lgoic0"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! This file is responsible for the spell-check of the `title`,
+//! `booktitle`, and `journal` fields (`check --spellcheck`, with
+//! the `spellcheck` feature).
+//!
+//! The words are read with `tokenize_value`, skipping the words that
+//! are protected by braces (`{RNA}`), in math mode (`$\alpha$`), or
+//! glued to a command or a group (`Erd\H{o}s`), as well as the words
+//! with digits or inner capitals (`LaTeX`, `GPT4`).
+//!
+//! A word is known when it is, up to a common suffix (`networks`,
+//! `learned`), in the bundled list of English words or in the user
+//! dictionary (`--dictionary`). The bundled list is far from complete,
+//! so an unknown word is only a likely typo when a known word is one
+//! or two edits away: the others are most likely technical terms.
+//! The lookups are cached by word, as titles repeat the same words.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use crate::bibtex::{join_escapes, tokenize_value, ValueTokenKind};
+use crate::similar_keys::bounded_distance;
+
+/// The fields whose values are spell-checked.
+pub const SPELLCHECKED_FIELDS: [&str; 3] = ["title", "booktitle", "journal"];
+
+/// The maximal number of suggestions for a typo.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// Suffixes removed to find the stem of a word, and their replacement.
+const SUFFIXES: [(&str, &str); 12] = [
+    ("s", ""),
+    ("es", ""),
+    ("ies", "y"),
+    ("ed", ""),
+    ("ed", "e"),
+    ("ied", "y"),
+    ("ing", ""),
+    ("ing", "e"),
+    ("ly", ""),
+    ("er", ""),
+    ("ers", ""),
+    ("'s", ""),
+];
+
+fn english_words() -> &'static HashSet<&'static str> {
+    static WORDS: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        include_str!("../data/english_words.txt")
+            .lines()
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .collect()
+    })
+}
+
+/// The words of a dictionary file, one per line,
+/// ignoring blank lines and `#` comments.
+pub fn parse_dictionary(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SpellChecker {
+    /// The words of the user dictionary (lowercase)
+    user: HashSet<String>,
+    /// The suggestions by word, `None` for the words that are fine
+    cache: RefCell<HashMap<String, Option<Vec<String>>>>,
+}
+
+impl SpellChecker {
+    pub fn new(user: impl IntoIterator<Item = String>) -> Self {
+        SpellChecker {
+            user: user.into_iter().map(|w| w.to_lowercase()).collect(),
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        english_words().contains(word) || self.user.contains(word)
+    }
+
+    fn is_known(&self, word: &str) -> bool {
+        self.is_word(word)
+            || SUFFIXES.iter().any(|(suffix, replacement)| {
+                word.strip_suffix(suffix).is_some_and(|stem| {
+                    stem.len() >= 2 && self.is_word(&(stem.to_string() + replacement))
+                })
+            })
+    }
+
+    /// The known words that are the closest to an unknown word.
+    fn suggestions(&self, word: &str) -> Vec<String> {
+        let chars = word.chars().collect::<Vec<_>>();
+        let max = if chars.len() <= 4 { 1 } else { 2 };
+        let mut close = english_words()
+            .iter()
+            .copied()
+            .chain(self.user.iter().map(String::as_str))
+            .filter(|w| w.chars().count().abs_diff(chars.len()) <= max)
+            .filter_map(|w| {
+                let other = w.chars().collect::<Vec<_>>();
+                bounded_distance(&chars, &other, max).map(|distance| (distance, w))
+            })
+            .collect::<Vec<_>>();
+        close.sort();
+        close.dedup();
+        close
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, w)| w.to_string())
+            .collect()
+    }
+
+    /// The suggestions for a likely typo, or `None`
+    /// when the word is known or far from any known word.
+    pub fn check(&self, word: &str) -> Option<Vec<String>> {
+        let word = word.to_lowercase();
+        if let Some(result) = self.cache.borrow().get(&word) {
+            return result.clone();
+        }
+        let result = if self.is_known(&word) {
+            None
+        } else {
+            Some(self.suggestions(&word)).filter(|s| !s.is_empty())
+        };
+        self.cache.borrow_mut().insert(word, result.clone());
+        result
+    }
+}
+
+/// Whether a word is checked: three letters at least, and no inner capital.
+fn is_checked(word: &str) -> bool {
+    word.len() >= 3
+        && word.chars().all(|c| c.is_ascii_alphabetic() || c == '\'')
+        && !word.chars().skip(1).any(|c| c.is_ascii_uppercase())
+}
+
+/// The parts of a text separated by whitespace, with their byte offsets.
+fn chunks(text: &str) -> Vec<(usize, &str)> {
+    let mut chunks = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                chunks.push((s, &text[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        chunks.push((s, &text[s..]));
+    }
+    chunks
+}
+
+/// The words to check in a field value, with their byte ranges
+/// in the file, given the offset of the value.
+pub fn value_words(value: &str, offset: usize) -> Vec<(Range<usize>, &str)> {
+    let mut words = vec![];
+    let mut math = false;
+    for token in join_escapes(tokenize_value(value, 0)) {
+        // only the text that is not protected by braces
+        if token.kind != ValueTokenKind::Text || token.depth != 1 {
+            continue;
+        }
+        let text = &value[token.span.clone()];
+        let after_group = value[..token.span.start].ends_with('}');
+        let before_group = value[token.span.end..].starts_with('{');
+        for (start, chunk) in chunks(text) {
+            let dollars = chunk.matches('$').count() - chunk.matches("\\$").count();
+            let glued =
+                (start == 0 && after_group) || (start + chunk.len() == text.len() && before_group);
+            let skipped = math || dollars > 0 || glued || chunk.contains('\\');
+            if dollars % 2 == 1 {
+                math = !math;
+            }
+            if skipped {
+                continue;
+            }
+            let trimmed = chunk.trim_start_matches(|c: char| !c.is_alphanumeric());
+            let mut part_start = token.span.start + start + chunk.len() - trimmed.len();
+            let trimmed = trimmed.trim_end_matches(|c: char| !c.is_alphanumeric());
+            for part in trimmed.split('-') {
+                if is_checked(part) {
+                    words.push((offset + part_start..offset + part_start + part.len(), part));
+                }
+                part_start += part.len() + 1;
+            }
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        let distance = |a: &str, b: &str| {
+            let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+            bounded_distance(&a, &b, 2)
+        };
+        assert_eq!(distance("learning", "learning"), Some(0));
+        assert_eq!(distance("learnign", "learning"), Some(2));
+        assert_eq!(distance("netwrok", "network"), Some(2));
+        assert_eq!(distance("grap", "graph"), Some(1));
+        assert_eq!(distance("", "abc"), None);
+    }
+
+    fn words(value: &str) -> Vec<&str> {
+        value_words(value, 0)
+            .into_iter()
+            .map(|(span, word)| {
+                assert_eq!(&value[span], word);
+                word
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_value_words() {
+        assert_eq!(
+            words("{Deep {RNA} learning, with $\\mathcal{O}(n)$ bounds}"),
+            vec!["Deep", "learning", "with", "bounds"]
+        );
+        assert_eq!(
+            words("{Erd\\H{o}s and the \\emph{non-trivial} LaTeX case of GPT4}"),
+            vec!["and", "the", "case"]
+        );
+        assert_eq!(
+            words("{Self-supervised \"robust\" models.}"),
+            vec!["Self", "supervised", "robust", "models"]
+        );
+        // macros are not checked
+        assert_eq!(words("jacm"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_check() {
+        let checker = SpellChecker::new(vec!["Coalgebraic".to_string()]);
+        assert_eq!(checker.check("Learning"), None);
+        assert_eq!(checker.check("networks"), None);
+        assert_eq!(checker.check("studies"), None);
+        assert_eq!(checker.check("coalgebraic"), None);
+        assert_eq!(checker.check("lerning"), Some(vec!["learning".to_string()]));
+        let suggestions = checker.check("netwrok").unwrap();
+        assert!(suggestions.len() <= MAX_SUGGESTIONS);
+        assert_eq!(suggestions[0], "network");
+        // far from every known word
+        assert_eq!(checker.check("zxqvbk"), None);
+        // cached
+        assert_eq!(checker.cache.borrow().len(), 7);
+    }
+
+    #[test]
+    fn test_parse_dictionary() {
+        assert_eq!(
+            parse_dictionary("# my words\nCoalgebra\n\n bisimilar # adjective\n"),
+            vec!["coalgebra", "bisimilar"]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! This file is responsible for partitioning
+//! a bibliography into several files, according
+//! to the year, the type, or the value of a given
+//! field of the entries.
+//!
+//! Every part contains the `@string` definitions
+//! that its entries need, so that each file can be
+//! used on its own. When several files are split
+//! together, the entries keep the definitions of
+//! their own file (or of the files before it), and
+//! a string they all define alike is written once
+//! per part. A part holding entries that need two
+//! definitions of a string is reported by `conflicts`.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::bibtex::{BibEntry, BibFile};
+
+/// The part of the entries missing the split field.
+pub const UNKNOWN_BUCKET: &str = "unknown";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitBy {
+    Year,
+    Type,
+    /// Comma separated values of a field (e.g. keywords)
+    Field(String),
+}
+
+impl FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "year" => Ok(SplitBy::Year),
+            "type" => Ok(SplitBy::Type),
+            _ => match s.strip_prefix("field:") {
+                Some(name) if !name.is_empty() => Ok(SplitBy::Field(name.to_lowercase())),
+                _ => Err(format!(
+                    "Cannot split by {:?} (expected year, type, or field:<name>)",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// Makes a value usable as a file name.
+pub fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Names of the parts an entry belongs to.
+pub fn bucket_names(bib: &BibFile, entry: &BibEntry, by: &SplitBy) -> Vec<String> {
+    let field = |name: &str| {
+        entry
+            .fields
+            .iter()
+            .find(|f| bib.get_slice(f.name).eq_ignore_ascii_case(name))
+            .map(|f| bib.get_braceless_slice(f.value).trim())
+    };
+    let values: Vec<&str> = match by {
+        SplitBy::Year => field("year").into_iter().collect(),
+        SplitBy::Type => vec![bib.get_slice(entry.entrytype).trim_start_matches('@')],
+        SplitBy::Field(name) => field(name)
+            .map(|v| v.split(',').map(|k| k.trim()).collect())
+            .unwrap_or_default(),
+    };
+    let mut names: Vec<String> = vec![];
+    for value in values {
+        let name = sanitize(&value.to_lowercase());
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.is_empty() {
+        names.push(UNKNOWN_BUCKET.to_string());
+    }
+    names
+}
+
+/// The parts of one or several files, filled by `add`.
+#[derive(Debug, Default)]
+pub struct Parts {
+    /// The distinct `@string` definitions of all the files, by
+    /// (lowercase) name in the order of their first definition,
+    /// with the names they refer to.
+    strings: Vec<(String, String, Vec<String>)>,
+    /// The definition of every name when the last file was added.
+    defined: HashMap<String, usize>,
+    /// The definitions used by the entries of every part, and
+    /// the entries (in the order of the files).
+    buckets: BTreeMap<String, (BTreeSet<usize>, Vec<String>)>,
+}
+
+impl Parts {
+    /// Adds the strings and the entries of a file to the parts.
+    pub fn add<'a>(&mut self, bib: &'a BibFile<'a>, by: &SplitBy) {
+        for s in bib.list_string_definitions() {
+            let name = bib.get_slice(s.name).to_lowercase();
+            let references = bib
+                .macro_references(s.value)
+                .into_iter()
+                .map(|r| r.to_lowercase())
+                .collect();
+            let text = bib.get_slice(s.loc);
+            let index = match self
+                .strings
+                .iter()
+                .position(|(n, t, _)| *n == name && t == text)
+            {
+                Some(index) => index,
+                None => {
+                    self.strings
+                        .push((name.clone(), text.to_string(), references));
+                    self.strings.len() - 1
+                }
+            };
+            self.defined.insert(name, index);
+        }
+
+        for entry in bib.list_entries() {
+            let used = entry
+                .fields
+                .iter()
+                .flat_map(|f| bib.macro_references(f.value))
+                .map(|n| n.to_lowercase())
+                .collect::<Vec<_>>();
+            let used = self.definitions(used);
+            for name in bucket_names(bib, &entry, by) {
+                let bucket = self.buckets.entry(name).or_default();
+                bucket.0.extend(used.iter().copied());
+                bucket.1.push(bib.get_slice(entry.loc).to_string());
+            }
+        }
+    }
+
+    /// The current definitions of the names, and of the names
+    /// they refer to (strings may themselves refer to other strings).
+    fn definitions(&self, mut names: Vec<String>) -> BTreeSet<usize> {
+        let mut used = BTreeSet::new();
+        while let Some(name) = names.pop() {
+            let Some(&index) = self.defined.get(&name) else {
+                continue;
+            };
+            if used.insert(index) {
+                names.extend(self.strings[index].2.iter().cloned());
+            }
+        }
+        used
+    }
+
+    /// The parts whose entries need several definitions of a
+    /// string, with its name. Only the last one is effective
+    /// in the written part.
+    pub fn conflicts(&self) -> Vec<(&str, &str)> {
+        let mut conflicts = vec![];
+        for (part, (used, _)) in self.buckets.iter() {
+            let mut names = HashSet::new();
+            for &index in used.iter() {
+                let name = self.strings[index].0.as_str();
+                if !names.insert(name) && !conflicts.contains(&(part.as_str(), name)) {
+                    conflicts.push((part.as_str(), name));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The content of every part: the `@string` definitions
+    /// needed by its entries followed by its entries.
+    pub fn contents(self) -> BTreeMap<String, String> {
+        let strings = self.strings;
+        self.buckets
+            .into_iter()
+            .map(|(name, (used, entries))| {
+                let content = used
+                    .iter()
+                    .map(|&index| strings[index].1.as_str())
+                    .chain(entries.iter().map(|e| e.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                (name, content + "\n")
+            })
+            .collect()
+    }
+}
+
+/// Partitions the file, returning for each part the
+/// `@string` definitions needed by its entries followed
+/// by its entries (in the order of the file).
+pub fn split_bibfile<'a>(bib: &'a BibFile<'a>, by: &SplitBy) -> BTreeMap<String, String> {
+    let mut parts = Parts::default();
+    parts.add(bib, by);
+    parts.contents()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_by() {
+        assert_eq!("year".parse(), Ok(SplitBy::Year));
+        assert_eq!("type".parse(), Ok(SplitBy::Type));
+        assert_eq!(
+            "field:Keywords".parse(),
+            Ok(SplitBy::Field("keywords".to_string()))
+        );
+        assert!("field:".parse::<SplitBy>().is_err());
+        assert!("author".parse::<SplitBy>().is_err());
+    }
+
+    #[test]
+    fn test_strings_written_once() {
+        let first = BibFile::new("@string{acm = {ACM}}\n@misc{a, year = 2020, journal = acm}\n");
+        let second = BibFile::new(
+            "@string{acm = {ACM}}\n@string{pacm = \"Proc. \" # acm}\n@misc{b, year = 2020, journal = pacm}\n@misc{c, year = 2021}\n",
+        );
+        let mut parts = Parts::default();
+        parts.add(&first, &SplitBy::Year);
+        parts.add(&second, &SplitBy::Year);
+        let contents = parts.contents();
+        assert_eq!(
+            contents["2020"],
+            "@string{acm = {ACM}}\n\n@string{pacm = \"Proc. \" # acm}\n\n@misc{a, year = 2020, journal = acm}\n\n@misc{b, year = 2020, journal = pacm}\n"
+        );
+        assert_eq!(contents["2021"], "@misc{c, year = 2021}\n");
+    }
+
+    #[test]
+    fn test_strings_defined_differently() {
+        let first = BibFile::new("@string{acm = {ACM}}\n@misc{a, year = 2020, journal = acm}\n");
+        let second = BibFile::new(
+            "@string{acm = {Assoc.}}\n@misc{b, year = 2021, journal = acm}\n@misc{c, year = 2020, journal = acm}\n",
+        );
+        let mut parts = Parts::default();
+        parts.add(&first, &SplitBy::Year);
+        parts.add(&second, &SplitBy::Year);
+        assert_eq!(parts.conflicts(), vec![("2020", "acm")]);
+        let contents = parts.contents();
+        // each entry keeps the definition of its file
+        assert_eq!(
+            contents["2021"],
+            "@string{acm = {Assoc.}}\n\n@misc{b, year = 2021, journal = acm}\n"
+        );
+        assert!(contents["2020"].starts_with("@string{acm = {ACM}}\n\n@string{acm = {Assoc.}}"));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("2023"), "2023");
+        assert_eq!(sanitize("model checking"), "model_checking");
+        assert_eq!(sanitize("../etc"), "___etc");
+    }
+}
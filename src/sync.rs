@@ -0,0 +1,254 @@
+//! This file is responsible for `bibadac sync refs.bib --dir papers/`:
+//! comparing the `filename` and `sha256` fields of the entries
+//! (as written by `bibadac setup`) with the PDFs of a directory.
+//!
+//! A file is matched by name, ignoring the directory recorded in
+//! `filename` (the directory may have moved), and by hash when the
+//! named file is missing, which finds the renamed files. The
+//! matching (`plan`) works on a listing of the directory, so that
+//! it can be tested without touching the filesystem.
+use std::collections::HashSet;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::bibtex::BibFile;
+use crate::pdf_identifiers::list_pdfs;
+
+/// The file an entry points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedEntry {
+    pub key: String,
+    /// The `filename` field, with the byte range of its value (braces excluded)
+    pub filename: Option<(String, Range<usize>)>,
+    pub sha256: Option<String>,
+}
+
+/// A file of the directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirFile {
+    /// Name of the file in the directory
+    pub name: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport<'a> {
+    /// Entries whose file is neither in the directory nor found by hash
+    pub missing: Vec<&'a LinkedEntry>,
+    /// Files that no entry points to
+    pub unreferenced: Vec<&'a DirFile>,
+    /// Entries whose file changed since its sha256 was recorded
+    pub changed: Vec<(&'a LinkedEntry, &'a DirFile)>,
+    /// Entries whose file is in the directory under another name
+    pub renamed: Vec<(&'a LinkedEntry, &'a DirFile)>,
+}
+
+impl SyncReport<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+            && self.unreferenced.is_empty()
+            && self.changed.is_empty()
+            && self.renamed.is_empty()
+    }
+}
+
+/// The entries having a `filename` or a `sha256` field.
+pub fn linked_entries(bib: &BibFile) -> Vec<LinkedEntry> {
+    bib.list_entries()
+        .filter_map(|entry| {
+            let mut filename = None;
+            let mut sha256 = None;
+            for field in entry.fields.iter() {
+                let mut range = field.value.byte_range();
+                let raw = bib.get_slice(field.value);
+                if raw.len() >= 2 && (raw.starts_with('{') || raw.starts_with('"')) {
+                    range = range.start + 1..range.end - 1;
+                }
+                let value = &bib.content[range.clone()];
+                match bib.get_slice(field.name).to_lowercase().as_str() {
+                    "filename" => filename = Some((value.to_string(), range)),
+                    "sha256" => sha256 = Some(value.to_lowercase()),
+                    _ => {}
+                }
+            }
+            if filename.is_none() && sha256.is_none() {
+                return None;
+            }
+            Some(LinkedEntry {
+                key: bib.get_slice(entry.key).to_string(),
+                filename,
+                sha256,
+            })
+        })
+        .collect()
+}
+
+/// The PDFs of the directory, with their sha256.
+pub fn list_dir(dir: &Path) -> io::Result<Vec<DirFile>> {
+    use sha2::Digest;
+    list_pdfs(dir)?
+        .iter()
+        .map(|path| {
+            let bytes = std::fs::read(path)?;
+            Ok(DirFile {
+                name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                sha256: format!("{:x}", sha2::Sha256::digest(&bytes)),
+            })
+        })
+        .collect()
+}
+
+fn base_name(filename: &str) -> &str {
+    Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(filename)
+}
+
+/// Matches the entries with the files of the directory.
+/// An entry with a `sha256` but no `filename` points
+/// to the file with this hash, if any.
+pub fn plan<'a>(entries: &'a [LinkedEntry], files: &'a [DirFile]) -> SyncReport<'a> {
+    let mut report = SyncReport::default();
+    let mut referenced = HashSet::new();
+    for entry in entries {
+        let by_hash = entry
+            .sha256
+            .as_ref()
+            .and_then(|sha| files.iter().position(|f| &f.sha256 == sha));
+        let Some((filename, _)) = &entry.filename else {
+            referenced.extend(by_hash);
+            continue;
+        };
+        let by_name = files.iter().position(|f| f.name == base_name(filename));
+        match (by_name, by_hash) {
+            (Some(i), _) => {
+                referenced.insert(i);
+                if entry
+                    .sha256
+                    .as_ref()
+                    .is_some_and(|sha| sha != &files[i].sha256)
+                {
+                    report.changed.push((entry, &files[i]));
+                }
+            }
+            (None, Some(i)) => {
+                referenced.insert(i);
+                report.renamed.push((entry, &files[i]));
+            }
+            (None, None) => report.missing.push(entry),
+        }
+    }
+    report.unreferenced = files
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !referenced.contains(i))
+        .map(|(_, f)| f)
+        .collect();
+    report
+}
+
+/// Points the `filename` fields of the renamed entries to their
+/// new name, keeping the directory they were recorded with.
+pub fn rename_files(source: &str, report: &SyncReport) -> String {
+    let mut renames = report
+        .renamed
+        .iter()
+        .filter_map(|(entry, file)| {
+            let (filename, range) = entry.filename.as_ref()?;
+            let renamed = Path::new(filename).with_file_name(&file.name);
+            Some((range.clone(), renamed.display().to_string()))
+        })
+        .collect::<Vec<_>>();
+    renames.sort_by_key(|(range, _)| range.start);
+    let mut out = String::new();
+    let mut last = 0;
+    for (range, renamed) in renames {
+        out.push_str(&source[last..range.start]);
+        out.push_str(&renamed);
+        last = range.end;
+    }
+    out.push_str(&source[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, filename: Option<&str>, sha256: Option<&str>) -> LinkedEntry {
+        LinkedEntry {
+            key: key.into(),
+            filename: filename.map(|f| (f.into(), 0..0)),
+            sha256: sha256.map(|s| s.into()),
+        }
+    }
+
+    fn file(name: &str, sha256: &str) -> DirFile {
+        DirFile {
+            name: name.into(),
+            sha256: sha256.into(),
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let entries = vec![
+            entry("same", Some("/old/papers/a.pdf"), Some("aaa")),
+            entry("changed", Some("papers/b.pdf"), Some("bbb")),
+            entry("renamed", Some("papers/c.pdf"), Some("ccc")),
+            entry("missing", Some("papers/d.pdf"), Some("ddd")),
+            entry("hash-only", None, Some("fff")),
+        ];
+        let files = vec![
+            file("a.pdf", "aaa"),
+            file("b.pdf", "b2b2b2"),
+            file("c-renamed.pdf", "ccc"),
+            file("e.pdf", "eee"),
+            file("f.pdf", "fff"),
+        ];
+        let report = plan(&entries, &files);
+        assert_eq!(report.missing, vec![&entries[3]]);
+        assert_eq!(report.unreferenced, vec![&files[3]]);
+        assert_eq!(report.changed, vec![(&entries[1], &files[1])]);
+        assert_eq!(report.renamed, vec![(&entries[2], &files[2])]);
+        assert!(plan(&entries[..1], &files[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_linked_entries() {
+        let source =
+            "@mapping{k, sha256 = {ABC}, filename = {papers/a.pdf}}\n@misc{other, title = {T}}\n";
+        let entries = linked_entries(&BibFile::new(source));
+        let start = source.find("papers").unwrap();
+        assert_eq!(
+            entries,
+            vec![LinkedEntry {
+                key: "k".into(),
+                filename: Some(("papers/a.pdf".into(), start..start + "papers/a.pdf".len())),
+                sha256: Some("abc".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rename_files() {
+        let source = "@mapping{k,\n  sha256 = {ccc},\n  filename = {papers/c.pdf},\n}\n";
+        let start = source.find("papers/c.pdf").unwrap();
+        let entries = vec![LinkedEntry {
+            key: "k".into(),
+            filename: Some(("papers/c.pdf".into(), start..start + "papers/c.pdf".len())),
+            sha256: Some("ccc".into()),
+        }];
+        let files = vec![file("c-renamed.pdf", "ccc")];
+        assert_eq!(
+            rename_files(source, &plan(&entries, &files)),
+            "@mapping{k,\n  sha256 = {ccc},\n  filename = {papers/c-renamed.pdf},\n}\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! This file is responsible for the timings of `bibadac check --timings`,
+//! to tell which part of the check is slow on a given file: the parsing,
+//! the collection of the entries (and of the maps of their keys and
+//! identifiers), the lints of every entry, or the lints of the whole
+//! file (syntax errors, duplicates, preprints, ...).
+//!
+//! The phases are timed with a `Clock` that never reads the time when
+//! the timings are not recorded.
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// The time spent on a file, in milliseconds, and its size.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FileTimings {
+    pub file: String,
+    pub parse_ms: f64,
+    pub collection_ms: f64,
+    pub entry_lints_ms: f64,
+    pub file_lints_ms: f64,
+    /// The wall time of the whole check of the file
+    pub total_ms: f64,
+    /// The nodes of the syntax tree
+    pub nodes: usize,
+    pub entries: usize,
+}
+
+impl FileTimings {
+    /// The time of the phases, which is the total but for the bookkeeping.
+    pub fn phases_ms(&self) -> f64 {
+        self.parse_ms + self.collection_ms + self.entry_lints_ms + self.file_lints_ms
+    }
+}
+
+/// A clock that is only read when enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock(Option<Instant>);
+
+impl Clock {
+    pub fn start(enabled: bool) -> Self {
+        Clock(enabled.then(Instant::now))
+    }
+
+    /// The milliseconds since the last lap (or the start), 0 when disabled.
+    pub fn lap(&mut self) -> f64 {
+        match &mut self.0 {
+            Some(last) => {
+                let now = Instant::now();
+                let elapsed = now.duration_since(*last);
+                *last = now;
+                elapsed.as_secs_f64() * 1000.0
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// The timings of the files, one per line, slowest first.
+pub fn timings_table(timings: &[FileTimings]) -> String {
+    let mut sorted = timings.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms));
+    let mut out = format!(
+        "{:>9} {:>9} {:>9} {:>9} {:>9} {:>8} {:>7}  {}\n",
+        "parse", "collect", "entries", "file", "total", "nodes", "entries", "file"
+    );
+    for t in sorted {
+        out.push_str(&format!(
+            "{:>7.1}ms {:>7.1}ms {:>7.1}ms {:>7.1}ms {:>7.1}ms {:>8} {:>7}  {}\n",
+            t.parse_ms,
+            t.collection_ms,
+            t.entry_lints_ms,
+            t.file_lints_ms,
+            t.total_ms,
+            t.nodes,
+            t.entries,
+            t.file
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut disabled = Clock::start(false);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(disabled.lap(), 0.0);
+
+        let mut clock = Clock::start(true);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(clock.lap() >= 2.0);
+    }
+
+    #[test]
+    fn test_timings_table() {
+        let timing = |file: &str, total_ms: f64| FileTimings {
+            file: file.to_string(),
+            parse_ms: 1.5,
+            total_ms,
+            nodes: 120,
+            entries: 4,
+            ..FileTimings::default()
+        };
+        let table = timings_table(&[timing("fast.bib", 2.0), timing("slow.bib", 30.0)]);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("parse"));
+        assert!(lines[1].ends_with("slow.bib"));
+        assert!(lines[1].contains("    1.5ms"));
+        assert!(lines[1].contains("   30.0ms      120       4"));
+        assert!(lines[2].ends_with("fast.bib"));
+    }
+}
@@ -0,0 +1,356 @@
+//! This file is responsible for the titles written entirely in
+//! uppercase (`THE COMPLEXITY OF DISTRIBUTED ALGORITHMS`) or entirely
+//! in lowercase, as some databases export them, which look wrong in
+//! any style (`title-case-suspicious`), and for their recasing by
+//! `bibadac format --fix-title-case sentence|title`.
+//!
+//! Only the words that a style may recase are considered: the groups
+//! protected by braces (`{RNA}`), the math (`$n$`), the commands
+//! (`\emph`), and the words glued to them (`Erd\H{o}s`) are left as
+//! they are, and so are the words with digits (`GPT4`), with mixed
+//! case (`LaTeX`), and the uppercase roman numerals (`II`). A title is
+//! suspicious when at least two of its words (of two letters or more)
+//! can be recased, and all of them are in the same case (a lowercase
+//! title also starts with a word that can be recased).
+//!
+//! Recasing loses the proper nouns of the uppercase titles, so that it
+//! is opt-in, and only changes the suspicious titles: the recased
+//! titles are not suspicious anymore, and formatting them again
+//! changes nothing.
+use std::ops::Range;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::bibtex::{join_escapes, tokenize_value, ValueTokenKind};
+
+/// The words kept in lowercase in title case,
+/// unless they start the title or a subtitle.
+const SMALL_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "nor", "of", "on",
+    "onto", "or", "over", "per", "so", "the", "to", "up", "via", "vs", "with", "yet",
+];
+
+/// The characters after which a subtitle starts.
+const SUBTITLE_MARKS: &[char] = &[':', '?', '!'];
+
+/// How a suspicious title is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TitleCaseKind {
+    AllUpper,
+    AllLower,
+}
+
+/// How `bibadac format --fix-title-case` recases the titles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleCase {
+    /// `The complexity of distributed algorithms`
+    Sentence,
+    /// `The Complexity of Distributed Algorithms`
+    Title,
+}
+
+/// A word of a title (letters, digits, and apostrophes).
+struct Word {
+    /// Byte range in the value
+    span: Range<usize>,
+    /// Whether it starts the title or a subtitle
+    first: bool,
+    /// Whether a style may recase it
+    recasable: bool,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\'' || c == '\u{2019}'
+}
+
+/// Whether a word is kept as it is: with digits, with mixed case
+/// (an uppercase letter after a lowercase one, or after the first
+/// letter of a word with lowercase letters), or an uppercase roman
+/// numeral.
+fn is_kept(word: &str) -> bool {
+    let has_lower = word.chars().any(char::is_lowercase);
+    let inner_upper = word.chars().skip(1).any(char::is_uppercase);
+    word.chars().any(|c| c.is_numeric())
+        || (has_lower && inner_upper)
+        || word.chars().all(|c| "IVX".contains(c))
+}
+
+/// The words of a value (as written, with its delimiters), in order,
+/// none for the macros and concatenations.
+fn title_words(value: &str) -> Vec<Word> {
+    let mut words = vec![];
+    let mut math = false;
+    let mut first = true;
+    for token in join_escapes(tokenize_value(value, 0)) {
+        if token.depth == 0
+            && matches!(
+                token.kind,
+                ValueTokenKind::MacroRef | ValueTokenKind::Concat
+            )
+        {
+            return vec![];
+        }
+        if token.depth != 1 {
+            continue;
+        }
+        if token.kind != ValueTokenKind::Text {
+            // a protected group
+            first = false;
+            continue;
+        }
+        let text = &value[token.span.clone()];
+        let after_group = value[..token.span.start].ends_with('}');
+        let before_group = value[token.span.end..].starts_with('{');
+        let mut offset = 0;
+        for chunk in text.split_inclusive(char::is_whitespace) {
+            let start = offset;
+            offset += chunk.len();
+            let chunk = chunk.trim_end();
+            if chunk.is_empty() {
+                continue;
+            }
+            let dollars = chunk.matches('$').count() - chunk.matches("\\$").count();
+            let glued =
+                (start == 0 && after_group) || (start + chunk.len() == text.len() && before_group);
+            let skipped = math || dollars > 0 || glued || chunk.contains('\\');
+            if dollars % 2 == 1 {
+                math = !math;
+            }
+            let mut rest = chunk;
+            while let Some(i) = rest.find(is_word_char) {
+                let word_start = token.span.start + start + chunk.len() - rest.len() + i;
+                let length = rest[i..]
+                    .find(|c| !is_word_char(c))
+                    .unwrap_or(rest.len() - i);
+                let word = &value[word_start..word_start + length];
+                words.push(Word {
+                    span: word_start..word_start + length,
+                    first,
+                    recasable: !skipped && !is_kept(word),
+                });
+                rest = &rest[i + length..];
+                // the punctuation up to the next word
+                let end = rest.find(is_word_char).unwrap_or(rest.len());
+                first = rest[..end].contains(SUBTITLE_MARKS);
+            }
+            if skipped && chunk.find(is_word_char).is_none() {
+                first = false;
+            }
+        }
+    }
+    words
+}
+
+/// Whether the title (as written, with its delimiters) is all
+/// uppercase or all lowercase. A lowercase title also starts with a
+/// lowercase word, since `{RNA} sequencing` is in sentence case.
+pub fn title_case_kind(value: &str) -> Option<TitleCaseKind> {
+    let words = title_words(value);
+    let lowercase_start = words.first().is_some_and(|w| {
+        w.first && w.recasable && value[w.span.clone()].starts_with(char::is_lowercase)
+    });
+    let recasable = words
+        .iter()
+        .filter(|w| w.recasable)
+        .map(|w| &value[w.span.clone()])
+        .filter(|w| w.chars().filter(|c| c.is_alphabetic()).count() >= 2)
+        .collect::<Vec<_>>();
+    if recasable.len() < 2 {
+        return None;
+    }
+    let letters = || {
+        recasable
+            .iter()
+            .flat_map(|w| w.chars())
+            .filter(|c| c.is_alphabetic())
+    };
+    if letters().all(char::is_uppercase) {
+        Some(TitleCaseKind::AllUpper)
+    } else if lowercase_start && letters().all(char::is_lowercase) {
+        Some(TitleCaseKind::AllLower)
+    } else {
+        None
+    }
+}
+
+/// The word with its first letter in uppercase, and the others in lowercase.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(c) => c
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// The title (as written, with its delimiters) recased in the
+/// given style, or `None` when it is not suspicious.
+pub fn recase_title(value: &str, style: TitleCase) -> Option<String> {
+    title_case_kind(value)?;
+    let mut out = String::with_capacity(value.len());
+    let mut last = 0;
+    for word in title_words(value).into_iter().filter(|w| w.recasable) {
+        let text = &value[word.span.clone()];
+        let lowercase = text.to_lowercase();
+        let recased = match style {
+            TitleCase::Sentence if word.first => capitalize(text),
+            TitleCase::Sentence => lowercase,
+            TitleCase::Title if word.first => capitalize(text),
+            TitleCase::Title if SMALL_WORDS.contains(&lowercase.as_str()) => lowercase,
+            TitleCase::Title => capitalize(text),
+        };
+        out.push_str(&value[last..word.span.start]);
+        out.push_str(&recased);
+        last = word.span.end;
+    }
+    out.push_str(&value[last..]);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TitleCase::*;
+    use TitleCaseKind::*;
+
+    #[test]
+    fn test_title_case_kind() {
+        let kind = |title: &str| title_case_kind(&format!("{{{}}}", title));
+        assert_eq!(
+            kind("THE COMPLEXITY OF DISTRIBUTED ALGORITHMS"),
+            Some(AllUpper)
+        );
+        assert_eq!(
+            kind("the complexity of distributed algorithms"),
+            Some(AllLower)
+        );
+        assert_eq!(kind("The Complexity of Distributed Algorithms"), None);
+        assert_eq!(kind("The complexity of distributed algorithms"), None);
+        // a single word, or an acronym
+        assert_eq!(kind("ALGORITHMS"), None);
+        assert_eq!(kind("T"), None);
+        assert_eq!(kind("A B"), None);
+        // the protected groups, math, and commands are not considered
+        assert_eq!(kind("{The} {Complexity} OF ALGORITHMS"), Some(AllUpper));
+        assert_eq!(kind("{The Complexity of Algorithms}"), None);
+        assert_eq!(kind("LEARNING $\\mathcal{O}(n)$ BOUNDS"), Some(AllUpper));
+        assert_eq!(kind("The \\emph{LTL} PROBLEM"), None);
+        // the kept words do not count
+        assert_eq!(kind("LaTeX FOR BEGINNERS"), Some(AllUpper));
+        assert_eq!(kind("GPT4 and gpt5 EVALS"), None);
+        assert_eq!(kind("ALGORITHMS II"), None);
+        assert_eq!(kind("deep learning with pytorch 2"), Some(AllLower));
+        // in sentence case
+        assert_eq!(kind("{RNA} sequencing of cells"), None);
+        assert_eq!(kind("iPhone apps for kids"), None);
+        assert_eq!(title_case_kind("\"DEEP {RNA} LEARNING\""), Some(AllUpper));
+        assert_eq!(title_case_kind("ieee # \" TRANSACTIONS ON LOGIC\""), None);
+        assert_eq!(title_case_kind("{}"), None);
+    }
+
+    fn recase(title: &str, style: TitleCase) -> String {
+        let value = format!("{{{}}}", title);
+        let recased = recase_title(&value, style).unwrap_or(value);
+        // recasing is idempotent
+        assert_eq!(recase_title(&recased, style), None, "{}", recased);
+        recased[1..recased.len() - 1].to_string()
+    }
+
+    #[test]
+    fn test_recase() {
+        let title = "THE COMPLEXITY OF DISTRIBUTED ALGORITHMS";
+        assert_eq!(
+            recase(title, Sentence),
+            "The complexity of distributed algorithms"
+        );
+        assert_eq!(
+            recase(title, Title),
+            "The Complexity of Distributed Algorithms"
+        );
+        let title = "the complexity of distributed algorithms";
+        assert_eq!(
+            recase(title, Sentence),
+            "The complexity of distributed algorithms"
+        );
+        assert_eq!(
+            recase(title, Title),
+            "The Complexity of Distributed Algorithms"
+        );
+        // the titles that are not suspicious are left as they are
+        assert_eq!(recase("On the LTL Problem", Sentence), "On the LTL Problem");
+        assert_eq!(recase("ALGORITHMS", Title), "ALGORITHMS");
+    }
+
+    #[test]
+    fn test_recase_tricky_titles() {
+        let cases = [
+            (
+                "{RNA} SEQUENCING OF {E. coli}",
+                "{RNA} sequencing of {E. coli}",
+                "{RNA} Sequencing of {E. coli}",
+            ),
+            (
+                "A NOTE ON $\\omega$-REGULAR LANGUAGES",
+                "A note on $\\omega$-REGULAR languages",
+                "A Note on $\\omega$-REGULAR Languages",
+            ),
+            (
+                "REAL-TIME STATE-OF-THE-ART SYSTEMS",
+                "Real-time state-of-the-art systems",
+                "Real-Time State-of-the-Art Systems",
+            ),
+            (
+                "DISTRIBUTED ALGORITHMS: A SURVEY",
+                "Distributed algorithms: A survey",
+                "Distributed Algorithms: A Survey",
+            ),
+            (
+                "WHY IS IT HARD? THE ANSWER OF THE LOGIC",
+                "Why is it hard? The answer of the logic",
+                "Why Is It Hard? The Answer of the Logic",
+            ),
+            (
+                "DON'T STOP PRETRAINING WITH GPT2 AND BERT4REC",
+                "Don't stop pretraining with GPT2 and BERT4REC",
+                "Don't Stop Pretraining with GPT2 and BERT4REC",
+            ),
+            (
+                "LaTeX FOR BEGINNERS, VOLUME II",
+                "LaTeX for beginners, volume II",
+                "LaTeX for Beginners, Volume II",
+            ),
+            (
+                "ON ERD\\H{O}S NUMBERS AND \\emph{GRAPHS}",
+                "On ERD\\H{O}S numbers and \\emph{GRAPHS}",
+                "On ERD\\H{O}S Numbers and \\emph{GRAPHS}",
+            ),
+            (
+                "learning to rank in 3d scenes",
+                "Learning to rank in 3d scenes",
+                "Learning to Rank in 3d Scenes",
+            ),
+            (
+                "über die grenzen der logik",
+                "Über die grenzen der logik",
+                "Über Die Grenzen Der Logik",
+            ),
+            (
+                "THE\nCOMPLEXITY  OF\tLOGIC",
+                "The\ncomplexity  of\tlogic",
+                "The\nComplexity  of\tLogic",
+            ),
+        ];
+        for (title, sentence, title_case) in cases {
+            assert_eq!(recase(title, Sentence), sentence);
+            assert_eq!(recase(title, Title), title_case);
+        }
+        // the delimiters are kept
+        assert_eq!(
+            recase_title("\"MODAL LOGIC\"", Sentence).unwrap(),
+            "\"Modal logic\""
+        );
+    }
+}
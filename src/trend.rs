@@ -0,0 +1,247 @@
+//! This file is responsible for `bibadac trend`, which follows the
+//! number of diagnostics of every rule across the json reports of
+//! successive runs of `bibadac check --to-json`, e.g. to show that
+//! a bibliography gets better over time.
+//!
+//! The reports are ordered by the time they were generated at or,
+//! for the reports of older versions that do not record it, by the
+//! modification time of their file.
+use std::collections::BTreeMap;
+
+use crate::dates::Date;
+use crate::report::JsonReport;
+
+/// The number of diagnostics of every rule across reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trend {
+    /// The name of every report and its time (seconds since the Unix epoch), in order
+    pub reports: Vec<(String, u64)>,
+    /// The counts of every rule, one per report
+    pub rules: BTreeMap<&'static str, Vec<usize>>,
+}
+
+/// The date and time (UTC) of a Unix timestamp, e.g. `2024-03-01 14:05`.
+pub fn label(time: u64) -> String {
+    let date = Date::from_unix_days((time / 86400) as i64);
+    let (hours, minutes) = (time % 86400 / 3600, time % 3600 / 60);
+    format!("{} {:02}:{:02}", date, hours, minutes)
+}
+
+/// The change between two counts, e.g. `+3` or `-2`.
+fn delta(from: usize, to: usize) -> String {
+    if to >= from {
+        format!("+{}", to - from)
+    } else {
+        format!("-{}", from - to)
+    }
+}
+
+/// A field of a csv row, quoted when needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl Trend {
+    /// The trend of the `reports`, given along with
+    /// their name and the modification time of their file.
+    pub fn new(reports: Vec<(String, u64, JsonReport)>) -> Trend {
+        let mut reports = reports
+            .into_iter()
+            .map(|(name, mtime, report)| (name, report.generated_at.unwrap_or(mtime), report))
+            .collect::<Vec<_>>();
+        reports.sort_by(|(a, s, _), (b, t, _)| s.cmp(t).then_with(|| a.cmp(b)));
+        let mut rules: BTreeMap<&'static str, Vec<usize>> = BTreeMap::new();
+        for (i, (_, _, report)) in reports.iter().enumerate() {
+            for reported in report.files.iter().flat_map(|f| f.errors.iter()) {
+                let rule = reported.diagnostic.msg.rule_name();
+                rules.entry(rule).or_insert_with(|| vec![0; reports.len()])[i] += 1;
+            }
+        }
+        Trend {
+            reports: reports
+                .into_iter()
+                .map(|(name, time, _)| (name, time))
+                .collect(),
+            rules,
+        }
+    }
+
+    /// The number of diagnostics of every report.
+    pub fn totals(&self) -> Vec<usize> {
+        (0..self.reports.len())
+            .map(|i| self.rules.values().map(|counts| counts[i]).sum())
+            .collect()
+    }
+
+    /// A table with a row per rule and a column per report,
+    /// along with the change since the first report.
+    pub fn table(&self) -> String {
+        let totals = self.totals();
+        let mut rows = vec![];
+        let mut header = vec!["rule".to_string()];
+        header.extend(self.reports.iter().map(|(_, time)| label(*time)));
+        header.push("delta".to_string());
+        rows.push(header);
+        let counts = self.rules.iter().map(|(rule, counts)| (*rule, counts));
+        for (rule, counts) in counts.chain(std::iter::once(("total", &totals))) {
+            let mut row = vec![rule.to_string()];
+            row.extend(counts.iter().map(|c| c.to_string()));
+            match (counts.first(), counts.last()) {
+                (Some(first), Some(last)) => row.push(delta(*first, *last)),
+                _ => row.push(String::new()),
+            }
+            rows.push(row);
+        }
+        let widths = (0..rows[0].len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        rows.iter()
+            .map(|row| {
+                let cells = row
+                    .iter()
+                    .zip(widths.iter())
+                    .enumerate()
+                    .map(|(i, (cell, w))| {
+                        // the rules on the left, the counts on the right
+                        if i == 0 {
+                            format!("{:<w$}", cell, w = w)
+                        } else {
+                            format!("{:>w$}", cell, w = w)
+                        }
+                    });
+                cells.collect::<Vec<_>>().join("  ").trim_end().to_string() + "\n"
+            })
+            .collect()
+    }
+
+    /// Csv rows `report,generated_at,rule,count,delta`, where the
+    /// delta is the change since the previous report.
+    pub fn csv(&self) -> String {
+        let mut csv = "report,generated_at,rule,count,delta\n".to_string();
+        for (i, (name, time)) in self.reports.iter().enumerate() {
+            for (rule, counts) in self.rules.iter() {
+                let previous = if i == 0 { counts[i] } else { counts[i - 1] };
+                csv += &format!(
+                    "{},{},{},{},{}\n",
+                    csv_field(name),
+                    time,
+                    rule,
+                    counts[i],
+                    delta(previous, counts[i])
+                );
+            }
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::ReportedDiagnostic;
+    use crate::bibliography::Diagnostic;
+    use crate::linter::LintMessage;
+    use crate::report::ReportedFile;
+
+    fn report(generated_at: Option<u64>, messages: Vec<LintMessage>) -> JsonReport {
+        let errors = messages
+            .into_iter()
+            .map(|msg| ReportedDiagnostic {
+                diagnostic: Diagnostic {
+                    msg,
+                    loc: vec![],
+                    severity: None,
+                    fixable: false,
+                },
+                key: None,
+            })
+            .collect();
+        JsonReport {
+            generated_at,
+            files: vec![ReportedFile {
+                file: "refs.bib".into(),
+                errors,
+            }],
+            ..JsonReport::default()
+        }
+    }
+
+    fn trend() -> Trend {
+        let day = 86400;
+        Trend::new(vec![
+            (
+                "new.json".into(),
+                0,
+                report(Some(20 * day + 3600), vec![LintMessage::ByteOrderMark]),
+            ),
+            (
+                // an old report, without its time
+                "old, first.json".into(),
+                10 * day,
+                report(
+                    None,
+                    vec![
+                        LintMessage::ByteOrderMark,
+                        LintMessage::ByteOrderMark,
+                        LintMessage::SyntaxError("@".into()),
+                    ],
+                ),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label(0), "1970-01-01 00:00");
+        assert_eq!(label(1_709_301_900), "2024-03-01 14:05");
+    }
+
+    #[test]
+    fn test_trend() {
+        let trend = trend();
+        assert_eq!(
+            trend.reports,
+            vec![
+                ("old, first.json".to_string(), 10 * 86400),
+                ("new.json".to_string(), 20 * 86400 + 3600),
+            ]
+        );
+        let rule = LintMessage::ByteOrderMark.rule_name();
+        assert_eq!(trend.rules[rule], vec![2, 1]);
+        assert_eq!(trend.totals(), vec![3, 1]);
+    }
+
+    #[test]
+    fn test_trend_output() {
+        let trend = trend();
+        let bom = LintMessage::ByteOrderMark.rule_name();
+        let syntax = LintMessage::SyntaxError(String::new()).rule_name();
+        let table = trend.table();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("1970-01-11 00:00  1970-01-21 01:00  delta"));
+        assert!(lines[1].starts_with(bom) && lines[1].ends_with("-1"));
+        assert!(lines[3].starts_with("total") && lines[3].ends_with("-2"));
+        assert_eq!(
+            trend.csv(),
+            format!(
+                "report,generated_at,rule,count,delta\n\
+                 \"old, first.json\",864000,{bom},2,+0\n\
+                 \"old, first.json\",864000,{syntax},1,+0\n\
+                 new.json,1731600,{bom},1,-1\n\
+                 new.json,1731600,{syntax},0,-1\n",
+                bom = bom,
+                syntax = syntax
+            )
+        );
+    }
+}
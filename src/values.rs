@@ -0,0 +1,192 @@
+//! This file is responsible for deciding when two values of the
+//! same field are the same, so that `title = {Model Checking}`,
+//! `title = {{Model Checking}}`, and `TITLE = "model  checking"` are
+//! duplicates for the linter, the merge, and the database completion.
+//!
+//! A value is compared by its normalized form: the delimiters and
+//! braces are removed (with the value tokenizer, so that escaped braces
+//! are kept), the whitespace is collapsed, and the text is case folded,
+//! except for the verbatim fields (urls, files, eprints). The dois are
+//! case insensitive, and compared without their resolver (`doi.org/`),
+//! and the arXiv eprints are compared without their version.
+//!
+//! The parts of a concatenation (`"Part " # "One"`) are joined, the month
+//! macros are replaced by their value (see `BUILTIN_STRINGS`), and the
+//! months are compared by number, so that `jan`, `{January}`, and `{1}`
+//! are the same month.
+use crate::arxiv_identifiers::ArxivId;
+use crate::author_format::format_authors;
+use crate::bibtex::{group_content, join_escapes, tokenize_value, ValueTokenKind};
+use crate::convert::month_value;
+use crate::doi_identifiers::extract_doi;
+
+/// The fields whose values are compared with their case.
+pub const VERBATIM_FIELDS: [&str; 5] = ["url", "file", "pdf", "eprint", "verba"];
+
+/// The strings that BibTeX predefines, which need no `@string` definition.
+pub const BUILTIN_STRINGS: [(&str, &str); 12] = [
+    ("jan", "January"),
+    ("feb", "February"),
+    ("mar", "March"),
+    ("apr", "April"),
+    ("may", "May"),
+    ("jun", "June"),
+    ("jul", "July"),
+    ("aug", "August"),
+    ("sep", "September"),
+    ("oct", "October"),
+    ("nov", "November"),
+    ("dec", "December"),
+];
+
+/// The value of a predefined string (macro names are case insensitive).
+pub fn builtin_string(name: &str) -> Option<&'static str> {
+    BUILTIN_STRINGS
+        .iter()
+        .find(|(macro_name, _)| macro_name.eq_ignore_ascii_case(name))
+        .map(|(_, value)| *value)
+}
+
+/// The text of a value, as written in the file (with its delimiters):
+/// without delimiters and braces, with the parts of the concatenations
+/// joined, the month macros replaced, and the whitespace collapsed.
+fn value_text(value: &str) -> String {
+    let text = join_escapes(tokenize_value(value, 0))
+        .into_iter()
+        .filter_map(|t| match t.kind {
+            ValueTokenKind::Text => Some(&value[t.span]),
+            ValueTokenKind::MacroRef => {
+                let name = &value[t.span];
+                Some(builtin_string(name).unwrap_or(name))
+            }
+            _ => None,
+        })
+        .collect::<String>();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The normalized form of a value, as written in the file
+/// (with its delimiters), of the field `field`.
+pub fn normalize_value(field: &str, value: &str) -> String {
+    let text = value_text(value);
+    let field = field.to_lowercase();
+    match field.as_str() {
+        "month" => match month_value(&text) {
+            Some(month) => month.to_string(),
+            None => text.to_lowercase(),
+        },
+        "doi" => extract_doi(&text).unwrap_or(&text).to_lowercase(),
+        "eprint" => match ArxivId::try_from(text.as_str()) {
+            Ok(id) => id.id.to_string(),
+            Err(()) => text,
+        },
+        _ if VERBATIM_FIELDS.contains(&field.as_str()) => text,
+        _ => text.to_lowercase(),
+    }
+}
+
+/// The canonical form of a value, which unlike `normalize_value` keeps
+/// the case of the text: only the months (by number), the dois (bare
+/// and in lowercase), and the names of the people (written `Last, First`
+/// as the formatter does) are rewritten.
+pub fn canonical_value(field: &str, value: &str) -> String {
+    let text = value_text(value);
+    match field.to_lowercase().as_str() {
+        "month" => month_value(&text).map_or(text, |month| month.to_string()),
+        "doi" => extract_doi(&text).unwrap_or(&text).to_lowercase(),
+        // the braces keep a name (e.g. `{World Health Organization}`)
+        // in one piece until the names are formatted
+        "author" | "editor" => match group_content(value) {
+            Some(names) => {
+                let names = names.split_whitespace().collect::<Vec<_>>().join(" ");
+                value_text(&format!("{{{}}}", format_authors(&names)))
+            }
+            None => format_authors(&text),
+        },
+        _ => text,
+    }
+}
+
+/// Whether two values of the field `field` are the same (see `normalize_value`).
+pub fn same_value(field: &str, a: &str, b: &str) -> bool {
+    normalize_value(field, a) == normalize_value(field, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_value() {
+        // (field, value, value, same)
+        let matrix = [
+            ("title", "{Model Checking}", "{{Model Checking}}", true),
+            ("title", "{Model Checking}", "\"model  checking\"", true),
+            ("title", "{Model {C}hecking}", "{model\n  checking}", true),
+            ("title", "{Model Checking}", "{Model-Checking}", false),
+            ("title", "{A \\{B\\}}", "{A B}", false),
+            ("month", "jan", "{Jan}", true),
+            ("month", "jan", "{January}", true),
+            ("month", "{1}", "JAN", true),
+            ("month", "\"01\"", "{Jan.}", true),
+            ("month", "jan", "feb", false),
+            ("month", "{13}", "jan", false),
+            ("month", "jan # \"~15\"", "{January~15}", true),
+            ("title", "\"Part \" # \"One\"", "{Part One}", true),
+            ("title", "\"Part\" # \"One\"", "{Part One}", false),
+            ("note", "\"In \" # dec", "{in december}", true),
+            ("year", "2020", "{2020}", true),
+            ("doi", "{10.1145/ABC}", "{10.1145/abc}", true),
+            ("doi", "{10.1145/abc}", "{https://doi.org/10.1145/ABC}", true),
+            ("doi", "{10.1145/abc}", "{10.1145/abd}", false),
+            ("eprint", "{2101.00001v2}", "{2101.00001}", true),
+            ("eprint", "{2101.00001v2}", "{2101.00002v2}", false),
+            ("url", "{https://example.org/A}", "{https://example.org/a}", false),
+            ("url", "{https://example.org/A}", "{ https://example.org/A }", true),
+            ("file", "{Paper.pdf}", "{paper.pdf}", false),
+            ("sha256", "{ABCDEF}", "{abcdef}", true),
+        ];
+        for (field, a, b, same) in matrix {
+            assert_eq!(same_value(field, a, b), same, "{} = {} / {}", field, a, b);
+        }
+        // field names are case insensitive
+        assert!(same_value("DOI", "{10.1/A}", "{10.1/a}"));
+        assert!(!same_value("URL", "{http://a/X}", "{http://a/x}"));
+    }
+
+    #[test]
+    fn test_normalize_value() {
+        assert_eq!(normalize_value("title", "{{Model} Checking}"), "model checking");
+        assert_eq!(normalize_value("eprint", "{math/0101001v1}"), "math/0101001");
+        assert_eq!(normalize_value("eprint", "{not an id}"), "not an id");
+        assert_eq!(normalize_value("month", "sep"), "9");
+        assert_eq!(normalize_value("month", "{Sept.}"), "9");
+        assert_eq!(normalize_value("month", "{Spring}"), "spring");
+        // the strings that are not predefined are left as their name
+        assert_eq!(normalize_value("journal", "pacm # { 3}"), "pacm 3");
+        assert_eq!(builtin_string("Mar"), Some("March"));
+        assert_eq!(builtin_string("acm"), None);
+    }
+
+    #[test]
+    fn test_canonical_value() {
+        assert_eq!(
+            canonical_value("title", "{{DNA}  Sequencing}"),
+            "DNA Sequencing"
+        );
+        assert_eq!(canonical_value("title", "\"Part \" # \"One\""), "Part One");
+        assert_eq!(canonical_value("Month", "{January}"), "1");
+        assert_eq!(
+            canonical_value("doi", "{https://doi.org/10.1/ABC}"),
+            "10.1/abc"
+        );
+        assert_eq!(
+            canonical_value("author", "{Jane Doe and Smith, John}"),
+            "Doe, Jane and Smith, John"
+        );
+        assert_eq!(
+            canonical_value("author", "{{World Health Organization} and Jane Doe}"),
+            "World Health Organization and Doe, Jane"
+        );
+    }
+}
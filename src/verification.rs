@@ -0,0 +1,108 @@
+//! This file is responsible for the review status of the entries,
+//! recorded in a `verified` field such as `{2024-01-10 by alice}`.
+//!
+//! The value is written by hand, so its parsing is tolerant: the
+//! reviewer is what follows the word `by`, and the date is the rest
+//! of the value if it is a date (ISO-8601, or a form that the `dates`
+//! module can rewrite, e.g. `January 10, 2024`), or else the first
+//! word that is one (`checked on 2024-01-10`, `alice, 2024-01-10`).
+use crate::dates::{Date, normalize_date};
+
+/// The field recording when (and by whom) an entry was verified.
+pub const VERIFIED_FIELD: &str = "verified";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub date: Date,
+    pub by: Option<String>,
+}
+
+fn parse_date(s: &str) -> Option<Date> {
+    let s = s
+        .trim()
+        .trim_matches(|c: char| c == ',' || c == ';' || c == ':');
+    Date::parse(s).or_else(|| Date::parse(&normalize_date(s)?))
+}
+
+/// Parses the value of a `verified` field (without its braces),
+/// returning `None` when it contains no date.
+pub fn parse_verification(value: &str) -> Option<Verification> {
+    let value = value.trim();
+    let words = value.split_whitespace().collect::<Vec<_>>();
+    let by_index = words.iter().position(|w| w.eq_ignore_ascii_case("by"));
+    let (when, by) = match by_index {
+        Some(i) => (&words[..i], &words[i + 1..]),
+        None => (&words[..], &[][..]),
+    };
+    let date = parse_date(&when.join(" ")).or_else(|| words.iter().find_map(|w| parse_date(w)))?;
+    let by = by
+        .iter()
+        .filter(|w| parse_date(w).is_none())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let by = by.trim_matches(|c: char| c == ',' || c == ';');
+    Some(Verification {
+        date,
+        by: (!by.is_empty()).then(|| by.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Date {
+        Date::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_verification() {
+        assert_eq!(
+            parse_verification("2024-01-10 by alice"),
+            Some(Verification {
+                date: date("2024-01-10"),
+                by: Some("alice".into())
+            })
+        );
+        assert_eq!(
+            parse_verification("  2024-01-10   BY  Alice Smith "),
+            Some(Verification {
+                date: date("2024-01-10"),
+                by: Some("Alice Smith".into())
+            })
+        );
+        assert_eq!(
+            parse_verification("January 10, 2024 by bob"),
+            Some(Verification {
+                date: date("2024-01-10"),
+                by: Some("bob".into())
+            })
+        );
+        assert_eq!(
+            parse_verification("2024-01-10"),
+            Some(Verification {
+                date: date("2024-01-10"),
+                by: None
+            })
+        );
+        assert_eq!(
+            parse_verification("checked on 2024-01-10, by carol;"),
+            Some(Verification {
+                date: date("2024-01-10"),
+                by: Some("carol".into())
+            })
+        );
+        // the date may come after the reviewer
+        assert_eq!(
+            parse_verification("by alice 2024-01"),
+            Some(Verification {
+                date: date("2024-01"),
+                by: Some("alice".into())
+            })
+        );
+        assert_eq!(parse_verification("by alice"), None);
+        assert_eq!(parse_verification("yes"), None);
+        assert_eq!(parse_verification(""), None);
+    }
+}
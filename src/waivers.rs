@@ -0,0 +1,383 @@
+//! This file is responsible for the comments written right above an
+//! entry to tell bibadac something about it, such as
+//!
+//! ```bibtex
+//! % bibadac-accept: weird-characters sha1=3f0c2a9e61b4
+//! @book{ocr1852,
+//!   title = {Trait\'e des \textit{maladies}},
+//! }
+//! ```
+//!
+//! which waives a message about a value of the entry, but only as long
+//! as the value is the one that was accepted: the waiver records the
+//! digest of the value (the beginning of its sha1, after collapsing the
+//! spaces), so that editing the value brings the message back. Only the
+//! messages about a field are waived, not the ones about a whole entry
+//! (such as a missing field).
+//!
+//! The comments are the `%` lines just above the entry, read with
+//! `comment_directives`. Their `bibadac-<directive>: <argument>` is
+//! parsed by `parse_directive`, as is the one of the include directives
+//! (see `includes`).
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::bibliography::{Bibliography, Diagnostic, Location};
+
+/// The directive of a waiver.
+pub const ACCEPT_DIRECTIVE: &str = "accept";
+
+/// The hex digits of the digest written in the waivers.
+pub const DIGEST_LEN: usize = 12;
+
+/// The first `DIGEST_LEN` hex digits of the sha1 of a value.
+pub fn digest(value: &str) -> String {
+    use sha1::Digest;
+    let digest = format!("{:x}", sha1::Sha1::digest(value.as_bytes()));
+    digest[..DIGEST_LEN].to_string()
+}
+
+/// A `% bibadac-accept: <rule> sha1=<digest>` comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Waiver {
+    pub rule: String,
+    pub digest: String,
+}
+
+impl Waiver {
+    /// Parses the argument of the directive, `<rule> sha1=<digest>`, the
+    /// digest having exactly `DIGEST_LEN` digits.
+    pub fn parse(argument: &str) -> Option<Waiver> {
+        let mut words = argument.split_whitespace();
+        let rule = words.next()?;
+        let digest = words.next()?.strip_prefix("sha1=")?;
+        if words.next().is_some() || digest.len() != DIGEST_LEN {
+            return None;
+        }
+        if !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Waiver {
+            rule: rule.to_string(),
+            digest: digest.to_ascii_lowercase(),
+        })
+    }
+
+    /// The waiver of the messages of `rule` about `value`.
+    pub fn of(rule: &str, value: &str) -> Waiver {
+        Waiver {
+            rule: rule.to_string(),
+            digest: digest(value),
+        }
+    }
+
+    /// Whether the waiver accepts a message of `rule` about `value`.
+    pub fn accepts(&self, rule: &str, value: &str) -> bool {
+        self.rule == rule && digest(value) == self.digest
+    }
+
+    /// The comment line of the waiver.
+    pub fn to_comment(&self) -> String {
+        format!(
+            "% bibadac-{}: {} sha1={}",
+            ACCEPT_DIRECTIVE, self.rule, self.digest
+        )
+    }
+}
+
+/// The directive and the argument of the text of a comment,
+/// `bibadac-<directive>: <argument>`.
+pub fn parse_directive(comment: &str) -> Option<(&str, &str)> {
+    let (directive, argument) = comment.trim().split_once(':')?;
+    let directive = directive.trim_end().strip_prefix("bibadac-")?;
+    Some((directive, argument.trim()))
+}
+
+/// The `% bibadac-<directive>: <argument>` comments on the lines right
+/// above the byte `start` of the source (the start of an entry), as
+/// directives and arguments, from the top.
+pub fn comment_directives(source: &str, start: usize) -> Vec<(&str, &str)> {
+    let before = &source[..start];
+    // the text before the entry on its own line
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    if !before[line_start..].trim().is_empty() {
+        return vec![];
+    }
+    let mut directives = vec![];
+    for line in before[..line_start].lines().rev() {
+        let Some(comment) = line.trim().strip_prefix('%') else {
+            break;
+        };
+        directives.extend(parse_directive(comment));
+    }
+    directives.reverse();
+    directives
+}
+
+/// The value a message is about: the value of the field it is located
+/// at (`name = value`), with its spaces collapsed.
+pub fn located_value(source: &str, loc: &Location) -> Option<String> {
+    let text = source.get(loc.start_byte..loc.end_byte)?;
+    let (_, value) = text.split_once('=')?;
+    let value = value.trim().trim_end_matches(',');
+    Some(value.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// The byte ranges of the fields of a bibliography: the messages
+/// located elsewhere (at a whole entry) are not waived.
+fn field_spans(bib: &Bibliography) -> HashSet<Range<usize>> {
+    bib.with_bibfile(|file| {
+        file.list_entries()
+            .flat_map(|e| e.fields.into_iter().map(|f| f.loc.byte_range()))
+            .collect()
+    })
+}
+
+/// The index of the entry a diagnostic is located in, and the value
+/// of the field it is about.
+fn located(
+    source: &str,
+    entries: &[(Range<usize>, String)],
+    fields: &HashSet<Range<usize>>,
+    diagnostic: &Diagnostic,
+) -> Option<(usize, String)> {
+    let loc = diagnostic.loc.first()?;
+    if !fields.contains(&(loc.start_byte..loc.end_byte)) {
+        return None;
+    }
+    let i = entries
+        .iter()
+        .position(|(range, _)| range.contains(&loc.start_byte))?;
+    Some((i, located_value(source, loc)?))
+}
+
+/// The waivers of the entries of a bibliography.
+#[derive(Debug, Clone)]
+pub struct Waivers<'a> {
+    source: &'a str,
+    entries: Vec<(Range<usize>, String)>,
+    fields: HashSet<Range<usize>>,
+    waivers: Vec<Vec<Waiver>>,
+}
+
+impl<'a> Waivers<'a> {
+    pub fn of(bib: &'a Bibliography) -> Self {
+        let source = bib.source();
+        // the file is not parsed again without any waiver
+        if !source.contains("bibadac-accept") {
+            return Waivers {
+                source,
+                entries: vec![],
+                fields: HashSet::new(),
+                waivers: vec![],
+            };
+        }
+        let entries = bib.entry_keys();
+        let waivers = entries
+            .iter()
+            .map(|(range, _)| {
+                comment_directives(source, range.start)
+                    .into_iter()
+                    .filter(|(directive, _)| *directive == ACCEPT_DIRECTIVE)
+                    .filter_map(|(_, argument)| Waiver::parse(argument))
+                    .collect()
+            })
+            .collect();
+        Waivers {
+            source,
+            entries,
+            fields: field_spans(bib),
+            waivers,
+        }
+    }
+
+    /// Whether a waiver of its entry accepts the diagnostic.
+    pub fn accepts(&self, diagnostic: &Diagnostic) -> bool {
+        let Some((i, value)) = located(self.source, &self.entries, &self.fields, diagnostic) else {
+            return false;
+        };
+        let rule = diagnostic.msg.rule_name();
+        self.waivers[i].iter().any(|w| w.accepts(rule, &value))
+    }
+}
+
+/// Removes the diagnostics accepted by a waiver of their entry,
+/// and returns the number of removed ones.
+pub fn apply_waivers(bib: &Bibliography, diagnostics: &mut Vec<Diagnostic>) -> usize {
+    let waivers = Waivers::of(bib);
+    let before = diagnostics.len();
+    diagnostics.retain(|d| !waivers.accepts(d));
+    before - diagnostics.len()
+}
+
+/// The waivers that would accept the diagnostics about the values of
+/// fields, with the line and the key of their entries, in the order of
+/// the entries.
+pub fn emit_waivers(
+    bib: &Bibliography,
+    diagnostics: &[Diagnostic],
+) -> Vec<(usize, String, Waiver)> {
+    let entries = bib.entry_keys();
+    let fields = field_spans(bib);
+    let mut waivers = vec![];
+    for d in diagnostics {
+        let Some((i, value)) = located(bib.source(), &entries, &fields, d) else {
+            continue;
+        };
+        let (range, key) = &entries[i];
+        let line = Location::of_span(bib.source(), range.start..range.start).line;
+        let waiver = Waiver::of(d.msg.rule_name(), &value);
+        if !waivers.contains(&(line, key.clone(), waiver.clone())) {
+            waivers.push((line, key.clone(), waiver));
+        }
+    }
+    waivers.sort_by_key(|(line, _, _)| *line);
+    waivers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibliography::LintConfig;
+    use crate::linter::LintMessage;
+
+    const ENTRY: &str = "@book{ocr1852,\n  title = {Trait\\'e des maladies},\n  year = 1852,\n}\n";
+
+    fn weird(bib: &Bibliography) -> Vec<Diagnostic> {
+        bib.lint(&LintConfig::default())
+            .into_iter()
+            .filter(|d| matches!(d.msg, LintMessage::WeirdCharacters(_)))
+            .collect()
+    }
+
+    #[test]
+    fn test_comment_directives() {
+        let source = "@misc{a}\n\
+                      % bibadac-accept: weird-characters sha1=abc\n\
+                      % a note\n\
+                      %bibadac-other : x y\n\
+                      @misc{b}\n";
+        let start = source.find("@misc{b}").unwrap();
+        assert_eq!(
+            comment_directives(source, start),
+            vec![("accept", "weird-characters sha1=abc"), ("other", "x y")]
+        );
+        // a blank line ends the comments of the entry
+        let source = "% bibadac-accept: weird-characters sha1=abc\n\n@misc{b}\n";
+        assert!(comment_directives(source, source.find('@').unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            parse_directive(" bibadac-include : a.bib "),
+            Some(("include", "a.bib"))
+        );
+        assert_eq!(parse_directive("jabref-meta: x"), None);
+        assert_eq!(parse_directive("bibadac-accept"), None);
+    }
+
+    #[test]
+    fn test_parse_waiver() {
+        let waiver = Waiver::parse("weird-characters sha1=AB12cd34EF56").unwrap();
+        assert_eq!(waiver.rule, "weird-characters");
+        assert_eq!(waiver.digest, "ab12cd34ef56");
+        assert_eq!(
+            waiver.to_comment(),
+            "% bibadac-accept: weird-characters sha1=ab12cd34ef56"
+        );
+        assert_eq!(Waiver::parse("weird-characters sha256=3f0c2a9e61b4"), None);
+        assert_eq!(Waiver::parse("weird-characters"), None);
+        assert_eq!(Waiver::parse("weird-characters sha1=xyzxyzxyzxyz"), None);
+        assert_eq!(Waiver::parse("weird-characters md5=ab12cd34ef56"), None);
+        // a prefix of the digest is not enough
+        assert_eq!(Waiver::parse("weird-characters sha1=ab12"), None);
+        assert_eq!(Waiver::parse("weird-characters sha1=ab12cd34ef5678"), None);
+    }
+
+    #[test]
+    fn test_located_value() {
+        let loc = Location::of_span("title = {A\n   B},", 0..17);
+        assert_eq!(
+            located_value("title = {A\n   B},", &loc).as_deref(),
+            Some("{A B}")
+        );
+        assert_eq!(digest("{A B}").len(), DIGEST_LEN);
+    }
+
+    #[test]
+    fn test_entry_level_messages() {
+        let bib = Bibliography::from(ENTRY.to_string());
+        let missing = bib
+            .lint(&LintConfig::default())
+            .into_iter()
+            .filter(|d| matches!(d.msg, LintMessage::MissingField(_)))
+            .collect::<Vec<_>>();
+        assert!(!missing.is_empty());
+        assert!(emit_waivers(&bib, &missing).is_empty());
+
+        // nor is a waiver of the text of the entry read for them
+        let source = bib.source();
+        let loc = &missing[0].loc[0];
+        let value = located_value(source, loc).unwrap();
+        let waiver = Waiver::of(missing[0].msg.rule_name(), &value);
+        let waived = Bibliography::from(format!("{}\n{}", waiver.to_comment(), ENTRY));
+        let mut diagnostics = waived
+            .lint(&LintConfig::default())
+            .into_iter()
+            .filter(|d| matches!(d.msg, LintMessage::MissingField(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(apply_waivers(&waived, &mut diagnostics), 0);
+    }
+
+    #[test]
+    fn test_waivers() {
+        let bib = Bibliography::from(ENTRY.to_string());
+        let mut diagnostics = weird(&bib);
+        assert_eq!(diagnostics.len(), 1);
+        let waivers = emit_waivers(&bib, &diagnostics);
+        assert_eq!(waivers.len(), 1);
+        let (line, key, waiver) = &waivers[0];
+        assert_eq!((*line, key.as_str()), (1, "ocr1852"));
+        assert_eq!(waiver.rule, "weird-characters");
+
+        // the waiver accepts the value it was emitted for
+        let waived = Bibliography::from(format!("{}\n{}", waiver.to_comment(), ENTRY));
+        let mut waived_diagnostics = weird(&waived);
+        assert_eq!(apply_waivers(&waived, &mut waived_diagnostics), 1);
+        assert!(waived_diagnostics.is_empty());
+        assert_eq!(apply_waivers(&bib, &mut diagnostics), 0);
+
+        // reindenting the value keeps the digest
+        let reindented = Bibliography::from(format!(
+            "{}\n{}",
+            waiver.to_comment(),
+            ENTRY.replace("Trait\\'e des", "Trait\\'e\n    des")
+        ));
+        let mut reindented_diagnostics = weird(&reindented);
+        assert_eq!(apply_waivers(&reindented, &mut reindented_diagnostics), 1);
+    }
+
+    #[test]
+    fn test_edited_value_resurfaces() {
+        let bib = Bibliography::from(ENTRY.to_string());
+        let (_, _, waiver) = emit_waivers(&bib, &weird(&bib)).remove(0);
+        let edited = Bibliography::from(format!(
+            "{}\n{}",
+            waiver.to_comment(),
+            ENTRY.replace("maladies", "maladies du coeur")
+        ));
+        let mut diagnostics = weird(&edited);
+        assert_eq!(apply_waivers(&edited, &mut diagnostics), 0);
+        assert_eq!(diagnostics.len(), 1);
+
+        // nor does it accept another rule about the same value
+        let other = Waiver {
+            rule: "title-case".to_string(),
+            ..waiver
+        };
+        let other = Bibliography::from(format!("{}\n{}", other.to_comment(), ENTRY));
+        let mut diagnostics = weird(&other);
+        assert_eq!(apply_waivers(&other, &mut diagnostics), 0);
+    }
+}
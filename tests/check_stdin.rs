@@ -0,0 +1,53 @@
+//! Runs `bibadac check --stdin` through pipes, and looks at its exit code.
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn check_stdin(args: &[&std::ffi::OsStr], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bibadac"))
+        .arg("check")
+        .arg("--stdin")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Could not run bibadac");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input)
+        .expect("Could not write to bibadac");
+    child
+        .wait_with_output()
+        .expect("Could not wait for bibadac")
+}
+
+#[test]
+fn test_exit_code_follows_the_lints() {
+    let clean = b"@misc{doe2020,\n  author = {Doe, Jane},\n  title = {A Title},\n  year = {2020},\n  url = {https://example.org/doe},\n}\n";
+    let output = check_stdin(&[], clean);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let linted = b"@misc{doe2020,\n  title = {A Title},\n}\n";
+    let output = check_stdin(&[], linted);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_unreadable_file_db() {
+    // a directory passes the size guard but cannot be read as a bibfile
+    let db = std::env::temp_dir();
+    let output = check_stdin(
+        &["--file-db".as_ref(), db.as_os_str()],
+        b"@misc{doe2020,\n  title = {A Title},\n}\n",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("[ERR]"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
@@ -0,0 +1,102 @@
+//! Runs `bibadac format --stdin` through pipes, as editors do when
+//! formatting a buffer on save: the buffer is replaced by stdout.
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn format_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bibadac"))
+        .arg("format")
+        .arg("--stdin")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Could not run bibadac");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input)
+        .expect("Could not write to bibadac");
+    child
+        .wait_with_output()
+        .expect("Could not wait for bibadac")
+}
+
+#[test]
+fn test_malformed_input_is_given_back() {
+    let input = b"@article{ok,\n  title = {Fine},\n}\n\n@article{broken,\n  title = {Unclosed,\n  year = 2020,\n}\n";
+    let output = format_stdin(&[], input);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, input);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("syntax error"));
+}
+
+#[test]
+fn test_warnings_go_to_stderr() {
+    let input = b"@article{a,  title={A}, note = {N}}\n";
+    let output = format_stdin(&["--keep-field", "title"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "@article{a,\n  title = {A},\n}\n\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("dropped 1 fields"));
+}
+
+#[test]
+fn test_invalid_options_give_back_the_input() {
+    let input = b"@misc{a, title = {A}}\n";
+    let output = format_stdin(&["--file-db", "does/not/exist.bib"], input);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, input);
+}
+
+#[test]
+fn test_invalid_config_file_gives_back_the_input() {
+    let input = b"@misc{a, title = {A}}\n";
+    let output = format_stdin(&["--config-file", "does/not/exist.toml"], input);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, input);
+}
+
+#[test]
+fn test_documents_are_separated_by_nul_bytes() {
+    let names = ["--stdin-filename", "a.bib", "--stdin-filename", "b.bib"];
+    let input = b"@misc{a, title = {A}}\n\0@misc{b, title = {B}}\n";
+    let output = format_stdin(&names, input);
+    assert!(output.status.success());
+    let documents = output.stdout.split(|b| *b == 0).collect::<Vec<_>>();
+    assert_eq!(documents.len(), 2);
+    assert!(String::from_utf8_lossy(documents[1]).starts_with("@misc{b,"));
+
+    // the documents given back on an error are separated the same way
+    let output = format_stdin(
+        &[&names[..], &["--file-db", "does/not/exist.bib"]].concat(),
+        input,
+    );
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, input);
+}
+
+#[test]
+fn test_line_endings_are_kept() {
+    let input = include_bytes!("fixtures/line_endings/crlf.bib");
+    let output = format_stdin(&[], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\r\n"));
+    assert!(!stdout.replace("\r\n", "").contains(['\r', '\n']));
+    // a second format changes nothing
+    assert_eq!(
+        format_stdin(&[], stdout.as_bytes()).stdout,
+        stdout.as_bytes()
+    );
+
+    let output = format_stdin(&["--line-ending", "lf"], input);
+    assert!(output.status.success());
+    assert!(!output.stdout.contains(&b'\r'));
+    let lf = format_stdin(&[], include_bytes!("fixtures/line_endings/lf.bib"));
+    assert_eq!(output.stdout, lf.stdout);
+}
@@ -0,0 +1,43 @@
+//! Runs the inputs found by the fuzz target `parse_lint_format`
+//! (see `fuzz/`), minimized in `tests/fixtures/fuzz`, through the same
+//! pipeline: decoding, parsing, linting, and formatting.
+use std::path::Path;
+
+use bibadac::bibdb::LocalBibDb;
+use bibadac::bibtex::BibFile;
+use bibadac::encoding::decode;
+use bibadac::format::{FormatOptions, write_bibfile};
+use bibadac::linter::LinterState;
+
+fn parse_lint_format(data: &[u8]) -> String {
+    let (source, _) = decode(data.to_vec());
+    let bib = BibFile::new(&source);
+    LinterState::default().lint_file(&bib, bib.list_entries().collect());
+    let mut out = String::new();
+    write_bibfile(&bib, &FormatOptions::new(LocalBibDb::new()), &mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_fuzz_fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fuzz");
+    let mut inputs = std::fs::read_dir(&dir)
+        .expect("Could not read the fuzz fixtures")
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    inputs.sort();
+    assert!(!inputs.is_empty());
+    for input in inputs {
+        let data = std::fs::read(&input).unwrap();
+        let result = std::panic::catch_unwind(|| parse_lint_format(&data));
+        assert!(result.is_ok(), "{} panics", input.display());
+    }
+}
+
+#[test]
+fn test_authors_that_are_not_groups() {
+    let out = parse_lint_format(b"@misc{a, author = 1}\n");
+    assert!(out.contains("= 1"), "{}", out);
+    let out = parse_lint_format(b"@misc{a, author = d # \" and Roe\"}\n");
+    assert!(out.contains("= d # \" and Roe\""), "{}", out);
+}